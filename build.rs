@@ -0,0 +1,5 @@
+fn main() {
+    // the gRPC code of the log service is only generated when the service is enabled
+    #[cfg(feature = "service")]
+    tonic_build::compile_protos("proto/log_service.proto").unwrap();
+}
@@ -0,0 +1,40 @@
+//! Feeds arbitrary bytes to the .mav file header unpackers and the `MavLogParser`.
+//!
+//! Run with `cargo fuzz run mav_log_parser` from the repository root. Any panic is a bug, the
+//! parser must report malformed input as an error.
+#![no_main]
+
+use std::io::Write;
+
+use libfuzzer_sys::fuzz_target;
+use mavlink::ardupilotmega::MavMessage;
+use mavlink_log::mav_parser::MavParser;
+use mavlink_log::mavlog::header::FileHeader;
+use mavlink_log::mavlog::parser::MavLogParser;
+
+/// Upper bound on the entries parsed per input, since MAVLink only files resync on corruption.
+const MAX_ENTRIES: usize = 1000;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(packed) = data.first_chunk::<{ FileHeader::MIN_SIZE }>() {
+        let _ = FileHeader::unpack(packed);
+        let _ = FileHeader::try_unpack(packed);
+    }
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(data).unwrap();
+    let path = file.path().to_str().unwrap();
+    for parser in [
+        MavLogParser::<MavMessage>::try_new(path),
+        MavLogParser::<MavMessage>::try_new_lenient(path),
+    ] {
+        let Ok(mut parser) = parser else {
+            continue;
+        };
+        for _ in 0..MAX_ENTRIES {
+            if parser.parse_next_entry().is_err() {
+                break;
+            }
+        }
+    }
+});
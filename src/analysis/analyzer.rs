@@ -0,0 +1,154 @@
+//! Running several analyses in a single pass over a log.
+//!
+//! Each analysis implements `Analyzer`, receiving the entries of a log one at a time and
+//! producing its report once the log ends. Tuples of analyzers are analyzers themselves, so any
+//! combination of built-in and user defined analyses can be run by `analyze` while reading the
+//! log only once.
+use mavlink::Message;
+
+use crate::mav_parser::{LogEntry, MavParser, for_each_entry};
+
+/// An analysis fed with the entries of a log one at a time.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect of the analyzed entries.
+pub trait Analyzer<M: Message> {
+    /// Result of the analysis.
+    type Report;
+
+    /// Processes the next entry of the log.
+    ///
+    /// # Arguments
+    /// - `entry`: The entry, in log order.
+    fn on_entry(&mut self, entry: &LogEntry<M>);
+
+    /// Completes the analysis once every entry was processed.
+    ///
+    /// # Returns
+    /// The report of the analysis.
+    fn finish(self) -> Self::Report;
+}
+
+/// Implements `Analyzer` for tuples of analyzers, feeding every entry to each analyzer in turn.
+macro_rules! impl_analyzer_tuple {
+    ($($name:ident),+) => {
+        impl<M: Message, $($name: Analyzer<M>),+> Analyzer<M> for ($($name,)+) {
+            type Report = ($($name::Report,)+);
+
+            #[allow(non_snake_case)]
+            fn on_entry(&mut self, entry: &LogEntry<M>) {
+                let ($($name,)+) = self;
+                $($name.on_entry(entry);)+
+            }
+
+            #[allow(non_snake_case)]
+            fn finish(self) -> Self::Report {
+                let ($($name,)+) = self;
+                ($($name.finish(),)+)
+            }
+        }
+    };
+}
+
+impl_analyzer_tuple!(A);
+impl_analyzer_tuple!(A, B);
+impl_analyzer_tuple!(A, B, C);
+impl_analyzer_tuple!(A, B, C, D);
+impl_analyzer_tuple!(A, B, C, D, E);
+impl_analyzer_tuple!(A, B, C, D, E, F);
+impl_analyzer_tuple!(A, B, C, D, E, F, G);
+impl_analyzer_tuple!(A, B, C, D, E, F, G, H);
+
+/// Reads a full log and feeds every entry to an analyzer.
+///
+/// To run several analyzers in one pass, pass them as a tuple and receive a tuple of their
+/// reports.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `analyzer`: The analyzer, or tuple of analyzers, to run.
+///
+/// # Returns
+/// The report of the analyzer.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn analyze<P, A>(parser: &mut P, mut analyzer: A) -> std::io::Result<A::Report>
+where
+    P: MavParser + ?Sized,
+    A: Analyzer<P::M>,
+{
+    for_each_entry(parser, |entry| {
+        analyzer.on_entry(&entry);
+        Ok(())
+    })?;
+    Ok(analyzer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::common::MavMessage;
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    /// Counts the entries of a log.
+    struct EntryCounter(usize);
+
+    impl<M: Message> Analyzer<M> for EntryCounter {
+        type Report = usize;
+
+        fn on_entry(&mut self, _entry: &LogEntry<M>) {
+            self.0 += 1;
+        }
+
+        fn finish(self) -> usize {
+            self.0
+        }
+    }
+
+    /// Collects the text entries of a log.
+    struct TextCollector(Vec<String>);
+
+    impl<M: Message> Analyzer<M> for TextCollector {
+        type Report = Vec<String>;
+
+        fn on_entry(&mut self, entry: &LogEntry<M>) {
+            if let Some(text) = &entry.text {
+                self.0.push(text.clone());
+            }
+        }
+
+        fn finish(self) -> Vec<String> {
+            self.0
+        }
+    }
+
+    /// Test that several analyzers see every entry in a single pass.
+    #[test]
+    fn test_analyze_tuple() {
+        let entries = VecDeque::from([
+            LogEntry {
+                text: Some(String::from("first")),
+                ..Default::default()
+            },
+            LogEntry {
+                raw: Some(vec![1, 2, 3]),
+                ..Default::default()
+            },
+            LogEntry {
+                text: Some(String::from("second")),
+                ..Default::default()
+            },
+        ]);
+        let (count, texts) = analyze(
+            &mut EntryList::<MavMessage>(entries),
+            (EntryCounter(0), TextCollector(Vec::new())),
+        )
+        .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(texts, vec!["first", "second"]);
+    }
+}
@@ -0,0 +1,520 @@
+//! Detection of vibration, estimator and battery anomalies.
+//!
+//! Reviewing a flight starts with the few moments where something looked wrong. This module scans
+//! the health messages sent by autopilots, VIBRATION, EKF_STATUS_REPORT, SYS_STATUS and
+//! BATTERY_STATUS, against configurable thresholds and lists every violation with its time and
+//! severity.
+use std::collections::{BTreeMap, VecDeque};
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Severity of a finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Worth a look, such as vibrations degrading the position estimate.
+    Warning,
+    /// Likely to have affected the flight, such as an estimator failsafe.
+    Critical,
+}
+
+/// Warning and critical levels of a value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    /// Level from which a finding is a warning.
+    pub warning: f64,
+    /// Level from which a finding is critical.
+    pub critical: f64,
+}
+
+impl Threshold {
+    /// Returns the severity and exceeded level of a value that is worse the higher it is.
+    fn above(&self, value: f64) -> Option<(Severity, f64)> {
+        if value >= self.critical {
+            Some((Severity::Critical, self.critical))
+        } else if value >= self.warning {
+            Some((Severity::Warning, self.warning))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the severity and exceeded level of a value that is worse the lower it is.
+    fn below(&self, value: f64) -> Option<(Severity, f64)> {
+        if value <= self.critical {
+            Some((Severity::Critical, self.critical))
+        } else if value <= self.warning {
+            Some((Severity::Warning, self.warning))
+        } else {
+            None
+        }
+    }
+}
+
+/// Thresholds the health messages are checked against.
+///
+/// The defaults follow the ArduPilot guidance for vibration levels and EKF variances. There is
+/// no sensible default for the battery voltage as it depends on the pack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnomalyThresholds {
+    /// Vibration level of each axis in m/s/s.
+    pub vibration: Threshold,
+    /// Increase of an accelerometer clipping counter between consecutive VIBRATION messages.
+    pub clipping: Threshold,
+    /// Normalized EKF variances, 1.0 being the rejection limit of the estimator.
+    pub ekf_variance: Threshold,
+    /// Battery voltage in volts, worse the lower it is, if checked.
+    pub battery_voltage_v: Option<Threshold>,
+    /// Drop of the battery voltage below its highest value within `battery_sag_window_us`, in
+    /// volts.
+    pub battery_sag_v: Threshold,
+    /// Time over which the battery sag is measured in microseconds.
+    pub battery_sag_window_us: u64,
+    /// Remaining battery capacity in percent, worse the lower it is.
+    pub battery_remaining_pct: Threshold,
+}
+
+impl Default for AnomalyThresholds {
+    fn default() -> Self {
+        AnomalyThresholds {
+            vibration: Threshold {
+                warning: 30.0,
+                critical: 60.0,
+            },
+            clipping: Threshold {
+                warning: 1.0,
+                critical: 100.0,
+            },
+            ekf_variance: Threshold {
+                warning: 0.5,
+                critical: 0.8,
+            },
+            battery_voltage_v: None,
+            battery_sag_v: Threshold {
+                warning: 1.0,
+                critical: 2.0,
+            },
+            battery_sag_window_us: 5_000_000,
+            battery_remaining_pct: Threshold {
+                warning: 20.0,
+                critical: 10.0,
+            },
+        }
+    }
+}
+
+/// Kind of anomaly a finding reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AnomalyKind {
+    /// High vibration level on an axis.
+    Vibration,
+    /// Accelerometer clipping.
+    Clipping,
+    /// High EKF variance.
+    EkfVariance,
+    /// Low battery voltage.
+    BatteryVoltage,
+    /// Battery voltage dropping under load.
+    BatterySag,
+    /// Low remaining battery capacity.
+    BatteryRemaining,
+}
+
+/// A value of a vehicle crossing a threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    /// Timestamp of the message with the value.
+    pub timestamp_us: Option<u64>,
+    /// MAVLink system id of the vehicle.
+    pub system_id: u8,
+    /// Kind of anomaly.
+    pub kind: AnomalyKind,
+    /// Checked value, named `MESSAGE.field` such as `VIBRATION.vibration_z`. Values of
+    /// BATTERY_STATUS include the battery id, such as `BATTERY_STATUS[0].voltages`.
+    pub channel: String,
+    /// Severity of the finding.
+    pub severity: Severity,
+    /// The value, in the unit of its threshold.
+    pub value: f64,
+    /// The crossed level.
+    pub threshold: f64,
+}
+
+/// Analyzer listing the anomalies in the health messages of vehicles.
+///
+/// A finding is reported when a value crosses a threshold and when it escalates from a warning
+/// to critical, not for every message while it stays beyond the threshold. The battery voltage
+/// is read from SYS_STATUS and from the sum of the cell voltages of BATTERY_STATUS. The sag is
+/// only measured on entries with a timestamp.
+pub struct AnomalyDetector {
+    thresholds: AnomalyThresholds,
+    /// Current severity of each channel beyond a threshold, by system id and channel.
+    levels: BTreeMap<(u8, String), Severity>,
+    /// Last clipping counters by system id.
+    clipping: BTreeMap<u8, [u32; 3]>,
+    /// Recent timestamps and battery voltages by system id and channel.
+    voltages: BTreeMap<(u8, String), VecDeque<(u64, f64)>>,
+    findings: Vec<Finding>,
+}
+
+impl AnomalyDetector {
+    /// Creates a new `AnomalyDetector` analyzer.
+    ///
+    /// # Arguments
+    /// - `thresholds`: The thresholds to check values against.
+    pub fn new(thresholds: AnomalyThresholds) -> Self {
+        AnomalyDetector {
+            thresholds,
+            levels: BTreeMap::new(),
+            clipping: BTreeMap::new(),
+            voltages: BTreeMap::new(),
+            findings: Vec::new(),
+        }
+    }
+
+    /// Records the level of a value, reporting a finding if it crossed a threshold.
+    fn update(
+        &mut self,
+        timestamp_us: Option<u64>,
+        system_id: u8,
+        kind: AnomalyKind,
+        channel: String,
+        value: f64,
+        level: Option<(Severity, f64)>,
+    ) {
+        let key = (system_id, channel);
+        let Some((severity, threshold)) = level else {
+            self.levels.remove(&key);
+            return;
+        };
+        let previous = self.levels.insert(key.clone(), severity);
+        if previous.is_none_or(|previous| previous < severity) {
+            self.findings.push(Finding {
+                timestamp_us,
+                system_id,
+                kind,
+                channel: key.1,
+                severity,
+                value,
+                threshold,
+            });
+        }
+    }
+
+    /// Checks a battery voltage against the voltage and sag thresholds.
+    fn check_voltage(
+        &mut self,
+        timestamp_us: Option<u64>,
+        system_id: u8,
+        channel: String,
+        voltage_v: f64,
+    ) {
+        if let Some(threshold) = self.thresholds.battery_voltage_v {
+            self.update(
+                timestamp_us,
+                system_id,
+                AnomalyKind::BatteryVoltage,
+                channel.clone(),
+                voltage_v,
+                threshold.below(voltage_v),
+            );
+        }
+        let Some(timestamp_us) = timestamp_us else {
+            return;
+        };
+        let window_us = self.thresholds.battery_sag_window_us;
+        let recent = self
+            .voltages
+            .entry((system_id, channel.clone()))
+            .or_default();
+        while recent
+            .front()
+            .is_some_and(|&(time_us, _)| timestamp_us.saturating_sub(time_us) > window_us)
+        {
+            recent.pop_front();
+        }
+        let peak_v = recent
+            .iter()
+            .map(|&(_, voltage_v)| voltage_v)
+            .fold(voltage_v, f64::max);
+        recent.push_back((timestamp_us, voltage_v));
+        let sag_v = peak_v - voltage_v;
+        let level = self.thresholds.battery_sag_v.above(sag_v);
+        self.update(
+            Some(timestamp_us),
+            system_id,
+            AnomalyKind::BatterySag,
+            channel,
+            sag_v,
+            level,
+        );
+    }
+}
+
+impl<M: Message> Analyzer<M> for AnomalyDetector {
+    /// The findings of all vehicles in log order.
+    type Report = Vec<Finding>;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        let (timestamp_us, system_id) = (entry.timestamp, header.system_id);
+        let payload = fields::payload(msg);
+        match msg.message_id() {
+            fields::VIBRATION_ID => {
+                for (axis, offset) in [("x", 8), ("y", 12), ("z", 16)] {
+                    let value = fields::read_f32(&payload, offset) as f64;
+                    self.update(
+                        timestamp_us,
+                        system_id,
+                        AnomalyKind::Vibration,
+                        format!("VIBRATION.vibration_{axis}"),
+                        value,
+                        self.thresholds.vibration.above(value),
+                    );
+                }
+                let counters = [0, 1, 2].map(|index| fields::read_u32(&payload, 20 + 4 * index));
+                let previous = self.clipping.insert(system_id, counters);
+                for (index, counter) in counters.into_iter().enumerate() {
+                    // counters only reset on reboot, which is not a clipping event
+                    let increase = previous
+                        .map_or(0, |previous| counter.saturating_sub(previous[index]))
+                        as f64;
+                    self.update(
+                        timestamp_us,
+                        system_id,
+                        AnomalyKind::Clipping,
+                        format!("VIBRATION.clipping_{index}"),
+                        increase,
+                        self.thresholds.clipping.above(increase),
+                    );
+                }
+            }
+            fields::EKF_STATUS_REPORT_ID => {
+                let variances = [
+                    ("velocity_variance", 0),
+                    ("pos_horiz_variance", 4),
+                    ("pos_vert_variance", 8),
+                    ("compass_variance", 12),
+                    ("terrain_alt_variance", 16),
+                ];
+                for (name, offset) in variances {
+                    let value = fields::read_f32(&payload, offset) as f64;
+                    self.update(
+                        timestamp_us,
+                        system_id,
+                        AnomalyKind::EkfVariance,
+                        format!("EKF_STATUS_REPORT.{name}"),
+                        value,
+                        self.thresholds.ekf_variance.above(value),
+                    );
+                }
+            }
+            fields::SYS_STATUS_ID => {
+                let voltage_mv = fields::read_u16(&payload, 14);
+                if voltage_mv != u16::MAX {
+                    let channel = String::from("SYS_STATUS.voltage_battery");
+                    self.check_voltage(timestamp_us, system_id, channel, voltage_mv as f64 / 1e3);
+                }
+                let remaining = fields::read_u8(&payload, 30) as i8;
+                if remaining >= 0 {
+                    let value = remaining as f64;
+                    self.update(
+                        timestamp_us,
+                        system_id,
+                        AnomalyKind::BatteryRemaining,
+                        String::from("SYS_STATUS.battery_remaining"),
+                        value,
+                        self.thresholds.battery_remaining_pct.below(value),
+                    );
+                }
+            }
+            fields::BATTERY_STATUS_ID => {
+                let id = fields::read_u8(&payload, 32);
+                let cells_mv: Vec<u16> = (0..10)
+                    .map(|cell| fields::read_u16(&payload, 10 + 2 * cell))
+                    .filter(|&voltage_mv| voltage_mv != u16::MAX)
+                    .collect();
+                if !cells_mv.is_empty() {
+                    let voltage_v = cells_mv.iter().map(|&mv| mv as f64).sum::<f64>() / 1e3;
+                    let channel = format!("BATTERY_STATUS[{id}].voltages");
+                    self.check_voltage(timestamp_us, system_id, channel, voltage_v);
+                }
+                let remaining = fields::read_u8(&payload, 35) as i8;
+                if remaining >= 0 {
+                    let value = remaining as f64;
+                    self.update(
+                        timestamp_us,
+                        system_id,
+                        AnomalyKind::BatteryRemaining,
+                        format!("BATTERY_STATUS[{id}].battery_remaining"),
+                        value,
+                        self.thresholds.battery_remaining_pct.below(value),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> Vec<Finding> {
+        self.findings
+    }
+}
+
+/// Reads a full log and lists the anomalies in the health messages of vehicles.
+///
+/// See `AnomalyDetector` for when findings are reported and to run this analysis along with
+/// others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `thresholds`: The thresholds to check values against.
+///
+/// # Returns
+/// The findings of all vehicles in log order.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn detect_anomalies<P: MavParser + ?Sized>(
+    parser: &mut P,
+    thresholds: &AnomalyThresholds,
+) -> std::io::Result<Vec<Finding>> {
+    analyze(parser, AnomalyDetector::new(thresholds.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::ardupilotmega::{
+        EKF_STATUS_REPORT_DATA, MavMessage, SYS_STATUS_DATA, VIBRATION_DATA,
+    };
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    fn vibration(timestamp: u64, vibration_z: f32, clipping_2: u32) -> LogEntry<MavMessage> {
+        let msg = MavMessage::VIBRATION(VIBRATION_DATA {
+            vibration_x: 5.0,
+            vibration_y: 5.0,
+            vibration_z,
+            clipping_2,
+            ..Default::default()
+        });
+        entry(timestamp, msg)
+    }
+
+    fn battery(timestamp: u64, voltage_mv: u16, remaining: i8) -> LogEntry<MavMessage> {
+        let msg = MavMessage::SYS_STATUS(SYS_STATUS_DATA {
+            voltage_battery: voltage_mv,
+            battery_remaining: remaining,
+            ..Default::default()
+        });
+        entry(timestamp, msg)
+    }
+
+    /// Test that threshold crossings and escalations are reported once with their severity.
+    #[test]
+    fn test_detect_anomalies() {
+        let ekf = MavMessage::EKF_STATUS_REPORT(EKF_STATUS_REPORT_DATA {
+            pos_horiz_variance: 0.9,
+            ..Default::default()
+        });
+        let entries = VecDeque::from([
+            vibration(0, 10.0, 0),
+            battery(0, 16_000, 80),
+            vibration(1_000_000, 35.0, 0),
+            vibration(2_000_000, 70.0, 3),
+            vibration(3_000_000, 65.0, 3),
+            entry(3_500_000, ekf),
+            battery(4_000_000, 14_500, 15),
+            vibration(5_000_000, 10.0, 3),
+            vibration(6_000_000, 40.0, 3),
+            // the voltage peak is out of the sag window
+            battery(12_000_000, 14_400, 15),
+        ]);
+        let thresholds = AnomalyThresholds::default();
+        let findings = detect_anomalies(&mut EntryList(entries), &thresholds).unwrap();
+        let summary: Vec<_> = findings
+            .iter()
+            .map(|finding| {
+                (
+                    finding.timestamp_us.unwrap(),
+                    finding.kind,
+                    finding.channel.as_str(),
+                    finding.severity,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    1_000_000,
+                    AnomalyKind::Vibration,
+                    "VIBRATION.vibration_z",
+                    Severity::Warning
+                ),
+                (
+                    2_000_000,
+                    AnomalyKind::Vibration,
+                    "VIBRATION.vibration_z",
+                    Severity::Critical
+                ),
+                (
+                    2_000_000,
+                    AnomalyKind::Clipping,
+                    "VIBRATION.clipping_2",
+                    Severity::Warning
+                ),
+                (
+                    3_500_000,
+                    AnomalyKind::EkfVariance,
+                    "EKF_STATUS_REPORT.pos_horiz_variance",
+                    Severity::Critical
+                ),
+                (
+                    4_000_000,
+                    AnomalyKind::BatterySag,
+                    "SYS_STATUS.voltage_battery",
+                    Severity::Warning
+                ),
+                (
+                    4_000_000,
+                    AnomalyKind::BatteryRemaining,
+                    "SYS_STATUS.battery_remaining",
+                    Severity::Warning
+                ),
+                (
+                    6_000_000,
+                    AnomalyKind::Vibration,
+                    "VIBRATION.vibration_z",
+                    Severity::Warning
+                ),
+            ]
+        );
+        assert_eq!(findings[2].value, 3.0);
+        assert_eq!(findings[4].value, 16.0 - 14.5);
+        assert_eq!(findings[4].threshold, 1.0);
+    }
+}
@@ -0,0 +1,651 @@
+//! As-applied maps of spraying and seeding jobs.
+//!
+//! Applicators log a change of state of each actuator applying product, such as a boom section
+//! turning on or a seed meter changing rate, with `RotatingMavLogger::write_actuator`.
+//! `AsAppliedMapper` ties each event to the vehicle position at the time and follows the path of
+//! every active actuator, producing the segments where product was applied at a given rate.
+//! These can be exported as GeoJSON, or as a Shapefile with the `shapefile` feature, to be
+//! loaded into farm management software.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+#[cfg(feature = "shapefile")]
+use std::path::Path;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{ActuatorEvent, LogEntry, MavParser};
+
+/// Mean radius of the earth in meters.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Configuration of an as-applied map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsAppliedConfig {
+    /// System id of the vehicle carrying the actuators, or `None` to use the positions of any
+    /// system.
+    pub system_id: Option<u8>,
+    /// Longest time in microseconds between two positions, or between a position and an
+    /// actuator event, for them to be joined. Applied segments are split at longer gaps so that
+    /// link losses are not drawn as applied.
+    pub max_gap_us: u64,
+}
+
+impl Default for AsAppliedConfig {
+    /// Provides the positions of any system with gaps of up to 2 seconds.
+    fn default() -> Self {
+        AsAppliedConfig {
+            system_id: None,
+            max_gap_us: 2_000_000,
+        }
+    }
+}
+
+/// An actuator event with the position of the vehicle at the time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionedEvent {
+    /// Timestamp of the event, if the log records one.
+    pub timestamp_us: Option<u64>,
+    /// The new state of the actuator.
+    pub event: ActuatorEvent,
+    /// Position of the vehicle as (latitude, longitude) in degrees, `None` if no position was
+    /// logged within the maximum gap before the event.
+    pub position: Option<(f64, f64)>,
+}
+
+/// A path along which an actuator applied product at a constant rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedSegment {
+    /// Id of the actuator.
+    pub actuator: u8,
+    /// Target application rate in the units the events were logged with.
+    pub rate: f32,
+    /// Timestamp at which application started, if the log records one.
+    pub start_us: Option<u64>,
+    /// Timestamp of the last position or event of the segment, if the log records one.
+    pub end_us: Option<u64>,
+    /// Positions of the vehicle while applying as (latitude, longitude) in degrees.
+    pub path: Vec<(f64, f64)>,
+}
+
+impl AppliedSegment {
+    /// Returns the length of the path in meters.
+    pub fn length_m(&self) -> f64 {
+        self.path
+            .windows(2)
+            .map(|pair| distance_m(pair[0], pair[1]))
+            .sum()
+    }
+}
+
+/// Actuator events and applied segments of a log.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AsAppliedReport {
+    /// Every actuator event in log order.
+    pub events: Vec<PositionedEvent>,
+    /// The applied segments ordered by start time and actuator.
+    pub segments: Vec<AppliedSegment>,
+}
+
+impl AsAppliedReport {
+    /// Returns the length in meters along which product was applied, summed over actuators.
+    pub fn applied_length_m(&self) -> f64 {
+        self.segments.iter().map(AppliedSegment::length_m).sum()
+    }
+
+    /// Returns the map as a GeoJSON FeatureCollection.
+    ///
+    /// Segments are LineString features with their actuator, rate, start and end in seconds and
+    /// length as properties. Segments with fewer than 2 positions cannot be drawn as a line and
+    /// are left out. Events with a position are Point features with their actuator, state, rate
+    /// and time in seconds.
+    pub fn to_geojson(&self) -> String {
+        let mut features: Vec<String> = Vec::new();
+        for segment in self
+            .segments
+            .iter()
+            .filter(|segment| segment.path.len() >= 2)
+        {
+            let line: Vec<String> = segment
+                .path
+                .iter()
+                .map(|(latitude_deg, longitude_deg)| {
+                    format!("[{longitude_deg:.8},{latitude_deg:.8}]")
+                })
+                .collect();
+            let mut feature = format!(
+                r#"{{"type":"Feature","geometry":{{"type":"LineString","coordinates":[{}]}},"#,
+                line.join(",")
+            );
+            write!(
+                feature,
+                r#""properties":{{"actuator":{},"rate":{},"start_s":{},"end_s":{},"length_m":{:.2}}}}}"#,
+                segment.actuator,
+                segment.rate,
+                json_seconds(segment.start_us),
+                json_seconds(segment.end_us),
+                segment.length_m()
+            )
+            .unwrap();
+            features.push(feature);
+        }
+        for event in &self.events {
+            let Some((latitude_deg, longitude_deg)) = event.position else {
+                continue;
+            };
+            features.push(format!(
+                r#"{{"type":"Feature","geometry":{{"type":"Point","coordinates":[{longitude_deg:.8},{latitude_deg:.8}]}},"properties":{{"actuator":{},"active":{},"rate":{},"time_s":{}}}}}"#,
+                event.event.actuator,
+                event.event.active,
+                event.event.rate,
+                json_seconds(event.timestamp_us)
+            ));
+        }
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+    }
+
+    /// Writes the applied segments as an ESRI Shapefile of PolyLine shapes in WGS 84.
+    ///
+    /// The shapes are written to `path`, and the index, attributes and projection next to it
+    /// with the `.shx`, `.dbf` and `.prj` extensions. The attributes of each segment are its
+    /// actuator, rate, start and end in seconds and length in meters. Segments with fewer than 2
+    /// positions are left out.
+    ///
+    /// # Arguments
+    /// - `path`: The path of the `.shp` file.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if a file could not be written.
+    #[cfg(feature = "shapefile")]
+    pub fn write_shapefile(&self, path: &Path) -> std::io::Result<()> {
+        let lines: Vec<&AppliedSegment> = self
+            .segments
+            .iter()
+            .filter(|segment| segment.path.len() >= 2)
+            .collect();
+        let bounds = bounding_box(lines.iter().flat_map(|segment| segment.path.iter()));
+        let mut records: Vec<u8> = Vec::new();
+        let mut index: Vec<u8> = Vec::new();
+        for (number, segment) in lines.iter().enumerate() {
+            let content = polyline_content(segment);
+            // offsets and lengths are counted in 16-bit words
+            index.extend_from_slice(
+                &((SHAPEFILE_HEADER_LEN + records.len()) as i32 / 2).to_be_bytes(),
+            );
+            index.extend_from_slice(&(content.len() as i32 / 2).to_be_bytes());
+            records.extend_from_slice(&(number as i32 + 1).to_be_bytes());
+            records.extend_from_slice(&(content.len() as i32 / 2).to_be_bytes());
+            records.extend_from_slice(&content);
+        }
+        let mut shp = shapefile_header(SHAPEFILE_HEADER_LEN + records.len(), bounds);
+        shp.extend_from_slice(&records);
+        std::fs::write(path, shp)?;
+        let mut shx = shapefile_header(SHAPEFILE_HEADER_LEN + index.len(), bounds);
+        shx.extend_from_slice(&index);
+        std::fs::write(path.with_extension("shx"), shx)?;
+        std::fs::write(path.with_extension("dbf"), dbase_table(&lines))?;
+        std::fs::write(path.with_extension("prj"), WGS84_WKT)
+    }
+}
+
+/// Formats an optional timestamp as seconds, or `null` if there is none.
+fn json_seconds(timestamp_us: Option<u64>) -> String {
+    match timestamp_us {
+        Some(timestamp_us) => format!("{}", timestamp_us as f64 / 1e6),
+        None => String::from("null"),
+    }
+}
+
+/// Returns the distance in meters between two positions given in degrees.
+///
+/// Uses an equirectangular approximation, which is accurate for the short steps between two
+/// positions of a vehicle.
+fn distance_m(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let mean_latitude = ((from.0 + to.0) / 2.0).to_radians();
+    let north = (to.0 - from.0).to_radians() * EARTH_RADIUS_M;
+    let east = (to.1 - from.1).to_radians() * EARTH_RADIUS_M * mean_latitude.cos();
+    north.hypot(east)
+}
+
+/// Length of the header of Shapefile shape and index files in bytes.
+#[cfg(feature = "shapefile")]
+const SHAPEFILE_HEADER_LEN: usize = 100;
+/// Shapefile shape type of PolyLine shapes.
+#[cfg(feature = "shapefile")]
+const POLYLINE: i32 = 3;
+/// Projection of Shapefile coordinates.
+#[cfg(feature = "shapefile")]
+const WGS84_WKT: &str = r#"GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984",SPHEROID["WGS_1984",6378137.0,298.257223563]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]]"#;
+/// Name, width and decimal places of the numeric attributes of each segment.
+#[cfg(feature = "shapefile")]
+const DBASE_FIELDS: [(&str, usize, usize); 5] = [
+    ("ACTUATOR", 3, 0),
+    ("RATE", 14, 4),
+    ("START_S", 18, 6),
+    ("END_S", 18, 6),
+    ("LENGTH_M", 12, 2),
+];
+
+/// Returns the bounds of positions as minimum longitude, minimum latitude, maximum longitude and
+/// maximum latitude, all 0 if there are none.
+#[cfg(feature = "shapefile")]
+fn bounding_box<'a>(positions: impl Iterator<Item = &'a (f64, f64)>) -> [f64; 4] {
+    let bounds = positions.fold(None, |bounds: Option<[f64; 4]>, &(latitude, longitude)| {
+        Some(match bounds {
+            None => [longitude, latitude, longitude, latitude],
+            Some([x_min, y_min, x_max, y_max]) => [
+                x_min.min(longitude),
+                y_min.min(latitude),
+                x_max.max(longitude),
+                y_max.max(latitude),
+            ],
+        })
+    });
+    bounds.unwrap_or([0.0; 4])
+}
+
+/// Returns the header of a Shapefile shape or index file.
+///
+/// # Arguments
+/// - `file_len`: The length of the complete file in bytes.
+/// - `bounds`: The bounds of all shapes, see `bounding_box`.
+#[cfg(feature = "shapefile")]
+fn shapefile_header(file_len: usize, bounds: [f64; 4]) -> Vec<u8> {
+    let mut header: Vec<u8> = Vec::with_capacity(SHAPEFILE_HEADER_LEN);
+    header.extend_from_slice(&9994i32.to_be_bytes());
+    header.extend_from_slice(&[0; 20]);
+    header.extend_from_slice(&(file_len as i32 / 2).to_be_bytes());
+    header.extend_from_slice(&1000i32.to_le_bytes());
+    header.extend_from_slice(&POLYLINE.to_le_bytes());
+    for bound in bounds {
+        header.extend_from_slice(&bound.to_le_bytes());
+    }
+    // no Z or M range
+    header.extend_from_slice(&[0; 32]);
+    header
+}
+
+/// Returns the content of the PolyLine record of a segment, a single part through its path.
+#[cfg(feature = "shapefile")]
+fn polyline_content(segment: &AppliedSegment) -> Vec<u8> {
+    let mut content: Vec<u8> = Vec::with_capacity(48 + 16 * segment.path.len());
+    content.extend_from_slice(&POLYLINE.to_le_bytes());
+    for bound in bounding_box(segment.path.iter()) {
+        content.extend_from_slice(&bound.to_le_bytes());
+    }
+    content.extend_from_slice(&1i32.to_le_bytes());
+    content.extend_from_slice(&(segment.path.len() as i32).to_le_bytes());
+    content.extend_from_slice(&0i32.to_le_bytes());
+    for (latitude, longitude) in &segment.path {
+        content.extend_from_slice(&longitude.to_le_bytes());
+        content.extend_from_slice(&latitude.to_le_bytes());
+    }
+    content
+}
+
+/// Returns the dBase III table holding the attributes of the segments.
+#[cfg(feature = "shapefile")]
+fn dbase_table(segments: &[&AppliedSegment]) -> Vec<u8> {
+    let record_len = 1 + DBASE_FIELDS
+        .iter()
+        .map(|(_, width, _)| width)
+        .sum::<usize>();
+    let header_len = 32 + 32 * DBASE_FIELDS.len() + 1;
+    let mut table: Vec<u8> = Vec::with_capacity(header_len + record_len * segments.len() + 1);
+    // version and date of last update, left at 1970-01-01 so that exports are reproducible
+    table.extend_from_slice(&[0x03, 70, 1, 1]);
+    table.extend_from_slice(&(segments.len() as u32).to_le_bytes());
+    table.extend_from_slice(&(header_len as u16).to_le_bytes());
+    table.extend_from_slice(&(record_len as u16).to_le_bytes());
+    table.extend_from_slice(&[0; 20]);
+    for (name, width, decimals) in DBASE_FIELDS {
+        let mut descriptor = [0u8; 32];
+        descriptor[..name.len()].copy_from_slice(name.as_bytes());
+        descriptor[11] = b'N';
+        descriptor[16] = width as u8;
+        descriptor[17] = decimals as u8;
+        table.extend_from_slice(&descriptor);
+    }
+    table.push(0x0d);
+    for segment in segments {
+        let seconds = |timestamp_us: Option<u64>| timestamp_us.map(|t| t as f64 / 1e6);
+        let values = [
+            Some(segment.actuator as f64),
+            Some(segment.rate as f64),
+            seconds(segment.start_us),
+            seconds(segment.end_us),
+            Some(segment.length_m()),
+        ];
+        // records start with a blank deletion flag, missing values are left blank
+        table.push(b' ');
+        for ((_, width, decimals), value) in DBASE_FIELDS.into_iter().zip(values) {
+            let text = match value {
+                Some(value) => format!("{value:>width$.decimals$}"),
+                None => " ".repeat(width),
+            };
+            table.extend_from_slice(&text.as_bytes()[..width]);
+        }
+    }
+    table.push(0x1a);
+    table
+}
+
+/// Analyzer building the as-applied map of the actuator events of a log.
+///
+/// Each actuator event is tied to the last position of the vehicle if it is recent enough. While
+/// an actuator is active, the positions of the vehicle are appended to its current segment. A
+/// segment ends when the actuator turns off, changes rate, when positions stop for longer than
+/// the maximum gap, or at the end of the log. Positions at 0, 0 are ignored as missing fixes.
+pub struct AsAppliedMapper {
+    config: AsAppliedConfig,
+    /// Timestamp, latitude and longitude of the last position.
+    position: Option<(Option<u64>, f64, f64)>,
+    /// The segments of the active actuators by actuator id.
+    active: BTreeMap<u8, AppliedSegment>,
+    events: Vec<PositionedEvent>,
+    segments: Vec<AppliedSegment>,
+}
+
+impl AsAppliedMapper {
+    /// Creates an analyzer for a configuration.
+    ///
+    /// # Arguments
+    /// - `config`: The vehicle to follow and the longest gap to join positions across.
+    pub fn new(config: AsAppliedConfig) -> Self {
+        AsAppliedMapper {
+            config,
+            position: None,
+            active: BTreeMap::new(),
+            events: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Returns whether two timestamps are close enough to be joined. Missing timestamps are
+    /// always joined.
+    fn within_gap(&self, from: Option<u64>, to: Option<u64>) -> bool {
+        match (from, to) {
+            (Some(from), Some(to)) => to.saturating_sub(from) <= self.config.max_gap_us,
+            _ => true,
+        }
+    }
+
+    /// Applies an actuator event, ending the current segment of the actuator and starting a new
+    /// one if it is active.
+    fn on_event(&mut self, timestamp_us: Option<u64>, event: ActuatorEvent) {
+        let position = self
+            .position
+            .filter(|(at, _, _)| self.within_gap(*at, timestamp_us))
+            .map(|(_, latitude, longitude)| (latitude, longitude));
+        self.events.push(PositionedEvent {
+            timestamp_us,
+            event,
+            position,
+        });
+        if let Some(mut segment) = self.active.remove(&event.actuator) {
+            if let Some(position) =
+                position.filter(|position| segment.path.last() != Some(position))
+            {
+                segment.path.push(position);
+            }
+            segment.end_us = timestamp_us.or(segment.end_us);
+            self.segments.push(segment);
+        }
+        if event.active {
+            self.active.insert(
+                event.actuator,
+                AppliedSegment {
+                    actuator: event.actuator,
+                    rate: event.rate,
+                    start_us: timestamp_us,
+                    end_us: timestamp_us,
+                    path: position.into_iter().collect(),
+                },
+            );
+        }
+    }
+
+    /// Appends a position to the segments of the active actuators, splitting them first if the
+    /// position follows a gap.
+    fn on_position(&mut self, timestamp_us: Option<u64>, latitude: f64, longitude: f64) {
+        let previous = self.position.replace((timestamp_us, latitude, longitude));
+        let gap = previous.is_some_and(|(at, _, _)| !self.within_gap(at, timestamp_us));
+        for segment in self.active.values_mut() {
+            if gap && !segment.path.is_empty() {
+                let resumed = AppliedSegment {
+                    start_us: timestamp_us,
+                    end_us: timestamp_us,
+                    path: Vec::new(),
+                    ..segment.clone()
+                };
+                self.segments.push(std::mem::replace(segment, resumed));
+            }
+            segment.path.push((latitude, longitude));
+            segment.end_us = timestamp_us.or(segment.end_us);
+        }
+    }
+}
+
+impl<M: Message> Analyzer<M> for AsAppliedMapper {
+    /// The actuator events and applied segments.
+    type Report = AsAppliedReport;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        if let Some(event) = entry.actuator {
+            self.on_event(entry.timestamp, event);
+            return;
+        }
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        if msg.message_id() != fields::GLOBAL_POSITION_INT_ID
+            || self
+                .config
+                .system_id
+                .is_some_and(|system_id| system_id != header.system_id)
+        {
+            return;
+        }
+        let payload = fields::payload(msg);
+        let latitude = fields::read_i32(&payload, 4) as f64 / 1e7;
+        let longitude = fields::read_i32(&payload, 8) as f64 / 1e7;
+        if latitude != 0.0 || longitude != 0.0 {
+            self.on_position(entry.timestamp, latitude, longitude);
+        }
+    }
+
+    fn finish(mut self) -> AsAppliedReport {
+        self.segments.extend(self.active.into_values());
+        self.segments
+            .sort_by_key(|segment| (segment.start_us, segment.actuator));
+        AsAppliedReport {
+            events: self.events,
+            segments: self.segments,
+        }
+    }
+}
+
+/// Reads a full log and builds the as-applied map of its actuator events.
+///
+/// See `AsAppliedMapper` for how events and positions are joined and to run this analysis along
+/// with others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `config`: The vehicle to follow and the longest gap to join positions across.
+///
+/// # Returns
+/// The actuator events and applied segments.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn as_applied_map<P: MavParser + ?Sized>(
+    parser: &mut P,
+    config: &AsAppliedConfig,
+) -> std::io::Result<AsAppliedReport> {
+    analyze(parser, AsAppliedMapper::new(config.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    /// Returns a position of system 1 given in 1e-4 degrees north and east of 47, 8.
+    fn position(timestamp: u64, north: i32, east: i32) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                lat: 470_000_000 + north * 1000,
+                lon: 80_000_000 + east * 1000,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// Returns an actuator event.
+    fn event(timestamp: u64, actuator: u8, active: bool, rate: f32) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            actuator: Some(ActuatorEvent {
+                actuator,
+                active,
+                rate,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Test that events are tied to positions and split the paths of the actuators into
+    /// segments.
+    #[test]
+    fn test_as_applied_map() {
+        let entries = VecDeque::from([
+            position(0, 0, 0),
+            event(500_000, 0, true, 150.0),
+            position(1_000_000, 1, 0),
+            event(1_500_000, 1, true, 150.0),
+            position(2_000_000, 2, 0),
+            event(2_000_000, 0, true, 200.0),
+            position(3_000_000, 3, 0),
+            event(3_000_000, 1, false, 0.0),
+            // after a link loss
+            position(10_000_000, 4, 0),
+            position(11_000_000, 5, 0),
+        ]);
+        let report = as_applied_map(&mut EntryList(entries), &AsAppliedConfig::default()).unwrap();
+        assert_eq!(report.events.len(), 4);
+        assert_eq!(report.events[0].position, Some((47.0, 8.0)));
+        assert_eq!(report.events[3].position, Some((47.0003, 8.0)));
+
+        let summary: Vec<(u8, f32, Option<u64>, Option<u64>, usize)> = report
+            .segments
+            .iter()
+            .map(|segment| {
+                (
+                    segment.actuator,
+                    segment.rate,
+                    segment.start_us,
+                    segment.end_us,
+                    segment.path.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, 150.0, Some(500_000), Some(2_000_000), 3),
+                (1, 150.0, Some(1_500_000), Some(3_000_000), 3),
+                (0, 200.0, Some(2_000_000), Some(3_000_000), 2),
+                (0, 200.0, Some(10_000_000), Some(11_000_000), 2),
+            ]
+        );
+        // 1e-4 degrees of latitude are about 11.1 meters
+        assert!((report.segments[0].length_m() - 22.24).abs() < 0.01);
+        assert!((report.applied_length_m() - 66.72).abs() < 0.05);
+
+        let geojson = report.to_geojson();
+        assert!(geojson.starts_with(r#"{"type":"FeatureCollection","features":[{"#));
+        assert_eq!(geojson.matches(r#""type":"LineString""#).count(), 4);
+        assert_eq!(geojson.matches(r#""type":"Point""#).count(), 4);
+        assert!(geojson.contains(
+            r#""properties":{"actuator":1,"rate":150,"start_s":1.5,"end_s":3,"length_m":22.24}"#
+        ));
+        assert!(
+            geojson.contains(r#""properties":{"actuator":1,"active":false,"rate":0,"time_s":3}"#)
+        );
+        assert!(geojson.ends_with("}]}"));
+    }
+
+    /// Test that events without a recent position start segments at the next position.
+    #[test]
+    fn test_event_without_position() {
+        let entries = VecDeque::from([
+            position(0, 0, 0),
+            event(5_000_000, 0, true, 1.0),
+            position(6_000_000, 1, 0),
+            position(7_000_000, 2, 0),
+        ]);
+        let report = as_applied_map(&mut EntryList(entries), &AsAppliedConfig::default()).unwrap();
+        assert_eq!(report.events[0].position, None);
+        assert_eq!(report.segments.len(), 1);
+        assert_eq!(report.segments[0].start_us, Some(5_000_000));
+        assert_eq!(
+            report.segments[0].path,
+            vec![(47.0001, 8.0), (47.0002, 8.0)]
+        );
+    }
+
+    /// Test that the applied segments are written as a Shapefile.
+    #[cfg(feature = "shapefile")]
+    #[test]
+    fn test_write_shapefile() {
+        let entries = VecDeque::from([
+            event(0, 2, true, 80.0),
+            position(0, 0, 0),
+            position(1_000_000, 1, 1),
+            position(2_000_000, 2, 0),
+            event(2_000_000, 2, false, 0.0),
+        ]);
+        let report = as_applied_map(&mut EntryList(entries), &AsAppliedConfig::default()).unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("applied.shp");
+        report.write_shapefile(&path).unwrap();
+
+        let shp = std::fs::read(&path).unwrap();
+        // one record of a single part through 3 points
+        assert_eq!(shp.len(), 100 + 8 + 44 + 4 + 3 * 16);
+        assert_eq!(shp[..4], 9994i32.to_be_bytes());
+        assert_eq!(shp[24..28], (shp.len() as i32 / 2).to_be_bytes());
+        assert_eq!(shp[32..36], 3i32.to_le_bytes());
+        assert_eq!(f64::from_le_bytes(shp[36..44].try_into().unwrap()), 8.0);
+        assert_eq!(f64::from_le_bytes(shp[60..68].try_into().unwrap()), 47.0002);
+
+        let shx = std::fs::read(path.with_extension("shx")).unwrap();
+        assert_eq!(shx.len(), 108);
+        assert_eq!(shx[100..104], 50i32.to_be_bytes());
+
+        let dbf = std::fs::read(path.with_extension("dbf")).unwrap();
+        assert_eq!(dbf[4..8], 1u32.to_le_bytes());
+        let record = std::str::from_utf8(&dbf[32 + 5 * 32 + 1..dbf.len() - 1]).unwrap();
+        assert!(record.starts_with("   2       80.0000"));
+        assert!(dbf.ends_with(&[0x1a]));
+        assert!(path.with_extension("prj").exists());
+    }
+}
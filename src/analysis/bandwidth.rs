@@ -0,0 +1,266 @@
+//! Accounting of the bytes a log's messages take up on the wire.
+//!
+//! Logs do not record how each message was framed, so the wire size of every message is
+//! computed for the framing the link is expected to use. This gives the bandwidth a radio link
+//! would need to carry the same traffic.
+use std::collections::BTreeMap;
+
+use mavlink::{MavlinkVersion, Message};
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Size of the MAVLink 1 header and checksum.
+const V1_OVERHEAD: usize = 8;
+/// Size of the MAVLink 2 header and checksum.
+const V2_OVERHEAD: usize = 12;
+/// Size of a MAVLink 2 signature.
+const SIGNATURE_SIZE: usize = 13;
+
+/// Framing used to compute the wire size of messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Framing {
+    /// MAVLink 1 frames with full length payloads.
+    V1,
+    /// Unsigned MAVLink 2 frames with truncated payloads.
+    #[default]
+    V2,
+    /// Signed MAVLink 2 frames with truncated payloads.
+    V2Signed,
+}
+
+impl Framing {
+    /// Returns the size of a message framed on the wire, including header, checksum and
+    /// signature.
+    pub fn frame_len<M: Message>(&self, msg: &M) -> usize {
+        match self {
+            Framing::V1 => V1_OVERHEAD + fields::payload_len(msg, MavlinkVersion::V1),
+            Framing::V2 => V2_OVERHEAD + fields::payload_len(msg, MavlinkVersion::V2),
+            Framing::V2Signed => {
+                V2_OVERHEAD + SIGNATURE_SIZE + fields::payload_len(msg, MavlinkVersion::V2)
+            }
+        }
+    }
+}
+
+/// Number of messages and the bytes they take up on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ByteCount {
+    /// Number of messages.
+    pub messages: u64,
+    /// Total size of the framed messages in bytes.
+    pub bytes: u64,
+}
+
+impl ByteCount {
+    fn add(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// Bytes on the wire of a log by message id, by system and over time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandwidthReport {
+    /// Bytes of all messages.
+    pub total: ByteCount,
+    /// Bytes by MAVLink message id.
+    pub by_message_id: BTreeMap<u32, ByteCount>,
+    /// Bytes by sending MAVLink system id.
+    pub by_system: BTreeMap<u8, ByteCount>,
+    /// Timestamp of the start of the first bucket, the timestamp of the first message.
+    pub start_us: u64,
+    /// Length of each bucket in microseconds.
+    pub bucket_us: u64,
+    /// Bytes of the timestamped messages in each bucket, covering the log from its first to its
+    /// last timestamped message.
+    pub buckets: Vec<ByteCount>,
+}
+
+impl BandwidthReport {
+    /// Returns the bandwidth used in each bucket, in bytes per second.
+    pub fn bytes_per_second(&self) -> Vec<f64> {
+        let bucket_s = self.bucket_us as f64 / 1e6;
+        self.buckets
+            .iter()
+            .map(|count| count.bytes as f64 / bucket_s)
+            .collect()
+    }
+}
+
+/// Analyzer accounting the bytes the MAVLink messages of a log take up on the wire.
+///
+/// Messages without a timestamp are counted in the totals but not in any bucket, and messages
+/// timestamped before the first message are counted in the first bucket.
+pub struct BandwidthCounter {
+    framing: Framing,
+    start_us: Option<u64>,
+    report: BandwidthReport,
+}
+
+impl BandwidthCounter {
+    /// Creates a new `BandwidthCounter` analyzer.
+    ///
+    /// # Arguments
+    /// - `framing`: The framing to compute the wire size of messages with.
+    /// - `bucket_us`: The length of each time bucket in microseconds.
+    ///
+    /// # Panics
+    /// Panics if `bucket_us` is 0.
+    pub fn new(framing: Framing, bucket_us: u64) -> Self {
+        assert!(bucket_us > 0, "The bucket length must not be 0");
+        BandwidthCounter {
+            framing,
+            start_us: None,
+            report: BandwidthReport {
+                total: ByteCount::default(),
+                by_message_id: BTreeMap::new(),
+                by_system: BTreeMap::new(),
+                start_us: 0,
+                bucket_us,
+                buckets: Vec::new(),
+            },
+        }
+    }
+}
+
+impl<M: Message> Analyzer<M> for BandwidthCounter {
+    type Report = BandwidthReport;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        let report = &mut self.report;
+        let frame_len = self.framing.frame_len(msg);
+        report.total.add(frame_len);
+        report
+            .by_message_id
+            .entry(msg.message_id())
+            .or_default()
+            .add(frame_len);
+        report
+            .by_system
+            .entry(header.system_id)
+            .or_default()
+            .add(frame_len);
+        if let Some(timestamp) = entry.timestamp {
+            let start = *self.start_us.get_or_insert(timestamp);
+            let bucket = (timestamp.saturating_sub(start) / report.bucket_us) as usize;
+            if report.buckets.len() <= bucket {
+                report.buckets.resize(bucket + 1, ByteCount::default());
+            }
+            report.buckets[bucket].add(frame_len);
+        }
+    }
+
+    fn finish(mut self) -> BandwidthReport {
+        self.report.start_us = self.start_us.unwrap_or(0);
+        self.report
+    }
+}
+
+/// Reads a full log and accounts the bytes its MAVLink messages take up on the wire.
+///
+/// See `BandwidthCounter` for how messages are accounted and to run this analysis along with
+/// others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `framing`: The framing to compute the wire size of messages with.
+/// - `bucket_us`: The length of each time bucket in microseconds.
+///
+/// # Returns
+/// The bandwidth report of the log.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read, or of kind `InvalidInput` if
+/// `bucket_us` is 0.
+pub fn bandwidth<P: MavParser + ?Sized>(
+    parser: &mut P,
+    framing: Framing,
+    bucket_us: u64,
+) -> std::io::Result<BandwidthReport> {
+    if bucket_us == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "The bucket length must not be 0",
+        ));
+    }
+    analyze(parser, BandwidthCounter::new(framing, bucket_us))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{HEARTBEAT_DATA, MavMessage};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(system_id: u8, timestamp: Option<u64>, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp,
+            mav_header: Some(MavHeader {
+                system_id,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    /// Test the wire size of messages for each framing.
+    #[test]
+    fn test_frame_len() {
+        let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            mavlink_version: 3,
+            ..Default::default()
+        });
+        assert_eq!(Framing::V1.frame_len(&heartbeat), 17);
+        assert_eq!(Framing::V2.frame_len(&heartbeat), 21);
+        assert_eq!(Framing::V2Signed.frame_len(&heartbeat), 34);
+        // all zero payloads keep a single byte
+        let empty = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+        assert_eq!(Framing::V2.frame_len(&empty), 13);
+    }
+
+    /// Test that bytes are accounted by message id, by system and by time bucket.
+    #[test]
+    fn test_bandwidth() {
+        let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            mavlink_version: 3,
+            ..Default::default()
+        });
+        let entries = VecDeque::from([
+            entry(1, Some(1_000_000), heartbeat.clone()),
+            entry(2, Some(1_500_000), heartbeat.clone()),
+            entry(1, Some(3_200_000), heartbeat.clone()),
+            entry(1, None, heartbeat),
+            LogEntry {
+                text: Some(String::from("note")),
+                ..Default::default()
+            },
+        ]);
+        let report = bandwidth(&mut EntryList(entries), Framing::V2, 1_000_000).unwrap();
+        assert_eq!(
+            report.total,
+            ByteCount {
+                messages: 4,
+                bytes: 84
+            }
+        );
+        assert_eq!(report.by_message_id[&0].bytes, 84);
+        assert_eq!(report.by_system[&1].messages, 3);
+        assert_eq!(report.by_system[&2].bytes, 21);
+        assert_eq!(report.start_us, 1_000_000);
+        let buckets: Vec<u64> = report.buckets.iter().map(|count| count.bytes).collect();
+        assert_eq!(buckets, vec![42, 0, 21]);
+        assert_eq!(report.bytes_per_second(), vec![42.0, 0.0, 21.0]);
+    }
+}
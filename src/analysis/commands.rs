@@ -0,0 +1,370 @@
+//! Pairing of MAVLink commands with their acknowledgements.
+//!
+//! Debugging a vehicle that ignored a command starts with knowing whether the command reached
+//! it, whether it was rejected and how long the answer took.
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// MAV_RESULT_ACCEPTED.
+const RESULT_ACCEPTED: u8 = 0;
+/// MAV_RESULT_IN_PROGRESS, sent before the final result of long running commands.
+const RESULT_IN_PROGRESS: u8 = 5;
+
+/// Message a command was sent with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandKind {
+    Long,
+    Int,
+}
+
+/// A command and the acknowledgement it received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandExchange {
+    /// MAV_CMD id of the command.
+    pub command: u16,
+    /// Message the command was sent with.
+    pub kind: CommandKind,
+    /// MAVLink system id of the sender.
+    pub source_system: u8,
+    /// MAVLink component id of the sender.
+    pub source_component: u8,
+    /// MAVLink system id the command was addressed to, 0 for broadcast.
+    pub target_system: u8,
+    /// MAVLink component id the command was addressed to, 0 for broadcast.
+    pub target_component: u8,
+    /// Timestamp of the first transmission of the command.
+    pub sent_us: Option<u64>,
+    /// Number of times the command was sent, including retransmissions.
+    pub attempts: u32,
+    /// MAV_RESULT of the last acknowledgement, `None` if the command was never acknowledged.
+    /// MAV_RESULT_IN_PROGRESS means no final result was received.
+    pub result: Option<u8>,
+    /// Timestamp of the last acknowledgement.
+    pub ack_us: Option<u64>,
+}
+
+impl CommandExchange {
+    /// Checks whether the command was accepted.
+    pub fn is_accepted(&self) -> bool {
+        self.result == Some(RESULT_ACCEPTED)
+    }
+
+    /// Returns the time from the first transmission to the last acknowledgement, if the
+    /// command was acknowledged and the log provides timestamps.
+    pub fn latency_us(&self) -> Option<u64> {
+        Some(self.ack_us?.saturating_sub(self.sent_us?))
+    }
+}
+
+/// A command waiting for its final acknowledgement.
+struct Pending {
+    /// Position of the command among all commands of the log.
+    index: usize,
+    exchange: CommandExchange,
+    /// Timestamp of the last transmission or acknowledgement of the command.
+    last_activity_us: Option<u64>,
+}
+
+impl Pending {
+    /// Checks whether the command has waited for an answer for longer than the timeout.
+    fn is_expired(&self, timestamp: Option<u64>, timeout_us: u64) -> bool {
+        match (timestamp, self.last_activity_us) {
+            (Some(now), Some(last)) => now.saturating_sub(last) > timeout_us,
+            _ => false,
+        }
+    }
+
+    /// Checks whether an acknowledgement answers this command.
+    fn is_answered_by(&self, ack_source: (u8, u8), command: u16, ack_target: (u8, u8)) -> bool {
+        let exchange = &self.exchange;
+        exchange.command == command
+            && (exchange.target_system == 0 || exchange.target_system == ack_source.0)
+            && (exchange.target_component == 0 || exchange.target_component == ack_source.1)
+            // older autopilots leave the target of acknowledgements unset
+            && (ack_target.0 == 0 || ack_target.0 == exchange.source_system)
+            && (ack_target.1 == 0 || ack_target.1 == exchange.source_component)
+    }
+}
+
+/// Analyzer pairing every COMMAND_LONG and COMMAND_INT with its COMMAND_ACK.
+///
+/// An acknowledgement answers the oldest pending command with the same command id that was
+/// addressed to the acknowledging component and sent by the component the acknowledgement is
+/// addressed to. A command sent again while pending to the same target is counted as a
+/// retransmission. MAV_RESULT_IN_PROGRESS acknowledgements keep the command pending until its
+/// final result. A command is given up as unacknowledged once no answer arrived within the
+/// timeout of its last transmission or acknowledgement. Without timestamps commands never time
+/// out.
+pub struct CommandTracker {
+    timeout_us: u64,
+    pending: Vec<Pending>,
+    /// Completed commands with their position among all commands.
+    done: Vec<(usize, CommandExchange)>,
+    command_count: usize,
+}
+
+impl CommandTracker {
+    /// Creates a new `CommandTracker` analyzer.
+    ///
+    /// # Arguments
+    /// - `timeout_us`: The time to wait for an acknowledgement in microseconds.
+    pub fn new(timeout_us: u64) -> Self {
+        CommandTracker {
+            timeout_us,
+            pending: Vec::new(),
+            done: Vec::new(),
+            command_count: 0,
+        }
+    }
+}
+
+impl<M: Message> Analyzer<M> for CommandTracker {
+    /// All commands in the order they were first sent.
+    type Report = Vec<CommandExchange>;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        let timeout_us = self.timeout_us;
+        let (expired, waiting): (Vec<Pending>, Vec<Pending>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|p| p.is_expired(entry.timestamp, timeout_us));
+        self.done
+            .extend(expired.into_iter().map(|p| (p.index, p.exchange)));
+        self.pending = waiting;
+
+        let msg_id = msg.message_id();
+        let payload = fields::payload(msg);
+        let kind = match msg_id {
+            fields::COMMAND_LONG_ID => CommandKind::Long,
+            fields::COMMAND_INT_ID => CommandKind::Int,
+            fields::COMMAND_ACK_ID => {
+                let command = fields::read_u16(&payload, 0);
+                let result = fields::read_u8(&payload, 2);
+                let ack_source = (header.system_id, header.component_id);
+                let ack_target = (fields::read_u8(&payload, 8), fields::read_u8(&payload, 9));
+                let Some(position) = self
+                    .pending
+                    .iter()
+                    .position(|p| p.is_answered_by(ack_source, command, ack_target))
+                else {
+                    return;
+                };
+                let answered = &mut self.pending[position];
+                answered.exchange.result = Some(result);
+                answered.exchange.ack_us = entry.timestamp;
+                answered.last_activity_us = entry.timestamp.or(answered.last_activity_us);
+                if result != RESULT_IN_PROGRESS {
+                    let answered = self.pending.remove(position);
+                    self.done.push((answered.index, answered.exchange));
+                }
+                return;
+            }
+            _ => return,
+        };
+        // both command messages share the layout of these fields
+        let command = fields::read_u16(&payload, 28);
+        let target_system = fields::read_u8(&payload, 30);
+        let target_component = fields::read_u8(&payload, 31);
+        let retransmission = self.pending.iter_mut().find(|p| {
+            p.exchange.command == command
+                && p.exchange.source_system == header.system_id
+                && p.exchange.source_component == header.component_id
+                && p.exchange.target_system == target_system
+                && p.exchange.target_component == target_component
+        });
+        if let Some(retransmission) = retransmission {
+            retransmission.exchange.attempts += 1;
+            retransmission.last_activity_us = entry.timestamp.or(retransmission.last_activity_us);
+            return;
+        }
+        self.pending.push(Pending {
+            index: self.command_count,
+            exchange: CommandExchange {
+                command,
+                kind,
+                source_system: header.system_id,
+                source_component: header.component_id,
+                target_system,
+                target_component,
+                sent_us: entry.timestamp,
+                attempts: 1,
+                result: None,
+                ack_us: None,
+            },
+            last_activity_us: entry.timestamp,
+        });
+        self.command_count += 1;
+    }
+
+    fn finish(mut self) -> Vec<CommandExchange> {
+        self.done
+            .extend(self.pending.into_iter().map(|p| (p.index, p.exchange)));
+        self.done.sort_by_key(|(index, _)| *index);
+        self.done
+            .into_iter()
+            .map(|(_, exchange)| exchange)
+            .collect()
+    }
+}
+
+/// Reads a full log and pairs every COMMAND_LONG and COMMAND_INT with its COMMAND_ACK.
+///
+/// See `CommandTracker` for how commands are paired and to run this analysis along with others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `timeout_us`: The time to wait for an acknowledgement in microseconds.
+///
+/// # Returns
+/// All commands in the order they were first sent.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn command_exchanges<P: MavParser + ?Sized>(
+    parser: &mut P,
+    timeout_us: u64,
+) -> std::io::Result<Vec<CommandExchange>> {
+    analyze(parser, CommandTracker::new(timeout_us))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{
+        COMMAND_ACK_DATA, COMMAND_INT_DATA, COMMAND_LONG_DATA, MavCmd, MavMessage, MavResult,
+    };
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(source: (u8, u8), timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: source.0,
+                component_id: source.1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    fn command_long(command: MavCmd, target: (u8, u8)) -> MavMessage {
+        MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command,
+            target_system: target.0,
+            target_component: target.1,
+            ..Default::default()
+        })
+    }
+
+    fn ack(command: MavCmd, result: MavResult, target: (u8, u8)) -> MavMessage {
+        MavMessage::COMMAND_ACK(COMMAND_ACK_DATA {
+            command,
+            result,
+            target_system: target.0,
+            target_component: target.1,
+            ..Default::default()
+        })
+    }
+
+    /// Test that commands are paired with their acknowledgements.
+    #[test]
+    fn test_command_exchanges() {
+        let gcs = (255, 190);
+        let autopilot = (1, 1);
+        let arm = MavCmd::MAV_CMD_COMPONENT_ARM_DISARM;
+        let takeoff = MavCmd::MAV_CMD_NAV_TAKEOFF;
+        let calibration = MavCmd::MAV_CMD_PREFLIGHT_CALIBRATION;
+        let reposition = MavCmd::MAV_CMD_DO_REPOSITION;
+        let entries = VecDeque::from([
+            // rejected after a retransmission
+            entry(gcs, 0, command_long(arm, autopilot)),
+            entry(gcs, 1_000_000, command_long(arm, autopilot)),
+            entry(
+                autopilot,
+                1_200_000,
+                ack(arm, MavResult::MAV_RESULT_DENIED, gcs),
+            ),
+            // acknowledged while in progress, then accepted
+            entry(gcs, 2_000_000, command_long(calibration, autopilot)),
+            entry(
+                autopilot,
+                2_100_000,
+                ack(calibration, MavResult::MAV_RESULT_IN_PROGRESS, gcs),
+            ),
+            entry(
+                autopilot,
+                5_000_000,
+                ack(calibration, MavResult::MAV_RESULT_ACCEPTED, gcs),
+            ),
+            // never acknowledged
+            entry(gcs, 7_000_000, command_long(takeoff, autopilot)),
+            // acknowledged by an autopilot leaving the target unset
+            entry(
+                gcs,
+                8_000_000,
+                MavMessage::COMMAND_INT(COMMAND_INT_DATA {
+                    command: reposition,
+                    target_system: 1,
+                    target_component: 0,
+                    ..Default::default()
+                }),
+            ),
+            // an acknowledgement for another ground station is ignored
+            entry(
+                autopilot,
+                8_010_000,
+                ack(reposition, MavResult::MAV_RESULT_ACCEPTED, (254, 1)),
+            ),
+            entry(
+                autopilot,
+                8_050_000,
+                ack(reposition, MavResult::MAV_RESULT_ACCEPTED, (0, 0)),
+            ),
+            // the takeoff acknowledgement arrives after the timeout
+            entry(
+                autopilot,
+                11_000_000,
+                ack(takeoff, MavResult::MAV_RESULT_ACCEPTED, gcs),
+            ),
+        ]);
+
+        let exchanges = command_exchanges(&mut EntryList(entries), 3_000_000).unwrap();
+        assert_eq!(exchanges.len(), 4);
+
+        assert_eq!(exchanges[0].command, arm as u16);
+        assert_eq!(exchanges[0].kind, CommandKind::Long);
+        assert_eq!(exchanges[0].attempts, 2);
+        assert_eq!(
+            exchanges[0].result,
+            Some(MavResult::MAV_RESULT_DENIED as u8)
+        );
+        assert!(!exchanges[0].is_accepted());
+        assert_eq!(exchanges[0].latency_us(), Some(1_200_000));
+
+        assert!(exchanges[1].is_accepted());
+        assert_eq!(exchanges[1].latency_us(), Some(3_000_000));
+
+        assert_eq!(exchanges[2].command, takeoff as u16);
+        assert_eq!(exchanges[2].result, None);
+        assert_eq!(exchanges[2].latency_us(), None);
+
+        assert_eq!(exchanges[3].kind, CommandKind::Int);
+        assert_eq!(
+            (exchanges[3].target_system, exchanges[3].target_component),
+            (1, 0)
+        );
+        assert!(exchanges[3].is_accepted());
+        assert_eq!(exchanges[3].latency_us(), Some(50_000));
+    }
+}
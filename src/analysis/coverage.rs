@@ -0,0 +1,458 @@
+//! Coverage of an area by vehicle positions.
+//!
+//! Spraying and seeding operations must show that every part of a field was covered, and for
+//! how long. `CoverageMapper` bins positions into a grid of square cells and reports the time
+//! spent in each, which can be checked against a field boundary with
+//! `CoverageReport::field_coverage` or exported as GeoJSON to be shown as a heatmap.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::analysis::geofence::OperatingArea;
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Mean radius of the earth in meters.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Configuration of a coverage grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageConfig {
+    /// Side of the square cells in meters.
+    pub cell_size_m: f64,
+    /// South west corner of the cell at row and column 0 as (latitude, longitude) in degrees,
+    /// or `None` to align the grid on the first position. The grid is treated as planar, which
+    /// is accurate for areas of a few kilometers away from the poles.
+    pub origin: Option<(f64, f64)>,
+    /// Highest altitude above home in meters at which positions are counted, such as the
+    /// maximum spraying height, or `None` to count every position.
+    pub max_altitude_m: Option<f64>,
+    /// Longest time in microseconds between two positions of a vehicle for the time between
+    /// them to be counted as dwell time. Longer gaps are link losses or pauses.
+    pub max_gap_us: u64,
+}
+
+impl Default for CoverageConfig {
+    /// Provides 5 meter cells aligned on the first position, counting every position and gaps
+    /// of up to 2 seconds.
+    fn default() -> Self {
+        CoverageConfig {
+            cell_size_m: 5.0,
+            origin: None,
+            max_altitude_m: None,
+            max_gap_us: 2_000_000,
+        }
+    }
+}
+
+/// Time spent by vehicles in one cell of a coverage grid.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CoverageCell {
+    /// Number of positions in the cell.
+    pub samples: u64,
+    /// Number of times a vehicle entered the cell.
+    pub visits: u64,
+    /// Time spent in the cell in microseconds. The time between two positions of a vehicle is
+    /// counted in the cell of the first one.
+    pub dwell_us: u64,
+    /// Timestamp of the first position in the cell.
+    pub first_us: Option<u64>,
+    /// Timestamp of the last position in the cell.
+    pub last_us: Option<u64>,
+}
+
+/// Coverage of a field boundary by a coverage grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldCoverage {
+    /// Number of cells whose center is within the field.
+    pub cells_in_field: u64,
+    /// Number of those cells with at least one position.
+    pub cells_covered: u64,
+    /// Rows and columns of the cells within the field without any position.
+    pub uncovered: Vec<(i64, i64)>,
+}
+
+impl FieldCoverage {
+    /// Returns the covered fraction of the field between 0 and 1, 0 for an empty field.
+    pub fn fraction(&self) -> f64 {
+        if self.cells_in_field == 0 {
+            return 0.0;
+        }
+        self.cells_covered as f64 / self.cells_in_field as f64
+    }
+}
+
+/// Cells of a coverage grid with the time vehicles spent in them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageReport {
+    /// South west corner of the cell at row and column 0 as (latitude, longitude) in degrees,
+    /// `None` if the log has no position.
+    pub origin: Option<(f64, f64)>,
+    /// Side of the square cells in meters.
+    pub cell_size_m: f64,
+    /// The cells with at least one position by row, counted north, and column, counted east.
+    pub cells: BTreeMap<(i64, i64), CoverageCell>,
+}
+
+impl CoverageReport {
+    /// Returns the covered area in square meters.
+    pub fn covered_area_m2(&self) -> f64 {
+        self.cells.len() as f64 * self.cell_size_m * self.cell_size_m
+    }
+
+    /// Returns the corners of a cell as (latitude, longitude) in degrees, counter clockwise from
+    /// the south west corner, or `None` if the report has no origin.
+    pub fn cell_corners(&self, row: i64, column: i64) -> Option<[(f64, f64); 4]> {
+        let origin = self.origin?;
+        let corner = |row: i64, column: i64| {
+            from_local_m(
+                origin,
+                column as f64 * self.cell_size_m,
+                row as f64 * self.cell_size_m,
+            )
+        };
+        Some([
+            corner(row, column),
+            corner(row, column + 1),
+            corner(row + 1, column + 1),
+            corner(row + 1, column),
+        ])
+    }
+
+    /// Checks which cells of a field were covered.
+    ///
+    /// A cell is part of the field if its center is within the boundary polygon. Altitude limits
+    /// of the area are ignored.
+    ///
+    /// # Arguments
+    /// - `field`: The field boundary.
+    ///
+    /// # Returns
+    /// The coverage of the field, empty if the report has no origin.
+    pub fn field_coverage(&self, field: &OperatingArea) -> FieldCoverage {
+        let mut coverage = FieldCoverage {
+            cells_in_field: 0,
+            cells_covered: 0,
+            uncovered: Vec::new(),
+        };
+        let Some(origin) = self.origin else {
+            return coverage;
+        };
+        let cell = |&(latitude_deg, longitude_deg): &(f64, f64)| {
+            let (east, north) = to_local_m(origin, latitude_deg, longitude_deg);
+            (
+                (north / self.cell_size_m).floor() as i64,
+                (east / self.cell_size_m).floor() as i64,
+            )
+        };
+        let vertices: Vec<(i64, i64)> = field.polygon.iter().map(cell).collect();
+        let (Some(min_row), Some(max_row)) = (
+            vertices.iter().map(|&(row, _)| row).min(),
+            vertices.iter().map(|&(row, _)| row).max(),
+        ) else {
+            return coverage;
+        };
+        let min_column = vertices
+            .iter()
+            .map(|&(_, column)| column)
+            .min()
+            .unwrap_or(0);
+        let max_column = vertices
+            .iter()
+            .map(|&(_, column)| column)
+            .max()
+            .unwrap_or(0);
+        for row in min_row..=max_row {
+            for column in min_column..=max_column {
+                let (latitude_deg, longitude_deg) = from_local_m(
+                    origin,
+                    (column as f64 + 0.5) * self.cell_size_m,
+                    (row as f64 + 0.5) * self.cell_size_m,
+                );
+                if !field.contains(latitude_deg, longitude_deg) {
+                    continue;
+                }
+                coverage.cells_in_field += 1;
+                if self.cells.contains_key(&(row, column)) {
+                    coverage.cells_covered += 1;
+                } else {
+                    coverage.uncovered.push((row, column));
+                }
+            }
+        }
+        coverage
+    }
+
+    /// Formats the cells as a GeoJSON feature collection of polygons.
+    ///
+    /// Each feature has the `row`, `column`, `samples`, `visits` and `dwell_s` properties of its
+    /// cell. Coordinates are written as (longitude, latitude) as GeoJSON requires.
+    pub fn to_geojson(&self) -> String {
+        let mut json = String::from(r#"{"type":"FeatureCollection","features":["#);
+        for (index, (&(row, column), cell)) in self.cells.iter().enumerate() {
+            let Some(corners) = self.cell_corners(row, column) else {
+                break;
+            };
+            if index > 0 {
+                json.push(',');
+            }
+            // the ring is closed by repeating its first corner
+            let ring: Vec<String> = corners
+                .iter()
+                .chain(&corners[..1])
+                .map(|(latitude_deg, longitude_deg)| {
+                    format!("[{longitude_deg:.8},{latitude_deg:.8}]")
+                })
+                .collect();
+            write!(
+                json,
+                r#"{{"type":"Feature","geometry":{{"type":"Polygon","coordinates":[[{}]]}},"#,
+                ring.join(",")
+            )
+            .unwrap();
+            write!(
+                json,
+                r#""properties":{{"row":{row},"column":{column},"samples":{},"visits":{},"dwell_s":{}}}}}"#,
+                cell.samples,
+                cell.visits,
+                cell.dwell_us as f64 / 1e6
+            )
+            .unwrap();
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Returns the position of a point in meters east and north of an origin.
+fn to_local_m(origin: (f64, f64), latitude_deg: f64, longitude_deg: f64) -> (f64, f64) {
+    let east =
+        (longitude_deg - origin.1).to_radians() * origin.0.to_radians().cos() * EARTH_RADIUS_M;
+    let north = (latitude_deg - origin.0).to_radians() * EARTH_RADIUS_M;
+    (east, north)
+}
+
+/// Returns the (latitude, longitude) in degrees of a point given in meters east and north of an
+/// origin.
+fn from_local_m(origin: (f64, f64), east: f64, north: f64) -> (f64, f64) {
+    let latitude_deg = origin.0 + (north / EARTH_RADIUS_M).to_degrees();
+    let longitude_deg =
+        origin.1 + (east / (EARTH_RADIUS_M * origin.0.to_radians().cos())).to_degrees();
+    (latitude_deg, longitude_deg)
+}
+
+/// Last counted position of a vehicle.
+struct LastPosition {
+    cell: (i64, i64),
+    timestamp_us: Option<u64>,
+}
+
+/// Analyzer binning vehicle positions into a grid of square cells.
+///
+/// Positions are taken from GLOBAL_POSITION_INT messages. Positions at latitude and longitude 0,
+/// sent by autopilots without a position estimate, and positions above the altitude limit are
+/// not counted, and the time until the next counted position is not counted as dwell time.
+pub struct CoverageMapper {
+    config: CoverageConfig,
+    origin: Option<(f64, f64)>,
+    cells: BTreeMap<(i64, i64), CoverageCell>,
+    /// Last counted position by system id.
+    last: BTreeMap<u8, LastPosition>,
+}
+
+impl CoverageMapper {
+    /// Creates a new `CoverageMapper` analyzer.
+    ///
+    /// # Arguments
+    /// - `config`: The grid and the positions to count.
+    pub fn new(config: CoverageConfig) -> Self {
+        CoverageMapper {
+            origin: config.origin,
+            config,
+            cells: BTreeMap::new(),
+            last: BTreeMap::new(),
+        }
+    }
+}
+
+impl<M: Message> Analyzer<M> for CoverageMapper {
+    /// The cells with at least one position.
+    type Report = CoverageReport;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        if msg.message_id() != fields::GLOBAL_POSITION_INT_ID {
+            return;
+        }
+        let payload = fields::payload(msg);
+        let latitude_deg = fields::read_i32(&payload, 4) as f64 / 1e7;
+        let longitude_deg = fields::read_i32(&payload, 8) as f64 / 1e7;
+        let altitude_m = fields::read_i32(&payload, 16) as f64 / 1e3;
+        let counted = (latitude_deg != 0.0 || longitude_deg != 0.0)
+            && self
+                .config
+                .max_altitude_m
+                .is_none_or(|max| altitude_m <= max);
+        if !counted {
+            self.last.remove(&header.system_id);
+            return;
+        }
+        let origin = *self.origin.get_or_insert((latitude_deg, longitude_deg));
+        let (east, north) = to_local_m(origin, latitude_deg, longitude_deg);
+        let cell = (
+            (north / self.config.cell_size_m).floor() as i64,
+            (east / self.config.cell_size_m).floor() as i64,
+        );
+        let previous = self.last.insert(
+            header.system_id,
+            LastPosition {
+                cell,
+                timestamp_us: entry.timestamp,
+            },
+        );
+        let dwell = previous.as_ref().and_then(|previous| {
+            let gap_us = entry.timestamp?.checked_sub(previous.timestamp_us?)?;
+            (gap_us <= self.config.max_gap_us).then_some((previous.cell, gap_us))
+        });
+        if let Some((previous_cell, gap_us)) = dwell {
+            self.cells.entry(previous_cell).or_default().dwell_us += gap_us;
+        }
+        let current = self.cells.entry(cell).or_default();
+        current.samples += 1;
+        if previous.is_none_or(|previous| previous.cell != cell) {
+            current.visits += 1;
+        }
+        current.first_us = current.first_us.or(entry.timestamp);
+        current.last_us = entry.timestamp.or(current.last_us);
+    }
+
+    fn finish(self) -> CoverageReport {
+        CoverageReport {
+            origin: self.origin,
+            cell_size_m: self.config.cell_size_m,
+            cells: self.cells,
+        }
+    }
+}
+
+/// Reads a full log and bins the positions of its vehicles into a grid of square cells.
+///
+/// See `CoverageMapper` for which positions are counted and to run this analysis along with
+/// others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `config`: The grid and the positions to count.
+///
+/// # Returns
+/// The cells with at least one position.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn coverage_grid<P: MavParser + ?Sized>(
+    parser: &mut P,
+    config: &CoverageConfig,
+) -> std::io::Result<CoverageReport> {
+    analyze(parser, CoverageMapper::new(config.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    const ORIGIN: (f64, f64) = (47.0, 8.0);
+
+    /// Returns a position given in meters east and north of the origin.
+    fn position(timestamp: u64, east: f64, north: f64, altitude_m: f64) -> LogEntry<MavMessage> {
+        let (latitude_deg, longitude_deg) = from_local_m(ORIGIN, east, north);
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                lat: (latitude_deg * 1e7).round() as i32,
+                lon: (longitude_deg * 1e7).round() as i32,
+                relative_alt: (altitude_m * 1e3).round() as i32,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// Test that positions are binned with their dwell time and checked against a field.
+    #[test]
+    fn test_coverage_grid() {
+        let mut no_position = position(0, 0.0, 0.0, 5.0);
+        no_position.mav_message = Some(MavMessage::GLOBAL_POSITION_INT(Default::default()));
+        let entries = VecDeque::from([
+            no_position,
+            position(0, 5.0, 5.0, 5.0),
+            position(1_000_000, 6.0, 4.0, 5.0),
+            position(2_000_000, 15.0, 5.0, 5.0),
+            // climbing above the spraying height
+            position(3_000_000, 15.0, 5.0, 50.0),
+            position(4_000_000, 5.0, 5.0, 5.0),
+            // after a link loss
+            position(10_000_000, 5.0, 15.0, 5.0),
+        ]);
+        let config = CoverageConfig {
+            cell_size_m: 10.0,
+            origin: Some(ORIGIN),
+            max_altitude_m: Some(20.0),
+            ..CoverageConfig::default()
+        };
+        let report = coverage_grid(&mut EntryList(entries), &config).unwrap();
+        assert_eq!(report.cells.len(), 3);
+        assert_eq!(
+            report.cells[&(0, 0)],
+            CoverageCell {
+                samples: 3,
+                visits: 2,
+                dwell_us: 2_000_000,
+                first_us: Some(0),
+                last_us: Some(4_000_000),
+            }
+        );
+        assert_eq!(report.cells[&(0, 1)].dwell_us, 0);
+        assert_eq!(report.cells[&(1, 0)].visits, 1);
+        assert_eq!(report.covered_area_m2(), 300.0);
+        assert_eq!(report.cell_corners(0, 0).unwrap()[0], ORIGIN);
+
+        let field = OperatingArea {
+            polygon: [(1.0, 1.0), (19.0, 1.0), (19.0, 19.0), (1.0, 19.0)]
+                .into_iter()
+                .map(|(east, north)| from_local_m(ORIGIN, east, north))
+                .collect(),
+            min_altitude_m: None,
+            max_altitude_m: None,
+        };
+        let coverage = report.field_coverage(&field);
+        assert_eq!(coverage.cells_in_field, 4);
+        assert_eq!(coverage.uncovered, vec![(1, 1)]);
+        assert_eq!(coverage.fraction(), 0.75);
+
+        let geojson = report.to_geojson();
+        assert!(geojson.starts_with(r#"{"type":"FeatureCollection","features":[{"#));
+        assert_eq!(geojson.matches(r#""type":"Feature","#).count(), 3);
+        assert!(
+            geojson.contains(
+                r#""properties":{"row":0,"column":0,"samples":3,"visits":2,"dwell_s":2}"#
+            )
+        );
+        assert!(geojson.ends_with("}]}"));
+    }
+}
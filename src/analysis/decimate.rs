@@ -0,0 +1,277 @@
+//! A parser adapter thinning out high rate messages.
+//!
+//! Plotting a long log does not need every ATTITUDE or IMU sample. `Decimate` keeps a subset of
+//! the messages it is configured for and passes every other entry through unchanged.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+use mavlink::error::MessageReadError;
+
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// How often a message is kept by `Decimate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rate {
+    /// Keep the first of every N messages. 0 and 1 keep every message.
+    EveryNth(u32),
+    /// Keep at most the given number of messages per second, based on entry timestamps.
+    /// Messages without a timestamp are kept.
+    MaxHz(f64),
+}
+
+/// Decimation state of a single message id from a single component.
+#[derive(Default)]
+struct Stream {
+    /// Number of messages seen since the last one kept.
+    skipped: u32,
+    /// Timestamp of the last message kept.
+    last_kept_us: Option<u64>,
+}
+
+/// Parser adapter yielding only a subset of high rate messages.
+///
+/// Messages are decimated separately per message id and sending component, so that the
+/// messages of each vehicle are thinned out evenly.
+pub struct Decimate<P: MavParser> {
+    parser: P,
+    decimator: Decimator,
+}
+
+impl<P: MavParser> Decimate<P> {
+    /// Creates a new `Decimate` adapter.
+    ///
+    /// # Arguments
+    /// - `parser`: The parser to read entries from.
+    /// - `rate_per_msgid`: The rate to keep messages at by MAVLink message id. Messages with
+    ///   other ids and entries that are not MAVLink messages are all kept.
+    pub fn new(parser: P, rate_per_msgid: BTreeMap<u32, Rate>) -> Self {
+        Self {
+            parser,
+            decimator: Decimator::new(rate_per_msgid),
+        }
+    }
+
+    /// Returns the number of messages left out so far.
+    pub fn dropped(&self) -> u64 {
+        self.decimator.dropped()
+    }
+
+    /// Returns the wrapped parser.
+    pub fn into_inner(self) -> P {
+        self.parser
+    }
+}
+
+/// Decimation state shared by `Decimate` and the live rate limits of a recorder.
+pub(crate) struct Decimator {
+    rates: BTreeMap<u32, Rate>,
+    default_rate: Option<Rate>,
+    streams: BTreeMap<(u32, u8, u8), Stream>,
+    dropped: u64,
+}
+
+impl Decimator {
+    /// Creates a new `Decimator` keeping messages at the given rates by message id.
+    pub(crate) fn new(rate_per_msgid: BTreeMap<u32, Rate>) -> Self {
+        Self::with_default_rate(rate_per_msgid, None)
+    }
+
+    /// Creates a new `Decimator` keeping messages at the given rates by message id, and
+    /// messages with other ids at the default rate, if any.
+    pub(crate) fn with_default_rate(
+        rate_per_msgid: BTreeMap<u32, Rate>,
+        default_rate: Option<Rate>,
+    ) -> Self {
+        Self {
+            rates: rate_per_msgid,
+            default_rate,
+            streams: BTreeMap::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Returns the number of messages left out so far.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Decides whether an entry is passed through.
+    pub(crate) fn keep<M: Message>(&mut self, entry: &LogEntry<M>) -> bool {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return true,
+        };
+        self.keep_message(
+            msg.message_id(),
+            header.system_id,
+            header.component_id,
+            entry.timestamp,
+        )
+    }
+
+    /// Decides whether a message is passed through, given its id, its sender and the timestamp
+    /// of its entry.
+    pub(crate) fn keep_message(
+        &mut self,
+        msg_id: u32,
+        system_id: u8,
+        component_id: u8,
+        timestamp: Option<u64>,
+    ) -> bool {
+        let Some(rate) = self.rates.get(&msg_id).copied().or(self.default_rate) else {
+            return true;
+        };
+        let stream = self
+            .streams
+            .entry((msg_id, system_id, component_id))
+            .or_default();
+        let keep = match rate {
+            Rate::EveryNth(n) => {
+                let keep = stream.skipped == 0;
+                stream.skipped += 1;
+                if stream.skipped >= n {
+                    stream.skipped = 0;
+                }
+                keep
+            }
+            Rate::MaxHz(hz) => match (timestamp, stream.last_kept_us) {
+                (Some(timestamp), Some(last_kept_us)) if timestamp >= last_kept_us => {
+                    let period_us = 1e6 / hz;
+                    (timestamp - last_kept_us) as f64 >= period_us
+                }
+                // keep the first message, and restart after a timestamp going backwards
+                _ => true,
+            },
+        };
+        if keep {
+            stream.last_kept_us = timestamp;
+        } else {
+            self.dropped += 1;
+        }
+        keep
+    }
+}
+
+impl<P: MavParser> MavParser for Decimate<P> {
+    type M = P::M;
+
+    /// Reads the next entry that is not decimated away.
+    ///
+    /// # Errors
+    /// Returns the errors of the wrapped parser unchanged.
+    fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+        loop {
+            let entry = self.parser.parse_next_entry()?;
+            if self.decimator.keep(&entry) {
+                return Ok(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::MavMessage;
+
+    use super::*;
+    use crate::mav_parser::{EntryList, for_each_entry};
+
+    fn entry(system_id: u8, timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    /// Reads all entries kept from a list of 100 Hz ATTITUDE messages of two systems with a
+    /// HEARTBEAT and a text entry every second.
+    fn decimate(rate: Rate) -> (Vec<(u8, u64)>, usize, u64) {
+        let mut entries: VecDeque<LogEntry<MavMessage>> = VecDeque::new();
+        for i in 0..200u64 {
+            let timestamp = i * 10_000;
+            for system_id in [1, 2] {
+                entries.push_back(entry(
+                    system_id,
+                    timestamp,
+                    MavMessage::ATTITUDE(Default::default()),
+                ));
+            }
+            if i % 100 == 0 {
+                entries.push_back(entry(
+                    1,
+                    timestamp,
+                    MavMessage::HEARTBEAT(Default::default()),
+                ));
+                entries.push_back(LogEntry {
+                    text: Some(String::from("note")),
+                    ..Default::default()
+                });
+            }
+        }
+        let mut parser = Decimate::new(EntryList(entries), BTreeMap::from([(30, rate)]));
+        let mut attitudes: Vec<(u8, u64)> = Vec::new();
+        let mut others: usize = 0;
+        for_each_entry(&mut parser, |entry| {
+            match entry.mav_message {
+                Some(MavMessage::ATTITUDE(_)) => attitudes.push((
+                    entry.mav_header.unwrap().system_id,
+                    entry.timestamp.unwrap(),
+                )),
+                _ => others += 1,
+            }
+            Ok(())
+        })
+        .unwrap();
+        (attitudes, others, parser.dropped())
+    }
+
+    /// Test that every Nth message is kept per system.
+    #[test]
+    fn test_decimate_every_nth() {
+        let (attitudes, others, dropped) = decimate(Rate::EveryNth(50));
+        assert_eq!(
+            attitudes,
+            vec![
+                (1, 0),
+                (2, 0),
+                (1, 500_000),
+                (2, 500_000),
+                (1, 1_000_000),
+                (2, 1_000_000),
+                (1, 1_500_000),
+                (2, 1_500_000)
+            ]
+        );
+        assert_eq!(others, 4);
+        assert_eq!(dropped, 392);
+    }
+
+    /// Test that messages are kept at most at the given rate per system.
+    #[test]
+    fn test_decimate_max_hz() {
+        let (attitudes, others, dropped) = decimate(Rate::MaxHz(4.0));
+        let system_1: Vec<u64> = attitudes
+            .iter()
+            .filter(|(system_id, _)| *system_id == 1)
+            .map(|(_, timestamp)| *timestamp)
+            .collect();
+        assert_eq!(
+            system_1,
+            vec![
+                0, 250_000, 500_000, 750_000, 1_000_000, 1_250_000, 1_500_000, 1_750_000
+            ]
+        );
+        assert_eq!(attitudes.len(), 16);
+        assert_eq!(others, 4);
+        assert_eq!(dropped, 384);
+    }
+}
@@ -0,0 +1,105 @@
+//! Discovery of the MAVLink systems and components present in a log.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Summary of a single MAVLink system/component pair seen in a log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemInfo {
+    /// MAVLink system id.
+    pub system_id: u8,
+    /// MAVLink component id.
+    pub component_id: u8,
+    /// MAV_TYPE enum value from the most recent HEARTBEAT, if one was seen.
+    pub mav_type: Option<u8>,
+    /// MAV_AUTOPILOT enum value from the most recent HEARTBEAT, if one was seen.
+    pub autopilot: Option<u8>,
+    /// Timestamp of the first entry from this component, if the log provides timestamps.
+    pub first_seen_us: Option<u64>,
+    /// Timestamp of the last entry from this component, if the log provides timestamps.
+    pub last_seen_us: Option<u64>,
+    /// Number of MAVLink messages received from this component.
+    pub message_count: u64,
+}
+
+impl SystemInfo {
+    fn new(system_id: u8, component_id: u8) -> Self {
+        SystemInfo {
+            system_id,
+            component_id,
+            mav_type: None,
+            autopilot: None,
+            first_seen_us: None,
+            last_seen_us: None,
+            message_count: 0,
+        }
+    }
+}
+
+/// Analyzer collecting every system/component pair that sent a MAVLink message.
+///
+/// The MAV_TYPE and autopilot are taken from HEARTBEAT messages. Components that never sent a
+/// HEARTBEAT are still reported but without type information.
+#[derive(Default)]
+pub struct SystemDiscovery {
+    systems: BTreeMap<(u8, u8), SystemInfo>,
+}
+
+impl SystemDiscovery {
+    /// Creates a new `SystemDiscovery` analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<M: Message> Analyzer<M> for SystemDiscovery {
+    /// A list of `SystemInfo` ordered by system id then component id.
+    type Report = Vec<SystemInfo>;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        let info = self
+            .systems
+            .entry((header.system_id, header.component_id))
+            .or_insert_with(|| SystemInfo::new(header.system_id, header.component_id));
+        info.message_count += 1;
+        if let Some(timestamp) = entry.timestamp {
+            if info.first_seen_us.is_none() {
+                info.first_seen_us = Some(timestamp);
+            }
+            info.last_seen_us = Some(timestamp);
+        }
+        if msg.message_id() == fields::HEARTBEAT_ID {
+            let payload = fields::payload(msg);
+            info.mav_type = Some(fields::read_u8(&payload, 4));
+            info.autopilot = Some(fields::read_u8(&payload, 5));
+        }
+    }
+
+    fn finish(self) -> Vec<SystemInfo> {
+        self.systems.into_values().collect()
+    }
+}
+
+/// Reads a full log and reports every system/component pair that sent a MAVLink message.
+///
+/// See `SystemDiscovery` to run this analysis along with others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+///
+/// # Returns
+/// A list of `SystemInfo` ordered by system id then component id.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn discover_systems<P: MavParser + ?Sized>(parser: &mut P) -> std::io::Result<Vec<SystemInfo>> {
+    analyze(parser, SystemDiscovery::new())
+}
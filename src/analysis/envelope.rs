@@ -0,0 +1,397 @@
+//! Flight envelope of each armed segment of a log.
+//!
+//! Summarizing a few key channels per flight gives fleet health dashboards comparable numbers
+//! across vehicles without replaying whole logs.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// MAV_MODE_FLAG_SAFETY_ARMED bit of the HEARTBEAT base mode.
+const SAFETY_ARMED: u8 = 0x80;
+/// MAV_AUTOPILOT_INVALID, the autopilot of components that are not flight controllers.
+const AUTOPILOT_INVALID: u8 = 8;
+/// SYS_STATUS battery voltage when it is not reported.
+const VOLTAGE_UNKNOWN: u16 = u16::MAX;
+/// SYS_STATUS battery current when it is not reported.
+const CURRENT_UNKNOWN: i16 = -1;
+
+/// Minimum, maximum and mean of the samples of a channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Number of samples.
+    pub count: u64,
+}
+
+/// Running statistics of a channel.
+#[derive(Default)]
+struct Accumulator {
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u64,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn stats(&self) -> Option<ChannelStats> {
+        (self.count > 0).then(|| ChannelStats {
+            min: self.min,
+            max: self.max,
+            mean: self.sum / self.count as f64,
+            count: self.count,
+        })
+    }
+}
+
+/// Summary of the key channels of a system while it was armed.
+///
+/// A channel is `None` if the system did not report it during the segment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArmedSegment {
+    /// MAVLink system id of the vehicle.
+    pub system_id: u8,
+    /// Timestamp of the HEARTBEAT reporting the vehicle armed, if the log provides timestamps.
+    pub start_us: Option<u64>,
+    /// Timestamp of the HEARTBEAT reporting the vehicle disarmed, or of the last message of the
+    /// vehicle if the log ends while it is armed.
+    pub end_us: Option<u64>,
+    /// Altitude above home from GLOBAL_POSITION_INT, in meters.
+    pub relative_altitude_m: Option<ChannelStats>,
+    /// Groundspeed from VFR_HUD, in meters per second.
+    pub groundspeed_m_s: Option<ChannelStats>,
+    /// Battery voltage from SYS_STATUS, in volts.
+    pub battery_voltage_v: Option<ChannelStats>,
+    /// Battery current from SYS_STATUS, in amperes.
+    pub battery_current_a: Option<ChannelStats>,
+    /// Roll rate from ATTITUDE, in radians per second.
+    pub roll_rate_rad_s: Option<ChannelStats>,
+    /// Pitch rate from ATTITUDE, in radians per second.
+    pub pitch_rate_rad_s: Option<ChannelStats>,
+    /// Yaw rate from ATTITUDE, in radians per second.
+    pub yaw_rate_rad_s: Option<ChannelStats>,
+    /// Wind speed estimated by the autopilot from WIND_COV, in meters per second.
+    pub wind_speed_m_s: Option<ChannelStats>,
+}
+
+/// Channels of an armed segment being accumulated.
+struct OpenSegment {
+    start_us: Option<u64>,
+    end_us: Option<u64>,
+    relative_altitude_m: Accumulator,
+    groundspeed_m_s: Accumulator,
+    battery_voltage_v: Accumulator,
+    battery_current_a: Accumulator,
+    roll_rate_rad_s: Accumulator,
+    pitch_rate_rad_s: Accumulator,
+    yaw_rate_rad_s: Accumulator,
+    wind_speed_m_s: Accumulator,
+}
+
+impl OpenSegment {
+    fn new(start_us: Option<u64>) -> Self {
+        OpenSegment {
+            start_us,
+            end_us: start_us,
+            relative_altitude_m: Accumulator::default(),
+            groundspeed_m_s: Accumulator::default(),
+            battery_voltage_v: Accumulator::default(),
+            battery_current_a: Accumulator::default(),
+            roll_rate_rad_s: Accumulator::default(),
+            pitch_rate_rad_s: Accumulator::default(),
+            yaw_rate_rad_s: Accumulator::default(),
+            wind_speed_m_s: Accumulator::default(),
+        }
+    }
+
+    /// Adds the channels carried by a message.
+    fn add(&mut self, msg_id: u32, payload: &[u8]) {
+        match msg_id {
+            fields::SYS_STATUS_ID => {
+                let voltage = fields::read_u16(payload, 14);
+                if voltage != VOLTAGE_UNKNOWN {
+                    self.battery_voltage_v.add(voltage as f64 / 1e3);
+                }
+                let current = fields::read_i16(payload, 16);
+                if current != CURRENT_UNKNOWN {
+                    self.battery_current_a.add(current as f64 / 1e2);
+                }
+            }
+            fields::ATTITUDE_ID => {
+                self.roll_rate_rad_s
+                    .add(fields::read_f32(payload, 16) as f64);
+                self.pitch_rate_rad_s
+                    .add(fields::read_f32(payload, 20) as f64);
+                self.yaw_rate_rad_s
+                    .add(fields::read_f32(payload, 24) as f64);
+            }
+            fields::GLOBAL_POSITION_INT_ID => {
+                self.relative_altitude_m
+                    .add(fields::read_i32(payload, 16) as f64 / 1e3);
+            }
+            fields::VFR_HUD_ID => {
+                self.groundspeed_m_s
+                    .add(fields::read_f32(payload, 4) as f64);
+            }
+            fields::WIND_COV_ID => {
+                let north = fields::read_f32(payload, 8) as f64;
+                let east = fields::read_f32(payload, 12) as f64;
+                // autopilots without a wind estimate report NaN
+                if north.is_finite() && east.is_finite() {
+                    self.wind_speed_m_s.add(north.hypot(east));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn close(self, system_id: u8) -> ArmedSegment {
+        ArmedSegment {
+            system_id,
+            start_us: self.start_us,
+            end_us: self.end_us,
+            relative_altitude_m: self.relative_altitude_m.stats(),
+            groundspeed_m_s: self.groundspeed_m_s.stats(),
+            battery_voltage_v: self.battery_voltage_v.stats(),
+            battery_current_a: self.battery_current_a.stats(),
+            roll_rate_rad_s: self.roll_rate_rad_s.stats(),
+            pitch_rate_rad_s: self.pitch_rate_rad_s.stats(),
+            yaw_rate_rad_s: self.yaw_rate_rad_s.stats(),
+            wind_speed_m_s: self.wind_speed_m_s.stats(),
+        }
+    }
+}
+
+/// Analyzer summarizing the key channels of every vehicle over each armed segment.
+///
+/// A vehicle is armed while the HEARTBEATs of its flight controller, any component of the
+/// system with an autopilot other than MAV_AUTOPILOT_INVALID, have the safety armed flag set.
+/// The channels of every component of the system are attributed to its armed segments, and
+/// messages sent while disarmed are ignored.
+#[derive(Default)]
+pub struct FlightEnvelope {
+    /// Segments of the vehicles currently armed, with their position among all segments.
+    open: BTreeMap<u8, (usize, OpenSegment)>,
+    closed: Vec<(usize, ArmedSegment)>,
+    segment_count: usize,
+}
+
+impl FlightEnvelope {
+    /// Creates a new `FlightEnvelope` analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<M: Message> Analyzer<M> for FlightEnvelope {
+    /// The armed segments of all vehicles in the order they were armed.
+    type Report = Vec<ArmedSegment>;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        let msg_id = msg.message_id();
+        let payload = fields::payload(msg);
+        if msg_id == fields::HEARTBEAT_ID && fields::read_u8(&payload, 5) != AUTOPILOT_INVALID {
+            let armed = fields::read_u8(&payload, 6) & SAFETY_ARMED != 0;
+            if armed && !self.open.contains_key(&header.system_id) {
+                let segment = OpenSegment::new(entry.timestamp);
+                self.open
+                    .insert(header.system_id, (self.segment_count, segment));
+                self.segment_count += 1;
+            } else if !armed {
+                if let Some((index, mut segment)) = self.open.remove(&header.system_id) {
+                    segment.end_us = entry.timestamp.or(segment.end_us);
+                    self.closed.push((index, segment.close(header.system_id)));
+                }
+            }
+            return;
+        }
+        if let Some((_, segment)) = self.open.get_mut(&header.system_id) {
+            segment.end_us = entry.timestamp.or(segment.end_us);
+            segment.add(msg_id, &payload);
+        }
+    }
+
+    fn finish(mut self) -> Vec<ArmedSegment> {
+        for (system_id, (index, segment)) in self.open {
+            self.closed.push((index, segment.close(system_id)));
+        }
+        self.closed.sort_by_key(|(index, _)| *index);
+        self.closed
+            .into_iter()
+            .map(|(_, segment)| segment)
+            .collect()
+    }
+}
+
+/// Reads a full log and summarizes the key channels of every vehicle over each armed segment.
+///
+/// See `FlightEnvelope` for how armed segments are determined and to run this analysis along
+/// with others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+///
+/// # Returns
+/// The armed segments of all vehicles in the order they were armed.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn flight_envelope<P: MavParser + ?Sized>(
+    parser: &mut P,
+) -> std::io::Result<Vec<ArmedSegment>> {
+    analyze(parser, FlightEnvelope::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{
+        ATTITUDE_DATA, GLOBAL_POSITION_INT_DATA, HEARTBEAT_DATA, MavAutopilot, MavMessage,
+        MavModeFlag, SYS_STATUS_DATA, VFR_HUD_DATA, WIND_COV_DATA,
+    };
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(system_id: u8, timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    fn heartbeat(autopilot: MavAutopilot, armed: bool) -> MavMessage {
+        let base_mode = if armed {
+            MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED
+        } else {
+            MavModeFlag::empty()
+        };
+        MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            autopilot,
+            base_mode,
+            ..Default::default()
+        })
+    }
+
+    fn sys_status(voltage_battery: u16, current_battery: i16) -> MavMessage {
+        MavMessage::SYS_STATUS(SYS_STATUS_DATA {
+            voltage_battery,
+            current_battery,
+            ..Default::default()
+        })
+    }
+
+    /// Test that channels are summarized per armed segment and vehicle.
+    #[test]
+    fn test_flight_envelope() {
+        let px4 = MavAutopilot::MAV_AUTOPILOT_PX4;
+        let gcs = MavAutopilot::MAV_AUTOPILOT_INVALID;
+        let entries = VecDeque::from([
+            entry(1, 0, sys_status(12600, 50)),
+            entry(1, 1_000, heartbeat(px4, true)),
+            entry(2, 1_500, heartbeat(px4, true)),
+            // a ground station reporting disarmed does not end the segment
+            entry(1, 2_000, heartbeat(gcs, false)),
+            entry(1, 3_000, sys_status(12400, 1500)),
+            entry(1, 4_000, sys_status(12000, CURRENT_UNKNOWN)),
+            entry(
+                1,
+                5_000,
+                MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                    relative_alt: 25_500,
+                    ..Default::default()
+                }),
+            ),
+            entry(
+                1,
+                6_000,
+                MavMessage::ATTITUDE(ATTITUDE_DATA {
+                    rollspeed: -0.5,
+                    pitchspeed: 0.25,
+                    yawspeed: 1.0,
+                    ..Default::default()
+                }),
+            ),
+            entry(
+                2,
+                6_200,
+                MavMessage::WIND_COV(WIND_COV_DATA {
+                    wind_x: 3.0,
+                    wind_y: -4.0,
+                    ..Default::default()
+                }),
+            ),
+            entry(
+                2,
+                6_500,
+                MavMessage::VFR_HUD(VFR_HUD_DATA {
+                    groundspeed: 8.0,
+                    ..Default::default()
+                }),
+            ),
+            entry(1, 7_000, heartbeat(px4, false)),
+            entry(1, 8_000, sys_status(11000, 0)),
+            entry(1, 9_000, heartbeat(px4, true)),
+            entry(1, 10_000, sys_status(11500, 200)),
+        ]);
+
+        let segments = flight_envelope(&mut EntryList(entries)).unwrap();
+        assert_eq!(segments.len(), 3);
+
+        let first = &segments[0];
+        assert_eq!(first.system_id, 1);
+        assert_eq!((first.start_us, first.end_us), (Some(1_000), Some(7_000)));
+        let voltage = first.battery_voltage_v.unwrap();
+        assert_eq!((voltage.min, voltage.max, voltage.count), (12.0, 12.4, 2));
+        assert!((voltage.mean - 12.2).abs() < 1e-9);
+        let current = first.battery_current_a.unwrap();
+        assert_eq!((current.min, current.max, current.count), (15.0, 15.0, 1));
+        assert_eq!(first.relative_altitude_m.unwrap().max, 25.5);
+        assert_eq!(first.roll_rate_rad_s.unwrap().min, -0.5);
+        assert_eq!(first.pitch_rate_rad_s.unwrap().mean, 0.25);
+        assert_eq!(first.yaw_rate_rad_s.unwrap().max, 1.0);
+        assert!(first.groundspeed_m_s.is_none());
+        assert!(first.wind_speed_m_s.is_none());
+
+        let second = &segments[1];
+        assert_eq!(second.system_id, 2);
+        assert_eq!((second.start_us, second.end_us), (Some(1_500), Some(6_500)));
+        assert_eq!(second.groundspeed_m_s.unwrap().mean, 8.0);
+        assert_eq!(second.wind_speed_m_s.unwrap().max, 5.0);
+        assert!(second.battery_voltage_v.is_none());
+
+        // the last segment is still armed at the end of the log
+        let third = &segments[2];
+        assert_eq!((third.start_us, third.end_us), (Some(9_000), Some(10_000)));
+        assert_eq!(third.battery_voltage_v.unwrap().count, 1);
+    }
+}
@@ -0,0 +1,240 @@
+//! Aggregate features of channels over sliding time windows.
+//!
+//! Anomaly detection models are trained on fixed size feature vectors rather than raw
+//! telemetry. `window_features` slides a window over resampled channels and computes aggregates
+//! such as the mean or rate of change of each channel per window, so a log is turned into a
+//! training matrix without a separate ETL stage. `extract_features` resamples and aggregates a
+//! log in one call. With the `ml` feature, features convert to an `ndarray::Array2`.
+use std::fmt::Debug;
+
+use crate::analysis::resample::{Interpolation, ResampledChannels, resample};
+use crate::mav_parser::MavParser;
+
+/// Aggregate computed over the values of a channel in a window.
+///
+/// NaN values, left by the resampler before the first sample and in gaps, are skipped. An
+/// aggregate of a window without enough values is NaN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// Arithmetic mean.
+    Mean,
+    /// Population variance.
+    Variance,
+    /// Smallest value.
+    Min,
+    /// Largest value.
+    Max,
+    /// Change between the first and last value per second.
+    RateOfChange,
+}
+
+impl Aggregate {
+    /// Returns the suffix naming the aggregate in feature names.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Mean => "mean",
+            Aggregate::Variance => "var",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+            Aggregate::RateOfChange => "rate",
+        }
+    }
+
+    /// Computes the aggregate of the values of a window.
+    ///
+    /// # Arguments
+    /// - `values`: The values of the window.
+    /// - `period_us`: The time between consecutive values in microseconds.
+    fn compute(&self, values: &[f64], period_us: u64) -> f64 {
+        let valid = || values.iter().copied().filter(|value| !value.is_nan());
+        let count = valid().count();
+        if count == 0 {
+            return f64::NAN;
+        }
+        match self {
+            Aggregate::Mean => valid().sum::<f64>() / count as f64,
+            Aggregate::Variance => {
+                let mean = valid().sum::<f64>() / count as f64;
+                valid().map(|value| (value - mean).powi(2)).sum::<f64>() / count as f64
+            }
+            Aggregate::Min => valid().fold(f64::INFINITY, f64::min),
+            Aggregate::Max => valid().fold(f64::NEG_INFINITY, f64::max),
+            Aggregate::RateOfChange => {
+                let first = values.iter().position(|value| !value.is_nan());
+                let last = values.iter().rposition(|value| !value.is_nan());
+                match (first, last) {
+                    (Some(first), Some(last)) if last > first => {
+                        let elapsed_s = ((last - first) as u64 * period_us) as f64 / 1e6;
+                        (values[last] - values[first]) / elapsed_s
+                    }
+                    _ => f64::NAN,
+                }
+            }
+        }
+    }
+}
+
+/// Features of consecutive windows, one row per window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowFeatures {
+    /// Names of the features, `MESSAGE.field:aggregate` such as `ATTITUDE.roll:mean`, grouped by
+    /// channel.
+    pub names: Vec<String>,
+    /// Timestamp of the start of each window.
+    pub window_start_us: Vec<u64>,
+    /// Feature values of each window, in the order of `names`.
+    pub rows: Vec<Vec<f64>>,
+}
+
+impl WindowFeatures {
+    /// Converts the features to a matrix with one row per window and one column per feature.
+    #[cfg(feature = "ml")]
+    pub fn to_ndarray(&self) -> ndarray::Array2<f64> {
+        let mut matrix = ndarray::Array2::from_elem((self.rows.len(), self.names.len()), f64::NAN);
+        for (mut target, row) in matrix.rows_mut().into_iter().zip(&self.rows) {
+            target.assign(&ndarray::ArrayView1::from(row.as_slice()));
+        }
+        matrix
+    }
+}
+
+/// Computes aggregate features of resampled channels over sliding windows.
+///
+/// Windows start at the first grid point and advance by the stride. Only complete windows are
+/// output, so a log shorter than one window has no features.
+///
+/// # Arguments
+/// - `resampled`: The channels, as returned by `resample`.
+/// - `window_us`: The length of each window in microseconds, rounded down to whole grid periods
+///   and at least one.
+/// - `stride_us`: The time between the start of consecutive windows in microseconds, rounded
+///   down to whole grid periods and at least one.
+/// - `aggregates`: The aggregates to compute for each channel.
+///
+/// # Returns
+/// The features of each window.
+pub fn window_features(
+    resampled: &ResampledChannels,
+    window_us: u64,
+    stride_us: u64,
+    aggregates: &[Aggregate],
+) -> WindowFeatures {
+    let period_us = resampled.period_us;
+    let window = ((window_us / period_us) as usize).max(1);
+    let stride = ((stride_us / period_us) as usize).max(1);
+    let names = resampled
+        .channels
+        .iter()
+        .flat_map(|channel| {
+            aggregates
+                .iter()
+                .map(move |aggregate| format!("{channel}:{}", aggregate.name()))
+        })
+        .collect();
+    let mut features = WindowFeatures {
+        names,
+        window_start_us: Vec::new(),
+        rows: Vec::new(),
+    };
+    let mut start = 0;
+    while start + window <= resampled.len() {
+        features.window_start_us.push(resampled.timestamp_us(start));
+        features.rows.push(
+            resampled
+                .values
+                .iter()
+                .flat_map(|values| {
+                    let window_values = &values[start..start + window];
+                    aggregates
+                        .iter()
+                        .map(move |aggregate| aggregate.compute(window_values, period_us))
+                })
+                .collect(),
+        );
+        start += stride;
+    }
+    features
+}
+
+/// Resamples channels of a log and computes their features over sliding windows.
+///
+/// Channels are resampled with a zero-order hold, see `resample` and `window_features`.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `channels`: The channels to aggregate, named `MESSAGE.field`.
+/// - `period_us`: The period the channels are resampled at in microseconds.
+/// - `window_us`: The length of each window in microseconds.
+/// - `stride_us`: The time between the start of consecutive windows in microseconds.
+/// - `aggregates`: The aggregates to compute for each channel.
+///
+/// # Returns
+/// The features of each window.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read, or of kind `InvalidInput` if a channel
+/// name has no `.`.
+///
+/// # Panics
+/// Panics if `period_us` is 0.
+pub fn extract_features<P>(
+    parser: &mut P,
+    channels: &[&str],
+    period_us: u64,
+    window_us: u64,
+    stride_us: u64,
+    aggregates: &[Aggregate],
+) -> std::io::Result<WindowFeatures>
+where
+    P: MavParser + ?Sized,
+    P::M: Debug,
+{
+    let resampled = resample(parser, channels, period_us, Interpolation::ZeroOrderHold)?;
+    Ok(window_features(
+        &resampled, window_us, stride_us, aggregates,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that aggregates are computed per window, skipping missing values.
+    #[test]
+    fn test_window_features() {
+        let resampled = ResampledChannels {
+            start_us: 1_000_000,
+            period_us: 500_000,
+            channels: vec![String::from("VFR_HUD.alt")],
+            values: vec![vec![f64::NAN, 10.0, 12.0, 14.0, 13.0, 13.0]],
+        };
+        let aggregates = [
+            Aggregate::Mean,
+            Aggregate::Variance,
+            Aggregate::Min,
+            Aggregate::Max,
+            Aggregate::RateOfChange,
+        ];
+        let features = window_features(&resampled, 1_500_000, 1_000_000, &aggregates);
+        assert_eq!(
+            features.names,
+            vec![
+                "VFR_HUD.alt:mean",
+                "VFR_HUD.alt:var",
+                "VFR_HUD.alt:min",
+                "VFR_HUD.alt:max",
+                "VFR_HUD.alt:rate"
+            ]
+        );
+        assert_eq!(features.window_start_us, vec![1_000_000, 2_000_000]);
+        assert_eq!(features.rows[0], vec![11.0, 1.0, 10.0, 12.0, 4.0]);
+        assert_eq!(features.rows[1], vec![13.0, 2.0 / 3.0, 12.0, 14.0, 1.0]);
+
+        #[cfg(feature = "ml")]
+        {
+            let matrix = features.to_ndarray();
+            assert_eq!(matrix.shape(), &[2, 5]);
+            assert_eq!(matrix[[1, 3]], 14.0);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! Entry filters written as small boolean expressions.
+//!
+//! `ParserFilter::from_expr` parses expressions such as
+//! `msg == 'ATTITUDE' && sysid == 1 && t > 120s`, so that a filter can be given on a command
+//! line or in a configuration file instead of being written as a closure. A parsed filter tests
+//! single entries with `ParserFilter::matches`, or wraps a parser with `ParserFilter::apply`.
+//!
+//! Comparisons are made on the following fields:
+//! - `msg`: The MAVLink message name, compared with `==` and `!=` only.
+//! - `msgid`: The MAVLink message id.
+//! - `sysid`, `compid`: The system and component ids of the MAVLink header.
+//! - `seq`: The entry sequence number.
+//! - `t`: The entry timestamp. Values are in seconds unless they end with `s`, `ms` or `us`.
+//!
+//! Comparisons are combined with `&&`, `||` and `!`, `&&` binding more tightly than `||`, and
+//! grouped with parentheses. Text values are quoted with `'` or `"`, or written bare. A
+//! comparison on a field an entry does not have, such as `msg` on a text entry, is false.
+use std::fmt;
+
+use mavlink::Message;
+use mavlink::error::MessageReadError;
+
+use crate::expr::{Cursor, ExprError, Token};
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Error returned when a filter expression cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    /// Byte offset in the expression at which the error was found.
+    pub position: usize,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid filter at position {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+impl From<ExprError> for FilterError {
+    fn from(error: ExprError) -> Self {
+        Self {
+            position: error.position,
+            message: error.message,
+        }
+    }
+}
+
+/// An entry field a filter compares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Msg,
+    MsgId,
+    SysId,
+    CompId,
+    Seq,
+    Time,
+}
+
+/// A comparison operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn compare<T: PartialOrd>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+/// A parsed filter expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    /// Compares the message name with a text value.
+    Name(CmpOp, String),
+    /// Compares a numeric field with a value, timestamps in microseconds.
+    Number(Field, CmpOp, f64),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+impl Expr {
+    fn matches<M: Message>(&self, entry: &LogEntry<M>) -> bool {
+        match self {
+            Expr::Name(op, name) => entry
+                .mav_message
+                .as_ref()
+                .is_some_and(|msg| op.compare(msg.message_name(), name.as_str())),
+            Expr::Number(field, op, value) => {
+                let actual = match field {
+                    Field::Msg => None,
+                    Field::MsgId => entry
+                        .mav_message
+                        .as_ref()
+                        .map(|msg| msg.message_id() as f64),
+                    Field::SysId => entry.mav_header.map(|header| header.system_id as f64),
+                    Field::CompId => entry.mav_header.map(|header| header.component_id as f64),
+                    Field::Seq => entry.sequence.map(|sequence| sequence as f64),
+                    Field::Time => entry.timestamp.map(|timestamp| timestamp as f64),
+                };
+                actual.is_some_and(|actual| op.compare(actual, *value))
+            }
+            Expr::And(lhs, rhs) => lhs.matches(entry) && rhs.matches(entry),
+            Expr::Or(lhs, rhs) => lhs.matches(entry) || rhs.matches(entry),
+            Expr::Not(expr) => !expr.matches(entry),
+        }
+    }
+}
+
+/// Operators and punctuation of filter expressions.
+const SYMBOLS: &[&str] = &["&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "(", ")"];
+
+/// Returns the comparison operator of a token, if it is one.
+fn cmp_op(token: Option<&Token>) -> Option<CmpOp> {
+    match token {
+        Some(Token::Symbol("==")) => Some(CmpOp::Eq),
+        Some(Token::Symbol("!=")) => Some(CmpOp::Ne),
+        Some(Token::Symbol("<")) => Some(CmpOp::Lt),
+        Some(Token::Symbol("<=")) => Some(CmpOp::Le),
+        Some(Token::Symbol(">")) => Some(CmpOp::Gt),
+        Some(Token::Symbol(">=")) => Some(CmpOp::Ge),
+        _ => None,
+    }
+}
+
+/// Parses `and ('||' and)*`.
+fn or(cursor: &mut Cursor) -> Result<Expr, ExprError> {
+    let mut expr = and(cursor)?;
+    while cursor.eat("||") {
+        expr = Expr::Or(Box::new(expr), Box::new(and(cursor)?));
+    }
+    Ok(expr)
+}
+
+/// Parses `unary ('&&' unary)*`.
+fn and(cursor: &mut Cursor) -> Result<Expr, ExprError> {
+    let mut expr = unary(cursor)?;
+    while cursor.eat("&&") {
+        expr = Expr::And(Box::new(expr), Box::new(unary(cursor)?));
+    }
+    Ok(expr)
+}
+
+/// Parses a negation, a parenthesized expression or a comparison.
+///
+/// Every recursion of the grammar goes through here, so it is where nesting is bounded.
+fn unary(cursor: &mut Cursor) -> Result<Expr, ExprError> {
+    cursor.nested(|cursor| {
+        if cursor.eat("!") {
+            return Ok(Expr::Not(Box::new(unary(cursor)?)));
+        }
+        if cursor.eat("(") {
+            let expr = or(cursor)?;
+            cursor.expect(")", "expected ')'")?;
+            return Ok(expr);
+        }
+        comparison(cursor)
+    })
+}
+
+/// Parses `field op value`.
+fn comparison(cursor: &mut Cursor) -> Result<Expr, ExprError> {
+    let field = match cursor.peek() {
+        Some(Token::Word(word)) => match word.as_str() {
+            "msg" => Field::Msg,
+            "msgid" => Field::MsgId,
+            "sysid" => Field::SysId,
+            "compid" => Field::CompId,
+            "seq" => Field::Seq,
+            "t" => Field::Time,
+            _ => return Err(cursor.error("unknown field")),
+        },
+        _ => return Err(cursor.error("expected a field name")),
+    };
+    cursor.next += 1;
+    let Some(op) = cmp_op(cursor.peek()) else {
+        return Err(cursor.error("expected a comparison operator"));
+    };
+    cursor.next += 1;
+    let value_error = cursor.error("expected a value");
+    match (field, cursor.bump()) {
+        (Field::Msg, Some(Token::Text(name) | Token::Word(name))) => {
+            if !matches!(op, CmpOp::Eq | CmpOp::Ne) {
+                cursor.next -= 2;
+                return Err(cursor.error("message names are compared with '==' or '!=' only"));
+            }
+            Ok(Expr::Name(op, name))
+        }
+        (Field::Time, Some(Token::Number(value, unit))) => {
+            let scale = match unit.as_str() {
+                "" | "s" => 1e6,
+                "ms" => 1e3,
+                "us" => 1.0,
+                _ => {
+                    cursor.next -= 1;
+                    return Err(cursor.error("time units are 's', 'ms' or 'us'"));
+                }
+            };
+            Ok(Expr::Number(field, op, value * scale))
+        }
+        (Field::Msg | Field::Time, _) => Err(value_error),
+        (_, Some(Token::Number(value, unit))) if unit.is_empty() => {
+            Ok(Expr::Number(field, op, value))
+        }
+        _ => Err(value_error),
+    }
+}
+
+/// A filter selecting log entries, parsed from a boolean expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParserFilter {
+    expr: Expr,
+}
+
+impl ParserFilter {
+    /// Parses a filter expression.
+    ///
+    /// # Arguments
+    /// - `expr`: The expression, such as `msg == 'ATTITUDE' && sysid == 1 && t > 120s`.
+    ///
+    /// # Errors
+    /// Returns a `FilterError` locating the first problem if the expression is invalid.
+    pub fn from_expr(expr: &str) -> Result<Self, FilterError> {
+        let mut cursor = Cursor::new(expr, SYMBOLS)?;
+        let parsed = or(&mut cursor)?;
+        if cursor.peek().is_some() {
+            return Err(cursor
+                .error("expected '&&', '||' or the end of the expression")
+                .into());
+        }
+        Ok(Self { expr: parsed })
+    }
+
+    /// Creates a filter selecting the entries timestamped in a time range.
+    ///
+    /// # Arguments
+    /// - `start_us`: The first timestamp selected, in microseconds.
+    /// - `end_us`: The timestamp the range ends at, excluded, in microseconds.
+    pub fn between(start_us: u64, end_us: u64) -> Self {
+        Self {
+            expr: Expr::And(
+                Box::new(Expr::Number(Field::Time, CmpOp::Ge, start_us as f64)),
+                Box::new(Expr::Number(Field::Time, CmpOp::Lt, end_us as f64)),
+            ),
+        }
+    }
+
+    /// Combines two filters into one selecting the entries selected by both.
+    pub fn and(self, other: ParserFilter) -> Self {
+        Self {
+            expr: Expr::And(Box::new(self.expr), Box::new(other.expr)),
+        }
+    }
+
+    /// Returns whether an entry is selected by the filter.
+    pub fn matches<M: Message>(&self, entry: &LogEntry<M>) -> bool {
+        self.expr.matches(entry)
+    }
+
+    /// Wraps a parser so that it only yields the entries selected by the filter.
+    pub fn apply<P: MavParser>(self, parser: P) -> Filtered<P> {
+        Filtered {
+            parser,
+            filter: self,
+        }
+    }
+}
+
+/// Parser adapter yielding only the entries selected by a `ParserFilter`.
+pub struct Filtered<P: MavParser> {
+    parser: P,
+    filter: ParserFilter,
+}
+
+impl<P: MavParser> Filtered<P> {
+    /// Returns the wrapped parser.
+    pub fn into_inner(self) -> P {
+        self.parser
+    }
+}
+
+impl<P: MavParser> MavParser for Filtered<P> {
+    type M = P::M;
+
+    /// Reads the next entry selected by the filter.
+    ///
+    /// # Errors
+    /// Returns the errors of the wrapped parser unchanged.
+    fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+        loop {
+            let entry = self.parser.parse_next_entry()?;
+            if self.filter.matches(&entry) {
+                return Ok(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::MavHeader;
+    use mavlink::common::MavMessage;
+
+    use super::*;
+
+    fn entry(msg: MavMessage, system_id: u8, timestamp: u64) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    /// Test that expressions select entries by message, ids and time with the usual precedence.
+    #[test]
+    fn test_filter_matches() {
+        let attitude = entry(MavMessage::ATTITUDE(Default::default()), 1, 150_000_000);
+        let early = entry(MavMessage::ATTITUDE(Default::default()), 1, 60_000_000);
+        let heartbeat = entry(MavMessage::HEARTBEAT(Default::default()), 2, 150_000_000);
+        let text = LogEntry::<MavMessage> {
+            timestamp: Some(150_000_000),
+            text: Some("note".to_string()),
+            ..Default::default()
+        };
+
+        let filter =
+            ParserFilter::from_expr("msg == 'ATTITUDE' && sysid == 1 && t > 120s").unwrap();
+        assert!(filter.matches(&attitude));
+        assert!(!filter.matches(&early));
+        assert!(!filter.matches(&heartbeat));
+        assert!(!filter.matches(&text));
+
+        let filter = ParserFilter::from_expr("msgid == 0 || sysid == 1 && t < 90000ms").unwrap();
+        assert!(!filter.matches(&attitude));
+        assert!(filter.matches(&early));
+        assert!(filter.matches(&heartbeat));
+
+        let filter = ParserFilter::from_expr("!(msg == HEARTBEAT) && t >= 150s").unwrap();
+        assert!(filter.matches(&attitude));
+        assert!(!filter.matches(&heartbeat));
+        assert!(filter.matches(&text));
+    }
+
+    /// Test that a time range filter combines with an expression.
+    #[test]
+    fn test_filter_between() {
+        let filter = ParserFilter::from_expr("sysid == 1")
+            .unwrap()
+            .and(ParserFilter::between(60_000_000, 150_000_000));
+        assert!(filter.matches(&entry(
+            MavMessage::ATTITUDE(Default::default()),
+            1,
+            60_000_000
+        )));
+        assert!(!filter.matches(&entry(
+            MavMessage::ATTITUDE(Default::default()),
+            1,
+            150_000_000
+        )));
+        assert!(!filter.matches(&entry(
+            MavMessage::ATTITUDE(Default::default()),
+            2,
+            90_000_000
+        )));
+    }
+
+    /// Test that invalid expressions are rejected with the position of the problem.
+    #[test]
+    fn test_filter_errors() {
+        let position = |expr: &str| ParserFilter::from_expr(expr).unwrap_err().position;
+        assert_eq!(position("alt > 10"), 0);
+        assert_eq!(position("msg > 'ATTITUDE'"), 4);
+        assert_eq!(position("sysid == 1 &&"), 13);
+        assert_eq!(position("t > 5h"), 4);
+        assert_eq!(position("(sysid == 1"), 11);
+        assert_eq!(position("msg == 'ATTITUDE"), 7);
+        assert_eq!(position("sysid == 1 sysid == 2"), 11);
+
+        let deep = format!("{}sysid == 1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let error = ParserFilter::from_expr(&deep).unwrap_err();
+        assert_eq!(error.message, "expression is nested too deeply");
+        assert!(ParserFilter::from_expr(&format!("{}sysid == 1", "!".repeat(100_000))).is_err());
+    }
+}
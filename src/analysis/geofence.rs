@@ -0,0 +1,308 @@
+//! Detection of operating area violations.
+//!
+//! Compliance reporting after an operation needs every time a vehicle left its approved area or
+//! altitude band, and for how long.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Area vehicles are allowed to operate in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperatingArea {
+    /// Vertices of the boundary polygon as (latitude, longitude) in degrees, in order and
+    /// without repeating the first vertex. The polygon is treated as planar, which is accurate
+    /// for areas of a few kilometers away from the poles and the antimeridian.
+    pub polygon: Vec<(f64, f64)>,
+    /// Lowest allowed altitude above home in meters, if limited.
+    pub min_altitude_m: Option<f64>,
+    /// Highest allowed altitude above home in meters, if limited.
+    pub max_altitude_m: Option<f64>,
+}
+
+impl OperatingArea {
+    /// Checks whether a position is within the boundary polygon.
+    ///
+    /// Polygons with fewer than 3 vertices contain no position.
+    pub fn contains(&self, latitude_deg: f64, longitude_deg: f64) -> bool {
+        let mut inside = false;
+        let mut previous = match self.polygon.last() {
+            Some(&vertex) if self.polygon.len() >= 3 => vertex,
+            _ => return false,
+        };
+        for &vertex in &self.polygon {
+            let ((lat_a, lon_a), (lat_b, lon_b)) = (previous, vertex);
+            // count the edges crossed by a ray from the position towards increasing longitude
+            if (lat_a > latitude_deg) != (lat_b > latitude_deg) {
+                let crossing = lon_a + (latitude_deg - lat_a) / (lat_b - lat_a) * (lon_b - lon_a);
+                if longitude_deg < crossing {
+                    inside = !inside;
+                }
+            }
+            previous = vertex;
+        }
+        inside
+    }
+}
+
+/// Way a vehicle violated its operating area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Violation {
+    /// Outside of the boundary polygon.
+    Outside,
+    /// Below the minimum altitude.
+    BelowMinAltitude,
+    /// Above the maximum altitude.
+    AboveMaxAltitude,
+}
+
+/// A continuous period during which a vehicle violated its operating area.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Excursion {
+    /// MAVLink system id of the vehicle.
+    pub system_id: u8,
+    /// The violated limit.
+    pub violation: Violation,
+    /// Timestamp of the first position violating the limit.
+    pub start_us: Option<u64>,
+    /// Timestamp of the first position back within the limit, or of the last position of the
+    /// vehicle if the log ends during the excursion.
+    pub end_us: Option<u64>,
+    /// Number of positions violating the limit.
+    pub samples: u64,
+    /// Largest distance beyond an altitude limit in meters, 0 for `Violation::Outside`.
+    pub max_exceedance_m: f64,
+}
+
+impl Excursion {
+    /// Returns the duration of the excursion, if the log provides timestamps.
+    pub fn duration_us(&self) -> Option<u64> {
+        Some(self.end_us?.saturating_sub(self.start_us?))
+    }
+}
+
+/// Analyzer reporting every excursion of a vehicle out of an operating area.
+///
+/// Positions are taken from GLOBAL_POSITION_INT messages, using the altitude above home.
+/// Positions at latitude and longitude 0, sent by autopilots without a position estimate, are
+/// not checked against the polygon. Each limit is tracked separately, so a vehicle climbing out
+/// of the area through its ceiling gives two overlapping excursions.
+pub struct GeofenceMonitor {
+    area: OperatingArea,
+    /// Excursions still in progress by system id and violated limit.
+    open: BTreeMap<(u8, Violation), Excursion>,
+    excursions: Vec<Excursion>,
+}
+
+impl GeofenceMonitor {
+    /// Creates a new `GeofenceMonitor` analyzer.
+    ///
+    /// # Arguments
+    /// - `area`: The operating area.
+    pub fn new(area: OperatingArea) -> Self {
+        GeofenceMonitor {
+            area,
+            open: BTreeMap::new(),
+            excursions: Vec::new(),
+        }
+    }
+}
+
+impl<M: Message> Analyzer<M> for GeofenceMonitor {
+    /// The excursions of all vehicles in the order they started.
+    type Report = Vec<Excursion>;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        if msg.message_id() != fields::GLOBAL_POSITION_INT_ID {
+            return;
+        }
+        let area = &self.area;
+        let payload = fields::payload(msg);
+        let latitude_deg = fields::read_i32(&payload, 4) as f64 / 1e7;
+        let longitude_deg = fields::read_i32(&payload, 8) as f64 / 1e7;
+        let altitude_m = fields::read_i32(&payload, 16) as f64 / 1e3;
+        let has_position = latitude_deg != 0.0 || longitude_deg != 0.0;
+        let checks = [
+            (
+                Violation::Outside,
+                has_position.then(|| !area.contains(latitude_deg, longitude_deg)),
+                0.0,
+            ),
+            (
+                Violation::BelowMinAltitude,
+                area.min_altitude_m.map(|min| altitude_m < min),
+                area.min_altitude_m.map_or(0.0, |min| min - altitude_m),
+            ),
+            (
+                Violation::AboveMaxAltitude,
+                area.max_altitude_m.map(|max| altitude_m > max),
+                area.max_altitude_m.map_or(0.0, |max| altitude_m - max),
+            ),
+        ];
+        for (violation, violated, exceedance_m) in checks {
+            let key = (header.system_id, violation);
+            match violated {
+                Some(true) => {
+                    let excursion = self.open.entry(key).or_insert_with(|| Excursion {
+                        system_id: header.system_id,
+                        violation,
+                        start_us: entry.timestamp,
+                        end_us: entry.timestamp,
+                        samples: 0,
+                        max_exceedance_m: 0.0,
+                    });
+                    excursion.samples += 1;
+                    excursion.end_us = entry.timestamp.or(excursion.end_us);
+                    excursion.max_exceedance_m = excursion.max_exceedance_m.max(exceedance_m);
+                }
+                Some(false) => {
+                    if let Some(mut excursion) = self.open.remove(&key) {
+                        excursion.end_us = entry.timestamp.or(excursion.end_us);
+                        self.excursions.push(excursion);
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<Excursion> {
+        self.excursions.extend(self.open.into_values());
+        self.excursions.sort_by_key(|excursion| excursion.start_us);
+        self.excursions
+    }
+}
+
+/// Reads a full log and reports every excursion of a vehicle out of an operating area.
+///
+/// See `GeofenceMonitor` for how positions are checked and to run this analysis along with
+/// others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `area`: The operating area.
+///
+/// # Returns
+/// The excursions of all vehicles in the order they started.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn geofence_excursions<P: MavParser + ?Sized>(
+    parser: &mut P,
+    area: &OperatingArea,
+) -> std::io::Result<Vec<Excursion>> {
+    analyze(parser, GeofenceMonitor::new(area.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn position(
+        timestamp: u64,
+        latitude_deg: f64,
+        longitude_deg: f64,
+        altitude_m: f64,
+    ) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                lat: (latitude_deg * 1e7).round() as i32,
+                lon: (longitude_deg * 1e7).round() as i32,
+                relative_alt: (altitude_m * 1e3).round() as i32,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// L shaped field between latitudes 47.0 and 47.02 and longitudes 8.0 and 8.02.
+    fn field() -> OperatingArea {
+        OperatingArea {
+            polygon: vec![
+                (47.0, 8.0),
+                (47.02, 8.0),
+                (47.02, 8.01),
+                (47.01, 8.01),
+                (47.01, 8.02),
+                (47.0, 8.02),
+            ],
+            min_altitude_m: Some(5.0),
+            max_altitude_m: Some(120.0),
+        }
+    }
+
+    /// Test that positions are checked against a concave polygon.
+    #[test]
+    fn test_contains() {
+        let area = field();
+        assert!(area.contains(47.005, 8.005));
+        assert!(area.contains(47.015, 8.005));
+        assert!(area.contains(47.005, 8.015));
+        assert!(!area.contains(47.015, 8.015));
+        assert!(!area.contains(46.99, 8.005));
+        assert!(!area.contains(47.005, 8.03));
+        let line = OperatingArea {
+            polygon: vec![(47.0, 8.0), (47.1, 8.1)],
+            min_altitude_m: None,
+            max_altitude_m: None,
+        };
+        assert!(!line.contains(47.05, 8.05));
+    }
+
+    /// Test that excursions out of the polygon and altitude band are reported with their time.
+    #[test]
+    fn test_geofence_excursions() {
+        let entries = VecDeque::from([
+            // no position estimate yet
+            position(0, 0.0, 0.0, 0.0),
+            position(1_000_000, 47.005, 8.005, 2.0),
+            position(2_000_000, 47.005, 8.005, 50.0),
+            // cutting the inner corner of the L while climbing too high
+            position(3_000_000, 47.015, 8.015, 121.5),
+            position(4_000_000, 47.015, 8.016, 130.0),
+            position(5_000_000, 47.005, 8.015, 100.0),
+            // leaving the field at the end of the log
+            position(6_000_000, 46.99, 8.005, 100.0),
+        ]);
+        let excursions = geofence_excursions(&mut EntryList(entries), &field()).unwrap();
+        assert_eq!(excursions.len(), 4);
+
+        assert_eq!(excursions[0].violation, Violation::BelowMinAltitude);
+        assert_eq!(excursions[0].start_us, Some(0));
+        assert_eq!(excursions[0].duration_us(), Some(2_000_000));
+        assert_eq!(excursions[0].samples, 2);
+        assert_eq!(excursions[0].max_exceedance_m, 5.0);
+
+        assert_eq!(excursions[1].violation, Violation::Outside);
+        assert_eq!(excursions[1].start_us, Some(3_000_000));
+        assert_eq!(excursions[1].end_us, Some(5_000_000));
+        assert_eq!(excursions[1].samples, 2);
+
+        assert_eq!(excursions[2].violation, Violation::AboveMaxAltitude);
+        assert_eq!(excursions[2].duration_us(), Some(2_000_000));
+        assert_eq!(excursions[2].max_exceedance_m, 10.0);
+
+        assert_eq!(excursions[3].violation, Violation::Outside);
+        assert_eq!(excursions[3].start_us, Some(6_000_000));
+        assert_eq!(excursions[3].duration_us(), Some(0));
+    }
+}
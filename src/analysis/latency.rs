@@ -0,0 +1,230 @@
+//! Link round trip latency from TIMESYNC and PING exchanges.
+//!
+//! Control issues often coincide with link degradation. The request and response of every
+//! TIMESYNC and PING exchange in a log give the latency of the link over time.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Time after which an unanswered request is forgotten, in microseconds.
+const MAX_ROUND_TRIP_US: u64 = 10_000_000;
+
+/// Message a round trip was measured with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LatencySource {
+    Timesync,
+    Ping,
+}
+
+/// A single round trip measurement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencySample {
+    /// Timestamp of the response.
+    pub timestamp_us: u64,
+    /// Time between the request and the response as seen by the logger, in microseconds.
+    pub round_trip_us: u64,
+    /// Message the round trip was measured with.
+    pub source: LatencySource,
+    /// MAVLink system and component id of the component sending the request.
+    pub requester: (u8, u8),
+    /// MAVLink system and component id of the component answering the request.
+    pub responder: (u8, u8),
+}
+
+/// Analyzer measuring the round trip time of every answered TIMESYNC and PING request.
+///
+/// A TIMESYNC request has `tc1` set to 0 and its response echoes `ts1`. A PING request is
+/// broadcast and its response is addressed to the requester with the same `time_usec` and
+/// `seq`. The round trip is the difference between the log timestamps of the request and the
+/// response, so it is only meaningful when the log was recorded next to the requester. Entries
+/// without a timestamp are ignored, and requests unanswered after 10 s are dropped.
+#[derive(Default)]
+pub struct LatencyTracker {
+    /// Requests by source, requester and request identifier, with their timestamp.
+    requests: BTreeMap<(LatencySource, (u8, u8), u64, u32), u64>,
+    samples: Vec<LatencySample>,
+}
+
+impl LatencyTracker {
+    /// Creates a new `LatencyTracker` analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<M: Message> Analyzer<M> for LatencyTracker {
+    /// The round trip samples in the order of their responses.
+    type Report = Vec<LatencySample>;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (timestamp, header, msg) =
+            match (entry.timestamp, &entry.mav_header, &entry.mav_message) {
+                (Some(timestamp), Some(header), Some(msg)) => (timestamp, header, msg),
+                _ => return,
+            };
+        let sender = (header.system_id, header.component_id);
+        let payload = fields::payload(msg);
+        let (source, id, seq, target, is_request) = match msg.message_id() {
+            fields::TIMESYNC_ID => {
+                let tc1 = fields::read_u64(&payload, 0);
+                let target = (fields::read_u8(&payload, 16), fields::read_u8(&payload, 17));
+                let ts1 = fields::read_u64(&payload, 8);
+                (LatencySource::Timesync, ts1, 0, target, tc1 == 0)
+            }
+            fields::PING_ID => {
+                let target = (fields::read_u8(&payload, 12), fields::read_u8(&payload, 13));
+                let time_usec = fields::read_u64(&payload, 0);
+                let seq = fields::read_u32(&payload, 8);
+                (
+                    LatencySource::Ping,
+                    time_usec,
+                    seq,
+                    target,
+                    target == (0, 0),
+                )
+            }
+            _ => return,
+        };
+        self.requests
+            .retain(|_, &mut sent_us| timestamp.saturating_sub(sent_us) <= MAX_ROUND_TRIP_US);
+        if is_request {
+            self.requests.insert((source, sender, id, seq), timestamp);
+            return;
+        }
+        // TIMESYNC responses of older implementations do not carry the target
+        let requester = if target == (0, 0) {
+            self.requests
+                .keys()
+                .find(|key| key.0 == source && key.2 == id && key.3 == seq && key.1 != sender)
+                .map(|key| key.1)
+        } else {
+            Some(target)
+        };
+        let Some(requester) = requester else {
+            return;
+        };
+        if let Some(sent_us) = self.requests.remove(&(source, requester, id, seq)) {
+            self.samples.push(LatencySample {
+                timestamp_us: timestamp,
+                round_trip_us: timestamp.saturating_sub(sent_us),
+                source,
+                requester,
+                responder: sender,
+            });
+        }
+    }
+
+    fn finish(self) -> Vec<LatencySample> {
+        self.samples
+    }
+}
+
+/// Reads a full log and measures the round trip time of every answered TIMESYNC and PING
+/// request.
+///
+/// See `LatencyTracker` for how requests and responses are paired and to run this analysis
+/// along with others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+///
+/// # Returns
+/// The round trip samples in the order of their responses.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn round_trip_latency<P: MavParser + ?Sized>(
+    parser: &mut P,
+) -> std::io::Result<Vec<LatencySample>> {
+    analyze(parser, LatencyTracker::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{MavMessage, PING_DATA, TIMESYNC_DATA};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(source: (u8, u8), timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: source.0,
+                component_id: source.1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    fn timesync(tc1: i64, ts1: i64) -> MavMessage {
+        MavMessage::TIMESYNC(TIMESYNC_DATA {
+            tc1,
+            ts1,
+            ..Default::default()
+        })
+    }
+
+    fn ping(time_usec: u64, seq: u32, target: (u8, u8)) -> MavMessage {
+        MavMessage::PING(PING_DATA {
+            time_usec,
+            seq,
+            target_system: target.0,
+            target_component: target.1,
+        })
+    }
+
+    /// Test that requests are paired with their responses.
+    #[test]
+    fn test_round_trip_latency() {
+        let gcs = (255, 190);
+        let autopilot = (1, 1);
+        let entries = VecDeque::from([
+            // TIMESYNC responses without target as sent by older implementations
+            entry(gcs, 1_000_000, timesync(0, 42)),
+            entry(autopilot, 1_080_000, timesync(7, 42)),
+            // unanswered request
+            entry(gcs, 2_000_000, timesync(0, 43)),
+            entry(autopilot, 3_000_000, ping(3_000_000, 1, (0, 0))),
+            entry(gcs, 3_250_000, ping(3_000_000, 1, autopilot)),
+            entry(autopilot, 4_000_000, timesync(0, 99)),
+            entry(gcs, 4_030_000, timesync(5, 99)),
+            entry(autopilot, 4_050_000, ping(4_050_000, 2, (0, 0))),
+            entry(gcs, 4_060_000, ping(4_050_000, 2, autopilot)),
+            // response to a request that was dropped
+            entry(autopilot, 20_000_000, timesync(8, 43)),
+        ]);
+
+        let samples = round_trip_latency(&mut EntryList(entries)).unwrap();
+        let summary: Vec<(u64, u64, LatencySource, (u8, u8), (u8, u8))> = samples
+            .iter()
+            .map(|s| {
+                (
+                    s.timestamp_us,
+                    s.round_trip_us,
+                    s.source,
+                    s.requester,
+                    s.responder,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1_080_000, 80_000, LatencySource::Timesync, gcs, autopilot),
+                (3_250_000, 250_000, LatencySource::Ping, autopilot, gcs),
+                (4_030_000, 30_000, LatencySource::Timesync, autopilot, gcs),
+                (4_060_000, 10_000, LatencySource::Ping, autopilot, gcs),
+            ]
+        );
+    }
+}
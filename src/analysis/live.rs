@@ -0,0 +1,286 @@
+//! Analyzers fed from live data.
+//!
+//! An `Analyzer` does not care whether its entries come from a log file or from a live link.
+//! `SharedAnalyzer` lets a capture or logging loop own an analyzer while dashboards read its
+//! state through an `AnalyzerHandle` on other threads. `LiveStats` provides the rolling
+//! statistics usually shown during a flight.
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::Analyzer;
+use crate::fields;
+use crate::mav_parser::LogEntry;
+
+/// An analyzer whose state can be read through handles while it is being fed.
+pub struct SharedAnalyzer<A> {
+    inner: Arc<Mutex<Option<A>>>,
+}
+
+impl<A> SharedAnalyzer<A> {
+    /// Creates a new `SharedAnalyzer` wrapping an analyzer.
+    pub fn new(analyzer: A) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Some(analyzer))),
+        }
+    }
+
+    /// Returns a handle to read the state of the analyzer.
+    pub fn handle(&self) -> AnalyzerHandle<A> {
+        AnalyzerHandle {
+            inner: self.inner.clone(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<A>> {
+        // an analyzer is only ever modified by its own methods, so its state stays usable
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<M: Message, A: Analyzer<M>> Analyzer<M> for SharedAnalyzer<A> {
+    type Report = A::Report;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        if let Some(analyzer) = self.lock().as_mut() {
+            analyzer.on_entry(entry);
+        }
+    }
+
+    /// Completes the analysis. Handles no longer see the analyzer afterwards.
+    fn finish(self) -> A::Report {
+        let analyzer = self.lock().take();
+        analyzer.expect("only finish takes the analyzer").finish()
+    }
+}
+
+/// Handle reading the state of a `SharedAnalyzer` from any thread.
+pub struct AnalyzerHandle<A> {
+    inner: Arc<Mutex<Option<A>>>,
+}
+
+impl<A> Clone for AnalyzerHandle<A> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<A> AnalyzerHandle<A> {
+    /// Runs a closure on the current state of the analyzer.
+    ///
+    /// The analyzer is locked while the closure runs, so the closure should only copy out the
+    /// data it needs.
+    ///
+    /// # Returns
+    /// The result of the closure, or `None` if the analysis has finished.
+    pub fn with<R>(&self, f: impl FnOnce(&A) -> R) -> Option<R> {
+        let guard = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        guard.as_ref().map(f)
+    }
+}
+
+/// A GPS fix from a GPS_RAW_INT message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsFix {
+    /// Timestamp of the entry holding the fix.
+    pub timestamp_us: Option<u64>,
+    /// MAVLink system id of the vehicle.
+    pub system_id: u8,
+    /// GPS_FIX_TYPE of the fix.
+    pub fix_type: u8,
+    pub latitude_deg: f64,
+    pub longitude_deg: f64,
+    /// Altitude above mean sea level in meters.
+    pub altitude_m: f64,
+    pub satellites_visible: u8,
+}
+
+/// Rolling statistics of a MAVLink stream.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LiveSnapshot {
+    /// Number of MAVLink messages received.
+    pub messages: u64,
+    /// Number of MAVLink messages missed according to the sequence numbers of each component.
+    pub dropped: u64,
+    /// Rate of each message id over the most recent window, in Hz.
+    pub rates_hz: BTreeMap<u32, f64>,
+    /// The most recent GPS fix of any vehicle.
+    pub last_gps_fix: Option<GpsFix>,
+}
+
+/// Analyzer keeping rolling statistics of a MAVLink stream for live dashboards.
+///
+/// Message rates are computed over a sliding window ending at the most recent timestamp.
+/// Messages without a timestamp are counted but not included in the rates.
+pub struct LiveStats {
+    window_us: u64,
+    /// Timestamps and message ids of the messages within the window.
+    recent: VecDeque<(u64, u32)>,
+    /// Last sequence number by system and component id.
+    sequences: BTreeMap<(u8, u8), u8>,
+    messages: u64,
+    dropped: u64,
+    last_gps_fix: Option<GpsFix>,
+}
+
+impl LiveStats {
+    /// Creates a new `LiveStats` analyzer.
+    ///
+    /// # Arguments
+    /// - `window_us`: The length of the window message rates are computed over, in
+    ///   microseconds.
+    ///
+    /// # Panics
+    /// Panics if `window_us` is 0.
+    pub fn new(window_us: u64) -> Self {
+        assert!(window_us > 0, "The rate window must not be 0");
+        LiveStats {
+            window_us,
+            recent: VecDeque::new(),
+            sequences: BTreeMap::new(),
+            messages: 0,
+            dropped: 0,
+            last_gps_fix: None,
+        }
+    }
+
+    /// Returns the current statistics.
+    pub fn snapshot(&self) -> LiveSnapshot {
+        let mut counts: BTreeMap<u32, u64> = BTreeMap::new();
+        for (_, msg_id) in &self.recent {
+            *counts.entry(*msg_id).or_default() += 1;
+        }
+        let window_s = self.window_us as f64 / 1e6;
+        let rates_hz = counts
+            .into_iter()
+            .map(|(msg_id, count)| (msg_id, count as f64 / window_s))
+            .collect();
+        LiveSnapshot {
+            messages: self.messages,
+            dropped: self.dropped,
+            rates_hz,
+            last_gps_fix: self.last_gps_fix,
+        }
+    }
+}
+
+impl<M: Message> Analyzer<M> for LiveStats {
+    /// The statistics at the end of the stream.
+    type Report = LiveSnapshot;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        self.messages += 1;
+        let component = (header.system_id, header.component_id);
+        if let Some(last) = self.sequences.insert(component, header.sequence) {
+            self.dropped += header.sequence.wrapping_sub(last).wrapping_sub(1) as u64;
+        }
+        let msg_id = msg.message_id();
+        if let Some(timestamp) = entry.timestamp {
+            self.recent.push_back((timestamp, msg_id));
+            let start = timestamp.saturating_sub(self.window_us);
+            while self.recent.front().is_some_and(|(t, _)| *t <= start) {
+                self.recent.pop_front();
+            }
+        }
+        if msg_id == fields::GPS_RAW_INT_ID {
+            let payload = fields::payload(msg);
+            self.last_gps_fix = Some(GpsFix {
+                timestamp_us: entry.timestamp,
+                system_id: header.system_id,
+                fix_type: fields::read_u8(&payload, 28),
+                latitude_deg: fields::read_i32(&payload, 8) as f64 / 1e7,
+                longitude_deg: fields::read_i32(&payload, 12) as f64 / 1e7,
+                altitude_m: fields::read_i32(&payload, 16) as f64 / 1e3,
+                satellites_visible: fields::read_u8(&payload, 29),
+            });
+        }
+    }
+
+    fn finish(self) -> LiveSnapshot {
+        self.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::MavHeader;
+    use mavlink::common::{GPS_RAW_INT_DATA, GpsFixType, MavMessage};
+
+    use super::*;
+
+    fn entry(sequence: u8, timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    /// Test that rates, drops and the last GPS fix are tracked as entries arrive.
+    #[test]
+    fn test_live_stats() {
+        let mut stats = LiveStats::new(1_000_000);
+        let mut shared = SharedAnalyzer::new(LiveStats::new(1_000_000));
+        let handle = shared.handle();
+        let mut sequence: u8 = 250;
+        for i in 0..40u64 {
+            // every fifth frame is lost on the link
+            if i % 5 != 4 {
+                let entry = entry(
+                    sequence,
+                    i * 100_000,
+                    MavMessage::ATTITUDE(Default::default()),
+                );
+                stats.on_entry(&entry);
+                shared.on_entry(&entry);
+            }
+            sequence = sequence.wrapping_add(1);
+        }
+        let gps = entry(
+            sequence,
+            4_000_000,
+            MavMessage::GPS_RAW_INT(GPS_RAW_INT_DATA {
+                lat: 470_000_000,
+                lon: 80_000_000,
+                alt: 450_000,
+                fix_type: GpsFixType::GPS_FIX_TYPE_3D_FIX,
+                satellites_visible: 12,
+                ..Default::default()
+            }),
+        );
+        stats.on_entry(&gps);
+        shared.on_entry(&gps);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.messages, 33);
+        assert_eq!(snapshot.dropped, 8);
+        // the window after 3.0 s holds 7 ATTITUDE and the GPS fix
+        assert_eq!(snapshot.rates_hz[&30], 7.0);
+        assert_eq!(snapshot.rates_hz[&24], 1.0);
+        let fix = snapshot.last_gps_fix.unwrap();
+        assert_eq!(fix.fix_type, GpsFixType::GPS_FIX_TYPE_3D_FIX as u8);
+        assert_eq!((fix.latitude_deg, fix.longitude_deg), (47.0, 8.0));
+        assert_eq!(fix.altitude_m, 450.0);
+        assert_eq!(fix.satellites_visible, 12);
+
+        // the handle sees the same state until the analysis finishes
+        assert_eq!(
+            handle.with(|stats| stats.snapshot()),
+            Some(snapshot.clone())
+        );
+        assert_eq!(Analyzer::<MavMessage>::finish(shared), snapshot);
+        assert!(handle.with(|stats| stats.snapshot()).is_none());
+    }
+}
@@ -0,0 +1,515 @@
+//! Conformance of a flight to its planned mission.
+//!
+//! Survey QA needs to know whether a vehicle flew the mission it was given: which waypoints it
+//! reached, where it strayed from the planned track and how long the mission took. Missions are
+//! read from QGroundControl `.plan` files and compared to the positions of a log.
+use mavlink::Message;
+use serde_json::Value;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Mean radius of the earth in meters.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Highest MAV_CMD id of a navigation command.
+const MAX_NAV_COMMAND: u64 = 95;
+
+/// A mission item with a position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedWaypoint {
+    /// Sequence number of the item once uploaded, the planned home position being 0.
+    pub sequence: u16,
+    /// MAV_CMD of the item, such as 16 for MAV_CMD_NAV_WAYPOINT.
+    pub command: u16,
+    /// Latitude in degrees.
+    pub latitude_deg: f64,
+    /// Longitude in degrees.
+    pub longitude_deg: f64,
+    /// Altitude in meters, in the frame of the item.
+    pub altitude_m: f64,
+    /// Acceptance radius in meters, 0 if the plan leaves it to the autopilot.
+    pub acceptance_radius_m: f64,
+}
+
+/// Waypoints of a mission plan in flight order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissionPlan {
+    /// The navigation items with a position. Items without a position, such as camera
+    /// commands or a takeoff from the current position, are left out.
+    pub waypoints: Vec<PlannedWaypoint>,
+}
+
+/// Returns an `io::Error` of kind `InvalidData` for a malformed plan.
+fn invalid_plan(reason: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Invalid plan: {reason}"),
+    )
+}
+
+impl MissionPlan {
+    /// Parses a QGroundControl `.plan` document.
+    ///
+    /// Survey and corridor scan items are expanded to the waypoints QGroundControl generated
+    /// for them.
+    ///
+    /// # Arguments
+    /// - `text`: The JSON document.
+    ///
+    /// # Returns
+    /// The waypoints of the mission.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` of kind `InvalidData` if the document is not a plan, or of kind
+    /// `Unsupported` if it holds a complex item without generated waypoints, such as a structure
+    /// scan.
+    pub fn from_plan_json(text: &str) -> std::io::Result<Self> {
+        let plan: Value = serde_json::from_str(text).map_err(|e| invalid_plan(&e.to_string()))?;
+        let items = plan["mission"]["items"]
+            .as_array()
+            .ok_or_else(|| invalid_plan("no mission items"))?;
+        let mut simple_items = Vec::new();
+        for item in items {
+            match item["type"].as_str() {
+                Some("SimpleItem") => simple_items.push(item),
+                Some("ComplexItem") => {
+                    let generated = item["TransectStyleComplexItem"]["Items"]
+                        .as_array()
+                        .ok_or_else(|| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::Unsupported,
+                                format!(
+                                    "Complex mission item {} has no generated waypoints",
+                                    item["complexItemType"]
+                                ),
+                            )
+                        })?;
+                    simple_items.extend(generated);
+                }
+                _ => return Err(invalid_plan("unknown mission item type")),
+            }
+        }
+
+        let mut waypoints = Vec::new();
+        for (index, item) in simple_items.into_iter().enumerate() {
+            let command = item["command"]
+                .as_u64()
+                .ok_or_else(|| invalid_plan("mission item without command"))?;
+            let params = item["params"]
+                .as_array()
+                .ok_or_else(|| invalid_plan("mission item without params"))?;
+            // QGroundControl writes NaN parameters as null
+            let param = |number: usize| params.get(number).and_then(Value::as_f64);
+            let (Some(latitude_deg), Some(longitude_deg)) = (param(4), param(5)) else {
+                continue;
+            };
+            if command > MAX_NAV_COMMAND || (latitude_deg == 0.0 && longitude_deg == 0.0) {
+                continue;
+            }
+            waypoints.push(PlannedWaypoint {
+                sequence: (index + 1) as u16,
+                command: command as u16,
+                latitude_deg,
+                longitude_deg,
+                altitude_m: param(6).unwrap_or_default(),
+                acceptance_radius_m: param(1).unwrap_or_default(),
+            });
+        }
+        Ok(MissionPlan { waypoints })
+    }
+
+    /// Reads a QGroundControl `.plan` file, see `from_plan_json`.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the file could not be read, and the errors of `from_plan_json`.
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        Self::from_plan_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Outcome of a planned waypoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaypointResult {
+    /// The planned waypoint.
+    pub waypoint: PlannedWaypoint,
+    /// Timestamp of the first position within the acceptance radius, if reached.
+    pub reached_us: Option<u64>,
+    /// Whether a position within the acceptance radius was logged.
+    pub reached: bool,
+    /// Smallest horizontal distance between the vehicle and the waypoint in meters, if any
+    /// position was logged.
+    pub closest_approach_m: Option<f64>,
+}
+
+/// A continuous period during which the vehicle was further from the planned track than the
+/// tolerance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackDeviation {
+    /// Timestamp of the first position off the track.
+    pub start_us: Option<u64>,
+    /// Timestamp of the first position back on the track, or of the last position if the log
+    /// ends during the deviation.
+    pub end_us: Option<u64>,
+    /// Number of positions off the track.
+    pub samples: u64,
+    /// Largest horizontal distance from the track in meters.
+    pub max_deviation_m: f64,
+}
+
+/// Conformance of a flight to its mission plan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceReport {
+    /// Outcome of each planned waypoint, in flight order.
+    pub waypoints: Vec<WaypointResult>,
+    /// Deviations from the planned track while flying the mission.
+    pub deviations: Vec<TrackDeviation>,
+    /// Timestamp at which a waypoint was first reached.
+    pub start_us: Option<u64>,
+    /// Timestamp at which the last planned waypoint was reached, if it was.
+    pub end_us: Option<u64>,
+}
+
+impl ConformanceReport {
+    /// Returns the number of waypoints reached.
+    pub fn reached_count(&self) -> usize {
+        self.waypoints
+            .iter()
+            .filter(|result| result.reached)
+            .count()
+    }
+
+    /// Returns the time from reaching the first waypoint to reaching the last one, if the
+    /// mission was completed and the log provides timestamps.
+    pub fn duration_us(&self) -> Option<u64> {
+        Some(self.end_us?.saturating_sub(self.start_us?))
+    }
+
+    /// Checks whether every waypoint was reached without leaving the planned track.
+    pub fn is_conformant(&self) -> bool {
+        self.reached_count() == self.waypoints.len() && self.deviations.is_empty()
+    }
+}
+
+/// Tolerances a flight is checked against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceTolerances {
+    /// Acceptance radius of waypoints without one in the plan, in meters.
+    pub acceptance_radius_m: f64,
+    /// Largest allowed horizontal distance from the planned track in meters.
+    pub track_tolerance_m: f64,
+}
+
+impl Default for ConformanceTolerances {
+    fn default() -> Self {
+        ConformanceTolerances {
+            acceptance_radius_m: 5.0,
+            track_tolerance_m: 10.0,
+        }
+    }
+}
+
+/// Returns the position of a point in meters east and north of an origin.
+///
+/// The earth is treated as flat around the origin, which is accurate for the distances within a
+/// mission.
+fn local_m(origin: (f64, f64), point: (f64, f64)) -> (f64, f64) {
+    let east = (point.1 - origin.1).to_radians() * origin.0.to_radians().cos() * EARTH_RADIUS_M;
+    let north = (point.0 - origin.0).to_radians() * EARTH_RADIUS_M;
+    (east, north)
+}
+
+/// Returns the horizontal distance in meters from a position to the segment between two
+/// waypoints, all as (latitude, longitude) in degrees.
+fn distance_to_leg_m(position: (f64, f64), from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (ax, ay) = local_m(position, from);
+    let (bx, by) = local_m(position, to);
+    let (dx, dy) = (bx - ax, by - ay);
+    let length_squared = dx * dx + dy * dy;
+    let along = if length_squared > 0.0 {
+        (-(ax * dx + ay * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (ax + along * dx).hypot(ay + along * dy)
+}
+
+/// Analyzer comparing the positions of a vehicle to a mission plan.
+///
+/// Positions are taken from the GLOBAL_POSITION_INT messages of the first system sending them.
+/// A waypoint is reached when a position is within its acceptance radius, in any order. The
+/// track is checked horizontally, from reaching the first waypoint until the last planned
+/// waypoint is reached, so that takeoff and the return home are not reported as deviations.
+pub struct MissionConformance {
+    tolerances: ConformanceTolerances,
+    system_id: Option<u8>,
+    results: Vec<WaypointResult>,
+    open: Option<TrackDeviation>,
+    deviations: Vec<TrackDeviation>,
+    start_us: Option<u64>,
+    end_us: Option<u64>,
+    in_mission: bool,
+}
+
+impl MissionConformance {
+    /// Creates a new `MissionConformance` analyzer.
+    ///
+    /// # Arguments
+    /// - `plan`: The planned mission.
+    /// - `tolerances`: The tolerances to check the flight against.
+    pub fn new(plan: MissionPlan, tolerances: ConformanceTolerances) -> Self {
+        MissionConformance {
+            tolerances,
+            system_id: None,
+            results: plan
+                .waypoints
+                .into_iter()
+                .map(|waypoint| WaypointResult {
+                    waypoint,
+                    reached_us: None,
+                    reached: false,
+                    closest_approach_m: None,
+                })
+                .collect(),
+            open: None,
+            deviations: Vec::new(),
+            start_us: None,
+            end_us: None,
+            in_mission: false,
+        }
+    }
+
+    /// Returns the horizontal distance in meters from a position to the planned track.
+    fn track_distance_m(&self, position: (f64, f64)) -> f64 {
+        let points: Vec<(f64, f64)> = self
+            .results
+            .iter()
+            .map(|result| (result.waypoint.latitude_deg, result.waypoint.longitude_deg))
+            .collect();
+        match points.as_slice() {
+            [] => 0.0,
+            [point] => distance_to_leg_m(position, *point, *point),
+            _ => points
+                .windows(2)
+                .map(|leg| distance_to_leg_m(position, leg[0], leg[1]))
+                .fold(f64::INFINITY, f64::min),
+        }
+    }
+}
+
+impl<M: Message> Analyzer<M> for MissionConformance {
+    type Report = ConformanceReport;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        if msg.message_id() != fields::GLOBAL_POSITION_INT_ID
+            || *self.system_id.get_or_insert(header.system_id) != header.system_id
+        {
+            return;
+        }
+        let payload = fields::payload(msg);
+        let position = (
+            fields::read_i32(&payload, 4) as f64 / 1e7,
+            fields::read_i32(&payload, 8) as f64 / 1e7,
+        );
+        if position == (0.0, 0.0) {
+            return;
+        }
+
+        let was_in_mission = self.in_mission;
+        let default_radius_m = self.tolerances.acceptance_radius_m;
+        for result in &mut self.results {
+            let waypoint = (result.waypoint.latitude_deg, result.waypoint.longitude_deg);
+            let distance_m = distance_to_leg_m(position, waypoint, waypoint);
+            result.closest_approach_m = Some(
+                result
+                    .closest_approach_m
+                    .map_or(distance_m, |closest| closest.min(distance_m)),
+            );
+            let radius_m = if result.waypoint.acceptance_radius_m > 0.0 {
+                result.waypoint.acceptance_radius_m
+            } else {
+                default_radius_m
+            };
+            if !result.reached && distance_m <= radius_m {
+                result.reached = true;
+                result.reached_us = entry.timestamp;
+                if !self.in_mission && self.start_us.is_none() {
+                    self.in_mission = true;
+                    self.start_us = entry.timestamp;
+                }
+            }
+        }
+        if self.results.last().is_some_and(|last| last.reached) && self.end_us.is_none() {
+            self.end_us = self.results.last().and_then(|last| last.reached_us);
+            self.in_mission = false;
+        }
+
+        if !was_in_mission {
+            return;
+        }
+        let deviation_m = self.track_distance_m(position);
+        if deviation_m > self.tolerances.track_tolerance_m {
+            let deviation = self.open.get_or_insert(TrackDeviation {
+                start_us: entry.timestamp,
+                end_us: entry.timestamp,
+                samples: 0,
+                max_deviation_m: 0.0,
+            });
+            deviation.samples += 1;
+            deviation.end_us = entry.timestamp.or(deviation.end_us);
+            deviation.max_deviation_m = deviation.max_deviation_m.max(deviation_m);
+        } else if let Some(mut deviation) = self.open.take() {
+            deviation.end_us = entry.timestamp.or(deviation.end_us);
+            self.deviations.push(deviation);
+        }
+    }
+
+    fn finish(mut self) -> ConformanceReport {
+        self.deviations.extend(self.open);
+        ConformanceReport {
+            waypoints: self.results,
+            deviations: self.deviations,
+            start_us: self.start_us,
+            end_us: self.end_us,
+        }
+    }
+}
+
+/// Reads a full log and compares the flight to a mission plan.
+///
+/// See `MissionConformance` for how the flight is checked and to run this analysis along with
+/// others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `plan`: The planned mission, such as read with `MissionPlan::load`.
+/// - `tolerances`: The tolerances to check the flight against.
+///
+/// # Returns
+/// The conformance report of the flight.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn mission_conformance<P: MavParser + ?Sized>(
+    parser: &mut P,
+    plan: &MissionPlan,
+    tolerances: &ConformanceTolerances,
+) -> std::io::Result<ConformanceReport> {
+    analyze(
+        parser,
+        MissionConformance::new(plan.clone(), tolerances.clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn position(timestamp: u64, latitude_deg: f64, longitude_deg: f64) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                lat: (latitude_deg * 1e7).round() as i32,
+                lon: (longitude_deg * 1e7).round() as i32,
+                relative_alt: 50_000,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// Plan with a takeoff, a waypoint, a camera command and a survey of one generated waypoint.
+    const PLAN: &str = r#"{
+        "fileType": "Plan",
+        "version": 1,
+        "mission": {
+            "plannedHomePosition": [47.0, 7.999, 400],
+            "items": [
+                {"type": "SimpleItem", "command": 22, "params": [0, 0, 0, null, 0, 0, 50]},
+                {"type": "SimpleItem", "command": 16, "params": [0, 0, 0, null, 47.0, 8.0, 50]},
+                {"type": "SimpleItem", "command": 206, "params": [10, 0, 1, 0, 0, 0, 0]},
+                {
+                    "type": "ComplexItem",
+                    "complexItemType": "survey",
+                    "TransectStyleComplexItem": {
+                        "Items": [
+                            {"type": "SimpleItem", "command": 16,
+                             "params": [0, 0, 0, null, 47.0, 8.01, 50]},
+                            {"type": "SimpleItem", "command": 16,
+                             "params": [0, 8, 0, null, 47.005, 8.01, 50]}
+                        ]
+                    }
+                }
+            ]
+        }
+    }"#;
+
+    /// Test that waypoints are read from a plan, expanding complex items.
+    #[test]
+    fn test_plan() {
+        let plan = MissionPlan::from_plan_json(PLAN).unwrap();
+        let sequences: Vec<u16> = plan.waypoints.iter().map(|w| w.sequence).collect();
+        assert_eq!(sequences, vec![2, 4, 5]);
+        assert_eq!(plan.waypoints[2].acceptance_radius_m, 8.0);
+
+        let structure = r#"{"mission": {"items": [{"type": "ComplexItem",
+            "complexItemType": "StructureScan"}]}}"#;
+        let error = MissionPlan::from_plan_json(structure).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+        let error = MissionPlan::from_plan_json("{}").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    /// Test that reached waypoints, track deviations and mission timing are reported.
+    #[test]
+    fn test_mission_conformance() {
+        let entries = VecDeque::from([
+            // climbing out at home
+            position(0, 47.0, 7.999),
+            position(1_000_000, 47.0, 8.0),
+            position(2_000_000, 47.0, 8.005),
+            // about 55 meters north of the first leg
+            position(3_000_000, 47.0005, 8.008),
+            position(4_000_000, 47.0, 8.01),
+            position(5_000_000, 47.003, 8.0101),
+        ]);
+        let plan = MissionPlan::from_plan_json(PLAN).unwrap();
+        let report = mission_conformance(
+            &mut EntryList(entries),
+            &plan,
+            &ConformanceTolerances::default(),
+        )
+        .unwrap();
+        let reached: Vec<Option<u64>> = report.waypoints.iter().map(|r| r.reached_us).collect();
+        assert_eq!(reached, vec![Some(1_000_000), Some(4_000_000), None]);
+        assert_eq!(report.reached_count(), 2);
+        let closest_m = report.waypoints[2].closest_approach_m.unwrap();
+        assert!((closest_m - 222.4).abs() < 1.0, "{closest_m}");
+        assert_eq!(report.start_us, Some(1_000_000));
+        assert_eq!(report.duration_us(), None);
+
+        assert_eq!(report.deviations.len(), 1);
+        let deviation = &report.deviations[0];
+        assert_eq!(deviation.start_us, Some(3_000_000));
+        assert_eq!(deviation.end_us, Some(4_000_000));
+        assert_eq!(deviation.samples, 1);
+        assert!((deviation.max_deviation_m - 55.6).abs() < 0.5);
+        assert!(!report.is_conformant());
+    }
+}
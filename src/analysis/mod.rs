@@ -0,0 +1,58 @@
+//! Utilities for summarizing the content of a parsed MAVLink log.
+//!
+//! Every analysis in this module is written against the `MavParser` trait so that it can be run
+//! on any supported log format. Analyses that only need to see each entry once also implement
+//! `Analyzer`, so that several of them can be run with `analyze` in a single pass over a log, or
+//! be fed live data through `SharedAnalyzer`.
+
+pub mod analyzer;
+pub mod anomaly;
+pub mod as_applied;
+pub mod bandwidth;
+pub mod commands;
+pub mod coverage;
+pub mod decimate;
+pub mod discovery;
+pub mod envelope;
+pub mod features;
+pub mod filter;
+pub mod geofence;
+pub mod latency;
+pub mod live;
+#[cfg(feature = "mission")]
+pub mod mission;
+pub mod passthrough;
+pub mod preview;
+pub mod rates;
+pub mod resample;
+
+pub use analyzer::{Analyzer, analyze};
+pub use anomaly::{
+    AnomalyDetector, AnomalyKind, AnomalyThresholds, Finding, Severity, Threshold, detect_anomalies,
+};
+pub use as_applied::{
+    AppliedSegment, AsAppliedConfig, AsAppliedMapper, AsAppliedReport, PositionedEvent,
+    as_applied_map,
+};
+pub use bandwidth::{BandwidthCounter, BandwidthReport, ByteCount, Framing, bandwidth};
+pub use commands::{CommandExchange, CommandKind, CommandTracker, command_exchanges};
+pub use coverage::{
+    CoverageCell, CoverageConfig, CoverageMapper, CoverageReport, FieldCoverage, coverage_grid,
+};
+pub use decimate::{Decimate, Rate};
+pub use discovery::{SystemDiscovery, SystemInfo, discover_systems};
+pub use envelope::{ArmedSegment, ChannelStats, FlightEnvelope, flight_envelope};
+pub use features::{Aggregate, WindowFeatures, extract_features, window_features};
+pub use filter::{FilterError, Filtered, ParserFilter};
+pub use geofence::{Excursion, GeofenceMonitor, OperatingArea, Violation, geofence_excursions};
+pub use latency::{LatencySample, LatencySource, LatencyTracker, round_trip_latency};
+pub use live::{AnalyzerHandle, GpsFix, LiveSnapshot, LiveStats, SharedAnalyzer};
+#[cfg(feature = "mission")]
+pub use mission::{
+    ConformanceReport, ConformanceTolerances, MissionConformance, MissionPlan, PlannedWaypoint,
+    TrackDeviation, WaypointResult, mission_conformance,
+};
+pub use passthrough::{PassthroughChannel, extract_passthrough};
+pub use preview::preview;
+pub use rates::{RateCounter, RateHistogram, rate_histogram};
+pub use resample::{Interpolation, ResampledChannels, Resampler, resample};
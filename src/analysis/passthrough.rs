@@ -0,0 +1,195 @@
+//! Extraction of the byte streams tunnelled through SERIAL_CONTROL and TUNNEL messages.
+//!
+//! These messages carry arbitrary protocols, such as the NMEA output of a companion computer,
+//! a gimbal protocol or an autopilot shell, split across many MAVLink messages. Concatenating
+//! their payloads per channel restores the original streams.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+
+use crate::fields;
+use crate::mav_parser::{MavParser, for_each_entry};
+
+/// SERIAL_CONTROL_FLAG_REPLY, set on data sent back by the serial device.
+const SERIAL_CONTROL_FLAG_REPLY: u8 = 0x01;
+/// Size of the data field of SERIAL_CONTROL.
+const SERIAL_CONTROL_DATA_SIZE: usize = 70;
+/// Size of the payload field of TUNNEL.
+const TUNNEL_PAYLOAD_SIZE: usize = 128;
+
+/// A byte stream carried by SERIAL_CONTROL or TUNNEL messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PassthroughChannel {
+    /// SERIAL_CONTROL data sent by the given component for a serial device.
+    Serial {
+        /// MAVLink system id of the sender.
+        system_id: u8,
+        /// MAVLink component id of the sender.
+        component_id: u8,
+        /// SERIAL_CONTROL_DEV enum value of the serial device.
+        device: u8,
+        /// Whether the data was sent back by the device rather than written to it.
+        reply: bool,
+    },
+    /// TUNNEL payloads sent by the given component to a target component.
+    Tunnel {
+        /// MAVLink system id of the sender.
+        system_id: u8,
+        /// MAVLink component id of the sender.
+        component_id: u8,
+        /// MAVLink system id of the target.
+        target_system: u8,
+        /// MAVLink component id of the target.
+        target_component: u8,
+        /// MAV_TUNNEL_PAYLOAD_TYPE enum value identifying the tunnelled protocol.
+        payload_type: u16,
+    },
+}
+
+/// Reads a full log and reassembles the byte stream of every SERIAL_CONTROL and TUNNEL channel.
+///
+/// SERIAL_CONTROL messages without data, such as the polls requesting device output, do not
+/// contribute to the streams.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+///
+/// # Returns
+/// The bytes of each channel in log order.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn extract_passthrough<P: MavParser + ?Sized>(
+    parser: &mut P,
+) -> std::io::Result<BTreeMap<PassthroughChannel, Vec<u8>>> {
+    let mut streams: BTreeMap<PassthroughChannel, Vec<u8>> = BTreeMap::new();
+    for_each_entry(parser, |entry| {
+        let (header, msg) = match (entry.mav_header, entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return Ok(()),
+        };
+        let (channel, data) = match msg.message_id() {
+            fields::SERIAL_CONTROL_ID => {
+                let payload = fields::payload(&msg);
+                let count = (fields::read_u8(&payload, 8) as usize).min(SERIAL_CONTROL_DATA_SIZE);
+                let channel = PassthroughChannel::Serial {
+                    system_id: header.system_id,
+                    component_id: header.component_id,
+                    device: fields::read_u8(&payload, 6),
+                    reply: fields::read_u8(&payload, 7) & SERIAL_CONTROL_FLAG_REPLY != 0,
+                };
+                (channel, payload[9..9 + count].to_vec())
+            }
+            fields::TUNNEL_ID => {
+                let payload = fields::payload(&msg);
+                let length = (fields::read_u8(&payload, 4) as usize).min(TUNNEL_PAYLOAD_SIZE);
+                let channel = PassthroughChannel::Tunnel {
+                    system_id: header.system_id,
+                    component_id: header.component_id,
+                    target_system: fields::read_u8(&payload, 2),
+                    target_component: fields::read_u8(&payload, 3),
+                    payload_type: fields::read_u16(&payload, 0),
+                };
+                (channel, payload[5..5 + length].to_vec())
+            }
+            _ => return Ok(()),
+        };
+        if !data.is_empty() {
+            streams.entry(channel).or_default().extend_from_slice(&data);
+        }
+        Ok(())
+    })?;
+    Ok(streams)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{
+        MavMessage, MavTunnelPayloadType, SERIAL_CONTROL_DATA, SerialControlDev, SerialControlFlag,
+        TUNNEL_DATA,
+    };
+
+    use super::*;
+    use crate::mav_parser::{EntryList, LogEntry};
+
+    fn entry(system_id: u8, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            mav_header: Some(MavHeader {
+                system_id,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    fn serial(flags: SerialControlFlag, text: &str) -> MavMessage {
+        let mut data = [0u8; SERIAL_CONTROL_DATA_SIZE];
+        data[..text.len()].copy_from_slice(text.as_bytes());
+        MavMessage::SERIAL_CONTROL(SERIAL_CONTROL_DATA {
+            device: SerialControlDev::SERIAL_CONTROL_DEV_SHELL,
+            flags,
+            count: text.len() as u8,
+            data,
+            ..Default::default()
+        })
+    }
+
+    fn tunnel(payload_type: MavTunnelPayloadType, bytes: &[u8]) -> MavMessage {
+        let mut payload = [0u8; TUNNEL_PAYLOAD_SIZE];
+        payload[..bytes.len()].copy_from_slice(bytes);
+        MavMessage::TUNNEL(TUNNEL_DATA {
+            payload_type,
+            target_system: 1,
+            target_component: 154,
+            payload_length: bytes.len() as u8,
+            payload,
+        })
+    }
+
+    /// Test that SERIAL_CONTROL and TUNNEL payloads are concatenated per channel.
+    #[test]
+    fn test_extract_passthrough() {
+        let respond = SerialControlFlag::SERIAL_CONTROL_FLAG_RESPOND;
+        let reply = SerialControlFlag::SERIAL_CONTROL_FLAG_REPLY;
+        let storm32 = MavTunnelPayloadType::MAV_TUNNEL_PAYLOAD_TYPE_STORM32_RESERVED0;
+        let mut parser = EntryList(VecDeque::from([
+            entry(255, serial(respond, "ls\n")),
+            entry(1, serial(reply, "bin ")),
+            entry(255, serial(respond, "")),
+            entry(1, tunnel(storm32, b"$GPGGA,")),
+            entry(1, serial(reply, "etc\n")),
+            entry(1, MavMessage::HEARTBEAT(Default::default())),
+            entry(1, tunnel(storm32, b"123519*47\r\n")),
+        ]));
+
+        let streams = extract_passthrough(&mut parser).unwrap();
+        assert_eq!(streams.len(), 3);
+        let request = PassthroughChannel::Serial {
+            system_id: 255,
+            component_id: 1,
+            device: 10,
+            reply: false,
+        };
+        assert_eq!(streams[&request], b"ls\n");
+        let response = PassthroughChannel::Serial {
+            system_id: 1,
+            component_id: 1,
+            device: 10,
+            reply: true,
+        };
+        assert_eq!(streams[&response], b"bin etc\n");
+        let tunnel = PassthroughChannel::Tunnel {
+            system_id: 1,
+            component_id: 1,
+            target_system: 1,
+            target_component: 154,
+            payload_type: 200,
+        };
+        assert_eq!(streams[&tunnel], b"$GPGGA,123519*47\r\n");
+    }
+}
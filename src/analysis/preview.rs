@@ -0,0 +1,186 @@
+//! Generation of a bounded overview of a log.
+//!
+//! A log overview UI needs a few thousand entries spread evenly over the log and every entry
+//! marking an event, not millions of samples. `preview` selects them in a single pass with
+//! memory bounded by the requested budget.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser, for_each_entry};
+
+/// Width of the time buckets the preview starts with, in microseconds.
+const INITIAL_BUCKET_WIDTH_US: u64 = 1000;
+
+/// Reads a full log and selects a time-uniform subset of its entries along with every event.
+///
+/// The log is divided into equally long time slots and the first entry of each slot is kept,
+/// with the slots growing as needed to stay within the budget. Events are always kept in
+/// addition to the budget. Events are:
+/// - HEARTBEAT messages whose base mode or custom mode differs from the previous HEARTBEAT of
+///   the same component, including the first one.
+/// - STATUSTEXT messages.
+/// - Text entries, which applications use as bookmarks.
+///
+/// Entries without a timestamp are placed at the last timestamp seen.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `budget_entries`: The maximum number of entries selected for time coverage.
+///
+/// # Returns
+/// The selected entries in log order.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn preview<P: MavParser + ?Sized>(
+    parser: &mut P,
+    budget_entries: usize,
+) -> std::io::Result<Vec<LogEntry<P::M>>> {
+    let mut events: Vec<(usize, LogEntry<P::M>)> = Vec::new();
+    let mut buckets: BTreeMap<u64, (usize, LogEntry<P::M>)> = BTreeMap::new();
+    let mut bucket_width_us: u64 = INITIAL_BUCKET_WIDTH_US;
+    let mut modes: BTreeMap<(u8, u8), (u8, u32)> = BTreeMap::new();
+    let mut first_timestamp: Option<u64> = None;
+    let mut last_timestamp: u64 = 0;
+    let mut index: usize = 0;
+    for_each_entry(parser, |entry| {
+        index += 1;
+        if is_event(&entry, &mut modes) {
+            events.push((index, entry));
+            return Ok(());
+        }
+        if budget_entries == 0 {
+            return Ok(());
+        }
+        if let Some(timestamp) = entry.timestamp {
+            last_timestamp = timestamp;
+        }
+        let start = *first_timestamp.get_or_insert(last_timestamp);
+        let bucket = last_timestamp.saturating_sub(start) / bucket_width_us;
+        buckets.entry(bucket).or_insert((index, entry));
+        while buckets.len() > budget_entries {
+            // merge pairs of neighbouring slots, keeping the first entry of each pair
+            bucket_width_us *= 2;
+            let mut merged: BTreeMap<u64, (usize, LogEntry<P::M>)> = BTreeMap::new();
+            for (bucket, selected) in std::mem::take(&mut buckets) {
+                merged.entry(bucket / 2).or_insert(selected);
+            }
+            buckets = merged;
+        }
+        Ok(())
+    })?;
+    let mut selected: Vec<(usize, LogEntry<P::M>)> = events;
+    selected.extend(buckets.into_values());
+    selected.sort_by_key(|(index, _)| *index);
+    Ok(selected.into_iter().map(|(_, entry)| entry).collect())
+}
+
+/// Checks whether an entry marks an event, tracking the mode of each component.
+fn is_event<M: Message>(entry: &LogEntry<M>, modes: &mut BTreeMap<(u8, u8), (u8, u32)>) -> bool {
+    if entry.text.is_some() {
+        return true;
+    }
+    let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+        (Some(header), Some(msg)) => (header, msg),
+        _ => return false,
+    };
+    match msg.message_id() {
+        fields::STATUSTEXT_ID => true,
+        fields::HEARTBEAT_ID => {
+            let payload = fields::payload(msg);
+            let mode = (fields::read_u8(&payload, 6), fields::read_u32(&payload, 0));
+            let component = (header.system_id, header.component_id);
+            modes.insert(component, mode) != Some(mode)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{HEARTBEAT_DATA, MavMessage, STATUSTEXT_DATA};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    fn heartbeat(custom_mode: u32) -> MavMessage {
+        MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode,
+            ..Default::default()
+        })
+    }
+
+    /// Test that the preview covers the log evenly within the budget and keeps every event.
+    #[test]
+    fn test_preview() {
+        let mut entries: VecDeque<LogEntry<MavMessage>> = VecDeque::new();
+        // 100 s of 50 Hz ATTITUDE with a HEARTBEAT every second and a mode change at 40 s
+        for i in 0..5000u64 {
+            let timestamp = i * 20_000;
+            entries.push_back(entry(timestamp, MavMessage::ATTITUDE(Default::default())));
+            if i % 50 == 0 {
+                let mode = if i < 2000 { 0 } else { 3 };
+                entries.push_back(entry(timestamp, heartbeat(mode)));
+            }
+            if i == 3000 {
+                entries.push_back(entry(
+                    timestamp,
+                    MavMessage::STATUSTEXT(STATUSTEXT_DATA::default()),
+                ));
+                entries.push_back(LogEntry {
+                    text: Some(String::from("bookmark")),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let selected = preview(&mut EntryList(entries), 100).unwrap();
+        let mut attitudes: Vec<u64> = Vec::new();
+        let mut events: Vec<u64> = Vec::new();
+        for entry in &selected {
+            match &entry.mav_message {
+                Some(MavMessage::ATTITUDE(_)) => attitudes.push(entry.timestamp.unwrap()),
+                Some(_) => events.push(entry.timestamp.unwrap()),
+                None => events.push(0),
+            }
+        }
+        assert_eq!(events, vec![0, 40_000_000, 60_000_000, 0]);
+        assert!(attitudes.len() <= 100);
+        assert!(attitudes.len() >= 50);
+        assert!(attitudes.windows(2).all(|pair| pair[0] < pair[1]));
+        // no gap between selected entries is much larger than the average spacing
+        let largest_gap = attitudes.windows(2).map(|pair| pair[1] - pair[0]).max();
+        assert!(largest_gap.unwrap() <= 2 * 100_000_000 / attitudes.len() as u64);
+    }
+
+    /// Test that a budget of 0 selects only the events.
+    #[test]
+    fn test_preview_events_only() {
+        let entries = VecDeque::from([
+            entry(0, heartbeat(1)),
+            entry(1, MavMessage::ATTITUDE(Default::default())),
+            entry(2, heartbeat(1)),
+        ]);
+        let selected = preview(&mut EntryList(entries), 0).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].timestamp, Some(0));
+    }
+}
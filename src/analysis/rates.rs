@@ -0,0 +1,220 @@
+//! Message rates of a log over time.
+//!
+//! Counting the messages of each id in fixed time buckets shows telemetry dropouts as gaps and
+//! bandwidth hogs as the highest rows of the histogram.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Number of messages per message id in consecutive time buckets of equal length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateHistogram {
+    /// Timestamp of the start of the first bucket, the timestamp of the first message.
+    pub start_us: u64,
+    /// Length of each bucket in microseconds.
+    pub bucket_us: u64,
+    /// MAVLink message ids present in the log, in ascending order.
+    pub message_ids: Vec<u32>,
+    /// Number of messages per bucket for each message id, in the order of `message_ids`. Every
+    /// row has the same length, covering the log from its first to its last message.
+    pub counts: Vec<Vec<u32>>,
+}
+
+impl RateHistogram {
+    /// Returns the number of buckets covering the log.
+    pub fn bucket_count(&self) -> usize {
+        self.counts.first().map_or(0, |row| row.len())
+    }
+
+    /// Returns the start timestamp of a bucket.
+    pub fn bucket_start_us(&self, bucket: usize) -> u64 {
+        self.start_us + bucket as u64 * self.bucket_us
+    }
+
+    /// Returns the rate of a message id in each bucket.
+    ///
+    /// # Arguments
+    /// - `message_id`: The MAVLink message id.
+    ///
+    /// # Returns
+    /// The rate in Hz per bucket, or `None` if the message id is not in the log.
+    pub fn rates_hz(&self, message_id: u32) -> Option<Vec<f64>> {
+        let row = self.message_ids.binary_search(&message_id).ok()?;
+        let bucket_s = self.bucket_us as f64 / 1e6;
+        Some(
+            self.counts[row]
+                .iter()
+                .map(|&count| count as f64 / bucket_s)
+                .collect(),
+        )
+    }
+}
+
+/// Analyzer counting the messages of each message id in time buckets.
+///
+/// Messages from all systems are counted together. Messages without a timestamp cannot be
+/// placed in a bucket and are not counted, and messages timestamped before the first message
+/// are counted in the first bucket.
+pub struct RateCounter {
+    bucket_us: u64,
+    start_us: Option<u64>,
+    bucket_count: usize,
+    counts: BTreeMap<u32, Vec<u32>>,
+}
+
+impl RateCounter {
+    /// Creates a new `RateCounter` analyzer.
+    ///
+    /// # Arguments
+    /// - `bucket_us`: The length of each bucket in microseconds, for example 1 000 000 for
+    ///   rates per second.
+    ///
+    /// # Panics
+    /// Panics if `bucket_us` is 0.
+    pub fn new(bucket_us: u64) -> Self {
+        assert!(bucket_us > 0, "The bucket length must not be 0");
+        RateCounter {
+            bucket_us,
+            start_us: None,
+            bucket_count: 0,
+            counts: BTreeMap::new(),
+        }
+    }
+}
+
+impl<M: Message> Analyzer<M> for RateCounter {
+    /// The histogram of the log, without any bucket if the log has no timestamped message.
+    type Report = RateHistogram;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (Some(timestamp), Some(msg)) = (entry.timestamp, &entry.mav_message) else {
+            return;
+        };
+        let start = *self.start_us.get_or_insert(timestamp);
+        let bucket = (timestamp.saturating_sub(start) / self.bucket_us) as usize;
+        self.bucket_count = self.bucket_count.max(bucket + 1);
+        let row = self.counts.entry(msg.message_id()).or_default();
+        if row.len() <= bucket {
+            row.resize(bucket + 1, 0);
+        }
+        row[bucket] += 1;
+    }
+
+    fn finish(self) -> RateHistogram {
+        let mut message_ids: Vec<u32> = Vec::with_capacity(self.counts.len());
+        let mut rows: Vec<Vec<u32>> = Vec::with_capacity(self.counts.len());
+        for (message_id, mut row) in self.counts {
+            row.resize(self.bucket_count, 0);
+            message_ids.push(message_id);
+            rows.push(row);
+        }
+        RateHistogram {
+            start_us: self.start_us.unwrap_or(0),
+            bucket_us: self.bucket_us,
+            message_ids,
+            counts: rows,
+        }
+    }
+}
+
+/// Reads a full log and counts the messages of each message id in time buckets.
+///
+/// See `RateCounter` for how messages are counted and to run this analysis along with others.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `bucket_us`: The length of each bucket in microseconds, for example 1 000 000 for rates
+///   per second.
+///
+/// # Returns
+/// The histogram of the log, without any bucket if the log has no timestamped message.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read, or of kind `InvalidInput` if
+/// `bucket_us` is 0.
+pub fn rate_histogram<P: MavParser + ?Sized>(
+    parser: &mut P,
+    bucket_us: u64,
+) -> std::io::Result<RateHistogram> {
+    if bucket_us == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "The bucket length must not be 0",
+        ));
+    }
+    analyze(parser, RateCounter::new(bucket_us))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::MavMessage;
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(timestamp: Option<u64>, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp,
+            mav_header: Some(MavHeader::default()),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    /// Test that messages are counted per message id and bucket, including empty buckets.
+    #[test]
+    fn test_rate_histogram() {
+        let mut entries: VecDeque<LogEntry<MavMessage>> = VecDeque::new();
+        // 10 Hz ATTITUDE for 4 s with a dropout in the third second, 1 Hz HEARTBEAT
+        for i in 0..40u64 {
+            let timestamp = 5_000_000 + i * 100_000;
+            if !(20..30).contains(&i) {
+                entries.push_back(entry(
+                    Some(timestamp),
+                    MavMessage::ATTITUDE(Default::default()),
+                ));
+            }
+            if i % 10 == 0 {
+                entries.push_back(entry(
+                    Some(timestamp),
+                    MavMessage::HEARTBEAT(Default::default()),
+                ));
+            }
+        }
+        entries.push_back(entry(None, MavMessage::HEARTBEAT(Default::default())));
+        entries.push_back(LogEntry {
+            text: Some(String::from("note")),
+            ..Default::default()
+        });
+
+        let histogram = rate_histogram(&mut EntryList(entries), 1_000_000).unwrap();
+        assert_eq!(histogram.start_us, 5_000_000);
+        assert_eq!(histogram.bucket_count(), 4);
+        assert_eq!(histogram.bucket_start_us(2), 7_000_000);
+        assert_eq!(histogram.message_ids, vec![0, 30]);
+        assert_eq!(
+            histogram.counts,
+            vec![vec![1, 1, 1, 1], vec![10, 10, 0, 10]]
+        );
+        assert_eq!(histogram.rates_hz(30).unwrap(), vec![10.0, 10.0, 0.0, 10.0]);
+        assert!(histogram.rates_hz(1).is_none());
+    }
+
+    /// Test that an empty log gives an empty histogram and that empty buckets are rejected.
+    #[test]
+    fn test_rate_histogram_empty() {
+        let histogram =
+            rate_histogram(&mut EntryList::<MavMessage>(VecDeque::new()), 1_000_000).unwrap();
+        assert_eq!(histogram.bucket_count(), 0);
+        assert!(histogram.message_ids.is_empty());
+
+        let error = rate_histogram(&mut EntryList::<MavMessage>(VecDeque::new()), 0).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
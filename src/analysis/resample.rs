@@ -0,0 +1,462 @@
+//! Numeric channels resampled on a fixed time grid.
+//!
+//! Messages are logged at different rates and with jitter, while machine learning features and
+//! channel comparisons need one value per channel at the same instants. A `Resampler` reads the
+//! selected fields, named `MESSAGE.field` such as `ATTITUDE.roll`, and outputs them on a grid
+//! of fixed period, holding or linearly interpolating between samples. Array elements are
+//! selected by suffixing the field with `_` and their index, such as `RC_CHANNELS_RAW.chan1_raw`
+//! or `ACTUATOR_CONTROL_TARGET.controls_0`. Channels computed from the resampled ones, such as
+//! a ground speed, are added with `ResampledChannels::derive`.
+//!
+//! Fields are read from the `Debug` representation of messages so that every dialect is
+//! supported. Boolean fields read as 0 or 1, and fields that are not numbers, such as enums,
+//! are not sampled. Samples of every system are merged, so logs with several vehicles should be
+//! narrowed to one with a `ParserFilter` first.
+use std::fmt::Debug;
+use std::io::Write;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::derive::DerivedChannel;
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// How values between two samples are computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The value of the last sample is held until the next one.
+    ZeroOrderHold,
+    /// Values are linearly interpolated between samples. The last sample is held.
+    Linear,
+}
+
+/// Channels sampled on a fixed time grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResampledChannels {
+    /// Timestamp of the first grid point, a multiple of the period.
+    pub start_us: u64,
+    /// Period of the grid in microseconds.
+    pub period_us: u64,
+    /// Names of the channels, in the order they were selected.
+    pub channels: Vec<String>,
+    /// Values of each channel per grid point, in the order of `channels`. Grid points before the
+    /// first sample of a channel, or within a gap, are NaN.
+    pub values: Vec<Vec<f64>>,
+}
+
+impl ResampledChannels {
+    /// Returns the number of grid points.
+    pub fn len(&self) -> usize {
+        self.values.first().map_or(0, |values| values.len())
+    }
+
+    /// Returns whether the grid has no points, as when no channel was sampled.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the timestamp of a grid point.
+    pub fn timestamp_us(&self, index: usize) -> u64 {
+        self.start_us + index as u64 * self.period_us
+    }
+
+    /// Returns the values of a channel by name.
+    pub fn channel(&self, name: &str) -> Option<&[f64]> {
+        let index = self.channels.iter().position(|channel| channel == name)?;
+        Some(&self.values[index])
+    }
+
+    /// Adds a channel computed from the other channels at each grid point.
+    ///
+    /// The variables of the channel are the names of previously resampled or derived channels,
+    /// such as `GLOBAL_POSITION_INT.vx`. Grid points where one of them is NaN are NaN.
+    ///
+    /// # Arguments
+    /// - `derived`: The channel to add, such as one parsed from
+    ///   `speed = hypot(GLOBAL_POSITION_INT.vx, GLOBAL_POSITION_INT.vy)`.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` of kind `InvalidInput` if a variable is not a channel, or if a
+    /// channel already has the name of the derived channel.
+    pub fn derive(&mut self, derived: &DerivedChannel) -> std::io::Result<()> {
+        if self.channel(derived.name()).is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Channel {} already exists", derived.name()),
+            ));
+        }
+        let mut inputs = Vec::new();
+        for variable in derived.variables() {
+            let Some(index) = self.channels.iter().position(|channel| channel == variable) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Channel {variable} is not resampled"),
+                ));
+            };
+            inputs.push((variable, index));
+        }
+        let values = (0..self.len())
+            .map(|point| {
+                let lookup = |name: &str| {
+                    inputs
+                        .iter()
+                        .find(|(variable, _)| *variable == name)
+                        .map(|&(_, index)| self.values[index][point])
+                };
+                derived.evaluate(&lookup).unwrap_or(f64::NAN)
+            })
+            .collect();
+        self.channels.push(derived.name().to_string());
+        self.values.push(values);
+        Ok(())
+    }
+
+    /// Writes the channels as CSV, one row per grid point.
+    ///
+    /// The first column holds the timestamp in microseconds and the header row holds the
+    /// channel names. NaN values are written as empty cells.
+    ///
+    /// # Arguments
+    /// - `writer`: The writer receiving the CSV text.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the text could not be written.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "timestamp_us")?;
+        for channel in &self.channels {
+            write!(writer, ",{channel}")?;
+        }
+        writeln!(writer)?;
+        for index in 0..self.len() {
+            write!(writer, "{}", self.timestamp_us(index))?;
+            for values in &self.values {
+                let value = values[index];
+                if value.is_nan() {
+                    write!(writer, ",")?;
+                } else {
+                    write!(writer, ",{value}")?;
+                }
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+}
+
+/// A selected field and its samples.
+struct Channel {
+    message: String,
+    field: String,
+    /// Timestamps and values of the field, in log order.
+    samples: Vec<(u64, f64)>,
+}
+
+/// Analyzer sampling numeric channels and resampling them on a fixed grid.
+///
+/// Entries without a timestamp cannot be placed on the grid and are ignored.
+pub struct Resampler {
+    period_us: u64,
+    interpolation: Interpolation,
+    max_gap_us: Option<u64>,
+    channels: Vec<Channel>,
+}
+
+impl Resampler {
+    /// Creates a new `Resampler` analyzer holding values between samples.
+    ///
+    /// # Arguments
+    /// - `channels`: The channels to resample, named `MESSAGE.field`.
+    /// - `period_us`: The period of the grid in microseconds.
+    ///
+    /// # Returns
+    /// The resampler, or an `io::Error` of kind `InvalidInput` if a channel name has no `.`.
+    ///
+    /// # Panics
+    /// Panics if `period_us` is 0.
+    pub fn new(channels: &[&str], period_us: u64) -> std::io::Result<Self> {
+        assert!(period_us > 0, "The grid period must not be 0");
+        let channels = channels
+            .iter()
+            .map(|name| match name.split_once('.') {
+                Some((message, field)) => Ok(Channel {
+                    message: message.to_string(),
+                    field: field.to_string(),
+                    samples: Vec::new(),
+                }),
+                None => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Channel {name} is not named MESSAGE.field"),
+                )),
+            })
+            .collect::<std::io::Result<Vec<Channel>>>()?;
+        Ok(Self {
+            period_us,
+            interpolation: Interpolation::ZeroOrderHold,
+            max_gap_us: None,
+            channels,
+        })
+    }
+
+    /// Sets how values between samples are computed.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    /// Leaves grid points without a value when the surrounding samples are too far apart.
+    ///
+    /// # Arguments
+    /// - `max_gap_us`: The longest time between samples, or since the last sample, over which
+    ///   values are held or interpolated.
+    pub fn set_max_gap(&mut self, max_gap_us: u64) {
+        self.max_gap_us = Some(max_gap_us);
+    }
+
+    /// Computes the value of a channel at each grid point.
+    fn resample(&self, samples: &[(u64, f64)], start_us: u64, len: usize) -> Vec<f64> {
+        let max_gap_us = self.max_gap_us.unwrap_or(u64::MAX);
+        let mut values = Vec::with_capacity(len);
+        // index of the first sample after the grid point
+        let mut next = 0;
+        for index in 0..len {
+            let time_us = start_us + index as u64 * self.period_us;
+            while next < samples.len() && samples[next].0 <= time_us {
+                next += 1;
+            }
+            let value = match (next.checked_sub(1).map(|i| samples[i]), samples.get(next)) {
+                (None, _) => f64::NAN,
+                (Some((before_us, before)), Some(&(after_us, after)))
+                    if self.interpolation == Interpolation::Linear =>
+                {
+                    if after_us - before_us > max_gap_us {
+                        f64::NAN
+                    } else {
+                        let fraction = (time_us - before_us) as f64 / (after_us - before_us) as f64;
+                        before + (after - before) * fraction
+                    }
+                }
+                (Some((before_us, before)), after) => {
+                    let gap_us =
+                        after.map_or(time_us - before_us, |&(after_us, _)| after_us - before_us);
+                    if gap_us > max_gap_us || time_us - before_us > max_gap_us {
+                        f64::NAN
+                    } else {
+                        before
+                    }
+                }
+            };
+            values.push(value);
+        }
+        values
+    }
+}
+
+impl<M: Message + Debug> Analyzer<M> for Resampler {
+    type Report = ResampledChannels;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        let (Some(timestamp), Some(msg)) = (entry.timestamp, &entry.mav_message) else {
+            return;
+        };
+        let name = msg.message_name();
+        if !self.channels.iter().any(|channel| channel.message == name) {
+            return;
+        }
+        let values = fields::debug_fields(&format!("{msg:?}"));
+        for channel in self
+            .channels
+            .iter_mut()
+            .filter(|channel| channel.message == name)
+        {
+            let value = values
+                .iter()
+                .find(|(field, _)| *field == channel.field)
+                .and_then(|(_, value)| match value.as_str() {
+                    "true" => Some(1.0),
+                    "false" => Some(0.0),
+                    value => value.parse::<f64>().ok(),
+                });
+            if let Some(value) = value {
+                channel.samples.push((timestamp, value));
+            }
+        }
+    }
+
+    fn finish(mut self) -> ResampledChannels {
+        for channel in &mut self.channels {
+            // entries of a log are nearly sorted, keep the order of equal timestamps
+            channel.samples.sort_by_key(|&(timestamp, _)| timestamp);
+        }
+        let first_us = self
+            .channels
+            .iter()
+            .filter_map(|channel| channel.samples.first().map(|&(timestamp, _)| timestamp))
+            .min();
+        let last_us = self
+            .channels
+            .iter()
+            .filter_map(|channel| channel.samples.last().map(|&(timestamp, _)| timestamp))
+            .max();
+        let (start_us, len) = match (first_us, last_us) {
+            (Some(first_us), Some(last_us)) => {
+                let start_us = first_us - first_us % self.period_us;
+                (
+                    start_us,
+                    ((last_us - start_us) / self.period_us) as usize + 1,
+                )
+            }
+            _ => (0, 0),
+        };
+        let values = self
+            .channels
+            .iter()
+            .map(|channel| self.resample(&channel.samples, start_us, len))
+            .collect();
+        ResampledChannels {
+            start_us,
+            period_us: self.period_us,
+            channels: self
+                .channels
+                .iter()
+                .map(|channel| format!("{}.{}", channel.message, channel.field))
+                .collect(),
+            values,
+        }
+    }
+}
+
+/// Resamples numeric channels of a log on a fixed time grid.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `channels`: The channels to resample, named `MESSAGE.field`.
+/// - `period_us`: The period of the grid in microseconds.
+/// - `interpolation`: How values between samples are computed.
+///
+/// # Returns
+/// The resampled channels.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read, or of kind `InvalidInput` if a channel
+/// name has no `.`.
+///
+/// # Panics
+/// Panics if `period_us` is 0.
+pub fn resample<P>(
+    parser: &mut P,
+    channels: &[&str],
+    period_us: u64,
+    interpolation: Interpolation,
+) -> std::io::Result<ResampledChannels>
+where
+    P: MavParser + ?Sized,
+    P::M: Debug,
+{
+    let mut resampler = Resampler::new(channels, period_us)?;
+    resampler.set_interpolation(interpolation);
+    analyze(parser, resampler)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::common::{ATTITUDE_DATA, MavMessage, SYS_STATUS_DATA};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn log() -> EntryList {
+        let attitude = |timestamp: u64, roll: f32| LogEntry {
+            timestamp: Some(timestamp),
+            mav_message: Some(MavMessage::ATTITUDE(ATTITUDE_DATA {
+                roll,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let status = |timestamp: u64, voltage_battery: u16| LogEntry {
+            timestamp: Some(timestamp),
+            mav_message: Some(MavMessage::SYS_STATUS(SYS_STATUS_DATA {
+                voltage_battery,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        EntryList(VecDeque::from(vec![
+            attitude(1_050_000, 0.0),
+            status(1_100_000, 12000),
+            attitude(1_250_000, 1.0),
+            attitude(1_850_000, 2.0),
+            status(1_900_000, 11000),
+        ]))
+    }
+
+    /// Test that channels logged at different rates are held on a common grid.
+    #[test]
+    fn test_resample_zero_order_hold() {
+        let channels = ["ATTITUDE.roll", "SYS_STATUS.voltage_battery"];
+        let resampled =
+            resample(&mut log(), &channels, 200_000, Interpolation::ZeroOrderHold).unwrap();
+        assert_eq!(resampled.start_us, 1_000_000);
+        assert_eq!(resampled.len(), 5);
+        let roll = resampled.channel("ATTITUDE.roll").unwrap();
+        assert!(roll[0].is_nan());
+        assert_eq!(&roll[1..], &[0.0, 1.0, 1.0, 1.0]);
+        let voltage = resampled.channel("SYS_STATUS.voltage_battery").unwrap();
+        assert!(voltage[0].is_nan());
+        assert_eq!(&voltage[1..], &[12000.0, 12000.0, 12000.0, 12000.0]);
+
+        let mut csv = Vec::new();
+        resampled.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with(
+            "timestamp_us,ATTITUDE.roll,SYS_STATUS.voltage_battery\n1000000,,\n1200000,0,12000\n"
+        ));
+    }
+
+    /// Test that linear interpolation leaves gaps longer than the maximum without values.
+    #[test]
+    fn test_resample_linear_gaps() {
+        let mut resampler = Resampler::new(&["ATTITUDE.roll"], 100_000).unwrap();
+        resampler.set_interpolation(Interpolation::Linear);
+        resampler.set_max_gap(500_000);
+        let resampled = analyze(&mut log(), resampler).unwrap();
+        let roll = resampled.channel("ATTITUDE.roll").unwrap();
+        assert_eq!(resampled.start_us, 1_000_000);
+        assert_eq!(roll.len(), 9);
+        assert!(roll[0].is_nan());
+        assert_eq!(roll[1], 0.5 * 0.5);
+        assert_eq!(roll[2], 0.5 * 1.5);
+        // 1.25 s to 1.85 s exceeds the maximum gap
+        assert!(roll[3..].iter().all(|value| value.is_nan()));
+
+        assert!(Resampler::new(&["roll"], 100_000).is_err());
+    }
+
+    /// Test that derived channels are computed per grid point and written with the others.
+    #[test]
+    fn test_resample_derived() {
+        let channels = ["ATTITUDE.roll", "SYS_STATUS.voltage_battery"];
+        let mut resampled =
+            resample(&mut log(), &channels, 200_000, Interpolation::ZeroOrderHold).unwrap();
+        let derived =
+            DerivedChannel::parse("volts_roll = SYS_STATUS.voltage_battery / 1000 + ATTITUDE.roll")
+                .unwrap();
+        resampled.derive(&derived).unwrap();
+        let volts_roll = resampled.channel("volts_roll").unwrap();
+        assert!(volts_roll[0].is_nan());
+        assert_eq!(&volts_roll[1..], &[12.0, 13.0, 13.0, 13.0]);
+
+        let mut csv = Vec::new();
+        resampled.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with(
+            "timestamp_us,ATTITUDE.roll,SYS_STATUS.voltage_battery,volts_roll\n1000000,,,\n1200000,0,12000,12\n"
+        ));
+
+        assert!(resampled.derive(&derived).is_err());
+        let unknown = DerivedChannel::parse("x = ATTITUDE.pitch * 2").unwrap();
+        assert!(resampled.derive(&unknown).is_err());
+    }
+}
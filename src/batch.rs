@@ -0,0 +1,274 @@
+//! Processing of many logs in parallel.
+//!
+//! Nightly jobs analyze thousands of logs. `process` runs a job on every log of a list across a
+//! pool of threads, reporting progress as files complete, and isolates failures so that one
+//! corrupted or unsupported file does not stop the batch: its error, or its panic, is recorded
+//! in its result and the other files are still processed. `analyze_logs` runs an `Analyzer` on
+//! every log, opening each with `open` whatever its format.
+//!
+//! ```no_run
+//! use mavlink::common::MavMessage;
+//! use mavlink_log::analysis::SystemDiscovery;
+//! use mavlink_log::batch::{analyze_logs, glob_logs};
+//!
+//! let paths = glob_logs("/data/flights/**/*.mav").unwrap();
+//! let report = analyze_logs::<MavMessage, _, _>(&paths, 8, |_| SystemDiscovery::new(), |p| {
+//!     println!("{}/{} {}", p.completed, p.total, p.path.display());
+//! });
+//! println!("{} logs failed", report.failures().count());
+//! ```
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+
+/// Progress of a batch, reported each time a file completes.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress<'a> {
+    /// Number of files completed, including this one.
+    pub completed: usize,
+    /// Number of files in the batch.
+    pub total: usize,
+    /// The file that completed.
+    pub path: &'a Path,
+    /// Whether the job succeeded on the file.
+    pub succeeded: bool,
+}
+
+/// Outcome of the job on one file.
+#[derive(Debug)]
+pub struct FileResult<R> {
+    /// The file.
+    pub path: PathBuf,
+    /// The result of the job, or the error it failed with. A panic of the job is an error of
+    /// kind `Other`.
+    pub result: std::io::Result<R>,
+}
+
+/// Outcomes of the job on every file of a batch, in the order the files were given.
+#[derive(Debug)]
+pub struct BatchReport<R> {
+    /// The outcome of each file.
+    pub results: Vec<FileResult<R>>,
+}
+
+impl<R> BatchReport<R> {
+    /// Returns the files the job succeeded on with their results.
+    pub fn successes(&self) -> impl Iterator<Item = (&Path, &R)> {
+        self.results
+            .iter()
+            .filter_map(|file| Some((file.path.as_path(), file.result.as_ref().ok()?)))
+    }
+
+    /// Returns the files the job failed on with their errors.
+    pub fn failures(&self) -> impl Iterator<Item = (&Path, &std::io::Error)> {
+        self.results
+            .iter()
+            .filter_map(|file| Some((file.path.as_path(), file.result.as_ref().err()?)))
+    }
+}
+
+/// Lists the files matching a glob pattern, such as `/data/**/*.tlog`, sorted by path.
+///
+/// # Errors
+/// Returns an `io::Error` of kind `InvalidInput` if the pattern is invalid, or the error of the
+/// first directory that could not be read.
+pub fn glob_logs(pattern: &str) -> std::io::Result<Vec<PathBuf>> {
+    let paths = glob::glob(pattern)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+    let mut logs = Vec::new();
+    for path in paths {
+        let path = path.map_err(glob::GlobError::into_error)?;
+        if path.is_file() {
+            logs.push(path);
+        }
+    }
+    logs.sort();
+    Ok(logs)
+}
+
+/// Runs a job on every file of a list across a pool of threads.
+///
+/// Files are handed out to the threads one at a time, so that a few large logs do not leave
+/// the other threads idle. The progress callback is called on the calling thread.
+///
+/// # Arguments
+/// - `paths`: The files to process.
+/// - `threads`: The number of threads, at least 1.
+/// - `job`: The job run on each file.
+/// - `progress`: The callback invoked each time a file completes.
+///
+/// # Returns
+/// The outcome of every file, in the order of `paths`.
+pub fn process<R, J, C>(
+    paths: &[PathBuf],
+    threads: usize,
+    job: J,
+    mut progress: C,
+) -> BatchReport<R>
+where
+    R: Send,
+    J: Fn(&Path) -> std::io::Result<R> + Sync,
+    C: FnMut(Progress),
+{
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<std::io::Result<R>>> = paths.iter().map(|_| None).collect();
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..threads.clamp(1, paths.len().max(1)) {
+            let sender = sender.clone();
+            let (next, job) = (&next, &job);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    let result = std::panic::catch_unwind(AssertUnwindSafe(|| job(path)))
+                        .unwrap_or_else(|_| {
+                            Err(std::io::Error::other(format!(
+                                "Processing {} panicked",
+                                path.display()
+                            )))
+                        });
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for (completed, (index, result)) in receiver.into_iter().enumerate() {
+            progress(Progress {
+                completed: completed + 1,
+                total: paths.len(),
+                path: &paths[index],
+                succeeded: result.is_ok(),
+            });
+            results[index] = Some(result);
+        }
+    });
+    BatchReport {
+        results: paths
+            .iter()
+            .zip(results)
+            .map(|(path, result)| FileResult {
+                path: path.clone(),
+                result: result.expect("every file is processed"),
+            })
+            .collect(),
+    }
+}
+
+/// Runs an analyzer on every log of a list across a pool of threads.
+///
+/// Each log is opened with `open`, so .mav and TLOG files can be mixed.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to parse messages with.
+///
+/// # Arguments
+/// - `paths`: The logs to analyze.
+/// - `threads`: The number of threads, at least 1.
+/// - `analyzer`: Creates the analyzer of a log.
+/// - `progress`: The callback invoked each time a log completes.
+///
+/// # Returns
+/// The report of the analyzer on every log, in the order of `paths`.
+pub fn analyze_logs<M, A, F>(
+    paths: &[PathBuf],
+    threads: usize,
+    analyzer: F,
+    progress: impl FnMut(Progress),
+) -> BatchReport<A::Report>
+where
+    M: Message + 'static,
+    A: Analyzer<M>,
+    A::Report: Send,
+    F: Fn(&Path) -> A + Sync,
+{
+    process(
+        paths,
+        threads,
+        |path| {
+            let mut parser = crate::open::<M>(&path.to_string_lossy())?;
+            analyze(&mut *parser, analyzer(path))
+        },
+        progress,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::MavMessage;
+
+    use super::*;
+    use crate::analysis::SystemDiscovery;
+    use crate::mav_logger::MavLogger;
+    use crate::tlog::logger::RotatingTlog;
+
+    /// Test that every log is processed while failures and panics stay isolated to their file.
+    #[test]
+    fn test_process() {
+        let paths: Vec<PathBuf> = (0..20).map(|i| PathBuf::from(format!("{i}"))).collect();
+        let mut completed = Vec::new();
+        let report = process(
+            &paths,
+            4,
+            |path| {
+                let number: u32 = path.to_str().unwrap().parse().unwrap();
+                match number {
+                    7 => Err(std::io::Error::other("corrupted")),
+                    13 => panic!("analysis bug"),
+                    _ => Ok(number * 2),
+                }
+            },
+            |progress| completed.push((progress.completed, progress.total)),
+        );
+        assert_eq!(completed.len(), 20);
+        assert_eq!(completed.last(), Some(&(20, 20)));
+        assert_eq!(report.results.len(), 20);
+        assert_eq!(report.results[3].result.as_ref().unwrap(), &6);
+        assert_eq!(report.successes().count(), 18);
+        let failures: Vec<&Path> = report.failures().map(|(path, _)| path).collect();
+        assert_eq!(failures, vec![Path::new("7"), Path::new("13")]);
+    }
+
+    /// Test that an analyzer is run on logs found with a glob pattern.
+    #[test]
+    fn test_analyze_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.tlog", "b.tlog"] {
+            let path = dir.path().join(name);
+            let mut logger = RotatingTlog::new(path.to_str().unwrap(), u64::MAX, 0).unwrap();
+            logger
+                .write_mavlink(mavlink::MavFrame {
+                    header: mavlink::MavHeader {
+                        system_id: 3,
+                        component_id: 1,
+                        sequence: 0,
+                    },
+                    msg: MavMessage::HEARTBEAT(Default::default()),
+                    protocol_version: mavlink::MavlinkVersion::V2,
+                })
+                .unwrap();
+        }
+        std::fs::write(dir.path().join("c.tlog"), b"").unwrap();
+        let pattern = format!("{}/*.tlog", dir.path().display());
+        let paths = glob_logs(&pattern).unwrap();
+        assert_eq!(paths.len(), 3);
+
+        let report =
+            analyze_logs::<MavMessage, _, _>(&paths, 2, |_| SystemDiscovery::new(), |_| {});
+        let systems: Vec<usize> = report
+            .results
+            .iter()
+            .map(|file| file.result.as_ref().map_or(0, |systems| systems.len()))
+            .collect();
+        assert_eq!(systems, vec![1, 1, 0]);
+    }
+}
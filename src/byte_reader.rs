@@ -0,0 +1,69 @@
+//! A buffered reader supporting arbitrarily sized look ahead.
+//!
+//! The `PeekReader` provided by the mavlink crate only allows peeking a single MAVLink frame.
+//! Log records wrap frames with additional fields so the parsers need to look further ahead
+//! before deciding whether the data is valid.
+use std::io::Read;
+
+/// Size of each read from the underlying reader.
+const CHUNK_SIZE: usize = 4096;
+
+/// A reader that allows peeking any amount of data before consuming it.
+pub struct ByteReader<R: Read> {
+    inner: R,
+    buffer: Vec<u8>,
+    start: usize,
+    position: u64,
+}
+
+impl<R: Read> ByteReader<R> {
+    /// Creates a new `ByteReader` wrapping the provided reader.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+            start: 0,
+            position: 0,
+        }
+    }
+
+    /// Returns the number of bytes consumed so far, the offset of the next byte to be read.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the next `amount` bytes without consuming them.
+    ///
+    /// # Errors
+    /// Returns an `UnexpectedEof` error if the end of the data is reached before `amount` bytes
+    /// are available.
+    pub fn peek(&mut self, amount: usize) -> std::io::Result<&[u8]> {
+        while self.buffer.len() - self.start < amount {
+            if self.start > 0 {
+                self.buffer.drain(..self.start);
+                self.start = 0;
+            }
+            let mut chunk = [0u8; CHUNK_SIZE];
+            let count = match self.inner.read(&mut chunk) {
+                Ok(count) => count,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if count == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "End of data reached",
+                ));
+            }
+            self.buffer.extend_from_slice(&chunk[..count]);
+        }
+        Ok(&self.buffer[self.start..self.start + amount])
+    }
+
+    /// Consumes `amount` bytes that have previously been peeked.
+    pub fn consume(&mut self, amount: usize) {
+        let amount = amount.min(self.buffer.len() - self.start);
+        self.start += amount;
+        self.position += amount as u64;
+    }
+}
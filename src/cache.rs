@@ -0,0 +1,475 @@
+//! A fast to read cache of decoded log entries.
+//!
+//! Parsing a log validates every record, checks every frame checksum and, for some formats,
+//! decrypts or decompresses the data. Pipelines that analyze the same logs many times can store
+//! the decoded entries once with `write_cache` and read them back with a `CacheParser`, which
+//! only deserializes them.
+//!
+//! A cache starts with the magic `MAVCACHE`, the cache format version and the length and
+//! modification time of the log it was written from, so `is_fresh` can tell whether the log
+//! changed since. Each entry follows as a little endian record length and the `LogEntry`
+//! serialized as MessagePack with its field names. Messages are stored through the serde
+//! representation of the dialect, so reading a cache does not parse MAVLink payloads, and a
+//! cache can be read with any dialect defining its messages under the same names.
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::time::UNIX_EPOCH;
+
+use mavlink::Message;
+use mavlink::error::MessageReadError;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::mav_parser::{LogEntry, MavParser, for_each_entry};
+
+/// Magic identifying a cache file.
+pub const CACHE_MAGIC: &[u8; 8] = b"MAVCACHE";
+/// Version of the cache format written by this crate.
+pub const CACHE_VERSION: u16 = 3;
+/// Largest record accepted by default, see `CacheParser::set_max_record_len`.
+pub const DEFAULT_MAX_RECORD_LEN: usize = 64 * 1024 * 1024;
+/// Length of the cache header: magic, version, source length and source modification time.
+const HEADER_LEN: usize = 8 + 2 + 8 + 8;
+
+/// Identifies the version of a log a cache was written from.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct SourceStamp {
+    /// Length of the log in bytes.
+    pub len: u64,
+    /// Modification time of the log in nanoseconds since the UNIX epoch, 0 if unknown.
+    pub modified_ns: u64,
+}
+
+impl SourceStamp {
+    /// Reads the stamp of a log file from its metadata.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the metadata of the file could not be read.
+    pub fn of(path: &str) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let modified_ns = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Ok(SourceStamp {
+            len: metadata.len(),
+            modified_ns,
+        })
+    }
+}
+
+/// Writes entries to a cache.
+pub struct CacheWriter<W: Write> {
+    writer: W,
+    record: Vec<u8>,
+}
+
+impl<W: Write> CacheWriter<W> {
+    /// Creates a new `CacheWriter` and writes the cache header.
+    ///
+    /// # Arguments
+    /// - `writer`: Destination of the cache.
+    /// - `source`: Stamp of the log the entries are read from.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the header could not be written.
+    pub fn new(mut writer: W, source: SourceStamp) -> std::io::Result<Self> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(CACHE_MAGIC);
+        header.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+        header.extend_from_slice(&source.len.to_le_bytes());
+        header.extend_from_slice(&source.modified_ns.to_le_bytes());
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            record: Vec::new(),
+        })
+    }
+
+    /// Appends an entry to the cache.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the entry could not be serialized or written.
+    pub fn write_entry<M: Message + Serialize>(
+        &mut self,
+        entry: &LogEntry<M>,
+    ) -> std::io::Result<()> {
+        self.record.clear();
+        encode_entry(entry, &mut self.record)?;
+        self.writer
+            .write_all(&(self.record.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&self.record)
+    }
+
+    /// Flushes the cache and returns the underlying writer.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the cache could not be flushed.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes every entry of a parser to a cache file.
+///
+/// The cache is written to a temporary file next to `cache_path` and renamed once complete, so
+/// an interrupted run never leaves a truncated cache behind.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from.
+/// - `source`: Stamp of the log the parser reads.
+/// - `cache_path`: Path of the cache file to write.
+///
+/// # Returns
+/// The number of entries written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log or the cache could not be read or written.
+pub fn write_cache<P>(parser: &mut P, source: SourceStamp, cache_path: &str) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Serialize,
+{
+    let tmp_path = format!("{cache_path}.tmp");
+    let mut writer = CacheWriter::new(BufWriter::new(File::create(&tmp_path)?), source)?;
+    let mut count = 0;
+    for_each_entry(parser, |entry| {
+        count += 1;
+        writer.write_entry(&entry)
+    })?;
+    writer.finish()?.into_inner()?.sync_all()?;
+    std::fs::rename(&tmp_path, cache_path)?;
+    Ok(count)
+}
+
+/// Returns whether a cache was written from the current version of a log.
+///
+/// # Returns
+/// `false` if the cache does not exist, is not a cache of the current format version or was
+/// written from a log of another length or modification time.
+///
+/// # Errors
+/// Returns an `io::Error` if the log metadata could not be read.
+pub fn is_fresh(cache_path: &str, log_path: &str) -> std::io::Result<bool> {
+    let source = SourceStamp::of(log_path)?;
+    let cached = File::open(cache_path).and_then(|mut file| read_header(&mut file));
+    Ok(cached.is_ok_and(|cached| cached == source))
+}
+
+/// Opens a log through its cache, writing the cache first if it is missing or stale.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to parse messages with.
+///
+/// # Arguments
+/// - `log_path`: Path of the log file, of any format supported by `open`.
+/// - `cache_path`: Path of the cache file.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be opened or the cache could not be written or
+/// opened.
+#[cfg(any(feature = "tlog", feature = "mavlog"))]
+pub fn open_cached<M: Message + Serialize + DeserializeOwned + 'static>(
+    log_path: &str,
+    cache_path: &str,
+) -> std::io::Result<CacheParser<M>> {
+    if !is_fresh(cache_path, log_path)? {
+        let source = SourceStamp::of(log_path)?;
+        let mut parser = crate::open::<M>(log_path)?;
+        write_cache(&mut parser, source, cache_path)?;
+    }
+    CacheParser::open(cache_path)
+}
+
+/// Parser reading the entries of a cache.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to decode messages with.
+pub struct CacheParser<M: Message + DeserializeOwned> {
+    reader: BufReader<File>,
+    source: SourceStamp,
+    record: Vec<u8>,
+    max_record_len: usize,
+    _marker: PhantomData<M>,
+}
+
+impl<M: Message + DeserializeOwned> CacheParser<M> {
+    /// Opens a cache file and reads its header.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the file could not be read, and of kind `InvalidData` if it is
+    /// not a cache or was written with another cache format version.
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let source = read_header(&mut reader)?;
+        Ok(Self {
+            reader,
+            source,
+            record: Vec::new(),
+            max_record_len: DEFAULT_MAX_RECORD_LEN,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns the stamp of the log the cache was written from.
+    pub fn source(&self) -> SourceStamp {
+        self.source
+    }
+
+    /// Sets the largest record accepted, `DEFAULT_MAX_RECORD_LEN` by default.
+    ///
+    /// A record length above the maximum is taken as corruption and reported as an error
+    /// instead of being allocated.
+    ///
+    /// # Arguments
+    /// - `max_record_len`: The largest record in bytes.
+    pub fn set_max_record_len(&mut self, max_record_len: usize) {
+        self.max_record_len = max_record_len;
+    }
+}
+
+impl<M: Message + DeserializeOwned> MavParser for CacheParser<M> {
+    type M = M;
+
+    /// Reads the next entry of the cache.
+    ///
+    /// # Errors
+    /// Returns a `MessageReadError::Io` error of kind `UnexpectedEof` at the end of the cache,
+    /// and of kind `InvalidData` if a record is longer than the maximum or cannot be decoded.
+    fn parse_next_entry(&mut self) -> Result<LogEntry<M>, MessageReadError> {
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > self.max_record_len {
+            return Err(invalid_data("Cache record longer than the maximum").into());
+        }
+        self.record.resize(len, 0);
+        self.reader.read_exact(&mut self.record)?;
+        decode_entry(&self.record)
+    }
+}
+
+/// Reads a cache header and returns the stamp of the log the cache was written from.
+fn read_header<R: Read>(reader: &mut R) -> std::io::Result<SourceStamp> {
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    if &header[..8] != CACHE_MAGIC {
+        return Err(invalid_data("Not a MAVLink log cache"));
+    }
+    let version = u16::from_le_bytes([header[8], header[9]]);
+    if version != CACHE_VERSION {
+        return Err(invalid_data("Unsupported cache format version"));
+    }
+    Ok(SourceStamp {
+        len: u64::from_le_bytes(header[10..18].try_into().unwrap()),
+        modified_ns: u64::from_le_bytes(header[18..26].try_into().unwrap()),
+    })
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Serializes an entry into a record.
+fn encode_entry<M: Message + Serialize>(
+    entry: &LogEntry<M>,
+    record: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    entry
+        .serialize(&mut rmp_serde::Serializer::new(record).with_struct_map())
+        .map_err(std::io::Error::other)
+}
+
+/// Deserializes an entry from a record.
+fn decode_entry<M: Message + DeserializeOwned>(
+    record: &[u8],
+) -> Result<LogEntry<M>, MessageReadError> {
+    rmp_serde::from_slice(record)
+        .map_err(|e| invalid_data(&format!("Invalid cache record: {e}")).into())
+}
+
+/// Serde representation of the MAVLink header of an entry, as system id, component id and
+/// sequence.
+pub(crate) mod header {
+    use mavlink::MavHeader;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(crate) fn pack(header: &MavHeader) -> (u8, u8, u8) {
+        (header.system_id, header.component_id, header.sequence)
+    }
+
+    pub(crate) fn unpack((system_id, component_id, sequence): (u8, u8, u8)) -> MavHeader {
+        MavHeader {
+            system_id,
+            component_id,
+            sequence,
+        }
+    }
+
+    pub(crate) fn serialize<S: Serializer>(
+        header: &Option<MavHeader>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        header.as_ref().map(pack).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<MavHeader>, D::Error> {
+        Ok(Option::<(u8, u8, u8)>::deserialize(deserializer)?.map(unpack))
+    }
+}
+
+/// Serde representation of the protocol version of an entry, as its number.
+pub(crate) mod version {
+    use mavlink::MavlinkVersion;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(
+        version: &Option<MavlinkVersion>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        version
+            .map(|version| match version {
+                MavlinkVersion::V1 => 1u8,
+                MavlinkVersion::V2 => 2u8,
+            })
+            .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<MavlinkVersion>, D::Error> {
+        Ok(
+            Option::<u8>::deserialize(deserializer)?.map(|version| match version {
+                1 => MavlinkVersion::V1,
+                _ => MavlinkVersion::V2,
+            }),
+        )
+    }
+}
+
+/// Serde representation of the snapshot of an entry, with headers as in `header`.
+pub(crate) mod snapshot {
+    use mavlink::MavHeader;
+    use serde::de::DeserializeOwned;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::header::{pack, unpack};
+
+    pub(crate) fn serialize<M: Serialize, S: Serializer>(
+        snapshot: &Option<Vec<(MavHeader, M)>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        snapshot
+            .as_ref()
+            .map(|snapshot| {
+                snapshot
+                    .iter()
+                    .map(|(header, msg)| (pack(header), msg))
+                    .collect::<Vec<_>>()
+            })
+            .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, M: DeserializeOwned, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<(MavHeader, M)>>, D::Error> {
+        let snapshot = Option::<Vec<((u8, u8, u8), M)>>::deserialize(deserializer)?;
+        Ok(snapshot.map(|snapshot| {
+            snapshot
+                .into_iter()
+                .map(|(header, msg)| (unpack(header), msg))
+                .collect()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::{ATTITUDE_DATA, MavMessage};
+    use mavlink::{MavHeader, MavlinkVersion};
+
+    use super::*;
+    use crate::mav_parser::{ActuatorEvent, Blob, RtcmData};
+    use crate::metadata::{MissionMetadata, ProductBatch};
+    use crate::weather::WeatherObservation;
+
+    /// Test that every field of an entry survives a round trip through a record.
+    #[test]
+    fn test_record_round_trip() {
+        let entry: LogEntry<MavMessage> = LogEntry {
+            timestamp: Some(1_500_000),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 190,
+                sequence: 7,
+            }),
+            mav_message: Some(MavMessage::ATTITUDE(ATTITUDE_DATA {
+                time_boot_ms: 33,
+                roll: 0.5,
+                ..Default::default()
+            })),
+            protocol_version: Some(MavlinkVersion::V1),
+            incompat_flags: Some(1),
+            compat_flags: Some(0x80),
+            text: Some(String::from("armed")),
+            raw: Some(vec![0xfd, 0, 1]),
+            sequence: Some(42),
+            drops: Some(vec![(30, 2), (24, 1)]),
+            blob: Some(Blob {
+                id: 3,
+                fragments: 2,
+                data: vec![9; 300],
+            }),
+            rtcm: Some(RtcmData {
+                link: 4,
+                data: vec![0xd3, 0, 0],
+            }),
+            snapshot: Some(vec![(
+                MavHeader {
+                    system_id: 1,
+                    component_id: 1,
+                    sequence: 3,
+                },
+                MavMessage::HEARTBEAT(Default::default()),
+            )]),
+            actuator: Some(ActuatorEvent {
+                actuator: 2,
+                active: true,
+                rate: 120.5,
+            }),
+            weather: Some(WeatherObservation {
+                temperature_c: Some(21.0),
+                note: String::from("calm"),
+                ..Default::default()
+            }),
+            metadata: Some(MissionMetadata {
+                job_id: Some(String::from("J-31")),
+                products: vec![ProductBatch {
+                    product: String::from("GLY-360"),
+                    batch: String::from("L2291"),
+                }],
+                ..Default::default()
+            }),
+            offset: Some(108),
+            entry_len: Some(51),
+        };
+        let mut record = Vec::new();
+        encode_entry(&entry, &mut record).unwrap();
+        assert_eq!(decode_entry::<MavMessage>(&record).unwrap(), entry);
+
+        let empty = LogEntry::<MavMessage>::default();
+        record.clear();
+        encode_entry(&empty, &mut record).unwrap();
+        assert_eq!(decode_entry::<MavMessage>(&record).unwrap(), empty);
+
+        record.clear();
+        encode_entry(&entry, &mut record).unwrap();
+        let truncated = &record[..record.len() - 1];
+        assert!(decode_entry::<MavMessage>(truncated).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+//! Manifest of the logs of a directory tree.
+//!
+//! A log library needs to list, search and deduplicate logs without opening every file each
+//! time. A `Catalog` holds one `CatalogEntry` per log with its identity, vehicles, time range,
+//! size and message counts, and is saved as a JSON manifest. Updating a catalog only reads the
+//! logs that were added or modified since it was last updated, so it can be refreshed on every
+//! run of a nightly job.
+//!
+//! ```no_run
+//! use mavlink::common::MavMessage;
+//! use mavlink_log::catalog::update_catalog;
+//!
+//! let catalog = update_catalog::<MavMessage>("/data/flights", "/data/flights/catalog.json")
+//!     .unwrap();
+//! println!("{} logs", catalog.entries.len());
+//! ```
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use mavlink::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::DetectedFormat;
+use crate::fields;
+use crate::fingerprint::{Fingerprinter, entry_digest};
+use crate::mav_parser::for_each_entry;
+use crate::open::{detect_file_format, list_files};
+
+/// Version of the manifest format written by `Catalog::save`.
+pub const CATALOG_VERSION: u32 = 1;
+
+/// HEARTBEAT autopilot value of systems that are not vehicles, such as ground stations.
+const MAV_AUTOPILOT_INVALID: u8 = 8;
+
+/// Description of a log in a catalog.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    /// Path of the log relative to the root of the catalog, with `/` separators.
+    pub path: String,
+    /// Size of the file in bytes.
+    pub size_bytes: u64,
+    /// Modification time of the file in seconds since the unix epoch.
+    pub modified_s: u64,
+    /// Format of the log, `mav` or `tlog`.
+    pub format: String,
+    /// UUID of the file header of a .mav log.
+    pub uuid: Option<String>,
+    /// Content id of the log, see `LogFingerprint::content_id`.
+    pub content_id: String,
+    /// System ids of the vehicles sending heartbeats, excluding ground stations.
+    pub vehicles: Vec<u8>,
+    /// Timestamp of the first entry.
+    pub start_us: Option<u64>,
+    /// Timestamp of the last entry.
+    pub end_us: Option<u64>,
+    /// Number of MAVLink messages.
+    pub messages: u64,
+    /// Number of messages by message name.
+    pub message_counts: BTreeMap<String, u64>,
+}
+
+impl CatalogEntry {
+    /// Returns the time between the first and last entry, if the log has timestamps.
+    pub fn duration_us(&self) -> Option<u64> {
+        Some(self.end_us?.saturating_sub(self.start_us?))
+    }
+}
+
+/// Changes made by `Catalog::update`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CatalogUpdate {
+    /// Paths of the logs added.
+    pub added: Vec<String>,
+    /// Paths of the logs read again because they were modified.
+    pub modified: Vec<String>,
+    /// Paths of the logs removed because their file no longer exists.
+    pub removed: Vec<String>,
+    /// Number of logs that were unchanged and not read.
+    pub unchanged: usize,
+}
+
+/// Catalog of the logs of a directory tree.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Catalog {
+    /// Version of the manifest format.
+    pub version: u32,
+    /// The logs, sorted by path.
+    pub entries: Vec<CatalogEntry>,
+}
+
+/// Returns an `io::Error` of kind `InvalidData` for an unreadable manifest.
+fn invalid_manifest(reason: String) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Invalid catalog manifest: {reason}"),
+    )
+}
+
+impl Catalog {
+    /// Reads a JSON manifest written by `save`.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the file could not be read, or of kind `InvalidData` if it is
+    /// not a manifest of a supported version.
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let catalog: Catalog = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| invalid_manifest(e.to_string()))?;
+        if catalog.version != CATALOG_VERSION {
+            return Err(invalid_manifest(format!(
+                "unsupported version {}",
+                catalog.version
+            )));
+        }
+        Ok(catalog)
+    }
+
+    /// Writes the catalog as a JSON manifest.
+    ///
+    /// The manifest is written to a temporary file renamed over `path`, so that readers never
+    /// see a partial manifest.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the file could not be written.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let temporary = format!("{path}.tmp");
+        let text = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(&temporary, text)?;
+        std::fs::rename(&temporary, path)
+    }
+
+    /// Returns the entry of a log, if it is in the catalog.
+    ///
+    /// # Arguments
+    /// - `path`: The path of the log relative to the root of the catalog.
+    pub fn get(&self, path: &str) -> Option<&CatalogEntry> {
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+
+    /// Updates the catalog with the logs of a directory tree.
+    ///
+    /// Logs whose size and modification time match their entry are not read again. Files that
+    /// are not .mav or TLOG logs, or that could not be read, are left out of the catalog.
+    ///
+    /// # Type Parameters
+    /// - `M`: The MAVLink dialect to parse messages with.
+    ///
+    /// # Arguments
+    /// - `root`: The root of the directory tree.
+    ///
+    /// # Returns
+    /// The changes made to the catalog.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the directory tree could not be listed.
+    pub fn update<M: Message + 'static>(&mut self, root: &Path) -> std::io::Result<CatalogUpdate> {
+        let mut files = Vec::new();
+        list_files(root, &mut files)?;
+        let mut previous: BTreeMap<String, CatalogEntry> = std::mem::take(&mut self.entries)
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        let mut update = CatalogUpdate::default();
+        for file in files {
+            let Ok(relative) = file.strip_prefix(root) else {
+                continue;
+            };
+            let relative = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let Ok(metadata) = file.metadata() else {
+                continue;
+            };
+            let modified_s = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |modified| modified.as_secs());
+            match previous.remove(&relative) {
+                Some(entry)
+                    if entry.size_bytes == metadata.len() && entry.modified_s == modified_s =>
+                {
+                    self.entries.push(entry);
+                    update.unchanged += 1;
+                }
+                known => {
+                    let Ok(Some(mut entry)) = describe::<M>(&file) else {
+                        continue;
+                    };
+                    entry.path = relative.clone();
+                    entry.size_bytes = metadata.len();
+                    entry.modified_s = modified_s;
+                    self.entries.push(entry);
+                    match known {
+                        Some(_) => update.modified.push(relative),
+                        None => update.added.push(relative),
+                    }
+                }
+            }
+        }
+        update.removed = previous.into_keys().collect();
+        self.version = CATALOG_VERSION;
+        self.entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(update)
+    }
+}
+
+/// Reads a log and describes it, leaving its path, size and modification time empty.
+///
+/// # Returns
+/// The description of the log, or `None` if the file is not a .mav or TLOG log.
+fn describe<M: Message + 'static>(path: &Path) -> std::io::Result<Option<CatalogEntry>> {
+    let path_text = path.to_string_lossy();
+    let (format, uuid) = match detect_file_format(path)? {
+        DetectedFormat::MavLog { .. } => {
+            let header = crate::mavlog::parser::read_header(&path_text)?;
+            ("mav", Some(header.uuid.to_string()))
+        }
+        DetectedFormat::Tlog => ("tlog", None),
+        _ => return Ok(None),
+    };
+    let mut parser = crate::open::<M>(&path_text)?;
+    let mut fingerprinter = Fingerprinter::default();
+    let mut vehicles = BTreeSet::new();
+    let mut message_counts: BTreeMap<String, u64> = BTreeMap::new();
+    let (mut start_us, mut end_us) = (None, None);
+    for_each_entry(&mut *parser, |entry| {
+        if let Some(timestamp) = entry.timestamp {
+            start_us = start_us.or(Some(timestamp));
+            end_us = Some(timestamp);
+        }
+        if let Some(digest) = entry_digest(&entry) {
+            fingerprinter.push(digest);
+        }
+        if let (Some(header), Some(msg)) = (&entry.mav_header, &entry.mav_message) {
+            *message_counts
+                .entry(msg.message_name().to_string())
+                .or_default() += 1;
+            if msg.message_id() == fields::HEARTBEAT_ID
+                && fields::read_u8(&fields::payload(msg), 5) != MAV_AUTOPILOT_INVALID
+            {
+                vehicles.insert(header.system_id);
+            }
+        }
+        Ok(())
+    })?;
+    let fingerprint = fingerprinter.finish(None);
+    Ok(Some(CatalogEntry {
+        path: String::new(),
+        size_bytes: 0,
+        modified_s: 0,
+        format: String::from(format),
+        uuid,
+        content_id: fingerprint.content_id(),
+        vehicles: vehicles.into_iter().collect(),
+        start_us,
+        end_us,
+        messages: fingerprint.entries,
+        message_counts,
+    }))
+}
+
+/// Updates the manifest of the logs of a directory tree, creating it if needed.
+///
+/// See `Catalog::update` for which logs are read.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to parse messages with.
+///
+/// # Arguments
+/// - `root`: The root of the directory tree.
+/// - `manifest`: Path of the JSON manifest. It may be within the tree, it is not a log.
+///
+/// # Returns
+/// The updated catalog.
+///
+/// # Errors
+/// Returns an `io::Error` if the manifest exists but could not be read, the directory tree
+/// could not be listed or the manifest could not be written.
+pub fn update_catalog<M: Message + 'static>(
+    root: &str,
+    manifest: &str,
+) -> std::io::Result<Catalog> {
+    let mut catalog = match Catalog::load(manifest) {
+        Ok(catalog) => catalog,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Catalog::default(),
+        Err(e) => return Err(e),
+    };
+    catalog.update::<M>(Path::new(root))?;
+    catalog.save(manifest)?;
+    Ok(catalog)
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::{HEARTBEAT_DATA, MavAutopilot, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+
+    use super::*;
+    use crate::mav_logger::MavLogger;
+    use crate::tlog::logger::RotatingTlog;
+
+    /// Writes a TLOG of heartbeats from a vehicle and a ground station.
+    fn write_log(path: &Path, heartbeats: u8) {
+        let mut logger = RotatingTlog::new(path.to_str().unwrap(), u64::MAX, 0).unwrap();
+        for sequence in 0..heartbeats {
+            for (system_id, autopilot) in [
+                (1, MavAutopilot::MAV_AUTOPILOT_ARDUPILOTMEGA),
+                (255, MavAutopilot::MAV_AUTOPILOT_INVALID),
+            ] {
+                let frame = MavFrame {
+                    header: MavHeader {
+                        system_id,
+                        component_id: 1,
+                        sequence,
+                    },
+                    msg: MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+                        autopilot,
+                        ..Default::default()
+                    }),
+                    protocol_version: MavlinkVersion::V2,
+                };
+                logger.write_mavlink(frame).unwrap();
+            }
+        }
+    }
+
+    /// Test that a catalog describes logs and is only updated with added, modified and removed
+    /// logs.
+    #[test]
+    fn test_update_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("flights");
+        std::fs::create_dir_all(root.join("2024")).unwrap();
+        write_log(&root.join("2024").join("a.tlog"), 3);
+        write_log(&root.join("b.tlog"), 2);
+        std::fs::write(root.join("readme.txt"), "flights").unwrap();
+        let manifest = dir.path().join("catalog.json");
+        let manifest = manifest.to_str().unwrap();
+
+        let catalog = update_catalog::<MavMessage>(root.to_str().unwrap(), manifest).unwrap();
+        assert_eq!(catalog.entries.len(), 2);
+        let entry = catalog.get("2024/a.tlog").unwrap();
+        assert_eq!(entry.format, "tlog");
+        assert_eq!(entry.vehicles, vec![1]);
+        assert_eq!(entry.messages, 6);
+        assert_eq!(entry.message_counts["HEARTBEAT"], 6);
+        assert!(entry.duration_us().is_some());
+        assert_eq!(Catalog::load(manifest).unwrap(), catalog);
+
+        let mut catalog = Catalog::load(manifest).unwrap();
+        std::fs::remove_file(root.join("b.tlog")).unwrap();
+        write_log(&root.join("c.tlog"), 1);
+        let update = catalog.update::<MavMessage>(&root).unwrap();
+        assert_eq!(update.added, vec![String::from("c.tlog")]);
+        assert_eq!(update.removed, vec![String::from("b.tlog")]);
+        assert!(update.modified.is_empty());
+        assert_eq!(update.unchanged, 1);
+        let paths: Vec<&str> = catalog.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["2024/a.tlog", "c.tlog"]);
+    }
+}
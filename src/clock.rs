@@ -0,0 +1,444 @@
+//! Clocks timestamping log entries.
+//!
+//! Loggers read a `Clock` for the timestamp of every entry not given an explicit one. The source
+//! of the clock decides what timestamps mean, and its `BackwardsPolicy` decides what happens when
+//! the source jumps backwards, as the system clock does when it is adjusted.
+//!
+//! A GPS disciplined clock stamps entries with UTC derived from the SYSTEM_TIME messages
+//! passing through the logger rather than from the system clock, which is often unset or
+//! drifting on companion computers. The correction model relating the monotonic clock to UTC is
+//! recorded in the log as text entries so the timestamps can be reproduced. The file header is
+//! written before the first SYSTEM_TIME message arrives, so it cannot hold the model.
+use std::collections::VecDeque;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use mavlink::{MavlinkVersion, Message};
+
+/// SYSTEM_TIME message id.
+const SYSTEM_TIME_ID: u32 = 2;
+
+/// Number of SYSTEM_TIME samples the correction model is fitted to.
+const DISCIPLINE_WINDOW: usize = 32;
+
+/// Largest drift of the monotonic clock from UTC accepted by the correction model, in parts per
+/// million.
+const MAX_DRIFT_PPM: f64 = 1000.0;
+
+/// Deviation from the correction model above which a SYSTEM_TIME sample restarts the fit, such
+/// as after the autopilot gets its first GPS fix.
+const MAX_SAMPLE_ERROR_US: i64 = 1_000_000;
+
+/// Change of the corrected time above which a new correction model is recorded.
+const RECORD_THRESHOLD_US: i64 = 1_000;
+
+/// Where a clock reads the time from.
+pub enum ClockSource {
+    /// Microseconds since the clock was created, from the monotonic clock of the system. Never
+    /// goes backwards.
+    Monotonic,
+    /// Microseconds since the Unix epoch, from the system clock. Goes backwards when the system
+    /// clock is adjusted.
+    Wall,
+    /// Microseconds from a caller supplied function, such as a GPS or flight controller clock.
+    Custom(Box<dyn Fn() -> u64 + Send>),
+    /// Microseconds since the Unix epoch in UTC, from the monotonic clock corrected with the
+    /// SYSTEM_TIME messages observed by the clock. Falls back to the system clock until a
+    /// SYSTEM_TIME message with a valid time is observed.
+    GpsDisciplined,
+}
+
+/// Linear model relating the monotonic clock of a GPS disciplined clock to UTC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorrectionModel {
+    /// Monotonic time of the reference point, in microseconds since the clock was created.
+    pub reference_monotonic_us: u64,
+    /// UTC time of the reference point, in microseconds since the Unix epoch.
+    pub reference_utc_us: u64,
+    /// Rate of UTC relative to the monotonic clock, in parts per million above 1.
+    pub drift_ppm: f64,
+    /// Number of SYSTEM_TIME samples the model was fitted to.
+    pub samples: u32,
+}
+
+impl CorrectionModel {
+    /// Prefix of the text entries recording a correction model.
+    pub const TEXT_PREFIX: &str = "clock_model";
+
+    /// Returns the UTC time in microseconds since the Unix epoch of a monotonic time.
+    pub fn utc_us(&self, monotonic_us: u64) -> u64 {
+        let elapsed = monotonic_us as f64 - self.reference_monotonic_us as f64;
+        let corrected = self.reference_utc_us as f64 + elapsed * (1.0 + self.drift_ppm * 1e-6);
+        corrected.max(0.0) as u64
+    }
+
+    /// Formats the model as the text entry recording it.
+    pub fn to_text(&self) -> String {
+        format!(
+            "{} reference_monotonic_us={} reference_utc_us={} drift_ppm={} samples={}",
+            Self::TEXT_PREFIX,
+            self.reference_monotonic_us,
+            self.reference_utc_us,
+            self.drift_ppm,
+            self.samples
+        )
+    }
+
+    /// Parses a text entry recording a correction model.
+    ///
+    /// # Returns
+    ///
+    /// The model, or `None` if the text does not record one.
+    pub fn from_text(text: &str) -> Option<Self> {
+        let mut fields = text.split(' ');
+        if fields.next() != Some(Self::TEXT_PREFIX) {
+            return None;
+        }
+        let mut value = |name: &str| fields.next()?.strip_prefix(name)?.strip_prefix('=');
+        Some(CorrectionModel {
+            reference_monotonic_us: value("reference_monotonic_us")?.parse().ok()?,
+            reference_utc_us: value("reference_utc_us")?.parse().ok()?,
+            drift_ppm: value("drift_ppm")?.parse().ok()?,
+            samples: value("samples")?.parse().ok()?,
+        })
+    }
+}
+
+/// SYSTEM_TIME samples of a GPS disciplined clock and the model fitted to them.
+struct Discipline {
+    /// Monotonic and UTC times of the most recent samples.
+    samples: VecDeque<(u64, u64)>,
+    model: Option<CorrectionModel>,
+    recorded: Option<CorrectionModel>,
+}
+
+impl Discipline {
+    /// Adds a sample and refits the correction model with a least squares line.
+    fn add_sample(&mut self, monotonic_us: u64, utc_us: u64) {
+        if let Some(model) = &self.model {
+            let error = utc_us as i64 - model.utc_us(monotonic_us) as i64;
+            if error.abs() > MAX_SAMPLE_ERROR_US {
+                self.samples.clear();
+            }
+        }
+        if self.samples.len() == DISCIPLINE_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((monotonic_us, utc_us));
+
+        // fit relative to the first sample to keep the sums precise
+        let (x0, y0) = self.samples[0];
+        let n = self.samples.len() as f64;
+        let points = self
+            .samples
+            .iter()
+            .map(|&(x, y)| ((x - x0) as f64, y as f64 - y0 as f64));
+        let (mut sx, mut sy, mut sxx, mut sxy) = (0.0, 0.0, 0.0, 0.0);
+        for (x, y) in points {
+            sx += x;
+            sy += y;
+            sxx += x * x;
+            sxy += x * y;
+        }
+        let variance = n * sxx - sx * sx;
+        let rate = if variance > 0.0 {
+            (n * sxy - sx * sy) / variance
+        } else {
+            1.0
+        };
+        let drift_ppm = ((rate - 1.0) * 1e6).clamp(-MAX_DRIFT_PPM, MAX_DRIFT_PPM);
+        // the line passes through the mean of the samples, taken as the reference point
+        let mean_x = sx / n;
+        let reference_x = mean_x.round();
+        let reference_y = sy / n + (reference_x - mean_x) * (1.0 + drift_ppm * 1e-6);
+        self.model = Some(CorrectionModel {
+            reference_monotonic_us: x0 + reference_x as u64,
+            reference_utc_us: (y0 as f64 + reference_y).max(0.0) as u64,
+            drift_ppm,
+            samples: self.samples.len() as u32,
+        });
+    }
+
+    /// Returns the model if it should be recorded, because none was or because the recorded
+    /// one no longer predicts the current time.
+    fn model_to_record(&mut self, monotonic_us: u64) -> Option<CorrectionModel> {
+        let model = self.model?;
+        let changed = self.recorded.is_none_or(|recorded| {
+            let shift = recorded.utc_us(monotonic_us) as i64 - model.utc_us(monotonic_us) as i64;
+            shift.abs() > RECORD_THRESHOLD_US
+        });
+        if changed {
+            self.recorded = Some(model);
+        }
+        changed.then_some(model)
+    }
+}
+
+/// What a clock does when its source reads a time before the previous timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackwardsPolicy {
+    /// Repeat the previous timestamp until the source catches up with it.
+    #[default]
+    Hold,
+    /// Shift all later timestamps so time continues from the previous timestamp, keeping the
+    /// intervals between entries.
+    Rebase,
+    /// Use the time read from the source.
+    Allow,
+    /// Fail to read the clock, so the entry is not written.
+    Reject,
+}
+
+/// A clock timestamping log entries.
+pub struct Clock {
+    source: ClockSource,
+    policy: BackwardsPolicy,
+    start: Instant,
+    /// Offset added to the source by the `Rebase` policy.
+    offset_us: u64,
+    last_us: Option<u64>,
+    discipline: Option<Discipline>,
+}
+
+impl Clock {
+    /// Creates a new `Clock`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where the clock reads the time from.
+    /// * `policy` - What the clock does when its source goes backwards.
+    pub fn new(source: ClockSource, policy: BackwardsPolicy) -> Self {
+        let discipline = match source {
+            ClockSource::GpsDisciplined => Some(Discipline {
+                samples: VecDeque::new(),
+                model: None,
+                recorded: None,
+            }),
+            _ => None,
+        };
+        Clock {
+            source,
+            policy,
+            start: Instant::now(),
+            offset_us: 0,
+            last_us: None,
+            discipline,
+        }
+    }
+
+    /// Returns the current correction model of a GPS disciplined clock, if it has one.
+    pub fn correction_model(&self) -> Option<CorrectionModel> {
+        self.discipline.as_ref()?.model
+    }
+
+    /// Observes a MAVLink message passing through the logger.
+    ///
+    /// A GPS disciplined clock refines its correction model with SYSTEM_TIME messages holding a
+    /// valid UTC time. Other clocks and messages are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` - The message passing through the logger.
+    ///
+    /// # Returns
+    ///
+    /// The correction model if it should be recorded in the log, because it is the first one
+    /// or because it changed the corrected time by more than a millisecond since it was last
+    /// recorded.
+    pub fn observe<M: Message>(&mut self, msg: &M) -> Option<CorrectionModel> {
+        if msg.message_id() != SYSTEM_TIME_ID {
+            return None;
+        }
+        let monotonic_us = self.start.elapsed().as_micros() as u64;
+        let discipline = self.discipline.as_mut()?;
+        let mut payload = [0u8; 255];
+        msg.ser(MavlinkVersion::V2, &mut payload);
+        let utc_us = u64::from_le_bytes(payload[0..8].try_into().unwrap());
+        if utc_us == 0 {
+            // the autopilot has no time yet
+            return None;
+        }
+        discipline.add_sample(monotonic_us, utc_us);
+        discipline.model_to_record(monotonic_us)
+    }
+
+    /// Returns the last timestamp read from the clock, if any.
+    pub fn last_us(&self) -> Option<u64> {
+        self.last_us
+    }
+
+    /// Reads the clock.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the current timestamp in microseconds. An error of kind
+    /// `InvalidData` is returned if the source went backwards and the policy is `Reject`.
+    pub fn now_us(&mut self) -> std::io::Result<u64> {
+        let now_us = self.read_source().saturating_add(self.offset_us);
+        let last_us = match self.last_us {
+            Some(last_us) if now_us < last_us => last_us,
+            _ => {
+                self.last_us = Some(now_us);
+                return Ok(now_us);
+            }
+        };
+        match self.policy {
+            BackwardsPolicy::Hold => Ok(last_us),
+            BackwardsPolicy::Rebase => {
+                self.offset_us += last_us - now_us;
+                Ok(last_us)
+            }
+            BackwardsPolicy::Allow => {
+                self.last_us = Some(now_us);
+                Ok(now_us)
+            }
+            BackwardsPolicy::Reject => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Clock went backwards by {} us", last_us - now_us),
+            )),
+        }
+    }
+
+    /// Reads the time from the source, without the offset of the `Rebase` policy.
+    fn read_source(&self) -> u64 {
+        match &self.source {
+            ClockSource::Monotonic => self.start.elapsed().as_micros() as u64,
+            ClockSource::Wall => Self::wall_us(),
+            ClockSource::Custom(clock) => clock(),
+            ClockSource::GpsDisciplined => match self.correction_model() {
+                Some(model) => model.utc_us(self.start.elapsed().as_micros() as u64),
+                None => Self::wall_us(),
+            },
+        }
+    }
+
+    /// Reads the system clock in microseconds since the Unix epoch.
+    fn wall_us() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros() as u64)
+            .unwrap_or(0)
+    }
+}
+
+impl Default for Clock {
+    /// Provides a monotonic clock holding on backwards time, which never goes backwards.
+    fn default() -> Self {
+        Clock::new(ClockSource::Monotonic, BackwardsPolicy::Hold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    /// Returns a clock reading a shared time and the handle setting it.
+    fn manual_clock(policy: BackwardsPolicy) -> (Clock, Arc<AtomicU64>) {
+        let time = Arc::new(AtomicU64::new(0));
+        let source_time = time.clone();
+        let source = ClockSource::Custom(Box::new(move || source_time.load(Ordering::Relaxed)));
+        (Clock::new(source, policy), time)
+    }
+
+    /// Reads a clock at each of the source times.
+    fn read_at(clock: &mut Clock, time: &AtomicU64, times: &[u64]) -> Vec<Option<u64>> {
+        times
+            .iter()
+            .map(|&t| {
+                time.store(t, Ordering::Relaxed);
+                clock.now_us().ok()
+            })
+            .collect()
+    }
+
+    /// Test each policy on a source jumping backwards by 70 us.
+    #[test]
+    fn test_backwards_policies() {
+        let times = [100, 200, 130, 150, 250];
+        let expected = [
+            (BackwardsPolicy::Hold, [100, 200, 200, 200, 250].map(Some)),
+            (BackwardsPolicy::Rebase, [100, 200, 200, 220, 320].map(Some)),
+            (BackwardsPolicy::Allow, [100, 200, 130, 150, 250].map(Some)),
+            (
+                BackwardsPolicy::Reject,
+                [Some(100), Some(200), None, None, Some(250)],
+            ),
+        ];
+        for (policy, expected) in expected {
+            let (mut clock, time) = manual_clock(policy);
+            assert_eq!(read_at(&mut clock, &time, &times), expected, "{policy:?}");
+        }
+    }
+
+    /// Test that the correction model follows the drift of the samples and restarts on a jump.
+    #[test]
+    fn test_discipline_fit() {
+        let mut discipline = Discipline {
+            samples: VecDeque::new(),
+            model: None,
+            recorded: None,
+        };
+        let utc = |x: u64| 1_700_000_000_000_000 + x + x / 20_000;
+        for i in 0..50 {
+            let x = 10_000 + i * 1_000_000;
+            discipline.add_sample(x, utc(x));
+        }
+        let model = discipline.model.unwrap();
+        assert_eq!(model.samples, DISCIPLINE_WINDOW as u32);
+        assert!((model.drift_ppm - 50.0).abs() < 0.01, "{model:?}");
+        let x = 100_000_000;
+        assert!((model.utc_us(x) as i64 - utc(x) as i64).abs() < 2);
+        assert_eq!(discipline.model_to_record(x), Some(model));
+        assert_eq!(discipline.model_to_record(x), None);
+
+        // a GPS time jump restarts the fit
+        discipline.add_sample(x, utc(x) + 10_000_000);
+        let model = discipline.model.unwrap();
+        assert_eq!(model.samples, 1);
+        assert_eq!(model.utc_us(x), utc(x) + 10_000_000);
+        assert_eq!(discipline.model_to_record(x), Some(model));
+    }
+
+    /// Test that a GPS disciplined clock switches to the time of SYSTEM_TIME messages and that
+    /// its correction model survives a text round trip.
+    #[test]
+    fn test_gps_disciplined() {
+        use mavlink::common::{MavMessage, SYSTEM_TIME_DATA};
+
+        let mut clock = Clock::new(ClockSource::GpsDisciplined, BackwardsPolicy::Allow);
+        assert!(
+            clock
+                .observe(&MavMessage::HEARTBEAT(Default::default()))
+                .is_none()
+        );
+        let no_fix = MavMessage::SYSTEM_TIME(SYSTEM_TIME_DATA::default());
+        assert!(clock.observe(&no_fix).is_none());
+        assert!(clock.correction_model().is_none());
+
+        let utc_us = 1_000_000_000_000_000;
+        let system_time = MavMessage::SYSTEM_TIME(SYSTEM_TIME_DATA {
+            time_unix_usec: utc_us,
+            time_boot_ms: 0,
+        });
+        let model = clock.observe(&system_time).unwrap();
+        assert_eq!(clock.correction_model(), Some(model));
+        let now_us = clock.now_us().unwrap();
+        assert!((utc_us..utc_us + 1_000_000).contains(&now_us));
+
+        assert_eq!(CorrectionModel::from_text(&model.to_text()), Some(model));
+        assert_eq!(CorrectionModel::from_text("clock_model samples=1"), None);
+        assert_eq!(CorrectionModel::from_text("hello"), None);
+    }
+
+    /// Test that the monotonic clock starts near 0 and the wall clock near the current time.
+    #[test]
+    fn test_sources() {
+        let mut monotonic = Clock::default();
+        assert!(monotonic.now_us().unwrap() < 1_000_000);
+        let mut wall = Clock::new(ClockSource::Wall, BackwardsPolicy::Hold);
+        // after 2020
+        assert!(wall.now_us().unwrap() > 1_577_836_800_000_000);
+    }
+}
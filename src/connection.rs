@@ -0,0 +1,189 @@
+//! A parser reading from a live MAVLink connection.
+//!
+//! `ConnectionParser` wraps a `mavlink::MavConnection` and implements `MavParser`, timestamping
+//! each message as it arrives. Analyzers, exporters and filters written against `MavParser` then
+//! run the same on a live link, such as a UDP or serial connection, as on a log file.
+//!
+//! A live link has no end, so `for_each_entry` and similar loops run until the connection fails
+//! or the parser is stopped through the flag returned by `stop_handle`.
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use mavlink::error::MessageReadError;
+use mavlink::{MavConnection, Message};
+
+use crate::clock::{BackwardsPolicy, Clock, ClockSource};
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Time waited before polling a non-blocking connection again when no message is available.
+const WOULD_BLOCK_DELAY: Duration = Duration::from_millis(1);
+
+/// A parser reading the messages of a live MAVLink connection, see the module documentation.
+///
+/// Entries are timestamped with the wall clock when their message is received, unless another
+/// clock is set with `set_clock`.
+///
+/// # Type Parameters
+/// - `M`: The type of MAVLink message being received.
+pub struct ConnectionParser<M: Message> {
+    connection: Box<dyn MavConnection<M> + Sync + Send>,
+    clock: Clock,
+    stop: Arc<AtomicBool>,
+}
+
+impl<M: Message> ConnectionParser<M> {
+    /// Creates a new `ConnectionParser` reading from an open connection.
+    ///
+    /// # Arguments
+    /// - `connection`: The connection to receive messages from, such as one returned by
+    ///   `mavlink::connect`.
+    pub fn new(connection: Box<dyn MavConnection<M> + Sync + Send>) -> Self {
+        Self {
+            connection,
+            clock: Clock::new(ClockSource::Wall, BackwardsPolicy::Hold),
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Opens a connection and creates a new `ConnectionParser` reading from it.
+    ///
+    /// # Arguments
+    /// - `address`: The address of the connection in the format of `mavlink::connect`, such as
+    ///   `udpin:0.0.0.0:14550` or `serial:/dev/ttyUSB0:57600`.
+    ///
+    /// # Returns
+    /// A `Result` containing the parser or an `io::Error` if the connection could not be opened.
+    pub fn connect(address: &str) -> std::io::Result<Self> {
+        Ok(Self::new(mavlink::connect::<M>(address)?))
+    }
+
+    /// Sets the clock entries are timestamped by.
+    ///
+    /// # Arguments
+    /// - `clock`: The clock to read entry timestamps from. It should count microseconds since
+    ///   the Unix epoch.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Returns a flag stopping the parser when set, such as from another thread.
+    ///
+    /// Once the flag is set, the parser reports the end of the log, so that loops like
+    /// `for_each_entry` finish normally. The flag is checked before waiting for each message,
+    /// so a parser blocked on a silent connection stops when the next message arrives. A
+    /// non-blocking connection reporting that no message is available is polled again after
+    /// a millisecond, so a parser waiting on it stops within about a millisecond.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Returns the wrapped connection, such as to send messages on it.
+    pub fn connection(&self) -> &(dyn MavConnection<M> + Sync + Send) {
+        self.connection.as_ref()
+    }
+}
+
+impl<M: Message> MavParser for ConnectionParser<M> {
+    type M = M;
+
+    /// Waits for the next message of the connection and returns it as a `LogEntry`.
+    ///
+    /// The protocol version of the entry is the version the connection is configured with, as
+    /// the connection does not report the version of each frame received.
+    ///
+    /// # Errors
+    /// Returns a `MessageReadError::Io` error of kind `UnexpectedEof` once the parser is
+    /// stopped, and the errors of the connection otherwise, such as a `MessageReadError::Parse`
+    /// error for a message missing from the dialect.
+    fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+        loop {
+            if self.stop.load(Ordering::SeqCst) {
+                return Err(MessageReadError::Io(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "The connection parser was stopped",
+                )));
+            }
+            match self.connection.recv() {
+                Ok((header, msg)) => {
+                    self.clock.observe(&msg);
+                    return Ok(LogEntry {
+                        timestamp: Some(self.clock.now_us()?),
+                        mav_header: Some(header),
+                        mav_message: Some(msg),
+                        protocol_version: Some(self.connection.get_protocol_version()),
+                        ..Default::default()
+                    });
+                }
+                // non-blocking connections report that no message is available yet, wait a
+                // little rather than spinning on the connection
+                Err(MessageReadError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(WOULD_BLOCK_DELAY);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU64;
+
+    use mavlink::common::{ATTITUDE_DATA, MavMessage};
+    use mavlink::{MAVLinkV2MessageRaw, MavHeader, MavlinkVersion};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Test that messages received on a connection are returned as timestamped entries until
+    /// the end of the connection, and that a stopped parser reports the end of the log.
+    #[test]
+    fn test_connection_parser() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("link.bin");
+        let messages = [
+            MavMessage::HEARTBEAT(Default::default()),
+            MavMessage::ATTITUDE(ATTITUDE_DATA {
+                roll: 0.5,
+                ..Default::default()
+            }),
+        ];
+        let mut bytes: Vec<u8> = Vec::new();
+        for msg in &messages {
+            let mut raw = MAVLinkV2MessageRaw::new();
+            raw.serialize_message(MavHeader::default(), msg);
+            bytes.extend_from_slice(raw.raw_bytes());
+        }
+        std::fs::write(&path, bytes).unwrap();
+
+        let address = format!("file:{}", path.to_str().unwrap());
+        let mut parser = ConnectionParser::<MavMessage>::connect(&address).unwrap();
+        let time = AtomicU64::new(1_700_000_000_000_000);
+        parser.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || time.fetch_add(1_000, Ordering::Relaxed))),
+            BackwardsPolicy::Hold,
+        ));
+        let mut entries = Vec::new();
+        while let Ok(entry) = parser.parse_next_entry() {
+            assert_eq!(entry.protocol_version, Some(MavlinkVersion::V2));
+            entries.push((entry.timestamp.unwrap(), entry.mav_message.unwrap()));
+        }
+        assert_eq!(
+            entries,
+            vec![
+                (1_700_000_000_000_000, messages[0].clone()),
+                (1_700_000_000_001_000, messages[1].clone()),
+            ]
+        );
+
+        let mut parser = ConnectionParser::<MavMessage>::connect(&address).unwrap();
+        parser.stop_handle().store(true, Ordering::SeqCst);
+        match parser.parse_next_entry() {
+            Err(MessageReadError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof)
+            }
+            _ => panic!("a stopped parser should report the end of the log"),
+        }
+    }
+}
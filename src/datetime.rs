@@ -0,0 +1,325 @@
+//! Conversion of entry timestamps to calendar dates and parsing of human time ranges.
+//!
+//! Entry timestamps are microseconds since the Unix epoch when the logger clock reads the wall
+//! clock, a GPS disciplined clock or a flight controller UTC time. `to_utc` and `to_local`
+//! convert them to `chrono` date times, and `TimeRange` parses ranges such as
+//! `2024-05-01 10:00..10:30` into timestamps that can select entries with a `ParserFilter`.
+//!
+//! A range is written `start..end`. Each bound is a date (`2024-05-01`), a date and time
+//! (`2024-05-01 10:00`, `2024-05-01T10:00:30.5`) or an RFC 3339 time with an offset
+//! (`2024-05-01T10:00:00+02:00`). The end may also be a time alone, on the day of the start or on
+//! the next day if it is not after the start time. A date ends the range at the end of that day,
+//! and a date alone without `..` is the whole day. Times without an offset are read in the time
+//! zone given to `TimeRange::parse_in`, UTC for `TimeRange::parse`.
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Days, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat,
+    TimeZone, Utc,
+};
+
+#[cfg(feature = "analysis")]
+use crate::analysis::filter::ParserFilter;
+
+/// Converts an entry timestamp to a UTC date time.
+///
+/// # Returns
+/// The date time, or `None` if the timestamp is out of the range `chrono` represents.
+pub fn to_utc(timestamp_us: u64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_micros(i64::try_from(timestamp_us).ok()?)
+}
+
+/// Converts an entry timestamp to a date time in the local time zone of the system.
+///
+/// # Returns
+/// The date time, or `None` if the timestamp is out of the range `chrono` represents.
+pub fn to_local(timestamp_us: u64) -> Option<DateTime<Local>> {
+    Some(to_utc(timestamp_us)?.with_timezone(&Local))
+}
+
+/// Converts a date time to an entry timestamp.
+///
+/// # Returns
+/// The timestamp in microseconds since the Unix epoch, or `None` if the date time is before the
+/// epoch.
+pub fn to_timestamp_us<Tz: TimeZone>(datetime: &DateTime<Tz>) -> Option<u64> {
+    u64::try_from(datetime.timestamp_micros()).ok()
+}
+
+/// Error returned when a time range cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeRangeError {
+    /// Description of the error.
+    pub message: String,
+}
+
+impl TimeRangeError {
+    fn new(message: &str) -> Self {
+        TimeRangeError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for TimeRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid time range: {}", self.message)
+    }
+}
+
+impl std::error::Error for TimeRangeError {}
+
+/// A bound of a time range as written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    Time(NaiveTime),
+    Zoned(DateTime<FixedOffset>),
+}
+
+impl Bound {
+    fn parse(text: &str) -> Result<Self, TimeRangeError> {
+        let text = text.trim();
+        if let Ok(zoned) = DateTime::parse_from_rfc3339(text) {
+            return Ok(Bound::Zoned(zoned));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            return Ok(Bound::Date(date));
+        }
+        let spaced = text.replacen('T', " ", 1);
+        for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%d %H:%M"] {
+            if let Ok(datetime) = NaiveDateTime::parse_from_str(&spaced, format) {
+                return Ok(Bound::DateTime(datetime));
+            }
+        }
+        for format in ["%H:%M:%S%.f", "%H:%M"] {
+            if let Ok(time) = NaiveTime::parse_from_str(text, format) {
+                return Ok(Bound::Time(time));
+            }
+        }
+        Err(TimeRangeError {
+            message: format!("'{text}' is not a date, a time or a date and time"),
+        })
+    }
+}
+
+/// Reads a date and time in a time zone, taking the earliest of ambiguous times.
+fn resolve<Tz: TimeZone>(datetime: NaiveDateTime, tz: &Tz) -> Result<DateTime<Tz>, TimeRangeError> {
+    tz.from_local_datetime(&datetime)
+        .earliest()
+        .ok_or_else(|| TimeRangeError {
+            message: format!("{datetime} does not exist in the time zone"),
+        })
+}
+
+/// Returns the day following a date.
+fn next_day(date: NaiveDate) -> Result<NaiveDate, TimeRangeError> {
+    date.checked_add_days(Days::new(1))
+        .ok_or_else(|| TimeRangeError::new("the end of the range is out of range"))
+}
+
+/// Converts a date time to a timestamp, rejecting dates before the Unix epoch.
+fn timestamp_us<Tz: TimeZone>(datetime: &DateTime<Tz>) -> Result<u64, TimeRangeError> {
+    to_timestamp_us(datetime)
+        .ok_or_else(|| TimeRangeError::new("times before 1970 are not supported"))
+}
+
+/// A range of entry timestamps, including its start and excluding its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    /// The first timestamp of the range, in microseconds since the Unix epoch.
+    pub start_us: u64,
+    /// The timestamp the range ends at, excluded, in microseconds since the Unix epoch.
+    pub end_us: u64,
+}
+
+impl TimeRange {
+    /// Parses a time range, reading times without an offset in UTC.
+    ///
+    /// # Arguments
+    /// - `text`: The range, such as `2024-05-01 10:00..10:30`.
+    ///
+    /// # Errors
+    /// Returns a `TimeRangeError` if the range is invalid or empty.
+    pub fn parse(text: &str) -> Result<Self, TimeRangeError> {
+        Self::parse_in(text, &Utc)
+    }
+
+    /// Parses a time range, reading times without an offset in a time zone.
+    ///
+    /// # Arguments
+    /// - `text`: The range, such as `2024-05-01 10:00..10:30`.
+    /// - `tz`: The time zone of times without an offset, such as `Local`.
+    ///
+    /// # Errors
+    /// Returns a `TimeRangeError` if the range is invalid or empty, or if a bound does not exist
+    /// in the time zone because of a daylight saving time change.
+    pub fn parse_in<Tz: TimeZone>(text: &str, tz: &Tz) -> Result<Self, TimeRangeError> {
+        let (start, end) = match text.split_once("..") {
+            Some((start, end)) => (Bound::parse(start)?, Some(Bound::parse(end)?)),
+            None => (Bound::parse(text)?, None),
+        };
+        let start = match start {
+            Bound::Date(date) => resolve(date.and_time(NaiveTime::MIN), tz)?,
+            Bound::DateTime(datetime) => resolve(datetime, tz)?,
+            Bound::Zoned(zoned) => zoned.with_timezone(tz),
+            Bound::Time(_) => return Err(TimeRangeError::new("the start of a range needs a date")),
+        };
+        let end = match end {
+            None if text.contains(':') => {
+                return Err(TimeRangeError::new(
+                    "a date and time needs an end, written 'start..end'",
+                ));
+            }
+            None => resolve(next_day(start.date_naive())?.and_time(NaiveTime::MIN), tz)?,
+            Some(Bound::Date(date)) => resolve(next_day(date)?.and_time(NaiveTime::MIN), tz)?,
+            Some(Bound::DateTime(datetime)) => resolve(datetime, tz)?,
+            Some(Bound::Zoned(zoned)) => zoned.with_timezone(tz),
+            Some(Bound::Time(time)) => {
+                let local_start = start.naive_local();
+                let date = if time > local_start.time() {
+                    local_start.date()
+                } else {
+                    next_day(local_start.date())?
+                };
+                resolve(date.and_time(time), tz)?
+            }
+        };
+        let range = TimeRange {
+            start_us: timestamp_us(&start)?,
+            end_us: timestamp_us(&end)?,
+        };
+        if range.end_us <= range.start_us {
+            return Err(TimeRangeError::new(
+                "the end of the range is not after its start",
+            ));
+        }
+        Ok(range)
+    }
+
+    /// Returns whether a timestamp is in the range.
+    pub fn contains(&self, timestamp_us: u64) -> bool {
+        (self.start_us..self.end_us).contains(&timestamp_us)
+    }
+
+    /// Returns the length of the range in microseconds.
+    pub fn duration_us(&self) -> u64 {
+        self.end_us - self.start_us
+    }
+
+    /// Returns a filter selecting the entries timestamped in the range.
+    #[cfg(feature = "analysis")]
+    pub fn to_filter(&self) -> ParserFilter {
+        ParserFilter::between(self.start_us, self.end_us)
+    }
+}
+
+impl FromStr for TimeRange {
+    type Err = TimeRangeError;
+
+    /// Parses a time range, reading times without an offset in UTC, see `TimeRange::parse`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        TimeRange::parse(text)
+    }
+}
+
+impl fmt::Display for TimeRange {
+    /// Formats the range as RFC 3339 UTC times, which parse back to the same range.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = |timestamp_us: u64| {
+            to_utc(timestamp_us).map_or_else(
+                || format!("{timestamp_us}us"),
+                |datetime| datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            )
+        };
+        write!(f, "{}..{}", format(self.start_us), format(self.end_us))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-05-01T00:00:00Z in microseconds since the Unix epoch.
+    const MAY_FIRST_US: u64 = 1_714_521_600_000_000;
+    const HOUR_US: u64 = 3_600_000_000;
+
+    /// Test that timestamps convert to date times and back.
+    #[test]
+    fn test_conversions() {
+        let datetime = to_utc(MAY_FIRST_US + 1).unwrap();
+        assert_eq!(datetime.to_rfc3339(), "2024-05-01T00:00:00.000001+00:00");
+        assert_eq!(to_timestamp_us(&datetime), Some(MAY_FIRST_US + 1));
+        assert_eq!(
+            to_timestamp_us(&to_local(MAY_FIRST_US).unwrap()),
+            Some(MAY_FIRST_US)
+        );
+        assert_eq!(to_utc(u64::MAX), None);
+    }
+
+    /// Test that ranges are parsed from dates, times and offsets.
+    #[test]
+    fn test_parse() {
+        let range = TimeRange::parse("2024-05-01 10:00..10:30").unwrap();
+        assert_eq!(range.start_us, MAY_FIRST_US + 10 * HOUR_US);
+        assert_eq!(range.duration_us(), HOUR_US / 2);
+        assert!(range.contains(range.start_us));
+        assert!(!range.contains(range.end_us));
+
+        let range: TimeRange = "2024-05-01".parse().unwrap();
+        assert_eq!(range.start_us, MAY_FIRST_US);
+        assert_eq!(range.duration_us(), 24 * HOUR_US);
+
+        let range = TimeRange::parse("2024-05-01T23:30..00:30").unwrap();
+        assert_eq!(range.duration_us(), HOUR_US);
+
+        let range = TimeRange::parse("2024-05-01T10:00:00+02:00..2024-05-02").unwrap();
+        assert_eq!(range.start_us, MAY_FIRST_US + 8 * HOUR_US);
+        assert_eq!(range.end_us, MAY_FIRST_US + 48 * HOUR_US);
+        assert_eq!(
+            range.to_string(),
+            "2024-05-01T08:00:00Z..2024-05-03T00:00:00Z"
+        );
+        assert_eq!(range.to_string().parse::<TimeRange>().unwrap(), range);
+
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        let range = TimeRange::parse_in("2024-05-01 10:00..10:30:15.5", &tz).unwrap();
+        assert_eq!(range.start_us, MAY_FIRST_US + 8 * HOUR_US);
+        assert_eq!(range.duration_us(), HOUR_US / 2 + 15_500_000);
+    }
+
+    /// Test that invalid and empty ranges are rejected.
+    #[test]
+    fn test_parse_errors() {
+        for text in [
+            "yesterday",
+            "10:00..11:00",
+            "2024-05-01 10:00",
+            "2024-05-01 10:00..2024-05-01 09:00",
+            "1969-12-31",
+            "2024-05-01..2024-13-01",
+        ] {
+            assert!(TimeRange::parse(text).is_err(), "{text}");
+        }
+    }
+
+    /// Test that a range selects entries through a filter.
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_to_filter() {
+        use crate::mav_parser::LogEntry;
+        use mavlink::common::MavMessage;
+
+        let filter = TimeRange::parse("2024-05-01 10:00..10:30")
+            .unwrap()
+            .to_filter();
+        let entry = |timestamp_us: u64| LogEntry::<MavMessage> {
+            timestamp: Some(timestamp_us),
+            ..Default::default()
+        };
+        assert!(filter.matches(&entry(MAY_FIRST_US + 10 * HOUR_US)));
+        assert!(!filter.matches(&entry(MAY_FIRST_US + 11 * HOUR_US)));
+    }
+}
@@ -0,0 +1,391 @@
+//! Derived channels computed from the fields of messages during export.
+//!
+//! A `DerivedChannel` is parsed from a definition such as `speed = hypot(vx, vy, vz)` or
+//! `power = voltage_battery * current_battery`, so that common derived signals are written
+//! with the exported data instead of being computed afterwards. Derived channels are added to
+//! resampled channels with `ResampledChannels::derive`, to JSON objects with `to_derived_json`
+//! and to line protocol with `to_derived_line`.
+//!
+//! Expressions combine numbers and variables with `+`, `-`, `*`, `/` and `^`, `^` binding most
+//! tightly and being right associative, and the following functions:
+//! - `hypot(a, b, ...)`: The Euclidean norm of the arguments.
+//! - `sqrt(a)`, `abs(a)`: The square root and absolute value.
+//! - `min(a, b, ...)`, `max(a, b, ...)`: The smallest and largest argument.
+//! - `atan2(y, x)`: The four quadrant arc tangent, in radians.
+//! - `degrees(a)`, `radians(a)`: Conversions between radians and degrees.
+//!
+//! Variables are channel or field names. They may hold dots, so that resampled channels such
+//! as `GLOBAL_POSITION_INT.vx` are named as they are selected. NaN values, such as grid points
+//! without a sample, propagate to the result.
+use std::fmt;
+
+use crate::expr::{Cursor, ExprError, Token};
+
+/// Error returned when a derived channel definition cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeriveError {
+    /// Byte offset in the definition at which the error was found.
+    pub position: usize,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for DeriveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid derived channel at position {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl std::error::Error for DeriveError {}
+
+impl From<ExprError> for DeriveError {
+    fn from(error: ExprError) -> Self {
+        Self {
+            position: error.position,
+            message: error.message,
+        }
+    }
+}
+
+/// A function that can be called in an expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Hypot,
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Atan2,
+    Degrees,
+    Radians,
+}
+
+impl Function {
+    /// Returns the function of a name and its number of arguments, `None` if variadic.
+    fn from_name(name: &str) -> Option<(Self, Option<usize>)> {
+        let function = match name {
+            "hypot" => (Function::Hypot, None),
+            "sqrt" => (Function::Sqrt, Some(1)),
+            "abs" => (Function::Abs, Some(1)),
+            "min" => (Function::Min, None),
+            "max" => (Function::Max, None),
+            "atan2" => (Function::Atan2, Some(2)),
+            "degrees" => (Function::Degrees, Some(1)),
+            "radians" => (Function::Radians, Some(1)),
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        match self {
+            Function::Hypot => args.iter().map(|arg| arg * arg).sum::<f64>().sqrt(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Abs => args[0].abs(),
+            // f64::min and f64::max skip NaN, which should propagate like in other operations
+            Function::Min | Function::Max if args.iter().any(|arg| arg.is_nan()) => f64::NAN,
+            Function::Min => args.iter().copied().fold(f64::INFINITY, f64::min),
+            Function::Max => args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Function::Atan2 => args[0].atan2(args[1]),
+            Function::Degrees => args[0].to_degrees(),
+            Function::Radians => args[0].to_radians(),
+        }
+    }
+}
+
+/// A binary operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// A parsed arithmetic expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Variable(String),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        let value = match self {
+            Expr::Number(value) => *value,
+            Expr::Variable(name) => lookup(name)?,
+            Expr::Neg(expr) => -expr.evaluate(lookup)?,
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(lookup)?, rhs.evaluate(lookup)?);
+                match op {
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div => lhs / rhs,
+                    BinOp::Pow => lhs.powf(rhs),
+                }
+            }
+            Expr::Call(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(lookup))
+                    .collect::<Option<Vec<f64>>>()?;
+                function.apply(&args)
+            }
+        };
+        Some(value)
+    }
+
+    fn collect_variables<'a>(&'a self, variables: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Variable(name) => {
+                if !variables.contains(&name.as_str()) {
+                    variables.push(name);
+                }
+            }
+            Expr::Neg(expr) => expr.collect_variables(variables),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.collect_variables(variables);
+                rhs.collect_variables(variables);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.collect_variables(variables)),
+        }
+    }
+}
+
+/// Operators and punctuation of definitions.
+const SYMBOLS: &[&str] = &["+", "-", "*", "/", "^", "=", ",", "(", ")"];
+
+/// Returns the binary operator of a token, if it is one.
+fn bin_op(token: Option<&Token>) -> Option<BinOp> {
+    match token {
+        Some(Token::Symbol("+")) => Some(BinOp::Add),
+        Some(Token::Symbol("-")) => Some(BinOp::Sub),
+        Some(Token::Symbol("*")) => Some(BinOp::Mul),
+        Some(Token::Symbol("/")) => Some(BinOp::Div),
+        Some(Token::Symbol("^")) => Some(BinOp::Pow),
+        _ => None,
+    }
+}
+
+/// Parses `product (('+' | '-') product)*`.
+fn sum(cursor: &mut Cursor) -> Result<Expr, ExprError> {
+    let mut expr = product(cursor)?;
+    while let Some(op @ (BinOp::Add | BinOp::Sub)) = bin_op(cursor.peek()) {
+        cursor.next += 1;
+        expr = Expr::Binary(op, Box::new(expr), Box::new(product(cursor)?));
+    }
+    Ok(expr)
+}
+
+/// Parses `unary (('*' | '/') unary)*`.
+fn product(cursor: &mut Cursor) -> Result<Expr, ExprError> {
+    let mut expr = unary(cursor)?;
+    while let Some(op @ (BinOp::Mul | BinOp::Div)) = bin_op(cursor.peek()) {
+        cursor.next += 1;
+        expr = Expr::Binary(op, Box::new(expr), Box::new(unary(cursor)?));
+    }
+    Ok(expr)
+}
+
+/// Parses a negation or `atom ('^' unary)?`.
+///
+/// Every recursion of the grammar goes through here, so it is where nesting is bounded.
+fn unary(cursor: &mut Cursor) -> Result<Expr, ExprError> {
+    cursor.nested(|cursor| {
+        if cursor.eat("-") {
+            return Ok(Expr::Neg(Box::new(unary(cursor)?)));
+        }
+        let expr = atom(cursor)?;
+        if cursor.eat("^") {
+            return Ok(Expr::Binary(
+                BinOp::Pow,
+                Box::new(expr),
+                Box::new(unary(cursor)?),
+            ));
+        }
+        Ok(expr)
+    })
+}
+
+/// Parses a number, a variable, a function call or a parenthesized expression.
+fn atom(cursor: &mut Cursor) -> Result<Expr, ExprError> {
+    match cursor.bump() {
+        Some(Token::Number(value, unit)) if unit.is_empty() => Ok(Expr::Number(value)),
+        Some(Token::Symbol("(")) => {
+            let expr = sum(cursor)?;
+            cursor.expect(")", "expected ')'")?;
+            Ok(expr)
+        }
+        Some(Token::Word(name)) => {
+            if cursor.peek() != Some(&Token::Symbol("(")) {
+                return Ok(Expr::Variable(name));
+            }
+            cursor.next -= 1;
+            let Some((function, arity)) = Function::from_name(&name) else {
+                return Err(cursor.error("unknown function"));
+            };
+            let call = cursor.next;
+            cursor.next += 2;
+            let mut args = vec![sum(cursor)?];
+            while cursor.eat(",") {
+                args.push(sum(cursor)?);
+            }
+            cursor.expect(")", "expected ',' or ')'")?;
+            if arity.is_some_and(|arity| arity != args.len()) {
+                cursor.next = call;
+                return Err(cursor.error("wrong number of arguments"));
+            }
+            Ok(Expr::Call(function, args))
+        }
+        _ => {
+            cursor.next -= 1;
+            Err(cursor.error("expected a number, a variable or '('"))
+        }
+    }
+}
+
+/// A channel computed from other channels or fields, parsed from a definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivedChannel {
+    name: String,
+    expr: Expr,
+}
+
+impl DerivedChannel {
+    /// Parses a derived channel definition.
+    ///
+    /// # Arguments
+    /// - `definition`: The definition, such as `speed = hypot(vx, vy, vz)`.
+    ///
+    /// # Errors
+    /// Returns a `DeriveError` locating the first problem if the definition is invalid.
+    pub fn parse(definition: &str) -> Result<Self, DeriveError> {
+        let mut cursor = Cursor::new(definition, SYMBOLS)?;
+        let Some(Token::Word(name)) = cursor.peek().cloned() else {
+            return Err(cursor.error("expected a channel name").into());
+        };
+        cursor.next += 1;
+        cursor.expect("=", "expected '='")?;
+        let expr = sum(&mut cursor)?;
+        if cursor.peek().is_some() {
+            return Err(cursor
+                .error("expected an operator or the end of the definition")
+                .into());
+        }
+        Ok(Self { name, expr })
+    }
+
+    /// Returns the name of the channel.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the variables the expression reads, in the order they first appear.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables = Vec::new();
+        self.expr.collect_variables(&mut variables);
+        variables
+    }
+
+    /// Computes the value of the channel.
+    ///
+    /// # Arguments
+    /// - `lookup`: Returns the value of a variable, or `None` if it is not available.
+    ///
+    /// # Returns
+    /// The value, or `None` if a variable is not available.
+    pub fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        self.expr.evaluate(lookup)
+    }
+}
+
+/// Computes derived channels from the fields of a message.
+///
+/// Variables are field names, such as `vx`, or field names qualified by the message name, such
+/// as `GLOBAL_POSITION_INT.vx`, which are only available on that message. Channels reading a
+/// variable the message does not have are left out.
+#[cfg(any(feature = "influx", feature = "json"))]
+pub(crate) fn evaluate_fields(
+    derived: &[DerivedChannel],
+    message_name: &str,
+    field: &dyn Fn(&str) -> Option<f64>,
+) -> Vec<(String, f64)> {
+    let lookup = |name: &str| match name.split_once('.') {
+        Some((message, name)) if message == message_name => field(name),
+        Some(_) => None,
+        None => field(name),
+    };
+    derived
+        .iter()
+        .filter_map(|channel| Some((channel.name.clone(), channel.evaluate(&lookup)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that definitions are evaluated with the usual precedence and functions.
+    #[test]
+    fn test_evaluate() {
+        let values = |name: &str| match name {
+            "vx" => Some(3.0),
+            "GLOBAL_POSITION_INT.vy" => Some(4.0),
+            "vz" => Some(12.0),
+            "missing" => None,
+            _ => Some(f64::NAN),
+        };
+        let evaluate =
+            |definition: &str| DerivedChannel::parse(definition).unwrap().evaluate(&values);
+        assert_eq!(
+            evaluate("speed = hypot(vx, GLOBAL_POSITION_INT.vy, vz)"),
+            Some(13.0)
+        );
+        assert_eq!(evaluate("x = 1 + 2 * 3 - -4 / 2"), Some(9.0));
+        assert_eq!(evaluate("x = 2 ^ 3 ^ 2"), Some(512.0));
+        assert_eq!(evaluate("x = -vx ^ 2"), Some(-9.0));
+        assert_eq!(evaluate("x = (1 + 2) * 1.5e1"), Some(45.0));
+        assert_eq!(evaluate("x = min(vx, vz, 7) + max(vx, vz)"), Some(15.0));
+        assert_eq!(evaluate("x = degrees(atan2(1, 1))"), Some(45.0));
+        assert_eq!(evaluate("x = vx + missing"), None);
+        assert!(evaluate("x = min(vx, nan) + abs(1)").unwrap().is_nan());
+
+        let channel = DerivedChannel::parse("power = voltage * current / 1000").unwrap();
+        assert_eq!(channel.name(), "power");
+        assert_eq!(channel.variables(), vec!["voltage", "current"]);
+    }
+
+    /// Test that invalid definitions are located.
+    #[test]
+    fn test_parse_errors() {
+        let position = |definition: &str| DerivedChannel::parse(definition).unwrap_err().position;
+        assert_eq!(position("hypot(vx, vy)"), 5);
+        assert_eq!(position("x = vx +"), 8);
+        assert_eq!(position("x = foo(vx)"), 4);
+        assert_eq!(position("x = sqrt(vx, vy)"), 4);
+        assert_eq!(position("x = (vx"), 7);
+        assert_eq!(position("x = vx vy"), 7);
+        assert_eq!(position("x = vx # 2"), 7);
+        assert_eq!(
+            DerivedChannel::parse("= 1").unwrap_err().to_string(),
+            "invalid derived channel at position 0: expected a channel name"
+        );
+        assert_eq!(position("x = 2s"), 4);
+
+        let deep = format!("x = {}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let error = DerivedChannel::parse(&deep).unwrap_err();
+        assert_eq!(error.message, "expression is nested too deeply");
+        let deep = format!("x = {}1", "-".repeat(100_000));
+        assert!(DerivedChannel::parse(&deep).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! Decoding messages of several MAVLink dialects in a single pass.
+//!
+//! Parsers decode messages into a single dialect type and drop frames whose message id the
+//! dialect does not define. Logs recorded on mixed fleets hold traffic from several dialects, so
+//! `MultiDialect` combines two dialects into one message type that any parser can decode into.
+//! Combinations of more than two dialects are built by nesting, for example
+//! `MultiDialect<A, MultiDialect<B, C>>` tries `A`, then `B`, then `C`.
+//!
+//! Decoding a log with a dialect that does not define its messages silently drops or misdecodes
+//! them. `check_dialect` compares the dialect recorded by a log with the dialects compiled into
+//! the message type used to parse it.
+use mavlink::error::ParserError;
+use mavlink::{MavlinkVersion, Message};
+
+/// A message of either of two MAVLink dialects.
+///
+/// Message ids defined by the primary dialect are always decoded with it, so the dialect whose
+/// definitions should win for ids defined by both must be the primary one. Ids the primary
+/// dialect does not define are decoded with the fallback dialect.
+///
+/// # Type Parameters
+/// - `A`: The primary dialect.
+/// - `B`: The fallback dialect.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MultiDialect<A, B> {
+    /// A message decoded with the primary dialect.
+    Primary(A),
+    /// A message decoded with the fallback dialect.
+    Fallback(B),
+}
+
+impl<A: Message, B: Message> MultiDialect<A, B> {
+    /// Returns the message if it was decoded with the primary dialect.
+    pub fn primary(&self) -> Option<&A> {
+        match self {
+            MultiDialect::Primary(msg) => Some(msg),
+            MultiDialect::Fallback(_) => None,
+        }
+    }
+
+    /// Returns the message if it was decoded with the fallback dialect.
+    pub fn fallback(&self) -> Option<&B> {
+        match self {
+            MultiDialect::Primary(_) => None,
+            MultiDialect::Fallback(msg) => Some(msg),
+        }
+    }
+
+    /// Returns whether the primary dialect defines a message id.
+    fn primary_defines(id: u32) -> bool {
+        A::default_message_from_id(id).is_ok()
+    }
+}
+
+impl<A: Message, B: Message> Message for MultiDialect<A, B> {
+    fn message_id(&self) -> u32 {
+        match self {
+            MultiDialect::Primary(msg) => msg.message_id(),
+            MultiDialect::Fallback(msg) => msg.message_id(),
+        }
+    }
+
+    fn message_name(&self) -> &'static str {
+        match self {
+            MultiDialect::Primary(msg) => msg.message_name(),
+            MultiDialect::Fallback(msg) => msg.message_name(),
+        }
+    }
+
+    fn ser(&self, version: MavlinkVersion, bytes: &mut [u8]) -> usize {
+        match self {
+            MultiDialect::Primary(msg) => msg.ser(version, bytes),
+            MultiDialect::Fallback(msg) => msg.ser(version, bytes),
+        }
+    }
+
+    fn parse(version: MavlinkVersion, msgid: u32, payload: &[u8]) -> Result<Self, ParserError> {
+        if Self::primary_defines(msgid) {
+            A::parse(version, msgid, payload).map(MultiDialect::Primary)
+        } else {
+            B::parse(version, msgid, payload).map(MultiDialect::Fallback)
+        }
+    }
+
+    fn message_id_from_name(name: &str) -> Result<u32, &'static str> {
+        A::message_id_from_name(name).or_else(|_| B::message_id_from_name(name))
+    }
+
+    fn default_message_from_id(id: u32) -> Result<Self, &'static str> {
+        match A::default_message_from_id(id) {
+            Ok(msg) => Ok(MultiDialect::Primary(msg)),
+            Err(_) => B::default_message_from_id(id).map(MultiDialect::Fallback),
+        }
+    }
+
+    /// Returns the CRC extra byte of the dialect the message id is decoded with, so frames of
+    /// either dialect pass the checksum check.
+    fn extra_crc(id: u32) -> u8 {
+        if Self::primary_defines(id) {
+            A::extra_crc(id)
+        } else {
+            B::extra_crc(id)
+        }
+    }
+}
+
+/// Dialects directly included by each dialect shipped with the mavlink crate, per the include
+/// elements of their XML definitions.
+const INCLUDES: &[(&str, &[&str])] = &[
+    ("standard", &["minimal"]),
+    ("common", &["standard"]),
+    (
+        "ardupilotmega",
+        &["common", "uavionix", "icarous", "cubepilot", "csairlink"],
+    ),
+    ("asluav", &["common"]),
+    ("avssuas", &["common"]),
+    ("cubepilot", &["common"]),
+    ("development", &["common"]),
+    ("matrixpilot", &["common"]),
+    ("paparazzi", &["common"]),
+    ("storm32", &["ardupilotmega"]),
+    ("ualberta", &["common"]),
+    ("uavionix", &["common"]),
+];
+
+/// Returns whether a dialect defines every message of another dialect.
+///
+/// Dialect names are compared ignoring case. The `all` dialect includes every dialect.
+///
+/// # Arguments
+/// - `dialect`: The name of the including dialect, such as `ardupilotmega`.
+/// - `other`: The name of the included dialect, such as `common`.
+pub fn dialect_includes(dialect: &str, other: &str) -> bool {
+    if dialect.eq_ignore_ascii_case(other) || dialect.eq_ignore_ascii_case("all") {
+        return true;
+    }
+    INCLUDES
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(dialect))
+        .flat_map(|(_, included)| included.iter())
+        .any(|included| dialect_includes(included, other))
+}
+
+/// Returns the names of the mavlink crate dialects a message type decodes.
+///
+/// The names are taken from the type name of `M`, so `mavlink::common::MavMessage` gives
+/// `common` and a `MultiDialect` gives the dialects it combines. Message types defined outside
+/// the mavlink crate give no names.
+pub fn compiled_dialects<M: Message>() -> Vec<&'static str> {
+    std::any::type_name::<M>()
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+        .filter_map(|path| path.strip_prefix("mavlink::")?.strip_suffix("::MavMessage"))
+        .collect()
+}
+
+/// Warning that a log was recorded with a dialect the message type used to parse it does not
+/// include.
+///
+/// Messages of the recorded dialect missing from the compiled ones are dropped, and messages
+/// whose definition differs between dialects may be misdecoded.
+#[derive(PartialEq, Clone, Debug)]
+pub struct DialectMismatch {
+    /// The dialect recorded in the log.
+    pub recorded: String,
+    /// The dialects compiled into the message type, see `compiled_dialects`.
+    pub compiled: Vec<&'static str>,
+}
+
+impl std::fmt::Display for DialectMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Log recorded with MAVLink dialect \"{}\" is parsed with {}",
+            self.recorded,
+            self.compiled.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for DialectMismatch {}
+
+impl From<DialectMismatch> for std::io::Error {
+    fn from(mismatch: DialectMismatch) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, mismatch)
+    }
+}
+
+/// Checks that a message type decodes the dialect a log was recorded with.
+///
+/// The check passes if any compiled dialect includes the recorded one. It cannot be made, and
+/// passes, if the recorded dialect is empty or if `M` is not a mavlink crate dialect.
+///
+/// # Arguments
+/// - `recorded`: The dialect recorded in the log.
+///
+/// # Errors
+/// Returns a `DialectMismatch` if none of the compiled dialects include the recorded one.
+pub fn check_dialect<M: Message>(recorded: &str) -> Result<(), DialectMismatch> {
+    let compiled = compiled_dialects::<M>();
+    if recorded.is_empty()
+        || compiled.is_empty()
+        || compiled
+            .iter()
+            .any(|dialect| dialect_includes(dialect, recorded))
+    {
+        return Ok(());
+    }
+    Err(DialectMismatch {
+        recorded: String::from(recorded),
+        compiled,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::{ardupilotmega, common};
+
+    use super::*;
+
+    type CommonFirst = MultiDialect<common::MavMessage, ardupilotmega::MavMessage>;
+
+    /// Test that ids of the primary dialect are decoded with it and others with the fallback.
+    #[test]
+    fn test_parse_priority() {
+        let heartbeat = common::MavMessage::HEARTBEAT(Default::default());
+        let ahrs = ardupilotmega::MavMessage::AHRS(Default::default());
+        let mut payload = [0u8; 255];
+
+        let len = heartbeat.ser(MavlinkVersion::V2, &mut payload);
+        let msg = CommonFirst::parse(MavlinkVersion::V2, 0, &payload[..len]).unwrap();
+        assert_eq!(msg.primary(), Some(&heartbeat));
+
+        let len = ahrs.ser(MavlinkVersion::V2, &mut payload);
+        let msg = CommonFirst::parse(MavlinkVersion::V2, ahrs.message_id(), &payload[..len]);
+        let msg = msg.unwrap();
+        assert_eq!(msg.fallback(), Some(&ahrs));
+        assert_eq!(msg.message_name(), "AHRS");
+        assert_eq!(
+            CommonFirst::extra_crc(ahrs.message_id()),
+            ardupilotmega::MavMessage::extra_crc(ahrs.message_id())
+        );
+        assert_eq!(
+            CommonFirst::message_id_from_name("AHRS"),
+            Ok(ahrs.message_id())
+        );
+        // ids defined by neither dialect are still rejected
+        assert!(CommonFirst::default_message_from_id(60000).is_err());
+    }
+
+    /// Test that dialects include the dialects their definitions include, recursively.
+    #[test]
+    fn test_dialect_includes() {
+        assert!(dialect_includes("ardupilotmega", "common"));
+        assert!(dialect_includes("ArduPilotMega", "minimal"));
+        assert!(dialect_includes("storm32", "common"));
+        assert!(dialect_includes("all", "ardupilotmega"));
+        assert!(!dialect_includes("common", "ardupilotmega"));
+        assert!(!dialect_includes("minimal", "test"));
+    }
+
+    /// Test that the recorded dialect is checked against every compiled dialect.
+    #[test]
+    fn test_check_dialect() {
+        assert_eq!(compiled_dialects::<common::MavMessage>(), vec!["common"]);
+        assert_eq!(
+            compiled_dialects::<CommonFirst>(),
+            vec!["common", "ardupilotmega"]
+        );
+        assert!(check_dialect::<common::MavMessage>("common").is_ok());
+        assert!(check_dialect::<ardupilotmega::MavMessage>("common").is_ok());
+        assert!(check_dialect::<CommonFirst>("ardupilotmega").is_ok());
+        assert!(check_dialect::<common::MavMessage>("").is_ok());
+        let mismatch = check_dialect::<common::MavMessage>("ardupilotmega").unwrap_err();
+        assert_eq!(mismatch.recorded, "ardupilotmega");
+        assert_eq!(mismatch.compiled, vec!["common"]);
+        assert!(mismatch.to_string().contains("\"ardupilotmega\""));
+    }
+}
@@ -0,0 +1,259 @@
+//! Tokenizer and parser cursor shared by the small expression languages of this crate.
+//!
+//! Filter expressions, see `analysis::filter`, and derived channel definitions, see `derive`,
+//! are split into the same kinds of tokens and parsed by recursive descent over a `Cursor`. Each
+//! language gives the operators and punctuation it accepts and writes its own grammar, while
+//! tokenizing, error positions and the nesting limit are handled here.
+
+/// Deepest nesting of parentheses, negations and function calls accepted, so that inputs such
+/// as `((((…` are rejected before they overflow the stack.
+pub(crate) const MAX_DEPTH: usize = 64;
+
+/// Error found while tokenizing or parsing an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ExprError {
+    /// Byte offset in the expression at which the error was found.
+    pub(crate) position: usize,
+    /// Description of the error.
+    pub(crate) message: String,
+}
+
+/// A token of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    /// A name, such as a field, variable or function name, or a bare text value. Names may hold
+    /// dots, as in `GLOBAL_POSITION_INT.vx`.
+    Word(String),
+    /// A text value quoted with `'` or `"`.
+    Text(String),
+    /// A number and the unit letters directly following it, empty if there are none.
+    Number(f64, String),
+    /// An operator or punctuation, one of the symbols of the language.
+    Symbol(&'static str),
+}
+
+/// Splits an expression into tokens and their byte offsets.
+///
+/// # Arguments
+/// - `expr`: The expression.
+/// - `symbols`: The operators and punctuation of the language. Longer symbols are matched
+///   first, so `==` is not read as two `=`.
+///
+/// # Errors
+/// Returns an `ExprError` for an invalid number, an unterminated text value or a character that
+/// starts no token.
+pub(crate) fn tokenize(
+    expr: &str,
+    symbols: &[&'static str],
+) -> Result<Vec<(usize, Token)>, ExprError> {
+    let error = |position: usize, message: &str| ExprError {
+        position,
+        message: message.to_string(),
+    };
+    let bytes = expr.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let symbol = symbols
+            .iter()
+            .filter(|symbol| expr[pos..].starts_with(**symbol))
+            .max_by_key(|symbol| symbol.len());
+        if let Some(symbol) = symbol {
+            pos += symbol.len();
+            tokens.push((start, Token::Symbol(symbol)));
+            continue;
+        }
+        match bytes[pos] {
+            b' ' | b'\t' | b'\n' | b'\r' => pos += 1,
+            quote @ (b'\'' | b'"') => {
+                let Some(len) = bytes[pos + 1..].iter().position(|&b| b == quote) else {
+                    return Err(error(start, "unterminated text value"));
+                };
+                pos += len + 2;
+                tokens.push((start, Token::Text(expr[start + 1..pos - 1].to_string())));
+            }
+            b'0'..=b'9' | b'.' => {
+                while pos < bytes.len() && (bytes[pos].is_ascii_digit() || bytes[pos] == b'.') {
+                    pos += 1;
+                }
+                // exponent, as in 1e-3
+                if pos < bytes.len() && matches!(bytes[pos], b'e' | b'E') {
+                    let sign = usize::from(matches!(bytes.get(pos + 1), Some(b'+' | b'-')));
+                    if bytes.get(pos + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                        pos += 1 + sign;
+                        while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                            pos += 1;
+                        }
+                    }
+                }
+                let number = expr[start..pos]
+                    .parse::<f64>()
+                    .map_err(|_| error(start, "invalid number"))?;
+                let unit_start = pos;
+                while pos < bytes.len() && bytes[pos].is_ascii_alphabetic() {
+                    pos += 1;
+                }
+                tokens.push((
+                    start,
+                    Token::Number(number, expr[unit_start..pos].to_string()),
+                ));
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                while pos < bytes.len()
+                    && (bytes[pos].is_ascii_alphanumeric() || matches!(bytes[pos], b'_' | b'.'))
+                {
+                    pos += 1;
+                }
+                tokens.push((start, Token::Word(expr[start..pos].to_string())));
+            }
+            _ => return Err(error(start, "unexpected character")),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Position of a recursive descent parser in the tokens of an expression.
+pub(crate) struct Cursor {
+    tokens: Vec<(usize, Token)>,
+    /// Index of the next token.
+    pub(crate) next: usize,
+    /// Length of the expression, reported as the position of errors at its end.
+    len: usize,
+    /// Current nesting depth, see `nested`.
+    depth: usize,
+}
+
+impl Cursor {
+    /// Tokenizes an expression and positions the cursor at its first token.
+    ///
+    /// # Arguments
+    /// - `expr`: The expression.
+    /// - `symbols`: The operators and punctuation of the language, see `tokenize`.
+    ///
+    /// # Errors
+    /// Returns the errors of `tokenize`.
+    pub(crate) fn new(expr: &str, symbols: &[&'static str]) -> Result<Self, ExprError> {
+        Ok(Self {
+            tokens: tokenize(expr, symbols)?,
+            next: 0,
+            len: expr.len(),
+            depth: 0,
+        })
+    }
+
+    /// Returns an error located at the next token, or at the end of the expression.
+    pub(crate) fn error(&self, message: &str) -> ExprError {
+        ExprError {
+            position: self.tokens.get(self.next).map_or(self.len, |(pos, _)| *pos),
+            message: message.to_string(),
+        }
+    }
+
+    /// Returns the next token without consuming it.
+    pub(crate) fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    /// Consumes and returns the next token.
+    pub(crate) fn bump(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.next += 1;
+        token
+    }
+
+    /// Consumes the next token if it is a symbol, returning whether it was.
+    pub(crate) fn eat(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(next)) if *next == symbol);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    /// Consumes the next token if it is a symbol, or returns an error with a message.
+    pub(crate) fn expect(&mut self, symbol: &str, message: &str) -> Result<(), ExprError> {
+        if !self.eat(symbol) {
+            return Err(self.error(message));
+        }
+        Ok(())
+    }
+
+    /// Runs a parsing step one nesting level deeper.
+    ///
+    /// Grammars call this on each rule that may recurse, so that every cycle of the grammar
+    /// goes through it.
+    ///
+    /// # Errors
+    /// Returns an `ExprError` located at the next token if the expression is nested deeper than
+    /// `MAX_DEPTH`, and the errors of the step otherwise.
+    pub(crate) fn nested<T>(
+        &mut self,
+        step: impl FnOnce(&mut Self) -> Result<T, ExprError>,
+    ) -> Result<T, ExprError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("expression is nested too deeply"));
+        }
+        self.depth += 1;
+        let result = step(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that expressions are split into words, text, numbers with units and the longest
+    /// symbols of the language.
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize(
+            "t >= 1.5e1ms && GPS.vx == 'A B' = 3",
+            &["=", "==", ">=", "&&"],
+        )
+        .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, Token::Word(String::from("t"))),
+                (2, Token::Symbol(">=")),
+                (5, Token::Number(15.0, String::from("ms"))),
+                (12, Token::Symbol("&&")),
+                (15, Token::Word(String::from("GPS.vx"))),
+                (22, Token::Symbol("==")),
+                (25, Token::Text(String::from("A B"))),
+                (31, Token::Symbol("=")),
+                (33, Token::Number(3.0, String::new())),
+            ]
+        );
+        assert_eq!(tokenize("a # b", &[]).unwrap_err().position, 2);
+        assert_eq!(tokenize("'open", &[]).unwrap_err().position, 0);
+    }
+
+    /// Test that nesting deeper than the limit is rejected instead of overflowing the stack.
+    #[test]
+    fn test_nesting_limit() {
+        /// Parses `'(' nested ')' | word`.
+        fn parens(cursor: &mut Cursor) -> Result<(), ExprError> {
+            cursor.nested(|cursor| {
+                if cursor.eat("(") {
+                    parens(cursor)?;
+                    return cursor.expect(")", "expected ')'");
+                }
+                match cursor.bump() {
+                    Some(Token::Word(_)) => Ok(()),
+                    _ => Err(cursor.error("expected a word")),
+                }
+            })
+        }
+        let expr = |depth: usize| format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+        let mut cursor = Cursor::new(&expr(MAX_DEPTH - 1), &["(", ")"]).unwrap();
+        assert!(parens(&mut cursor).is_ok());
+        let mut cursor = Cursor::new(&expr(100_000), &["(", ")"]).unwrap();
+        let error = parens(&mut cursor).unwrap_err();
+        assert_eq!(error.position, MAX_DEPTH);
+        assert_eq!(error.message, "expression is nested too deeply");
+    }
+}
@@ -0,0 +1,195 @@
+//! Helpers for reading individual MAVLink message fields independent of the dialect.
+//!
+//! The MAVLink wire layout of a message is identical across every dialect that includes it, so
+//! rather than matching on a dialect specific `MavMessage` enum we serialize the message payload
+//! and read fields at their wire offsets. Offsets assume the payload is ordered per the MAVLink
+//! serialization rules (fields sorted by size with extensions appended).
+//!
+//! Fields are also read by name from the `Debug` representation of messages with
+//! `debug_fields`, for exports and analyses that let users select any field.
+#![cfg_attr(not(feature = "analysis"), allow(dead_code))]
+use mavlink::{MavlinkVersion, Message};
+
+/// Maximum size of a MAVLink payload in bytes.
+pub const MAX_PAYLOAD_SIZE: usize = 255;
+
+/// HEARTBEAT message id.
+pub const HEARTBEAT_ID: u32 = 0;
+
+/// SYS_STATUS message id.
+pub const SYS_STATUS_ID: u32 = 1;
+
+/// PING message id.
+pub const PING_ID: u32 = 4;
+
+/// PARAM_VALUE message id.
+pub const PARAM_VALUE_ID: u32 = 22;
+
+/// GPS_RAW_INT message id.
+pub const GPS_RAW_INT_ID: u32 = 24;
+
+/// ATTITUDE message id.
+pub const ATTITUDE_ID: u32 = 30;
+
+/// GLOBAL_POSITION_INT message id.
+pub const GLOBAL_POSITION_INT_ID: u32 = 33;
+
+/// VFR_HUD message id.
+pub const VFR_HUD_ID: u32 = 74;
+
+/// COMMAND_INT message id.
+pub const COMMAND_INT_ID: u32 = 75;
+
+/// COMMAND_LONG message id.
+pub const COMMAND_LONG_ID: u32 = 76;
+
+/// COMMAND_ACK message id.
+pub const COMMAND_ACK_ID: u32 = 77;
+
+/// RADIO_STATUS message id.
+pub const RADIO_STATUS_ID: u32 = 109;
+
+/// TIMESYNC message id.
+pub const TIMESYNC_ID: u32 = 111;
+
+/// SERIAL_CONTROL message id.
+pub const SERIAL_CONTROL_ID: u32 = 126;
+
+/// BATTERY_STATUS message id.
+pub const BATTERY_STATUS_ID: u32 = 147;
+
+/// EKF_STATUS_REPORT message id, from the ardupilotmega dialect.
+pub const EKF_STATUS_REPORT_ID: u32 = 193;
+
+/// WIND_COV message id.
+pub const WIND_COV_ID: u32 = 231;
+
+/// GPS_RTCM_DATA message id.
+pub const GPS_RTCM_DATA_ID: u32 = 233;
+
+/// VIBRATION message id.
+pub const VIBRATION_ID: u32 = 241;
+
+/// HOME_POSITION message id.
+pub const HOME_POSITION_ID: u32 = 242;
+
+/// STATUSTEXT message id.
+pub const STATUSTEXT_ID: u32 = 253;
+
+/// CAMERA_IMAGE_CAPTURED message id.
+pub const CAMERA_IMAGE_CAPTURED_ID: u32 = 263;
+
+/// TUNNEL message id.
+pub const TUNNEL_ID: u32 = 385;
+
+/// Serializes the payload of a MAVLink message into a zero padded buffer.
+///
+/// MAVLink 2 truncates trailing zero bytes so padding the buffer allows any field offset within
+/// the message to be read directly.
+pub fn payload<M: Message>(msg: &M) -> [u8; MAX_PAYLOAD_SIZE] {
+    let mut buf = [0u8; MAX_PAYLOAD_SIZE];
+    msg.ser(MavlinkVersion::V2, &mut buf);
+    buf
+}
+
+/// Returns the length of the payload of a MAVLink message as sent on the wire.
+///
+/// MAVLink 2 payloads are truncated after their last non zero byte, keeping at least one byte,
+/// while MAVLink 1 payloads always have their full length.
+pub fn payload_len<M: Message>(msg: &M, version: MavlinkVersion) -> usize {
+    let mut buf = [0u8; MAX_PAYLOAD_SIZE];
+    msg.ser(version, &mut buf)
+}
+
+/// Reads a `u8` at the given payload offset.
+pub fn read_u8(payload: &[u8], offset: usize) -> u8 {
+    payload[offset]
+}
+
+/// Reads a little-endian `u16` at the given payload offset.
+pub fn read_u16(payload: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(payload[offset..offset + 2].try_into().unwrap())
+}
+
+/// Reads a little-endian `i16` at the given payload offset.
+pub fn read_i16(payload: &[u8], offset: usize) -> i16 {
+    i16::from_le_bytes(payload[offset..offset + 2].try_into().unwrap())
+}
+
+/// Reads a little-endian `u32` at the given payload offset.
+pub fn read_u32(payload: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads a little-endian `i32` at the given payload offset.
+pub fn read_i32(payload: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads a little-endian `f32` at the given payload offset.
+pub fn read_f32(payload: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads a little-endian `u64` at the given payload offset.
+pub fn read_u64(payload: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(payload[offset..offset + 8].try_into().unwrap())
+}
+
+/// Extracts the fields of a message from its `Debug` representation.
+///
+/// Arrays are expanded to one field per element, named after the array suffixed with `_` and
+/// the element index.
+///
+/// # Arguments
+/// - `debug`: The message formatted as `NAME(NAME_DATA { field: value, ... })`.
+///
+/// # Returns
+/// The field names and their values as formatted by `Debug`.
+pub fn debug_fields(debug: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let body = match (debug.find('{'), debug.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &debug[start + 1..end],
+        _ => return fields,
+    };
+    for field in split_top_level(body) {
+        let Some((name, value)) = field.split_once(':') else {
+            continue;
+        };
+        push_field(&mut fields, name.trim().to_string(), value.trim());
+    }
+    fields
+}
+
+/// Adds a field, expanding arrays to one field per element.
+fn push_field(fields: &mut Vec<(String, String)>, name: String, value: &str) {
+    if let Some(elements) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        for (index, element) in split_top_level(elements).into_iter().enumerate() {
+            push_field(fields, format!("{name}_{index}"), element.trim());
+        }
+        return;
+    }
+    fields.push((name, value.to_string()));
+}
+
+/// Splits a list on the commas that are not nested in brackets, parentheses or braces.
+fn split_top_level(list: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth: usize = 0;
+    let mut start = 0;
+    for (index, c) in list.char_indices() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                items.push(&list[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if !list[start..].trim().is_empty() {
+        items.push(&list[start..]);
+    }
+    items
+}
@@ -0,0 +1,376 @@
+//! Content based identity of logs and detection of duplicates.
+//!
+//! The same flight is often archived twice, as the TLOG of the ground station and as the .mav
+//! log of the vehicle, and logs are copied between archives under new names. A
+//! `LogFingerprint` identifies a log by the MAVLink messages it holds rather than by its file,
+//! so that copies in any supported format have the same content id. `find_duplicates` walks a
+//! directory tree and reports the logs that are copies of each other or that overlap.
+//!
+//! Entries are compared on their MAVLink header and payload only. Timestamps are left out as
+//! every recorder stamps messages with its own clock, and entries that are not MAVLink messages
+//! are ignored.
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use mavlink::{MavlinkVersion, Message};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::fields::MAX_PAYLOAD_SIZE;
+use crate::mav_parser::{LogEntry, MavParser, for_each_entry};
+use crate::open::list_files;
+
+/// Number of entries at the start and at the end of a log hashed into its fingerprint.
+pub const FINGERPRINT_ENTRIES: usize = 16;
+
+/// Identity of a log derived from its content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogFingerprint {
+    /// UUID of the file header of a .mav log.
+    pub uuid: Option<Uuid>,
+    /// SHA-256 hash of the first `FINGERPRINT_ENTRIES` MAVLink entries.
+    pub head: [u8; 32],
+    /// SHA-256 hash of the last `FINGERPRINT_ENTRIES` MAVLink entries.
+    pub tail: [u8; 32],
+    /// Number of MAVLink entries.
+    pub entries: u64,
+}
+
+impl LogFingerprint {
+    /// Returns the hex encoded SHA-256 hash of the content of the log.
+    ///
+    /// Logs holding the same MAVLink messages have the same content id, whatever their format,
+    /// file header or timestamps.
+    pub fn content_id(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.head);
+        hasher.update(self.tail);
+        hasher.update(self.entries.to_le_bytes());
+        hex(&hasher.finalize())
+    }
+
+    /// Returns the stable identity of the log: the UUID of its file header, if any, followed by
+    /// its content id.
+    pub fn id(&self) -> String {
+        match self.uuid {
+            Some(uuid) => format!("{uuid}-{}", self.content_id()),
+            None => self.content_id(),
+        }
+    }
+}
+
+/// Formats bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut text, byte| {
+        write!(text, "{byte:02x}").unwrap();
+        text
+    })
+}
+
+/// Returns the digest of the MAVLink content of an entry, or `None` if it has none.
+pub(crate) fn entry_digest<M: Message>(entry: &LogEntry<M>) -> Option<[u8; 32]> {
+    let (header, msg) = (entry.mav_header.as_ref()?, entry.mav_message.as_ref()?);
+    let mut payload = [0u8; MAX_PAYLOAD_SIZE];
+    let len = msg.ser(MavlinkVersion::V2, &mut payload);
+    let mut hasher = Sha256::new();
+    hasher.update([header.system_id, header.component_id, header.sequence]);
+    hasher.update(msg.message_id().to_le_bytes());
+    hasher.update(&payload[..len]);
+    Some(hasher.finalize().into())
+}
+
+/// Hashes a run of entry digests.
+fn hash_digests<'a>(digests: impl IntoIterator<Item = &'a [u8; 32]>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for digest in digests {
+        hasher.update(digest);
+    }
+    hasher.finalize().into()
+}
+
+/// Accumulates the entries of a log into its fingerprint.
+#[derive(Default)]
+pub(crate) struct Fingerprinter {
+    head: Vec<[u8; 32]>,
+    tail: VecDeque<[u8; 32]>,
+    entries: u64,
+}
+
+impl Fingerprinter {
+    /// Adds the digest of the next MAVLink entry.
+    pub(crate) fn push(&mut self, digest: [u8; 32]) {
+        if self.head.len() < FINGERPRINT_ENTRIES {
+            self.head.push(digest);
+        }
+        if self.tail.len() == FINGERPRINT_ENTRIES {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(digest);
+        self.entries += 1;
+    }
+
+    /// Returns the fingerprint of the entries added.
+    pub(crate) fn finish(self, uuid: Option<Uuid>) -> LogFingerprint {
+        LogFingerprint {
+            uuid,
+            head: hash_digests(&self.head),
+            tail: hash_digests(&self.tail),
+            entries: self.entries,
+        }
+    }
+}
+
+/// Reads a full log and computes its fingerprint.
+///
+/// The UUID of the fingerprint is left empty, see `fingerprint_file` to fingerprint a file.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+///
+/// # Returns
+/// The fingerprint of the log.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn fingerprint<P: MavParser + ?Sized>(parser: &mut P) -> std::io::Result<LogFingerprint> {
+    let mut fingerprinter = Fingerprinter::default();
+    for_each_entry(parser, |entry| {
+        if let Some(digest) = entry_digest(&entry) {
+            fingerprinter.push(digest);
+        }
+        Ok(())
+    })?;
+    Ok(fingerprinter.finish(None))
+}
+
+/// Opens a log file of any supported format and computes its fingerprint.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to parse messages with.
+///
+/// # Arguments
+/// - `path`: Path of the log file.
+///
+/// # Returns
+/// The fingerprint of the log, with the UUID of its file header if it is a .mav log.
+///
+/// # Errors
+/// Returns the errors of `open` if the file is not a supported log, and an `io::Error` if it
+/// could not be read.
+pub fn fingerprint_file<M: Message + 'static>(path: &str) -> std::io::Result<LogFingerprint> {
+    let mut parser = crate::open::<M>(path)?;
+    let mut fingerprint = fingerprint(&mut *parser)?;
+    fingerprint.uuid = crate::mavlog::parser::read_header(path)
+        .ok()
+        .map(|header| header.uuid);
+    Ok(fingerprint)
+}
+
+/// How two logs relate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    /// Both logs hold the same MAVLink messages.
+    Duplicate,
+    /// The second log starts within the first one, such as a partial copy or a recording
+    /// started later from the same link.
+    Overlap,
+}
+
+/// Two logs holding the same messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogMatch {
+    /// The first log, the one the second starts within for an overlap.
+    pub first: PathBuf,
+    /// The second log.
+    pub second: PathBuf,
+    /// How the logs relate.
+    pub relation: Relation,
+}
+
+/// Finds the logs of a directory tree that are duplicates of each other or overlap.
+///
+/// Duplicates have the same content id. A log overlaps another if its first
+/// `FINGERPRINT_ENTRIES` MAVLink entries appear in a row in the other one, so logs shorter than
+/// that are only compared as duplicates. Every log is read once in full, after its first entries.
+/// Files that are not supported logs or that could not be read are skipped.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to parse messages with.
+///
+/// # Arguments
+/// - `directory`: The root of the directory tree.
+///
+/// # Returns
+/// Each pair of related logs once, ordered by the path of their first log.
+///
+/// # Errors
+/// Returns an `io::Error` if the directory tree could not be listed.
+pub fn find_duplicates<M: Message + 'static>(directory: &Path) -> std::io::Result<Vec<LogMatch>> {
+    let mut files = Vec::new();
+    list_files(directory, &mut files)?;
+    files.sort();
+
+    // the first entries of every log, to find the logs starting within another one
+    let mut heads: HashMap<Vec<[u8; 32]>, Vec<usize>> = HashMap::new();
+    let mut logs = Vec::new();
+    for path in files {
+        let Ok(mut parser) = crate::open::<M>(&path.to_string_lossy()) else {
+            continue;
+        };
+        let mut head = Vec::with_capacity(FINGERPRINT_ENTRIES);
+        while head.len() < FINGERPRINT_ENTRIES {
+            match parser.parse_next_entry() {
+                Ok(entry) => head.extend(entry_digest(&entry)),
+                Err(mavlink::error::MessageReadError::Io(e))
+                    if e.kind() == std::io::ErrorKind::InvalidData => {}
+                Err(_) => break,
+            }
+        }
+        if head.len() == FINGERPRINT_ENTRIES {
+            heads.entry(head).or_default().push(logs.len());
+        }
+        logs.push(path);
+    }
+
+    let mut content_ids = Vec::with_capacity(logs.len());
+    let mut starts_within: Vec<(usize, usize)> = Vec::new();
+    for (index, path) in logs.iter().enumerate() {
+        let mut window: VecDeque<[u8; 32]> = VecDeque::with_capacity(FINGERPRINT_ENTRIES);
+        let mut check_window = |digest: [u8; 32]| {
+            if window.len() == FINGERPRINT_ENTRIES {
+                window.pop_front();
+            }
+            window.push_back(digest);
+            if window.len() == FINGERPRINT_ENTRIES {
+                let key: Vec<[u8; 32]> = window.iter().copied().collect();
+                for &other in heads.get(&key).into_iter().flatten() {
+                    if other != index && !starts_within.contains(&(index, other)) {
+                        starts_within.push((index, other));
+                    }
+                }
+            }
+        };
+        let mut fingerprinter = Fingerprinter::default();
+        let read = crate::open::<M>(&path.to_string_lossy()).and_then(|mut parser| {
+            for_each_entry(&mut *parser, |entry| {
+                if let Some(digest) = entry_digest(&entry) {
+                    check_window(digest);
+                    fingerprinter.push(digest);
+                }
+                Ok(())
+            })
+        });
+        content_ids.push(read.ok().map(|_| fingerprinter.finish(None).content_id()));
+    }
+
+    let mut matches = Vec::new();
+    for first in 0..logs.len() {
+        for second in first + 1..logs.len() {
+            let (Some(first_id), Some(second_id)) = (&content_ids[first], &content_ids[second])
+            else {
+                continue;
+            };
+            let relation = if first_id == second_id {
+                Relation::Duplicate
+            } else if starts_within.contains(&(first, second)) {
+                Relation::Overlap
+            } else if starts_within.contains(&(second, first)) {
+                matches.push(LogMatch {
+                    first: logs[second].clone(),
+                    second: logs[first].clone(),
+                    relation: Relation::Overlap,
+                });
+                continue;
+            } else {
+                continue;
+            };
+            matches.push(LogMatch {
+                first: logs[first].clone(),
+                second: logs[second].clone(),
+                relation,
+            });
+        }
+    }
+    matches.sort_by(|a, b| (&a.first, &a.second).cmp(&(&b.first, &b.second)));
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::{HEARTBEAT_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader};
+
+    use super::*;
+    use crate::mav_logger::MavLogger;
+    use crate::tlog::logger::RotatingTlog;
+
+    /// Writes a TLOG of heartbeats with the given sequence numbers.
+    fn write_heartbeats(path: &Path, sequences: std::ops::Range<u8>) {
+        let mut logger = RotatingTlog::new(path.to_str().unwrap(), u64::MAX, 0).unwrap();
+        for sequence in sequences {
+            let frame = MavFrame {
+                header: MavHeader {
+                    system_id: 1,
+                    component_id: 1,
+                    sequence,
+                },
+                msg: MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+                    custom_mode: sequence as u32,
+                    ..Default::default()
+                }),
+                protocol_version: MavlinkVersion::V2,
+            };
+            logger.write_mavlink(frame).unwrap();
+        }
+    }
+
+    /// Test that copies and partial copies of a log are found in a directory tree.
+    #[test]
+    fn test_find_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("vehicle")).unwrap();
+        let (a, b, c) = (
+            dir.path().join("a.tlog"),
+            dir.path().join("b.tlog"),
+            dir.path().join("vehicle").join("c.tlog"),
+        );
+        write_heartbeats(&a, 0..40);
+        // written later, so only the timestamps differ
+        write_heartbeats(&b, 0..40);
+        write_heartbeats(&c, 10..40);
+        write_heartbeats(&dir.path().join("short.tlog"), 0..5);
+        std::fs::write(dir.path().join("notes.txt"), "not a log").unwrap();
+
+        let fingerprint_a = fingerprint_file::<MavMessage>(a.to_str().unwrap()).unwrap();
+        let fingerprint_b = fingerprint_file::<MavMessage>(b.to_str().unwrap()).unwrap();
+        assert_eq!(fingerprint_a.entries, 40);
+        assert_eq!(fingerprint_a.uuid, None);
+        assert_eq!(fingerprint_a.id(), fingerprint_b.content_id());
+        let fingerprint_c = fingerprint_file::<MavMessage>(c.to_str().unwrap()).unwrap();
+        assert_eq!(fingerprint_a.tail, fingerprint_c.tail);
+        assert_ne!(fingerprint_a.content_id(), fingerprint_c.content_id());
+
+        let matches = find_duplicates::<MavMessage>(dir.path()).unwrap();
+        assert_eq!(
+            matches,
+            vec![
+                LogMatch {
+                    first: a.clone(),
+                    second: b.clone(),
+                    relation: Relation::Duplicate,
+                },
+                LogMatch {
+                    first: a,
+                    second: c.clone(),
+                    relation: Relation::Overlap,
+                },
+                LogMatch {
+                    first: b,
+                    second: c,
+                    relation: Relation::Overlap,
+                },
+            ]
+        );
+    }
+}
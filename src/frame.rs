@@ -0,0 +1,301 @@
+//! Strict decoding of individual serialized MAVLink frames.
+//!
+//! Unlike `mavlink::read_versioned_msg`, these functions never search ahead for the next frame.
+//! A frame either decodes exactly where it starts or an error is returned, leaving the caller in
+//! control of how to recover. Both MAVLink 1 and MAVLink 2 frames are supported.
+#![cfg_attr(not(feature = "parser"), allow(dead_code))]
+use mavlink::error::ParserError;
+use mavlink::{MAV_STX, MAV_STX_V2, MavHeader, MavlinkVersion, Message};
+
+/// Number of bytes needed to determine the length of a frame.
+pub const LENGTH_PEEK_SIZE: usize = 3;
+/// Number of bytes needed to read the flags of a frame.
+pub const FLAGS_PEEK_SIZE: usize = 4;
+
+/// Size of the MAVLink 1 header including the magic byte.
+const V1_HEADER_SIZE: usize = 6;
+/// Size of the MAVLink 2 header including the magic byte.
+const V2_HEADER_SIZE: usize = 10;
+/// Size of the frame checksum.
+const CHECKSUM_SIZE: usize = 2;
+/// Size of a MAVLink 2 signature.
+pub(crate) const SIGNATURE_SIZE: usize = 13;
+/// MAVLink 2 incompatibility flag indicating the frame is signed.
+pub(crate) const IFLAG_SIGNED: u8 = 0x01;
+
+/// Reasons a frame could not be decoded.
+#[derive(Debug)]
+pub enum FrameError {
+    /// The bytes do not form a MAVLink frame (bad magic, truncated or checksum mismatch).
+    Invalid,
+    /// The frame is intact but its message could not be parsed.
+    Parse(ParserError),
+}
+
+/// A successfully decoded MAVLink frame.
+pub struct DecodedFrame<M: Message> {
+    /// MAVLink protocol version of the frame.
+    pub version: MavlinkVersion,
+    /// MAVLink header of the frame.
+    pub header: MavHeader,
+    /// Incompatibility and compatibility flags of a MAVLink 2 frame, `None` for MAVLink 1.
+    pub flags: Option<(u8, u8)>,
+    /// The decoded message.
+    pub msg: M,
+}
+
+/// Determines the MAVLink version from a frame magic byte.
+pub fn version_from_magic(magic: u8) -> Option<MavlinkVersion> {
+    match magic {
+        MAV_STX => Some(MavlinkVersion::V1),
+        MAV_STX_V2 => Some(MavlinkVersion::V2),
+        _ => None,
+    }
+}
+
+/// Reads the incompatibility and compatibility flags of the frame starting at `bytes`.
+///
+/// # Arguments
+/// - `bytes`: At least `FLAGS_PEEK_SIZE` bytes from the start of the frame.
+///
+/// # Returns
+/// The incompatibility and compatibility flags, or `None` if `bytes` does not start with a
+/// MAVLink 2 magic byte.
+pub fn flags(bytes: &[u8]) -> Option<(u8, u8)> {
+    match version_from_magic(bytes[0])? {
+        MavlinkVersion::V1 => None,
+        MavlinkVersion::V2 => Some((bytes[2], bytes[3])),
+    }
+}
+
+/// Determines the total length in bytes of the frame starting at `bytes`.
+///
+/// # Arguments
+/// - `bytes`: At least `LENGTH_PEEK_SIZE` bytes from the start of the frame.
+///
+/// # Returns
+/// The frame length including header, checksum and signature, or `None` if `bytes` does not
+/// start with a MAVLink magic byte.
+pub fn frame_len(bytes: &[u8]) -> Option<usize> {
+    let payload_len = bytes[1] as usize;
+    match version_from_magic(bytes[0])? {
+        MavlinkVersion::V1 => Some(V1_HEADER_SIZE + payload_len + CHECKSUM_SIZE),
+        MavlinkVersion::V2 => {
+            let signature_len = if bytes[2] & IFLAG_SIGNED != 0 {
+                SIGNATURE_SIZE
+            } else {
+                0
+            };
+            Some(V2_HEADER_SIZE + payload_len + CHECKSUM_SIZE + signature_len)
+        }
+    }
+}
+
+/// Checks whether `bytes` hold exactly one frame, without verifying its checksum.
+pub fn is_complete(bytes: &[u8]) -> bool {
+    bytes.len() >= LENGTH_PEEK_SIZE && frame_len(bytes) == Some(bytes.len())
+}
+
+/// Reads the message id of a complete frame.
+///
+/// # Arguments
+/// - `bytes`: Exactly the bytes of one frame, see `is_complete`.
+///
+/// # Returns
+/// The message id, or `None` if `bytes` is not a complete frame.
+pub fn message_id(bytes: &[u8]) -> Option<u32> {
+    if !is_complete(bytes) {
+        return None;
+    }
+    match version_from_magic(bytes[0])? {
+        MavlinkVersion::V1 => Some(bytes[5] as u32),
+        MavlinkVersion::V2 => Some(u32::from_le_bytes([bytes[7], bytes[8], bytes[9], 0])),
+    }
+}
+
+/// Reads the system and component ids of a complete frame.
+///
+/// # Arguments
+/// - `bytes`: Exactly the bytes of one frame, see `is_complete`.
+///
+/// # Returns
+/// The system and component ids, or `None` if `bytes` is not a complete frame.
+pub fn source(bytes: &[u8]) -> Option<(u8, u8)> {
+    if !is_complete(bytes) {
+        return None;
+    }
+    match version_from_magic(bytes[0])? {
+        MavlinkVersion::V1 => Some((bytes[3], bytes[4])),
+        MavlinkVersion::V2 => Some((bytes[5], bytes[6])),
+    }
+}
+
+/// Returns the payload of a complete frame, as serialized on the wire.
+///
+/// # Arguments
+/// - `bytes`: Exactly the bytes of one frame, see `is_complete`.
+///
+/// # Returns
+/// The payload, without the trailing zeros truncated by MAVLink 2, or `None` if `bytes` is not a
+/// complete frame.
+pub fn payload(bytes: &[u8]) -> Option<&[u8]> {
+    if !is_complete(bytes) {
+        return None;
+    }
+    let header_size = match version_from_magic(bytes[0])? {
+        MavlinkVersion::V1 => V1_HEADER_SIZE,
+        MavlinkVersion::V2 => V2_HEADER_SIZE,
+    };
+    Some(&bytes[header_size..header_size + bytes[1] as usize])
+}
+
+/// Computes the checksum of a frame from its header and payload.
+///
+/// # Arguments
+/// - `bytes`: The bytes of a frame up to at least the end of its payload. The checksum and
+///   signature are not read, so the frame may be incomplete or have a stale checksum.
+///
+/// # Returns
+/// The offset of the checksum in the frame and the checksum the frame should have, or `None` if
+/// `bytes` does not hold the header and payload of a frame.
+pub(crate) fn checksum<M: Message>(bytes: &[u8]) -> Option<(usize, u16)> {
+    let (header_size, msg_id) = match version_from_magic(*bytes.first()?)? {
+        MavlinkVersion::V1 => (V1_HEADER_SIZE, *bytes.get(5)? as u32),
+        MavlinkVersion::V2 => {
+            let id = bytes.get(7..V2_HEADER_SIZE)?;
+            (V2_HEADER_SIZE, u32::from_le_bytes([id[0], id[1], id[2], 0]))
+        }
+    };
+    let checksum_start = header_size + bytes[1] as usize;
+    let crc = crc_calculate(bytes.get(1..checksum_start)?);
+    Some((checksum_start, crc_accumulate(M::extra_crc(msg_id), crc)))
+}
+
+/// Decodes a complete frame.
+///
+/// # Arguments
+/// - `bytes`: Exactly the bytes of one frame as sized by `frame_len`.
+///
+/// # Errors
+/// Returns `FrameError::Invalid` if the bytes are not a valid frame and `FrameError::Parse` if
+/// the frame is valid but the message is unknown to the dialect or has invalid content.
+pub fn decode<M: Message>(bytes: &[u8]) -> Result<DecodedFrame<M>, FrameError> {
+    if !is_complete(bytes) {
+        return Err(FrameError::Invalid);
+    }
+    let payload_len = bytes[1] as usize;
+    let (version, header_size, header, msg_id) = match version_from_magic(bytes[0]) {
+        Some(MavlinkVersion::V1) => (
+            MavlinkVersion::V1,
+            V1_HEADER_SIZE,
+            MavHeader {
+                sequence: bytes[2],
+                system_id: bytes[3],
+                component_id: bytes[4],
+            },
+            bytes[5] as u32,
+        ),
+        Some(MavlinkVersion::V2) => (
+            MavlinkVersion::V2,
+            V2_HEADER_SIZE,
+            MavHeader {
+                sequence: bytes[4],
+                system_id: bytes[5],
+                component_id: bytes[6],
+            },
+            u32::from_le_bytes([bytes[7], bytes[8], bytes[9], 0]),
+        ),
+        None => return Err(FrameError::Invalid),
+    };
+
+    let checksum_start = header_size + payload_len;
+    let mut crc = crc_calculate(&bytes[1..checksum_start]);
+    crc = crc_accumulate(M::extra_crc(msg_id), crc);
+    let expected = u16::from_le_bytes([bytes[checksum_start], bytes[checksum_start + 1]]);
+    if crc != expected {
+        return Err(FrameError::Invalid);
+    }
+
+    let payload = &bytes[header_size..checksum_start];
+    let msg = M::parse(version, msg_id, payload).map_err(FrameError::Parse)?;
+    Ok(DecodedFrame {
+        version,
+        header,
+        flags: flags(bytes),
+        msg,
+    })
+}
+
+/// Accumulates a byte into the MAVLink X.25 checksum.
+fn crc_accumulate(byte: u8, crc: u16) -> u16 {
+    let mut tmp: u8 = byte ^ (crc & 0xff) as u8;
+    tmp ^= tmp << 4;
+    let tmp = tmp as u16;
+    (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4)
+}
+
+/// Calculates the MAVLink X.25 checksum of a byte slice.
+fn crc_calculate(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .fold(0xffff, |crc, &byte| crc_accumulate(byte, crc))
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::{MavMessage, PARAM_VALUE_DATA};
+    use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw};
+
+    use super::*;
+
+    fn sample_message() -> MavMessage {
+        MavMessage::PARAM_VALUE(PARAM_VALUE_DATA {
+            param_value: 1.5,
+            param_count: 10,
+            param_index: 3,
+            param_id: *b"TEST_PARAM\0\0\0\0\0\0",
+            param_type: mavlink::common::MavParamType::MAV_PARAM_TYPE_REAL32,
+        })
+    }
+
+    /// Test that both MAVLink versions decode and report the correct version and header.
+    #[test]
+    fn test_decode_v1_and_v2() {
+        let header = MavHeader {
+            sequence: 7,
+            system_id: 3,
+            component_id: 4,
+        };
+        let mut v1 = MAVLinkV1MessageRaw::new();
+        v1.serialize_message(header, &sample_message());
+        assert_eq!(frame_len(v1.raw_bytes()), Some(v1.raw_bytes().len()));
+        let decoded = decode::<MavMessage>(v1.raw_bytes()).unwrap();
+        assert_eq!(decoded.version, MavlinkVersion::V1);
+        assert_eq!(decoded.header, header);
+        assert_eq!(decoded.flags, None);
+        assert_eq!(decoded.msg, sample_message());
+
+        let mut v2 = MAVLinkV2MessageRaw::new();
+        v2.serialize_message(header, &sample_message());
+        assert_eq!(frame_len(v2.raw_bytes()), Some(v2.raw_bytes().len()));
+        let decoded = decode::<MavMessage>(v2.raw_bytes()).unwrap();
+        assert_eq!(decoded.version, MavlinkVersion::V2);
+        assert_eq!(decoded.header, header);
+        assert_eq!(decoded.flags, Some((0, 0)));
+        assert_eq!(decoded.msg, sample_message());
+    }
+
+    /// Test that corrupted frames are rejected rather than searched past.
+    #[test]
+    fn test_decode_rejects_corruption() {
+        let mut v2 = MAVLinkV2MessageRaw::new();
+        v2.serialize_message(MavHeader::default(), &sample_message());
+        let mut bytes = v2.raw_bytes().to_vec();
+        bytes[12] ^= 0xff;
+        assert!(matches!(
+            decode::<MavMessage>(&bytes),
+            Err(FrameError::Invalid)
+        ));
+        assert!(frame_len(&[0x00, 0x01, 0x02]).is_none());
+    }
+}
@@ -0,0 +1,166 @@
+//! Conversion of positions to local frames relative to the home position.
+//!
+//! Control engineers work in local north-east-down (NED) or east-north-up (ENU) frames rather
+//! than in latitude and longitude. `LocalFrameEnrichment` adds the local coordinates of every
+//! position fix to an export, see `terrain::Enrichment`, and `geodetic_to_ned` converts single
+//! positions.
+use std::collections::BTreeMap;
+
+use crate::terrain::{Enrichment, PositionFix};
+
+/// Semi-major axis of the WGS 84 ellipsoid in meters.
+const WGS84_A: f64 = 6_378_137.0;
+
+/// First eccentricity squared of the WGS 84 ellipsoid.
+const WGS84_E2: f64 = 6.69437999014e-3;
+
+/// Converts a geodetic position to earth-centered, earth-fixed coordinates in meters.
+fn to_ecef(latitude_deg: f64, longitude_deg: f64, altitude_m: f64) -> [f64; 3] {
+    let (sin_lat, cos_lat) = latitude_deg.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = longitude_deg.to_radians().sin_cos();
+    let normal = WGS84_A / (1.0 - WGS84_E2 * sin_lat * sin_lat).sqrt();
+    [
+        (normal + altitude_m) * cos_lat * cos_lon,
+        (normal + altitude_m) * cos_lat * sin_lon,
+        (normal * (1.0 - WGS84_E2) + altitude_m) * sin_lat,
+    ]
+}
+
+/// Converts a geodetic position to north-east-down coordinates relative to an origin.
+///
+/// The conversion goes through earth-centered, earth-fixed coordinates on the WGS 84 ellipsoid,
+/// so it stays exact far from the origin. Both altitudes must be relative to the same datum.
+///
+/// # Arguments
+/// - `origin`: The origin of the frame.
+/// - `position`: The position to convert.
+///
+/// # Returns
+/// The north, east and down distances from the origin in meters.
+pub fn geodetic_to_ned(origin: &PositionFix, position: &PositionFix) -> [f64; 3] {
+    let reference = to_ecef(
+        origin.latitude_deg,
+        origin.longitude_deg,
+        origin.altitude_msl_m,
+    );
+    let point = to_ecef(
+        position.latitude_deg,
+        position.longitude_deg,
+        position.altitude_msl_m,
+    );
+    let [dx, dy, dz] = [
+        point[0] - reference[0],
+        point[1] - reference[1],
+        point[2] - reference[2],
+    ];
+    let (sin_lat, cos_lat) = origin.latitude_deg.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = origin.longitude_deg.to_radians().sin_cos();
+    let east = -sin_lon * dx + cos_lon * dy;
+    let north = -sin_lat * cos_lon * dx - sin_lat * sin_lon * dy + cos_lat * dz;
+    let up = cos_lat * cos_lon * dx + cos_lat * sin_lon * dy + sin_lat * dz;
+    [north, east, -up]
+}
+
+/// Local frame positions are exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalFrame {
+    /// North-east-down, exported as `north`, `east` and `down`, as used by MAVLink and PX4.
+    Ned,
+    /// East-north-up, exported as `east`, `north` and `up`, as used by ROS.
+    Enu,
+}
+
+/// Enrichment adding the local coordinates of position fixes relative to the home position of
+/// their vehicle, in meters.
+///
+/// The origin of each vehicle is its last HOME_POSITION, or its first position fix until a
+/// HOME_POSITION is received. Positions logged before the home position was set are therefore
+/// relative to the first fix, as autopilots only set home once they have a position.
+pub struct LocalFrameEnrichment {
+    frame: LocalFrame,
+    /// Origins by system id.
+    origins: BTreeMap<u8, PositionFix>,
+}
+
+impl LocalFrameEnrichment {
+    /// Creates a new `LocalFrameEnrichment`.
+    ///
+    /// # Arguments
+    /// - `frame`: The frame to export positions in.
+    pub fn new(frame: LocalFrame) -> Self {
+        LocalFrameEnrichment {
+            frame,
+            origins: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the origin of the frame of a vehicle, if it sent a position.
+    pub fn origin(&self, system_id: u8) -> Option<&PositionFix> {
+        self.origins.get(&system_id)
+    }
+}
+
+impl Enrichment for LocalFrameEnrichment {
+    fn enrich(&mut self, fix: &PositionFix) -> Vec<(String, f64)> {
+        let origin = self
+            .origins
+            .entry(fix.system_id)
+            .or_insert_with(|| fix.clone());
+        let [north, east, down] = geodetic_to_ned(origin, fix);
+        let values = match self.frame {
+            LocalFrame::Ned => [("north", north), ("east", east), ("down", down)],
+            LocalFrame::Enu => [("east", east), ("north", north), ("up", -down)],
+        };
+        values
+            .into_iter()
+            .map(|(name, value)| (String::from(name), value))
+            .collect()
+    }
+
+    fn on_home(&mut self, home: &PositionFix) {
+        self.origins.insert(home.system_id, home.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(latitude_deg: f64, longitude_deg: f64, altitude_msl_m: f64) -> PositionFix {
+        PositionFix {
+            timestamp_us: None,
+            system_id: 1,
+            latitude_deg,
+            longitude_deg,
+            altitude_msl_m,
+        }
+    }
+
+    /// Test that positions are converted relative to the first fix, then to the home position.
+    #[test]
+    fn test_local_frame_enrichment() {
+        let origin = fix(47.0, 8.0, 500.0);
+        // one arc second of latitude at 47 degrees is about 30.88 meters
+        let [north, east, down] = geodetic_to_ned(&origin, &fix(47.0 + 1.0 / 3600.0, 8.0, 510.0));
+        assert!((north - 30.88).abs() < 0.01, "{north}");
+        assert!(east.abs() < 1e-6);
+        assert!((down + 10.0).abs() < 0.01);
+
+        let mut enrichment = LocalFrameEnrichment::new(LocalFrame::Enu);
+        let values = enrichment.enrich(&origin);
+        assert_eq!(
+            values,
+            vec![
+                (String::from("east"), 0.0),
+                (String::from("north"), 0.0),
+                (String::from("up"), 0.0)
+            ]
+        );
+        enrichment.on_home(&fix(47.0, 8.001, 500.0));
+        let values = enrichment.enrich(&origin);
+        assert!((values[0].1 + 76.0).abs() < 0.1, "{values:?}");
+        assert!(values[1].1.abs() < 0.01);
+        assert_eq!(enrichment.origin(1).unwrap().longitude_deg, 8.001);
+        assert_eq!(enrichment.origin(2), None);
+    }
+}
@@ -0,0 +1,582 @@
+//! Export of MAVLink messages as InfluxDB line protocol.
+//!
+//! Each MAVLink entry becomes one line: the message name is the measurement, the system and
+//! component ids are the `sysid` and `compid` tags, every message field is a field and the entry
+//! timestamp is converted to nanoseconds. Lines are written to a file or posted in batches to an
+//! HTTP endpoint accepting line protocol, such as the InfluxDB `/api/v2/write` or the
+//! VictoriaMetrics `/write` endpoint, so that flight data can be plotted directly in Grafana.
+//!
+//! Fields are read from the `Debug` representation of messages so that every dialect is
+//! supported. Integers are written as integer fields, floats as float fields, enums and flags as
+//! string fields, and arrays as one field per element suffixed with its index. Non finite floats
+//! have no line protocol representation and are left out. Channels computed from the message
+//! fields, see `DerivedChannel`, are added as float fields by `to_derived_line` and
+//! `write_derived_lines`.
+use std::fmt::{Debug, Write as _};
+use std::io::{BufWriter, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use mavlink::Message;
+
+use crate::derive::{DerivedChannel, evaluate_fields};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser, for_each_entry};
+#[cfg(feature = "terrain")]
+use crate::terrain::{Enrichment, home_position, position_fix};
+
+/// Number of lines sent per HTTP request.
+const HTTP_BATCH_LINES: usize = 5000;
+
+/// Formats a MAVLink entry as a line of line protocol.
+///
+/// Entries without a timestamp are written without one, leaving the server to timestamp them.
+///
+/// # Arguments
+/// - `entry`: The entry to format.
+///
+/// # Returns
+/// The line without a trailing newline, or `None` if the entry is not a MAVLink message or the
+/// message has no field that can be written.
+pub fn to_line<M: Message + Debug>(entry: &LogEntry<M>) -> Option<String> {
+    format_line(entry, Vec::new())
+}
+
+/// Formats a MAVLink entry as a line of line protocol with derived channels as float fields.
+///
+/// The variables of the channels are the numeric and boolean fields of the message, named as
+/// they are written. Channels reading a field the message does not have, or with a non finite
+/// value, are left out.
+///
+/// # Arguments
+/// - `entry`: The entry to format.
+/// - `derived`: The channels to compute, such as one parsed from `speed = hypot(vx, vy, vz)`.
+///
+/// # Returns
+/// The line without a trailing newline, or `None` if the entry is not a MAVLink message or the
+/// message has no field that can be written.
+pub fn to_derived_line<M: Message + Debug>(
+    entry: &LogEntry<M>,
+    derived: &[DerivedChannel],
+) -> Option<String> {
+    if derived.is_empty() {
+        return format_line(entry, Vec::new());
+    }
+    let msg = entry.mav_message.as_ref()?;
+    let values = fields::debug_fields(&format!("{msg:?}"));
+    let field = |name: &str| {
+        let (_, value) = values.iter().find(|(field, _)| field == name)?;
+        match value.as_str() {
+            "true" => Some(1.0),
+            "false" => Some(0.0),
+            value => value.parse::<f64>().ok(),
+        }
+    };
+    let extra_fields = evaluate_fields(derived, msg.message_name(), &field)
+        .into_iter()
+        .filter(|(_, value)| value.is_finite())
+        // formatted with a decimal point so that whole values stay float fields
+        .map(|(name, value)| (escape_key(&name), format!("{value:?}")))
+        .collect();
+    format_line(entry, extra_fields)
+}
+
+/// Formats a MAVLink entry as a line of line protocol, adding the values of an enrichment to
+/// position fixes as float fields.
+///
+/// HOME_POSITION messages are passed to the enrichment with `Enrichment::on_home` before the
+/// position fix of the entry, if any, is enriched.
+///
+/// # Arguments
+/// - `entry`: The entry to format.
+/// - `enrichment`: The enrichment invoked if the entry holds a position fix, see
+///   `terrain::position_fix`.
+///
+/// # Returns
+/// The line without a trailing newline, or `None` if the entry is not a MAVLink message or the
+/// message has no field that can be written.
+#[cfg(feature = "terrain")]
+pub fn to_enriched_line<M: Message + Debug>(
+    entry: &LogEntry<M>,
+    enrichment: &mut dyn Enrichment,
+) -> Option<String> {
+    if let Some(home) = home_position(entry) {
+        enrichment.on_home(&home);
+    }
+    let extra_fields = match position_fix(entry) {
+        Some(fix) => enrichment
+            .enrich(&fix)
+            .into_iter()
+            .filter(|(_, value)| value.is_finite())
+            // formatted with a decimal point so that whole values stay float fields
+            .map(|(name, value)| (escape_key(&name), format!("{value:?}")))
+            .collect(),
+        None => Vec::new(),
+    };
+    format_line(entry, extra_fields)
+}
+
+/// Formats a MAVLink entry as a line of line protocol with additional fields.
+fn format_line<M: Message + Debug>(
+    entry: &LogEntry<M>,
+    extra_fields: Vec<(String, String)>,
+) -> Option<String> {
+    let msg = entry.mav_message.as_ref()?;
+    let mut line = String::from(msg.message_name());
+    if let Some(header) = &entry.mav_header {
+        write!(
+            line,
+            ",sysid={},compid={}",
+            header.system_id, header.component_id
+        )
+        .unwrap();
+    }
+    let mut separator = ' ';
+    for (name, value) in message_fields(&format!("{msg:?}"))
+        .into_iter()
+        .chain(extra_fields)
+    {
+        line.push(separator);
+        separator = ',';
+        line.push_str(&name);
+        line.push('=');
+        line.push_str(&value);
+    }
+    if separator == ' ' {
+        return None;
+    }
+    if let Some(timestamp) = entry.timestamp {
+        write!(line, " {}", timestamp as u128 * 1000).unwrap();
+    }
+    Some(line)
+}
+
+/// Writes the MAVLink entries of a log as line protocol.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `writer`: The writer receiving one line per MAVLink entry.
+///
+/// # Returns
+/// The number of lines written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the lines could not be written.
+pub fn write_lines<P, W>(parser: &mut P, writer: &mut W) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Debug,
+    W: Write,
+{
+    write_derived_lines(parser, writer, &[])
+}
+
+/// Writes the MAVLink entries of a log as line protocol with derived channels, see
+/// `to_derived_line`.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `writer`: The writer receiving one line per MAVLink entry.
+/// - `derived`: The channels to compute from the fields of each message.
+///
+/// # Returns
+/// The number of lines written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the lines could not be written.
+pub fn write_derived_lines<P, W>(
+    parser: &mut P,
+    writer: &mut W,
+    derived: &[DerivedChannel],
+) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Debug,
+    W: Write,
+{
+    let mut lines: u64 = 0;
+    for_each_entry(parser, |entry| {
+        if let Some(line) = to_derived_line(&entry, derived) {
+            writeln!(writer, "{line}")?;
+            lines += 1;
+        }
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(lines)
+}
+
+/// Writes the MAVLink entries of a log as line protocol, adding the values of an enrichment to
+/// position fixes.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `writer`: The writer receiving one line per MAVLink entry.
+/// - `enrichment`: The enrichment invoked for each position fix, such as an `AglEnrichment`.
+///
+/// # Returns
+/// The number of lines written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the lines could not be written.
+#[cfg(feature = "terrain")]
+pub fn write_enriched_lines<P, W>(
+    parser: &mut P,
+    writer: &mut W,
+    enrichment: &mut dyn Enrichment,
+) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Debug,
+    W: Write,
+{
+    let mut lines: u64 = 0;
+    for_each_entry(parser, |entry| {
+        if let Some(line) = to_enriched_line(&entry, enrichment) {
+            writeln!(writer, "{line}")?;
+            lines += 1;
+        }
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(lines)
+}
+
+/// Writes the MAVLink entries of a log as line protocol to a file.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `path`: Path of the file to create.
+///
+/// # Returns
+/// The number of lines written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the file could not be written.
+pub fn export_file<P>(parser: &mut P, path: &str) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Debug,
+{
+    let mut writer = BufWriter::new(std::fs::File::create(path)?);
+    write_lines(parser, &mut writer)
+}
+
+/// Posts the MAVLink entries of a log as line protocol to an HTTP endpoint.
+///
+/// Lines are sent in batches of `HTTP_BATCH_LINES`, one request per batch. Only plain HTTP is
+/// supported, a TLS endpoint must be reached through a local proxy.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `url`: The write endpoint with its query, such as
+///   `http://localhost:8086/api/v2/write?bucket=flights&precision=ns` for InfluxDB or
+///   `http://localhost:8428/write` for VictoriaMetrics.
+/// - `token`: The InfluxDB API token sent in the `Authorization` header, if the endpoint needs
+///   one.
+///
+/// # Returns
+/// The number of lines sent.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read, the URL is invalid, the endpoint could
+/// not be reached or it rejected a batch.
+pub fn export_http<P>(parser: &mut P, url: &str, token: Option<&str>) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Debug,
+{
+    let endpoint = Endpoint::parse(url)?;
+    let mut batch = String::new();
+    let mut batch_lines: usize = 0;
+    let mut lines: u64 = 0;
+    for_each_entry(parser, |entry| {
+        let Some(line) = to_line(&entry) else {
+            return Ok(());
+        };
+        batch.push_str(&line);
+        batch.push('\n');
+        batch_lines += 1;
+        lines += 1;
+        if batch_lines == HTTP_BATCH_LINES {
+            endpoint.post(&batch, token)?;
+            batch.clear();
+            batch_lines = 0;
+        }
+        Ok(())
+    })?;
+    if batch_lines > 0 {
+        endpoint.post(&batch, token)?;
+    }
+    Ok(lines)
+}
+
+/// An HTTP endpoint parsed from a URL.
+struct Endpoint {
+    /// Host and port to connect to.
+    address: String,
+    /// Host sent in the `Host` header.
+    host: String,
+    /// Path and query of the request.
+    target: String,
+}
+
+impl Endpoint {
+    /// Parses an `http://host[:port][/path][?query]` URL.
+    fn parse(url: &str) -> std::io::Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidInput, "only http:// URLs are supported")
+        })?;
+        let (host, target) = match rest.find(['/', '?']) {
+            Some(start) if rest[start..].starts_with('?') => {
+                (&rest[..start], format!("/{}", &rest[start..]))
+            }
+            Some(start) => (&rest[..start], rest[start..].to_string()),
+            None => (rest, String::from("/")),
+        };
+        if host.is_empty() {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "URL has no host",
+            ));
+        }
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:80")
+        };
+        Ok(Self {
+            address,
+            host: host.to_string(),
+            target,
+        })
+    }
+
+    /// Posts a batch of lines and checks the endpoint accepted it.
+    fn post(&self, body: &str, token: Option<&str>) -> std::io::Result<()> {
+        let mut stream = TcpStream::connect(&self.address)?;
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: {}\r\nConnection: close\r\n",
+            self.target,
+            self.host,
+            body.len()
+        );
+        if let Some(token) = token {
+            write!(request, "Authorization: Token {token}\r\n").unwrap();
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+        stream.write_all(body.as_bytes())?;
+        stream.flush()?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        match status_line.split(' ').nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => Err(std::io::Error::other(format!(
+                "endpoint rejected line protocol: {status_line}"
+            ))),
+        }
+    }
+}
+
+/// Extracts the fields of a message from its `Debug` representation.
+///
+/// # Arguments
+/// - `debug`: The message formatted as `NAME(NAME_DATA { field: value, ... })`.
+///
+/// # Returns
+/// The escaped field keys and formatted values.
+fn message_fields(debug: &str) -> Vec<(String, String)> {
+    fields::debug_fields(debug)
+        .into_iter()
+        .filter_map(|(name, value)| Some((escape_key(&name), format_value(&value)?)))
+        .collect()
+}
+
+/// Formats a field value, or returns `None` for non finite floats.
+fn format_value(value: &str) -> Option<String> {
+    let formatted = if value == "true" || value == "false" {
+        value.to_string()
+    } else if value.parse::<i64>().is_ok() {
+        format!("{value}i")
+    } else if let Ok(float) = value.parse::<f64>() {
+        if !float.is_finite() {
+            return None;
+        }
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    };
+    Some(formatted)
+}
+
+/// Escapes the characters of a field key that are special in line protocol.
+fn escape_key(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for c in key.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::net::TcpListener;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{ATTITUDE_DATA, MavMessage};
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn attitude(timestamp: u64) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 2,
+                sequence: 0,
+            }),
+            mav_message: Some(MavMessage::ATTITUDE(ATTITUDE_DATA {
+                time_boot_ms: 1500,
+                roll: 0.5,
+                pitch: f32::NAN,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// Test that a message becomes a line with its tags, typed fields and nanosecond timestamp.
+    #[test]
+    fn test_to_line() {
+        let line = to_line(&attitude(1_000_000)).unwrap();
+        assert!(line.starts_with("ATTITUDE,sysid=1,compid=2 time_boot_ms=1500i,roll=0.5,"));
+        assert!(!line.contains("pitch"));
+        assert!(line.ends_with(" 1000000000"));
+
+        let heartbeat = to_line(&LogEntry::<MavMessage> {
+            mav_message: Some(MavMessage::HEARTBEAT(Default::default())),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(heartbeat.starts_with("HEARTBEAT "));
+        assert!(heartbeat.contains("custom_mode=0i"));
+        assert!(heartbeat.contains("mavtype=\"MAV_TYPE_"));
+
+        let text = LogEntry::<MavMessage> {
+            text: Some(String::from("note")),
+            ..Default::default()
+        };
+        assert_eq!(to_line(&text), None);
+        assert_eq!(
+            message_fields("X(X_DATA { values: [1, 2.5] })"),
+            vec![
+                (String::from("values_0"), String::from("1i")),
+                (String::from("values_1"), String::from("2.5")),
+            ]
+        );
+    }
+
+    /// Test that derived channels are written as float fields of the messages having their
+    /// variables.
+    #[test]
+    fn test_to_derived_line() {
+        let derived = [
+            DerivedChannel::parse("roll_deg = degrees(roll)").unwrap(),
+            DerivedChannel::parse("tilt = hypot(roll, pitch)").unwrap(),
+            DerivedChannel::parse("speed = hypot(vx, vy)").unwrap(),
+            DerivedChannel::parse("boot_s = ATTITUDE.time_boot_ms / 1000").unwrap(),
+        ];
+        let line = to_derived_line(&attitude(1_000_000), &derived).unwrap();
+        let fields = line.split(' ').nth(1).unwrap();
+        // pitch is NaN, so tilt is left out
+        assert!(fields.ends_with(&format!(",roll_deg={:?},boot_s=1.5", 0.5f64.to_degrees())));
+        assert!(!line.contains("speed"));
+        assert_eq!(to_derived_line(&attitude(0), &[]), to_line(&attitude(0)));
+    }
+
+    /// Test that position fixes are written with the fields of an enrichment.
+    #[cfg(feature = "terrain")]
+    #[test]
+    fn test_to_enriched_line() {
+        use crate::terrain::AglEnrichment;
+
+        let mut enrichment =
+            AglEnrichment::new(|_latitude_deg: f64, _longitude_deg: f64| Some(500.0));
+        let position = LogEntry::<MavMessage> {
+            mav_header: attitude(0).mav_header,
+            mav_message: Some(MavMessage::GLOBAL_POSITION_INT(
+                mavlink::common::GLOBAL_POSITION_INT_DATA {
+                    lat: 470_000_000,
+                    lon: 80_000_000,
+                    alt: 520_000,
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
+        };
+        let line = to_enriched_line(&position, &mut enrichment).unwrap();
+        assert!(line.ends_with(",terrain_alt=500.0,agl=20.0"));
+        assert_eq!(
+            to_enriched_line(&attitude(0), &mut enrichment),
+            to_line(&attitude(0))
+        );
+    }
+
+    /// Test that lines are posted in a single request to an HTTP endpoint.
+    #[test]
+    fn test_export_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/api/v2/write?bucket=flights&precision=ns",
+            listener.local_addr().unwrap()
+        );
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            // read until the whole body announced by Content-Length arrived
+            loop {
+                let len = stream.read(&mut buf).unwrap();
+                if len == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..len]);
+                let text = String::from_utf8_lossy(&request).into_owned();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let mut parser = EntryList(VecDeque::from([attitude(1_000_000), attitude(2_000_000)]));
+        let lines = export_http(&mut parser, &url, Some("secret")).unwrap();
+        assert_eq!(lines, 2);
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /api/v2/write?bucket=flights&precision=ns HTTP/1.1\r\n"));
+        assert!(request.contains("Authorization: Token secret\r\n"));
+        let body = request.split_once("\r\n\r\n").unwrap().1;
+        assert_eq!(body.lines().count(), 2);
+        assert!(body.ends_with(" 2000000000\n"));
+    }
+}
@@ -0,0 +1,144 @@
+//! Interceptors applied to entries before a parser returns them.
+//!
+//! `Intercepted` wraps any parser and runs a chain of interceptors on every entry it reads.
+//! Interceptors may rewrite an entry, such as correcting its timestamp, or drop it, so concerns
+//! like time correction or filtering compose without changing the parsers or their callers.
+use mavlink::Message;
+use mavlink::error::MessageReadError;
+
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// What a parser does with an entry after an interceptor ran on it.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Verdict {
+    /// Passes the entry, as modified by the interceptor, to the next interceptor and then to the
+    /// caller.
+    Pass,
+    /// Discards the entry. Later interceptors do not see it.
+    Drop,
+}
+
+/// An interceptor run on every entry read by an `Intercepted` parser.
+pub type Interceptor<M> = Box<dyn FnMut(&mut LogEntry<M>) -> Verdict + Send>;
+
+/// Parser running interceptors on the entries of another parser.
+///
+/// Errors of the wrapped parser are returned unchanged without running the interceptors.
+///
+/// # Type Parameters
+/// - `P`: The wrapped parser.
+pub struct Intercepted<P: MavParser> {
+    parser: P,
+    interceptors: Vec<Interceptor<P::M>>,
+}
+
+impl<P: MavParser> Intercepted<P> {
+    /// Creates a new `Intercepted` parser without interceptors.
+    pub fn new(parser: P) -> Self {
+        Self {
+            parser,
+            interceptors: Vec::new(),
+        }
+    }
+
+    /// Adds an interceptor run on every entry after those added before it.
+    ///
+    /// # Arguments
+    /// - `interceptor`: The interceptor to add.
+    ///
+    /// # Returns
+    /// The parser with the interceptor added.
+    pub fn with_interceptor(
+        mut self,
+        interceptor: impl FnMut(&mut LogEntry<P::M>) -> Verdict + Send + 'static,
+    ) -> Self {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+
+    /// Returns the wrapped parser.
+    pub fn into_inner(self) -> P {
+        self.parser
+    }
+}
+
+impl<P: MavParser> MavParser for Intercepted<P> {
+    type M = P::M;
+
+    fn parse_next_entry(&mut self) -> Result<LogEntry<P::M>, MessageReadError> {
+        'entries: loop {
+            let mut entry = self.parser.parse_next_entry()?;
+            for interceptor in self.interceptors.iter_mut() {
+                if interceptor(&mut entry) == Verdict::Drop {
+                    continue 'entries;
+                }
+            }
+            return Ok(entry);
+        }
+    }
+}
+
+/// Returns an interceptor shifting entry timestamps by a fixed offset.
+///
+/// Corrects logs recorded with a clock known to be off, such as an unset system clock.
+/// Timestamps are clamped to 0 rather than going negative.
+///
+/// # Arguments
+/// - `offset_us`: The offset added to every timestamp, in microseconds.
+pub fn shift_timestamps<M: Message>(
+    offset_us: i64,
+) -> impl FnMut(&mut LogEntry<M>) -> Verdict + Send + 'static {
+    move |entry| {
+        entry.timestamp = entry
+            .timestamp
+            .map(|timestamp| timestamp.saturating_add_signed(offset_us));
+        Verdict::Pass
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::MavMessage;
+
+    use super::*;
+    use crate::mav_parser::{EntryList, for_each_entry};
+
+    /// Test that interceptors run in order and that dropped entries are skipped.
+    #[test]
+    fn test_interceptors() {
+        let entries = (0..6u64)
+            .map(|i| LogEntry {
+                timestamp: Some(i * 1_000),
+                text: Some(format!("entry {i}")),
+                ..Default::default()
+            })
+            .collect();
+        let mut seen = 0;
+        let mut parser = Intercepted::new(EntryList::<MavMessage>(entries))
+            .with_interceptor(shift_timestamps(-2_000))
+            .with_interceptor(|entry| match entry.timestamp {
+                Some(0) => Verdict::Drop,
+                _ => Verdict::Pass,
+            })
+            .with_interceptor(move |entry| {
+                seen += 1;
+                entry.text = Some(format!("{} of {seen}", entry.text.as_ref().unwrap()));
+                Verdict::Pass
+            });
+
+        let mut result = Vec::new();
+        for_each_entry(&mut parser, |entry| {
+            result.push((entry.timestamp.unwrap(), entry.text.unwrap()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            result,
+            vec![
+                (1_000, String::from("entry 3 of 1")),
+                (2_000, String::from("entry 4 of 2")),
+                (3_000, String::from("entry 5 of 3")),
+            ]
+        );
+    }
+}
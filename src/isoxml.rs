@@ -0,0 +1,493 @@
+//! Export of flight application data as ISO 11783-10 (ISOXML) task data.
+//!
+//! Farm management systems import the work done by machines as ISOXML TimeLogs: a binary file of
+//! position records holding the values of process data variables, described by an XML header.
+//! `export_taskdata` converts the positions and actuator events of a log into a task with a
+//! single TimeLog, so that spraying and seeding flights can be ingested like the jobs of ground
+//! machines.
+//!
+//! The vehicle is described as a device with one section per actuator, each reporting its actual
+//! work state and application rate. The `TASKDATA.XML` file is written next to the TimeLog files
+//! and the directory can be copied as is to the `TASKDATA` directory of a transfer medium.
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use mavlink::Message;
+
+use crate::fields;
+use crate::mav_parser::{ActuatorEvent, MavParser, for_each_entry};
+
+/// DDI of the actual work state of a section, 1 when applying and 0 otherwise.
+pub const ACTUAL_WORK_STATE_DDI: u16 = 141;
+/// DDI of the setpoint volume per area application rate in mm³/m².
+pub const SETPOINT_VOLUME_PER_AREA_DDI: u16 = 1;
+/// DDI of the setpoint mass per area application rate in mg/m².
+pub const SETPOINT_MASS_PER_AREA_DDI: u16 = 6;
+
+/// Name of the TimeLog files, without extension.
+const TIMELOG_NAME: &str = "TLG00001";
+/// Position status of records when the log has no GPS_RAW_INT message.
+const STATUS_NOT_AVAILABLE: u8 = 15;
+/// Days between the Unix epoch and 1980-01-01, the epoch of TimeLog dates.
+const DAYS_TO_1980: u64 = 3652;
+/// Object id of the first process data description of the device.
+const FIRST_DPD_OBJECT_ID: u16 = 1000;
+
+/// Configuration of an ISOXML export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsoxmlConfig {
+    /// Designator of the task shown by farm management software.
+    pub task_name: String,
+    /// System id of the vehicle carrying the actuators, or `None` to use the positions of any
+    /// system.
+    pub system_id: Option<u8>,
+    /// DDI the application rates are reported with, such as `SETPOINT_VOLUME_PER_AREA_DDI`.
+    pub rate_ddi: u16,
+    /// Factor converting the rates of the actuator events to the unit of the DDI, such as 100
+    /// for rates logged in l/ha and reported in mm³/m².
+    pub rate_scale: f64,
+    /// Shortest time in microseconds between two records. A record is always written at the
+    /// first position following an actuator event.
+    pub min_interval_us: u64,
+}
+
+impl Default for IsoxmlConfig {
+    /// Provides a task reporting rates logged in l/ha as volume per area rates, with up to 5
+    /// records per second from the positions of any system.
+    fn default() -> Self {
+        IsoxmlConfig {
+            task_name: String::from("MAVLink flight"),
+            system_id: None,
+            rate_ddi: SETPOINT_VOLUME_PER_AREA_DDI,
+            rate_scale: 100.0,
+            min_interval_us: 200_000,
+        }
+    }
+}
+
+/// A position of the vehicle with the state of its actuators.
+struct TimeLogRecord {
+    timestamp_us: u64,
+    /// Latitude in 1e-7 degrees.
+    north: i32,
+    /// Longitude in 1e-7 degrees.
+    east: i32,
+    /// Altitude above mean sea level in millimeters.
+    up: i32,
+    status: u8,
+    /// The last event of each actuator at the time of the position.
+    actuators: Vec<ActuatorEvent>,
+}
+
+/// Reads a full log and writes its positions and actuator events as an ISOXML task.
+///
+/// A record is written for GLOBAL_POSITION_INT messages with a timestamp, at most once per
+/// minimum interval. The position status is taken from the fix type of the last GPS_RAW_INT
+/// message. Actuators report a work state and rate of 0 before their first event and while they
+/// are off.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `dir`: The directory to write `TASKDATA.XML`, `TLG00001.XML` and `TLG00001.BIN` to. It is
+///   created if it does not exist.
+/// - `config`: The task name, the vehicle to follow and how rates are reported.
+///
+/// # Returns
+/// The number of records written to the TimeLog.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or a file could not be written, or an
+/// error of kind `InvalidInput` if the log has more than 127 actuators, which do not fit the
+/// process data variables of a TimeLog.
+pub fn export_taskdata<P: MavParser + ?Sized>(
+    parser: &mut P,
+    dir: &Path,
+    config: &IsoxmlConfig,
+) -> std::io::Result<u64> {
+    let mut actuators: BTreeMap<u8, ActuatorEvent> = BTreeMap::new();
+    let mut records: Vec<TimeLogRecord> = Vec::new();
+    let mut status = STATUS_NOT_AVAILABLE;
+    let mut changed = false;
+    for_each_entry(parser, |entry| {
+        if let Some(event) = entry.actuator {
+            actuators.insert(event.actuator, event);
+            changed = true;
+            return Ok(());
+        }
+        let (Some(header), Some(msg), Some(timestamp_us)) =
+            (&entry.mav_header, &entry.mav_message, entry.timestamp)
+        else {
+            return Ok(());
+        };
+        if config
+            .system_id
+            .is_some_and(|system_id| system_id != header.system_id)
+        {
+            return Ok(());
+        }
+        let payload = fields::payload(msg);
+        match msg.message_id() {
+            fields::GPS_RAW_INT_ID => status = position_status(fields::read_u8(&payload, 28)),
+            fields::GLOBAL_POSITION_INT_ID => {
+                let north = fields::read_i32(&payload, 4);
+                let east = fields::read_i32(&payload, 8);
+                let due = changed
+                    || records.last().is_none_or(|last| {
+                        timestamp_us.saturating_sub(last.timestamp_us) >= config.min_interval_us
+                    });
+                if (north != 0 || east != 0) && due {
+                    records.push(TimeLogRecord {
+                        timestamp_us,
+                        north,
+                        east,
+                        up: fields::read_i32(&payload, 12),
+                        status,
+                        actuators: actuators.values().copied().collect(),
+                    });
+                    changed = false;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    let sections: Vec<u8> = actuators.keys().copied().collect();
+    if sections.len() > 127 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "ISOXML TimeLogs hold the work state and rate of at most 127 actuators",
+        ));
+    }
+    std::fs::create_dir_all(dir)?;
+    let span = records
+        .first()
+        .zip(records.last())
+        .map(|(first, last)| (first.timestamp_us, last.timestamp_us));
+    std::fs::write(
+        dir.join("TASKDATA.XML"),
+        taskdata_xml(config, &sections, span),
+    )?;
+    std::fs::write(
+        dir.join(format!("{TIMELOG_NAME}.XML")),
+        timelog_header(config, sections.len()),
+    )?;
+    let mut binary: Vec<u8> = Vec::new();
+    for record in &records {
+        encode_record(record, &sections, config.rate_scale, &mut binary);
+    }
+    std::fs::write(dir.join(format!("{TIMELOG_NAME}.BIN")), binary)?;
+    Ok(records.len() as u64)
+}
+
+/// Converts a MAVLink GPS_FIX_TYPE to an ISO 11783 position status.
+fn position_status(fix_type: u8) -> u8 {
+    match fix_type {
+        0 | 1 => 0,
+        2 | 3 => 1,
+        4 => 2,
+        // RTK fixed and float are numbered the other way around
+        5 => 5,
+        6 => 4,
+        7 => 7,
+        8 => 3,
+        _ => STATUS_NOT_AVAILABLE,
+    }
+}
+
+/// Returns the year, month and day of a number of days since the Unix epoch.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+/// Formats a timestamp as an XML date time in UTC with milliseconds.
+fn xml_date_time(timestamp_us: u64) -> String {
+    let ms = timestamp_us / 1000;
+    let (year, month, day) = civil_from_days(ms / 86_400_000);
+    let ms_of_day = ms % 86_400_000;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1000 % 60,
+        ms_of_day % 1000
+    )
+}
+
+/// Escapes the characters of a text that cannot appear in an XML attribute value.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns the id of the device element of a section, element 1 being the vehicle.
+fn section_element_id(index: usize) -> String {
+    format!("DET-{}", index + 2)
+}
+
+/// Returns the `TASKDATA.XML` content describing the device and the task.
+///
+/// # Arguments
+/// - `config`: The export configuration.
+/// - `sections`: The ids of the actuators in process data order.
+/// - `span`: The timestamps of the first and last records, if any.
+fn taskdata_xml(config: &IsoxmlConfig, sections: &[u8], span: Option<(u64, u64)>) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    write!(
+        xml,
+        "<ISO11783_TaskData VersionMajor=\"4\" VersionMinor=\"3\" \
+         ManagementSoftwareManufacturer=\"\" ManagementSoftwareVersion=\"\" \
+         TaskControllerManufacturer=\"mavlink_log\" TaskControllerVersion=\"{}\" \
+         DataTransferOrigin=\"2\">\n",
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+    // a device without a NAME or structure and localization labels of its own, localized to
+    // English with metric units
+    write!(
+        xml,
+        "<DVC A=\"DVC-1\" B=\"mavlink_log\" C=\"{}\" D=\"0000000000000000\" \
+         F=\"00000000000000\" G=\"FF000000006E65\">\n",
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+    xml.push_str("<DET A=\"DET-1\" B=\"1\" C=\"1\" D=\"Vehicle\" E=\"0\" F=\"0\"/>\n");
+    for (index, actuator) in sections.iter().enumerate() {
+        writeln!(
+            xml,
+            "<DET A=\"{}\" B=\"{}\" C=\"4\" D=\"Actuator {actuator}\" E=\"{}\" F=\"1\">\
+             <DOR A=\"{FIRST_DPD_OBJECT_ID}\"/><DOR A=\"{}\"/></DET>",
+            section_element_id(index),
+            index + 2,
+            index + 1,
+            FIRST_DPD_OBJECT_ID + 1
+        )
+        .unwrap();
+    }
+    writeln!(
+        xml,
+        "<DPD A=\"{FIRST_DPD_OBJECT_ID}\" B=\"{ACTUAL_WORK_STATE_DDI:04X}\" C=\"1\" D=\"8\" \
+         E=\"Actual work state\"/>"
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "<DPD A=\"{}\" B=\"{:04X}\" C=\"1\" D=\"8\" E=\"Application rate\"/>",
+        FIRST_DPD_OBJECT_ID + 1,
+        config.rate_ddi
+    )
+    .unwrap();
+    xml.push_str("</DVC>\n");
+    writeln!(
+        xml,
+        "<TSK A=\"TSK-1\" B=\"{}\" G=\"4\">",
+        escape_xml(&config.task_name)
+    )
+    .unwrap();
+    if let Some((start_us, end_us)) = span {
+        writeln!(
+            xml,
+            "<TIM A=\"{}\" B=\"{}\" D=\"4\"/>",
+            xml_date_time(start_us),
+            xml_date_time(end_us)
+        )
+        .unwrap();
+    }
+    xml.push_str("<DAN A=\"0000000000000000\" C=\"DVC-1\"/>\n");
+    writeln!(xml, "<TLG A=\"{TIMELOG_NAME}\"/>").unwrap();
+    xml.push_str("</TSK>\n</ISO11783_TaskData>\n");
+    xml
+}
+
+/// Returns the TimeLog header declaring the fields of the binary records.
+///
+/// The time, position and process data values are left empty, meaning every record holds them.
+/// The work state and rate of each section are declared in turn.
+fn timelog_header(config: &IsoxmlConfig, section_count: usize) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<TIM A=\"\" D=\"4\">\n<PTN A=\"\" B=\"\" C=\"\" D=\"\"/>\n");
+    for index in 0..section_count {
+        let element = section_element_id(index);
+        writeln!(
+            xml,
+            "<DLV A=\"{ACTUAL_WORK_STATE_DDI:04X}\" B=\"\" C=\"{element}\"/>"
+        )
+        .unwrap();
+        writeln!(
+            xml,
+            "<DLV A=\"{:04X}\" B=\"\" C=\"{element}\"/>",
+            config.rate_ddi
+        )
+        .unwrap();
+    }
+    xml.push_str("</TIM>\n");
+    xml
+}
+
+/// Appends a record to the binary TimeLog.
+///
+/// # Arguments
+/// - `record`: The position and actuator states to encode.
+/// - `sections`: The ids of the actuators in process data order.
+/// - `rate_scale`: The factor converting rates to the unit of the rate DDI.
+/// - `binary`: The TimeLog content the record is appended to.
+fn encode_record(record: &TimeLogRecord, sections: &[u8], rate_scale: f64, binary: &mut Vec<u8>) {
+    let ms = record.timestamp_us / 1000;
+    let days = (ms / 86_400_000).saturating_sub(DAYS_TO_1980);
+    binary.extend_from_slice(&((ms % 86_400_000) as u32).to_le_bytes());
+    binary.extend_from_slice(&(days as u16).to_le_bytes());
+    binary.extend_from_slice(&record.north.to_le_bytes());
+    binary.extend_from_slice(&record.east.to_le_bytes());
+    binary.extend_from_slice(&record.up.to_le_bytes());
+    binary.push(record.status);
+    binary.push((sections.len() * 2) as u8);
+    for (index, actuator) in sections.iter().enumerate() {
+        let (active, rate): (i32, i32) = match record
+            .actuators
+            .iter()
+            .find(|event| event.actuator == *actuator)
+        {
+            Some(event) if event.active => (1, (event.rate as f64 * rate_scale).round() as i32),
+            _ => (0, 0),
+        };
+        binary.push((index * 2) as u8);
+        binary.extend_from_slice(&active.to_le_bytes());
+        binary.push((index * 2 + 1) as u8);
+        binary.extend_from_slice(&rate.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{GLOBAL_POSITION_INT_DATA, GPS_RAW_INT_DATA, GpsFixType, MavMessage};
+
+    use super::*;
+    use crate::mav_parser::{EntryList, LogEntry};
+
+    const START_US: u64 = 1_700_000_000_000_000;
+
+    /// Returns a MAVLink entry of system 1.
+    fn mavlink(timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a position `north` times 1e-4 degrees north of 47, 8 at an altitude of 500 m.
+    fn position(timestamp: u64, north: i32) -> LogEntry<MavMessage> {
+        mavlink(
+            timestamp,
+            MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                lat: 470_000_000 + north * 1000,
+                lon: 80_000_000,
+                alt: 500_000,
+                ..Default::default()
+            }),
+        )
+    }
+
+    /// Returns an actuator event.
+    fn event(timestamp: u64, actuator: u8, active: bool, rate: f32) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            actuator: Some(ActuatorEvent {
+                actuator,
+                active,
+                rate,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Test that dates are converted from days since the Unix epoch.
+    #[test]
+    fn test_xml_date_time() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(DAYS_TO_1980), (1980, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(xml_date_time(START_US + 5_000), "2023-11-14T22:13:20.005Z");
+    }
+
+    /// Test that positions and actuator states are written as TimeLog records.
+    #[test]
+    fn test_export_taskdata() {
+        let entries = VecDeque::from([
+            mavlink(
+                START_US,
+                MavMessage::GPS_RAW_INT(GPS_RAW_INT_DATA {
+                    fix_type: GpsFixType::GPS_FIX_TYPE_RTK_FIXED,
+                    ..Default::default()
+                }),
+            ),
+            position(START_US, 0),
+            event(START_US + 100_000, 0, true, 1.5),
+            position(START_US + 150_000, 1),
+            // within the minimum interval
+            position(START_US + 250_000, 2),
+            position(START_US + 400_000, 3),
+            event(START_US + 450_000, 0, false, 0.0),
+            position(START_US + 500_000, 4),
+        ]);
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = IsoxmlConfig {
+            task_name: String::from("Vines & rows"),
+            ..IsoxmlConfig::default()
+        };
+        let records = export_taskdata(&mut EntryList(entries), dir.path(), &config).unwrap();
+        assert_eq!(records, 4);
+
+        let taskdata = std::fs::read_to_string(dir.path().join("TASKDATA.XML")).unwrap();
+        assert!(taskdata.contains(r#"<TSK A="TSK-1" B="Vines &amp; rows" G="4">"#));
+        assert!(
+            taskdata.contains(
+                r#"<TIM A="2023-11-14T22:13:20.000Z" B="2023-11-14T22:13:20.500Z" D="4"/>"#
+            )
+        );
+        assert!(taskdata.contains(r#"<DET A="DET-2" B="2" C="4" D="Actuator 0" E="1" F="1">"#));
+        assert!(taskdata.contains(r#"B="008D""#));
+        assert!(taskdata.contains(r#"<TLG A="TLG00001"/>"#));
+
+        let header = std::fs::read_to_string(dir.path().join("TLG00001.XML")).unwrap();
+        assert!(header.contains(r#"<DLV A="008D" B="" C="DET-2"/>"#));
+        assert!(header.contains(r#"<DLV A="0001" B="" C="DET-2"/>"#));
+
+        let binary = std::fs::read(dir.path().join("TLG00001.BIN")).unwrap();
+        // time, position and status, then 2 process data values
+        let record_len = 6 + 13 + 1 + 2 * 5;
+        assert_eq!(binary.len(), 4 * record_len);
+        let second = &binary[record_len..2 * record_len];
+        assert_eq!(second[..4], 80_000_150u32.to_le_bytes());
+        assert_eq!(second[4..6], 16_023u16.to_le_bytes());
+        assert_eq!(second[6..10], 470_001_000i32.to_le_bytes());
+        assert_eq!(second[14..18], 500_000i32.to_le_bytes());
+        assert_eq!(second[18], 4);
+        assert_eq!(second[19..], [2, 0, 1, 0, 0, 0, 1, 150, 0, 0, 0]);
+        assert_eq!(
+            binary[3 * record_len + 19..],
+            [2, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]
+        );
+    }
+}
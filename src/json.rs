@@ -0,0 +1,231 @@
+//! Export of MAVLink messages as JSON following the mavlink2rest conventions.
+//!
+//! Each MAVLink entry becomes one JSON object holding the MAVLink `header` and the `message`,
+//! structured exactly like the messages mavlink2rest serves and accepts: the message name is the
+//! `type` of the message, followed by its fields, and enum values are objects holding their name
+//! as `type`. Web frontends already consuming mavlink2rest can read exported logs without a new
+//! codec. The entry timestamp, if any, is added as `timestamp_us`.
+//!
+//! ```json
+//! {"header":{"system_id":1,"component_id":1,"sequence":0},"message":{"type":"HEARTBEAT",...},"timestamp_us":1700000000000000}
+//! ```
+//!
+//! Channels computed from the message fields, see `DerivedChannel`, are added as a `derived`
+//! object by `to_derived_json` and `write_derived_json_lines`.
+use std::io::{BufWriter, Write};
+
+use mavlink::Message;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::derive::{DerivedChannel, evaluate_fields};
+use crate::mav_parser::{LogEntry, MavParser, for_each_entry};
+
+/// Converts a MAVLink entry to a mavlink2rest JSON object.
+///
+/// Entries without a MAVLink header are given a header of zeros, as mavlink2rest requires one.
+///
+/// # Arguments
+/// - `entry`: The entry to convert.
+///
+/// # Returns
+/// The JSON object, or `None` if the entry is not a MAVLink message.
+pub fn to_json<M: Message + Serialize>(entry: &LogEntry<M>) -> Option<Value> {
+    to_derived_json(entry, &[])
+}
+
+/// Converts a MAVLink entry to a mavlink2rest JSON object with derived channels.
+///
+/// The variables of the channels are the numeric and boolean fields of the message, array
+/// elements being named after the array suffixed with `_` and their index. Channels reading a
+/// field the message does not have are left out, and the others are added to a `derived`
+/// object, non finite values as `null`.
+///
+/// # Arguments
+/// - `entry`: The entry to convert.
+/// - `derived`: The channels to compute, such as one parsed from `speed = hypot(vx, vy, vz)`.
+///
+/// # Returns
+/// The JSON object, or `None` if the entry is not a MAVLink message.
+pub fn to_derived_json<M: Message + Serialize>(
+    entry: &LogEntry<M>,
+    derived: &[DerivedChannel],
+) -> Option<Value> {
+    let msg = entry.mav_message.as_ref()?;
+    let header = entry.mav_header.unwrap_or_default();
+    let mut object = json!({
+        "header": {
+            "system_id": header.system_id,
+            "component_id": header.component_id,
+            "sequence": header.sequence,
+        },
+        "message": serde_json::to_value(msg).ok()?,
+    });
+    if let Some(timestamp) = entry.timestamp {
+        object["timestamp_us"] = json!(timestamp);
+    }
+    let values = evaluate_fields(derived, msg.message_name(), &|name: &str| {
+        field_value(&object["message"], name)
+    });
+    if !values.is_empty() {
+        object["derived"] = values.into_iter().collect();
+    }
+    Some(object)
+}
+
+/// Returns the value of a numeric or boolean field of a serialized message.
+fn field_value(message: &Value, name: &str) -> Option<f64> {
+    let value = match message.get(name) {
+        Some(value) => value,
+        None => {
+            let (array, index) = name.rsplit_once('_')?;
+            message.get(array)?.get(index.parse::<usize>().ok()?)?
+        }
+    };
+    value.as_f64().or_else(|| value.as_bool().map(f64::from))
+}
+
+/// Writes the MAVLink entries of a log as JSON lines, one mavlink2rest object per line.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `writer`: The writer receiving one line per MAVLink entry.
+///
+/// # Returns
+/// The number of messages written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the lines could not be written.
+pub fn write_json_lines<P, W>(parser: &mut P, writer: &mut W) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Serialize,
+    W: Write,
+{
+    write_derived_json_lines(parser, writer, &[])
+}
+
+/// Writes the MAVLink entries of a log as JSON lines with derived channels, see
+/// `to_derived_json`.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `writer`: The writer receiving one line per MAVLink entry.
+/// - `derived`: The channels to compute from the fields of each message.
+///
+/// # Returns
+/// The number of messages written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the lines could not be written.
+pub fn write_derived_json_lines<P, W>(
+    parser: &mut P,
+    writer: &mut W,
+    derived: &[DerivedChannel],
+) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Serialize,
+    W: Write,
+{
+    let mut written: u64 = 0;
+    for_each_entry(parser, |entry| {
+        if let Some(object) = to_derived_json(&entry, derived) {
+            serde_json::to_writer(&mut *writer, &object)?;
+            writer.write_all(b"\n")?;
+            written += 1;
+        }
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Writes the MAVLink entries of a log as JSON lines to a file.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `path`: Path of the file to create.
+///
+/// # Returns
+/// The number of messages written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the file could not be written.
+pub fn export_json<P>(parser: &mut P, path: &str) -> std::io::Result<u64>
+where
+    P: MavParser + ?Sized,
+    P::M: Serialize,
+{
+    let mut writer = BufWriter::new(std::fs::File::create(path)?);
+    write_json_lines(parser, &mut writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::MavHeader;
+    use mavlink::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+
+    use super::*;
+
+    /// Test that messages are structured like those of mavlink2rest.
+    #[test]
+    fn test_to_json() {
+        let entry = LogEntry {
+            timestamp: Some(1_000_000),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 2,
+                sequence: 3,
+            }),
+            mav_message: Some(MavMessage::HEARTBEAT(Default::default())),
+            ..Default::default()
+        };
+        let object = to_json(&entry).unwrap();
+        assert_eq!(
+            object["header"],
+            json!({"system_id": 1, "component_id": 2, "sequence": 3})
+        );
+        assert_eq!(object["message"]["type"], "HEARTBEAT");
+        assert_eq!(object["message"]["custom_mode"], 0);
+        assert_eq!(object["message"]["mavtype"]["type"], "MAV_TYPE_GENERIC");
+        assert_eq!(object["timestamp_us"], 1_000_000);
+
+        let text = LogEntry::<MavMessage> {
+            text: Some(String::from("note")),
+            ..Default::default()
+        };
+        assert_eq!(to_json(&text), None);
+    }
+
+    /// Test that derived channels are computed from the fields of the messages having them.
+    #[test]
+    fn test_to_derived_json() {
+        let derived = [
+            DerivedChannel::parse("speed = hypot(vx, vy, vz) / 100").unwrap(),
+            DerivedChannel::parse("alt_m = GLOBAL_POSITION_INT.relative_alt / 1000").unwrap(),
+            DerivedChannel::parse("mode = custom_mode + 1").unwrap(),
+        ];
+        let position = LogEntry {
+            mav_message: Some(MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                relative_alt: 12_500,
+                vx: 300,
+                vy: -400,
+                vz: 1200,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let object = to_derived_json(&position, &derived).unwrap();
+        assert_eq!(object["derived"], json!({"speed": 13.0, "alt_m": 12.5}));
+        assert_eq!(object["message"]["type"], "GLOBAL_POSITION_INT");
+
+        let heartbeat = LogEntry {
+            mav_message: Some(MavMessage::HEARTBEAT(Default::default())),
+            ..Default::default()
+        };
+        let object = to_derived_json(&heartbeat, &derived).unwrap();
+        assert_eq!(object["derived"], json!({"mode": 1.0}));
+        assert!(to_json(&heartbeat).unwrap().get("derived").is_none());
+    }
+}
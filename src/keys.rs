@@ -0,0 +1,168 @@
+//! This module provides access to the keys used to encrypt and sign log files.
+//!
+//! Log files record the id of the key they were written with rather than the key itself. Parsing
+//! a log looks the key up by that id through a `KeyProvider`, so keys can be rotated for new logs
+//! while the keys of historical logs stay available.
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Supplies 256 bit keys by key id.
+///
+/// For encryption the key is the AES-256 key. For signing it is the Ed25519 secret key when
+/// signing and the Ed25519 public key when verifying.
+pub trait KeyProvider {
+    /// Looks up a key.
+    ///
+    /// # Arguments
+    /// - `key_id`: The id of the key as recorded in the log file header.
+    ///
+    /// # Returns
+    /// The key, or `None` if no key with this id is available.
+    fn key(&self, key_id: &str) -> Option<[u8; 32]>;
+
+    /// Returns the id of the key new logs should be written with, if the provider designates
+    /// one. Retired keys remain available through `key` for reading older logs.
+    fn current_key_id(&self) -> Option<String> {
+        None
+    }
+}
+
+/// A key provider holding its keys in memory.
+#[derive(Default)]
+pub struct InMemoryKeyProvider {
+    keys: BTreeMap<String, [u8; 32]>,
+    current: Option<String>,
+}
+
+impl InMemoryKeyProvider {
+    /// Creates an empty `InMemoryKeyProvider`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a key, replacing any key with the same id.
+    ///
+    /// # Arguments
+    /// - `key_id`: The id of the key.
+    /// - `key`: The key.
+    pub fn insert(&mut self, key_id: &str, key: [u8; 32]) {
+        self.keys.insert(String::from(key_id), key);
+    }
+
+    /// Designates the key new logs should be written with.
+    ///
+    /// # Arguments
+    /// - `key_id`: The id of a key previously added with `insert`.
+    ///
+    /// # Returns
+    /// `true` if the key is known and is now current, `false` otherwise.
+    pub fn set_current(&mut self, key_id: &str) -> bool {
+        if !self.keys.contains_key(key_id) {
+            return false;
+        }
+        self.current = Some(String::from(key_id));
+        true
+    }
+}
+
+impl KeyProvider for InMemoryKeyProvider {
+    fn key(&self, key_id: &str) -> Option<[u8; 32]> {
+        self.keys.get(key_id).copied()
+    }
+
+    fn current_key_id(&self) -> Option<String> {
+        self.current.clone()
+    }
+}
+
+/// A key provider reading keys from a directory.
+///
+/// Each key is stored in a file named `<key_id>.key` holding either the 32 raw key bytes or the
+/// key as 64 hexadecimal characters. The current key is named by the content of an optional
+/// `current` file in the same directory. Keys are read on every lookup so keys added to the
+/// directory become available without restarting.
+pub struct FileKeyProvider {
+    directory: PathBuf,
+}
+
+impl FileKeyProvider {
+    /// Creates a new `FileKeyProvider` for a key directory.
+    ///
+    /// # Arguments
+    /// - `directory`: The directory holding the key files.
+    pub fn new(directory: &str) -> Self {
+        Self {
+            directory: PathBuf::from(directory),
+        }
+    }
+}
+
+impl KeyProvider for FileKeyProvider {
+    fn key(&self, key_id: &str) -> Option<[u8; 32]> {
+        // Key ids must not reach outside of the key directory
+        if key_id.is_empty() || key_id.contains(['/', '\\']) || key_id.starts_with('.') {
+            return None;
+        }
+        let content = std::fs::read(self.directory.join(format!("{key_id}.key"))).ok()?;
+        if let Ok(key) = content.as_slice().try_into() {
+            return Some(key);
+        }
+        parse_hex_key(std::str::from_utf8(&content).ok()?.trim())
+    }
+
+    fn current_key_id(&self) -> Option<String> {
+        let content = std::fs::read_to_string(self.directory.join("current")).ok()?;
+        let key_id = content.trim();
+        (!key_id.is_empty()).then(|| String::from(key_id))
+    }
+}
+
+/// Parses a key written as 64 hexadecimal characters.
+fn parse_hex_key(text: &str) -> Option<[u8; 32]> {
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that rotating the current key keeps retired keys available.
+    #[test]
+    fn test_in_memory_rotation() {
+        let mut keys = InMemoryKeyProvider::new();
+        keys.insert("2024", [1u8; 32]);
+        assert!(keys.set_current("2024"));
+        keys.insert("2025", [2u8; 32]);
+        assert!(keys.set_current("2025"));
+        assert!(!keys.set_current("2026"));
+
+        assert_eq!(keys.current_key_id().as_deref(), Some("2025"));
+        assert_eq!(keys.key("2024"), Some([1u8; 32]));
+        assert_eq!(keys.key("2026"), None);
+    }
+
+    /// Tests reading raw and hexadecimal key files and the current key.
+    #[test]
+    fn test_file_keys() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("raw.key"), [5u8; 32]).unwrap();
+        std::fs::write(dir.path().join("hex.key"), format!("{}\n", "0a".repeat(32))).unwrap();
+        std::fs::write(dir.path().join("bad.key"), "0a0b").unwrap();
+        std::fs::write(dir.path().join("current"), "hex\n").unwrap();
+
+        let keys = FileKeyProvider::new(dir.path().to_str().unwrap());
+        assert_eq!(keys.key("raw"), Some([5u8; 32]));
+        assert_eq!(keys.key("hex"), Some([10u8; 32]));
+        assert_eq!(keys.key("bad"), None);
+        assert_eq!(keys.key("missing"), None);
+        assert_eq!(keys.key("../raw"), None);
+        assert_eq!(keys.current_key_id().as_deref(), Some("hex"));
+    }
+}
@@ -9,13 +9,187 @@ pub mod tlog;
 #[cfg(feature = "mavlog")]
 pub mod mavlog;
 
+#[cfg(feature = "analysis")]
+pub mod analysis;
+
+#[cfg(feature = "report")]
+pub mod report;
+
+#[cfg(feature = "recorder")]
+pub mod recorder;
+
+#[cfg(feature = "parser")]
+pub mod dialect;
+
+#[cfg(feature = "parser")]
+pub mod intercept;
+
+#[cfg(feature = "parser")]
+pub mod connection;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "samples")]
+pub mod samples;
+
+#[cfg(feature = "cache")]
+pub mod cache;
+
+#[cfg(feature = "influx")]
+pub mod influx;
+
+#[cfg(feature = "rosbag")]
+pub mod rosbag;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(any(feature = "analysis", feature = "influx", feature = "json"))]
+pub mod derive;
+
+#[cfg(any(feature = "analysis", feature = "influx", feature = "json"))]
+mod expr;
+
+#[cfg(feature = "service")]
+pub mod service;
+
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+#[cfg(feature = "synthetic")]
+pub mod synthetic;
+
+#[cfg(feature = "replay")]
+pub mod replay;
+
+#[cfg(feature = "terrain")]
+pub mod terrain;
+
+#[cfg(feature = "frames")]
+pub mod frames;
+
+#[cfg(feature = "batch")]
+pub mod batch;
+
+#[cfg(feature = "fingerprint")]
+pub mod fingerprint;
+
+#[cfg(feature = "catalog")]
+pub mod catalog;
+
+#[cfg(feature = "retention")]
+pub mod retention;
+
+#[cfg(feature = "chrono")]
+pub mod datetime;
+
+#[cfg(feature = "render")]
+pub mod render;
+
+#[cfg(feature = "isoxml")]
+pub mod isoxml;
+
+#[cfg(feature = "pymavlink")]
+pub mod pymavlink;
+
+#[cfg(feature = "resign")]
+pub mod resign;
+
+#[cfg(feature = "remap")]
+pub mod remap;
+
+#[cfg(any(feature = "logger", feature = "parser"))]
+pub mod clock;
+
+#[cfg(any(feature = "logger", feature = "parser"))]
+pub mod weather;
+
+#[cfg(any(feature = "logger", feature = "parser"))]
+pub mod metadata;
+
+#[cfg(any(feature = "encryption", feature = "signing"))]
+pub mod keys;
+
+#[cfg(feature = "signing")]
+pub mod signature;
+
+#[cfg(any(
+    feature = "analysis",
+    feature = "influx",
+    feature = "rosbag",
+    feature = "terrain",
+    feature = "render",
+    feature = "isoxml",
+    all(feature = "mavlog", feature = "parser")
+))]
+mod fields;
+
+#[cfg(all(feature = "parser", any(feature = "tlog", feature = "mavlog")))]
+mod byte_reader;
+
+#[cfg(all(
+    any(feature = "parser", feature = "logger"),
+    any(
+        feature = "tlog",
+        feature = "mavlog",
+        feature = "resign",
+        feature = "remap"
+    )
+))]
+mod frame;
+
+#[cfg(any(feature = "resign", feature = "remap"))]
+pub use frame::FrameError;
+
+#[cfg(all(feature = "parser", any(feature = "tlog", feature = "mavlog")))]
+mod open;
+
+#[cfg(all(feature = "parser", any(feature = "tlog", feature = "mavlog")))]
+pub use open::{Compression, DETECT_SIZE, DetectedFormat, detect_format, open};
+
 #[cfg(feature = "logger")]
 pub mod mav_logger {
-    use mavlink::{MavFrame, Message};
+    use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavFrame, MavlinkVersion, Message};
 
     pub trait MavLogger {
         fn write_mavlink<M: Message>(&mut self, frame: MavFrame<M>) -> std::io::Result<()>;
     }
+
+    /// An object safe logger of serialized MAVLink frames.
+    ///
+    /// `MavLogger` is generic over the message type of each frame, so it cannot be used as a
+    /// trait object. Loggers of different types can instead be stored as
+    /// `Box<dyn MavFrameLogger>`, which also implements `MavLogger` by serializing each frame.
+    pub trait MavFrameLogger {
+        /// Writes a serialized MAVLink frame to the log exactly as provided.
+        ///
+        /// # Arguments
+        /// - `frame`: The bytes of exactly one MAVLink 1 or MAVLink 2 frame.
+        ///
+        /// # Errors
+        /// Returns an `io::Error` if the frame could not be written, of kind `InvalidInput` if
+        /// the bytes are not a single complete frame.
+        fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()>;
+    }
+
+    /// Allows boxed frame loggers to be used wherever a `MavLogger` is expected.
+    impl<L: MavFrameLogger + ?Sized> MavLogger for Box<L> {
+        fn write_mavlink<M: Message>(&mut self, frame: MavFrame<M>) -> std::io::Result<()> {
+            match frame.protocol_version {
+                MavlinkVersion::V1 => {
+                    let mut msg = MAVLinkV1MessageRaw::new();
+                    msg.serialize_message(frame.header, &frame.msg);
+                    (**self).write_frame(msg.raw_bytes())
+                }
+                MavlinkVersion::V2 => {
+                    let mut msg = MAVLinkV2MessageRaw::new();
+                    msg.serialize_message(frame.header, &frame.msg);
+                    (**self).write_frame(msg.raw_bytes())
+                }
+            }
+        }
+    }
 }
 
 #[cfg(feature = "parser")]
@@ -23,7 +197,10 @@ pub mod mav_parser {
     use std::option::Option;
 
     use mavlink::error::MessageReadError;
-    use mavlink::{MavHeader, Message};
+    use mavlink::{MavHeader, MavlinkVersion, Message};
+
+    use crate::metadata::MissionMetadata;
+    use crate::weather::WeatherObservation;
 
     /// Represents a single log entry in a MAVLink log or telemetry log.
     ///
@@ -34,14 +211,99 @@ pub mod mav_parser {
     /// - `timestamp`: The timestamp of the log entry, if available.
     /// - `mav_header`: The MAVLink header associated with the message, if available.
     /// - `mav_message`: The MAVLink message, if available.
+    /// - `protocol_version`: The MAVLink protocol version the message was framed with, if
+    ///   available.
+    /// - `incompat_flags`: The incompatibility flags of the MAVLink 2 frame, such as the signed
+    ///   flag, if available.
+    /// - `compat_flags`: The compatibility flags of the MAVLink 2 frame, if available.
     /// - `text`: Any textual information associated with the log entry, if available.
     /// - `raw`: The raw binary data of the log entry, if available.
+    /// - `sequence`: The entry sequence number, if the log records one.
+    /// - `drops`: Message ids and counts of MAVLink frames the logger discarded, if this entry is
+    ///   a drop report.
+    /// - `blob`: The reassembled blob, if this entry holds one.
+    /// - `rtcm`: RTCM correction data and the link it was received on, if this entry holds some.
+    /// - `snapshot`: The headers and messages of a snapshot of the vehicle state, if this entry
+    ///   is one.
+    /// - `actuator`: The new state of an actuator applying product, if this entry is an actuator
+    ///   event.
+    /// - `weather`: The weather at the time of the entry, if this entry is a weather observation.
+    /// - `metadata`: The operator, job and products of the following entries, if this entry is
+    ///   mission metadata.
+    /// - `offset`: The byte offset of the entry in the log file, if the parser can determine it.
+    /// - `entry_len`: The number of bytes the entry occupies in the log file starting at `offset`,
+    ///   if the parser can determine it.
+    #[derive(PartialEq, Debug, Clone)]
+    #[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+    #[cfg_attr(
+        feature = "cache",
+        serde(bound(
+            serialize = "M: serde::Serialize",
+            deserialize = "M: serde::de::DeserializeOwned"
+        ))
+    )]
     pub struct LogEntry<M: Message> {
         pub timestamp: Option<u64>,
+        #[cfg_attr(feature = "cache", serde(with = "crate::cache::header"))]
         pub mav_header: Option<MavHeader>,
         pub mav_message: Option<M>,
+        #[cfg_attr(feature = "cache", serde(with = "crate::cache::version"))]
+        pub protocol_version: Option<MavlinkVersion>,
+        pub incompat_flags: Option<u8>,
+        pub compat_flags: Option<u8>,
         pub text: Option<String>,
         pub raw: Option<Vec<u8>>,
+        pub sequence: Option<u32>,
+        pub drops: Option<Vec<(u32, u32)>>,
+        pub blob: Option<Blob>,
+        pub rtcm: Option<RtcmData>,
+        #[cfg_attr(feature = "cache", serde(with = "crate::cache::snapshot"))]
+        pub snapshot: Option<Vec<(MavHeader, M)>>,
+        pub actuator: Option<ActuatorEvent>,
+        pub weather: Option<WeatherObservation>,
+        pub metadata: Option<MissionMetadata>,
+        pub offset: Option<u64>,
+        pub entry_len: Option<u64>,
+    }
+
+    /// A large payload stored across several log entries and reassembled by the parser.
+    ///
+    /// The timestamp, sequence number and offset of the entry holding a blob are those of its
+    /// first fragment. Its length spans all fragments.
+    #[derive(PartialEq, Debug, Clone)]
+    #[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Blob {
+        /// Id of the blob, unique within the log that recorded it.
+        pub id: u32,
+        /// Number of entries the blob was stored in.
+        pub fragments: u32,
+        /// The blob content.
+        pub data: Vec<u8>,
+    }
+
+    /// RTCM correction data as received from a correction link.
+    #[derive(PartialEq, Debug, Clone)]
+    #[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+    pub struct RtcmData {
+        /// Application defined id of the link the data was received on, such as a radio or an
+        /// NTRIP caster.
+        pub link: u8,
+        /// The RTCM bytes as received.
+        pub data: Vec<u8>,
+    }
+
+    /// A change of state of an actuator applying product, such as a spray boom section or a
+    /// seed meter.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    #[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+    pub struct ActuatorEvent {
+        /// Application defined id of the actuator, such as the index of a boom section.
+        pub actuator: u8,
+        /// Whether the actuator is applying product.
+        pub active: bool,
+        /// Target application rate in application defined units, such as litres per hectare or
+        /// seeds per square metre.
+        pub rate: f32,
     }
 
     impl<M: Message> Default for LogEntry<M> {
@@ -53,8 +315,122 @@ pub mod mav_parser {
                 timestamp: None,
                 mav_header: None,
                 mav_message: None,
+                protocol_version: None,
+                incompat_flags: None,
+                compat_flags: None,
                 text: None,
                 raw: None,
+                sequence: None,
+                drops: None,
+                blob: None,
+                rtcm: None,
+                snapshot: None,
+                actuator: None,
+                weather: None,
+                metadata: None,
+                offset: None,
+                entry_len: None,
+            }
+        }
+    }
+
+    /// Number of raw bytes shown by the `Display` implementation of `LogEntry`.
+    const DISPLAY_RAW_BYTES: usize = 32;
+
+    /// Formats an entry as a single grep friendly line.
+    ///
+    /// The line starts with the timestamp in seconds, or `-` if there is none, and the entry
+    /// sequence number if there is one. It continues with the kind of the entry in capitals and
+    /// its content: the system and component ids, message name and fields of a MAVLink message,
+    /// the quoted text, the start of raw data in hex, or a summary of blobs, RTCM data, snapshots,
+    /// actuator events, weather observations, mission metadata and drop reports.
+    impl<M: Message + std::fmt::Debug> std::fmt::Display for LogEntry<M> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.fmt_with(f, |msg| {
+                // Debug prints NAME(NAME_DATA { fields }), keep only the fields
+                let debug = format!("{msg:?}");
+                let fields = match (debug.find('{'), debug.rfind('}')) {
+                    (Some(start), Some(end)) if start < end => &debug[start..=end],
+                    _ => debug.as_str(),
+                };
+                format!("{} {}", msg.message_name(), fields)
+            })
+        }
+    }
+
+    impl<M: Message> LogEntry<M> {
+        /// Formats an entry as the `Display` implementation does, with the message of MAVLink
+        /// entries formatted by a function.
+        pub(crate) fn fmt_with<F>(
+            &self,
+            f: &mut std::fmt::Formatter<'_>,
+            format_message: F,
+        ) -> std::fmt::Result
+        where
+            F: Fn(&M) -> String,
+        {
+            match self.timestamp {
+                Some(timestamp) => {
+                    write!(f, "{}.{:06}", timestamp / 1_000_000, timestamp % 1_000_000)?
+                }
+                None => write!(f, "-")?,
+            }
+            if let Some(sequence) = self.sequence {
+                write!(f, " #{sequence}")?;
+            }
+            if let Some(msg) = &self.mav_message {
+                write!(f, " MAVLINK")?;
+                if let Some(header) = &self.mav_header {
+                    write!(
+                        f,
+                        " {}/{} seq={}",
+                        header.system_id, header.component_id, header.sequence
+                    )?;
+                }
+                write!(f, " {}", format_message(msg))
+            } else if let Some(text) = &self.text {
+                write!(f, " TEXT {text:?}")
+            } else if let Some(blob) = &self.blob {
+                write!(
+                    f,
+                    " BLOB id={} fragments={} bytes={}",
+                    blob.id,
+                    blob.fragments,
+                    blob.data.len()
+                )
+            } else if let Some(rtcm) = &self.rtcm {
+                write!(f, " RTCM link={} bytes={}", rtcm.link, rtcm.data.len())
+            } else if let Some(snapshot) = &self.snapshot {
+                write!(f, " SNAPSHOT messages={}", snapshot.len())
+            } else if let Some(event) = &self.actuator {
+                write!(
+                    f,
+                    " ACTUATOR id={} {} rate={}",
+                    event.actuator,
+                    if event.active { "on" } else { "off" },
+                    event.rate
+                )
+            } else if let Some(weather) = &self.weather {
+                write!(f, " WEATHER {weather}")
+            } else if let Some(metadata) = &self.metadata {
+                write!(f, " METADATA {metadata}")
+            } else if let Some(drops) = &self.drops {
+                write!(f, " DROPS")?;
+                for (msg_id, count) in drops {
+                    write!(f, " {msg_id}:{count}")?;
+                }
+                Ok(())
+            } else if let Some(raw) = &self.raw {
+                write!(f, " RAW bytes={} ", raw.len())?;
+                for byte in raw.iter().take(DISPLAY_RAW_BYTES) {
+                    write!(f, "{byte:02x}")?;
+                }
+                if raw.len() > DISPLAY_RAW_BYTES {
+                    write!(f, "...")?;
+                }
+                Ok(())
+            } else {
+                write!(f, " EMPTY")
             }
         }
     }
@@ -80,4 +456,62 @@ pub mod mav_parser {
         /// - `Err(MessageReadError)`: An error if the log entry could not be read.
         fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError>;
     }
+
+    /// Allows boxed parsers, such as those returned by `open`, to be used wherever a parser is
+    /// expected.
+    impl<P: MavParser + ?Sized> MavParser for Box<P> {
+        type M = P::M;
+
+        fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+            (**self).parse_next_entry()
+        }
+    }
+
+    /// Runs the provided closure on every entry the parser is able to read.
+    ///
+    /// Entries that fail to parse are skipped. Iteration stops at the end of the log.
+    ///
+    /// # Arguments
+    /// - `parser`: The parser to read entries from.
+    /// - `f`: Closure called with each successfully parsed entry. An error returned by the
+    ///   closure stops iteration and is returned to the caller.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the underlying log could not be read for a reason other than
+    /// reaching the end of the file, or if the closure returned an error.
+    pub fn for_each_entry<P, F>(parser: &mut P, mut f: F) -> std::io::Result<()>
+    where
+        P: MavParser + ?Sized,
+        F: FnMut(LogEntry<P::M>) -> std::io::Result<()>,
+    {
+        loop {
+            match parser.parse_next_entry() {
+                Ok(entry) => f(entry)?,
+                Err(MessageReadError::Io(e)) => match e.kind() {
+                    std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                    // corrupted entry content, move on to the next entry
+                    std::io::ErrorKind::InvalidData => continue,
+                    _ => return Err(e),
+                },
+                Err(MessageReadError::Parse(_)) => continue,
+            }
+        }
+    }
+
+    /// Parser returning a fixed list of entries, shared by the unit tests of parser consumers.
+    #[cfg(test)]
+    pub(crate) struct EntryList<M: Message = mavlink::common::MavMessage>(
+        pub(crate) std::collections::VecDeque<LogEntry<M>>,
+    );
+
+    #[cfg(test)]
+    impl<M: Message> MavParser for EntryList<M> {
+        type M = M;
+
+        fn parse_next_entry(&mut self) -> Result<LogEntry<M>, MessageReadError> {
+            self.0
+                .pop_front()
+                .ok_or_else(|| MessageReadError::Io(std::io::ErrorKind::UnexpectedEof.into()))
+        }
+    }
 }
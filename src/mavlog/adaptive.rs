@@ -0,0 +1,412 @@
+//! Adaptive logging rate of the .mav logger.
+//!
+//! Logging every frame of a long job mostly stores steady cruise, while the moments worth
+//! investigating need every frame. `AdaptiveRate` is a transform that lets every frame through
+//! while something interesting happens, a flight mode change, a failsafe or high vibration, and
+//! otherwise keeps each message of each component at most once per cruise interval.
+//!
+//! The rate returns to cruise once no trigger was seen for the hold time. Vibration keeps the
+//! full rate until it falls below a lower threshold than the one raising it, so that the rate
+//! does not flap while vibration hovers around a threshold. Each change of rate is recorded as a
+//! text entry, such as `ADAPTIVE_RATE full trigger=failsafe`, written before the frame causing
+//! it.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::logger::EntryType;
+use super::transform::{Action, EntryDraft};
+use crate::frame;
+
+/// HEARTBEAT message id.
+const HEARTBEAT_ID: u32 = 0;
+/// VIBRATION message id.
+const VIBRATION_ID: u32 = 241;
+/// Lowest MAV_STATE of a vehicle in failsafe, MAV_STATE_CRITICAL.
+const MAV_STATE_CRITICAL: u8 = 5;
+/// Highest MAV_STATE of a vehicle in failsafe, MAV_STATE_EMERGENCY.
+const MAV_STATE_EMERGENCY: u8 = 6;
+/// Prefix of the text entries recording a change of rate.
+pub const RATE_CHANGE_PREFIX: &str = "ADAPTIVE_RATE";
+
+/// Settings of an adaptive logging rate.
+#[derive(PartialEq, Clone, Debug)]
+pub struct AdaptiveRateConfig {
+    /// Shortest interval between two kept frames of a message from a component during cruise.
+    pub cruise_interval: Duration,
+    /// How long the full rate is kept after the last trigger.
+    pub hold: Duration,
+    /// Vibration in m/s/s on any axis raising the rate to full.
+    pub vibration_enter_m_s2: f32,
+    /// Vibration in m/s/s all axes must fall below before the rate can return to cruise.
+    pub vibration_exit_m_s2: f32,
+    /// Ids of the messages never decimated, such as commands and status texts.
+    pub full_rate_messages: Vec<u32>,
+}
+
+impl Default for AdaptiveRateConfig {
+    /// Keeps one frame per second of each message during cruise and the full rate for 10 seconds
+    /// after a trigger. Vibration raises the rate from 30 m/s/s, the level ArduPilot reports as
+    /// high, until it falls below 20 m/s/s. HEARTBEAT, COMMAND_INT, COMMAND_LONG, COMMAND_ACK
+    /// and STATUSTEXT are never decimated.
+    fn default() -> Self {
+        Self {
+            cruise_interval: Duration::from_secs(1),
+            hold: Duration::from_secs(10),
+            vibration_enter_m_s2: 30.0,
+            vibration_exit_m_s2: 20.0,
+            full_rate_messages: vec![0, 75, 76, 77, 253],
+        }
+    }
+}
+
+/// How many frames a logger keeps.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum LoggingRate {
+    /// Every frame is kept.
+    Full,
+    /// Frames are decimated to the cruise interval.
+    Cruise,
+}
+
+impl fmt::Display for LoggingRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoggingRate::Full => write!(f, "full"),
+            LoggingRate::Cruise => write!(f, "cruise"),
+        }
+    }
+}
+
+/// A condition raising the rate to full.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum RateTrigger {
+    /// A component changed its mode or armed state, seen in its HEARTBEAT.
+    ModeChange,
+    /// A component reported a critical or emergency system status in its HEARTBEAT.
+    Failsafe,
+    /// A VIBRATION message reported high vibration.
+    Vibration,
+}
+
+impl fmt::Display for RateTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateTrigger::ModeChange => write!(f, "mode_change"),
+            RateTrigger::Failsafe => write!(f, "failsafe"),
+            RateTrigger::Vibration => write!(f, "vibration"),
+        }
+    }
+}
+
+/// State of an adaptive rate, updated by every MAVLink entry.
+struct RateState {
+    rate: LoggingRate,
+    /// Time of the last trigger in microseconds.
+    last_trigger_us: u64,
+    /// Last base mode and custom mode of each component.
+    modes: BTreeMap<(u8, u8), (u8, u32)>,
+    /// Whether vibration rose above the enter threshold and has not yet fallen below the exit
+    /// threshold.
+    vibration_high: bool,
+    /// Time of the last kept frame by system, component and message id, in microseconds.
+    last_kept_us: BTreeMap<(u8, u8, u32), u64>,
+    /// Time base of the entries of logs without timestamps.
+    started: Instant,
+}
+
+/// A transform adapting how many frames a logger keeps to the state of the vehicle.
+///
+/// The rate starts at cruise. Add it to a logger with
+/// `logger.with_transform(move |draft| rate.apply(draft))`, sharing it in an `Arc` to read the
+/// current rate from elsewhere. Entries other than MAVLink frames are always kept.
+pub struct AdaptiveRate {
+    config: AdaptiveRateConfig,
+    state: Mutex<RateState>,
+}
+
+impl AdaptiveRate {
+    /// Creates a new adaptive rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The settings of the rate.
+    pub fn new(config: AdaptiveRateConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(RateState {
+                rate: LoggingRate::Cruise,
+                last_trigger_us: 0,
+                modes: BTreeMap::new(),
+                vibration_high: false,
+                last_kept_us: BTreeMap::new(),
+                started: Instant::now(),
+            }),
+        }
+    }
+
+    /// Returns the current rate.
+    pub fn rate(&self) -> LoggingRate {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).rate
+    }
+
+    /// Updates the rate with an entry and decides whether the entry is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `draft` - The entry about to be written. Its timestamp is the time base of the rate,
+    ///     the time since the rate was created if the log does not record timestamps.
+    ///
+    /// # Returns
+    ///
+    /// `Action::Drop` if the entry is decimated away, `Action::Insert` with the text entry
+    /// recording a change of rate if the entry changed it, and `Action::Keep` otherwise.
+    pub fn apply(&self, draft: &mut EntryDraft) -> Action {
+        if draft.entry_type != EntryType::Mavlink {
+            return Action::Keep;
+        }
+        let bytes: &[u8] = &draft.data;
+        let (Some(msg_id), Some((system_id, component_id)), Some(payload)) = (
+            frame::message_id(bytes),
+            frame::source(bytes),
+            frame::payload(bytes),
+        ) else {
+            return Action::Keep;
+        };
+        // MAVLink 2 truncates trailing zeros of payloads
+        let mut fields = [0u8; 255];
+        fields[..payload.len()].copy_from_slice(payload);
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now_us = draft
+            .timestamp_us
+            .unwrap_or_else(|| state.started.elapsed().as_micros() as u64);
+        let trigger = state.observe(&self.config, msg_id, (system_id, component_id), &fields);
+        let previous = state.rate;
+        if trigger.is_some() {
+            state.last_trigger_us = now_us;
+            state.rate = LoggingRate::Full;
+        } else if previous == LoggingRate::Full
+            && !state.vibration_high
+            && now_us.saturating_sub(state.last_trigger_us) >= self.config.hold.as_micros() as u64
+        {
+            state.rate = LoggingRate::Cruise;
+        }
+
+        let key = (system_id, component_id, msg_id);
+        if state.rate == LoggingRate::Cruise && !self.config.full_rate_messages.contains(&msg_id) {
+            let interval_us = self.config.cruise_interval.as_micros() as u64;
+            let due = state
+                .last_kept_us
+                .get(&key)
+                .is_none_or(|&last_us| now_us.saturating_sub(last_us) >= interval_us);
+            if !due {
+                return Action::Drop;
+            }
+        }
+        state.last_kept_us.insert(key, now_us);
+        if state.rate == previous {
+            return Action::Keep;
+        }
+        let text = match trigger {
+            Some(trigger) => format!("{RATE_CHANGE_PREFIX} {} trigger={trigger}", state.rate),
+            None => format!("{RATE_CHANGE_PREFIX} {}", state.rate),
+        };
+        Action::Insert(EntryDraft {
+            entry_type: EntryType::Text,
+            timestamp_us: draft.timestamp_us,
+            data: text.into_bytes(),
+        })
+    }
+}
+
+impl RateState {
+    /// Updates the vehicle state with a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The settings of the rate.
+    /// * `msg_id` - The message id of the frame.
+    /// * `source` - The system and component ids of the frame.
+    /// * `fields` - The payload of the frame, padded with zeros.
+    ///
+    /// # Returns
+    ///
+    /// The condition raising the rate to full, if the frame reports one.
+    fn observe(
+        &mut self,
+        config: &AdaptiveRateConfig,
+        msg_id: u32,
+        source: (u8, u8),
+        fields: &[u8; 255],
+    ) -> Option<RateTrigger> {
+        match msg_id {
+            HEARTBEAT_ID => {
+                // custom_mode is at offset 0, base_mode at 6 and system_status at 7
+                let custom_mode = u32::from_le_bytes(fields[0..4].try_into().unwrap());
+                let mode = (fields[6], custom_mode);
+                let changed = self
+                    .modes
+                    .insert(source, mode)
+                    .is_some_and(|previous| previous != mode);
+                if (MAV_STATE_CRITICAL..=MAV_STATE_EMERGENCY).contains(&fields[7]) {
+                    Some(RateTrigger::Failsafe)
+                } else if changed {
+                    Some(RateTrigger::ModeChange)
+                } else {
+                    None
+                }
+            }
+            VIBRATION_ID => {
+                // vibration_x, vibration_y and vibration_z follow time_usec
+                let vibration = (8..20)
+                    .step_by(4)
+                    .map(|offset| {
+                        f32::from_le_bytes(fields[offset..offset + 4].try_into().unwrap())
+                    })
+                    .fold(0.0f32, f32::max);
+                self.vibration_high = if self.vibration_high {
+                    vibration >= config.vibration_exit_m_s2
+                } else {
+                    vibration >= config.vibration_enter_m_s2
+                };
+                self.vibration_high.then_some(RateTrigger::Vibration)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::{HEARTBEAT_DATA, MavMessage, MavState, VIBRATION_DATA};
+    use mavlink::{MAVLinkV2MessageRaw, MavHeader};
+
+    use super::*;
+
+    /// Returns an entry holding a message from the autopilot.
+    fn entry(timestamp_us: u64, msg: &MavMessage) -> EntryDraft {
+        let header = MavHeader {
+            system_id: 1,
+            component_id: 1,
+            sequence: 0,
+        };
+        let mut raw = MAVLinkV2MessageRaw::new();
+        raw.serialize_message(header, msg);
+        EntryDraft {
+            entry_type: EntryType::Mavlink,
+            timestamp_us: Some(timestamp_us),
+            data: raw.raw_bytes().to_vec(),
+        }
+    }
+
+    /// Returns a HEARTBEAT message with a custom mode and system status.
+    fn heartbeat(custom_mode: u32, system_status: MavState) -> MavMessage {
+        MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode,
+            system_status,
+            ..Default::default()
+        })
+    }
+
+    /// Returns a VIBRATION message with the same vibration on all axes.
+    fn vibration(level: f32) -> MavMessage {
+        MavMessage::VIBRATION(VIBRATION_DATA {
+            vibration_x: level,
+            vibration_y: level,
+            vibration_z: level,
+            ..Default::default()
+        })
+    }
+
+    /// Applies the rate to an entry and returns the text of the inserted entry or whether the
+    /// entry was kept.
+    fn apply(rate: &AdaptiveRate, timestamp_us: u64, msg: &MavMessage) -> Result<String, bool> {
+        match rate.apply(&mut entry(timestamp_us, msg)) {
+            Action::Insert(inserted) => Ok(String::from_utf8(inserted.data).unwrap()),
+            Action::Keep => Err(true),
+            _ => Err(false),
+        }
+    }
+
+    /// Test that frames are decimated during cruise and all kept after a mode change until the
+    /// hold time elapsed.
+    #[test]
+    fn test_mode_change_raises_rate() {
+        let rate = AdaptiveRate::new(AdaptiveRateConfig::default());
+        let attitude = MavMessage::ATTITUDE(Default::default());
+        let active = MavState::MAV_STATE_ACTIVE;
+        assert_eq!(apply(&rate, 0, &heartbeat(3, active)), Err(true));
+        assert_eq!(apply(&rate, 0, &attitude), Err(true));
+        assert_eq!(apply(&rate, 100_000, &attitude), Err(false));
+        assert_eq!(apply(&rate, 1_000_000, &attitude), Err(true));
+        assert_eq!(rate.rate(), LoggingRate::Cruise);
+
+        assert_eq!(
+            apply(&rate, 1_500_000, &heartbeat(4, active)).as_deref(),
+            Ok("ADAPTIVE_RATE full trigger=mode_change")
+        );
+        assert_eq!(apply(&rate, 1_600_000, &attitude), Err(true));
+        assert_eq!(apply(&rate, 1_700_000, &attitude), Err(true));
+        assert_eq!(rate.rate(), LoggingRate::Full);
+
+        assert_eq!(
+            apply(&rate, 11_500_000, &attitude).as_deref(),
+            Ok("ADAPTIVE_RATE cruise")
+        );
+        assert_eq!(apply(&rate, 11_600_000, &attitude), Err(false));
+        assert_eq!(apply(&rate, 11_600_000, &heartbeat(4, active)), Err(true));
+    }
+
+    /// Test that a failsafe keeps the full rate while it lasts.
+    #[test]
+    fn test_failsafe_holds_rate() {
+        let rate = AdaptiveRate::new(AdaptiveRateConfig::default());
+        let critical = MavState::MAV_STATE_CRITICAL;
+        assert_eq!(
+            apply(&rate, 0, &heartbeat(3, critical)).as_deref(),
+            Ok("ADAPTIVE_RATE full trigger=failsafe")
+        );
+        for second in 1..30 {
+            assert_eq!(
+                apply(&rate, second * 1_000_000, &heartbeat(3, critical)),
+                Err(true)
+            );
+        }
+        assert_eq!(rate.rate(), LoggingRate::Full);
+    }
+
+    /// Test that vibration keeps the full rate until it falls below the exit threshold.
+    #[test]
+    fn test_vibration_hysteresis() {
+        let rate = AdaptiveRate::new(AdaptiveRateConfig::default());
+        assert_eq!(apply(&rate, 0, &vibration(25.0)), Err(true));
+        assert_eq!(rate.rate(), LoggingRate::Cruise);
+        assert_eq!(
+            apply(&rate, 1_000_000, &vibration(35.0)).as_deref(),
+            Ok("ADAPTIVE_RATE full trigger=vibration")
+        );
+        assert_eq!(apply(&rate, 20_000_000, &vibration(25.0)), Err(true));
+        assert_eq!(rate.rate(), LoggingRate::Full);
+        assert_eq!(apply(&rate, 25_000_000, &vibration(15.0)), Err(true));
+        assert_eq!(
+            apply(&rate, 35_000_000, &vibration(15.0)).as_deref(),
+            Ok("ADAPTIVE_RATE cruise")
+        );
+    }
+
+    /// Test that entries other than MAVLink frames are never decimated.
+    #[test]
+    fn test_other_entries_kept() {
+        let rate = AdaptiveRate::new(AdaptiveRateConfig::default());
+        for _ in 0..3 {
+            let mut draft = EntryDraft {
+                entry_type: EntryType::Text,
+                timestamp_us: Some(0),
+                data: b"note".to_vec(),
+            };
+            assert!(matches!(rate.apply(&mut draft), Action::Keep));
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! This module provides a logger that writes .mav log entries on a background thread.
+//!
+//! Entries are handed to the writer thread through a bounded queue so that callers never block on
+//! disk I/O. When the queue is full MAVLink frames are dropped rather than delaying the caller.
+//! Dropped frames are counted per message id and periodically recorded in the log as drop report
+//! entries so that gaps in the data can be told apart from gaps on the link.
+use std::collections::BTreeMap;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavFrame, Message};
+
+use super::logger::{EntryType, RotatingMavLogger};
+use crate::clock::Clock;
+use crate::frame;
+use crate::mav_logger::{MavFrameLogger, MavLogger};
+
+/// A request sent to the writer thread.
+enum Record {
+    Entry {
+        entry_type: EntryType,
+        timestamp_us: u64,
+        data: Vec<u8>,
+    },
+    Drops {
+        timestamp_us: u64,
+        drops: Vec<(u32, u32)>,
+    },
+}
+
+/// A .mav logger that writes entries on a background thread and drops MAVLink frames under
+/// overload.
+pub struct BackgroundMavLogger {
+    sender: Option<SyncSender<Record>>,
+    handle: Option<JoinHandle<std::io::Result<()>>>,
+    clock: Clock,
+    mavlink_only: bool,
+    drop_report_interval: Duration,
+    last_drop_report: Instant,
+    pending_drops: BTreeMap<u32, u32>,
+    dropped: u64,
+}
+
+impl BackgroundMavLogger {
+    /// Creates a new `BackgroundMavLogger` and starts its writer thread.
+    ///
+    /// Entries are timestamped when they are queued, with the clock taken over from the logger.
+    ///
+    /// # Arguments
+    ///
+    /// * `logger` - The logger the writer thread writes entries to.
+    /// * `capacity` - The number of entries that may be queued before MAVLink frames are dropped.
+    /// * `drop_report_interval` - The minimum time between drop report entries.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `BackgroundMavLogger` or an `io::Error` if the writer thread
+    /// could not be started.
+    pub fn new(
+        mut logger: RotatingMavLogger,
+        capacity: usize,
+        drop_report_interval: Duration,
+    ) -> std::io::Result<Self> {
+        let clock = std::mem::take(&mut logger.clock);
+        let mavlink_only = logger.header().format_flags.mavlink_only;
+        let (sender, receiver) = sync_channel(capacity);
+        let handle = std::thread::Builder::new()
+            .name(String::from("mavlog-writer"))
+            .spawn(move || Self::write_loop(logger, receiver))?;
+        Ok(Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            clock,
+            mavlink_only,
+            drop_report_interval,
+            last_drop_report: Instant::now(),
+            pending_drops: BTreeMap::new(),
+            dropped: 0,
+        })
+    }
+
+    /// Returns the total number of MAVLink frames dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Queues a text message to be written to the log.
+    ///
+    /// Text entries are never dropped. This blocks while the queue is full. Fails if the log
+    /// only accepts MAVLink.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text message to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error is returned if the writer thread has
+    /// stopped because of a write failure.
+    pub fn write_text(&mut self, text: &str) -> std::io::Result<()> {
+        self.send_entry(EntryType::Text, text.as_bytes().to_vec())
+    }
+
+    /// Queues raw data to be written to the log.
+    ///
+    /// Raw entries are never dropped. This blocks while the queue is full. Fails if the log
+    /// only accepts MAVLink.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw data to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error is returned if the writer thread has
+    /// stopped because of a write failure.
+    pub fn write_raw(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.send_entry(EntryType::Raw, data.to_vec())
+    }
+
+    /// Writes any pending drop report, waits for the queue to drain and stops the writer thread.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error is returned if any entry could not be
+    /// written.
+    pub fn close(mut self) -> std::io::Result<()> {
+        self.shutdown()
+    }
+
+    /// Queues a serialized MAVLink frame, dropping it if the queue is full.
+    fn queue_frame(&mut self, msg_id: u32, data: Vec<u8>) -> std::io::Result<()> {
+        let record = Record::Entry {
+            entry_type: EntryType::Mavlink,
+            timestamp_us: self.clock.now_us()?,
+            data,
+        };
+        let result = match &self.sender {
+            Some(sender) => sender.try_send(record),
+            None => Err(TrySendError::Disconnected(record)),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                let count = self.pending_drops.entry(msg_id).or_insert(0);
+                *count = count.saturating_add(1);
+                self.dropped += 1;
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(record)) => self.send(record),
+        }
+    }
+
+    /// Queues an entry that must not be dropped.
+    fn send_entry(&mut self, entry_type: EntryType, data: Vec<u8>) -> std::io::Result<()> {
+        // Reject here rather than letting the writer thread fail on it.
+        if self.mavlink_only {
+            return Err(std::io::Error::other(
+                "This logger accepts only mavlink messages",
+            ));
+        }
+        let record = Record::Entry {
+            entry_type,
+            timestamp_us: self.clock.now_us()?,
+            data,
+        };
+        self.send(record)
+    }
+
+    /// Sends a record to the writer thread, blocking while the queue is full.
+    fn send(&mut self, record: Record) -> std::io::Result<()> {
+        let sent = match &self.sender {
+            Some(sender) => sender.send(record).is_ok(),
+            None => false,
+        };
+        if sent {
+            return Ok(());
+        }
+        // The writer thread only exits early on a write failure, report it.
+        match self.shutdown() {
+            Err(e) => Err(e),
+            Ok(()) => Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "The background writer has stopped",
+            )),
+        }
+    }
+
+    /// Queues a drop report if frames were dropped and the report interval has passed.
+    ///
+    /// The report is only queued if there is room, otherwise drops keep accumulating until the
+    /// next attempt.
+    fn report_drops(&mut self) -> std::io::Result<()> {
+        if self.pending_drops.is_empty() || self.mavlink_only {
+            return Ok(());
+        }
+        if self.last_drop_report.elapsed() < self.drop_report_interval {
+            return Ok(());
+        }
+        let record = Record::Drops {
+            timestamp_us: self.clock.now_us()?,
+            drops: self.pending_drops.iter().map(|(&id, &n)| (id, n)).collect(),
+        };
+        let result = match &self.sender {
+            Some(sender) => sender.try_send(record),
+            None => Err(TrySendError::Disconnected(record)),
+        };
+        match result {
+            Ok(()) => {
+                self.pending_drops.clear();
+                self.last_drop_report = Instant::now();
+                Ok(())
+            }
+            Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Disconnected(record)) => self.send(record),
+        }
+    }
+
+    /// Writes a pending drop report and joins the writer thread.
+    fn shutdown(&mut self) -> std::io::Result<()> {
+        if let Some(sender) = self.sender.take() {
+            let pending = std::mem::take(&mut self.pending_drops);
+            if !pending.is_empty() && !self.mavlink_only {
+                // a rejected clock reading still reports the drops at the last timestamp
+                let timestamp_us = self.clock.now_us();
+                let record = Record::Drops {
+                    timestamp_us: timestamp_us.unwrap_or(self.clock.last_us().unwrap_or(0)),
+                    drops: pending.into_iter().collect(),
+                };
+                // A send failure means the writer failed, which is reported by the join below.
+                let _ = sender.send(record);
+            }
+        }
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .unwrap_or_else(|_| Err(std::io::Error::other("The background writer panicked"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes queued records until the sending side is closed or a write fails.
+    fn write_loop(
+        mut logger: RotatingMavLogger,
+        receiver: Receiver<Record>,
+    ) -> std::io::Result<()> {
+        for record in receiver {
+            match record {
+                Record::Entry {
+                    entry_type,
+                    timestamp_us,
+                    data,
+                } => logger.write_at(entry_type, Some(timestamp_us), &data)?,
+                Record::Drops {
+                    timestamp_us,
+                    drops,
+                } => logger.write_drops_at(&drops, Some(timestamp_us))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MavLogger for BackgroundMavLogger {
+    /// Queues a MAVLink message to be written to the log.
+    ///
+    /// If the queue is full the frame is dropped and counted in the next drop report.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The MavFrame to log. This contains the MAVLink version, message, and header.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. Dropping a frame is not an error. An error is
+    /// returned if the writer thread has stopped because of a write failure.
+    fn write_mavlink<M: Message>(&mut self, frame: MavFrame<M>) -> std::io::Result<()> {
+        let data = match frame.protocol_version {
+            mavlink::MavlinkVersion::V1 => {
+                let mut msg = MAVLinkV1MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                msg.raw_bytes().to_vec()
+            }
+            mavlink::MavlinkVersion::V2 => {
+                let mut msg = MAVLinkV2MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                msg.raw_bytes().to_vec()
+            }
+        };
+        let model = self.clock.observe(&frame.msg);
+        self.queue_frame(frame.msg.message_id(), data)?;
+        if let Some(model) = model.filter(|_| !self.mavlink_only) {
+            self.send_entry(EntryType::Text, model.to_text().into_bytes())?;
+        }
+        self.report_drops()
+    }
+}
+
+impl MavFrameLogger for BackgroundMavLogger {
+    /// Queues a serialized MAVLink frame to be written to the log exactly as provided.
+    ///
+    /// If the queue is full the frame is dropped and counted in the next drop report.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The bytes of exactly one MAVLink 1 or MAVLink 2 frame.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error of kind `InvalidInput` is returned
+    /// if the bytes are not a single complete frame, and an error if the writer thread has
+    /// stopped because of a write failure.
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        let msg_id = frame::message_id(frame).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The data is not a single complete MAVLink frame",
+            )
+        })?;
+        self.queue_frame(msg_id, frame.to_vec())?;
+        self.report_drops()
+    }
+}
+
+impl Drop for BackgroundMavLogger {
+    /// Flushes queued entries and stops the writer thread. Write errors are ignored, use `close`
+    /// to observe them.
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use mavlink::MavHeader;
+    use mavlink::common::MavMessage;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mav_parser::for_each_entry;
+    use crate::mavlog::parser::MavLogParser;
+
+    /// Test that every frame is either written or accounted for in a drop report.
+    #[test]
+    fn test_drops_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("background.mav");
+        let logger =
+            RotatingMavLogger::new(log_path.to_str().unwrap(), 100_000_000, 0, None, None).unwrap();
+        let mut logger = BackgroundMavLogger::new(logger, 1, Duration::from_millis(1)).unwrap();
+        for _ in 0..2000 {
+            let frame = MavFrame {
+                header: MavHeader::default(),
+                msg: MavMessage::HEARTBEAT(Default::default()),
+                protocol_version: mavlink::MavlinkVersion::V2,
+            };
+            logger.write_mavlink(frame).unwrap();
+        }
+        logger.write_text("done").unwrap();
+        let dropped = logger.dropped();
+        logger.close().unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(log_path.to_str().unwrap());
+        let mut written: u64 = 0;
+        let mut reported: u64 = 0;
+        for_each_entry(&mut parser, |entry| {
+            if entry.mav_message.is_some() {
+                written += 1;
+            }
+            for (msg_id, count) in entry.drops.unwrap_or_default() {
+                assert_eq!(msg_id, 0);
+                reported += count as u64;
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(reported, dropped);
+        assert_eq!(written + reported, 2000);
+    }
+}
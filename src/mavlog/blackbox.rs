@@ -0,0 +1,122 @@
+//! Black box profile of the .mav logger, maximizing how much of the final seconds before a
+//! crash survive.
+//!
+//! Written entries normally sit in the page cache until the operating system writes them out,
+//! so a power loss loses the most recent seconds of the log. With the black box profile set,
+//! the logger syncs the log file to storage whenever it writes an entry and the oldest entry not
+//! yet synced was written at least `max_latency` ago. It also periodically rewrites the
+//! `timestamp_us` field of the file header with the current time, so the header records when
+//! the logger was last alive rather than when the file was created.
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Offset of the `timestamp_us` field in the file header.
+const HEADER_TIMESTAMP_OFFSET: u64 = 16;
+
+/// Settings of the black box profile.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct BlackBox {
+    /// The longest time a written entry may wait before the file is synced. Zero syncs after
+    /// every entry.
+    pub max_latency: Duration,
+    /// Interval at which the header timestamp is rewritten.
+    pub header_interval: Duration,
+}
+
+impl Default for BlackBox {
+    /// Syncs after every entry and rewrites the header timestamp every second.
+    fn default() -> Self {
+        Self {
+            max_latency: Duration::ZERO,
+            header_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Sync state of a logger with the black box profile set.
+pub(crate) struct BlackBoxState {
+    profile: BlackBox,
+    /// Time of the oldest entry written since the last sync.
+    pending_since: Option<Instant>,
+    last_header: Option<Instant>,
+}
+
+impl BlackBoxState {
+    pub(crate) fn new(profile: BlackBox) -> Self {
+        Self {
+            profile,
+            pending_since: None,
+            last_header: None,
+        }
+    }
+
+    /// Records that entries were written to the log file and syncs it once due.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the log file being written.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure of the sync.
+    pub(crate) fn on_write(&mut self, path: &str) -> std::io::Result<()> {
+        let now = Instant::now();
+        let pending_since = *self.pending_since.get_or_insert(now);
+        if now.duration_since(pending_since) < self.profile.max_latency {
+            return Ok(());
+        }
+        // the file is reopened for every sync so that the current file is synced after rotation
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let header_due = self
+            .last_header
+            .is_none_or(|last| now.duration_since(last) >= self.profile.header_interval);
+        if header_due {
+            let alive_us = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_micros() as u64);
+            file.seek(SeekFrom::Start(HEADER_TIMESTAMP_OFFSET))?;
+            file.write_all(&alive_us.to_le_bytes())?;
+            self.last_header = Some(now);
+        }
+        file.sync_data()?;
+        self.pending_since = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::mavlog::header::FileHeader;
+    use crate::mavlog::logger::RotatingMavLogger;
+
+    /// Test that the header timestamp is rewritten while entries are written, without
+    /// disturbing the entries.
+    #[test]
+    fn test_black_box_header_timestamp() {
+        let mut tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let tmpfile_path = tmpfile.path().to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(tmpfile_path, 100000, 0, None, None).unwrap();
+        logger
+            .set_black_box(Some(BlackBox {
+                max_latency: Duration::ZERO,
+                header_interval: Duration::ZERO,
+            }))
+            .unwrap();
+        let created_us = logger.header().timestamp_us;
+        std::thread::sleep(Duration::from_millis(5));
+        logger.write_raw(&[1, 2, 3]).unwrap();
+
+        let mut content: Vec<u8> = Vec::new();
+        tmpfile.read_to_end(&mut content).unwrap();
+        let alive_us = u64::from_le_bytes(content[16..24].try_into().unwrap());
+        assert!(alive_us >= created_us + 5_000);
+        assert_eq!(content.len(), FileHeader::MIN_SIZE + 14);
+        assert_eq!(content[FileHeader::MIN_SIZE + 11..], [1, 2, 3]);
+    }
+}
@@ -0,0 +1,706 @@
+//! This module provides the blocks of chunked .mav log files.
+//!
+//! In a chunked log the entries following the file header are grouped into blocks. Each block is
+//! compressed independently with zstd and protected by a CRC-32, so a corrupted block loses only
+//! the entries it holds and readers can skip whole blocks without decompressing them.
+//! See docs/mav_log_file_format.md for the layout of a block.
+//!
+//! `recompress` moves the entries of an existing log into blocks of another compression level,
+//! or out of blocks altogether, without decoding them.
+use std::collections::VecDeque;
+#[cfg(all(feature = "logger", feature = "parser"))]
+use std::fs::File;
+use std::io::Read;
+#[cfg(all(feature = "logger", feature = "parser"))]
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+
+#[cfg(all(feature = "logger", feature = "parser", feature = "hash_chain"))]
+use super::chain;
+#[cfg(all(feature = "logger", feature = "parser"))]
+use super::footer::LogFooter;
+#[cfg(all(feature = "logger", feature = "parser"))]
+use super::header::{FileHeader, FormatFlags};
+#[cfg(all(feature = "logger", feature = "parser"))]
+use super::parser;
+#[cfg(all(feature = "logger", feature = "parser"))]
+use crate::frame;
+
+/// Magic bytes starting every block.
+const MAGIC: [u8; 4] = *b"MAVB";
+/// Largest compressed or uncompressed block size accepted, guarding against corrupt sizes.
+pub const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Struct representing the header of a block.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BlockHeader {
+    /// Size of the compressed entries following the header in bytes.
+    pub compressed_size: u32,
+    /// Size of the entries once decompressed in bytes.
+    pub uncompressed_size: u32,
+    /// Number of entries in the block.
+    pub entry_count: u32,
+    /// Timestamp of the first entry in the block, 0 if entries are not timestamped.
+    pub first_timestamp_us: u64,
+    /// CRC-32 of the header fields preceding it and of the compressed entries.
+    pub crc: u32,
+}
+
+impl BlockHeader {
+    /// Size of the packed block header in bytes, including the magic bytes.
+    pub const SIZE: usize = 28;
+
+    /// Unpacks the block header fields following the magic bytes.
+    fn unpack(packed_data: &[u8; Self::SIZE - 4]) -> Self {
+        BlockHeader {
+            compressed_size: u32::from_le_bytes(packed_data[0..4].try_into().unwrap()),
+            uncompressed_size: u32::from_le_bytes(packed_data[4..8].try_into().unwrap()),
+            entry_count: u32::from_le_bytes(packed_data[8..12].try_into().unwrap()),
+            first_timestamp_us: u64::from_le_bytes(packed_data[12..20].try_into().unwrap()),
+            crc: u32::from_le_bytes(packed_data[20..24].try_into().unwrap()),
+        }
+    }
+
+    /// Packs the block header fields covered by the CRC.
+    #[cfg(feature = "logger")]
+    fn pack_checked_fields(&self) -> Vec<u8> {
+        let mut packed: Vec<u8> = Vec::with_capacity(20);
+        packed.extend_from_slice(&self.compressed_size.to_le_bytes());
+        packed.extend_from_slice(&self.uncompressed_size.to_le_bytes());
+        packed.extend_from_slice(&self.entry_count.to_le_bytes());
+        packed.extend_from_slice(&self.first_timestamp_us.to_le_bytes());
+        packed
+    }
+}
+
+/// Location and header of an intact block.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BlockInfo {
+    /// Offset of the block from the start of the scanned data in bytes.
+    pub offset: u64,
+    /// The block header.
+    pub header: BlockHeader,
+}
+
+/// Computes the CRC-32 of a block from its checked header fields and compressed entries.
+fn block_crc(checked_fields: &[u8], compressed: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(checked_fields);
+    hasher.update(compressed);
+    hasher.finalize()
+}
+
+/// Compresses packed entries into a block.
+///
+/// # Arguments
+///
+/// * `entries` - The packed entries of the block.
+/// * `entry_count` - The number of entries.
+/// * `first_timestamp_us` - The timestamp of the first entry, 0 if entries are not timestamped.
+///
+/// # Returns
+///
+/// A `Result` containing the packed block or an `io::Error` if compression failed.
+#[cfg(feature = "logger")]
+pub(crate) fn encode_block(
+    entries: &[u8],
+    entry_count: u32,
+    first_timestamp_us: u64,
+) -> std::io::Result<Vec<u8>> {
+    encode_block_with_level(
+        entries,
+        entry_count,
+        first_timestamp_us,
+        zstd::DEFAULT_COMPRESSION_LEVEL,
+    )
+}
+
+/// Compresses packed entries into a block with an explicit compression level.
+///
+/// # Arguments
+///
+/// * `entries` - The packed entries of the block.
+/// * `entry_count` - The number of entries.
+/// * `first_timestamp_us` - The timestamp of the first entry, 0 if entries are not timestamped.
+/// * `level` - The zstd compression level.
+///
+/// # Returns
+///
+/// A `Result` containing the packed block or an `io::Error` if compression failed.
+#[cfg(feature = "logger")]
+fn encode_block_with_level(
+    entries: &[u8],
+    entry_count: u32,
+    first_timestamp_us: u64,
+    level: i32,
+) -> std::io::Result<Vec<u8>> {
+    let compressed = zstd::bulk::compress(entries, level)?;
+    let mut header = BlockHeader {
+        compressed_size: compressed.len() as u32,
+        uncompressed_size: entries.len() as u32,
+        entry_count,
+        first_timestamp_us,
+        crc: 0,
+    };
+    let checked_fields = header.pack_checked_fields();
+    header.crc = block_crc(&checked_fields, &compressed);
+
+    let mut packed: Vec<u8> = Vec::with_capacity(BlockHeader::SIZE + compressed.len());
+    packed.extend_from_slice(&MAGIC);
+    packed.extend_from_slice(&checked_fields);
+    packed.extend_from_slice(&header.crc.to_le_bytes());
+    packed.extend_from_slice(&compressed);
+    Ok(packed)
+}
+
+/// Finds the intact blocks in a stream of blocks without decompressing them.
+///
+/// Corrupted blocks are skipped by searching for the next block header.
+///
+/// # Arguments
+///
+/// * `reader` - A reader positioned at the first block, such as a log file positioned after its
+///   file header.
+///
+/// # Returns
+///
+/// A `Result` containing the intact blocks in order or an `io::Error` if the data could not be
+/// read.
+pub fn scan_blocks<R: Read>(reader: R) -> std::io::Result<Vec<BlockInfo>> {
+    let mut scanner = BlockScanner::new(reader);
+    let mut blocks: Vec<BlockInfo> = Vec::new();
+    while let Some((info, _)) = scanner.next_block()? {
+        blocks.push(info);
+    }
+    Ok(blocks)
+}
+
+/// Reads blocks from a stream, resynchronizing on the next block header after corruption.
+struct BlockScanner<R: Read> {
+    reader: R,
+    /// Bytes given back after a failed block check, read before the underlying reader.
+    pending: VecDeque<u8>,
+    offset: u64,
+    /// Number of corrupted blocks skipped, kept for diagnostics.
+    #[cfg_attr(not(test), allow(dead_code))]
+    corrupt_blocks: u64,
+}
+
+impl<R: Read> BlockScanner<R> {
+    fn new(reader: R) -> Self {
+        BlockScanner {
+            reader,
+            pending: VecDeque::new(),
+            offset: 0,
+            corrupt_blocks: 0,
+        }
+    }
+
+    /// Reads the next intact block and its compressed entries.
+    ///
+    /// # Returns
+    ///
+    /// The block, or `None` at the end of the data.
+    fn next_block(&mut self) -> std::io::Result<Option<(BlockInfo, Vec<u8>)>> {
+        loop {
+            if !self.find_magic()? {
+                return Ok(None);
+            }
+            let offset = self.offset - MAGIC.len() as u64;
+            let mut fields = [0u8; BlockHeader::SIZE - 4];
+            if !self.fill(&mut fields)? {
+                // A block cut short at the end of the data
+                self.corrupt_blocks += 1;
+                return Ok(None);
+            }
+            let header = BlockHeader::unpack(&fields);
+            if header.compressed_size as usize > MAX_BLOCK_SIZE
+                || header.uncompressed_size as usize > MAX_BLOCK_SIZE
+            {
+                self.corrupt_blocks += 1;
+                self.give_back(&fields);
+                continue;
+            }
+            let mut compressed = vec![0u8; header.compressed_size as usize];
+            if !self.fill(&mut compressed)? {
+                self.corrupt_blocks += 1;
+                return Ok(None);
+            }
+            if block_crc(&fields[..20], &compressed) != header.crc {
+                // The sizes may be corrupt too, search for the next block right after the magic
+                self.corrupt_blocks += 1;
+                self.give_back(&compressed);
+                self.give_back(&fields);
+                continue;
+            }
+            return Ok(Some((BlockInfo { offset, header }, compressed)));
+        }
+    }
+
+    /// Consumes bytes until just after the next block magic.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a magic was found, `false` at the end of the data.
+    fn find_magic(&mut self) -> std::io::Result<bool> {
+        let mut window = [0u8; 4];
+        if !self.fill(&mut window)? {
+            return Ok(false);
+        }
+        let mut byte = [0u8; 1];
+        while window != MAGIC {
+            if !self.fill(&mut byte)? {
+                return Ok(false);
+            }
+            window.rotate_left(1);
+            window[3] = byte[0];
+        }
+        Ok(true)
+    }
+
+    /// Returns consumed bytes to be read again.
+    fn give_back(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter().rev() {
+            self.pending.push_front(byte);
+        }
+        self.offset -= bytes.len() as u64;
+    }
+
+    /// Fills the buffer completely.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the buffer was filled, `false` if the end of the data was reached first.
+    fn fill(&mut self, buffer: &mut [u8]) -> std::io::Result<bool> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            if let Some(byte) = self.pending.pop_front() {
+                buffer[filled] = byte;
+                filled += 1;
+                continue;
+            }
+            match self.reader.read(&mut buffer[filled..]) {
+                Ok(0) => {
+                    self.offset += filled as u64;
+                    return Ok(false);
+                }
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.offset += filled as u64;
+        Ok(true)
+    }
+}
+
+/// Reader presenting the entries of a stream of blocks as one continuous stream.
+///
+/// Corrupted blocks are skipped as a whole. Since blocks only hold whole entries, reading
+/// continues at the first entry of the next intact block.
+#[cfg(feature = "parser")]
+pub(crate) struct BlockReader<R: Read> {
+    scanner: BlockScanner<R>,
+    entries: VecDeque<u8>,
+}
+
+#[cfg(feature = "parser")]
+impl<R: Read> BlockReader<R> {
+    /// Creates a new `BlockReader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - A reader positioned at the first block.
+    pub(crate) fn new(reader: R) -> Self {
+        BlockReader {
+            scanner: BlockScanner::new(reader),
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of corrupted blocks skipped so far.
+    #[cfg(test)]
+    fn corrupt_blocks(&self) -> u64 {
+        self.scanner.corrupt_blocks
+    }
+
+    /// Decompresses the next intact block into the entry buffer.
+    ///
+    /// # Returns
+    ///
+    /// `false` at the end of the data.
+    fn next_block(&mut self) -> std::io::Result<bool> {
+        loop {
+            let (info, compressed) = match self.scanner.next_block()? {
+                Some(block) => block,
+                None => return Ok(false),
+            };
+            let uncompressed_size = info.header.uncompressed_size as usize;
+            match zstd::bulk::decompress(&compressed, uncompressed_size) {
+                Ok(entries) if entries.len() == uncompressed_size => {
+                    self.entries.extend(entries);
+                    return Ok(true);
+                }
+                _ => self.scanner.corrupt_blocks += 1,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "parser")]
+impl<R: Read> Read for BlockReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        if self.entries.is_empty() && !self.next_block()? {
+            return Ok(0);
+        }
+        self.entries.read(buffer)
+    }
+}
+
+/// Compression of the entries of a log file, see `recompress`.
+#[cfg(all(feature = "logger", feature = "parser"))]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Codec {
+    /// Entries follow the file header one after another. Entry payloads compressed with a
+    /// dictionary stay compressed.
+    Uncompressed,
+    /// Entries are grouped into blocks compressed with zstd, as in logs with the `chunked`
+    /// flag set.
+    Zstd {
+        /// The zstd compression level. Low and negative levels trade compression ratio for
+        /// speed, high levels suit archives.
+        level: i32,
+        /// The uncompressed size at which blocks are written, see
+        /// `RotatingMavLogger::set_block_size`.
+        block_size: usize,
+    },
+}
+
+/// Rewrites a log file with its entries compressed with another codec.
+///
+/// Entries are moved between blocks as they are stored, so they are never decoded or decrypted
+/// and the output keeps the file header of the input apart from the `chunked` flag. The footer
+/// of a finished log is kept. Blocks of the input that are corrupted are left out, and so is an
+/// entry cut short at the end of an unchunked input. Logs appended to an unchunked input are not
+/// supported.
+///
+/// The entries of a hash-chained log are linked again, starting at the rewritten file header.
+/// The chain of the input is checked while doing so, so that linking again never hides entries
+/// that were removed or altered.
+///
+/// # Arguments
+///
+/// * `path_in` - The log file to recompress.
+/// * `path_out` - The path to write the recompressed log file to. It must differ from `path_in`.
+/// * `codec` - The codec of the output.
+///
+/// # Returns
+///
+/// A `Result` containing the number of entries written or an `io::Error`. An error of kind
+/// `InvalidData` is returned if an entry of a MAVLink only input is not a MAVLink frame or the
+/// hash chain of the input is broken, of kind `InvalidInput` if an entry does not fit in a block
+/// and of kind `Unsupported` if the input is hash-chained and encrypted, since the links of its
+/// entries are authenticated, or the `hash_chain` feature is not enabled.
+#[cfg(all(feature = "logger", feature = "parser"))]
+pub fn recompress(path_in: &str, path_out: &str, codec: Codec) -> std::io::Result<u64> {
+    let mut header = parser::read_header(path_in)?;
+    if header.format_flags.hash_chain
+        && (header.format_flags.encrypted || cfg!(not(feature = "hash_chain")))
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Hash-chained logs can only be recompressed unencrypted with the hash_chain feature",
+        ));
+    }
+    let footer = LogFooter::read(path_in)?;
+    let mut file = File::open(path_in)?;
+    let entries_start = header.packed_size() as u64;
+    let file_len = file.metadata()?.len();
+    #[cfg(feature = "hash_chain")]
+    let mut header_in = vec![0u8; entries_start as usize];
+    #[cfg(feature = "hash_chain")]
+    file.read_exact(&mut header_in)?;
+    file.seek(SeekFrom::Start(entries_start))?;
+    let mut entries: Box<dyn Read> = if header.format_flags.chunked {
+        // A footer following the blocks is skipped along with any other data between blocks
+        Box::new(BlockReader::new(file))
+    } else {
+        let footer_len = footer.map_or(0, |_| LogFooter::SIZE as u64);
+        let entries_len = file_len.saturating_sub(entries_start + footer_len);
+        Box::new(BufReader::new(file.take(entries_len)))
+    };
+
+    let flags = header.format_flags;
+    // Entries of older format versions are laid out as in the current version, only the flags
+    // written need the current version
+    header.format_version = FileHeader::FILE_FORMAT_VERSION;
+    header.format_flags.chunked = matches!(codec, Codec::Zstd { .. });
+    let header_out = header.pack();
+    let mut output = BufWriter::new(File::create(path_out)?);
+    output.write_all(&header_out)?;
+    #[cfg(feature = "hash_chain")]
+    let mut chain = Relinker::new(&header_in, &header_out);
+    let mut block = PendingBlock::default();
+    let mut entry_count: u64 = 0;
+    #[allow(unused_mut)]
+    while let Some(mut entry) = read_packed_entry(&mut entries, &flags)? {
+        #[cfg(feature = "hash_chain")]
+        if flags.hash_chain {
+            chain.relink(&mut entry, &flags)?;
+        }
+        entry_count += 1;
+        let Codec::Zstd { level, block_size } = codec else {
+            output.write_all(&entry)?;
+            continue;
+        };
+        // Blocks stay below half the size readers accept, as written by the logger
+        let max_size = MAX_BLOCK_SIZE / 2;
+        if entry.len() > max_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Entry of {} bytes does not fit in a block", entry.len()),
+            ));
+        }
+        if block.entries.len() + entry.len() > max_size {
+            output.write_all(&block.take(level)?)?;
+        }
+        block.push(&entry, &flags);
+        if block.entries.len() >= block_size.clamp(1, max_size) {
+            output.write_all(&block.take(level)?)?;
+        }
+    }
+    if let Codec::Zstd { level, .. } = codec {
+        output.write_all(&block.take(level)?)?;
+    }
+    if let Some(footer) = footer {
+        output.write_all(&footer.pack())?;
+    }
+    output.flush()?;
+    Ok(entry_count)
+}
+
+/// Hash chain of the entries rewritten by `recompress`.
+#[cfg(all(feature = "logger", feature = "parser", feature = "hash_chain"))]
+struct Relinker {
+    /// Hash the next entry of the input links to.
+    previous_in: [u8; chain::HASH_SIZE],
+    /// Hash the next entry of the output links to.
+    previous_out: [u8; chain::HASH_SIZE],
+}
+
+#[cfg(all(feature = "logger", feature = "parser", feature = "hash_chain"))]
+impl Relinker {
+    /// Creates a new `Relinker` starting both chains at their file header.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_in` - The file header of the input as stored.
+    /// * `header_out` - The file header of the output.
+    fn new(header_in: &[u8], header_out: &[u8]) -> Self {
+        Relinker {
+            previous_in: chain::entry_hash(header_in),
+            previous_out: chain::entry_hash(header_out),
+        }
+    }
+
+    /// Checks that an entry links to the previous entry of the input and links it to the
+    /// previous entry of the output instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The packed entry, updated in place.
+    /// * `flags` - The format flags of the input.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, or an error of kind `InvalidData` if the chain of the
+    /// input is broken.
+    fn relink(&mut self, entry: &mut [u8], flags: &FormatFlags) -> std::io::Result<()> {
+        // The link follows the entry type, the timestamp and the sequence number
+        let start = 1 + if flags.no_timestamp { 0 } else { 8 } + if flags.sequence { 4 } else { 0 };
+        let link = start..start + chain::HASH_SIZE;
+        if entry[link.clone()] != self.previous_in {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Entry hash chain is broken, entries were removed, inserted or altered",
+            ));
+        }
+        self.previous_in = chain::entry_hash(entry);
+        entry[link].copy_from_slice(&self.previous_out);
+        self.previous_out = chain::entry_hash(entry);
+        Ok(())
+    }
+}
+
+/// Entries collected for the next block written by `recompress`.
+#[cfg(all(feature = "logger", feature = "parser"))]
+#[derive(Default)]
+struct PendingBlock {
+    entries: Vec<u8>,
+    entry_count: u32,
+    first_timestamp_us: u64,
+}
+
+#[cfg(all(feature = "logger", feature = "parser"))]
+impl PendingBlock {
+    /// Adds a packed entry to the block.
+    fn push(&mut self, entry: &[u8], flags: &FormatFlags) {
+        if self.entry_count == 0 && !flags.no_timestamp {
+            // The timestamp follows the entry type unless only MAVLink is logged
+            let start = usize::from(!flags.mavlink_only);
+            self.first_timestamp_us =
+                u64::from_le_bytes(entry[start..start + 8].try_into().unwrap());
+        }
+        self.entries.extend_from_slice(entry);
+        self.entry_count += 1;
+    }
+
+    /// Compresses the entries into a block and starts the next block.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the packed block, empty if the block has no entries, or an
+    /// `io::Error` if compression failed.
+    fn take(&mut self, level: i32) -> std::io::Result<Vec<u8>> {
+        if self.entry_count == 0 {
+            return Ok(Vec::new());
+        }
+        let block = std::mem::take(self);
+        encode_block_with_level(
+            &block.entries,
+            block.entry_count,
+            block.first_timestamp_us,
+            level,
+        )
+    }
+}
+
+/// Reads the next packed entry from a stream of entries, using only the entry framing.
+///
+/// # Arguments
+///
+/// * `reader` - The stream of entries.
+/// * `flags` - The format flags of the log the entries belong to.
+///
+/// # Returns
+///
+/// A `Result` containing the packed entry, or `None` at the end of the stream or if the last
+/// entry is cut short.
+#[cfg(all(feature = "logger", feature = "parser"))]
+fn read_packed_entry<R: Read>(
+    reader: &mut R,
+    flags: &FormatFlags,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let size_len = if flags.mavlink_only {
+        frame::LENGTH_PEEK_SIZE
+    } else if flags.large_entries {
+        4
+    } else {
+        2
+    };
+    let fields_len = usize::from(!flags.mavlink_only)
+        + if flags.no_timestamp { 0 } else { 8 }
+        + if flags.sequence { 4 } else { 0 }
+        + if flags.hash_chain { 8 } else { 0 }
+        + size_len;
+    let mut entry: Vec<u8> = vec![0u8; fields_len];
+    if !fill(reader, &mut entry)? {
+        return Ok(None);
+    }
+    let size_field = &entry[fields_len - size_len..];
+    let payload_len = if flags.mavlink_only {
+        // The length of a MAVLink frame is read from the start of the frame itself
+        frame::frame_len(size_field)
+            .map(|frame_len| frame_len - frame::LENGTH_PEEK_SIZE)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Entry is not a MAVLink frame",
+                )
+            })?
+    } else {
+        let mut size_raw = [0u8; 4];
+        size_raw[..size_len].copy_from_slice(size_field);
+        u32::from_le_bytes(size_raw) as usize
+    };
+    if payload_len > MAX_BLOCK_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Entry of {payload_len} bytes does not fit in a block"),
+        ));
+    }
+    entry.resize(fields_len + payload_len, 0);
+    if !fill(reader, &mut entry[fields_len..])? {
+        return Ok(None);
+    }
+    Ok(Some(entry))
+}
+
+/// Fills the buffer completely.
+///
+/// # Returns
+///
+/// `true` if the buffer was filled, `false` if the end of the data was reached first.
+#[cfg(all(feature = "logger", feature = "parser"))]
+fn fill<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(all(test, feature = "logger", feature = "parser"))]
+mod tests {
+    use super::*;
+
+    /// Builds three blocks of ten 8 byte entries each.
+    fn blocks() -> Vec<Vec<u8>> {
+        (0..3u64)
+            .map(|block| {
+                let entries: Vec<u8> = (0..10u64)
+                    .flat_map(|i| (block * 10 + i).to_le_bytes())
+                    .collect();
+                encode_block(&entries, 10, block * 1000).unwrap()
+            })
+            .collect()
+    }
+
+    /// Tests that intact blocks read back as one stream of entries.
+    #[test]
+    fn test_read_blocks() {
+        let data: Vec<u8> = blocks().concat();
+        let mut entries: Vec<u8> = Vec::new();
+        BlockReader::new(data.as_slice())
+            .read_to_end(&mut entries)
+            .unwrap();
+        let expected: Vec<u8> = (0..30u64).flat_map(|i| i.to_le_bytes()).collect();
+        assert_eq!(entries, expected);
+
+        let index = scan_blocks(data.as_slice()).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index[1].offset, blocks()[0].len() as u64);
+        assert_eq!(index[2].header.first_timestamp_us, 2000);
+        assert_eq!(index[2].header.entry_count, 10);
+    }
+
+    /// Tests that a corrupted block is skipped and the following blocks are still read.
+    #[test]
+    fn test_skip_corrupt_block() {
+        let blocks = blocks();
+        let mut data: Vec<u8> = blocks.concat();
+        // Corrupt the compressed entries of the first block
+        data[BlockHeader::SIZE + 2] ^= 0xFF;
+
+        let mut reader = BlockReader::new(data.as_slice());
+        let mut entries: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut entries).unwrap();
+        let expected: Vec<u8> = (10..30u64).flat_map(|i| i.to_le_bytes()).collect();
+        assert_eq!(entries, expected);
+        assert_eq!(reader.corrupt_blocks(), 1);
+
+        // A truncated last block is dropped
+        let truncated = &blocks.concat()[..data.len() - 3];
+        let index = scan_blocks(truncated).unwrap();
+        assert_eq!(index.len(), 2);
+    }
+}
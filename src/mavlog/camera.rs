@@ -0,0 +1,153 @@
+//! This module extracts camera images logged with `RotatingMavLogger::write_camera_image`.
+//!
+//! An image is stored as a blob written directly after the CAMERA_IMAGE_CAPTURED message that
+//! reports it. The message provides the geotag of the image.
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use mavlink::Message;
+
+use crate::fields;
+use crate::mav_parser::{MavParser, for_each_entry};
+
+/// Name of the geotag file written alongside the extracted images.
+pub const GEOTAG_FILE_NAME: &str = "geotags.csv";
+
+/// A camera image extracted from a log along with its geotag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedImage {
+    /// Path the image was written to.
+    pub path: PathBuf,
+    /// Timestamp of the log entry, if the log provides timestamps.
+    pub timestamp_us: Option<u64>,
+    /// MAVLink system id of the camera.
+    pub system_id: u8,
+    /// MAVLink component id of the camera.
+    pub component_id: u8,
+    /// Camera id within the component, 0 if the component has a single camera.
+    pub camera_id: u8,
+    /// Zero based index of the image within the capture sequence.
+    pub image_index: i32,
+    /// Capture time as a Unix timestamp in microseconds, 0 if unknown.
+    pub time_utc_us: u64,
+    /// Latitude where the image was taken in degrees.
+    pub latitude_deg: f64,
+    /// Longitude where the image was taken in degrees.
+    pub longitude_deg: f64,
+    /// Altitude (MSL) where the image was taken in meters.
+    pub altitude_m: f64,
+    /// Altitude above ground where the image was taken in meters.
+    pub relative_altitude_m: f64,
+}
+
+/// Geotag read from a CAMERA_IMAGE_CAPTURED message, waiting for the image that follows it.
+struct PendingImage {
+    timestamp_us: Option<u64>,
+    system_id: u8,
+    component_id: u8,
+    payload: [u8; fields::MAX_PAYLOAD_SIZE],
+}
+
+impl PendingImage {
+    /// Completes the image description once the image has been written to `path`.
+    fn into_image(self, path: PathBuf) -> CapturedImage {
+        CapturedImage {
+            path,
+            timestamp_us: self.timestamp_us,
+            system_id: self.system_id,
+            component_id: self.component_id,
+            camera_id: self.camera_id(),
+            image_index: self.image_index(),
+            time_utc_us: fields::read_u64(&self.payload, 0),
+            latitude_deg: fields::read_i32(&self.payload, 12) as f64 / 1e7,
+            longitude_deg: fields::read_i32(&self.payload, 16) as f64 / 1e7,
+            altitude_m: fields::read_i32(&self.payload, 20) as f64 / 1e3,
+            relative_altitude_m: fields::read_i32(&self.payload, 24) as f64 / 1e3,
+        }
+    }
+
+    fn image_index(&self) -> i32 {
+        fields::read_i32(&self.payload, 44)
+    }
+
+    fn camera_id(&self) -> u8 {
+        fields::read_u8(&self.payload, 48)
+    }
+
+    /// Name of the file the image is written to, unique per camera and image index.
+    fn file_name(&self) -> String {
+        format!(
+            "sys{}_comp{}_cam{}_{:05}.jpg",
+            self.system_id,
+            self.component_id,
+            self.camera_id(),
+            self.image_index()
+        )
+    }
+}
+
+/// Reads a full log and writes every camera image it holds to a directory.
+///
+/// Images are linked to the CAMERA_IMAGE_CAPTURED message directly preceding them. Blobs that
+/// do not follow such a message are ignored, as are messages whose image is missing. The
+/// geotags of the extracted images are written to `GEOTAG_FILE_NAME` in the same directory as
+/// comma separated values with a header row.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `directory`: The directory to write the images to. It is expected to exist. Files with the
+///   same names are overwritten.
+///
+/// # Returns
+/// The extracted images in log order.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or a file could not be written.
+pub fn extract_images<P: MavParser + ?Sized>(
+    parser: &mut P,
+    directory: &Path,
+) -> std::io::Result<Vec<CapturedImage>> {
+    let mut images: Vec<CapturedImage> = Vec::new();
+    let mut pending: Option<PendingImage> = None;
+    for_each_entry(parser, |entry| {
+        let pending_image = pending.take();
+        if let (Some(header), Some(msg)) = (entry.mav_header, entry.mav_message) {
+            if msg.message_id() == fields::CAMERA_IMAGE_CAPTURED_ID {
+                pending = Some(PendingImage {
+                    timestamp_us: entry.timestamp,
+                    system_id: header.system_id,
+                    component_id: header.component_id,
+                    payload: fields::payload(&msg),
+                });
+            }
+            return Ok(());
+        }
+        if let (Some(pending_image), Some(blob)) = (pending_image, entry.blob) {
+            let path = directory.join(pending_image.file_name());
+            std::fs::write(&path, &blob.data)?;
+            images.push(pending_image.into_image(path));
+        }
+        Ok(())
+    })?;
+
+    let mut geotags = File::create(directory.join(GEOTAG_FILE_NAME))?;
+    writeln!(
+        geotags,
+        "file,time_utc_us,latitude_deg,longitude_deg,altitude_m,relative_altitude_m"
+    )?;
+    for image in &images {
+        let file_name = image.path.file_name().unwrap_or_default().to_string_lossy();
+        writeln!(
+            geotags,
+            "{},{},{:.7},{:.7},{:.3},{:.3}",
+            file_name,
+            image.time_utc_us,
+            image.latitude_deg,
+            image.longitude_deg,
+            image.altitude_m,
+            image.relative_altitude_m
+        )?;
+    }
+    Ok(images)
+}
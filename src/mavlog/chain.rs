@@ -0,0 +1,22 @@
+//! Hashing of entries for hash-chained log files.
+//!
+//! Each entry of a hash-chained log records the truncated SHA-256 hash of the complete bytes of
+//! the previous entry. The first entry written by a logger records the hash of the file header.
+use sha2::{Digest, Sha256};
+
+/// Size of the truncated entry hash in bytes.
+pub const HASH_SIZE: usize = 8;
+
+/// Computes the truncated hash of an entry or file header.
+///
+/// # Arguments
+/// - `bytes`: The complete bytes of the entry as stored in the file.
+///
+/// # Returns
+/// The first `HASH_SIZE` bytes of the SHA-256 hash of `bytes`.
+pub fn entry_hash(bytes: &[u8]) -> [u8; HASH_SIZE] {
+    let digest = Sha256::digest(bytes);
+    let mut hash = [0u8; HASH_SIZE];
+    hash.copy_from_slice(&digest[..HASH_SIZE]);
+    hash
+}
@@ -0,0 +1,287 @@
+//! This module keeps log files of every released format version readable.
+//!
+//! Each format version has its own unpacker for the fixed part of the file header. The parser
+//! dispatches on the format version recorded in the header, so layout changes in newer versions
+//! never affect how archived files are read. `upgrade` rewrites an archived file in the current
+//! format version.
+//!
+//! Format version 1 only knew the `mavlink_only` and `no_timestamp` format flags, the raw,
+//! MAVLink and text entry types and 16-bit entry sizes, and had no footer. Version 2 added the
+//! format flags from `sequence` to `large_entries`, with the encryption header and dictionary id
+//! they bring to the file header, entry types 3 to 9, 32-bit entry sizes and the footer. Bits of
+//! a version 1 header that only have a meaning in version 2 are never read as the flags of
+//! version 2.
+use std::fs::File;
+use std::io::{Read, Write};
+
+use super::header::{
+    FileHeader, FormatFlags, HeaderError, MavlinkMessageDefinition, try_unpack_string,
+};
+
+/// The format version written by the logger.
+pub const CURRENT_VERSION: u32 = FileHeader::FILE_FORMAT_VERSION;
+
+/// Every format version the parser can read, oldest first.
+pub const SUPPORTED_VERSIONS: &[u32] = &[1, 2];
+
+/// Offset of the format version in the file header, which stays fixed across versions.
+const VERSION_OFFSET: usize = 56;
+
+/// Unpackers for format version 1.
+mod v1 {
+    use uuid::Uuid;
+
+    use super::{
+        FileHeader, FormatFlags, HeaderError, MavlinkMessageDefinition, try_unpack_string,
+    };
+
+    /// Bits of the packed format flags that have a meaning in version 1, `mavlink_only` and
+    /// `no_timestamp`.
+    pub const KNOWN_BITS: u16 = 0x03;
+
+    /// Unpacks the fixed part of a version 1 file header, ignoring invalid fields like
+    /// `FileHeader::unpack`.
+    pub fn unpack_header(packed_data: &[u8; 108]) -> FileHeader {
+        let format_flags = u16::from_le_bytes(packed_data[60..62].try_into().unwrap());
+        FileHeader {
+            uuid: Uuid::from_bytes(packed_data[0..16].try_into().unwrap()),
+            timestamp_us: u64::from_le_bytes(packed_data[16..24].try_into().unwrap()),
+            src_application_id: try_unpack_string("src_application_id", &packed_data[24..56])
+                .unwrap_or_default(),
+            format_version: 1,
+            format_flags: FormatFlags::unpack(format_flags & KNOWN_BITS),
+            message_definition: MavlinkMessageDefinition::unpack(
+                packed_data[62..].try_into().unwrap(),
+            ),
+            encryption: None,
+            dictionary_id: None,
+        }
+    }
+
+    /// Unpacks and validates the fixed part of a version 1 file header.
+    pub fn try_unpack_header(packed_data: &[u8; 108]) -> Result<FileHeader, HeaderError> {
+        let src_application_id = try_unpack_string("src_application_id", &packed_data[24..56])?;
+        let format_flags = u16::from_le_bytes(packed_data[60..62].try_into().unwrap());
+        if format_flags & !KNOWN_BITS != 0 {
+            return Err(HeaderError::new(
+                "format_flags",
+                format!("only bits {KNOWN_BITS:#06x} in format version 1"),
+                format!("{format_flags:#06x}"),
+            ));
+        }
+        Ok(FileHeader {
+            uuid: Uuid::from_bytes(packed_data[0..16].try_into().unwrap()),
+            timestamp_us: u64::from_le_bytes(packed_data[16..24].try_into().unwrap()),
+            src_application_id,
+            format_version: 1,
+            format_flags: FormatFlags::unpack(format_flags),
+            message_definition: MavlinkMessageDefinition::try_unpack(
+                packed_data[62..].try_into().unwrap(),
+            )?,
+            encryption: None,
+            dictionary_id: None,
+        })
+    }
+}
+
+/// Reads the format version from the start of a file header.
+///
+/// # Arguments
+/// - `packed_data`: At least the first 60 bytes of a log file.
+///
+/// # Returns
+/// The format version, or `None` if the data is too short to hold it.
+pub fn format_version(packed_data: &[u8]) -> Option<u32> {
+    let bytes = packed_data.get(VERSION_OFFSET..VERSION_OFFSET + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Returns whether log files of a format version can be parsed.
+pub fn is_supported(version: u32) -> bool {
+    SUPPORTED_VERSIONS.contains(&version)
+}
+
+/// Unpacks the fixed part of a file header with the unpacker of its format version.
+///
+/// # Arguments
+/// - `packed_data`: The fixed part of the file header.
+///
+/// # Returns
+/// The `FileHeader`, or `None` if the format version is not supported.
+pub fn unpack_header(packed_data: &[u8; 108]) -> Option<FileHeader> {
+    match format_version(packed_data)? {
+        1 => Some(v1::unpack_header(packed_data)),
+        CURRENT_VERSION => Some(FileHeader::unpack(packed_data)),
+        _ => None,
+    }
+}
+
+/// Unpacks and validates the fixed part of a file header with the unpacker of its format
+/// version.
+///
+/// # Arguments
+/// - `packed_data`: The fixed part of the file header.
+///
+/// # Returns
+/// A `Result` containing the `FileHeader`.
+///
+/// # Errors
+/// Returns a `HeaderError` if the format version is not supported or a field is invalid.
+pub fn try_unpack_header(packed_data: &[u8; 108]) -> Result<FileHeader, HeaderError> {
+    match format_version(packed_data) {
+        Some(1) => v1::try_unpack_header(packed_data),
+        Some(CURRENT_VERSION) => FileHeader::try_unpack(packed_data),
+        version => Err(HeaderError::new(
+            "format_version",
+            format!("one of {SUPPORTED_VERSIONS:?}"),
+            version.map_or_else(|| String::from("nothing"), |version| version.to_string()),
+        )),
+    }
+}
+
+/// Rewrites a log file in the current format version.
+///
+/// Version 1 files are validated with the unpacker of version 1 and written with the current
+/// format version. Their message definitions and entries are kept as they are, since they are
+/// laid out the same way in the current version. Files already in the current format version
+/// are copied unchanged.
+///
+/// # Arguments
+/// - `path_in`: The log file to upgrade.
+/// - `path_out`: The path to write the upgraded log file to. It must differ from `path_in`.
+///
+/// # Returns
+/// A `Result` containing the format version of the input file, or an `io::Error`. An error of
+/// kind `InvalidData` is returned if the input is not a log file or its header is invalid for
+/// its format version, and of kind `Unsupported` if its format version is unknown.
+pub fn upgrade(path_in: &str, path_out: &str) -> std::io::Result<u32> {
+    let mut file_in = File::open(path_in)?;
+    let mut fixed = [0u8; FileHeader::MIN_SIZE];
+    let too_short = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "File is too short to be a log file",
+        )
+    };
+    file_in.read_exact(&mut fixed).map_err(|_| too_short())?;
+    let version = format_version(&fixed).ok_or_else(too_short)?;
+    match version {
+        1 => {
+            v1::try_unpack_header(&fixed)?;
+            fixed[VERSION_OFFSET..VERSION_OFFSET + 4]
+                .copy_from_slice(&CURRENT_VERSION.to_le_bytes());
+            let mut file_out = File::create(path_out)?;
+            file_out.write_all(&fixed)?;
+            std::io::copy(&mut file_in, &mut file_out)?;
+        }
+        CURRENT_VERSION => {
+            std::fs::copy(path_in, path_out)?;
+        }
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("Unsupported file format version {version}"),
+            ));
+        }
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the fixed part of a file header with the given format version.
+    fn header_bytes(version: u32) -> [u8; 108] {
+        let mut packed = [0u8; 108];
+        packed[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(&version.to_le_bytes());
+        packed[62..66].copy_from_slice(&2u32.to_le_bytes()); // MAVLink major version
+        packed
+    }
+
+    /// Tests that headers are unpacked by the unpacker of their version.
+    #[test]
+    fn test_unpack_header_dispatch() {
+        assert_eq!(format_version(&header_bytes(1)), Some(1));
+        assert_eq!(format_version(&[0u8; 10]), None);
+        assert!(is_supported(1));
+        assert!(is_supported(CURRENT_VERSION));
+
+        for version in [1, CURRENT_VERSION] {
+            let header = unpack_header(&header_bytes(version)).unwrap();
+            assert_eq!(header.format_version, version);
+            assert_eq!(header.message_definition.version_major, 2);
+            assert_eq!(
+                try_unpack_header(&header_bytes(version))
+                    .unwrap()
+                    .format_version,
+                version
+            );
+        }
+        assert!(unpack_header(&header_bytes(0)).is_none());
+        assert!(unpack_header(&header_bytes(7)).is_none());
+        let error = try_unpack_header(&header_bytes(7)).unwrap_err();
+        assert_eq!(error.field, "format_version");
+        assert_eq!(error.found, "7");
+    }
+
+    /// Tests that flags added in version 2 are not read from version 1 headers.
+    #[test]
+    fn test_unpack_header_v1_flags() {
+        let with_flags = |version: u32| {
+            let mut packed = header_bytes(version);
+            packed[60..62].copy_from_slice(&0x0012u16.to_le_bytes()); // hash_chain, no_timestamp
+            packed
+        };
+        let header = unpack_header(&with_flags(1)).unwrap();
+        assert!(header.format_flags.no_timestamp);
+        assert!(!header.format_flags.hash_chain);
+        let error = try_unpack_header(&with_flags(1)).unwrap_err();
+        assert_eq!(error.field, "format_flags");
+        assert_eq!(error.found, "0x0012");
+
+        let header = try_unpack_header(&with_flags(2)).unwrap();
+        assert!(header.format_flags.no_timestamp);
+        assert!(header.format_flags.hash_chain);
+    }
+
+    /// Tests upgrading version 1, current, unknown and truncated files.
+    #[test]
+    fn test_upgrade() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path_in = dir.path().join("in.mav");
+        let path_in = path_in.to_str().unwrap();
+        let path_out = dir.path().join("out.mav");
+        let path_out = path_out.to_str().unwrap();
+
+        let mut content = header_bytes(1).to_vec();
+        content.extend_from_slice(b"entries");
+        std::fs::write(path_in, &content).unwrap();
+        assert_eq!(upgrade(path_in, path_out).unwrap(), 1);
+        let upgraded = std::fs::read(path_out).unwrap();
+        assert_eq!(format_version(&upgraded), Some(CURRENT_VERSION));
+        assert_eq!(upgraded[..VERSION_OFFSET], content[..VERSION_OFFSET]);
+        assert_eq!(
+            upgraded[VERSION_OFFSET + 4..],
+            content[VERSION_OFFSET + 4..]
+        );
+
+        std::fs::write(path_in, &upgraded).unwrap();
+        assert_eq!(upgrade(path_in, path_out).unwrap(), CURRENT_VERSION);
+        assert_eq!(std::fs::read(path_out).unwrap(), upgraded);
+
+        let mut invalid = header_bytes(1);
+        invalid[60] = 0x10;
+        std::fs::write(path_in, invalid).unwrap();
+        let error = upgrade(path_in, path_out).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        std::fs::write(path_in, header_bytes(7)).unwrap();
+        let error = upgrade(path_in, path_out).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+
+        std::fs::write(path_in, b"short").unwrap();
+        let error = upgrade(path_in, path_out).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
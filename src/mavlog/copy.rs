@@ -0,0 +1,387 @@
+//! This module defines a pass-through copy of entries from one .mav log to another.
+//!
+//! Entries are copied with only their framing read, their payloads are never decoded. This makes
+//! filtering or merging logs fast when the message contents do not matter, and it preserves
+//! MAVLink frames byte for byte, signatures included.
+//!
+//! A copy can be clipped to a time window and shifted in time in the same pass with a
+//! `CopyWindow`, such as to extract five minutes of a flight starting at t=0 with `convert`.
+use mavlink::Message;
+use mavlink::error::MessageReadError;
+
+use super::header::{FileHeader, FormatFlags};
+use super::logger::{EntryType, RotatingMavLogger};
+use super::parser::{MavLogParser, RawEntry, read_header};
+
+/// A time window entries are clipped to and the offset their timestamps are shifted by.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CopyWindow {
+    /// The first timestamp copied in microseconds, `None` to copy from the start of the log.
+    pub start_us: Option<u64>,
+    /// The timestamp the window ends at, excluded, in microseconds. `None` to copy until the end
+    /// of the log.
+    pub end_us: Option<u64>,
+    /// The offset added to the timestamps of the copied entries in microseconds.
+    pub offset_us: i64,
+}
+
+impl CopyWindow {
+    /// Creates a window copying the entries timestamped in a time range, without shifting them.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_us` - The first timestamp copied in microseconds.
+    /// * `end_us` - The timestamp the window ends at, excluded, in microseconds.
+    pub fn between(start_us: u64, end_us: u64) -> Self {
+        Self {
+            start_us: Some(start_us),
+            end_us: Some(end_us),
+            offset_us: 0,
+        }
+    }
+
+    /// Shifts the copied entries so that the window starts at a timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_us` - The timestamp the start of the window is shifted to, such as 0. Without a
+    ///     window start, the offset is left unchanged.
+    pub fn shifted_to(mut self, start_us: u64) -> Self {
+        if let Some(window_start) = self.start_us {
+            self.offset_us = start_us as i64 - window_start as i64;
+        }
+        self
+    }
+
+    /// Checks whether an entry timestamp is in the window.
+    ///
+    /// Entries without a timestamp are only in a window without bounds.
+    pub fn contains(&self, timestamp_us: Option<u64>) -> bool {
+        match timestamp_us {
+            Some(timestamp) => {
+                self.start_us.is_none_or(|start| timestamp >= start)
+                    && self.end_us.is_none_or(|end| timestamp < end)
+            }
+            None => self.start_us.is_none() && self.end_us.is_none(),
+        }
+    }
+
+    /// Returns a timestamp shifted by the window offset, saturating at 0 and `u64::MAX`.
+    pub fn shift(&self, timestamp_us: u64) -> u64 {
+        timestamp_us.saturating_add_signed(self.offset_us)
+    }
+
+    /// Returns an error of kind `InvalidInput` if the window is bounded or shifts entries, which
+    /// cannot be applied to a log without timestamps.
+    fn check_untimed(&self) -> std::io::Result<()> {
+        if self.start_us.is_some() || self.end_us.is_some() || self.offset_us != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "A bounded or shifted window cannot be applied to a log without timestamps",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Copies the entries of a log accepted by a filter to a logger.
+///
+/// Entry timestamps are preserved, entries of logs without timestamps are timestamped by the
+/// logger clock. The logger applies its own format flags, so entries are re-encrypted,
+/// recompressed and renumbered as it is configured. Entries of unknown types are copied as raw
+/// data, and only MAVLink entries are copied to a MAVLink only logger. Blob fragments are copied
+/// one by one with their blob id, which may then clash with blobs written to the logger directly.
+///
+/// Like `for_each_entry`, copying skips corrupted entries and continues past appended logs
+/// until the end of the log.
+///
+/// # Arguments
+///
+/// * `parser` - The parser to read entries from. It is consumed until the end of the log.
+/// * `logger` - The logger to write the entries to.
+/// * `filter` - Returns whether an entry is copied.
+///
+/// # Returns
+///
+/// A `Result` containing the number of entries copied or an `io::Error`.
+pub fn copy_entries<M, F>(
+    parser: &mut MavLogParser<M>,
+    logger: &mut RotatingMavLogger,
+    filter: F,
+) -> std::io::Result<u64>
+where
+    M: Message + 'static,
+    F: Fn(&RawEntry) -> bool,
+{
+    copy_window(parser, logger, &CopyWindow::default(), filter)
+}
+
+/// Copies the entries of a log in a time window and accepted by a filter to a logger, shifting
+/// their timestamps.
+///
+/// Entries are copied like with `copy_entries`, except that only the entries timestamped in the
+/// window are copied, with the window offset added to their timestamps. The filter is given
+/// the entries with their timestamps as logged.
+///
+/// Entries without a timestamp can only be copied with the default window, which neither
+/// bounds nor shifts entries.
+///
+/// # Arguments
+///
+/// * `parser` - The parser to read entries from.
+/// * `logger` - The logger to write the entries to.
+/// * `window` - The window to clip the entries to and the offset to shift them by.
+/// * `filter` - Returns whether an entry is copied.
+///
+/// # Returns
+///
+/// A `Result` containing the number of entries copied or an `io::Error`, of kind `InvalidInput`
+/// if the window is bounded or shifts entries and the log has entries without a timestamp.
+pub fn copy_window<M, F>(
+    parser: &mut MavLogParser<M>,
+    logger: &mut RotatingMavLogger,
+    window: &CopyWindow,
+    filter: F,
+) -> std::io::Result<u64>
+where
+    M: Message + 'static,
+    F: Fn(&RawEntry) -> bool,
+{
+    let mavlink_only = logger.header().format_flags.mavlink_only;
+    let mut copied: u64 = 0;
+    loop {
+        let entry = match parser.read_raw_entry() {
+            Ok(entry) => entry,
+            Err(MessageReadError::Io(e)) => match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => return Ok(copied),
+                // corrupted entry content or an appended log, move on to the next entry
+                std::io::ErrorKind::InvalidData => continue,
+                _ => return Err(e),
+            },
+            Err(MessageReadError::Parse(_)) => continue,
+        };
+        if entry.timestamp.is_none() {
+            window.check_untimed()?;
+        }
+        let entry_type = EntryType::try_from(entry.entry_type).unwrap_or(EntryType::Raw);
+        if (mavlink_only && entry_type != EntryType::Mavlink)
+            || !window.contains(entry.timestamp)
+            || !filter(&entry)
+        {
+            continue;
+        }
+        let timestamp = entry.timestamp.map(|timestamp| window.shift(timestamp));
+        logger.write_at(entry_type, timestamp, &entry.payload)?;
+        copied += 1;
+    }
+}
+
+/// Converts a .mav log to a new log holding the entries in a time window, shifted in time.
+///
+/// The new log has the format flags and message definition of the source log, but is written
+/// unencrypted, uncompressed and without a hash chain. Its header timestamp is the shifted
+/// start of the window, or the shifted header timestamp of the source log for a window without
+/// a start, so that the header agrees with the shifted entries. A footer is written once the
+/// entries are copied.
+///
+/// # Arguments
+///
+/// * `source_path` - The path of the log to convert.
+/// * `base_path` - The base path of the new log, see `RotatingMavLogger::new`. It is not rotated.
+/// * `window` - The window to clip the entries to and the offset to shift them by.
+/// * `filter` - Returns whether an entry is copied, see `copy_window`.
+///
+/// # Returns
+///
+/// A `Result` containing the number of entries copied or an `io::Error`, of kind `Unsupported`
+/// if the source log is encrypted, or of kind `InvalidInput` if the window is bounded or shifts
+/// entries and the source log has no timestamps.
+pub fn convert<M, F>(
+    source_path: &str,
+    base_path: &str,
+    window: &CopyWindow,
+    filter: F,
+) -> std::io::Result<u64>
+where
+    M: Message + 'static,
+    F: Fn(&RawEntry) -> bool,
+{
+    let source_header = read_header(source_path)?;
+    if source_header.format_flags.encrypted {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Encrypted logs cannot be converted",
+        ));
+    }
+    let source_flags = source_header.format_flags;
+    if source_flags.no_timestamp {
+        window.check_untimed()?;
+    }
+    let format_flags = FormatFlags {
+        mavlink_only: source_flags.mavlink_only,
+        no_timestamp: source_flags.no_timestamp,
+        sequence: source_flags.sequence,
+        large_entries: source_flags.large_entries,
+        ..FormatFlags::default()
+    };
+    let mut header = FileHeader::new(format_flags, source_header.message_definition);
+    header.timestamp_us = window.shift(window.start_us.unwrap_or(source_header.timestamp_us));
+
+    let mut parser = MavLogParser::<M>::try_new(source_path)?;
+    let mut logger = RotatingMavLogger::with_header(base_path, u64::MAX, 0, header)?;
+    let copied = copy_window(&mut parser, &mut logger, window, filter)?;
+    logger.finish()?;
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use mavlink::common::MavMessage;
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::clock::{BackwardsPolicy, Clock, ClockSource};
+    use crate::mav_logger::MavLogger;
+    use crate::mav_parser::for_each_entry;
+
+    fn frame(msg: MavMessage) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader::default(),
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    /// Test that filtered entries are copied with their timestamps and payloads unchanged.
+    #[test]
+    fn test_copy_entries() {
+        let dir = TempDir::new().unwrap();
+        let source_path = dir.path().join("source.mav");
+        let source_path = source_path.to_str().unwrap();
+        let mut source = RotatingMavLogger::new(source_path, 100000, 0, None, None).unwrap();
+        source
+            .write_mavlink(frame(MavMessage::HEARTBEAT(Default::default())))
+            .unwrap();
+        source
+            .write_mavlink(frame(MavMessage::ATTITUDE(Default::default())))
+            .unwrap();
+        source.write_text("note").unwrap();
+        drop(source);
+
+        let copy_path = dir.path().join("copy.mav");
+        let copy_path = copy_path.to_str().unwrap();
+        let flags = FormatFlags {
+            sequence: true,
+            ..Default::default()
+        };
+        let mut copy = RotatingMavLogger::new(copy_path, 100000, 0, Some(flags), None).unwrap();
+        let mut parser = MavLogParser::<MavMessage>::new(source_path);
+        let copied = copy_entries(&mut parser, &mut copy, |entry| {
+            entry.message_id() != Some(30)
+        })
+        .unwrap();
+        drop(copy);
+        assert_eq!(copied, 2);
+
+        let mut source = MavLogParser::<MavMessage>::new(source_path);
+        let mut expected = Vec::new();
+        for_each_entry(&mut source, |entry| {
+            expected.push((entry.timestamp, entry.mav_message, entry.text));
+            Ok(())
+        })
+        .unwrap();
+        expected.remove(1);
+        let mut entries = Vec::new();
+        for_each_entry(&mut MavLogParser::<MavMessage>::new(copy_path), |entry| {
+            entries.push((entry.timestamp, entry.mav_message, entry.text));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(entries, expected);
+    }
+
+    /// Test that a conversion clips entries to the window and shifts them and the header.
+    #[test]
+    fn test_convert_window() {
+        let dir = TempDir::new().unwrap();
+        let source_path = dir.path().join("source.mav");
+        let source_path = source_path.to_str().unwrap();
+        let mut source = RotatingMavLogger::new(source_path, 100000, 0, None, None).unwrap();
+        let time = AtomicU64::new(1_700_000_000_000_000);
+        source.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || {
+                time.fetch_add(1_000_000, Ordering::Relaxed)
+            })),
+            BackwardsPolicy::Allow,
+        ));
+        for _ in 0..5 {
+            source
+                .write_mavlink(frame(MavMessage::HEARTBEAT(Default::default())))
+                .unwrap();
+        }
+        drop(source);
+
+        let copy_path = dir.path().join("clip.mav");
+        let copy_path = copy_path.to_str().unwrap();
+        let window =
+            CopyWindow::between(1_700_000_001_000_000, 1_700_000_003_000_000).shifted_to(0);
+        let copied = convert::<MavMessage, _>(source_path, copy_path, &window, |_| true).unwrap();
+        assert_eq!(copied, 2);
+
+        assert_eq!(read_header(copy_path).unwrap().timestamp_us, 0);
+        let mut timestamps = Vec::new();
+        for_each_entry(&mut MavLogParser::<MavMessage>::new(copy_path), |entry| {
+            timestamps.extend(entry.timestamp);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(timestamps, vec![0, 1_000_000]);
+    }
+
+    /// Test that a bounded or shifted window is rejected for a log without timestamps instead
+    /// of producing an empty log.
+    #[test]
+    fn test_convert_window_without_timestamps() {
+        let dir = TempDir::new().unwrap();
+        let source_path = dir.path().join("source.mav");
+        let source_path = source_path.to_str().unwrap();
+        let flags = FormatFlags {
+            no_timestamp: true,
+            ..Default::default()
+        };
+        let mut source = RotatingMavLogger::new(source_path, 100000, 0, Some(flags), None).unwrap();
+        source
+            .write_mavlink(frame(MavMessage::HEARTBEAT(Default::default())))
+            .unwrap();
+        drop(source);
+
+        let copy_path = dir.path().join("clip.mav");
+        let copy_path = copy_path.to_str().unwrap();
+        let window = CopyWindow::between(0, 1_000_000);
+        let error =
+            convert::<MavMessage, _>(source_path, copy_path, &window, |_| true).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!std::path::Path::new(copy_path).exists());
+
+        let mut copy = RotatingMavLogger::new(copy_path, 100000, 0, Some(flags), None).unwrap();
+        let mut parser = MavLogParser::<MavMessage>::new(source_path);
+        let shifted = CopyWindow {
+            offset_us: 1_000,
+            ..Default::default()
+        };
+        let error = copy_window(&mut parser, &mut copy, &shifted, |_| true).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let copied = convert::<MavMessage, _>(
+            source_path,
+            dir.path().join("copy.mav").to_str().unwrap(),
+            &CopyWindow::default(),
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(copied, 1);
+    }
+}
@@ -0,0 +1,286 @@
+//! This module provides zstd dictionary compression of .mav log entry payloads.
+//!
+//! MAVLink frames are too small to compress well on their own, but they are highly repetitive
+//! across a log. A dictionary trained from sample logs captures that repetition so each entry can
+//! be compressed independently. The file header records the id of the dictionary, which is
+//! loaded from a dictionary directory when the log is parsed.
+//! See docs/mav_log_file_format.md for how compressed entries are stored.
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "parser")]
+use mavlink::{MAVLinkV2MessageRaw, Message};
+#[cfg(feature = "logger")]
+use zstd::bulk::Compressor;
+#[cfg(feature = "parser")]
+use zstd::bulk::Decompressor;
+
+#[cfg(feature = "parser")]
+use super::parser::MavLogParser;
+#[cfg(feature = "parser")]
+use crate::mav_parser::for_each_entry;
+
+/// Magic number starting every zstd dictionary.
+const DICTIONARY_MAGIC: [u8; 4] = [0x37, 0xA4, 0x30, 0xEC];
+/// File extension of dictionaries stored in a dictionary directory.
+const DICTIONARY_EXTENSION: &str = "zdict";
+/// Largest payload an entry can hold, bounding the size of a decompressed payload.
+#[cfg(feature = "parser")]
+const MAX_PAYLOAD_SIZE: usize = u16::MAX as usize;
+
+/// Compresses or decompresses the entry payloads of one log file with a zstd dictionary.
+pub struct EntryCompressor {
+    dictionary_id: u32,
+    #[cfg(feature = "logger")]
+    compressor: Compressor<'static>,
+    #[cfg(feature = "parser")]
+    decompressor: Decompressor<'static>,
+}
+
+impl EntryCompressor {
+    /// Creates a new `EntryCompressor` using the default compression level.
+    ///
+    /// # Arguments
+    ///
+    /// * `dictionary` - A zstd dictionary, as returned by `train_dictionary`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `EntryCompressor` or an `io::Error` of kind `InvalidInput`
+    /// if the dictionary has no id.
+    pub fn new(dictionary: &[u8]) -> std::io::Result<Self> {
+        Self::with_level(dictionary, zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    /// Creates a new `EntryCompressor` with an explicit compression level.
+    ///
+    /// # Arguments
+    ///
+    /// * `dictionary` - A zstd dictionary, as returned by `train_dictionary`.
+    /// * `level` - The zstd compression level. Only used when writing.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `EntryCompressor` or an `io::Error` of kind `InvalidInput`
+    /// if the dictionary has no id.
+    #[cfg_attr(not(feature = "logger"), allow(unused_variables))]
+    pub fn with_level(dictionary: &[u8], level: i32) -> std::io::Result<Self> {
+        let dictionary_id = dictionary_id(dictionary).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Not a zstd dictionary with an id",
+            )
+        })?;
+        #[cfg(feature = "logger")]
+        let mut compressor = Compressor::with_dictionary(level, dictionary)?;
+        // The dictionary id is recorded once in the file header rather than in every entry
+        #[cfg(feature = "logger")]
+        compressor.include_dictid(false)?;
+        Ok(Self {
+            dictionary_id,
+            #[cfg(feature = "logger")]
+            compressor,
+            #[cfg(feature = "parser")]
+            decompressor: Decompressor::with_dictionary(dictionary)?,
+        })
+    }
+
+    /// Returns the id of the dictionary to record in the log file header.
+    pub fn dictionary_id(&self) -> u32 {
+        self.dictionary_id
+    }
+
+    /// Compresses an entry payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The entry payload.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the compressed payload.
+    #[cfg(feature = "logger")]
+    pub(crate) fn compress(&mut self, payload: &[u8]) -> std::io::Result<Vec<u8>> {
+        self.compressor.compress(payload)
+    }
+
+    /// Decompresses an entry payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `compressed` - The stored entry payload.
+    ///
+    /// # Returns
+    ///
+    /// The decompressed payload, or `None` if the payload is not valid compressed data.
+    #[cfg(feature = "parser")]
+    pub(crate) fn decompress(&mut self, compressed: &[u8]) -> Option<Vec<u8>> {
+        self.decompressor
+            .decompress(compressed, MAX_PAYLOAD_SIZE)
+            .ok()
+    }
+}
+
+/// Returns the id of a zstd dictionary.
+///
+/// # Arguments
+///
+/// * `dictionary` - The zstd dictionary.
+///
+/// # Returns
+///
+/// The dictionary id, or `None` if the data is not a zstd dictionary or has no id.
+pub fn dictionary_id(dictionary: &[u8]) -> Option<u32> {
+    if dictionary.len() < 8 || dictionary[0..4] != DICTIONARY_MAGIC {
+        return None;
+    }
+    let id = u32::from_le_bytes(dictionary[4..8].try_into().unwrap());
+    (id != 0).then_some(id)
+}
+
+/// Trains a zstd dictionary from entry payload samples.
+///
+/// # Arguments
+///
+/// * `samples` - Entry payloads representative of the logs to compress.
+/// * `max_size` - The maximum size of the dictionary in bytes. A few kilobytes is usually
+///   enough for MAVLink traffic.
+///
+/// # Returns
+///
+/// A `Result` containing the dictionary or an `io::Error` if there are too few samples.
+pub fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> std::io::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size)
+}
+
+/// Collects entry payload samples from an existing log for dictionary training.
+///
+/// MAVLink messages are serialized as MAVLink 2 frames, text and raw entries are used as is.
+///
+/// # Arguments
+///
+/// * `file_path` - The path of the sample log.
+///
+/// # Returns
+///
+/// A `Result` containing the samples or an `io::Error` if the log could not be read.
+///
+/// # Panics
+///
+/// Panics if the log cannot be opened by `MavLogParser::new`.
+#[cfg(feature = "parser")]
+pub fn samples_from_log<M: Message + 'static>(file_path: &str) -> std::io::Result<Vec<Vec<u8>>> {
+    let mut samples: Vec<Vec<u8>> = Vec::new();
+    let mut parser = MavLogParser::<M>::new(file_path);
+    for_each_entry(&mut parser, |entry| {
+        if let (Some(header), Some(message)) = (entry.mav_header, entry.mav_message) {
+            let mut raw = MAVLinkV2MessageRaw::new();
+            raw.serialize_message(header, &message);
+            samples.push(raw.raw_bytes().to_vec());
+        } else if let Some(text) = entry.text {
+            samples.push(text.into_bytes());
+        } else if let Some(raw) = entry.raw {
+            samples.push(raw);
+        }
+        Ok(())
+    })?;
+    Ok(samples)
+}
+
+/// Returns the path a dictionary is stored at within a dictionary directory.
+pub fn dictionary_path(directory: &Path, dictionary_id: u32) -> PathBuf {
+    directory.join(format!("{dictionary_id}.{DICTIONARY_EXTENSION}"))
+}
+
+/// Stores a dictionary in a dictionary directory under its id.
+///
+/// # Arguments
+///
+/// * `directory` - The dictionary directory. It is expected to exist.
+/// * `dictionary` - The zstd dictionary.
+///
+/// # Returns
+///
+/// A `Result` containing the path of the stored dictionary or an `io::Error`. An error of kind
+/// `InvalidInput` is returned if the dictionary has no id.
+pub fn save_dictionary(directory: &Path, dictionary: &[u8]) -> std::io::Result<PathBuf> {
+    let id = dictionary_id(dictionary).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Not a zstd dictionary with an id",
+        )
+    })?;
+    let path = dictionary_path(directory, id);
+    std::fs::write(&path, dictionary)?;
+    Ok(path)
+}
+
+/// Loads a dictionary from a dictionary directory.
+///
+/// # Arguments
+///
+/// * `directory` - The dictionary directory.
+/// * `dictionary_id` - The id recorded in the log file header.
+///
+/// # Returns
+///
+/// A `Result` containing the dictionary or an `io::Error` if it is missing. An error of kind
+/// `InvalidData` is returned if the stored file does not have the expected id.
+pub fn load_dictionary(directory: &Path, dictionary_id: u32) -> std::io::Result<Vec<u8>> {
+    let dictionary = std::fs::read(dictionary_path(directory, dictionary_id))?;
+    if self::dictionary_id(&dictionary) != Some(dictionary_id) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Stored dictionary does not have id {dictionary_id}"),
+        ));
+    }
+    Ok(dictionary)
+}
+
+#[cfg(all(test, feature = "logger", feature = "parser"))]
+mod tests {
+    use super::*;
+
+    /// Builds varied samples resembling small telemetry frames.
+    fn samples() -> Vec<Vec<u8>> {
+        (0..2000u32)
+            .map(|i| {
+                let mut sample = vec![253, 28, 0, 0, (i % 256) as u8, 1, 1, 33, 0, 0];
+                sample.extend_from_slice(&(i * 100).to_le_bytes());
+                sample.extend_from_slice(&(473_000_000 + i as i32 % 97).to_le_bytes());
+                sample.extend_from_slice(&(-1_223_000_000 - i as i32 % 89).to_le_bytes());
+                sample.extend_from_slice(&[0, 0, 0x10, 0x27, 0, 0, 0, 0, 0, 0, 0, 0]);
+                sample
+            })
+            .collect()
+    }
+
+    /// Tests that a trained dictionary round trips payloads and is stored under its id.
+    #[test]
+    fn test_train_and_round_trip() {
+        let dictionary = train_dictionary(&samples(), 4096).unwrap();
+        let id = dictionary_id(&dictionary).unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = save_dictionary(dir.path(), &dictionary).unwrap();
+        assert_eq!(path, dir.path().join(format!("{id}.zdict")));
+        let dictionary = load_dictionary(dir.path(), id).unwrap();
+
+        let mut compressor = EntryCompressor::new(&dictionary).unwrap();
+        assert_eq!(compressor.dictionary_id(), id);
+        let payload = &samples()[1234];
+        let compressed = compressor.compress(payload).unwrap();
+        assert!(compressed.len() < payload.len());
+        assert_eq!(compressor.decompress(&compressed).unwrap(), *payload);
+        assert!(compressor.decompress(&[1, 2, 3]).is_none());
+    }
+
+    /// Tests that data without a dictionary id is rejected.
+    #[test]
+    fn test_not_a_dictionary() {
+        assert_eq!(dictionary_id(b"not a dictionary"), None);
+        assert!(EntryCompressor::new(b"not a dictionary").is_err());
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(save_dictionary(dir.path(), b"not a dictionary").is_err());
+        assert!(load_dictionary(dir.path(), 7).is_err());
+    }
+}
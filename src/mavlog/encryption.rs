@@ -0,0 +1,204 @@
+//! This module provides authenticated encryption of .mav log entry payloads with AES-256-GCM.
+//!
+//! Only entry payloads are encrypted. The entry type, timestamp and sequence number stay readable
+//! but are authenticated as associated data, so they cannot be altered without detection.
+//! See docs/mav_log_file_format.md for the layout of encrypted entries.
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+use super::header::{EncryptionHeader, NonceStrategy};
+use crate::keys::KeyProvider;
+
+/// Size of an AES-GCM nonce in bytes.
+const NONCE_SIZE: usize = 12;
+/// Size of the entry counter stored with each entry by the `Counter` nonce strategy.
+const COUNTER_SIZE: usize = 8;
+
+/// Encrypts or decrypts the entry payloads of one log file.
+pub struct EntryCipher {
+    cipher: Aes256Gcm,
+    key_id: String,
+    nonce_strategy: NonceStrategy,
+    nonce_base: [u8; NONCE_SIZE],
+    counter: u64,
+}
+
+impl EntryCipher {
+    /// Creates a new `EntryCipher` for writing a log file.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_provider` - The provider to look the key up with.
+    /// * `key_id` - The id of the key to encrypt with. Recorded in the file header, at most 32
+    ///   bytes.
+    /// * `nonce_strategy` - How the nonce of each entry is chosen.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `EntryCipher` or an `io::Error` if the key id is too long or
+    /// the key is not available.
+    pub fn new(
+        key_provider: &dyn KeyProvider,
+        key_id: &str,
+        nonce_strategy: NonceStrategy,
+    ) -> std::io::Result<Self> {
+        if key_id.len() > 32 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Key id must be 32 bytes or less",
+            ));
+        }
+        let mut nonce_base = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce_base);
+        let header = EncryptionHeader {
+            key_id: String::from(key_id),
+            nonce_strategy,
+            nonce_base,
+        };
+        Self::from_header(&header, key_provider)
+    }
+
+    /// Creates a new `EntryCipher` for writing a log file with the current key of a provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_provider` - The provider to take the current key from.
+    /// * `nonce_strategy` - How the nonce of each entry is chosen.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `EntryCipher` or an `io::Error` of kind `NotFound` if the
+    /// provider does not designate a current key.
+    pub fn with_current_key(
+        key_provider: &dyn KeyProvider,
+        nonce_strategy: NonceStrategy,
+    ) -> std::io::Result<Self> {
+        let key_id = key_provider.current_key_id().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "The key provider has no current key",
+            )
+        })?;
+        Self::new(key_provider, &key_id, nonce_strategy)
+    }
+
+    /// Creates an `EntryCipher` matching the encryption header of an existing log file.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The encryption header of the log file.
+    /// * `key_provider` - The provider to look the key up with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `EntryCipher` or an `io::Error` of kind `NotFound` if the key is
+    /// not available.
+    pub fn from_header(
+        header: &EncryptionHeader,
+        key_provider: &dyn KeyProvider,
+    ) -> std::io::Result<Self> {
+        let key = key_provider.key(&header.key_id).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Encryption key {} is not available", header.key_id),
+            )
+        })?;
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+            key_id: header.key_id.clone(),
+            nonce_strategy: header.nonce_strategy,
+            nonce_base: header.nonce_base,
+            counter: 0,
+        })
+    }
+
+    /// Returns the encryption header to record in the log file.
+    pub fn header(&self) -> EncryptionHeader {
+        EncryptionHeader {
+            key_id: self.key_id.clone(),
+            nonce_strategy: self.nonce_strategy,
+            nonce_base: self.nonce_base,
+        }
+    }
+
+    /// Encrypts an entry payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `aad` - The entry fields preceding the size field, authenticated but not encrypted.
+    /// * `plaintext` - The entry payload.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the stored nonce part followed by the ciphertext and tag.
+    #[cfg(feature = "logger")]
+    pub(crate) fn encrypt(&mut self, aad: &[u8], plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+        let (nonce, mut sealed): ([u8; NONCE_SIZE], Vec<u8>) = match self.nonce_strategy {
+            NonceStrategy::Random => {
+                let mut nonce = [0u8; NONCE_SIZE];
+                OsRng.fill_bytes(&mut nonce);
+                (nonce, nonce.to_vec())
+            }
+            NonceStrategy::Counter => {
+                let counter = self.counter.to_le_bytes();
+                self.counter = self.counter.wrapping_add(1);
+                (self.counter_nonce(&counter), counter.to_vec())
+            }
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| std::io::Error::other("Failed to encrypt entry"))?;
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts and authenticates an entry payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `aad` - The entry fields preceding the size field.
+    /// * `sealed` - The stored entry payload.
+    ///
+    /// # Returns
+    ///
+    /// The decrypted payload, or `None` if the entry is truncated or fails authentication.
+    #[cfg(feature = "parser")]
+    pub(crate) fn decrypt(&self, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        let (nonce, ciphertext): ([u8; NONCE_SIZE], &[u8]) = match self.nonce_strategy {
+            NonceStrategy::Random => (
+                sealed.get(..NONCE_SIZE)?.try_into().ok()?,
+                &sealed[NONCE_SIZE..],
+            ),
+            NonceStrategy::Counter => (
+                self.counter_nonce(sealed.get(..COUNTER_SIZE)?),
+                &sealed[COUNTER_SIZE..],
+            ),
+        };
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .ok()
+    }
+
+    /// Builds the nonce for an entry counter by XORing it into the header nonce base.
+    fn counter_nonce(&self, counter: &[u8]) -> [u8; NONCE_SIZE] {
+        let mut nonce = self.nonce_base;
+        for (byte, counter_byte) in nonce.iter_mut().zip(counter) {
+            *byte ^= counter_byte;
+        }
+        nonce
+    }
+}
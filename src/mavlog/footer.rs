@@ -0,0 +1,142 @@
+//! This module defines the footer closing a finished .mav log file.
+//!
+//! A logger finished with `RotatingMavLogger::finish` appends a fixed size footer after the last
+//! entry, recording the timestamps of the first and last entries and the number of entries. The
+//! duration of a finished log is then read from the end of the file instead of parsing every
+//! entry. Logs that were not finished, such as rotated backups or logs cut short by a crash, have
+//! no footer.
+use std::time::Duration;
+
+/// Magic characters starting the footer.
+const MAGIC: &[u8; 6] = b"MAVEND";
+/// Version of the footer format written by this crate.
+pub const FOOTER_VERSION: u16 = 1;
+
+/// Summary of the entries of a finished log file.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct LogFooter {
+    /// Timestamp of the first entry in microseconds, 0 if entries have no timestamp.
+    pub first_timestamp_us: u64,
+    /// Timestamp of the last entry in microseconds, 0 if entries have no timestamp.
+    pub last_timestamp_us: u64,
+    /// Number of entries in the file. Each fragment of a blob is an entry.
+    pub entry_count: u64,
+}
+
+impl LogFooter {
+    /// Size of the packed footer in bytes.
+    pub const SIZE: usize = 36;
+
+    /// Returns the time spanned by the entries of the file.
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(
+            self.last_timestamp_us
+                .saturating_sub(self.first_timestamp_us),
+        )
+    }
+
+    /// Adds an entry to the summary.
+    ///
+    /// # Arguments
+    /// - `timestamp_us`: The entry timestamp, `None` if entries have no timestamp.
+    #[cfg(feature = "logger")]
+    pub(crate) fn add_entry(&mut self, timestamp_us: Option<u64>) {
+        if let Some(timestamp_us) = timestamp_us {
+            if self.entry_count == 0 {
+                self.first_timestamp_us = timestamp_us;
+            }
+            self.last_timestamp_us = timestamp_us;
+        }
+        self.entry_count += 1;
+    }
+
+    /// Packs the footer into bytes.
+    ///
+    /// The fields are packed in little-endian order after the magic characters and the footer
+    /// version, followed by the CRC-32 of all preceding bytes.
+    #[cfg(feature = "logger")]
+    pub fn pack(&self) -> [u8; LogFooter::SIZE] {
+        let mut packed = [0u8; LogFooter::SIZE];
+        packed[0..6].copy_from_slice(MAGIC);
+        packed[6..8].copy_from_slice(&FOOTER_VERSION.to_le_bytes());
+        packed[8..16].copy_from_slice(&self.first_timestamp_us.to_le_bytes());
+        packed[16..24].copy_from_slice(&self.last_timestamp_us.to_le_bytes());
+        packed[24..32].copy_from_slice(&self.entry_count.to_le_bytes());
+        let crc = crc32fast::hash(&packed[..32]);
+        packed[32..36].copy_from_slice(&crc.to_le_bytes());
+        packed
+    }
+
+    /// Unpacks a footer.
+    ///
+    /// # Arguments
+    /// - `packed_data`: At least `LogFooter::SIZE` bytes, the footer being the first of them.
+    ///
+    /// # Returns
+    /// The footer, or `None` if the bytes do not start with a footer of a known version with a
+    /// matching CRC.
+    #[cfg(feature = "parser")]
+    pub fn unpack(packed_data: &[u8]) -> Option<Self> {
+        let packed = packed_data.get(..LogFooter::SIZE)?;
+        let field = |start: usize| u64::from_le_bytes(packed[start..start + 8].try_into().unwrap());
+        let version = u16::from_le_bytes(packed[6..8].try_into().unwrap());
+        let crc = u32::from_le_bytes(packed[32..36].try_into().unwrap());
+        if &packed[0..6] != MAGIC
+            || version != FOOTER_VERSION
+            || crc32fast::hash(&packed[..32]) != crc
+        {
+            return None;
+        }
+        Some(LogFooter {
+            first_timestamp_us: field(8),
+            last_timestamp_us: field(16),
+            entry_count: field(24),
+        })
+    }
+
+    /// Reads the footer at the end of a log file.
+    ///
+    /// # Arguments
+    /// - `path`: Path to the log file.
+    ///
+    /// # Returns
+    /// The footer, or `None` if the file does not end with one.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the file cannot be read.
+    #[cfg(feature = "parser")]
+    pub fn read(path: &str) -> std::io::Result<Option<Self>> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = std::fs::File::open(path)?;
+        if file.metadata()?.len() < LogFooter::SIZE as u64 {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::End(-(LogFooter::SIZE as i64)))?;
+        let mut packed = [0u8; LogFooter::SIZE];
+        file.read_exact(&mut packed)?;
+        Ok(LogFooter::unpack(&packed))
+    }
+}
+
+#[cfg(all(test, feature = "logger", feature = "parser"))]
+mod tests {
+    use super::*;
+
+    /// Test that a footer survives packing and that a damaged footer is rejected.
+    #[test]
+    fn test_footer_pack_unpack() {
+        let mut footer = LogFooter::default();
+        footer.add_entry(Some(1_000));
+        footer.add_entry(Some(2_500));
+        footer.add_entry(Some(4_000));
+        assert_eq!(footer.entry_count, 3);
+        assert_eq!(footer.duration(), Duration::from_micros(3_000));
+
+        let mut packed = footer.pack();
+        assert_eq!(LogFooter::unpack(&packed), Some(footer));
+        packed[20] ^= 1;
+        assert_eq!(LogFooter::unpack(&packed), None);
+        assert_eq!(LogFooter::unpack(&packed[..10]), None);
+    }
+}
@@ -4,19 +4,121 @@ use std::time::SystemTime;
 
 use uuid::Uuid;
 
+/// Error describing why a packed file header is invalid.
+///
+/// `HeaderError` names the offending field together with the values it may hold and the value
+/// that was found, so a corrupt or foreign file can be diagnosed without a hex dump.
+#[cfg(feature = "parser")]
+#[derive(PartialEq, Clone, Debug)]
+pub struct HeaderError {
+    /// Name of the invalid field.
+    pub field: &'static str,
+    /// Description of the values the field may hold.
+    pub expected: String,
+    /// Description of the value found in the file.
+    pub found: String,
+}
+
+#[cfg(feature = "parser")]
+impl HeaderError {
+    /// Creates a new `HeaderError`.
+    pub(crate) fn new(
+        field: &'static str,
+        expected: impl Into<String>,
+        found: impl Into<String>,
+    ) -> Self {
+        HeaderError {
+            field,
+            expected: expected.into(),
+            found: found.into(),
+        }
+    }
+}
+
+#[cfg(feature = "parser")]
+impl std::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid file header field {}: expected {}, found {}",
+            self.field, self.expected, self.found
+        )
+    }
+}
+
+#[cfg(feature = "parser")]
+impl std::error::Error for HeaderError {}
+
+#[cfg(feature = "parser")]
+impl From<HeaderError> for std::io::Error {
+    fn from(error: HeaderError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Unpacks a null terminated UTF-8 string field.
+///
+/// # Returns
+/// The string, or a `HeaderError` for `field` if it is not valid UTF-8.
+#[cfg(feature = "parser")]
+pub(crate) fn try_unpack_string(
+    field: &'static str,
+    packed_data: &[u8],
+) -> Result<String, HeaderError> {
+    // stop at the first null byte when unpacking a string
+    let end: usize = packed_data
+        .iter()
+        .position(|&x| x == 0)
+        .unwrap_or(packed_data.len());
+    String::from_utf8(packed_data[..end].to_vec()).map_err(|_| {
+        let found = format!("{:02x?}", &packed_data[..end]);
+        HeaderError::new(field, "UTF-8 text", found)
+    })
+}
+
 /// Struct representing format flags for the log file.
 ///
 /// `FormatFlags` contains options that modify the format of the log file:
 /// - `mavlink_only`: If set, only MAVLink messages are logged, allowing for a more compact log file.
 /// - `no_timestamp`: If set, timestamps per entry are not included in the log file.
+/// - `sequence`: If set, each entry includes an incrementing sequence number.
+/// - `encrypted`: If set, entry payloads are encrypted and an encryption header follows the
+///   message definitions.
+/// - `hash_chain`: If set, each entry includes a truncated hash of the previous entry.
+/// - `dictionary`: If set, entry payloads are compressed with a zstd dictionary whose id follows
+///   the encryption header.
+/// - `chunked`: If set, entries are grouped into independently compressed and checked blocks.
+/// - `large_entries`: If set, entry payload sizes are stored as 32-bit integers.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub struct FormatFlags {
     /// If set, only MAVLink messages are logged allowing for a more compact log file.
     pub mavlink_only: bool,
     /// If set, timestamps per entry are not included in the log file.
     pub no_timestamp: bool,
+    /// If set, each entry includes a sequence number so missing or reordered entries can be
+    /// detected. Cannot be combined with `mavlink_only`.
+    pub sequence: bool,
+    /// If set, entry payloads are encrypted with AES-256-GCM. Cannot be combined with
+    /// `mavlink_only`.
+    pub encrypted: bool,
+    /// If set, each entry includes a truncated hash of the previous entry so removed or inserted
+    /// entries can be detected. Cannot be combined with `mavlink_only`.
+    pub hash_chain: bool,
+    /// If set, entry payloads are compressed with the zstd dictionary identified in the file
+    /// header. Cannot be combined with `mavlink_only`.
+    pub dictionary: bool,
+    /// If set, entries are grouped into blocks that are compressed and checked independently so
+    /// a corrupted block loses only its own entries.
+    pub chunked: bool,
+    /// If set, entry payload sizes are stored as 32-bit instead of 16-bit integers so entries
+    /// can exceed 64 KiB. Cannot be combined with `dictionary`.
+    pub large_entries: bool,
 }
 
 impl FormatFlags {
+    /// Bits of the packed format flags that have a meaning in the current format version.
+    pub const KNOWN_BITS: u16 = 0xFF;
+
     /// Unpacks a 16-bit integer into a `FormatFlags` struct.
     ///
     /// # Arguments
@@ -29,6 +131,12 @@ impl FormatFlags {
         FormatFlags {
             mavlink_only: packed_data & 0x01 != 0,
             no_timestamp: packed_data & 0x02 != 0,
+            sequence: packed_data & 0x04 != 0,
+            encrypted: packed_data & 0x08 != 0,
+            hash_chain: packed_data & 0x10 != 0,
+            dictionary: packed_data & 0x20 != 0,
+            chunked: packed_data & 0x40 != 0,
+            large_entries: packed_data & 0x80 != 0,
         }
     }
 
@@ -40,7 +148,14 @@ impl FormatFlags {
     /// A `[u8; 2]` array containing the packed representation of the `FormatFlags`.
     #[cfg(feature = "logger")]
     pub fn pack(&self) -> [u8; 2] {
-        let flags: u16 = (self.mavlink_only as u16) | ((self.no_timestamp as u16) << 1);
+        let flags: u16 = (self.mavlink_only as u16)
+            | ((self.no_timestamp as u16) << 1)
+            | ((self.sequence as u16) << 2)
+            | ((self.encrypted as u16) << 3)
+            | ((self.hash_chain as u16) << 4)
+            | ((self.dictionary as u16) << 5)
+            | ((self.chunked as u16) << 6)
+            | ((self.large_entries as u16) << 7);
         flags.to_le_bytes()
     }
 }
@@ -48,11 +163,17 @@ impl FormatFlags {
 impl Default for FormatFlags {
     /// Provides default values for `FormatFlags`.
     ///
-    /// By default, both `mavlink_only` and `no_timestamp` are set to `false`.
+    /// By default, all flags are set to `false`.
     fn default() -> Self {
         FormatFlags {
             mavlink_only: false,
             no_timestamp: false,
+            sequence: false,
+            encrypted: false,
+            hash_chain: false,
+            dictionary: false,
+            chunked: false,
+            large_entries: false,
         }
     }
 }
@@ -96,6 +217,7 @@ impl TryFrom<u16> for MavlinkDefinitionPayloadType {
 /// Struct representing a MAVLink message definition.
 ///
 /// `MavlinkMessageDefinition` contains information about the MAVLink protocol version, dialect, payload type, and the actual payload.
+#[derive(PartialEq, Clone, Debug)]
 pub struct MavlinkMessageDefinition {
     /// MAVLink protocol major version number.
     pub version_major: u32,
@@ -117,6 +239,10 @@ impl MavlinkMessageDefinition {
 
     /// Unpacks a fixed-size byte array into a `MavlinkMessageDefinition` struct.
     ///
+    /// Invalid fields are replaced rather than rejected: a dialect that is not valid UTF-8
+    /// becomes empty and an unknown payload type is treated as `None`. Use `try_unpack` to
+    /// reject them instead.
+    ///
     /// # Arguments
     /// - `packed_data`: A fixed-size byte array containing the packed message definition.
     ///
@@ -124,23 +250,49 @@ impl MavlinkMessageDefinition {
     /// A `MavlinkMessageDefinition` struct with the unpacked data.
     #[cfg(feature = "parser")]
     pub fn unpack(packed_data: &[u8; 46]) -> Self {
-        // stop at the first null byte when unpacking a string
-        let end_dialect_ind: usize = match packed_data[8..40].iter().position(|&x| x == 0) {
-            Some(index) => index + 8,
-            None => 40,
-        };
+        let payload_type = u16::from_le_bytes(packed_data[40..42].try_into().unwrap())
+            .try_into()
+            .unwrap_or(MavlinkDefinitionPayloadType::None);
         MavlinkMessageDefinition {
             version_major: u32::from_le_bytes(packed_data[0..4].try_into().unwrap()),
             version_minor: u32::from_le_bytes(packed_data[4..8].try_into().unwrap()),
-            dialect: String::from_utf8(packed_data[8..end_dialect_ind].to_vec()).unwrap(),
-            payload_type: u16::from_le_bytes(packed_data[40..42].try_into().unwrap())
-                .try_into()
-                .unwrap(),
-            size: u32::from_le_bytes(packed_data[42..46].try_into().unwrap()),
+            dialect: try_unpack_string("dialect", &packed_data[8..40]).unwrap_or_default(),
+            payload_type,
+            size: match payload_type {
+                MavlinkDefinitionPayloadType::None => 0,
+                _ => u32::from_le_bytes(packed_data[42..46].try_into().unwrap()),
+            },
             payload: None,
         }
     }
 
+    /// Unpacks a fixed-size byte array into a `MavlinkMessageDefinition` struct, validating
+    /// each field.
+    ///
+    /// # Arguments
+    /// - `packed_data`: A fixed-size byte array containing the packed message definition.
+    ///
+    /// # Returns
+    /// A `Result` containing the `MavlinkMessageDefinition`.
+    ///
+    /// # Errors
+    /// Returns a `HeaderError` if the dialect is not valid UTF-8 or the payload type is unknown.
+    #[cfg(feature = "parser")]
+    pub fn try_unpack(packed_data: &[u8; 46]) -> Result<Self, HeaderError> {
+        let dialect = try_unpack_string("dialect", &packed_data[8..40])?;
+        let payload_type = u16::from_le_bytes(packed_data[40..42].try_into().unwrap());
+        let payload_type = MavlinkDefinitionPayloadType::try_from(payload_type)
+            .map_err(|_| HeaderError::new("payload_type", "0, 1 or 2", payload_type.to_string()))?;
+        Ok(MavlinkMessageDefinition {
+            version_major: u32::from_le_bytes(packed_data[0..4].try_into().unwrap()),
+            version_minor: u32::from_le_bytes(packed_data[4..8].try_into().unwrap()),
+            dialect,
+            payload_type,
+            size: u32::from_le_bytes(packed_data[42..46].try_into().unwrap()),
+            payload: None,
+        })
+    }
+
     /// Unpacks the payload for the message definition.
     ///
     /// # Arguments
@@ -209,10 +361,132 @@ impl Default for MavlinkMessageDefinition {
     }
 }
 
+/// Enum representing how the nonce of each encrypted entry is chosen.
+///
+/// `NonceStrategy` specifies how entry nonces are generated and stored:
+/// - `Random`: A random 12 byte nonce is stored with each entry.
+/// - `Counter`: The nonce is the random 12 byte base from the encryption header XORed with an
+///   8 byte entry counter. Only the counter is stored with each entry. The base is chosen
+///   randomly per logger from 96 bits, so logs sharing a key are as unlikely to reuse a nonce
+///   as with `Random`.
+///
+/// `Random` is the default.
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum NonceStrategy {
+    /// A random 12 byte nonce is stored with each entry.
+    #[default]
+    Random = 0,
+    /// The header nonce base XORed with an entry counter. Only the counter is stored with each
+    /// entry.
+    Counter = 1,
+}
+
+impl TryFrom<u8> for NonceStrategy {
+    type Error = ();
+
+    /// Converts an 8-bit integer into a `NonceStrategy`.
+    ///
+    /// # Arguments
+    /// - `value`: The 8-bit integer to convert.
+    ///
+    /// # Returns
+    /// A `Result` containing the corresponding `NonceStrategy` or an error if the value is invalid.
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(NonceStrategy::Random),
+            1 => Ok(NonceStrategy::Counter),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Struct representing the encryption header of an encrypted log file.
+///
+/// `EncryptionHeader` identifies the key used to encrypt the entries and how their nonces are
+/// chosen. It follows the message definitions when the `encrypted` format flag is set.
+#[derive(PartialEq, Clone, Debug)]
+pub struct EncryptionHeader {
+    /// Identifier of the key used to encrypt the entries.
+    pub key_id: String,
+    /// How the nonce of each entry is chosen.
+    pub nonce_strategy: NonceStrategy,
+    /// Random 96 bit base the entry counter is XORed with by the `Counter` nonce strategy.
+    pub nonce_base: [u8; 12],
+}
+
+impl EncryptionHeader {
+    /// Size of the packed encryption header in bytes.
+    pub const SIZE: usize = 45;
+
+    /// Unpacks a fixed-size byte array into an `EncryptionHeader` struct.
+    ///
+    /// Invalid fields are replaced rather than rejected: a key id that is not valid UTF-8
+    /// becomes empty and an unknown nonce strategy is treated as `Random`. Use `try_unpack` to
+    /// reject them instead.
+    ///
+    /// # Arguments
+    /// - `packed_data`: A fixed-size byte array containing the packed encryption header.
+    ///
+    /// # Returns
+    /// An `EncryptionHeader` struct with the unpacked data.
+    #[cfg(feature = "parser")]
+    pub fn unpack(packed_data: &[u8; 45]) -> Self {
+        EncryptionHeader {
+            key_id: try_unpack_string("key_id", &packed_data[0..32]).unwrap_or_default(),
+            nonce_strategy: packed_data[32].try_into().unwrap_or(NonceStrategy::Random),
+            nonce_base: packed_data[33..45].try_into().unwrap(),
+        }
+    }
+
+    /// Unpacks a fixed-size byte array into an `EncryptionHeader` struct, validating each field.
+    ///
+    /// # Arguments
+    /// - `packed_data`: A fixed-size byte array containing the packed encryption header.
+    ///
+    /// # Returns
+    /// A `Result` containing the `EncryptionHeader`.
+    ///
+    /// # Errors
+    /// Returns a `HeaderError` if the key id is not valid UTF-8 or the nonce strategy is unknown.
+    #[cfg(feature = "parser")]
+    pub fn try_unpack(packed_data: &[u8; 45]) -> Result<Self, HeaderError> {
+        let key_id = try_unpack_string("key_id", &packed_data[0..32])?;
+        let nonce_strategy = NonceStrategy::try_from(packed_data[32]).map_err(|_| {
+            HeaderError::new("nonce_strategy", "0 or 1", packed_data[32].to_string())
+        })?;
+        Ok(EncryptionHeader {
+            key_id,
+            nonce_strategy,
+            nonce_base: packed_data[33..45].try_into().unwrap(),
+        })
+    }
+
+    /// Packs the `EncryptionHeader` into a vector of bytes.
+    ///
+    /// The packed data contains the key id (32 bytes, UTF-8 encoded), the nonce strategy
+    /// (1 byte) and the nonce base (12 bytes).
+    ///
+    /// # Returns
+    /// A `Vec<u8>` containing the packed representation of the `EncryptionHeader`.
+    #[cfg(feature = "logger")]
+    pub fn pack(&self) -> Vec<u8> {
+        assert!(self.key_id.len() <= 32, "key_id must be 32 bytes or less");
+        let mut key_id_bytes = [0u8; 32];
+        key_id_bytes[..self.key_id.len()].copy_from_slice(self.key_id.as_bytes());
+
+        let mut packed: Vec<u8> = Vec::new();
+        packed.extend_from_slice(&key_id_bytes);
+        packed.push(self.nonce_strategy as u8);
+        packed.extend_from_slice(&self.nonce_base);
+        packed
+    }
+}
+
 /// Struct representing the file header for the log file.
 ///
 /// `FileHeader` contains metadata about the log file, including a unique identifier, timestamp, source application ID,
 /// format version, format flags, and message definitions.
+#[derive(PartialEq, Clone, Debug)]
 pub struct FileHeader {
     /// Unique id for log file. It is expected the uuid library will be used to generate this.
     pub uuid: Uuid,
@@ -226,13 +500,18 @@ pub struct FileHeader {
     pub format_flags: FormatFlags,
     /// The message definitions for the log file.
     pub message_definition: MavlinkMessageDefinition,
+    /// The encryption header, present if the `encrypted` format flag is set.
+    pub encryption: Option<EncryptionHeader>,
+    /// The id of the zstd dictionary entry payloads are compressed with, present if the
+    /// `dictionary` format flag is set.
+    pub dictionary_id: Option<u32>,
 }
 
 impl FileHeader {
     /// Minimum size of the file header in bytes. Can be more if message definitions are included.
     pub const MIN_SIZE: usize = 108;
-    /// Currently supported file format version.
-    pub const FILE_FORMAT_VERSION: u32 = 1;
+    /// Currently supported file format version. Older versions are read through `compat`.
+    pub const FILE_FORMAT_VERSION: u32 = 2;
     /// Default source application ID.
     pub const SRC_APPLICATION_ID: &str = "mavlink_logger";
 
@@ -266,11 +545,30 @@ impl FileHeader {
             format_version: FileHeader::FILE_FORMAT_VERSION,
             format_flags,
             message_definition,
+            encryption: None,
+            dictionary_id: None,
         }
     }
 
+    /// Returns the size of the packed file header in bytes, including the message definitions
+    /// payload and the optional encryption header and dictionary id.
+    pub fn packed_size(&self) -> usize {
+        let mut size = FileHeader::MIN_SIZE + self.message_definition.size as usize;
+        if self.encryption.is_some() {
+            size += EncryptionHeader::SIZE;
+        }
+        if self.dictionary_id.is_some() {
+            size += 4;
+        }
+        size
+    }
+
     /// Unpacks a fixed-size byte array into a `FileHeader` struct.
     ///
+    /// Invalid fields are replaced rather than rejected, see `MavlinkMessageDefinition::unpack`.
+    /// A source application id that is not valid UTF-8 becomes empty and unknown format flags
+    /// are ignored. Use `try_unpack` to reject them instead.
+    ///
     /// # Arguments
     /// - `packed_data`: A fixed-size byte array containing the packed file header.
     ///
@@ -278,19 +576,11 @@ impl FileHeader {
     /// A `FileHeader` struct with the unpacked data.
     #[cfg(feature = "parser")]
     pub fn unpack(packed_data: &[u8; 108]) -> Self {
-        let id_end: usize = match packed_data[24..56].iter().position(|&x| x == 0) {
-            Some(index) => index + 24,
-            None => 56,
-        };
-        let src_application_id: String = match String::from_utf8(packed_data[24..id_end].to_vec()) {
-            Ok(v) => v,
-            Err(_e) => "".to_string(),
-        };
-
         FileHeader {
             uuid: Uuid::from_bytes(packed_data[0..16].try_into().unwrap()),
             timestamp_us: u64::from_le_bytes(packed_data[16..24].try_into().unwrap()),
-            src_application_id,
+            src_application_id: try_unpack_string("src_application_id", &packed_data[24..56])
+                .unwrap_or_default(),
             format_version: u32::from_le_bytes(packed_data[56..60].try_into().unwrap()),
             format_flags: FormatFlags::unpack(u16::from_le_bytes(
                 packed_data[60..62].try_into().unwrap(),
@@ -298,7 +588,54 @@ impl FileHeader {
             message_definition: MavlinkMessageDefinition::unpack(
                 packed_data[62..].try_into().unwrap(),
             ),
+            encryption: None,
+            dictionary_id: None,
+        }
+    }
+
+    /// Unpacks a fixed-size byte array into a `FileHeader` struct, validating each field.
+    ///
+    /// # Arguments
+    /// - `packed_data`: A fixed-size byte array containing the packed file header.
+    ///
+    /// # Returns
+    /// A `Result` containing the `FileHeader`.
+    ///
+    /// # Errors
+    /// Returns a `HeaderError` naming the first invalid field if the format version is not the
+    /// current version, the source application id or dialect is not valid UTF-8, an unknown
+    /// format flag is set or the message definition payload type is unknown.
+    #[cfg(feature = "parser")]
+    pub fn try_unpack(packed_data: &[u8; 108]) -> Result<Self, HeaderError> {
+        let format_version = u32::from_le_bytes(packed_data[56..60].try_into().unwrap());
+        if format_version != FileHeader::FILE_FORMAT_VERSION {
+            return Err(HeaderError::new(
+                "format_version",
+                FileHeader::FILE_FORMAT_VERSION.to_string(),
+                format_version.to_string(),
+            ));
+        }
+        let src_application_id = try_unpack_string("src_application_id", &packed_data[24..56])?;
+        let format_flags = u16::from_le_bytes(packed_data[60..62].try_into().unwrap());
+        if format_flags & !FormatFlags::KNOWN_BITS != 0 {
+            return Err(HeaderError::new(
+                "format_flags",
+                format!("only bits {:#06x}", FormatFlags::KNOWN_BITS),
+                format!("{format_flags:#06x}"),
+            ));
         }
+        Ok(FileHeader {
+            uuid: Uuid::from_bytes(packed_data[0..16].try_into().unwrap()),
+            timestamp_us: u64::from_le_bytes(packed_data[16..24].try_into().unwrap()),
+            src_application_id,
+            format_version,
+            format_flags: FormatFlags::unpack(format_flags),
+            message_definition: MavlinkMessageDefinition::try_unpack(
+                packed_data[62..].try_into().unwrap(),
+            )?,
+            encryption: None,
+            dictionary_id: None,
+        })
     }
 
     /// Packs the `FileHeader` into a vector of bytes.
@@ -310,6 +647,8 @@ impl FileHeader {
     /// - Format version (8 bytes)
     /// - Format flags (2 bytes, packed)
     /// - Message definition (variable length, packed)
+    /// - Encryption header (45 bytes, packed) if present
+    /// - Dictionary id (4 bytes) if present
     /// All bytes are packed in little-endian format.
     ///
     /// # Returns
@@ -331,6 +670,12 @@ impl FileHeader {
         packed.extend_from_slice(&self.format_version.to_le_bytes());
         packed.extend_from_slice(&self.format_flags.pack());
         packed.extend_from_slice(&self.message_definition.pack());
+        if let Some(encryption) = &self.encryption {
+            packed.extend_from_slice(&encryption.pack());
+        }
+        if let Some(dictionary_id) = self.dictionary_id {
+            packed.extend_from_slice(&dictionary_id.to_le_bytes());
+        }
         packed
     }
 }
@@ -354,6 +699,8 @@ impl Default for FileHeader {
             format_version: FileHeader::FILE_FORMAT_VERSION,
             format_flags: FormatFlags::default(),
             message_definition: MavlinkMessageDefinition::default(),
+            encryption: None,
+            dictionary_id: None,
         }
     }
 }
@@ -389,6 +736,43 @@ mod parser_tests {
         let flags = FormatFlags::unpack(packed_data);
         assert!(!flags.mavlink_only);
         assert!(!flags.no_timestamp);
+        assert!(!flags.sequence);
+
+        let packed_data: u16 = 0b100;
+        let flags = FormatFlags::unpack(packed_data);
+        assert!(!flags.mavlink_only);
+        assert!(!flags.no_timestamp);
+        assert!(flags.sequence);
+        assert!(!flags.encrypted);
+
+        let packed_data: u16 = 0b1000;
+        let flags = FormatFlags::unpack(packed_data);
+        assert!(!flags.sequence);
+        assert!(flags.encrypted);
+        assert!(!flags.hash_chain);
+
+        let packed_data: u16 = 0b10000;
+        let flags = FormatFlags::unpack(packed_data);
+        assert!(!flags.encrypted);
+        assert!(flags.hash_chain);
+        assert!(!flags.dictionary);
+
+        let packed_data: u16 = 0b100000;
+        let flags = FormatFlags::unpack(packed_data);
+        assert!(!flags.hash_chain);
+        assert!(flags.dictionary);
+        assert!(!flags.chunked);
+
+        let packed_data: u16 = 0b1000000;
+        let flags = FormatFlags::unpack(packed_data);
+        assert!(!flags.dictionary);
+        assert!(flags.chunked);
+        assert!(!flags.large_entries);
+
+        let packed_data: u16 = 0b10000000;
+        let flags = FormatFlags::unpack(packed_data);
+        assert!(!flags.chunked);
+        assert!(flags.large_entries);
     }
 
     #[test]
@@ -495,6 +879,79 @@ mod parser_tests {
         assert_eq!(header.message_definition.size, 10);
         assert!(header.message_definition.payload.is_none());
     }
+
+    #[test]
+    /// Tests that `try_unpack` of `FileHeader` names the invalid field while `unpack` replaces it.
+    fn test_file_header_try_unpack() {
+        let mut packed_data = [0u8; 108];
+        packed_data[24..27].copy_from_slice(b"app");
+        packed_data[56..60].copy_from_slice(&2u32.to_le_bytes());
+        packed_data[62..66].copy_from_slice(&2u32.to_le_bytes());
+        let header = FileHeader::try_unpack(&packed_data).unwrap();
+        assert_eq!(header.src_application_id, "app");
+
+        let mut invalid = packed_data;
+        invalid[56] = 3;
+        let error = FileHeader::try_unpack(&invalid).unwrap_err();
+        assert_eq!(error.field, "format_version");
+        assert_eq!((error.expected.as_str(), error.found.as_str()), ("2", "3"));
+
+        let mut invalid = packed_data;
+        invalid[25] = 0xFF;
+        let error = FileHeader::try_unpack(&invalid).unwrap_err();
+        assert_eq!(error.field, "src_application_id");
+        assert_eq!(error.found, "[61, ff, 70]");
+        assert_eq!(FileHeader::unpack(&invalid).src_application_id, "");
+
+        let mut invalid = packed_data;
+        invalid[61] = 0x01;
+        let error = FileHeader::try_unpack(&invalid).unwrap_err();
+        assert_eq!(error.field, "format_flags");
+        assert_eq!(error.found, "0x0100");
+        assert!(!FileHeader::unpack(&invalid).format_flags.mavlink_only);
+
+        let mut invalid = packed_data;
+        invalid[70] = 0xC0;
+        let error = FileHeader::try_unpack(&invalid).unwrap_err();
+        assert_eq!(error.field, "dialect");
+        assert_eq!(FileHeader::unpack(&invalid).message_definition.dialect, "");
+
+        let mut invalid = packed_data;
+        invalid[102..104].copy_from_slice(&7u16.to_le_bytes());
+        invalid[104..108].copy_from_slice(&10u32.to_le_bytes());
+        let error = FileHeader::try_unpack(&invalid).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid file header field payload_type: expected 0, 1 or 2, found 7"
+        );
+        let header = FileHeader::unpack(&invalid);
+        assert_eq!(
+            header.message_definition.payload_type,
+            MavlinkDefinitionPayloadType::None
+        );
+        assert_eq!(header.message_definition.size, 0);
+    }
+
+    #[test]
+    /// Tests that `try_unpack` of `EncryptionHeader` rejects an unknown nonce strategy that
+    /// `unpack` replaces.
+    fn test_encryption_header_try_unpack() {
+        let mut packed_data = [0u8; 45];
+        packed_data[0..7].copy_from_slice(b"ops-key");
+        packed_data[32] = 1;
+        packed_data[33..45].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        let header = EncryptionHeader::try_unpack(&packed_data).unwrap();
+        assert_eq!(header.key_id, "ops-key");
+        assert_eq!(header.nonce_strategy, NonceStrategy::Counter);
+        assert_eq!(header.nonce_base, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+        packed_data[32] = 9;
+        let error = EncryptionHeader::try_unpack(&packed_data).unwrap_err();
+        assert_eq!(error.field, "nonce_strategy");
+        assert_eq!(error.found, "9");
+        let header = EncryptionHeader::unpack(&packed_data);
+        assert_eq!(header.nonce_strategy, NonceStrategy::Random);
+    }
 }
 
 #[cfg(test)]
@@ -513,26 +970,93 @@ mod logger_tests {
         let flags = FormatFlags {
             mavlink_only: false,
             no_timestamp: false,
+            sequence: false,
+            encrypted: false,
+            hash_chain: false,
+            dictionary: false,
+            chunked: false,
+            large_entries: false,
         };
         assert_eq!(flags.pack(), [0, 0]);
 
         let flags = FormatFlags {
             mavlink_only: true,
             no_timestamp: false,
+            sequence: false,
+            encrypted: false,
+            hash_chain: false,
+            dictionary: false,
+            chunked: false,
+            large_entries: false,
         };
         assert_eq!(flags.pack(), [1, 0]);
 
         let flags = FormatFlags {
             mavlink_only: false,
             no_timestamp: true,
+            sequence: false,
+            encrypted: false,
+            hash_chain: false,
+            dictionary: false,
+            chunked: false,
+            large_entries: false,
         };
         assert_eq!(flags.pack(), [2, 0]);
 
         let flags = FormatFlags {
             mavlink_only: true,
             no_timestamp: true,
+            sequence: false,
+            encrypted: false,
+            hash_chain: false,
+            dictionary: false,
+            chunked: false,
+            large_entries: false,
         };
         assert_eq!(flags.pack(), [3, 0]);
+
+        let flags = FormatFlags {
+            mavlink_only: false,
+            no_timestamp: false,
+            sequence: true,
+            encrypted: false,
+            hash_chain: false,
+            dictionary: false,
+            chunked: false,
+            large_entries: false,
+        };
+        assert_eq!(flags.pack(), [4, 0]);
+
+        let flags = FormatFlags {
+            encrypted: true,
+            ..Default::default()
+        };
+        assert_eq!(flags.pack(), [8, 0]);
+
+        let flags = FormatFlags {
+            hash_chain: true,
+            ..Default::default()
+        };
+        assert_eq!(flags.pack(), [16, 0]);
+
+        let flags = FormatFlags {
+            dictionary: true,
+            ..Default::default()
+        };
+        assert_eq!(flags.pack(), [32, 0]);
+
+        let flags = FormatFlags {
+            chunked: true,
+            large_entries: false,
+            ..Default::default()
+        };
+        assert_eq!(flags.pack(), [64, 0]);
+
+        let flags = FormatFlags {
+            large_entries: true,
+            ..Default::default()
+        };
+        assert_eq!(flags.pack(), [128, 0]);
     }
 
     #[test]
@@ -599,6 +1123,12 @@ mod logger_tests {
         let format_flags = FormatFlags {
             mavlink_only: true,
             no_timestamp: false,
+            sequence: false,
+            encrypted: false,
+            hash_chain: false,
+            dictionary: false,
+            chunked: false,
+            large_entries: false,
         };
         let message_definition = MavlinkMessageDefinition {
             version_major: 2,
@@ -616,7 +1146,7 @@ mod logger_tests {
             String::from_utf8(packed[24..56].to_vec()).unwrap(),
             "mavlink_logger\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"
         ); // src application id
-        assert_eq!(&packed[56..60], &[1, 0, 0, 0]); // file version
+        assert_eq!(&packed[56..60], &[2, 0, 0, 0]); // file version
         assert_eq!(&packed[60..62], &[1, 0]); // format flags
         assert_eq!(&packed[62..113], &header.message_definition.pack()[..]);
     }
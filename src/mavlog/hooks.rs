@@ -0,0 +1,174 @@
+//! Hooks run when a logger writes MAVLink frames of selected messages.
+//!
+//! Hooks registered with `RotatingMavLogger::on_message` or `RotatingMavLogger::send_message`
+//! are keyed by message id. They see each matching frame as it is written, so alerts such as a
+//! STATUSTEXT warning or a low battery can be raised in flight without parsing the stream a
+//! second time.
+use std::collections::BTreeMap;
+use std::sync::mpsc::Sender;
+
+use mavlink::{MavHeader, Message};
+
+use crate::frame;
+
+/// A MAVLink frame written by a logger.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct LoggedFrame {
+    /// The message id of the frame.
+    pub message_id: u32,
+    /// The entry timestamp in microseconds, `None` if the log does not record timestamps.
+    pub timestamp_us: Option<u64>,
+    /// The bytes of the complete frame as written.
+    pub frame: Vec<u8>,
+}
+
+impl LoggedFrame {
+    /// Decodes the message of the frame.
+    ///
+    /// # Returns
+    ///
+    /// The header and message of the frame, or `None` if the message is unknown to the dialect
+    /// or invalid.
+    pub fn decode<M: Message>(&self) -> Option<(MavHeader, M)> {
+        frame::decode::<M>(&self.frame)
+            .ok()
+            .map(|decoded| (decoded.header, decoded.msg))
+    }
+}
+
+/// A hook registered for a message id.
+enum Hook {
+    /// Called on the thread writing the frame.
+    Callback(Box<dyn FnMut(&LoggedFrame) + Send>),
+    /// Sent to a channel, until its receiver is dropped.
+    Channel(Sender<LoggedFrame>),
+}
+
+/// Hooks of a logger, by message id.
+#[derive(Default)]
+pub(crate) struct MessageHooks {
+    hooks: BTreeMap<u32, Vec<Hook>>,
+}
+
+impl MessageHooks {
+    /// Registers a callback for a message id.
+    pub(crate) fn add_callback(
+        &mut self,
+        message_id: u32,
+        callback: Box<dyn FnMut(&LoggedFrame) + Send>,
+    ) {
+        self.hooks
+            .entry(message_id)
+            .or_default()
+            .push(Hook::Callback(callback));
+    }
+
+    /// Registers a channel for a message id.
+    pub(crate) fn add_channel(&mut self, message_id: u32, sender: Sender<LoggedFrame>) {
+        self.hooks
+            .entry(message_id)
+            .or_default()
+            .push(Hook::Channel(sender));
+    }
+
+    /// Runs the hooks of a written frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_us` - The entry timestamp, `None` if the log does not record timestamps.
+    /// * `bytes` - The bytes of one complete MAVLink frame.
+    pub(crate) fn fire(&mut self, timestamp_us: Option<u64>, bytes: &[u8]) {
+        let Some(message_id) = frame::message_id(bytes) else {
+            return;
+        };
+        let Some(hooks) = self.hooks.get_mut(&message_id) else {
+            return;
+        };
+        let logged = LoggedFrame {
+            message_id,
+            timestamp_us,
+            frame: bytes.to_vec(),
+        };
+        // hooks whose channel was closed are removed
+        hooks.retain_mut(|hook| match hook {
+            Hook::Callback(callback) => {
+                callback(&logged);
+                true
+            }
+            Hook::Channel(sender) => sender.send(logged.clone()).is_ok(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+
+    use mavlink::common::{MavMessage, MavSeverity, STATUSTEXT_DATA};
+    use mavlink::{MavFrame, MavlinkVersion};
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::mav_logger::MavLogger;
+    use crate::mavlog::logger::RotatingMavLogger;
+
+    fn frame(msg: MavMessage) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader::default(),
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    fn statustext(severity: MavSeverity) -> MavFrame<MavMessage> {
+        frame(MavMessage::STATUSTEXT(STATUSTEXT_DATA {
+            severity,
+            ..Default::default()
+        }))
+    }
+
+    /// Test that callbacks and channels receive the frames of their message id only.
+    #[test]
+    fn test_message_hooks() {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let mut logger =
+            RotatingMavLogger::new(tmpfile.path().to_str().unwrap(), 100000, 0, None, None)
+                .unwrap();
+        let warnings = Arc::new(Mutex::new(0));
+        let counter = warnings.clone();
+        logger.on_message(253, move |logged| {
+            let warning = matches!(
+                logged.decode::<MavMessage>(),
+                Some((_, MavMessage::STATUSTEXT(data)))
+                    if data.severity as u8 <= MavSeverity::MAV_SEVERITY_WARNING as u8
+            );
+            if warning {
+                *counter.lock().unwrap() += 1;
+            }
+        });
+        let (sender, receiver) = mpsc::channel();
+        logger.send_message(0, sender);
+
+        logger
+            .write_mavlink(statustext(MavSeverity::MAV_SEVERITY_INFO))
+            .unwrap();
+        logger
+            .write_mavlink(frame(MavMessage::HEARTBEAT(Default::default())))
+            .unwrap();
+        logger
+            .write_mavlink(statustext(MavSeverity::MAV_SEVERITY_CRITICAL))
+            .unwrap();
+        logger.write_text("note").unwrap();
+
+        assert_eq!(*warnings.lock().unwrap(), 1);
+        let heartbeats: Vec<LoggedFrame> = receiver.try_iter().collect();
+        assert_eq!(heartbeats.len(), 1);
+        assert_eq!(heartbeats[0].message_id, 0);
+        assert!(heartbeats[0].timestamp_us.is_some());
+        assert!(matches!(
+            heartbeats[0].decode::<MavMessage>(),
+            Some((_, MavMessage::HEARTBEAT(_)))
+        ));
+    }
+}
@@ -4,28 +4,142 @@
 /// You can learn more at docs/mav_log_file_format.md.
 use std::option::Option;
 use std::option::Option::Some;
-use std::time::SystemTime;
+use std::sync::mpsc::Sender;
 
 use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw};
 use mavlink::{MavFrame, Message};
 use rotating_file_handler::RotatingFileHandler;
 
+use super::blackbox::{BlackBox, BlackBoxState};
+#[cfg(feature = "compression")]
+use super::block;
+#[cfg(feature = "hash_chain")]
+use super::chain;
+#[cfg(feature = "compression")]
+use super::dictionary::EntryCompressor;
+#[cfg(feature = "encryption")]
+use super::encryption::EntryCipher;
+use super::footer::LogFooter;
 use super::header::{FileHeader, FormatFlags, MavlinkMessageDefinition};
-use crate::mav_logger::MavLogger;
+use super::hooks::{LoggedFrame, MessageHooks};
+use super::segment::SegmentState;
+use super::snapshot::{LastValueCache, SnapshotConfig};
+use super::transform::{self, Action, EntryDraft, Transform};
+use crate::clock::Clock;
+use crate::frame;
+use crate::mav_logger::{MavFrameLogger, MavLogger};
+use crate::metadata::MissionMetadata;
+use crate::weather::WeatherObservation;
 
 /// Enum representing the type of log entry.
-#[derive(PartialEq, Debug)]
-enum EntryType {
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum EntryType {
+    /// Application defined binary data.
     Raw = 0,
+    /// A serialized MAVLink frame.
     Mavlink = 1,
+    /// UTF-8 text.
     Text = 2,
+    /// A report of discarded MAVLink frames.
+    Drops = 3,
+    /// A fragment of a blob.
+    Blob = 4,
+    /// RTCM correction data prefixed with the id of its link.
+    Rtcm = 5,
+    /// The last frames of selected messages, see `snapshot`.
+    Snapshot = 6,
+    /// A change of state of an actuator applying product.
+    Actuator = 7,
+    /// The weather at the time of the entry, see `weather`.
+    Weather = 8,
+    /// The operator, job and products of the following entries, see `metadata`.
+    Metadata = 9,
 }
 
+impl TryFrom<u8> for EntryType {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(EntryType::Raw),
+            1 => Ok(EntryType::Mavlink),
+            2 => Ok(EntryType::Text),
+            3 => Ok(EntryType::Drops),
+            4 => Ok(EntryType::Blob),
+            5 => Ok(EntryType::Rtcm),
+            6 => Ok(EntryType::Snapshot),
+            7 => Ok(EntryType::Actuator),
+            8 => Ok(EntryType::Weather),
+            9 => Ok(EntryType::Metadata),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Largest amount of blob data stored in a single entry. Leaves room for the fragment header and
+/// for encryption or compression overhead within a 16-bit entry size.
+pub const BLOB_FRAGMENT_SIZE: usize = 60 * 1024;
+
+/// CAMERA_IMAGE_CAPTURED message id.
+const CAMERA_IMAGE_CAPTURED_ID: u32 = 263;
+
+/// Default uncompressed size of the blocks of chunked logs in bytes.
+#[cfg(feature = "compression")]
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Largest size of the entries of a block. Blocks stay below half the size readers accept,
+/// leaving room for compression overhead.
+#[cfg(feature = "compression")]
+const MAX_BLOCK_ENTRIES_SIZE: usize = block::MAX_BLOCK_SIZE / 2;
+
 /// Struct representing a rotating file logger for MAVLink messages.
+///
+/// Entries of chunked logs are buffered until a block is complete. The last block is written
+/// by `flush` or when the logger is dropped.
+///
+/// Entry timestamps are read from a monotonic clock started when the logger is created, unless
+/// another clock is set with `set_clock`.
+///
+/// Transforms added with `with_transform` run on every entry before it is written.
+///
+/// A log closed with `finish` ends with a footer summarizing its entries.
 pub struct RotatingMavLogger {
+    base_path: String,
     header: FileHeader,
-    time: SystemTime,
+    pub(crate) clock: Clock,
+    sequence: u32,
+    next_blob_id: u32,
     file_handler: RotatingFileHandler,
+    transforms: Vec<Transform>,
+    hooks: MessageHooks,
+    black_box: Option<BlackBoxState>,
+    snapshot: Option<LastValueCache>,
+    segment_state: Option<SegmentState>,
+    max_bytes: u64,
+    /// Expected length of the current log file, used to detect rotations.
+    file_len: u64,
+    /// Whether the log file rotated since the last entry was written.
+    rotated: bool,
+    /// Number of times the log file rotated.
+    rotations: u64,
+    /// Number of bytes written to the log files, file headers excluded.
+    bytes_written: u64,
+    /// Summary of the entries written to the current log file.
+    footer: LogFooter,
+    #[cfg(feature = "encryption")]
+    cipher: Option<EntryCipher>,
+    #[cfg(feature = "compression")]
+    compressor: Option<EntryCompressor>,
+    #[cfg(feature = "compression")]
+    block: Vec<u8>,
+    #[cfg(feature = "compression")]
+    block_entries: u32,
+    #[cfg(feature = "compression")]
+    block_first_timestamp_us: u64,
+    #[cfg(feature = "compression")]
+    block_size: usize,
+    #[cfg(feature = "hash_chain")]
+    previous_hash: [u8; chain::HASH_SIZE],
 }
 
 impl RotatingMavLogger {
@@ -43,7 +157,11 @@ impl RotatingMavLogger {
     ///
     /// # Returns
     ///
-    /// A `Result` containing the new `RotatingFileMavLogger` or an `io::Error`.
+    /// A `Result` containing the new `RotatingFileMavLogger` or an `io::Error`. An error of kind
+    /// `InvalidInput` is returned if the `sequence` or `hash_chain` flag is combined with
+    /// `mavlink_only` or if the `encrypted` or `dictionary` flag is set, and an error of kind
+    /// `Unsupported` if the `hash_chain` flag is set without the hash_chain feature or the
+    /// `chunked` flag without the compression feature.
     pub fn new(
         base_path: &str,
         max_bytes: u64,
@@ -57,6 +175,12 @@ impl RotatingMavLogger {
             Some(f) => flags = f,
             None => flags = FormatFlags::default(),
         }
+        if flags.encrypted {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Encrypted logs must be created with new_encrypted",
+            ));
+        }
         // Handle optional mavlink message definitions
         let msg_definition: MavlinkMessageDefinition;
         match mavlink_definitions {
@@ -65,17 +189,404 @@ impl RotatingMavLogger {
         }
         // Create the file header
         let header: FileHeader = FileHeader::new(flags, msg_definition);
+        Self::with_header(base_path, max_bytes, backup_count, header)
+    }
+
+    /// Creates a new `RotatingMavLogger` that encrypts entry payloads.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_path` - The base path for the log files. A file extension of .mav is recommended.
+    ///     If the path includes more than the file name, such as parent directories, it is
+    ///     expected the folder path already exists.
+    /// * `max_bytes` - The maximum size of a log file before it is rotated.
+    /// * `backup_count` - The number of backup files to keep.
+    /// * `format_flags` - Optional format flags for the log file. The `encrypted` flag is set
+    ///     automatically and `mavlink_only` is not supported.
+    /// * `mavlink_definitions` - Optional MAVLink message definitions.
+    /// * `cipher` - The cipher to encrypt entry payloads with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `RotatingMavLogger` or an `io::Error`. An error of kind
+    /// `InvalidInput` is returned if the `mavlink_only` flag is set.
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted(
+        base_path: &str,
+        max_bytes: u64,
+        backup_count: usize,
+        format_flags: Option<FormatFlags>,
+        mavlink_definitions: Option<MavlinkMessageDefinition>,
+        cipher: EntryCipher,
+    ) -> std::io::Result<Self> {
+        let mut flags = format_flags.unwrap_or_default();
+        if flags.mavlink_only {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Encryption is not supported in MAVLink only logs",
+            ));
+        }
+        flags.encrypted = true;
+        let mut header: FileHeader =
+            FileHeader::new(flags, mavlink_definitions.unwrap_or_default());
+        header.encryption = Some(cipher.header());
+        let mut logger = Self::with_header(base_path, max_bytes, backup_count, header)?;
+        logger.cipher = Some(cipher);
+        Ok(logger)
+    }
+
+    /// Creates a new `RotatingMavLogger` that compresses entry payloads with a zstd dictionary.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_path` - The base path for the log files. A file extension of .mav is recommended.
+    ///     If the path includes more than the file name, such as parent directories, it is
+    ///     expected the folder path already exists.
+    /// * `max_bytes` - The maximum size of a log file before it is rotated.
+    /// * `backup_count` - The number of backup files to keep.
+    /// * `format_flags` - Optional format flags for the log file. The `dictionary` flag is set
+    ///     automatically and `mavlink_only` is not supported.
+    /// * `mavlink_definitions` - Optional MAVLink message definitions.
+    /// * `compressor` - The compressor holding the dictionary. The dictionary must be stored
+    ///     where parsers of the log can load it, see `dictionary::save_dictionary`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `RotatingMavLogger` or an `io::Error`. An error of kind
+    /// `InvalidInput` is returned if the `mavlink_only`, `encrypted` or `large_entries` flag is
+    /// set.
+    #[cfg(feature = "compression")]
+    pub fn new_compressed(
+        base_path: &str,
+        max_bytes: u64,
+        backup_count: usize,
+        format_flags: Option<FormatFlags>,
+        mavlink_definitions: Option<MavlinkMessageDefinition>,
+        compressor: EntryCompressor,
+    ) -> std::io::Result<Self> {
+        let mut flags = format_flags.unwrap_or_default();
+        if flags.mavlink_only || flags.encrypted || flags.large_entries {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Compression is not supported in MAVLink only, encrypted or large entry logs",
+            ));
+        }
+        flags.dictionary = true;
+        let mut header: FileHeader =
+            FileHeader::new(flags, mavlink_definitions.unwrap_or_default());
+        header.dictionary_id = Some(compressor.dictionary_id());
+        let mut logger = Self::with_header(base_path, max_bytes, backup_count, header)?;
+        logger.compressor = Some(compressor);
+        Ok(logger)
+    }
+
+    /// Creates a new `RotatingMavLogger` writing the provided file header.
+    pub(crate) fn with_header(
+        base_path: &str,
+        max_bytes: u64,
+        backup_count: usize,
+        header: FileHeader,
+    ) -> std::io::Result<Self> {
+        let flags = &header.format_flags;
+        if flags.mavlink_only && (flags.sequence || flags.hash_chain) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Sequence numbers and hash chains are not supported in MAVLink only logs",
+            ));
+        }
+        if flags.dictionary && header.dictionary_id.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Compressed logs must be created with new_compressed",
+            ));
+        }
+        if flags.hash_chain && cfg!(not(feature = "hash_chain")) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Hash chains require the hash_chain feature",
+            ));
+        }
+        if flags.chunked && cfg!(not(feature = "compression")) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Chunked logs require the compression feature",
+            ));
+        }
 
         // Create the rotating file handler
+        let header_bytes = header.pack();
+        let file_len = header_bytes.len() as u64;
+        // The first entry links to the file header
+        #[cfg(feature = "hash_chain")]
+        let previous_hash = chain::entry_hash(&header_bytes);
         let file_handler =
-            RotatingFileHandler::new(base_path, max_bytes, backup_count, Some(header.pack()))?;
+            RotatingFileHandler::new(base_path, max_bytes, backup_count, Some(header_bytes))?;
 
         Ok(Self {
+            base_path: String::from(base_path),
             header,
-            time: SystemTime::now(),
+            clock: Clock::default(),
+            sequence: 0,
+            next_blob_id: 0,
             file_handler,
+            transforms: Vec::new(),
+            hooks: MessageHooks::default(),
+            black_box: None,
+            snapshot: None,
+            segment_state: None,
+            max_bytes,
+            file_len,
+            rotated: false,
+            rotations: 0,
+            bytes_written: 0,
+            footer: LogFooter::default(),
+            #[cfg(feature = "encryption")]
+            cipher: None,
+            #[cfg(feature = "compression")]
+            compressor: None,
+            #[cfg(feature = "compression")]
+            block: Vec::new(),
+            #[cfg(feature = "compression")]
+            block_entries: 0,
+            #[cfg(feature = "compression")]
+            block_first_timestamp_us: 0,
+            #[cfg(feature = "compression")]
+            block_size: DEFAULT_BLOCK_SIZE,
+            #[cfg(feature = "hash_chain")]
+            previous_hash,
         })
     }
+
+    /// Returns the file header written at the start of every log file.
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// Returns the number of times the log file rotated since the logger was created.
+    pub fn rotations(&self) -> u64 {
+        self.rotations
+    }
+
+    /// Returns the number of bytes of entries written since the logger was created.
+    ///
+    /// Entries buffered in an incomplete block of a chunked log are counted once the block is
+    /// written.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Sets the clock entry timestamps are read from.
+    ///
+    /// Entries given an explicit timestamp do not read the clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock to read entry timestamps from.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Adds a transform run on every entry before it is written.
+    ///
+    /// Transforms run in the order they were added. Each one sees the entry as modified by the
+    /// previous ones and decides whether it is kept, dropped, also copied to another sink or
+    /// preceded by an inserted entry. The entry timestamp is read from the clock before the
+    /// transforms run.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - The transform to add.
+    ///
+    /// # Returns
+    ///
+    /// The logger with the transform added.
+    pub fn with_transform(
+        mut self,
+        transform: impl Fn(&mut EntryDraft) -> Action + Send + 'static,
+    ) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Registers a callback run whenever a MAVLink frame of a message is written.
+    ///
+    /// The callback runs on the thread writing the frame, once the frame is written, so it
+    /// should return quickly. Use `send_message` to handle frames on another thread. Frames
+    /// replayed at the start of a rotated file, see `set_state_replay`, do not run hooks.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - The id of the message.
+    /// * `callback` - The callback, given the written frame.
+    pub fn on_message(
+        &mut self,
+        message_id: u32,
+        callback: impl FnMut(&LoggedFrame) + Send + 'static,
+    ) {
+        self.hooks.add_callback(message_id, Box::new(callback));
+    }
+
+    /// Registers a channel a copy of every written MAVLink frame of a message is sent to.
+    ///
+    /// The channel is removed once its receiver is dropped. Frames replayed at the start of a
+    /// rotated file are not sent, as with `on_message`.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - The id of the message.
+    /// * `sender` - The sending half of the channel.
+    pub fn send_message(&mut self, message_id: u32, sender: Sender<LoggedFrame>) {
+        self.hooks.add_channel(message_id, sender);
+    }
+
+    /// Sets or clears the black box profile, syncing the log file to storage as entries are
+    /// written and periodically rewriting the header timestamp, see `blackbox`.
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - The black box settings, or `None` to leave syncing to the operating system.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error of kind `InvalidInput` is returned if
+    /// the `hash_chain` flag is set, as the first entry hash covers the header timestamp.
+    pub fn set_black_box(&mut self, profile: Option<BlackBox>) -> std::io::Result<()> {
+        if profile.is_some() && self.header.format_flags.hash_chain {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The black box profile cannot be used with hash chains",
+            ));
+        }
+        self.black_box = profile.map(BlackBoxState::new);
+        Ok(())
+    }
+
+    /// Enables or disables snapshots of the last frames of selected messages.
+    ///
+    /// Snapshots are written as a single SNAPSHOT entry at the configured interval and at the
+    /// start of each rotated file, so that every part of the log describes the vehicle state.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The messages to keep and the snapshot interval, or `None` to disable
+    ///     snapshots.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error of kind `InvalidInput` is returned if
+    /// the `mavlink_only` flag is set.
+    pub fn set_snapshot(&mut self, config: Option<SnapshotConfig>) -> std::io::Result<()> {
+        if config.is_some() && self.header.format_flags.mavlink_only {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Snapshots cannot be written to a MAVLink only log",
+            ));
+        }
+        self.file_len = std::fs::metadata(&self.base_path)?.len();
+        self.snapshot = config.map(LastValueCache::new);
+        Ok(())
+    }
+
+    /// Enables or disables the replay of the vehicle state at the start of each rotated file.
+    ///
+    /// The last HEARTBEAT, AUTOPILOT_VERSION and HOME_POSITION messages of each component, and
+    /// the last PARAM_VALUE reporting the parameter set hash, are written again as MAVLINK
+    /// entries at the start of every new file, timestamped when they are replayed.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to replay the state.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure of reading the size of the log file.
+    pub fn set_state_replay(&mut self, enabled: bool) -> std::io::Result<()> {
+        self.file_len = std::fs::metadata(&self.base_path)?.len();
+        self.segment_state = enabled.then(SegmentState::default);
+        Ok(())
+    }
+
+    /// Sets the uncompressed size at which the blocks of a chunked log are written.
+    ///
+    /// Smaller blocks lose fewer entries to corruption and allow finer seeking, larger blocks
+    /// compress better. Has no effect unless the `chunked` flag is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_size` - The block size in bytes. Limited to half of `block::MAX_BLOCK_SIZE`.
+    #[cfg(feature = "compression")]
+    pub fn set_block_size(&mut self, block_size: usize) {
+        self.block_size = block_size.clamp(1, MAX_BLOCK_ENTRIES_SIZE);
+    }
+
+    /// Writes the entries buffered for the current block of a chunked log.
+    ///
+    /// Does nothing for other logs, whose entries are written immediately.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        #[cfg(feature = "compression")]
+        if self.block_entries > 0 {
+            let packed = block::encode_block(
+                &self.block,
+                self.block_entries,
+                self.block_first_timestamp_us,
+            )?;
+            self.block.clear();
+            self.block_entries = 0;
+            self.emit_bytes(&packed)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the logger and signs the log file it was writing.
+    ///
+    /// Only the current log file is signed. Rotated backups can be signed with
+    /// `signature::sign_file`.
+    ///
+    /// # Arguments
+    ///
+    /// * `signing_key` - The Ed25519 key to sign with.
+    /// * `key_id` - Identifier of the key recorded in the signature sidecar, at most 32 bytes.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the path of the signature sidecar or an `io::Error`.
+    #[cfg(feature = "signing")]
+    pub fn close_and_sign(
+        self,
+        signing_key: &crate::signature::SigningKey,
+        key_id: &str,
+    ) -> std::io::Result<std::path::PathBuf> {
+        let base_path = self.base_path.clone();
+        drop(self);
+        crate::signature::sign_file(&base_path, signing_key, key_id)
+    }
+
+    /// Closes the logger and appends a footer to the log file it was writing.
+    ///
+    /// The footer records the timestamps of the first and last entries of the file and the
+    /// number of entries, so that parsers can report the duration of the log without reading
+    /// it, see `MavLogParser::duration`. Only the current log file gets a footer, rotated backups
+    /// are left as they are.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the footer written or an `io::Error`.
+    pub fn finish(mut self) -> std::io::Result<LogFooter> {
+        use std::io::Write;
+
+        self.flush()?;
+        let footer = self.footer;
+        let base_path = self.base_path.clone();
+        // the file handler is closed first so that the footer follows every entry
+        drop(self);
+        let mut file = std::fs::OpenOptions::new().append(true).open(base_path)?;
+        file.write_all(&footer.pack())?;
+        Ok(footer)
+    }
 }
 
 impl MavLogger for RotatingMavLogger {
@@ -89,18 +600,14 @@ impl MavLogger for RotatingMavLogger {
     ///
     /// A `Result` indicating success or failure.
     fn write_mavlink<M: Message>(&mut self, frame: MavFrame<M>) -> std::io::Result<()> {
-        match frame.protocol_version {
-            mavlink::MavlinkVersion::V1 => {
-                let mut msg: MAVLinkV1MessageRaw = MAVLinkV1MessageRaw::new();
-                msg.serialize_message(frame.header, &frame.msg);
-                return self.write(EntryType::Mavlink, msg.raw_bytes());
-            }
-            mavlink::MavlinkVersion::V2 => {
-                let mut msg: MAVLinkV2MessageRaw = MAVLinkV2MessageRaw::new();
-                msg.serialize_message(frame.header, &frame.msg);
-                return self.write(EntryType::Mavlink, msg.raw_bytes());
-            }
-        }
+        self.write_mavlink_at(frame, None)
+    }
+}
+
+impl MavFrameLogger for RotatingMavLogger {
+    /// Writes a serialized MAVLink frame to the log, see `write_mavlink_raw`.
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.write_mavlink_raw(frame)
     }
 }
 
@@ -132,17 +639,422 @@ impl RotatingMavLogger {
         self.write(EntryType::Raw, data)
     }
 
-    /// Writes a log entry to the file.
+    /// Writes a serialized MAVLink frame to the log exactly as captured.
+    ///
+    /// Unlike `write_mavlink`, the frame is not re-serialized, so its incompatibility and
+    /// compatibility flags and its signature are preserved. Use this when logging frames read
+    /// from a raw link so that signed traffic survives a log and replay round trip.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The bytes of exactly one MAVLink 1 or MAVLink 2 frame.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error of kind `InvalidInput` is returned
+    /// without writing anything if the bytes are not a single complete frame.
+    pub fn write_mavlink_raw(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        if !frame::is_complete(frame) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The data is not a single complete MAVLink frame",
+            ));
+        }
+        self.write(EntryType::Mavlink, frame)
+    }
+
+    /// Writes a blob to the log, fragmented across as many entries as needed.
+    ///
+    /// Blobs hold payloads too large for a single entry, such as images or point clouds. The
+    /// parser reassembles the fragments into a single entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The blob content.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the id of the blob, unique within the log, or an `io::Error`. An
+    /// error of kind `InvalidInput` is returned if the blob needs more than `u32::MAX` fragments.
+    pub fn write_blob(&mut self, data: &[u8]) -> std::io::Result<u32> {
+        self.write_blob_at(data, None)
+    }
+
+    /// Writes a blob to the log with an optional explicit timestamp for all its fragments.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The blob content.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the id of the blob or an `io::Error`.
+    pub(crate) fn write_blob_at(
+        &mut self,
+        data: &[u8],
+        timestamp_us: Option<u64>,
+    ) -> std::io::Result<u32> {
+        let count: u32 =
+            u32::try_from(data.len().div_ceil(BLOB_FRAGMENT_SIZE).max(1)).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "Blob is too large")
+            })?;
+        let blob_id = self.next_blob_id;
+        self.next_blob_id = self.next_blob_id.wrapping_add(1);
+        let mut fragments = data.chunks(BLOB_FRAGMENT_SIZE);
+        for index in 0..count {
+            let fragment = fragments.next().unwrap_or_default();
+            let mut payload: Vec<u8> = Vec::with_capacity(12 + fragment.len());
+            payload.extend_from_slice(&blob_id.to_le_bytes());
+            payload.extend_from_slice(&index.to_le_bytes());
+            payload.extend_from_slice(&count.to_le_bytes());
+            payload.extend_from_slice(fragment);
+            self.write_at(EntryType::Blob, timestamp_us, &payload)?;
+        }
+        Ok(blob_id)
+    }
+
+    /// Writes RTCM correction data to the log.
+    ///
+    /// The data is stored as received so that the correction stream of each link can be
+    /// reconstructed with `rtcm::extract_rtcm`.
     ///
     /// # Arguments
     ///
-    /// * `entry_type` - The type of log entry (Raw, Mavlink, or Text).
+    /// * `link` - Application defined id of the link the data was received on.
+    /// * `data` - The RTCM bytes.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn write_rtcm(&mut self, link: u8, data: &[u8]) -> std::io::Result<()> {
+        self.write_rtcm_at(link, data, None)
+    }
+
+    /// Writes RTCM correction data to the log with an optional explicit timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `link` - Application defined id of the link the data was received on.
+    /// * `data` - The RTCM bytes.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub(crate) fn write_rtcm_at(
+        &mut self,
+        link: u8,
+        data: &[u8],
+        timestamp_us: Option<u64>,
+    ) -> std::io::Result<()> {
+        let mut payload: Vec<u8> = Vec::with_capacity(1 + data.len());
+        payload.push(link);
+        payload.extend_from_slice(data);
+        self.write_at(EntryType::Rtcm, timestamp_us, &payload)
+    }
+
+    /// Writes a change of state of an actuator applying product, such as a spray boom section
+    /// turning on or a seed meter changing rate.
+    ///
+    /// Each event records the complete state of the actuator so that the as-applied map of a
+    /// job can be built with `analysis::as_applied` from the events and the positions logged
+    /// around them.
+    ///
+    /// # Arguments
+    ///
+    /// * `actuator` - Application defined id of the actuator, such as the index of a section.
+    /// * `active` - Whether the actuator is now applying product.
+    /// * `rate` - The target application rate in application defined units.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn write_actuator(&mut self, actuator: u8, active: bool, rate: f32) -> std::io::Result<()> {
+        self.write_actuator_at(actuator, active, rate, None)
+    }
+
+    /// Writes an actuator event to the log with an optional explicit timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `actuator` - Application defined id of the actuator, such as the index of a section.
+    /// * `active` - Whether the actuator is now applying product.
+    /// * `rate` - The target application rate in application defined units.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub(crate) fn write_actuator_at(
+        &mut self,
+        actuator: u8,
+        active: bool,
+        rate: f32,
+        timestamp_us: Option<u64>,
+    ) -> std::io::Result<()> {
+        let mut payload: Vec<u8> = Vec::with_capacity(6);
+        payload.push(actuator);
+        payload.push(active as u8);
+        payload.extend_from_slice(&rate.to_le_bytes());
+        self.write_at(EntryType::Actuator, timestamp_us, &payload)
+    }
+
+    /// Writes the weather at the time of the entry to the log.
+    ///
+    /// Observations can hold measured values, such as the wind estimate of a WIND_COV message
+    /// converted with `WeatherObservation::from_wind_cov`, or the weather entered by the
+    /// operator, so that the conditions of an application can be reviewed along with the track.
+    ///
+    /// # Arguments
+    ///
+    /// * `weather` - The observation to record.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn write_weather(&mut self, weather: &WeatherObservation) -> std::io::Result<()> {
+        self.write_weather_at(weather, None)
+    }
+
+    /// Writes a weather observation to the log with an optional explicit timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `weather` - The observation to record.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub(crate) fn write_weather_at(
+        &mut self,
+        weather: &WeatherObservation,
+        timestamp_us: Option<u64>,
+    ) -> std::io::Result<()> {
+        self.write_at(EntryType::Weather, timestamp_us, &weather.to_payload())
+    }
+
+    /// Writes the operator, job and products of the following entries to the log.
+    ///
+    /// Metadata applies from its entry until the next one, so it should be written when a job
+    /// starts and again whenever the operator, job or products change. Compliance exports can
+    /// then attribute telemetry to job records with `metadata::JobIndex`.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - The metadata to record.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn write_metadata(&mut self, metadata: &MissionMetadata) -> std::io::Result<()> {
+        self.write_metadata_at(metadata, None)
+    }
+
+    /// Writes mission metadata to the log with an optional explicit timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - The metadata to record.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub(crate) fn write_metadata_at(
+        &mut self,
+        metadata: &MissionMetadata,
+        timestamp_us: Option<u64>,
+    ) -> std::io::Result<()> {
+        self.write_at(EntryType::Metadata, timestamp_us, &metadata.to_payload())
+    }
+
+    /// Writes a captured camera image to the log along with the CAMERA_IMAGE_CAPTURED message
+    /// reporting it.
+    ///
+    /// The message is written first and the image follows immediately as a blob, both with the
+    /// same timestamp, so that `camera::extract_images` can link the image to its geotag.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The MavFrame holding the CAMERA_IMAGE_CAPTURED message.
+    /// * `image` - The encoded image, typically a JPEG.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the id of the image blob or an `io::Error`. An error of kind
+    /// `InvalidInput` is returned without writing anything if the frame does not hold a
+    /// CAMERA_IMAGE_CAPTURED message or if the logger only accepts MAVLink messages.
+    pub fn write_camera_image<M: Message>(
+        &mut self,
+        frame: MavFrame<M>,
+        image: &[u8],
+    ) -> std::io::Result<u32> {
+        if frame.msg.message_id() != CAMERA_IMAGE_CAPTURED_ID {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Camera images must be written with a CAMERA_IMAGE_CAPTURED message",
+            ));
+        }
+        if self.header.format_flags.mavlink_only {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Camera images cannot be written to a MAVLink only log",
+            ));
+        }
+        let timestamp_us: Option<u64> = if self.header.format_flags.no_timestamp {
+            None
+        } else {
+            Some(self.clock.now_us()?)
+        };
+        self.write_mavlink_at(frame, timestamp_us)?;
+        self.write_blob_at(image, timestamp_us)
+    }
+
+    /// Writes a drop report to the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `drops` - Message ids and counts of MAVLink frames that were discarded.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub(crate) fn write_drops_at(
+        &mut self,
+        drops: &[(u32, u32)],
+        timestamp_us: Option<u64>,
+    ) -> std::io::Result<()> {
+        let mut payload: Vec<u8> = Vec::with_capacity(drops.len() * 8);
+        for (msg_id, count) in drops {
+            payload.extend_from_slice(&msg_id.to_le_bytes());
+            payload.extend_from_slice(&count.to_le_bytes());
+        }
+        self.write_at(EntryType::Drops, timestamp_us, &payload)
+    }
+
+    /// Writes a MAVLink message to the log with an optional explicit timestamp.
+    ///
+    /// The message is observed by the logger clock. If a GPS disciplined clock updates its
+    /// correction model, the model is recorded in a text entry following the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The MavFrame to log.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub(crate) fn write_mavlink_at<M: Message>(
+        &mut self,
+        frame: MavFrame<M>,
+        timestamp_us: Option<u64>,
+    ) -> std::io::Result<()> {
+        let model = self.clock.observe(&frame.msg);
+        match frame.protocol_version {
+            mavlink::MavlinkVersion::V1 => {
+                let mut msg: MAVLinkV1MessageRaw = MAVLinkV1MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                self.write_at(EntryType::Mavlink, timestamp_us, msg.raw_bytes())?;
+            }
+            mavlink::MavlinkVersion::V2 => {
+                let mut msg: MAVLinkV2MessageRaw = MAVLinkV2MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                self.write_at(EntryType::Mavlink, timestamp_us, msg.raw_bytes())?;
+            }
+        }
+        // MAVLink only logs cannot record the correction model
+        match model {
+            Some(model) if !self.header.format_flags.mavlink_only => {
+                self.write_at(EntryType::Text, timestamp_us, model.to_text().as_bytes())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes a log entry to the file using the logger clock for the timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry_type` - The type of log entry.
     /// * `data` - The data to log.
     ///
     /// # Returns
     ///
     /// A `Result` indicating success or failure.
     fn write(&mut self, entry_type: EntryType, data: &[u8]) -> std::io::Result<()> {
+        self.write_at(entry_type, None, data)
+    }
+
+    /// Writes a log entry to the file after running the transforms on it.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry_type` - The type of log entry.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    /// * `data` - The data to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. Nothing is written if a transform drops the
+    /// entry. An error of kind `InvalidInput` is returned without writing anything if the
+    /// payload does not fit the entry size field, see the `large_entries` format flag, or does
+    /// not fit a block of a chunked log. The error of the clock is returned if it rejects
+    /// backwards time, see `BackwardsPolicy::Reject`, and the error of a sink if copying the
+    /// entry to it failed.
+    pub(crate) fn write_at(
+        &mut self,
+        entry_type: EntryType,
+        timestamp_us: Option<u64>,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        if self.transforms.is_empty() {
+            return self.emit_entry(entry_type, timestamp_us, data);
+        }
+        let timestamp_us: Option<u64> = if self.header.format_flags.no_timestamp {
+            None
+        } else {
+            match timestamp_us {
+                Some(timestamp_us) => Some(timestamp_us),
+                None => Some(self.clock.now_us()?),
+            }
+        };
+        let mut draft = EntryDraft {
+            entry_type,
+            timestamp_us,
+            data: data.to_vec(),
+        };
+        let mut inserted: Vec<EntryDraft> = Vec::new();
+        let kept = transform::apply(&self.transforms, &mut draft, &mut inserted)?;
+        for entry in inserted {
+            self.emit_entry(entry.entry_type, entry.timestamp_us, &entry.data)?;
+        }
+        if !kept {
+            return Ok(());
+        }
+        self.emit_entry(draft.entry_type, draft.timestamp_us, &draft.data)
+    }
+
+    /// Packs a log entry and writes it to the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry_type` - The type of log entry.
+    /// * `timestamp_us` - The entry timestamp to record. If `None`, the logger clock is used.
+    /// * `data` - The data to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure, see `write_at`.
+    fn emit_entry(
+        &mut self,
+        entry_type: EntryType,
+        timestamp_us: Option<u64>,
+        data: &[u8],
+    ) -> std::io::Result<()> {
         // If we are in MAVLink only mode and there is an attempt to write a non MAVLink entry, return an error.
         if entry_type != EntryType::Mavlink && self.header.format_flags.mavlink_only {
             return Err(std::io::Error::new(
@@ -150,34 +1062,285 @@ impl RotatingMavLogger {
                 "This logger accepts only mavlink messages",
             ));
         }
+        if let (Some(cache), EntryType::Mavlink) = (&mut self.snapshot, entry_type) {
+            cache.observe(data);
+        }
+        if let (Some(state), EntryType::Mavlink) = (&mut self.segment_state, entry_type) {
+            state.observe(data);
+        }
+
+        let recorded_timestamp_us: Option<u64> = if self.header.format_flags.no_timestamp {
+            None
+        } else {
+            match timestamp_us {
+                Some(timestamp_us) => Some(timestamp_us),
+                None => Some(self.clock.now_us()?),
+            }
+        };
+        #[allow(unused_mut)]
+        let mut record_bytes = self.pack_entry(entry_type, recorded_timestamp_us, data)?;
+        #[cfg(feature = "compression")]
+        if self.header.format_flags.chunked
+            && record_bytes.len() <= MAX_BLOCK_ENTRIES_SIZE
+            && self.block.len() + record_bytes.len() > MAX_BLOCK_ENTRIES_SIZE
+        {
+            // Writing the full block may rotate the file, and the entry must then link to the
+            // header of the new file instead of the last entry of the block
+            self.flush()?;
+            if self.rotated {
+                record_bytes = self.pack_entry(entry_type, recorded_timestamp_us, data)?;
+            }
+        }
+        // Linked before writing, so that a rotation while writing starts the chain over
+        #[cfg(feature = "hash_chain")]
+        if self.header.format_flags.hash_chain {
+            self.previous_hash = chain::entry_hash(&record_bytes);
+        }
+        #[cfg(feature = "compression")]
+        if self.header.format_flags.chunked {
+            self.buffer_entry(&record_bytes)?;
+        } else {
+            self.emit_bytes(&record_bytes)?;
+        }
+        #[cfg(not(feature = "compression"))]
+        self.emit_bytes(&record_bytes)?;
+        if self.header.format_flags.sequence {
+            self.sequence = self.sequence.wrapping_add(1);
+        }
+        self.footer.add_entry(recorded_timestamp_us);
+        if entry_type == EntryType::Mavlink {
+            self.hooks.fire(recorded_timestamp_us, data);
+        }
+
+        if entry_type == EntryType::Snapshot {
+            return Ok(());
+        }
+        let rotated = std::mem::take(&mut self.rotated);
+        if rotated {
+            self.replay_state()?;
+        }
+        self.write_due_snapshot(rotated)
+    }
 
-        // Construct the log entry
+    /// Packs a log entry as stored in the file, linked to the previous entry of the hash chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry_type` - The type of log entry.
+    /// * `timestamp_us` - The entry timestamp to record, `None` if timestamps are not logged.
+    /// * `data` - The data to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the packed entry, see `write_at` for the errors.
+    fn pack_entry(
+        &mut self,
+        entry_type: EntryType,
+        timestamp_us: Option<u64>,
+        data: &[u8],
+    ) -> std::io::Result<Vec<u8>> {
         let mut record_bytes: Vec<u8> = Vec::new();
         if !self.header.format_flags.mavlink_only {
             // If mavlink only, there is no need to track the entry type
             record_bytes.extend_from_slice(&(entry_type as u8).to_le_bytes());
         }
-        if !self.header.format_flags.no_timestamp {
-            // If tracking log entry time, add the timestamp
-            let timestamp_us: u64 = match self.time.elapsed() {
-                Ok(elapsed) => elapsed.as_micros() as u64,
-                Err(_) => {
-                    self.time = SystemTime::now();
-                    0
-                }
-            };
+        if let Some(timestamp_us) = timestamp_us {
             record_bytes.extend_from_slice(&timestamp_us.to_le_bytes());
         }
+        if self.header.format_flags.sequence {
+            record_bytes.extend_from_slice(&self.sequence.to_le_bytes());
+        }
+        #[cfg(feature = "hash_chain")]
+        if self.header.format_flags.hash_chain {
+            record_bytes.extend_from_slice(&self.previous_hash);
+        }
+        // The entry payload, compressed and encrypted as configured
+        let payload: &[u8] = data;
+        #[cfg(feature = "compression")]
+        let compressed: Vec<u8>;
+        #[cfg(feature = "compression")]
+        let payload: &[u8] = match &mut self.compressor {
+            Some(compressor) => {
+                compressed = compressor.compress(payload)?;
+                &compressed
+            }
+            None => payload,
+        };
+        #[cfg(feature = "encryption")]
+        let sealed: Vec<u8>;
+        #[cfg(feature = "encryption")]
+        let payload: &[u8] = match &mut self.cipher {
+            Some(cipher) => {
+                // The entry fields written so far are authenticated along with the payload
+                sealed = cipher.encrypt(&record_bytes, payload)?;
+                &sealed
+            }
+            None => payload,
+        };
         if !self.header.format_flags.mavlink_only {
             // If mavlink only, no need to add the payload size
-            let size: u16 = data.len() as u16;
-            record_bytes.extend_from_slice(&size.to_le_bytes());
+            record_bytes.extend_from_slice(&self.size_field(payload.len())?);
         }
-        record_bytes.extend_from_slice(data);
-        self.file_handler.emit(&record_bytes)?;
+        record_bytes.extend_from_slice(payload);
+        Ok(record_bytes)
+    }
+
+    /// Writes the kept vehicle state at the start of a new file, if state replay is enabled.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn replay_state(&mut self) -> std::io::Result<()> {
+        let frames = match &self.segment_state {
+            Some(state) => state.frames(),
+            None => return Ok(()),
+        };
+        // replayed frames were already seen by the hooks when first written
+        let hooks = std::mem::take(&mut self.hooks);
+        let replayed = frames
+            .iter()
+            .try_for_each(|frame| self.emit_entry(EntryType::Mavlink, None, frame));
+        self.hooks = hooks;
+        replayed
+    }
 
+    /// Writes a snapshot if the log file rotated or the snapshot interval elapsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `rotated` - Whether the log file rotated while writing the last entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn write_due_snapshot(&mut self, rotated: bool) -> std::io::Result<()> {
+        let payload = match &mut self.snapshot {
+            Some(cache) if rotated || cache.is_due() => cache.take_snapshot(),
+            _ => return Ok(()),
+        };
+        if payload.is_empty() {
+            return Ok(());
+        }
+        self.emit_entry(EntryType::Snapshot, None, &payload)
+    }
+
+    /// Writes packed bytes to the file, syncing it if the black box profile is set and noting
+    /// rotations.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The packed entries or block.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn emit_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.file_handler.emit(bytes)?;
+        if let Some(black_box) = &mut self.black_box {
+            black_box.on_write(&self.base_path)?;
+        }
+        self.file_len += bytes.len() as u64;
+        self.bytes_written += bytes.len() as u64;
+        // the file handler starts a new file with the header once the current file is full, so
+        // the size of the file only needs checking from then on
+        if self.file_len >= self.max_bytes {
+            let file_len = std::fs::metadata(&self.base_path)?.len();
+            if file_len < self.file_len {
+                self.rotated = true;
+                self.rotations += 1;
+                self.footer = LogFooter::default();
+                // The first entry of the new file links to its header
+                #[cfg(feature = "hash_chain")]
+                {
+                    self.previous_hash = chain::entry_hash(&self.header.pack());
+                }
+            }
+            self.file_len = file_len;
+        }
         Ok(())
     }
+
+    /// Packs the payload size field of an entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the stored entry payload.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the 2 byte size field, or the 4 byte size field if the
+    /// `large_entries` format flag is set. An error of kind `InvalidInput` is returned if the
+    /// size does not fit the field.
+    fn size_field(&self, size: usize) -> std::io::Result<Vec<u8>> {
+        if self.header.format_flags.large_entries {
+            match u32::try_from(size) {
+                Ok(size) => Ok(size.to_le_bytes().to_vec()),
+                Err(_) => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Entry payload of {size} bytes exceeds 4 GiB"),
+                )),
+            }
+        } else {
+            match u16::try_from(size) {
+                Ok(size) => Ok(size.to_le_bytes().to_vec()),
+                Err(_) => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Entry payload of {size} bytes requires the large_entries format flag"),
+                )),
+            }
+        }
+    }
+
+    /// Adds an entry to the current block of a chunked log, writing the block once full.
+    ///
+    /// The block must have room for the entry, `emit_entry` writes it beforehand otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `record_bytes` - The packed entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    #[cfg(feature = "compression")]
+    fn buffer_entry(&mut self, record_bytes: &[u8]) -> std::io::Result<()> {
+        if record_bytes.len() > MAX_BLOCK_ENTRIES_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Entry of {} bytes does not fit in a block",
+                    record_bytes.len()
+                ),
+            ));
+        }
+        if self.block_entries == 0 {
+            self.block_first_timestamp_us = 0;
+            if !self.header.format_flags.no_timestamp {
+                // The timestamp follows the entry type unless only MAVLink is logged
+                let start = if self.header.format_flags.mavlink_only {
+                    0
+                } else {
+                    1
+                };
+                self.block_first_timestamp_us =
+                    u64::from_le_bytes(record_bytes[start..start + 8].try_into().unwrap());
+            }
+        }
+        self.block.extend_from_slice(record_bytes);
+        self.block_entries += 1;
+        if self.block.len() >= self.block_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RotatingMavLogger {
+    /// Writes the last block of a chunked log. Write errors are ignored, use `flush` before
+    /// dropping the logger to observe them.
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
 }
 
 #[cfg(test)]
@@ -494,4 +1657,138 @@ mod tests {
         // Remove the temporary file
         tmpfile.close().unwrap();
     }
+
+    /// Test that entries are timestamped with the clock set on the logger and that an entry is
+    /// not written when the clock rejects backwards time.
+    #[test]
+    fn test_write_custom_clock() {
+        use crate::clock::{BackwardsPolicy, ClockSource};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let mut tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let tmpfile_path = tmpfile.path().to_str().unwrap();
+        let mut logger: RotatingMavLogger =
+            RotatingMavLogger::new(tmpfile_path, 1000, 0, None, None)
+                .expect("Failed to create logger");
+        let time = Arc::new(AtomicU64::new(5_000));
+        let source_time = time.clone();
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || source_time.load(Ordering::Relaxed))),
+            BackwardsPolicy::Reject,
+        ));
+
+        logger.write_raw(&[1]).unwrap();
+        time.store(4_000, Ordering::Relaxed);
+        let error = logger.write_raw(&[2]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        time.store(6_000, Ordering::Relaxed);
+        logger.write_raw(&[3]).unwrap();
+
+        let mut content: Vec<u8> = Vec::new();
+        tmpfile.read_to_end(&mut content).unwrap();
+        let entries = &content[FileHeader::MIN_SIZE..];
+        assert_eq!(entries.len(), 2 * 12);
+        assert_eq!(entries[1..9], 5_000u64.to_le_bytes());
+        assert_eq!(entries[11], 1);
+        assert_eq!(entries[13..21], 6_000u64.to_le_bytes());
+        assert_eq!(entries[23], 3);
+        tmpfile.close().unwrap();
+    }
+
+    /// Test that transforms modify, drop and copy entries before they are written.
+    #[test]
+    fn test_write_transforms() {
+        use crate::clock::{BackwardsPolicy, ClockSource};
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        let mut tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let tmpfile_path = tmpfile.path().to_str().unwrap();
+
+        let copies: Arc<Mutex<Vec<EntryDraft>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = copies.clone();
+        let count = AtomicU64::new(0);
+        let mut logger: RotatingMavLogger =
+            RotatingMavLogger::new(tmpfile_path, 100000, 0, None, None)
+                .expect("Failed to create logger")
+                // keep every other raw entry
+                .with_transform(move |draft| {
+                    let dropped = draft.entry_type == EntryType::Raw
+                        && count.fetch_add(1, Ordering::Relaxed) % 2 == 1;
+                    if dropped { Action::Drop } else { Action::Keep }
+                })
+                .with_transform(|draft| {
+                    if draft.entry_type == EntryType::Text {
+                        draft.data = b"redacted".to_vec();
+                    }
+                    Action::Keep
+                })
+                .with_transform(move |draft| match draft.entry_type {
+                    EntryType::Text => Action::Duplicate(sink.clone()),
+                    _ => Action::Keep,
+                });
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(|| 7_000)),
+            BackwardsPolicy::Hold,
+        ));
+
+        for i in 0..4 {
+            logger.write_raw(&[i]).unwrap();
+        }
+        logger.write_text("password").unwrap();
+
+        let mut content: Vec<u8> = Vec::new();
+        tmpfile.read_to_end(&mut content).unwrap();
+        let entries = &content[FileHeader::MIN_SIZE..];
+        assert_eq!(entries.len(), 2 * 12 + 11 + 8);
+        assert_eq!(entries[11], 0);
+        assert_eq!(entries[23], 2);
+        assert_eq!(&entries[35..], b"redacted");
+
+        let copies = copies.lock().unwrap();
+        assert_eq!(
+            *copies,
+            vec![EntryDraft {
+                entry_type: EntryType::Text,
+                timestamp_us: Some(7_000),
+                data: b"redacted".to_vec(),
+            }]
+        );
+        tmpfile.close().unwrap();
+    }
+
+    /// Test that entries inserted by a transform are written before the entry, even if a later
+    /// transform drops it.
+    #[test]
+    fn test_write_transform_insert() {
+        let mut tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let tmpfile_path = tmpfile.path().to_str().unwrap();
+
+        let mut logger: RotatingMavLogger =
+            RotatingMavLogger::new(tmpfile_path, 100000, 0, None, None)
+                .expect("Failed to create logger")
+                .with_transform(|draft| {
+                    Action::Insert(EntryDraft {
+                        entry_type: EntryType::Text,
+                        timestamp_us: draft.timestamp_us,
+                        data: format!("before {}", draft.data[0]).into_bytes(),
+                    })
+                })
+                .with_transform(|draft| match draft.data[0] {
+                    0 => Action::Drop,
+                    _ => Action::Keep,
+                });
+        logger.write_raw(&[0]).unwrap();
+        logger.write_raw(&[1]).unwrap();
+
+        let mut content: Vec<u8> = Vec::new();
+        tmpfile.read_to_end(&mut content).unwrap();
+        let entries = &content[FileHeader::MIN_SIZE..];
+        assert_eq!(entries.len(), 2 * (11 + 8) + 11 + 1);
+        assert_eq!(&entries[11..19], b"before 0");
+        assert_eq!(&entries[30..38], b"before 1");
+        assert_eq!(entries[49], 1);
+        tmpfile.close().unwrap();
+    }
 }
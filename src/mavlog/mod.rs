@@ -1,7 +1,63 @@
 pub mod header;
 
+pub mod footer;
+
 #[cfg(feature = "parser")]
 pub mod parser;
 
+#[cfg(feature = "parser")]
+pub mod compat;
+
+#[cfg(feature = "parser")]
+pub mod camera;
+
+#[cfg(feature = "parser")]
+pub mod rtcm;
+
 #[cfg(feature = "logger")]
 pub mod logger;
+
+#[cfg(feature = "logger")]
+pub mod background;
+
+#[cfg(feature = "logger")]
+pub mod transform;
+
+#[cfg(feature = "logger")]
+pub mod adaptive;
+
+#[cfg(feature = "logger")]
+pub mod session;
+
+#[cfg(feature = "logger")]
+pub mod ring;
+
+#[cfg(feature = "logger")]
+pub mod blackbox;
+
+#[cfg(feature = "logger")]
+pub mod snapshot;
+
+#[cfg(feature = "logger")]
+pub mod hooks;
+
+#[cfg(feature = "logger")]
+mod segment;
+
+#[cfg(all(feature = "parser", feature = "logger"))]
+pub mod splitter;
+
+#[cfg(all(feature = "parser", feature = "logger"))]
+pub mod copy;
+
+#[cfg(feature = "encryption")]
+pub mod encryption;
+
+#[cfg(feature = "hash_chain")]
+mod chain;
+
+#[cfg(feature = "compression")]
+pub mod dictionary;
+
+#[cfg(feature = "compression")]
+pub mod block;
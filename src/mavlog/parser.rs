@@ -1,13 +1,47 @@
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::fs::File;
+use std::io::Read;
+#[cfg(feature = "compression")]
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use mavlink::error::MessageReadError;
 use mavlink::peek_reader::PeekReader;
-use mavlink::{MavlinkVersion, Message, read_versioned_msg};
+use mavlink::{MavHeader, MavlinkVersion, Message, read_versioned_msg};
 
-use super::header::{FileHeader, MavlinkDefinitionPayloadType};
-use crate::mav_parser::{LogEntry, MavParser};
+#[cfg(feature = "compression")]
+use super::block::BlockReader;
+#[cfg(feature = "hash_chain")]
+use super::chain;
+use super::compat;
+#[cfg(feature = "compression")]
+use super::dictionary::{self, EntryCompressor};
+#[cfg(feature = "encryption")]
+use super::encryption::EntryCipher;
+use super::footer::LogFooter;
+use super::header::{EncryptionHeader, FileHeader, MavlinkDefinitionPayloadType};
+use crate::dialect::{DialectMismatch, check_dialect};
+use crate::frame::{self, FrameError};
+#[cfg(feature = "encryption")]
+use crate::keys::KeyProvider;
+use crate::mav_parser::{ActuatorEvent, Blob, LogEntry, MavParser, RtcmData};
+use crate::metadata::MissionMetadata;
+use crate::weather::WeatherObservation;
+
+/// Size of the link to the previous entry in hash-chained log files.
+const HASH_LINK_SIZE: usize = 8;
+/// Size of the header preceding the data of a blob fragment.
+const BLOB_FRAGMENT_HEADER_SIZE: usize = 12;
+/// Largest amount of data taken from a `PeekReader` at once. The reader only buffers about one
+/// MAVLink frame, so larger payloads are read in pieces.
+const MAX_READ_SIZE: usize = 255;
+/// Default largest entry payload accepted, in bytes. Entries of logs without the
+/// `large_entries` format flag are always smaller.
+pub const DEFAULT_MAX_ENTRY_SIZE: usize = 16 * 1024 * 1024;
+/// Largest timestamp step from the last entry an entry found while resynchronizing may have.
+const MAX_RESYNC_STEP_US: u64 = 3_600_000_000;
 
 /// Enum representing the type of log entry.
 ///
@@ -15,10 +49,24 @@ use crate::mav_parser::{LogEntry, MavParser};
 /// - `Raw`: Raw binary data.
 /// - `Mavlink`: MAVLink message.
 /// - `Utf8Text`: UTF-8 encoded text.
+/// - `Drops`: Report of MAVLink frames discarded by the logger.
+/// - `Blob`: Fragment of a blob.
+/// - `Rtcm`: RTCM correction data.
+/// - `Snapshot`: The last frames of selected messages.
+/// - `Actuator`: A change of state of an actuator applying product.
+/// - `Weather`: The weather at the time of the entry.
+/// - `Metadata`: The operator, job and products of the following entries.
 enum EntryType {
     Raw = 0,
     Mavlink = 1,
     Utf8Text = 2,
+    Drops = 3,
+    Blob = 4,
+    Rtcm = 5,
+    Snapshot = 6,
+    Actuator = 7,
+    Weather = 8,
+    Metadata = 9,
 }
 
 impl TryFrom<u8> for EntryType {
@@ -29,18 +77,224 @@ impl TryFrom<u8> for EntryType {
             0 => Ok(EntryType::Raw),
             1 => Ok(EntryType::Mavlink),
             2 => Ok(EntryType::Utf8Text),
+            3 => Ok(EntryType::Drops),
+            4 => Ok(EntryType::Blob),
+            5 => Ok(EntryType::Rtcm),
+            6 => Ok(EntryType::Snapshot),
+            7 => Ok(EntryType::Actuator),
+            8 => Ok(EntryType::Weather),
+            9 => Ok(EntryType::Metadata),
             _ => Err(()),
         }
     }
 }
 
+/// Source of the entries of a log file.
+///
+/// Entries are read directly from the file, or from its decompressed blocks if the log is
+/// chunked.
+enum EntrySource {
+    Plain(File),
+    #[cfg(feature = "compression")]
+    Blocks(BlockReader<File>),
+    /// Left behind by a parser whose reader moved on to an appended log.
+    Empty,
+}
+
+impl Read for EntrySource {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            EntrySource::Plain(file) => file.read(buffer),
+            #[cfg(feature = "compression")]
+            EntrySource::Blocks(blocks) => blocks.read(buffer),
+            EntrySource::Empty => Ok(0),
+        }
+    }
+}
+
+/// Reads an exact amount of data that may exceed the look-ahead buffer of a `PeekReader`.
+///
+/// The data is collected piece by piece, so a corrupt size field cannot allocate more memory
+/// than the remaining content of the file.
+///
+/// # Errors
+///
+/// Returns a `MessageReadError` if the data ends before `size` bytes are read.
+fn read_bytes(
+    reader: &mut PeekReader<EntrySource>,
+    size: usize,
+) -> Result<Vec<u8>, MessageReadError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    while bytes.len() < size {
+        let amount = (size - bytes.len()).min(MAX_READ_SIZE);
+        bytes.extend_from_slice(reader.read_exact(amount)?);
+    }
+    Ok(bytes)
+}
+
+/// Builds the error for a file header that ends early.
+fn truncated(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, message)
+}
+
+/// Builds the error for a file header describing an unsupported format.
+fn unsupported(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, message)
+}
+
+/// Builds the error for a file header with contradicting fields.
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Records the location of an entry and advances the file offset of the next entry.
+///
+/// The location is lost for good once an entry of unknown length is read, since the parser may
+/// then have skipped data searching for the next MAVLink frame.
+///
+/// # Arguments
+///
+/// - `entry`: The entry that was read.
+/// - `position`: The file offset of the entry, updated to the offset of the next entry.
+/// - `entry_len`: The length of the entry in bytes, if known.
+fn track_entry<M: Message>(
+    entry: &mut LogEntry<M>,
+    position: &mut Option<u64>,
+    entry_len: Option<usize>,
+) {
+    let entry_len: Option<u64> = position.and(entry_len.map(|len| len as u64));
+    entry.offset = entry_len.and(*position);
+    entry.entry_len = entry_len;
+    *position = position.zip(entry_len).map(|(offset, len)| offset + len);
+}
+
+/// Decodes the MAVLink frames of a SNAPSHOT entry payload.
+///
+/// Frames of messages unknown to the dialect are skipped.
+///
+/// # Arguments
+///
+/// - `payload`: The complete MAVLink frames one after another.
+///
+/// # Errors
+///
+/// Returns a `MessageReadError` if the payload does not split into valid MAVLink frames.
+fn decode_snapshot<M: Message>(payload: &[u8]) -> Result<Vec<(MavHeader, M)>, MessageReadError> {
+    let mut messages = Vec::new();
+    let mut rest = payload;
+    while !rest.is_empty() {
+        let len = match (rest.len() >= frame::LENGTH_PEEK_SIZE)
+            .then(|| frame::frame_len(rest))
+            .flatten()
+        {
+            Some(len) if len <= rest.len() => len,
+            _ => {
+                return Err(MessageReadError::Io(invalid(
+                    "Snapshot entry holds a truncated MAVLink frame",
+                )));
+            }
+        };
+        match frame::decode::<M>(&rest[..len]) {
+            Ok(decoded) => messages.push((decoded.header, decoded.msg)),
+            Err(FrameError::Parse(_)) => {}
+            Err(FrameError::Invalid) => {
+                return Err(MessageReadError::Io(invalid(
+                    "Snapshot entry holds an invalid MAVLink frame",
+                )));
+            }
+        }
+        rest = &rest[len..];
+    }
+    Ok(messages)
+}
+
+/// Records the MAVLink 2 flags of the frame an entry was read from.
+///
+/// Frames read with `read_versioned_msg` have their flags peeked beforehand, so like the entry
+/// length they are only accurate when the frame starts where it is expected.
+///
+/// # Arguments
+///
+/// - `entry`: The entry that was read.
+/// - `flags`: The incompatibility and compatibility flags, `None` for MAVLink 1 frames.
+fn set_frame_flags<M: Message>(entry: &mut LogEntry<M>, flags: Option<(u8, u8)>) {
+    entry.incompat_flags = flags.map(|(incompat, _)| incompat);
+    entry.compat_flags = flags.map(|(_, compat)| compat);
+}
+
+/// Reads the next MAVLink frame of a MAVLink only log without decoding it.
+///
+/// Data that does not start a frame of the expected version is skipped, after which file
+/// offsets are no longer reported.
+///
+/// # Arguments
+///
+/// - `reader`: The reader positioned at the next entry.
+/// - `mav_version`: The MAVLink version of the log file.
+/// - `timestamped`: Whether each frame is preceded by a timestamp.
+/// - `position`: The file offset of the entry, updated to the offset of the next entry.
+///
+/// # Errors
+///
+/// Returns a `MessageReadError::Io` error if the end of the file is reached.
+fn read_raw_frame(
+    reader: &mut PeekReader<EntrySource>,
+    mav_version: MavlinkVersion,
+    timestamped: bool,
+    position: &mut Option<u64>,
+) -> Result<RawEntry, MessageReadError> {
+    let timestamp_len = if timestamped { 8 } else { 0 };
+    let frame_len = loop {
+        let peeked: &[u8] = reader.peek_exact(timestamp_len + frame::LENGTH_PEEK_SIZE)?;
+        let frame = &peeked[timestamp_len..];
+        let frame_len = frame::frame_len(frame)
+            .filter(|_| frame::version_from_magic(frame[0]) == Some(mav_version));
+        if let Some(frame_len) = frame_len {
+            break frame_len;
+        }
+        reader.read_u8()?;
+        *position = None;
+    };
+    let timestamp = if timestamped {
+        reader
+            .read_exact(8)?
+            .try_into()
+            .ok()
+            .map(u64::from_le_bytes)
+    } else {
+        None
+    };
+    let payload: Vec<u8> = read_bytes(reader, frame_len)?;
+    let offset = *position;
+    *position = position.map(|offset| offset + (timestamp_len + frame_len) as u64);
+    Ok(RawEntry {
+        entry_type: EntryType::Mavlink as u8,
+        timestamp,
+        sequence: None,
+        offset,
+        payload,
+    })
+}
+
+/// Returns the file offset of the first entry, or `None` if entries are stored in compressed
+/// blocks where file offsets do not apply.
+fn first_entry_offset(header: &FileHeader) -> Option<u64> {
+    if header.format_flags.chunked {
+        None
+    } else {
+        Some(header.packed_size() as u64)
+    }
+}
+
 /// Parser for MAVLink-only log files without timestamps.
 ///
 /// This parser assumes the log file contains only MAVLink messages and no timestamps.
 /// It reads MAVLink messages sequentially from the file.
 struct MavlinkOnlyNoTimestampParser<M: Message> {
-    reader: PeekReader<File>,
+    reader: PeekReader<EntrySource>,
     mav_version: MavlinkVersion,
+    /// File offset of the next entry, `None` if unknown.
+    position: Option<u64>,
     _phantom: std::marker::PhantomData<M>,
 }
 
@@ -72,20 +326,70 @@ impl<M: Message> MavParser for MavlinkOnlyNoTimestampParser<M> {
         // it tries to unpack the current data and gets something unexpected. Since this is a mavlink only file with
         // no timestamps, we can safely allow this to happen. The Mavlink infrastructure has a lot of hours and false
         // positives in the magic number search do not seem like a problem with Mavlink only data streams.
-        let (header, message) = read_versioned_msg::<M, File>(&mut self.reader, self.mav_version)?;
+        let peeked: &[u8] = self.reader.peek_exact(frame::FLAGS_PEEK_SIZE)?;
+        let frame_len = frame::frame_len(peeked);
+        let flags = frame::flags(peeked);
+        let (header, message) =
+            read_versioned_msg::<M, EntrySource>(&mut self.reader, self.mav_version)?;
         entry.mav_header = Some(header);
         entry.mav_message = Some(message);
+        entry.protocol_version = Some(self.mav_version);
+        set_frame_flags(&mut entry, flags);
+        track_entry(&mut entry, &mut self.position, frame_len);
         Ok(entry)
     }
 }
 
+/// A parser for the entries of a log file, as selected from its format flags.
+trait EntryParser: MavParser {
+    /// Returns the reader the entries are read from.
+    fn reader(&mut self) -> &mut PeekReader<EntrySource>;
+
+    /// Returns the offset of the next entry from the start of the log, `None` if unknown.
+    fn position(&self) -> Option<u64>;
+
+    /// Returns whether an entry read ahead is waiting to be returned.
+    fn has_pending(&self) -> bool {
+        false
+    }
+
+    /// Reads the next entry without decoding its payload, see `MavLogParser::read_raw_entry`.
+    fn read_raw_entry(&mut self) -> Result<RawEntry, MessageReadError>;
+
+    /// Sets the largest entry payload accepted, see `MavLogParser::set_max_entry_size`.
+    ///
+    /// MAVLink only files hold frames of bounded size, so their parsers ignore it.
+    fn set_max_entry_size(&mut self, _max_entry_size: usize) {}
+}
+
+impl<M: Message> EntryParser for MavlinkOnlyNoTimestampParser<M> {
+    fn reader(&mut self) -> &mut PeekReader<EntrySource> {
+        &mut self.reader
+    }
+
+    fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    fn read_raw_entry(&mut self) -> Result<RawEntry, MessageReadError> {
+        read_raw_frame(
+            &mut self.reader,
+            self.mav_version,
+            false,
+            &mut self.position,
+        )
+    }
+}
+
 /// Parser for MAVLink-only log files with timestamps.
 ///
 /// This parser assumes the log file contains only MAVLink type data, each preceded by a timestamp.
 /// It reads MAVLink messages and their associated timestamps sequentially from the file.
 struct TimestampedMavlinkOnlyParser<M: Message> {
-    reader: PeekReader<File>,
+    reader: PeekReader<EntrySource>,
     mav_version: MavlinkVersion,
+    /// File offset of the next entry, `None` if unknown.
+    position: Option<u64>,
     _phantom: std::marker::PhantomData<M>,
 }
 
@@ -117,7 +421,12 @@ impl<M: Message> MavParser for TimestampedMavlinkOnlyParser<M> {
             MavlinkVersion::V1 => mavlink::MAV_STX,
             MavlinkVersion::V2 => mavlink::MAV_STX_V2,
         };
-        if self.reader.peek_exact(9)?[8] == magic_number {
+        let peeked: &[u8] = self.reader.peek_exact(8 + frame::FLAGS_PEEK_SIZE)?;
+        let mut entry_len: Option<usize> = None;
+        let mut flags: Option<(u8, u8)> = None;
+        if peeked[8] == magic_number {
+            entry_len = frame::frame_len(&peeked[8..]).map(|frame_len| 8 + frame_len);
+            flags = frame::flags(&peeked[8..]);
             let timestamp_raw: &[u8] = self.reader.read_exact(8)?;
             entry.timestamp = match timestamp_raw.try_into() {
                 Ok(bytes) => Some(u64::from_le_bytes(bytes)),
@@ -127,24 +436,144 @@ impl<M: Message> MavParser for TimestampedMavlinkOnlyParser<M> {
         // WARNING: this will silently fail and try to get next mavlink message on data corruption
         // this is a concern that some messages could be associated with the wrong timestamp
         // we need a version of this to fail immediately on any parsing issue
-        let (header, message) = read_versioned_msg::<M, File>(&mut self.reader, self.mav_version)?;
+        let (header, message) =
+            read_versioned_msg::<M, EntrySource>(&mut self.reader, self.mav_version)?;
         entry.mav_header = Some(header);
         entry.mav_message = Some(message);
+        entry.protocol_version = Some(self.mav_version);
+        set_frame_flags(&mut entry, flags);
+        track_entry(&mut entry, &mut self.position, entry_len);
         Ok(entry)
     }
 }
 
+impl<M: Message> EntryParser for TimestampedMavlinkOnlyParser<M> {
+    fn reader(&mut self) -> &mut PeekReader<EntrySource> {
+        &mut self.reader
+    }
+
+    fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    fn read_raw_entry(&mut self) -> Result<RawEntry, MessageReadError> {
+        read_raw_frame(&mut self.reader, self.mav_version, true, &mut self.position)
+    }
+}
+
 /// Parser for mixed log files containing various entry types.
 ///
 /// This parser can handle log files with raw data, MAVLink messages, and UTF-8 text entries.
-/// It also supports optional timestamps for each entry.
+/// It also supports optional timestamps and sequence numbers for each entry, and encrypted or
+/// compressed entry payloads.
 pub struct MixedParser<M: Message> {
     timestamped: bool,
-    reader: PeekReader<File>,
+    sequenced: bool,
+    chained: bool,
+    large_entries: bool,
+    reader: PeekReader<EntrySource>,
     mav_version: MavlinkVersion,
+    /// File offset of the next entry, `None` if unknown.
+    position: Option<u64>,
+    /// Largest entry payload accepted, larger size fields are treated as corruption.
+    max_entry_size: usize,
+    /// Timestamp of the last entry read, used to recognize entries when resynchronizing.
+    last_timestamp: Option<u64>,
+    #[cfg(feature = "encryption")]
+    cipher: Option<EntryCipher>,
+    #[cfg(feature = "compression")]
+    decompressor: Option<EntryCompressor>,
+    #[cfg(feature = "hash_chain")]
+    previous_hash: Option<[u8; HASH_LINK_SIZE]>,
+    /// The entry of the first fragment, the blob assembled so far and its fragment count.
+    pending_blob: Option<(LogEntry<M>, Blob, u32)>,
+    /// An entry read while a blob was incomplete, returned after reporting the blob.
+    queued: Option<LogEntry<M>>,
     _phantom: std::marker::PhantomData<M>,
 }
 
+impl<M: Message> EntryParser for MixedParser<M> {
+    fn reader(&mut self) -> &mut PeekReader<EntrySource> {
+        &mut self.reader
+    }
+
+    fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    fn has_pending(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    fn read_raw_entry(&mut self) -> Result<RawEntry, MessageReadError> {
+        self.read_raw()
+    }
+
+    fn set_max_entry_size(&mut self, max_entry_size: usize) {
+        self.max_entry_size = max_entry_size;
+    }
+}
+
+/// Fields of an entry preceding its payload, as needed to read the payload.
+struct EntryFields {
+    entry_type: EntryType,
+    entry_type_raw: u8,
+    /// The entry fields preceding the size, kept for hash chain and encryption checks.
+    #[cfg_attr(
+        not(any(feature = "hash_chain", feature = "encryption")),
+        allow(dead_code)
+    )]
+    prefix: Vec<u8>,
+    /// The packed size field, kept for the hash chain check.
+    #[cfg_attr(not(feature = "hash_chain"), allow(dead_code))]
+    size_field: Vec<u8>,
+    payload_size: usize,
+}
+
+/// Fragment of a blob as stored in a single entry.
+struct BlobFragment {
+    id: u32,
+    index: u32,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl BlobFragment {
+    /// Unpacks a blob fragment from an entry payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `MessageReadError::Io` error of kind `InvalidData` if the payload is too short
+    /// or the fragment index is not below the fragment count.
+    fn unpack(payload: &[u8]) -> Result<Self, MessageReadError> {
+        let field = |index: usize| {
+            payload
+                .get(index * 4..index * 4 + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        match (field(0), field(1), field(2)) {
+            (Some(id), Some(index), Some(count)) if index < count => Ok(BlobFragment {
+                id,
+                index,
+                count,
+                data: payload[BLOB_FRAGMENT_HEADER_SIZE..].to_vec(),
+            }),
+            _ => Err(MessageReadError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Entry payload is not a valid blob fragment",
+            ))),
+        }
+    }
+}
+
+/// Builds the error reported for a blob whose fragments are missing.
+fn incomplete_blob() -> MessageReadError {
+    MessageReadError::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "Blob is missing fragments",
+    ))
+}
+
 impl<M: Message> MavParser for MixedParser<M> {
     type M = M;
 
@@ -154,7 +583,17 @@ impl<M: Message> MavParser for MixedParser<M> {
     /// - `Raw`: Reads raw binary data.
     /// - `Mavlink`: Reads a MAVLink message.
     /// - `Utf8Text`: Reads UTF-8 encoded text.
+    /// - `Drops`: Reads the message ids and counts of discarded MAVLink frames.
+    /// - `Blob`: Reads the fragments of a blob until it is complete.
+    /// - `Rtcm`: Reads RTCM correction data and the link it was received on.
+    /// - `Snapshot`: Reads the MAVLink frames of a snapshot.
+    /// - `Actuator`: Reads the actuator id, state and application rate of an actuator event.
+    /// - `Weather`: Reads the measured values and note of a weather observation.
+    /// - `Metadata`: Reads the operator, job and product identifiers of mission metadata.
     /// If timestamps are enabled, reads the timestamp for the entry.
+    /// If sequence numbers are enabled, reads the sequence number for the entry.
+    /// If hash chaining is enabled, checks the entry links to the previous entry.
+    /// If large entries are enabled, the payload size is read as a 32-bit integer.
     ///
     /// # Returns
     ///
@@ -165,49 +604,441 @@ impl<M: Message> MavParser for MixedParser<M> {
     /// Returns a `MessageReadError` if there is an issue parsing the log entry data. This includes:
     /// - I/O errors while reading from the file.
     /// - Corrupted MAVLink packets or invalid UTF-8 text.
+    /// - Entries breaking the hash chain.
+    /// - Entry payloads that fail to decrypt or decompress.
+    /// - Blobs missing fragments. The entry following the incomplete blob is returned by the
+    ///   next call.
     ///
-    /// # Panics
+    fn parse_next_entry(&mut self) -> Result<LogEntry<M>, MessageReadError> {
+        if let Some(entry) = self.queued.take() {
+            return Ok(entry);
+        }
+        loop {
+            let (entry, fragment) = self.read_entry()?;
+            match fragment {
+                Some(fragment) => {
+                    if let Some(entry) = self.add_fragment(entry, fragment)? {
+                        return Ok(entry);
+                    }
+                }
+                None if self.pending_blob.take().is_some() => {
+                    self.queued = Some(entry);
+                    return Err(incomplete_blob());
+                }
+                None => return Ok(entry),
+            }
+        }
+    }
+}
+
+impl<M: Message> MixedParser<M> {
+    /// Creates a new `MixedParser` reading entries as described by the file header.
     ///
-    /// Panics if the entry payload size cannot be read because this is unrecoverable.
+    /// # Arguments
     ///
-    fn parse_next_entry(&mut self) -> Result<LogEntry<M>, MessageReadError> {
+    /// - `reader`: A `PeekReader` positioned at the first entry.
+    /// - `header`: The file header of the log file.
+    /// - `header_bytes`: The bytes the file header was read from.
+    /// - `mav_version`: The MAVLink version of the log file.
+    #[cfg_attr(not(feature = "hash_chain"), allow(unused_variables))]
+    fn new(
+        reader: PeekReader<EntrySource>,
+        header: &FileHeader,
+        header_bytes: &[u8],
+        mav_version: MavlinkVersion,
+    ) -> Self {
+        MixedParser {
+            timestamped: !header.format_flags.no_timestamp,
+            sequenced: header.format_flags.sequence,
+            chained: header.format_flags.hash_chain,
+            large_entries: header.format_flags.large_entries,
+            reader,
+            mav_version,
+            position: first_entry_offset(header),
+            max_entry_size: DEFAULT_MAX_ENTRY_SIZE,
+            last_timestamp: None,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+            #[cfg(feature = "compression")]
+            decompressor: None,
+            // the first entry of every file links to the file header
+            #[cfg(feature = "hash_chain")]
+            previous_hash: Some(chain::entry_hash(header_bytes)),
+            pending_blob: None,
+            queued: None,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Reads the next entry from the file, leaving blob fragments to be reassembled.
+    ///
+    /// # Returns
+    ///
+    /// The entry with the fields preceding the payload filled in, along with the blob fragment
+    /// it holds if it is a blob entry.
+    ///
+    /// # Errors
+    ///
+    /// See `parse_next_entry`.
+    fn read_entry(&mut self) -> Result<(LogEntry<M>, Option<BlobFragment>), MessageReadError> {
+        let (mut entry, fields) = self.read_fields()?;
+
+        #[cfg(feature = "encryption")]
+        let encrypted = self.cipher.is_some();
+        #[cfg(not(feature = "encryption"))]
+        let encrypted = false;
+        #[cfg(feature = "compression")]
+        let compressed = self.decompressor.is_some();
+        #[cfg(not(feature = "compression"))]
+        let compressed = false;
+        if matches!(fields.entry_type, EntryType::Mavlink)
+            && !self.chained
+            && !encrypted
+            && !compressed
+        {
+            let flags = frame::flags(self.reader.peek_exact(frame::FLAGS_PEEK_SIZE)?);
+            // Frames with incompatibility flags, such as signed frames, are decoded in full
+            // below since read_versioned_msg may discard them or leave their signature unread
+            if !flags.is_some_and(|(incompat, _)| incompat != 0) {
+                // WARNING: this will silently fail and try to get next mavlink message on data corruption
+                // this is a concern that some messages could be associated with the wrong timestamp
+                // or non mavlink entries could get skipped
+                // we need a version of this to fail immediately on any parsing issue
+                let (header, message) =
+                    read_versioned_msg::<M, EntrySource>(&mut self.reader, self.mav_version)?;
+                entry.mav_header = Some(header);
+                entry.mav_message = Some(message);
+                entry.protocol_version = Some(self.mav_version);
+                set_frame_flags(&mut entry, flags);
+                return Ok((entry, None));
+            }
+        }
+
+        let payload: Vec<u8> = self.read_payload(&fields)?;
+        if matches!(fields.entry_type, EntryType::Blob) {
+            return Ok((entry, Some(BlobFragment::unpack(&payload)?)));
+        }
+        Ok((
+            Self::decode_payload(entry, fields.entry_type, &payload)?,
+            None,
+        ))
+    }
+
+    /// Reads the next entry from the file without decoding its payload.
+    ///
+    /// # Returns
+    ///
+    /// The entry with its payload decrypted and decompressed. Blob fragments are returned one
+    /// by one.
+    ///
+    /// # Errors
+    ///
+    /// See `parse_next_entry`. Payloads are not checked against their entry type.
+    fn read_raw(&mut self) -> Result<RawEntry, MessageReadError> {
+        let (entry, fields) = self.read_fields()?;
+        let payload: Vec<u8> = self.read_payload(&fields)?;
+        Ok(RawEntry {
+            entry_type: fields.entry_type_raw,
+            timestamp: entry.timestamp,
+            sequence: entry.sequence,
+            offset: entry.offset,
+            payload,
+        })
+    }
+
+    /// Reads the fields of the next entry preceding its payload.
+    ///
+    /// # Returns
+    ///
+    /// The entry with its timestamp, sequence number and location filled in, along with the
+    /// fields needed to read its payload.
+    ///
+    /// # Errors
+    ///
+    /// See `check_entry_size`, and returns I/O errors while reading from the file.
+    fn read_fields(&mut self) -> Result<(LogEntry<M>, EntryFields), MessageReadError> {
+        self.check_entry_size()?;
         let mut entry: LogEntry<M> = LogEntry::default();
-        let entry_type: EntryType = self
-            .reader
-            .read_u8()
-            // If entry type is unknown default to raw
-            .map(|value| value.try_into().unwrap_or(EntryType::Raw))?;
+        let entry_type_raw: u8 = self.reader.read_u8()?;
+        // If entry type is unknown default to raw
+        let entry_type: EntryType = entry_type_raw.try_into().unwrap_or(EntryType::Raw);
+        // The entry fields preceding the size, kept for hash chain and encryption checks
+        let mut prefix: Vec<u8> = vec![entry_type_raw];
         if self.timestamped {
             let timestamp_raw: &[u8] = self.reader.read_exact(8)?;
+            prefix.extend_from_slice(timestamp_raw);
             entry.timestamp = match timestamp_raw.try_into() {
                 Ok(bytes) => Some(u64::from_le_bytes(bytes)),
                 Err(_) => None,
             };
+            self.last_timestamp = entry.timestamp;
         }
-        let payload_size: u16 = u16::from_le_bytes(
-            self.reader
-                .read_exact(2)?
-                .try_into()
-                .expect("Failed to read log entry payload size."),
-        );
-        match entry_type {
-            EntryType::Raw => {
-                let payload = self.reader.read_exact(payload_size as usize)?;
-                entry.raw = Some(payload.to_vec())
+        if self.sequenced {
+            let sequence_raw: &[u8] = self.reader.read_exact(4)?;
+            prefix.extend_from_slice(sequence_raw);
+            entry.sequence = sequence_raw.try_into().ok().map(u32::from_le_bytes);
+        }
+        if self.chained {
+            prefix.extend_from_slice(self.reader.read_exact(HASH_LINK_SIZE)?);
+        }
+        // The size field is kept for the hash chain check
+        let (payload_size, size_field): (usize, Vec<u8>) = if self.large_entries {
+            let size_raw: [u8; 4] = self.reader.read_exact(4)?.try_into().unwrap_or_default();
+            (u32::from_le_bytes(size_raw) as usize, size_raw.to_vec())
+        } else {
+            let size_raw: [u8; 2] = self.reader.read_exact(2)?.try_into().unwrap_or_default();
+            (u16::from_le_bytes(size_raw) as usize, size_raw.to_vec())
+        };
+        let entry_len = prefix.len() + size_field.len() + payload_size;
+        track_entry(&mut entry, &mut self.position, Some(entry_len));
+        let fields = EntryFields {
+            entry_type,
+            entry_type_raw,
+            prefix,
+            size_field,
+            payload_size,
+        };
+        Ok((entry, fields))
+    }
+
+    /// Reads the payload of an entry and checks, decrypts and decompresses it as needed.
+    ///
+    /// # Arguments
+    ///
+    /// - `fields`: The fields of the entry, as read by `read_fields`.
+    ///
+    /// # Errors
+    ///
+    /// See `parse_next_entry`.
+    fn read_payload(&mut self, fields: &EntryFields) -> Result<Vec<u8>, MessageReadError> {
+        let payload: Vec<u8> = read_bytes(&mut self.reader, fields.payload_size)?;
+        #[cfg(feature = "hash_chain")]
+        if self.chained {
+            self.check_chain(&fields.prefix, &fields.size_field, &payload)?;
+        }
+        #[cfg(feature = "encryption")]
+        let payload: Vec<u8> = match &self.cipher {
+            // The entry fields preceding the size are authenticated along with the payload
+            Some(cipher) => cipher.decrypt(&fields.prefix, &payload).ok_or_else(|| {
+                MessageReadError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Failed to decrypt or authenticate entry payload",
+                ))
+            })?,
+            None => payload,
+        };
+        #[cfg(feature = "compression")]
+        let payload: Vec<u8> = match &mut self.decompressor {
+            Some(decompressor) => decompressor.decompress(&payload).ok_or_else(|| {
+                MessageReadError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Failed to decompress entry payload",
+                ))
+            })?,
+            None => payload,
+        };
+        Ok(payload)
+    }
+
+    /// Checks the size field of the next entry against the maximum entry size.
+    ///
+    /// A larger size can only come from corruption. Rather than reading a payload that large,
+    /// the reader is moved forward a byte at a time until it reaches a plausible entry: one with
+    /// a known entry type, an acceptable size and, in timestamped logs, a timestamp shortly
+    /// after the last entry read. Offsets of the entries that follow are no longer reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `MessageReadError::Io` error of kind `InvalidData` once the reader is
+    /// positioned on a plausible entry, or the error that ended the search.
+    fn check_entry_size(&mut self) -> Result<(), MessageReadError> {
+        let mut size_offset = 1;
+        if self.timestamped {
+            size_offset += 8;
+        }
+        if self.sequenced {
+            size_offset += 4;
+        }
+        if self.chained {
+            size_offset += HASH_LINK_SIZE;
+        }
+        let size_len = if self.large_entries { 4 } else { 2 };
+        let mut resync = false;
+        loop {
+            let header: Vec<u8> = self.reader.peek_exact(size_offset + size_len)?.to_vec();
+            let mut size_raw = [0u8; 4];
+            size_raw[..size_len].copy_from_slice(&header[size_offset..]);
+            let size_ok = u32::from_le_bytes(size_raw) as usize <= self.max_entry_size;
+            if !resync && size_ok {
+                return Ok(());
             }
-            EntryType::Mavlink => {
-                // WARNING: this will silently fail and try to get next mavlink message on data corruption
-                // this is a concern that some messages could be associated with the wrong timestamp
-                // or non mavlink entries could get skipped
-                // we need a version of this to fail immediately on any parsing issue
-                let (header, message) =
-                    read_versioned_msg::<M, File>(&mut self.reader, self.mav_version)?;
-                entry.mav_header = Some(header);
-                entry.mav_message = Some(message);
-                return Ok(entry);
+            if resync && size_ok && self.plausible_entry(&header) {
+                self.position = None;
+                return Err(MessageReadError::Io(invalid(
+                    "Entry size exceeds the maximum entry size",
+                )));
             }
+            resync = true;
+            self.reader.read_u8()?;
+        }
+    }
+
+    /// Checks whether an entry header found while resynchronizing is likely a real entry.
+    ///
+    /// # Arguments
+    ///
+    /// - `header`: The entry header, from the entry type to the size field.
+    fn plausible_entry(&self, header: &[u8]) -> bool {
+        if EntryType::try_from(header[0]).is_err() {
+            return false;
+        }
+        if !self.timestamped {
+            return true;
+        }
+        let Some(last_timestamp) = self.last_timestamp else {
+            return true;
+        };
+        let timestamp = u64::from_le_bytes(header[1..9].try_into().unwrap());
+        timestamp >= last_timestamp && timestamp - last_timestamp <= MAX_RESYNC_STEP_US
+    }
+
+    /// Adds a blob fragment to the blob being reassembled.
+    ///
+    /// # Arguments
+    ///
+    /// - `entry`: The entry holding the fragment.
+    /// - `fragment`: The blob fragment.
+    ///
+    /// # Returns
+    ///
+    /// The entry holding the complete blob once its last fragment is added, `None` otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `MessageReadError::Io` error of kind `InvalidData` if the fragment does not
+    /// follow the fragments read so far, in which case the incomplete blob is discarded.
+    fn add_fragment(
+        &mut self,
+        entry: LogEntry<M>,
+        fragment: BlobFragment,
+    ) -> Result<Option<LogEntry<M>>, MessageReadError> {
+        let continues = match &self.pending_blob {
+            Some((_, blob, count)) => {
+                blob.id == fragment.id
+                    && blob.fragments == fragment.index
+                    && *count == fragment.count
+            }
+            None => false,
+        };
+        // A fragment that does not continue the pending blob discards it
+        let broken = !continues && self.pending_blob.take().is_some();
+        let fragment_len = entry.entry_len;
+        if !continues {
+            if fragment.index != 0 {
+                return Err(incomplete_blob());
+            }
+            let blob = Blob {
+                id: fragment.id,
+                fragments: 0,
+                data: Vec::new(),
+            };
+            self.pending_blob = Some((entry, blob, fragment.count));
+        }
+        let Some((first, blob, count)) = &mut self.pending_blob else {
+            return Ok(None);
+        };
+        if continues {
+            // The fragments are consecutive so the blob spans all of them
+            first.entry_len = first.entry_len.zip(fragment_len).map(|(a, b)| a + b);
+        }
+        blob.data.extend_from_slice(&fragment.data);
+        blob.fragments += 1;
+        let complete = blob.fragments == *count;
+        let entry = if complete {
+            self.pending_blob.take().map(|(mut entry, blob, _)| {
+                entry.blob = Some(blob);
+                entry
+            })
+        } else {
+            None
+        };
+        if broken {
+            self.queued = entry;
+            return Err(incomplete_blob());
+        }
+        Ok(entry)
+    }
+
+    /// Checks that an entry links to the previous entry of the hash chain.
+    ///
+    /// The chain starts at the file header, which the first entry of every log file links to,
+    /// rotated files included.
+    ///
+    /// # Arguments
+    ///
+    /// - `prefix`: The entry fields preceding the size, ending with the hash link.
+    /// - `size_field`: The packed size field of the entry.
+    /// - `payload`: The stored entry payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `MessageReadError::Io` error of kind `InvalidData` if the entry does not link to
+    /// the previous entry, meaning entries were removed, inserted or altered.
+    #[cfg(feature = "hash_chain")]
+    fn check_chain(
+        &mut self,
+        prefix: &[u8],
+        size_field: &[u8],
+        payload: &[u8],
+    ) -> Result<(), MessageReadError> {
+        let link = &prefix[prefix.len() - HASH_LINK_SIZE..];
+        let mut entry_bytes: Vec<u8> = prefix.to_vec();
+        entry_bytes.extend_from_slice(size_field);
+        entry_bytes.extend_from_slice(payload);
+        match self.previous_hash.replace(chain::entry_hash(&entry_bytes)) {
+            Some(expected) if expected != link => Err(MessageReadError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Entry hash chain is broken, entries were removed, inserted or altered",
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decodes a complete entry payload into the entry.
+    ///
+    /// # Arguments
+    ///
+    /// - `entry`: The entry with the fields preceding the payload already filled in.
+    /// - `entry_type`: The type of the entry.
+    /// - `payload`: The entry payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `MessageReadError` if the payload is not a valid MAVLink frame or UTF-8 text
+    /// as indicated by the entry type.
+    fn decode_payload(
+        mut entry: LogEntry<M>,
+        entry_type: EntryType,
+        payload: &[u8],
+    ) -> Result<LogEntry<M>, MessageReadError> {
+        match entry_type {
+            EntryType::Raw => entry.raw = Some(payload.to_vec()),
+            EntryType::Mavlink => match frame::decode::<M>(payload) {
+                Ok(decoded) => {
+                    entry.mav_header = Some(decoded.header);
+                    entry.mav_message = Some(decoded.msg);
+                    entry.protocol_version = Some(decoded.version);
+                    set_frame_flags(&mut entry, decoded.flags);
+                }
+                Err(FrameError::Parse(err)) => return Err(MessageReadError::Parse(err)),
+                Err(FrameError::Invalid) => {
+                    return Err(MessageReadError::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Entry payload is not a valid MAVLink frame",
+                    )));
+                }
+            },
             EntryType::Utf8Text => {
-                let payload = self.reader.read_exact(payload_size as usize)?;
                 entry.text = match String::from_utf8(payload.to_vec()) {
                     Ok(text) => Some(text),
                     Err(_) => {
@@ -218,17 +1049,317 @@ impl<M: Message> MavParser for MixedParser<M> {
                     }
                 };
             }
+            // Blob fragments are reassembled by the caller
+            EntryType::Blob => {}
+            EntryType::Rtcm => match payload.split_first() {
+                Some((&link, data)) => {
+                    entry.rtcm = Some(RtcmData {
+                        link,
+                        data: data.to_vec(),
+                    })
+                }
+                None => {
+                    return Err(MessageReadError::Io(invalid(
+                        "RTCM entry is missing its link id",
+                    )));
+                }
+            },
+            EntryType::Snapshot => entry.snapshot = Some(decode_snapshot(payload)?),
+            EntryType::Actuator => match payload {
+                [actuator, active, rate @ ..] if rate.len() == 4 => {
+                    entry.actuator = Some(ActuatorEvent {
+                        actuator: *actuator,
+                        active: *active != 0,
+                        rate: f32::from_le_bytes(rate.try_into().unwrap()),
+                    })
+                }
+                _ => {
+                    return Err(MessageReadError::Io(invalid(
+                        "Actuator entry payload is not 6 bytes",
+                    )));
+                }
+            },
+            EntryType::Weather => match WeatherObservation::from_payload(payload) {
+                Some(weather) => entry.weather = Some(weather),
+                None => {
+                    return Err(MessageReadError::Io(invalid(
+                        "Weather entry is truncated or its note is not UTF-8",
+                    )));
+                }
+            },
+            EntryType::Metadata => match MissionMetadata::from_payload(payload) {
+                Some(metadata) => entry.metadata = Some(metadata),
+                None => {
+                    return Err(MessageReadError::Io(invalid(
+                        "Metadata entry is truncated or holds invalid UTF-8",
+                    )));
+                }
+            },
+            EntryType::Drops => {
+                entry.drops = Some(
+                    payload
+                        .chunks_exact(8)
+                        .map(|record| {
+                            (
+                                u32::from_le_bytes(record[0..4].try_into().unwrap()),
+                                u32::from_le_bytes(record[4..8].try_into().unwrap()),
+                            )
+                        })
+                        .collect(),
+                );
+            }
         }
         Ok(entry)
     }
 }
 
+/// Reads the file header to extract metadata and format information.
+///
+/// # Arguments
+/// - `reader`: A `PeekReader` for the log file.
+/// - `lenient`: Whether invalid header fields are replaced instead of rejected, see
+///   `FileHeader::unpack`.
+///
+/// # Returns
+/// A `Result` containing the `FileHeader` with metadata about the log file, and the bytes it was
+/// read from. The first entry of a hash chain links to these bytes, which may differ from the
+/// packed header when lenient.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file header is corrupted or if the format is unsupported
+/// since that makes it impossible to guarantee correct parsing. Unless lenient, the error
+/// names the invalid field.
+///
+fn read_file_header(
+    reader: &mut PeekReader<EntrySource>,
+    lenient: bool,
+) -> std::io::Result<(FileHeader, Vec<u8>)> {
+    let header_bytes: [u8; 108] = read_bytes(reader, FileHeader::MIN_SIZE)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| truncated("Failed to read file header."))?;
+    let mut raw = header_bytes.to_vec();
+    if !compat::format_version(&header_bytes).is_some_and(compat::is_supported) {
+        return Err(unsupported("Unsupported file format version."));
+    }
+    let mut header = if lenient {
+        compat::unpack_header(&header_bytes)
+            .ok_or_else(|| unsupported("Unsupported file format version."))?
+    } else {
+        compat::try_unpack_header(&header_bytes)?
+    };
+    if header.message_definition.payload_type != MavlinkDefinitionPayloadType::None {
+        let definitions_raw = read_bytes(reader, header.message_definition.size as usize)
+            .map_err(|_| truncated("Failed to read message definitions."))?;
+        header.message_definition.unpack_payload(&definitions_raw);
+        raw.extend_from_slice(&definitions_raw);
+    } else {
+        header.message_definition.size = 0;
+    }
+
+    match header.message_definition.payload_type {
+        MavlinkDefinitionPayloadType::None => {}
+        MavlinkDefinitionPayloadType::Utf8SpaceDelimitedUrlsForXMLFiles => {
+            return Err(unsupported(
+                "Custom XML files for message definitions are not supported.",
+            ));
+        }
+        MavlinkDefinitionPayloadType::Utf8Xml => {
+            return Err(unsupported("XML for message definitions is not supported."));
+        }
+    }
+
+    if header.format_flags.sequence && header.format_flags.mavlink_only {
+        return Err(invalid(
+            "Sequence numbers are not supported in MAVLink only files.",
+        ));
+    }
+
+    if header.format_flags.hash_chain && header.format_flags.mavlink_only {
+        return Err(invalid(
+            "Hash chains are not supported in MAVLink only files.",
+        ));
+    }
+
+    if header.format_flags.encrypted {
+        if header.format_flags.mavlink_only {
+            return Err(invalid(
+                "Encryption is not supported in MAVLink only files.",
+            ));
+        }
+        let encryption_bytes: [u8; EncryptionHeader::SIZE] =
+            read_bytes(reader, EncryptionHeader::SIZE)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| truncated("Failed to read encryption header."))?;
+        raw.extend_from_slice(&encryption_bytes);
+        header.encryption = Some(if lenient {
+            EncryptionHeader::unpack(&encryption_bytes)
+        } else {
+            EncryptionHeader::try_unpack(&encryption_bytes)?
+        });
+    }
+
+    if header.format_flags.chunked && cfg!(not(feature = "compression")) {
+        return Err(unsupported(
+            "Chunked files require the compression feature.",
+        ));
+    }
+
+    if header.format_flags.dictionary {
+        if header.format_flags.mavlink_only {
+            return Err(invalid(
+                "Compression is not supported in MAVLink only files.",
+            ));
+        }
+        if header.format_flags.large_entries {
+            return Err(invalid(
+                "Compression is not supported in large entry files.",
+            ));
+        }
+        if cfg!(not(feature = "compression")) {
+            return Err(unsupported(
+                "Compressed files require the compression feature.",
+            ));
+        }
+        let dictionary_id_bytes: [u8; 4] = read_bytes(reader, 4)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| truncated("Failed to read dictionary id."))?;
+        raw.extend_from_slice(&dictionary_id_bytes);
+        header.dictionary_id = Some(u32::from_le_bytes(dictionary_id_bytes));
+    }
+
+    Ok((header, raw))
+}
+
+/// Reads the file header of a log file.
+///
+/// # Arguments
+/// - `file_path`: Path to the log file.
+///
+/// # Returns
+/// A `Result` containing the `FileHeader`.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be opened, or if the file header is corrupted or of
+/// an unsupported format.
+pub fn read_header(file_path: &str) -> std::io::Result<FileHeader> {
+    let file: File = File::open(file_path)?;
+    read_file_header(&mut PeekReader::new(EntrySource::Plain(file)), false)
+        .map(|(header, _)| header)
+}
+
+/// How `MavLogParser` continues past the file header of a log appended to the log being read.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum SegmentMode {
+    /// Each appended log is reported once as a `SegmentBoundary` error, then its entries are
+    /// returned with the timestamps they were recorded with.
+    #[default]
+    Report,
+    /// Appended logs are read transparently. The timestamps of an appended log starting before
+    /// the last entry read are shifted to continue from that entry, so that timestamps never go
+    /// backwards across segments.
+    Rebase,
+}
+
+/// The start of a log appended to the log being read.
+///
+/// Reported by `MavLogParser` as a `MessageReadError::Io` error of kind `InvalidData` wrapping
+/// this struct, so that `for_each_entry` continues with the entries of the appended log. Use
+/// `std::io::Error::get_ref` and `downcast_ref` to tell it apart from corrupted entries.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SegmentBoundary {
+    /// Offset of the appended file header in the file, `None` if unknown.
+    pub offset: Option<u64>,
+    /// The file header of the appended log.
+    pub header: FileHeader,
+}
+
+impl std::fmt::Display for SegmentBoundary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "Log {} appended at offset {offset}", self.header.uuid),
+            None => write!(f, "Log {} appended", self.header.uuid),
+        }
+    }
+}
+
+impl std::error::Error for SegmentBoundary {}
+
+impl From<SegmentBoundary> for std::io::Error {
+    fn from(boundary: SegmentBoundary) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, boundary)
+    }
+}
+
+/// An entry of a .mav log with its payload left undecoded, see `MavLogParser::read_raw_entry`.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct RawEntry {
+    /// The entry type as stored in the file, 1 for the MAVLink entries of MAVLink only logs.
+    /// The values are listed in docs/mav_log_file_format.md.
+    pub entry_type: u8,
+    /// The entry timestamp in microseconds, `None` if the log has no timestamps.
+    pub timestamp: Option<u64>,
+    /// The entry sequence number, `None` if the log has no sequence numbers.
+    pub sequence: Option<u32>,
+    /// The file offset of the entry, `None` if unknown.
+    pub offset: Option<u64>,
+    /// The decrypted and decompressed payload. For MAVLink entries, the complete frame.
+    pub payload: Vec<u8>,
+}
+
+impl RawEntry {
+    /// Returns the message id of a MAVLink entry, read from the frame header.
+    ///
+    /// # Returns
+    /// The message id, or `None` if the entry does not hold a MAVLink frame.
+    pub fn message_id(&self) -> Option<u32> {
+        if self.entry_type != EntryType::Mavlink as u8 {
+            return None;
+        }
+        frame::message_id(&self.payload)
+    }
+}
+
 /// High-level parser for MAVLink log files.
 ///
 /// `MavLogParser` automatically determines the log file format and selects the appropriate parser.
 /// It supports MAVLink-only files (with or without timestamps) and mixed log files.
+///
+/// If the log records entry sequence numbers, their continuity is checked while parsing. Gaps
+/// and reordered entries are counted and, in strict mode, reported as errors.
+///
+/// The dialect recorded in the file header is checked against the dialect `M` is compiled from.
+/// A mismatch is available from `dialect_mismatch` and, in strict dialect mode, refuses parsing.
+///
+/// Logs appended to the log being read, as produced by `cat a.mav b.mav > c.mav`, are parsed
+/// with the format of their own file header, see `set_segment_mode`. Appended logs that are
+/// encrypted or chunked are not supported.
+///
+/// The footer of a log closed with `RotatingMavLogger::finish` is read when the parser is
+/// created, see `duration`, and is not returned as an entry.
 pub struct MavLogParser<M: Message + 'static> {
-    parser: Box<dyn MavParser<M = M>>,
+    parser: Box<dyn EntryParser<M = M>>,
+    footer: Option<LogFooter>,
+    /// Directory the compression dictionaries of appended logs are loaded from.
+    dictionary_dir: PathBuf,
+    /// Whether entries are read directly from the file, where footers and appended logs may
+    /// follow them.
+    appendable: bool,
+    segment_mode: SegmentMode,
+    /// Offset of the current segment in the file, `None` if unknown.
+    segment_start: Option<u64>,
+    /// Amount added to the timestamps of the current segment, see `SegmentMode::Rebase`.
+    timestamp_shift: Option<u64>,
+    last_timestamp: Option<u64>,
+    dialect_mismatch: Option<DialectMismatch>,
+    strict_dialect: bool,
+    strict_sequence: bool,
+    next_sequence: Option<u32>,
+    sequence_errors: u64,
 }
 
 impl<M: Message + 'static> MavLogParser<M> {
@@ -246,87 +1377,563 @@ impl<M: Message + 'static> MavLogParser<M> {
     ///
     /// # Panics
     ///
-    /// Panics if the file header cannot be read or if the format is unsupported.
+    /// Panics if the file header cannot be read, if the format is unsupported or if the file is
+    /// encrypted. Also panics if the file is compressed and its dictionary is not stored in the
+    /// directory of the log file. Use `try_new` to handle these cases as errors.
     ///
     pub fn new(file_path: &str) -> Self {
-        let file: File = File::open(file_path).expect("Failed to open file");
-        let mut reader: PeekReader<File> = PeekReader::new(file);
+        Self::try_new(file_path).unwrap_or_else(|error| panic!("{error}"))
+    }
 
-        let header = Self::read_file_header(&mut reader);
+    /// Creates a new `MavLogParser` for the specified log file without panicking on invalid
+    /// files.
+    ///
+    /// # Arguments
+    ///
+    /// - `file_path`: Path to the log file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `MavLogParser` or an `io::Error` if the file cannot be read.
+    /// An error of kind `InvalidData` is returned if the file header is invalid, of kind
+    /// `Unsupported` if the format is not supported and of kind `InvalidInput` if the file is
+    /// encrypted.
+    pub fn try_new(file_path: &str) -> std::io::Result<Self> {
+        let (reader, header, header_bytes) = Self::open(file_path, false)?;
+        Self::reject_encrypted(&header)?;
+        let dictionary_dir = Self::log_directory(file_path);
+        let parser = Self::select_parser(reader, &header, &header_bytes, dictionary_dir)?;
+        Self::with_parser(parser, &header, file_path, dictionary_dir)
+    }
 
-        let mav_version = Self::determine_mavlink_version(&header);
+    /// Creates a new `MavLogParser` that reads past invalid file header fields.
+    ///
+    /// Behaves like `new`, except that a source application id or dialect that is not valid
+    /// UTF-8 is read as empty, unknown format flags are ignored and an unknown message
+    /// definition payload type is treated as `None`. Use this to recover entries from files
+    /// with a damaged header.
+    ///
+    /// # Arguments
+    ///
+    /// - `file_path`: Path to the log file.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `MavLogParser` initialized with the appropriate parser.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file header cannot be read, if the format is unsupported or if the file is
+    /// encrypted. Use `try_new_lenient` to handle these cases as errors.
+    ///
+    pub fn new_lenient(file_path: &str) -> Self {
+        Self::try_new_lenient(file_path).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Creates a new `MavLogParser` that reads past invalid file header fields without
+    /// panicking on unreadable files.
+    ///
+    /// # Arguments
+    ///
+    /// - `file_path`: Path to the log file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `MavLogParser` or an `io::Error`, see `try_new`.
+    pub fn try_new_lenient(file_path: &str) -> std::io::Result<Self> {
+        let (reader, header, header_bytes) = Self::open(file_path, true)?;
+        Self::reject_encrypted(&header)?;
+        let dictionary_dir = Self::log_directory(file_path);
+        let parser = Self::select_parser(reader, &header, &header_bytes, dictionary_dir)?;
+        Self::with_parser(parser, &header, file_path, dictionary_dir)
+    }
+
+    /// Creates a new `MavLogParser` for a log file that may be compressed with a dictionary kept
+    /// in a dictionary directory.
+    ///
+    /// Automatically detects the log file format and initializes the appropriate parser. If the
+    /// file is compressed, the dictionary recorded in its header is loaded from the directory.
+    ///
+    /// # Arguments
+    ///
+    /// - `file_path`: Path to the log file.
+    /// - `dictionary_dir`: The directory holding the dictionaries, see
+    ///   `dictionary::save_dictionary`.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `MavLogParser` initialized with the appropriate parser.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file header cannot be read, if the format is unsupported, if the file is
+    /// encrypted or if the dictionary is not available.
+    ///
+    #[cfg(feature = "compression")]
+    pub fn new_with_dictionaries(file_path: &str, dictionary_dir: &str) -> Self {
+        Self::try_new_with_dictionaries(file_path, dictionary_dir)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Creates a new `MavLogParser` for a log file that may be compressed with a dictionary kept
+    /// in a dictionary directory, without panicking on invalid files.
+    ///
+    /// # Arguments
+    ///
+    /// - `file_path`: Path to the log file.
+    /// - `dictionary_dir`: The directory holding the dictionaries.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `MavLogParser` or an `io::Error`, see `try_new`. An error of
+    /// kind `NotFound` is returned if the dictionary is not available.
+    #[cfg(feature = "compression")]
+    pub fn try_new_with_dictionaries(
+        file_path: &str,
+        dictionary_dir: &str,
+    ) -> std::io::Result<Self> {
+        let (reader, header, header_bytes) = Self::open(file_path, false)?;
+        Self::reject_encrypted(&header)?;
+        let dictionary_dir = Path::new(dictionary_dir);
+        let parser = Self::select_parser(reader, &header, &header_bytes, dictionary_dir)?;
+        Self::with_parser(parser, &header, file_path, dictionary_dir)
+    }
+
+    /// Creates a new `MavLogParser` for a log file that may be encrypted.
+    ///
+    /// Automatically detects the log file format and initializes the appropriate parser. If the
+    /// file is encrypted, the key recorded in its header is looked up with the key provider. If
+    /// the file is also compressed, its dictionary is loaded from the directory of the log file.
+    ///
+    /// # Arguments
+    ///
+    /// - `file_path`: Path to the log file.
+    /// - `key_provider`: The provider to look up the encryption key with.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `MavLogParser` initialized with the appropriate parser.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file header cannot be read, if the format is unsupported or if the
+    /// encryption key or compression dictionary is not available.
+    ///
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted(file_path: &str, key_provider: &dyn KeyProvider) -> Self {
+        Self::try_new_encrypted(file_path, key_provider).unwrap_or_else(|error| panic!("{error}"))
+    }
 
-        let parser: Box<dyn MavParser<M = M>> = if header.format_flags.mavlink_only {
+    /// Creates a new `MavLogParser` for a log file that may be encrypted, without panicking on
+    /// invalid files.
+    ///
+    /// # Arguments
+    ///
+    /// - `file_path`: Path to the log file.
+    /// - `key_provider`: The provider to look up the encryption key with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `MavLogParser` or an `io::Error`, see `try_new`. An error of
+    /// kind `NotFound` is returned if the encryption key or compression dictionary is not
+    /// available.
+    #[cfg(feature = "encryption")]
+    pub fn try_new_encrypted(
+        file_path: &str,
+        key_provider: &dyn KeyProvider,
+    ) -> std::io::Result<Self> {
+        let (reader, header, header_bytes) = Self::open(file_path, false)?;
+        let dictionary_dir = Self::log_directory(file_path);
+        let encryption = match &header.encryption {
+            Some(encryption) => encryption,
+            None => {
+                let parser = Self::select_parser(reader, &header, &header_bytes, dictionary_dir)?;
+                return Self::with_parser(parser, &header, file_path, dictionary_dir);
+            }
+        };
+        let cipher = EntryCipher::from_header(encryption, key_provider).map_err(|error| {
+            std::io::Error::new(
+                error.kind(),
+                format!("Encryption key not available. {error}"),
+            )
+        })?;
+        let mut parser = Self::mixed_parser(reader, &header, &header_bytes, dictionary_dir)?;
+        parser.cipher = Some(cipher);
+        Self::with_parser(Box::new(parser), &header, file_path, dictionary_dir)
+    }
+
+    /// Opens a log file and reads its header, returned with the bytes it was read from.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file cannot be opened or the file header cannot be read.
+    ///
+    fn open(
+        file_path: &str,
+        lenient: bool,
+    ) -> std::io::Result<(PeekReader<EntrySource>, FileHeader, Vec<u8>)> {
+        let file: File = File::open(file_path)?;
+        let mut reader: PeekReader<EntrySource> = PeekReader::new(EntrySource::Plain(file));
+        let (header, header_bytes) = read_file_header(&mut reader, lenient)?;
+        #[cfg(feature = "compression")]
+        if header.format_flags.chunked {
+            // Blocks are read through a second handle positioned after the file header
+            let mut file: File = File::open(file_path)?;
+            file.seek(SeekFrom::Start(header.packed_size() as u64))?;
+            let blocks = EntrySource::Blocks(BlockReader::new(file));
+            return Ok((PeekReader::new(blocks), header, header_bytes));
+        }
+        Ok((reader, header, header_bytes))
+    }
+
+    /// Returns an error of kind `InvalidInput` if a log file must be opened with a key provider.
+    fn reject_encrypted(header: &FileHeader) -> std::io::Result<()> {
+        if header.format_flags.encrypted {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Encrypted files must be opened with a key provider.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Wraps the parser selected for a log file, checks the dialect recorded in its header and
+    /// reads its footer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the end of the file cannot be read.
+    fn with_parser(
+        parser: Box<dyn EntryParser<M = M>>,
+        header: &FileHeader,
+        file_path: &str,
+        dictionary_dir: &Path,
+    ) -> std::io::Result<Self> {
+        Ok(MavLogParser {
+            parser,
+            footer: LogFooter::read(file_path)?,
+            dictionary_dir: dictionary_dir.to_path_buf(),
+            appendable: !header.format_flags.chunked,
+            segment_mode: SegmentMode::default(),
+            segment_start: Some(0),
+            timestamp_shift: Some(0),
+            last_timestamp: None,
+            dialect_mismatch: check_dialect::<M>(&header.message_definition.dialect).err(),
+            strict_dialect: false,
+            strict_sequence: false,
+            next_sequence: None,
+            sequence_errors: 0,
+        })
+    }
+
+    /// Returns the directory of a log file, where its compression dictionary is looked up by
+    /// default.
+    fn log_directory(file_path: &str) -> &Path {
+        Path::new(file_path).parent().unwrap_or(Path::new(""))
+    }
+
+    /// Selects the parser for an unencrypted log file based on its format flags.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the MAVLink version is unsupported or if the compression
+    /// dictionary is not available.
+    ///
+    fn select_parser(
+        reader: PeekReader<EntrySource>,
+        header: &FileHeader,
+        header_bytes: &[u8],
+        dictionary_dir: &Path,
+    ) -> std::io::Result<Box<dyn EntryParser<M = M>>> {
+        let mav_version = Self::determine_mavlink_version(header)?;
+
+        if header.format_flags.mavlink_only {
             if header.format_flags.no_timestamp {
-                Box::new(MavlinkOnlyNoTimestampParser {
+                Ok(Box::new(MavlinkOnlyNoTimestampParser {
                     reader,
                     mav_version,
+                    position: first_entry_offset(header),
                     _phantom: std::marker::PhantomData,
-                })
+                }))
             } else {
-                Box::new(TimestampedMavlinkOnlyParser {
+                Ok(Box::new(TimestampedMavlinkOnlyParser {
                     reader,
                     mav_version,
+                    position: first_entry_offset(header),
                     _phantom: std::marker::PhantomData,
-                })
+                }))
             }
         } else {
-            Box::new(MixedParser {
-                timestamped: !header.format_flags.no_timestamp,
+            Ok(Box::new(Self::mixed_parser(
                 reader,
-                mav_version,
-                _phantom: std::marker::PhantomData,
-            })
-        };
+                header,
+                header_bytes,
+                dictionary_dir,
+            )?))
+        }
+    }
+
+    /// Creates the parser for a log file with entry types, loading its compression dictionary if
+    /// it has one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the MAVLink version is unsupported or if the compression
+    /// dictionary is not available.
+    ///
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    fn mixed_parser(
+        reader: PeekReader<EntrySource>,
+        header: &FileHeader,
+        header_bytes: &[u8],
+        dictionary_dir: &Path,
+    ) -> std::io::Result<MixedParser<M>> {
+        let mav_version = Self::determine_mavlink_version(header)?;
+        #[allow(unused_mut)]
+        let mut parser = MixedParser::new(reader, header, header_bytes, mav_version);
+        #[cfg(feature = "compression")]
+        if let Some(dictionary_id) = header.dictionary_id {
+            let dictionary =
+                dictionary::load_dictionary(dictionary_dir, dictionary_id).map_err(|error| {
+                    std::io::Error::new(
+                        error.kind(),
+                        format!("Compression dictionary not available. {error}"),
+                    )
+                })?;
+            parser.decompressor = Some(EntryCompressor::new(&dictionary).map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Invalid compression dictionary.",
+                )
+            })?);
+        }
+        Ok(parser)
+    }
+
+    /// Enables or disables strict sequence checking.
+    ///
+    /// In strict mode an entry whose sequence number does not follow the previous entry is
+    /// returned as a `MessageReadError::Io` error of kind `InvalidData` instead of the entry.
+    /// Parsing may continue after the error with the entries that follow.
+    ///
+    /// # Arguments
+    ///
+    /// - `strict`: Whether sequence discontinuities are reported as errors.
+    pub fn set_strict_sequence(&mut self, strict: bool) {
+        self.strict_sequence = strict;
+    }
 
-        MavLogParser { parser }
+    /// Enables or disables strict dialect checking.
+    ///
+    /// In strict mode every call to `parse_next_entry` returns the `dialect_mismatch` as a
+    /// `MessageReadError::Io` error of kind `InvalidInput` instead of decoding entries with the
+    /// wrong dialect. Strict mode has no effect if the dialects match.
+    ///
+    /// # Arguments
+    ///
+    /// - `strict`: Whether a dialect mismatch refuses parsing.
+    pub fn set_strict_dialect(&mut self, strict: bool) {
+        self.strict_dialect = strict;
     }
 
-    /// Reads the file header to extract metadata and format information.
+    /// Returns the mismatch between the dialect recorded in the file header and the dialect `M`
+    /// is compiled from, or `None` if `M` decodes the recorded dialect. See `check_dialect`.
+    pub fn dialect_mismatch(&self) -> Option<&DialectMismatch> {
+        self.dialect_mismatch.as_ref()
+    }
+
+    /// Sets the largest entry payload accepted, `DEFAULT_MAX_ENTRY_SIZE` by default.
+    ///
+    /// An entry size field above the maximum is treated as corruption: the parser searches for
+    /// the next plausible entry and reports a `MessageReadError::Io` error of kind
+    /// `InvalidData`. This keeps a corrupted size field from making the parser read and
+    /// allocate a huge payload.
     ///
     /// # Arguments
-    /// - `reader`: A `PeekReader` for the log file.
+    ///
+    /// - `max_entry_size`: The largest entry payload in bytes.
+    pub fn set_max_entry_size(&mut self, max_entry_size: usize) {
+        self.parser.set_max_entry_size(max_entry_size);
+    }
+
+    /// Returns the number of entries parsed so far whose sequence number did not follow the
+    /// previous entry, indicating missing or reordered entries.
+    pub fn sequence_errors(&self) -> u64 {
+        self.sequence_errors
+    }
+
+    /// Returns the footer of the log file, `None` if the log was not closed with
+    /// `RotatingMavLogger::finish`.
+    ///
+    /// For concatenated logs, this is the footer of the last log.
+    pub fn footer(&self) -> Option<&LogFooter> {
+        self.footer.as_ref()
+    }
+
+    /// Returns the time spanned by the entries of the log file, read from its footer without
+    /// parsing the entries.
     ///
     /// # Returns
-    /// A `FileHeader` containing metadata about the log file.
     ///
-    /// # Panics
+    /// The duration, `None` if the log file has no footer.
+    pub fn duration(&self) -> Option<Duration> {
+        self.footer.as_ref().map(LogFooter::duration)
+    }
+
+    /// Reads the next entry without decoding its payload.
     ///
-    /// Panics if the file header is corrupted or if the format is unsupported since that makes
-    /// it impossible to guarantee correct parsing.
+    /// Only the entry framing is read, so entries can be filtered or copied to another log
+    /// without the cost of decoding MAVLink messages, see `copy::copy_entries`. Appended logs,
+    /// footers and sequence numbers are handled as by `parse_next_entry`, while the dialect is
+    /// not checked. Blob fragments are returned one by one.
     ///
-    fn read_file_header(reader: &mut PeekReader<File>) -> FileHeader {
-        let header_bytes: [u8; 108] = reader
-            .read_exact(FileHeader::MIN_SIZE)
-            .expect("Failed to read file header.")
-            .try_into()
-            .expect("Failed to read file header.");
-        let mut header = FileHeader::unpack(&header_bytes);
-        if header.message_definition.payload_type != MavlinkDefinitionPayloadType::None {
-            let definitions_raw: &[u8] = reader
-                .read_exact(header.message_definition.size as usize)
-                .expect("Failed to read message definitions.");
-            header.message_definition.unpack_payload(definitions_raw);
-        } else {
-            header.message_definition.size = 0;
+    /// # Returns
+    ///
+    /// The entry, with its payload decrypted and decompressed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `parse_next_entry`, except those of decoding payloads.
+    pub fn read_raw_entry(&mut self) -> Result<RawEntry, MessageReadError> {
+        let boundary = self.next_segment()?;
+        if let Some(boundary) = boundary.filter(|_| self.segment_mode == SegmentMode::Report) {
+            return Err(MessageReadError::Io(boundary.into()));
         }
+        let mut entry = self.parser.read_raw_entry()?;
+        self.rebase(&mut entry.offset, &mut entry.timestamp);
+        self.check_sequence(entry.sequence, 1)?;
+        Ok(entry)
+    }
 
-        match header.message_definition.payload_type {
-            MavlinkDefinitionPayloadType::None => {}
-            MavlinkDefinitionPayloadType::Utf8SpaceDelimitedUrlsForXMLFiles => {
-                panic!("Custom XML files for message definitions are not supported.");
-            }
-            MavlinkDefinitionPayloadType::Utf8Xml => {
-                panic!("XML for message definitions is not supported.");
-            }
-        }
+    /// Sets how logs appended to the log being read are handled, `SegmentMode::Report` by
+    /// default.
+    ///
+    /// # Arguments
+    ///
+    /// - `mode`: Whether appended logs are reported or read transparently.
+    pub fn set_segment_mode(&mut self, mode: SegmentMode) {
+        self.segment_mode = mode;
+    }
 
-        match header.format_version {
-            1 => {}
-            _ => panic!("Unsupported file format version."),
+    /// Skips the footer ending the current log, then checks whether the next entry is the file
+    /// header of an appended log and, if so, reads it and switches to a parser for the format of
+    /// the appended log.
+    ///
+    /// # Returns
+    ///
+    /// The boundary of the appended log, `None` if the next entry belongs to the current log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` of kind `Unsupported` if the appended log is encrypted or
+    /// chunked, and leaves it unread. Returns other errors if its header or compression
+    /// dictionary cannot be read.
+    fn next_segment(&mut self) -> std::io::Result<Option<SegmentBoundary>> {
+        if !self.appendable || self.parser.has_pending() {
+            return Ok(None);
+        }
+        let mut end = self
+            .segment_start
+            .zip(self.parser.position())
+            .map(|(start, position)| start + position);
+        let reader = self.parser.reader();
+        if reader
+            .peek_exact(LogFooter::SIZE)
+            .is_ok_and(|bytes| LogFooter::unpack(bytes).is_some())
+        {
+            reader
+                .read_exact(LogFooter::SIZE)
+                .map_err(|_| truncated("Failed to read log footer."))?;
+            end = end.map(|end| end + LogFooter::SIZE as u64);
         }
+        // less than a header left in the file means there is no appended log
+        let fixed_header = match reader.peek_exact(FileHeader::MIN_SIZE) {
+            Ok(bytes) => <[u8; FileHeader::MIN_SIZE]>::try_from(bytes).unwrap(),
+            Err(_) => return Ok(None),
+        };
+        if !compat::format_version(&fixed_header).is_some_and(compat::is_supported) {
+            return Ok(None);
+        }
+        let flags = match compat::try_unpack_header(&fixed_header) {
+            Ok(header) => header.format_flags,
+            Err(_) => return Ok(None),
+        };
+        if flags.encrypted || flags.chunked {
+            return Err(unsupported(
+                "Appended encrypted or chunked logs are not supported.",
+            ));
+        }
+        let (header, header_bytes) = read_file_header(reader, false)?;
+        let reader = std::mem::replace(reader, PeekReader::new(EntrySource::Empty));
+        self.parser = Self::select_parser(reader, &header, &header_bytes, &self.dictionary_dir)?;
+        self.segment_start = end;
+        self.next_sequence = None;
+        if self.dialect_mismatch.is_none() {
+            self.dialect_mismatch = check_dialect::<M>(&header.message_definition.dialect).err();
+        }
+        if self.segment_mode == SegmentMode::Rebase {
+            self.timestamp_shift = None;
+        }
+        Ok(Some(SegmentBoundary {
+            offset: self.segment_start,
+            header,
+        }))
+    }
 
-        header
+    /// Places an entry of the current segment in the file and applies the timestamp shift of
+    /// the segment.
+    ///
+    /// # Arguments
+    ///
+    /// - `offset`: The offset of the entry within the segment, made an offset in the file.
+    /// - `timestamp`: The timestamp of the entry.
+    fn rebase(&mut self, offset: &mut Option<u64>, timestamp: &mut Option<u64>) {
+        *offset = self
+            .segment_start
+            .zip(*offset)
+            .map(|(start, offset)| start + offset);
+        let Some(entry_timestamp) = *timestamp else {
+            return;
+        };
+        let shift = *self.timestamp_shift.get_or_insert_with(|| {
+            self.last_timestamp
+                .map_or(0, |last| last.saturating_sub(entry_timestamp))
+        });
+        *timestamp = Some(entry_timestamp + shift);
+        self.last_timestamp = *timestamp;
+    }
+
+    /// Checks that an entry sequence number follows the previous entry.
+    ///
+    /// # Arguments
+    ///
+    /// - `sequence`: The sequence number of the entry, if any.
+    /// - `entries`: The number of entries it spans, the fragments of a blob each having their
+    ///   own sequence number.
+    ///
+    /// # Errors
+    ///
+    /// In strict sequence mode, returns a `MessageReadError::Io` error of kind `InvalidData` if
+    /// the sequence number does not follow the previous entry.
+    fn check_sequence(
+        &mut self,
+        sequence: Option<u32>,
+        entries: u32,
+    ) -> Result<(), MessageReadError> {
+        let Some(sequence) = sequence else {
+            return Ok(());
+        };
+        let expected = self.next_sequence.replace(sequence.wrapping_add(entries));
+        if let Some(expected) = expected.filter(|&expected| expected != sequence) {
+            self.sequence_errors += 1;
+            if self.strict_sequence {
+                return Err(MessageReadError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Entry sequence discontinuity: expected {expected}, found {sequence}"),
+                )));
+            }
+        }
+        Ok(())
     }
 
     /// Determines the MAVLink version based on the file header.
@@ -337,15 +1944,15 @@ impl<M: Message + 'static> MavLogParser<M> {
     /// # Returns
     /// The MAVLink version (`V1` or `V2`).
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the MAVLink version is unsupported.
+    /// Returns an `io::Error` of kind `Unsupported` if the MAVLink version is unsupported.
     ///
-    fn determine_mavlink_version(header: &FileHeader) -> MavlinkVersion {
+    fn determine_mavlink_version(header: &FileHeader) -> std::io::Result<MavlinkVersion> {
         match header.message_definition.version_major {
-            2 => MavlinkVersion::V2,
-            1 => MavlinkVersion::V1,
-            _ => panic!("Unsupported MAVLink version."),
+            2 => Ok(MavlinkVersion::V2),
+            1 => Ok(MavlinkVersion::V1),
+            _ => Err(unsupported("Unsupported MAVLink version.")),
         }
     }
 }
@@ -361,7 +1968,33 @@ impl<M: Message + 'static> MavParser for MavLogParser<M> {
     ///
     /// a `LogEntry` containing the parsed data, which may include a timestamp, MAVLink message, or text.
     ///
+    /// # Errors
+    ///
+    /// In strict sequence mode, returns a `MessageReadError::Io` error of kind `InvalidData` if
+    /// the entry sequence number does not follow the previous entry. In strict dialect mode,
+    /// returns a `MessageReadError::Io` error of kind `InvalidInput` if the dialects mismatch.
+    /// In `SegmentMode::Report`, returns a `SegmentBoundary` as a `MessageReadError::Io` error
+    /// of kind `InvalidData` before the first entry of an appended log.
+    ///
     fn parse_next_entry(&mut self) -> Result<LogEntry<M>, MessageReadError> {
-        self.parser.parse_next_entry()
+        if let Some(mismatch) = self
+            .dialect_mismatch
+            .as_ref()
+            .filter(|_| self.strict_dialect)
+        {
+            return Err(MessageReadError::Io(mismatch.clone().into()));
+        }
+        let boundary = self.next_segment()?;
+        if let Some(boundary) = boundary.filter(|_| self.segment_mode == SegmentMode::Report) {
+            return Err(MessageReadError::Io(boundary.into()));
+        }
+        let mut entry = self.parser.parse_next_entry()?;
+        self.rebase(&mut entry.offset, &mut entry.timestamp);
+        if entry.offset.is_none() {
+            entry.entry_len = None;
+        }
+        let entries = entry.blob.as_ref().map_or(1, |blob| blob.fragments);
+        self.check_sequence(entry.sequence, entries)?;
+        Ok(entry)
     }
 }
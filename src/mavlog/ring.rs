@@ -0,0 +1,365 @@
+//! This module defines a logger keeping recent entries in memory until an event is worth
+//! recording.
+//!
+//! Monitoring deployments log continuously but rarely need the data, and constant writes wear
+//! out eMMC storage. A `RingBufferLogger` keeps the most recent entries in a bounded ring buffer
+//! and only creates its log file when triggered, writing the buffered history first with its
+//! original timestamps and every later entry directly until released.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavFrame, MavlinkVersion, Message};
+
+use super::header::FormatFlags;
+use super::logger::{EntryType, RotatingMavLogger};
+use super::session::{heartbeat_armed, heartbeat_mode};
+use crate::clock::Clock;
+use crate::mav_logger::MavLogger;
+
+/// MAV_STATE values of the HEARTBEAT system_status field reporting a failsafe: CRITICAL,
+/// EMERGENCY and FLIGHT_TERMINATION.
+const FAILSAFE_STATES: [u8; 3] = [5, 6, 8];
+
+/// Events triggering a `RingBufferLogger` automatically, detected from HEARTBEAT messages.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct Triggers {
+    /// Trigger when a vehicle arms.
+    pub arm: bool,
+    /// Trigger when a vehicle reports a critical, emergency or flight termination state.
+    pub failsafe: bool,
+}
+
+impl Default for Triggers {
+    /// Triggers on arming and on failsafes.
+    fn default() -> Self {
+        Self {
+            arm: true,
+            failsafe: true,
+        }
+    }
+}
+
+/// An entry held in the ring buffer.
+struct Buffered {
+    entry_type: EntryType,
+    timestamp_us: u64,
+    data: Vec<u8>,
+}
+
+/// Logger buffering recent entries in memory and writing them to a rotating .mav log only once
+/// triggered.
+///
+/// Entry timestamps are read from a monotonic clock started when the logger is created, unless
+/// another clock is set with `set_clock`.
+pub struct RingBufferLogger {
+    base_path: String,
+    max_bytes: u64,
+    backup_count: usize,
+    format_flags: FormatFlags,
+    clock: Clock,
+    history_us: u64,
+    max_buffer_bytes: usize,
+    triggers: Triggers,
+    buffer: VecDeque<Buffered>,
+    buffered_bytes: usize,
+    logger: Option<RotatingMavLogger>,
+    persisting: bool,
+}
+
+impl RingBufferLogger {
+    /// Creates a new `RingBufferLogger`. No file is created until the logger is triggered.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_path` - The base path for the log files. A file extension of .mav is recommended.
+    /// * `max_bytes` - The maximum size of a log file before it is rotated.
+    /// * `backup_count` - The number of backup files to keep.
+    /// * `format_flags` - Optional format flags for the log file.
+    /// * `history` - How long entries are kept in the ring buffer.
+    /// * `max_buffer_bytes` - The maximum amount of entry data kept in the ring buffer. The
+    ///     oldest entries are discarded first when either limit is reached.
+    pub fn new(
+        base_path: &str,
+        max_bytes: u64,
+        backup_count: usize,
+        format_flags: Option<FormatFlags>,
+        history: Duration,
+        max_buffer_bytes: usize,
+    ) -> Self {
+        Self {
+            base_path: String::from(base_path),
+            max_bytes,
+            backup_count,
+            format_flags: format_flags.unwrap_or_default(),
+            clock: Clock::default(),
+            history_us: history.as_micros() as u64,
+            max_buffer_bytes,
+            triggers: Triggers::default(),
+            buffer: VecDeque::new(),
+            buffered_bytes: 0,
+            logger: None,
+            persisting: false,
+        }
+    }
+
+    /// Sets the clock entry timestamps are read from.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock to read entry timestamps from.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Sets the events triggering the logger automatically.
+    ///
+    /// # Arguments
+    ///
+    /// * `triggers` - The events to trigger on.
+    pub fn set_triggers(&mut self, triggers: Triggers) {
+        self.triggers = triggers;
+    }
+
+    /// Returns whether entries are currently written to the log.
+    pub fn is_persisting(&self) -> bool {
+        self.persisting
+    }
+
+    /// Returns the number of entries held in the ring buffer.
+    pub fn buffered_entries(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Starts writing entries to the log, writing the buffered history first.
+    ///
+    /// The log file is created on the first trigger. Does nothing if the logger is already
+    /// persisting.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error is returned if the log could not be
+    /// created or written. Buffered entries not yet written are kept.
+    pub fn trigger(&mut self) -> std::io::Result<()> {
+        if self.persisting {
+            return Ok(());
+        }
+        if self.logger.is_none() {
+            self.logger = Some(RotatingMavLogger::new(
+                &self.base_path,
+                self.max_bytes,
+                self.backup_count,
+                Some(self.format_flags),
+                None,
+            )?);
+        }
+        let logger = self.logger.as_mut().expect("created above");
+        while let Some(entry) = self.buffer.front() {
+            logger.write_at(entry.entry_type, Some(entry.timestamp_us), &entry.data)?;
+            self.buffered_bytes -= entry.data.len();
+            self.buffer.pop_front();
+        }
+        self.persisting = true;
+        Ok(())
+    }
+
+    /// Stops writing entries to the log and goes back to buffering them.
+    pub fn release(&mut self) {
+        self.persisting = false;
+    }
+
+    /// Writes a text message to the log or the ring buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text message to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn write_text(&mut self, text: &str) -> std::io::Result<()> {
+        self.write(EntryType::Text, text.as_bytes())
+    }
+
+    /// Writes raw data to the log or the ring buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw data to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn write_raw(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.write(EntryType::Raw, data)
+    }
+
+    /// Timestamps an entry and writes it to the log when persisting, or to the ring buffer.
+    fn write(&mut self, entry_type: EntryType, data: &[u8]) -> std::io::Result<()> {
+        if entry_type != EntryType::Mavlink && self.format_flags.mavlink_only {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "This logger accepts only mavlink messages",
+            ));
+        }
+        let timestamp_us = self.clock.now_us()?;
+        if self.persisting {
+            let logger = self.logger.as_mut().expect("created by trigger");
+            return logger.write_at(entry_type, Some(timestamp_us), data);
+        }
+        self.buffered_bytes += data.len();
+        self.buffer.push_back(Buffered {
+            entry_type,
+            timestamp_us,
+            data: data.to_vec(),
+        });
+        let start_us = timestamp_us.saturating_sub(self.history_us);
+        while let Some(oldest) = self.buffer.front() {
+            if oldest.timestamp_us >= start_us && self.buffered_bytes <= self.max_buffer_bytes {
+                break;
+            }
+            self.buffered_bytes -= oldest.data.len();
+            self.buffer.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Returns whether a message is an event the logger is set to trigger on.
+    fn triggers_on<M: Message>(&self, msg: &M) -> bool {
+        let arm = self.triggers.arm && heartbeat_armed(msg) == Some(true);
+        let failsafe = self.triggers.failsafe
+            && heartbeat_mode(msg).is_some_and(|(_, status)| FAILSAFE_STATES.contains(&status));
+        arm || failsafe
+    }
+}
+
+impl MavLogger for RingBufferLogger {
+    /// Writes a MAVLink message to the log or the ring buffer.
+    ///
+    /// A message matching the triggers is buffered and then triggers the logger, so it is the
+    /// last entry of the written history.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The MavFrame to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn write_mavlink<M: Message>(&mut self, frame: MavFrame<M>) -> std::io::Result<()> {
+        let triggered = !self.persisting && self.triggers_on(&frame.msg);
+        match frame.protocol_version {
+            MavlinkVersion::V1 => {
+                let mut msg: MAVLinkV1MessageRaw = MAVLinkV1MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                self.write(EntryType::Mavlink, msg.raw_bytes())?;
+            }
+            MavlinkVersion::V2 => {
+                let mut msg: MAVLinkV2MessageRaw = MAVLinkV2MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                self.write(EntryType::Mavlink, msg.raw_bytes())?;
+            }
+        }
+        if triggered {
+            self.trigger()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use mavlink::MavHeader;
+    use mavlink::common::{HEARTBEAT_DATA, MavMessage, MavModeFlag};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::clock::{BackwardsPolicy, ClockSource};
+    use crate::mav_parser::for_each_entry;
+    use crate::mavlog::parser::MavLogParser;
+
+    fn frame(msg: MavMessage) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader::default(),
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    fn timestamps(path: &str) -> Vec<u64> {
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let mut timestamps = Vec::new();
+        for_each_entry(&mut parser, |entry| {
+            timestamps.extend(entry.timestamp);
+            Ok(())
+        })
+        .unwrap();
+        timestamps
+    }
+
+    /// Test that only the recent history is written once the vehicle arms, and that later
+    /// entries are written directly until the logger is released.
+    #[test]
+    fn test_ring_buffer_trigger() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("ring.mav");
+        let path = path.to_str().unwrap();
+        let mut logger =
+            RingBufferLogger::new(path, 100000, 0, None, Duration::from_secs(3), 100000);
+        let time = Arc::new(AtomicU64::new(0));
+        let source_time = time.clone();
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || source_time.load(Ordering::Relaxed))),
+            BackwardsPolicy::Hold,
+        ));
+
+        for s in 0..10 {
+            time.store(s * 1_000_000, Ordering::Relaxed);
+            logger
+                .write_mavlink(frame(MavMessage::ATTITUDE(Default::default())))
+                .unwrap();
+        }
+        assert_eq!(logger.buffered_entries(), 4);
+        assert!(!std::path::Path::new(path).exists());
+
+        let armed = MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            base_mode: MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED,
+            ..Default::default()
+        });
+        time.store(10_000_000, Ordering::Relaxed);
+        logger.write_mavlink(frame(armed)).unwrap();
+        assert!(logger.is_persisting());
+        assert_eq!(logger.buffered_entries(), 0);
+        time.store(11_000_000, Ordering::Relaxed);
+        logger.write_text("in flight").unwrap();
+        logger.release();
+        time.store(12_000_000, Ordering::Relaxed);
+        logger.write_text("buffered").unwrap();
+
+        assert_eq!(
+            timestamps(path),
+            vec![7_000_000, 8_000_000, 9_000_000, 10_000_000, 11_000_000]
+        );
+    }
+
+    /// Test that the ring buffer never holds more than its byte budget.
+    #[test]
+    fn test_ring_buffer_size_limit() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("ring.mav");
+        let mut logger = RingBufferLogger::new(
+            path.to_str().unwrap(),
+            100000,
+            0,
+            None,
+            Duration::from_secs(60),
+            100,
+        );
+        for _ in 0..20 {
+            logger.write_raw(&[0; 30]).unwrap();
+        }
+        assert_eq!(logger.buffered_entries(), 3);
+    }
+}
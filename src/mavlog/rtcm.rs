@@ -0,0 +1,181 @@
+//! This module reconstructs the RTCM correction streams recorded in a log.
+//!
+//! Corrections are recorded either as RTCM entries written by `RotatingMavLogger::write_rtcm`
+//! or as the GPS_RTCM_DATA messages injecting them into a vehicle. GPS_RTCM_DATA messages carry
+//! at most 180 bytes so longer RTCM messages are split across up to 4 fragments that are
+//! reassembled here.
+use std::collections::BTreeMap;
+
+use mavlink::Message;
+
+use crate::fields;
+use crate::mav_parser::{MavParser, for_each_entry};
+
+/// Largest amount of RTCM data carried by a single GPS_RTCM_DATA message.
+const MAX_FRAGMENT_SIZE: usize = 180;
+/// Number of fragments a GPS_RTCM_DATA sequence can be split into.
+const MAX_FRAGMENTS: u8 = 4;
+
+/// Origin of an RTCM correction stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RtcmSource {
+    /// RTCM entries recorded with the given link id.
+    Link(u8),
+    /// GPS_RTCM_DATA messages sent by the given MAVLink system and component.
+    Mavlink { system_id: u8, component_id: u8 },
+}
+
+/// Reassembles the fragments of GPS_RTCM_DATA messages from a single sender.
+#[derive(Default)]
+struct FragmentAssembler {
+    sequence: u8,
+    next_fragment: u8,
+    data: Vec<u8>,
+}
+
+impl FragmentAssembler {
+    /// Adds the content of a GPS_RTCM_DATA message to the stream.
+    ///
+    /// Fragment sequences missing a fragment are dropped so that the stream only holds whole
+    /// RTCM messages.
+    ///
+    /// # Arguments
+    /// - `flags`: The flags field of the message.
+    /// - `data`: The valid part of the data field of the message.
+    /// - `stream`: The stream completed messages are appended to.
+    fn push(&mut self, flags: u8, data: &[u8], stream: &mut Vec<u8>) {
+        let fragmented = flags & 0x01 != 0;
+        let fragment = (flags >> 1) & 0x03;
+        let sequence = flags >> 3;
+        let continues =
+            !self.data.is_empty() && sequence == self.sequence && fragment == self.next_fragment;
+        if !continues {
+            // A pending message whose fragments were all full is complete once a new message
+            // starts, otherwise it is missing a fragment
+            if !self.data.is_empty() && (sequence != self.sequence || !fragmented) {
+                stream.append(&mut self.data);
+            }
+            self.data.clear();
+        }
+        if !fragmented {
+            stream.extend_from_slice(data);
+            return;
+        }
+        if !continues && fragment != 0 {
+            return;
+        }
+        self.sequence = sequence;
+        self.next_fragment = fragment + 1;
+        self.data.extend_from_slice(data);
+        if data.len() < MAX_FRAGMENT_SIZE || self.next_fragment == MAX_FRAGMENTS {
+            stream.append(&mut self.data);
+        }
+    }
+
+    /// Appends a pending message whose fragments were all full to the stream.
+    fn finish(&mut self, stream: &mut Vec<u8>) {
+        stream.append(&mut self.data);
+    }
+}
+
+/// Reads a full log and reconstructs every RTCM correction stream it holds.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+///
+/// # Returns
+/// The RTCM bytes of each source in log order, ready to be replayed to a GNSS receiver.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read.
+pub fn extract_rtcm<P: MavParser + ?Sized>(
+    parser: &mut P,
+) -> std::io::Result<BTreeMap<RtcmSource, Vec<u8>>> {
+    let mut streams: BTreeMap<RtcmSource, Vec<u8>> = BTreeMap::new();
+    let mut assemblers: BTreeMap<RtcmSource, FragmentAssembler> = BTreeMap::new();
+    for_each_entry(parser, |entry| {
+        if let Some(rtcm) = entry.rtcm {
+            streams
+                .entry(RtcmSource::Link(rtcm.link))
+                .or_default()
+                .extend_from_slice(&rtcm.data);
+            return Ok(());
+        }
+        let (header, msg) = match (entry.mav_header, entry.mav_message) {
+            (Some(header), Some(msg)) if msg.message_id() == fields::GPS_RTCM_DATA_ID => {
+                (header, msg)
+            }
+            _ => return Ok(()),
+        };
+        let source = RtcmSource::Mavlink {
+            system_id: header.system_id,
+            component_id: header.component_id,
+        };
+        let payload = fields::payload(&msg);
+        let len = (fields::read_u8(&payload, 1) as usize).min(MAX_FRAGMENT_SIZE);
+        assemblers.entry(source).or_default().push(
+            fields::read_u8(&payload, 0),
+            &payload[2..2 + len],
+            streams.entry(source).or_default(),
+        );
+        Ok(())
+    })?;
+    for (source, mut assembler) in assemblers {
+        assembler.finish(streams.entry(source).or_default());
+    }
+    Ok(streams)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(fragmented: bool, fragment: u8, sequence: u8) -> u8 {
+        fragmented as u8 | (fragment << 1) | (sequence << 3)
+    }
+
+    /// Test that fragmented messages are reassembled and incomplete ones dropped.
+    #[test]
+    fn test_fragment_assembler() {
+        let mut assembler = FragmentAssembler::default();
+        let mut stream: Vec<u8> = Vec::new();
+        let full = [1u8; MAX_FRAGMENT_SIZE];
+
+        // unfragmented message
+        assembler.push(flags(false, 0, 0), &[9, 9], &mut stream);
+        assert_eq!(stream, vec![9, 9]);
+
+        // two fragments ending with a short fragment
+        stream.clear();
+        assembler.push(flags(true, 0, 1), &full, &mut stream);
+        assert!(stream.is_empty());
+        assembler.push(flags(true, 1, 1), &[2, 2], &mut stream);
+        assert_eq!(stream.len(), MAX_FRAGMENT_SIZE + 2);
+
+        // missing second fragment drops the message
+        stream.clear();
+        assembler.push(flags(true, 0, 2), &full, &mut stream);
+        assembler.push(flags(true, 2, 2), &[3], &mut stream);
+        assert!(stream.is_empty());
+
+        // missing first fragment drops the message
+        assembler.push(flags(true, 1, 3), &[3], &mut stream);
+        assert!(stream.is_empty());
+
+        // full fragments are complete once the next message starts
+        assembler.push(flags(true, 0, 4), &full, &mut stream);
+        assembler.push(flags(true, 1, 4), &full, &mut stream);
+        assert!(stream.is_empty());
+        assembler.push(flags(true, 0, 5), &full, &mut stream);
+        assert_eq!(stream.len(), 2 * MAX_FRAGMENT_SIZE);
+        assembler.finish(&mut stream);
+        assert_eq!(stream.len(), 3 * MAX_FRAGMENT_SIZE);
+
+        // four fragments are always complete
+        stream.clear();
+        for fragment in 0..MAX_FRAGMENTS {
+            assembler.push(flags(true, fragment, 6), &full, &mut stream);
+        }
+        assert_eq!(stream.len(), 4 * MAX_FRAGMENT_SIZE);
+    }
+}
@@ -0,0 +1,114 @@
+//! Context replayed at the start of each rotated .mav file.
+//!
+//! Low rate messages describing the vehicle are easily missing from a rotated file, leaving it
+//! unreadable on its own: the autopilot and vehicle type, the firmware version, the parameter set
+//! in use and the home position. With state replay enabled, the logger keeps the last frame of
+//! these messages and writes them again as ordinary MAVLINK entries at the start of every new
+//! file, so that any parser can interpret the file without its predecessors.
+use std::collections::BTreeMap;
+
+use crate::frame;
+
+/// HEARTBEAT message id.
+const HEARTBEAT_ID: u32 = 0;
+/// PARAM_VALUE message id.
+const PARAM_VALUE_ID: u32 = 22;
+/// AUTOPILOT_VERSION message id.
+const AUTOPILOT_VERSION_ID: u32 = 148;
+/// HOME_POSITION message id.
+const HOME_POSITION_ID: u32 = 242;
+/// Offset of the param_id field in the PARAM_VALUE payload.
+const PARAM_ID_OFFSET: usize = 8;
+/// Parameter reporting the hash of the parameter set, as sent by ArduPilot and PX4.
+const PARAM_HASH_ID: &[u8] = b"_HASH_CHECK";
+
+/// Last frame of each replayed message, by system, component and message id.
+#[derive(Default)]
+pub(crate) struct SegmentState {
+    frames: BTreeMap<(u8, u8, u32), Vec<u8>>,
+}
+
+impl SegmentState {
+    /// Keeps a written frame if it is part of the replayed state.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes of one complete MAVLink frame.
+    pub(crate) fn observe(&mut self, bytes: &[u8]) {
+        let (msg_id, (system_id, component_id)) =
+            match frame::message_id(bytes).zip(frame::source(bytes)) {
+                Some(ids) => ids,
+                None => return,
+            };
+        let replayed = match msg_id {
+            HEARTBEAT_ID | AUTOPILOT_VERSION_ID | HOME_POSITION_ID => true,
+            PARAM_VALUE_ID => frame::payload(bytes).is_some_and(is_param_hash),
+            _ => false,
+        };
+        if replayed {
+            self.frames
+                .insert((system_id, component_id, msg_id), bytes.to_vec());
+        }
+    }
+
+    /// Returns the kept frames in the order they are replayed.
+    pub(crate) fn frames(&self) -> Vec<Vec<u8>> {
+        self.frames.values().cloned().collect()
+    }
+}
+
+/// Returns whether a PARAM_VALUE payload reports the parameter set hash.
+fn is_param_hash(payload: &[u8]) -> bool {
+    // trailing zeros of the payload may be truncated, so the name is compared zero padded
+    let mut param_id = [0u8; 16];
+    let name = payload.get(PARAM_ID_OFFSET..).unwrap_or_default();
+    let len = name.len().min(param_id.len());
+    param_id[..len].copy_from_slice(&name[..len]);
+    param_id.starts_with(PARAM_HASH_ID) && param_id[PARAM_HASH_ID.len()] == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::{MavMessage, PARAM_VALUE_DATA};
+    use mavlink::{MAVLinkV2MessageRaw, MavHeader};
+
+    use super::*;
+
+    fn serialize(component_id: u8, msg: &MavMessage) -> Vec<u8> {
+        let header = MavHeader {
+            system_id: 1,
+            component_id,
+            sequence: 0,
+        };
+        let mut raw = MAVLinkV2MessageRaw::new();
+        raw.serialize_message(header, msg);
+        raw.raw_bytes().to_vec()
+    }
+
+    fn param(name: &str) -> MavMessage {
+        let mut param_id = [0u8; 16];
+        param_id[..name.len()].copy_from_slice(name.as_bytes());
+        MavMessage::PARAM_VALUE(PARAM_VALUE_DATA {
+            param_id,
+            ..Default::default()
+        })
+    }
+
+    /// Test that only the state messages and the parameter hash are kept, the last frame of
+    /// each component replacing the previous one.
+    #[test]
+    fn test_segment_state_observe() {
+        let mut state = SegmentState::default();
+        let heartbeat = serialize(1, &MavMessage::HEARTBEAT(Default::default()));
+        let hash = serialize(1, &param("_HASH_CHECK"));
+        state.observe(&serialize(1, &MavMessage::ATTITUDE(Default::default())));
+        state.observe(&serialize(1, &param("_HASH_CHECK_2")));
+        state.observe(&serialize(1, &param("SYSID_THISMAV")));
+        state.observe(&serialize(1, &MavMessage::HEARTBEAT(Default::default())));
+        state.observe(&heartbeat);
+        state.observe(&hash);
+        let camera = serialize(100, &MavMessage::HEARTBEAT(Default::default()));
+        state.observe(&camera);
+        assert_eq!(state.frames(), vec![heartbeat, hash, camera]);
+    }
+}
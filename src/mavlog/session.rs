@@ -0,0 +1,241 @@
+//! This module defines a logger writing one .mav log per flight.
+//!
+//! The flights are delimited by the armed flag of the HEARTBEAT messages passing through the
+//! logger: a new log is started when a vehicle arms and closed when the same vehicle disarms.
+//! Messages received while no vehicle is armed are not logged.
+//!
+//! Logs are written to `sys<system id>_<start time>_<index>.mav` in the configured directory,
+//! with the start time in seconds since the UNIX epoch and an index unique within the logger.
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mavlink::{MavFrame, MavlinkVersion, Message};
+
+use super::header::FormatFlags;
+use super::logger::RotatingMavLogger;
+use crate::mav_logger::MavLogger;
+
+/// HEARTBEAT message id.
+const HEARTBEAT_ID: u32 = 0;
+/// Offset of the base_mode field in the HEARTBEAT payload.
+const HEARTBEAT_BASE_MODE_OFFSET: usize = 6;
+/// Offset of the system_status field in the HEARTBEAT payload.
+const HEARTBEAT_SYSTEM_STATUS_OFFSET: usize = 7;
+/// MAV_MODE_FLAG_SAFETY_ARMED bit of the HEARTBEAT base_mode field.
+const SAFETY_ARMED: u8 = 0x80;
+
+/// The log of the flight in progress.
+struct Session {
+    system_id: u8,
+    path: String,
+    logger: RotatingMavLogger,
+}
+
+/// Logger starting a new .mav log each time a vehicle arms and closing it when it disarms.
+pub struct ArmSessionLogger {
+    directory: String,
+    max_bytes: u64,
+    backup_count: usize,
+    format_flags: Option<FormatFlags>,
+    session: Option<Session>,
+    next_index: u64,
+}
+
+impl ArmSessionLogger {
+    /// Creates a new `ArmSessionLogger`. No log is created until a vehicle arms.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory the logs are written to. It is expected to exist.
+    /// * `max_bytes` - The maximum size of a log file before it is rotated.
+    /// * `backup_count` - The number of backup files to keep for each flight.
+    /// * `format_flags` - Optional format flags for the log files.
+    pub fn new(
+        directory: &str,
+        max_bytes: u64,
+        backup_count: usize,
+        format_flags: Option<FormatFlags>,
+    ) -> Self {
+        Self {
+            directory: String::from(directory),
+            max_bytes,
+            backup_count,
+            format_flags,
+            session: None,
+            next_index: 0,
+        }
+    }
+
+    /// Returns the path of the log of the flight in progress, if a vehicle is armed.
+    pub fn current_path(&self) -> Option<&str> {
+        self.session.as_ref().map(|session| session.path.as_str())
+    }
+
+    /// Returns the system id of the armed vehicle, if any.
+    pub fn armed_system(&self) -> Option<u8> {
+        self.session.as_ref().map(|session| session.system_id)
+    }
+
+    /// Closes the log of the flight in progress, if any.
+    pub fn close(&mut self) {
+        self.session = None;
+    }
+
+    /// Starts the log of a flight.
+    fn open(&mut self, system_id: u8) -> std::io::Result<Session> {
+        let start_s = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let name = format!("sys{system_id}_{start_s}_{}.mav", self.next_index);
+        let path = Path::new(&self.directory).join(name);
+        let path = path.to_string_lossy().into_owned();
+        let logger = RotatingMavLogger::new(
+            &path,
+            self.max_bytes,
+            self.backup_count,
+            self.format_flags,
+            None,
+        )?;
+        self.next_index += 1;
+        Ok(Session {
+            system_id,
+            path,
+            logger,
+        })
+    }
+}
+
+/// Returns the base_mode and system_status fields of a HEARTBEAT message, `None` for other
+/// messages.
+pub(crate) fn heartbeat_mode<M: Message>(msg: &M) -> Option<(u8, u8)> {
+    if msg.message_id() != HEARTBEAT_ID {
+        return None;
+    }
+    // fields truncated from the end of the payload are 0
+    let mut payload = [0u8; 255];
+    msg.ser(MavlinkVersion::V2, &mut payload);
+    Some((
+        payload[HEARTBEAT_BASE_MODE_OFFSET],
+        payload[HEARTBEAT_SYSTEM_STATUS_OFFSET],
+    ))
+}
+
+/// Returns the armed state reported by a HEARTBEAT message, `None` for other messages.
+pub(crate) fn heartbeat_armed<M: Message>(msg: &M) -> Option<bool> {
+    heartbeat_mode(msg).map(|(base_mode, _)| base_mode & SAFETY_ARMED != 0)
+}
+
+impl MavLogger for ArmSessionLogger {
+    /// Writes a MAVLink message to the log of the flight in progress.
+    ///
+    /// A HEARTBEAT reporting an armed vehicle while no flight is in progress starts a new log
+    /// holding it. A HEARTBEAT of the armed vehicle reporting it disarmed is written and then
+    /// closes the log. Other messages are discarded while no flight is in progress.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The MavFrame to log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error is returned if the log of a new
+    /// flight could not be created or if the message could not be written.
+    fn write_mavlink<M: Message>(&mut self, frame: MavFrame<M>) -> std::io::Result<()> {
+        let system_id = frame.header.system_id;
+        let armed = heartbeat_armed(&frame.msg);
+        if self.session.is_none() && armed == Some(true) {
+            self.session = Some(self.open(system_id)?);
+        }
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+        session.logger.write_mavlink(frame)?;
+        if armed == Some(false) && session.system_id == system_id {
+            self.session = None;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use mavlink::MavHeader;
+    use mavlink::common::{HEARTBEAT_DATA, MavMessage, MavModeFlag};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mav_parser::for_each_entry;
+    use crate::mavlog::parser::MavLogParser;
+
+    fn frame(system_id: u8, msg: MavMessage) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader {
+                system_id,
+                ..Default::default()
+            },
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    fn heartbeat(system_id: u8, armed: bool) -> MavFrame<MavMessage> {
+        let base_mode = if armed {
+            MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED
+        } else {
+            MavModeFlag::empty()
+        };
+        frame(
+            system_id,
+            MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+                base_mode,
+                ..Default::default()
+            }),
+        )
+    }
+
+    fn count_messages(path: &str) -> u64 {
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let mut messages = 0;
+        for_each_entry(&mut parser, |entry| {
+            if entry.mav_message.is_some() {
+                messages += 1;
+            }
+            Ok(())
+        })
+        .unwrap();
+        messages
+    }
+
+    /// Test that each flight between arming and disarming is written to its own log.
+    #[test]
+    fn test_arm_sessions() {
+        let dir = TempDir::new().unwrap();
+        let mut logger = ArmSessionLogger::new(dir.path().to_str().unwrap(), 100000, 0, None);
+        let attitude = |system_id| frame(system_id, MavMessage::ATTITUDE(Default::default()));
+
+        logger.write_mavlink(heartbeat(1, false)).unwrap();
+        logger.write_mavlink(attitude(1)).unwrap();
+        assert_eq!(logger.current_path(), None);
+
+        let mut paths = Vec::new();
+        for _ in 0..2 {
+            logger.write_mavlink(heartbeat(1, true)).unwrap();
+            assert_eq!(logger.armed_system(), Some(1));
+            paths.push(logger.current_path().unwrap().to_string());
+            logger.write_mavlink(attitude(1)).unwrap();
+            // a disarmed ground station does not end the flight
+            logger.write_mavlink(heartbeat(255, false)).unwrap();
+            logger.write_mavlink(heartbeat(1, false)).unwrap();
+            assert_eq!(logger.current_path(), None);
+            logger.write_mavlink(attitude(1)).unwrap();
+        }
+
+        assert_ne!(paths[0], paths[1]);
+        assert!(paths[0].contains("sys1_"));
+        for path in &paths {
+            assert_eq!(count_messages(path), 4);
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
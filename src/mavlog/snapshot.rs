@@ -0,0 +1,170 @@
+//! Periodic snapshots of the vehicle state in .mav logs.
+//!
+//! A parser starting in the middle of a log, or reading a rotated file, only learns the state of
+//! the vehicle once each low rate message comes around again. With snapshots enabled, the
+//! logger keeps the last frame of selected messages from each component and periodically
+//! writes them all in a single SNAPSHOT entry, as well as at the start of each rotated file.
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::frame;
+
+/// Settings of the snapshots written by a logger.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SnapshotConfig {
+    /// Ids of the messages kept in snapshots.
+    pub message_ids: Vec<u32>,
+    /// Interval at which snapshots are written.
+    pub interval: Duration,
+}
+
+impl Default for SnapshotConfig {
+    /// Keeps HEARTBEAT, SYS_STATUS, GPS_RAW_INT, GLOBAL_POSITION_INT, BATTERY_STATUS,
+    /// HOME_POSITION and EXTENDED_SYS_STATE, written every 10 seconds.
+    fn default() -> Self {
+        Self {
+            message_ids: vec![0, 1, 24, 33, 147, 242, 245],
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Last frame of each selected message, by system, component and message id.
+pub(crate) struct LastValueCache {
+    config: SnapshotConfig,
+    frames: BTreeMap<(u8, u8, u32), Vec<u8>>,
+    last_snapshot: Instant,
+}
+
+impl LastValueCache {
+    pub(crate) fn new(config: SnapshotConfig) -> Self {
+        Self {
+            config,
+            frames: BTreeMap::new(),
+            last_snapshot: Instant::now(),
+        }
+    }
+
+    /// Keeps a written frame if its message is selected.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes of one complete MAVLink frame.
+    pub(crate) fn observe(&mut self, bytes: &[u8]) {
+        let (msg_id, (system_id, component_id)) =
+            match frame::message_id(bytes).zip(frame::source(bytes)) {
+                Some(ids) => ids,
+                None => return,
+            };
+        if self.config.message_ids.contains(&msg_id) {
+            self.frames
+                .insert((system_id, component_id, msg_id), bytes.to_vec());
+        }
+    }
+
+    /// Returns whether the snapshot interval has elapsed since the last snapshot.
+    pub(crate) fn is_due(&self) -> bool {
+        self.last_snapshot.elapsed() >= self.config.interval
+    }
+
+    /// Packs the payload of a SNAPSHOT entry and restarts the snapshot interval.
+    ///
+    /// # Returns
+    ///
+    /// The kept frames one after another, empty if no selected message was written yet.
+    pub(crate) fn take_snapshot(&mut self) -> Vec<u8> {
+        self.last_snapshot = Instant::now();
+        self.frames.values().flatten().copied().collect()
+    }
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use mavlink::common::MavMessage;
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::mav_logger::MavLogger;
+    use crate::mav_parser::for_each_entry;
+    use crate::mavlog::header::FormatFlags;
+    use crate::mavlog::logger::RotatingMavLogger;
+    use crate::mavlog::parser::MavLogParser;
+
+    fn frame(system_id: u8, msg: MavMessage) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader {
+                system_id,
+                ..Default::default()
+            },
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    /// Test that snapshots hold the last frame of the selected messages of every system.
+    #[test]
+    fn test_snapshot_entries() {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let tmpfile_path = tmpfile.path().to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(tmpfile_path, 100000, 0, None, None).unwrap();
+        logger
+            .set_snapshot(Some(SnapshotConfig {
+                message_ids: vec![0],
+                interval: Duration::ZERO,
+            }))
+            .unwrap();
+        logger
+            .write_mavlink(frame(1, MavMessage::HEARTBEAT(Default::default())))
+            .unwrap();
+        logger
+            .write_mavlink(frame(2, MavMessage::HEARTBEAT(Default::default())))
+            .unwrap();
+        logger
+            .write_mavlink(frame(1, MavMessage::ATTITUDE(Default::default())))
+            .unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(tmpfile_path);
+        let mut snapshots = Vec::new();
+        for_each_entry(&mut parser, |entry| {
+            snapshots.extend(entry.snapshot);
+            Ok(())
+        })
+        .unwrap();
+        let systems: Vec<Vec<u8>> = snapshots
+            .iter()
+            .map(|snapshot| {
+                snapshot
+                    .iter()
+                    .map(|(header, msg)| {
+                        assert!(matches!(msg, MavMessage::HEARTBEAT(_)));
+                        header.system_id
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(systems, vec![vec![1], vec![1, 2], vec![1, 2]]);
+    }
+
+    /// Test that snapshots are refused by a MAVLink only logger.
+    #[test]
+    fn test_snapshot_mavlink_only() {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let flags = FormatFlags {
+            mavlink_only: true,
+            ..Default::default()
+        };
+        let mut logger = RotatingMavLogger::new(
+            tmpfile.path().to_str().unwrap(),
+            100000,
+            0,
+            Some(flags),
+            None,
+        )
+        .unwrap();
+        let err = logger
+            .set_snapshot(Some(SnapshotConfig::default()))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,504 @@
+//! This module defines a splitter that demultiplexes a log into one .mav log per MAVLink system.
+//! Any format supported by a `MavParser` can be used as the input so that, for example, a ground
+//! station tlog containing several vehicles becomes one .mav log per vehicle. A .mav log is best
+//! split with `SystemSplitter::split_log`, which copies its MAVLink frames byte for byte.
+use std::collections::BTreeMap;
+
+use mavlink::error::MessageReadError;
+use mavlink::{MAVLinkV2MessageRaw, MavFrame, MavlinkVersion, Message};
+
+use super::header::{FormatFlags, MavlinkMessageDefinition};
+use super::logger::{EntryType, RotatingMavLogger};
+use super::parser::{MavLogParser, read_header};
+use crate::frame;
+use crate::mav_parser::{LogEntry, MavParser, for_each_entry};
+
+/// Routes log entries to a separate rotating .mav log per MAVLink system id.
+///
+/// Output logs are created the first time a system id is seen and each output gets its own
+/// freshly generated file header, holding the message definition set with
+/// `set_message_definition`. Entry timestamps from the source log are preserved.
+pub struct SystemSplitter {
+    path_prefix: String,
+    max_bytes: u64,
+    backup_count: usize,
+    format_flags: FormatFlags,
+    message_definition: MavlinkMessageDefinition,
+    loggers: BTreeMap<u8, RotatingMavLogger>,
+}
+
+impl SystemSplitter {
+    /// Creates a new `SystemSplitter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path_prefix` - Prefix for the output log paths. Each output is written to
+    ///   `<path_prefix>_sys<system_id>.mav`. Parent directories are expected to exist.
+    /// * `max_bytes` - The maximum size of an output log file before it is rotated.
+    /// * `backup_count` - The number of backup files to keep per output.
+    /// * `format_flags` - Optional format flags used for every output log.
+    pub fn new(
+        path_prefix: &str,
+        max_bytes: u64,
+        backup_count: usize,
+        format_flags: Option<FormatFlags>,
+    ) -> Self {
+        Self {
+            path_prefix: String::from(path_prefix),
+            max_bytes,
+            backup_count,
+            format_flags: format_flags.unwrap_or_default(),
+            message_definition: MavlinkMessageDefinition::default(),
+            loggers: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the message definition written in the header of the output logs created from now
+    /// on, such as the definition of the source log.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_definition` - The MAVLink message definition of the entries being split.
+    pub fn set_message_definition(&mut self, message_definition: MavlinkMessageDefinition) {
+        self.message_definition = message_definition;
+    }
+
+    /// Returns the path of the output log for the given system id.
+    pub fn output_path(&self, system_id: u8) -> String {
+        format!("{}_sys{}.mav", self.path_prefix, system_id)
+    }
+
+    /// Returns the system ids that an output log has been created for.
+    pub fn system_ids(&self) -> Vec<u8> {
+        self.loggers.keys().copied().collect()
+    }
+
+    /// Routes a single log entry to the relevant output log.
+    ///
+    /// MAVLink entries are written to the output of the system that sent them, and the frames
+    /// of a snapshot to the snapshot of each system, see `write_snapshot_at`. Other entries,
+    /// such as text, raw data, drop reports or blobs, carry no system id so they are written to
+    /// every output that exists at the time. Entries other than MAVLink entries are dropped if
+    /// the outputs only accept MAVLink. MAVLink entries holding their frame in `raw` are written
+    /// byte for byte, others are re-serialized with their protocol version, MAVLink 2 if it is
+    /// unknown. Snapshot frames are re-serialized as MAVLink 2.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The log entry to route.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn write_entry<M: Message>(&mut self, entry: LogEntry<M>) -> std::io::Result<()> {
+        if let (Some(header), Some(msg)) = (entry.mav_header, entry.mav_message) {
+            let logger = self.logger_for(header.system_id)?;
+            if let Some(raw) = entry.raw.filter(|raw| frame::is_complete(raw)) {
+                return logger.write_at(EntryType::Mavlink, entry.timestamp, &raw);
+            }
+            let frame = MavFrame {
+                header,
+                msg,
+                protocol_version: entry.protocol_version.unwrap_or(MavlinkVersion::V2),
+            };
+            return logger.write_mavlink_at(frame, entry.timestamp);
+        }
+
+        if self.format_flags.mavlink_only {
+            return Ok(());
+        }
+        if let Some(snapshot) = entry.snapshot {
+            let mut payload: Vec<u8> = Vec::new();
+            for (header, msg) in snapshot {
+                let mut raw = MAVLinkV2MessageRaw::new();
+                raw.serialize_message(header, &msg);
+                payload.extend_from_slice(raw.raw_bytes());
+            }
+            return self.write_snapshot_at(&payload, entry.timestamp);
+        }
+        if let Some(drops) = entry.drops {
+            for logger in self.loggers.values_mut() {
+                logger.write_drops_at(&drops, entry.timestamp)?;
+            }
+            return Ok(());
+        }
+        if let Some(blob) = entry.blob {
+            for logger in self.loggers.values_mut() {
+                logger.write_blob_at(&blob.data, entry.timestamp)?;
+            }
+            return Ok(());
+        }
+        if let Some(rtcm) = entry.rtcm {
+            for logger in self.loggers.values_mut() {
+                logger.write_rtcm_at(rtcm.link, &rtcm.data, entry.timestamp)?;
+            }
+            return Ok(());
+        }
+        if let Some(event) = entry.actuator {
+            for logger in self.loggers.values_mut() {
+                logger.write_actuator_at(
+                    event.actuator,
+                    event.active,
+                    event.rate,
+                    entry.timestamp,
+                )?;
+            }
+            return Ok(());
+        }
+        if let Some(weather) = entry.weather {
+            for logger in self.loggers.values_mut() {
+                logger.write_weather_at(&weather, entry.timestamp)?;
+            }
+            return Ok(());
+        }
+        if let Some(metadata) = entry.metadata {
+            for logger in self.loggers.values_mut() {
+                logger.write_metadata_at(&metadata, entry.timestamp)?;
+            }
+            return Ok(());
+        }
+        let (entry_type, data): (EntryType, Vec<u8>) = match (entry.text, entry.raw) {
+            (Some(text), _) => (EntryType::Text, text.into_bytes()),
+            (None, Some(raw)) => (EntryType::Raw, raw),
+            (None, None) => return Ok(()),
+        };
+        for logger in self.loggers.values_mut() {
+            logger.write_at(entry_type, entry.timestamp, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Reads every entry from a parser and routes it to the relevant output log.
+    ///
+    /// # Arguments
+    ///
+    /// * `parser` - The parser to read entries from. It is consumed until the end of the log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn split<P: MavParser + ?Sized>(&mut self, parser: &mut P) -> std::io::Result<()> {
+        for_each_entry(parser, |entry| self.write_entry(entry))
+    }
+
+    /// Splits a .mav log, copying its entries without decoding them.
+    ///
+    /// MAVLink frames are copied byte for byte, signatures included, and routed by the system id
+    /// of their frame header, as are the frames of snapshots, see `write_snapshot_at`. Other
+    /// entries are copied to every output that exists at the time, blobs fragment by fragment,
+    /// unless the outputs only accept MAVLink. The message
+    /// definition of the source log is written in the header of the outputs created. Like
+    /// `for_each_entry`, splitting skips corrupted entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_path` - The path of the log to split.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure, an error of kind `Unsupported` if the source
+    /// log is encrypted.
+    pub fn split_log<M: Message + 'static>(&mut self, source_path: &str) -> std::io::Result<()> {
+        let source_header = read_header(source_path)?;
+        if source_header.format_flags.encrypted {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Encrypted logs cannot be split without decoding them",
+            ));
+        }
+        self.set_message_definition(source_header.message_definition);
+        let mut parser = MavLogParser::<M>::try_new(source_path)?;
+        loop {
+            let entry = match parser.read_raw_entry() {
+                Ok(entry) => entry,
+                Err(MessageReadError::Io(e)) => match e.kind() {
+                    std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                    // corrupted entry content or an appended log, move on to the next entry
+                    std::io::ErrorKind::InvalidData => continue,
+                    _ => return Err(e),
+                },
+                Err(MessageReadError::Parse(_)) => continue,
+            };
+            let entry_type = EntryType::try_from(entry.entry_type).unwrap_or(EntryType::Raw);
+            if entry_type == EntryType::Mavlink {
+                if let Some((system_id, _)) = frame::source(&entry.payload) {
+                    self.logger_for(system_id)?.write_at(
+                        entry_type,
+                        entry.timestamp,
+                        &entry.payload,
+                    )?;
+                }
+                continue;
+            }
+            if self.format_flags.mavlink_only {
+                continue;
+            }
+            if entry_type == EntryType::Snapshot {
+                self.write_snapshot_at(&entry.payload, entry.timestamp)?;
+                continue;
+            }
+            for logger in self.loggers.values_mut() {
+                logger.write_at(entry_type, entry.timestamp, &entry.payload)?;
+            }
+        }
+    }
+
+    /// Writes the frames of a snapshot as one snapshot per system, each to the output of the
+    /// system that sent its frames.
+    ///
+    /// Frames are copied byte for byte. The snapshot ends at the first frame that is truncated
+    /// or not a MAVLink frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The MAVLink frames of the snapshot one after another.
+    /// * `timestamp_us` - The timestamp of the snapshot entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn write_snapshot_at(
+        &mut self,
+        payload: &[u8],
+        timestamp_us: Option<u64>,
+    ) -> std::io::Result<()> {
+        let mut snapshots: BTreeMap<u8, Vec<u8>> = BTreeMap::new();
+        let mut rest = payload;
+        while rest.len() >= frame::LENGTH_PEEK_SIZE {
+            let Some(len) = frame::frame_len(rest).filter(|len| *len <= rest.len()) else {
+                break;
+            };
+            let Some((system_id, _)) = frame::source(&rest[..len]) else {
+                break;
+            };
+            snapshots
+                .entry(system_id)
+                .or_default()
+                .extend_from_slice(&rest[..len]);
+            rest = &rest[len..];
+        }
+        for (system_id, snapshot) in snapshots {
+            self.logger_for(system_id)?
+                .write_at(EntryType::Snapshot, timestamp_us, &snapshot)?;
+        }
+        Ok(())
+    }
+
+    /// Retrieves the output logger for a system id, creating it if necessary.
+    fn logger_for(&mut self, system_id: u8) -> std::io::Result<&mut RotatingMavLogger> {
+        if !self.loggers.contains_key(&system_id) {
+            let logger = RotatingMavLogger::new(
+                &self.output_path(system_id),
+                self.max_bytes,
+                self.backup_count,
+                Some(FormatFlags {
+                    encrypted: false,
+                    dictionary: false,
+                    ..self.format_flags
+                }),
+                Some(self.message_definition.clone()),
+            )?;
+            self.loggers.insert(system_id, logger);
+        }
+        Ok(self.loggers.get_mut(&system_id).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use mavlink::MavHeader;
+    use mavlink::common::MavMessage;
+
+    use super::*;
+    use crate::mav_logger::MavLogger;
+
+    fn heartbeat_entry(system_id: u8, timestamp: u64) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(MavMessage::HEARTBEAT(Default::default())),
+            ..Default::default()
+        }
+    }
+
+    /// Test that entries from different systems end up in separate output logs.
+    #[test]
+    fn test_split_by_system() {
+        let dir = TempDir::new().unwrap();
+        let prefix = dir.path().join("flight");
+        let mut splitter = SystemSplitter::new(prefix.to_str().unwrap(), 100000, 0, None);
+
+        for i in 0..6 {
+            splitter
+                .write_entry(heartbeat_entry(1 + i % 3, i as u64))
+                .unwrap();
+        }
+        let note: LogEntry<MavMessage> = LogEntry {
+            text: Some(String::from("note")),
+            ..Default::default()
+        };
+        splitter.write_entry(note).unwrap();
+        assert_eq!(splitter.system_ids(), vec![1, 2, 3]);
+
+        for system_id in 1..=3u8 {
+            let mut parser = MavLogParser::<MavMessage>::new(&splitter.output_path(system_id));
+            let mut timestamps: Vec<u64> = Vec::new();
+            let mut texts: usize = 0;
+            for_each_entry(&mut parser, |entry| {
+                if let Some(header) = entry.mav_header {
+                    assert_eq!(header.system_id, system_id);
+                    timestamps.push(entry.timestamp.unwrap());
+                }
+                if entry.text.is_some() {
+                    texts += 1;
+                }
+                Ok(())
+            })
+            .unwrap();
+            let first = (system_id - 1) as u64;
+            assert_eq!(timestamps, vec![first, first + 3]);
+            assert_eq!(texts, 1);
+        }
+    }
+
+    /// Test that a .mav log is split with its frames copied byte for byte and its message
+    /// definition, and that decoded entries keep their protocol version.
+    #[test]
+    fn test_split_log_raw_frames() {
+        let dir = TempDir::new().unwrap();
+        let source_path = dir.path().join("source.mav");
+        let source_path = source_path.to_str().unwrap();
+        let definition = MavlinkMessageDefinition {
+            dialect: String::from("ardupilotmega"),
+            ..Default::default()
+        };
+        let mut source =
+            RotatingMavLogger::new(source_path, 100000, 0, None, Some(definition.clone())).unwrap();
+        for (system_id, protocol_version) in [(1, MavlinkVersion::V1), (2, MavlinkVersion::V2)] {
+            source
+                .write_mavlink(MavFrame {
+                    header: MavHeader {
+                        system_id,
+                        component_id: 1,
+                        sequence: 7,
+                    },
+                    msg: MavMessage::HEARTBEAT(Default::default()),
+                    protocol_version,
+                })
+                .unwrap();
+        }
+        source.write_text("note").unwrap();
+        drop(source);
+
+        let prefix = dir.path().join("split");
+        let mut splitter = SystemSplitter::new(prefix.to_str().unwrap(), 100000, 0, None);
+        splitter.split_log::<MavMessage>(source_path).unwrap();
+        assert_eq!(splitter.system_ids(), vec![1, 2]);
+        drop(splitter);
+
+        let mut source_frames = Vec::new();
+        let mut parser = MavLogParser::<MavMessage>::new(source_path);
+        while let Ok(entry) = parser.read_raw_entry() {
+            if entry.entry_type == EntryType::Mavlink as u8 {
+                source_frames.push((entry.timestamp, entry.payload));
+            }
+        }
+        for (index, system_id) in [1u8, 2].into_iter().enumerate() {
+            let path = format!("{}_sys{}.mav", prefix.to_str().unwrap(), system_id);
+            assert_eq!(read_header(&path).unwrap().message_definition, definition);
+            let mut parser = MavLogParser::<MavMessage>::new(&path);
+            let entry = parser.read_raw_entry().unwrap();
+            assert_eq!((entry.timestamp, entry.payload), source_frames[index]);
+        }
+
+        // decoded entries are written with their own protocol version
+        let prefix = dir.path().join("decoded");
+        let mut splitter = SystemSplitter::new(prefix.to_str().unwrap(), 100000, 0, None);
+        splitter
+            .split(&mut MavLogParser::<MavMessage>::new(source_path))
+            .unwrap();
+        drop(splitter);
+        let mut parser =
+            MavLogParser::<MavMessage>::new(&format!("{}_sys1.mav", prefix.to_str().unwrap()));
+        let entry = parser.parse_next_entry().unwrap();
+        assert_eq!(entry.protocol_version, Some(MavlinkVersion::V1));
+    }
+
+    /// Test that drop reports are copied to every output and snapshots split per system, both
+    /// when splitting a .mav log and decoded entries.
+    #[test]
+    fn test_split_drops_and_snapshots() {
+        let dir = TempDir::new().unwrap();
+        let source_path = dir.path().join("source.mav");
+        let source_path = source_path.to_str().unwrap();
+        let header = |system_id| MavHeader {
+            system_id,
+            component_id: 1,
+            sequence: 0,
+        };
+        let mut source = RotatingMavLogger::new(source_path, 100000, 0, None, None).unwrap();
+        let mut snapshot: Vec<u8> = Vec::new();
+        for (system_id, msg) in [
+            (1, MavMessage::HEARTBEAT(Default::default())),
+            (2, MavMessage::HEARTBEAT(Default::default())),
+            (1, MavMessage::ATTITUDE(Default::default())),
+        ] {
+            let mut raw = MAVLinkV2MessageRaw::new();
+            raw.serialize_message(header(system_id), &msg);
+            snapshot.extend_from_slice(raw.raw_bytes());
+            source
+                .write_mavlink(MavFrame {
+                    header: header(system_id),
+                    msg,
+                    protocol_version: MavlinkVersion::V2,
+                })
+                .unwrap();
+        }
+        source.write_drops_at(&[(0, 3)], Some(10)).unwrap();
+        source
+            .write_at(EntryType::Snapshot, Some(20), &snapshot)
+            .unwrap();
+        drop(source);
+
+        for name in ["raw", "decoded"] {
+            let prefix = dir.path().join(name);
+            let mut splitter = SystemSplitter::new(prefix.to_str().unwrap(), 100000, 0, None);
+            if name == "raw" {
+                splitter.split_log::<MavMessage>(source_path).unwrap();
+            } else {
+                splitter
+                    .split(&mut MavLogParser::<MavMessage>::new(source_path))
+                    .unwrap();
+            }
+            assert_eq!(splitter.system_ids(), vec![1, 2]);
+
+            for (system_id, frame_count) in [(1u8, 2), (2, 1)] {
+                let mut parser = MavLogParser::<MavMessage>::new(&splitter.output_path(system_id));
+                let mut drops = Vec::new();
+                let mut snapshots = Vec::new();
+                for_each_entry(&mut parser, |entry| {
+                    drops.extend(entry.drops.map(|drops| (entry.timestamp, drops)));
+                    snapshots.extend(entry.snapshot.map(|frames| (entry.timestamp, frames)));
+                    Ok(())
+                })
+                .unwrap();
+                assert_eq!(drops, vec![(Some(10), vec![(0, 3)])], "{name}");
+                assert_eq!(snapshots.len(), 1, "{name}");
+                let (timestamp, frames) = &snapshots[0];
+                assert_eq!(*timestamp, Some(20));
+                assert_eq!(frames.len(), frame_count, "{name}");
+                assert!(
+                    frames
+                        .iter()
+                        .all(|(header, _)| header.system_id == system_id)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Transformations applied to entries before a `RotatingMavLogger` writes them.
+//!
+//! Transforms added with `RotatingMavLogger::with_transform` run in the order they were added on
+//! every entry the logger writes. Each transform may modify the entry, drop it, or copy it to
+//! another sink, or insert a new entry before it, which allows redaction, enrichment and sampling
+//! without changing the logger.
+use std::sync::{Arc, Mutex};
+
+use super::logger::{EntryType, RotatingMavLogger};
+
+/// An entry about to be written by a logger.
+#[derive(PartialEq, Clone, Debug)]
+pub struct EntryDraft {
+    /// The type of the entry.
+    pub entry_type: EntryType,
+    /// The entry timestamp in microseconds, `None` if the log does not record timestamps.
+    pub timestamp_us: Option<u64>,
+    /// The entry payload: the serialized frame of MAVLink entries, the UTF-8 text of text
+    /// entries and the packed content of other entries.
+    pub data: Vec<u8>,
+}
+
+/// A destination entries can be copied to by a transform.
+pub trait EntrySink {
+    /// Writes a copy of an entry.
+    fn write_entry(&mut self, draft: &EntryDraft) -> std::io::Result<()>;
+}
+
+/// Collects copied entries in memory.
+impl EntrySink for Vec<EntryDraft> {
+    fn write_entry(&mut self, draft: &EntryDraft) -> std::io::Result<()> {
+        self.push(draft.clone());
+        Ok(())
+    }
+}
+
+/// Writes copied entries to another log, with their original timestamp. The entries also pass
+/// through the transforms of that logger.
+impl EntrySink for RotatingMavLogger {
+    fn write_entry(&mut self, draft: &EntryDraft) -> std::io::Result<()> {
+        self.write_at(draft.entry_type, draft.timestamp_us, &draft.data)
+    }
+}
+
+/// A sink shared between a transform and the code owning it.
+pub type SharedSink = Arc<Mutex<dyn EntrySink + Send>>;
+
+/// What a logger does with an entry after a transform ran on it.
+pub enum Action {
+    /// Passes the entry, as modified by the transform, to the next transform and then writes it.
+    Keep,
+    /// Discards the entry. Later transforms do not see it.
+    Drop,
+    /// Writes a copy of the entry, as modified by the transform, to a sink and continues as
+    /// with `Keep`.
+    Duplicate(SharedSink),
+    /// Writes another entry before the entry, such as a note recording why the transform changed
+    /// its behavior, and continues as with `Keep`. Later transforms do not see the inserted
+    /// entry.
+    Insert(EntryDraft),
+}
+
+/// A transform run on every entry before it is written.
+pub type Transform = Box<dyn Fn(&mut EntryDraft) -> Action + Send>;
+
+/// Runs transforms on an entry in order.
+///
+/// # Arguments
+///
+/// * `transforms` - The transforms to run.
+/// * `draft` - The entry, modified in place.
+/// * `inserted` - Receives the entries inserted by the transforms, to be written before the
+///     entry even if a later transform drops it.
+///
+/// # Returns
+///
+/// A `Result` containing `false` if a transform dropped the entry or an `io::Error` if copying
+/// the entry to a sink failed.
+pub(crate) fn apply(
+    transforms: &[Transform],
+    draft: &mut EntryDraft,
+    inserted: &mut Vec<EntryDraft>,
+) -> std::io::Result<bool> {
+    for transform in transforms {
+        match transform(draft) {
+            Action::Keep => {}
+            Action::Drop => return Ok(false),
+            Action::Insert(entry) => inserted.push(entry),
+            Action::Duplicate(sink) => {
+                // a sink is only modified by its own methods, so its state stays usable
+                let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+                sink.write_entry(draft)?;
+            }
+        }
+    }
+    Ok(true)
+}
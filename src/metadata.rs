@@ -0,0 +1,356 @@
+//! Operator and job metadata recorded in logs.
+//!
+//! Compliance exports must tie telemetry to the job it belongs to: who operated the vehicle, on
+//! which field and job, and which products and batches were applied. Loggers record these as
+//! mission metadata entries with `RotatingMavLogger::write_metadata` when a job starts or
+//! changes. Each entry applies from its timestamp until the next one, so a `JobIndex` can tell
+//! the job of any entry of a log without an external database.
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "analysis")]
+use crate::analysis::filter::ParserFilter;
+#[cfg(feature = "parser")]
+use crate::mav_parser::{MavParser, for_each_entry};
+
+/// Key of the operator id in an encoded entry.
+const OPERATOR_KEY: &str = "operator";
+/// Key of the field id in an encoded entry.
+const FIELD_KEY: &str = "field";
+/// Key of the job id in an encoded entry.
+const JOB_KEY: &str = "job";
+/// Key of a product in an encoded entry, followed by the key of its batch.
+const PRODUCT_KEY: &str = "product";
+/// Key of the batch of the preceding product in an encoded entry.
+const BATCH_KEY: &str = "batch";
+
+/// A product applied during a job, such as a chemical, and the batch it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProductBatch {
+    /// Identifier of the product, such as its registration number.
+    pub product: String,
+    /// Identifier of the batch or lot, empty if unknown.
+    pub batch: String,
+}
+
+/// Who operated the vehicle for which job, and what was applied.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct MissionMetadata {
+    /// Identifier of the operator, such as a pilot license number.
+    pub operator_id: Option<String>,
+    /// Identifier of the field in the farm management system.
+    pub field_id: Option<String>,
+    /// Identifier of the job in the farm management system.
+    pub job_id: Option<String>,
+    /// Products applied during the job.
+    pub products: Vec<ProductBatch>,
+    /// Other application defined identifiers by name, such as a customer or permit number.
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl MissionMetadata {
+    /// Encodes the metadata as the payload of a mission metadata entry.
+    ///
+    /// The payload is a sequence of key and value pairs, each key preceded by its length as a
+    /// byte and each value by its length as a little endian `u16`. Keys and values are UTF-8,
+    /// keys longer than 255 bytes and values longer than 65535 bytes are truncated.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        let mut put = |key: &str, value: &str| {
+            let key = &key.as_bytes()[..key.len().min(u8::MAX as usize)];
+            let value = &value.as_bytes()[..value.len().min(u16::MAX as usize)];
+            payload.push(key.len() as u8);
+            payload.extend_from_slice(key);
+            payload.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            payload.extend_from_slice(value);
+        };
+        for (key, value) in [
+            (OPERATOR_KEY, &self.operator_id),
+            (FIELD_KEY, &self.field_id),
+            (JOB_KEY, &self.job_id),
+        ] {
+            if let Some(value) = value {
+                put(key, value);
+            }
+        }
+        for product in &self.products {
+            put(PRODUCT_KEY, &product.product);
+            put(BATCH_KEY, &product.batch);
+        }
+        for (key, value) in &self.attributes {
+            put(key, value);
+        }
+        payload
+    }
+
+    /// Decodes the payload of a mission metadata entry, see `to_payload`.
+    ///
+    /// Keys are matched exactly, so application defined attributes never collide with the
+    /// identifiers decoded into fields unless they reuse their key.
+    ///
+    /// # Returns
+    /// The metadata, or `None` if the payload is truncated or holds invalid UTF-8.
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        let mut metadata = MissionMetadata::default();
+        let mut rest = payload;
+        while let Some((&key_len, after)) = rest.split_first() {
+            let key = std::str::from_utf8(after.get(..key_len as usize)?).ok()?;
+            let after = &after[key_len as usize..];
+            let value_len = u16::from_le_bytes(after.get(..2)?.try_into().unwrap()) as usize;
+            let value = std::str::from_utf8(after.get(2..2 + value_len)?)
+                .ok()?
+                .to_string();
+            rest = &after[2 + value_len..];
+            match key {
+                OPERATOR_KEY => metadata.operator_id = Some(value),
+                FIELD_KEY => metadata.field_id = Some(value),
+                JOB_KEY => metadata.job_id = Some(value),
+                PRODUCT_KEY => metadata.products.push(ProductBatch {
+                    product: value,
+                    batch: String::new(),
+                }),
+                BATCH_KEY => match metadata.products.last_mut() {
+                    Some(product) if product.batch.is_empty() => product.batch = value,
+                    _ => metadata.products.push(ProductBatch {
+                        product: String::new(),
+                        batch: value,
+                    }),
+                },
+                _ => {
+                    metadata.attributes.insert(key.to_string(), value);
+                }
+            }
+        }
+        Some(metadata)
+    }
+}
+
+/// Formats the identifiers as quoted `key="value"` pairs in payload order, such as
+/// `operator="P-104" job="J-31" product="GLY-360" batch="L2291"`.
+impl fmt::Display for MissionMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        for (key, value) in [
+            (OPERATOR_KEY, &self.operator_id),
+            (FIELD_KEY, &self.field_id),
+            (JOB_KEY, &self.job_id),
+        ] {
+            if let Some(value) = value {
+                parts.push(format!("{key}={value:?}"));
+            }
+        }
+        for product in &self.products {
+            parts.push(format!(
+                "{PRODUCT_KEY}={:?} {BATCH_KEY}={:?}",
+                product.product, product.batch
+            ));
+        }
+        for (key, value) in &self.attributes {
+            parts.push(format!("{key}={value:?}"));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// The period of a log a mission metadata entry applies to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobSpan {
+    /// The metadata recorded at the start of the period.
+    pub metadata: MissionMetadata,
+    /// Timestamp of the metadata entry, if the log records one.
+    pub start_us: Option<u64>,
+    /// Timestamp the period ends at, excluded: that of the next metadata entry, or just after
+    /// the last entry of the log for the last period. `None` if the log has no timestamps.
+    pub end_us: Option<u64>,
+}
+
+impl JobSpan {
+    /// Returns whether a timestamp is within the period.
+    pub fn contains(&self, timestamp_us: u64) -> bool {
+        match (self.start_us, self.end_us) {
+            (Some(start_us), Some(end_us)) => (start_us..end_us).contains(&timestamp_us),
+            _ => false,
+        }
+    }
+
+    /// Returns a filter selecting the entries of the period, to read or export them alone.
+    ///
+    /// # Returns
+    /// The filter, or `None` if the period has no start or end timestamp.
+    #[cfg(feature = "analysis")]
+    pub fn to_filter(&self) -> Option<ParserFilter> {
+        Some(ParserFilter::between(self.start_us?, self.end_us?))
+    }
+}
+
+/// The mission metadata entries of a log and the periods they apply to.
+#[cfg(feature = "parser")]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct JobIndex {
+    spans: Vec<JobSpan>,
+}
+
+#[cfg(feature = "parser")]
+impl JobIndex {
+    /// Reads a full log and indexes its mission metadata entries.
+    ///
+    /// # Arguments
+    /// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the log could not be read.
+    pub fn build<P: MavParser + ?Sized>(parser: &mut P) -> std::io::Result<Self> {
+        let mut spans: Vec<JobSpan> = Vec::new();
+        let mut last_us: Option<u64> = None;
+        for_each_entry(parser, |entry| {
+            if let Some(metadata) = entry.metadata {
+                if let Some(previous) = spans.last_mut() {
+                    previous.end_us = entry.timestamp.or(last_us);
+                }
+                spans.push(JobSpan {
+                    metadata,
+                    start_us: entry.timestamp,
+                    end_us: entry.timestamp,
+                });
+            }
+            last_us = entry.timestamp.or(last_us);
+            Ok(())
+        })?;
+        if let Some(last) = spans.last_mut() {
+            last.end_us = last_us.map(|last_us| last_us + 1);
+        }
+        Ok(JobIndex { spans })
+    }
+
+    /// Returns the periods of the log in order.
+    pub fn spans(&self) -> &[JobSpan] {
+        &self.spans
+    }
+
+    /// Returns the metadata applying to an entry with the given timestamp, if any.
+    pub fn at(&self, timestamp_us: u64) -> Option<&MissionMetadata> {
+        self.spans
+            .iter()
+            .rev()
+            .find(|span| span.contains(timestamp_us))
+            .map(|span| &span.metadata)
+    }
+
+    /// Returns the periods whose metadata matches a predicate, such as a job or operator id.
+    pub fn find<'a, F>(&'a self, predicate: F) -> impl Iterator<Item = &'a JobSpan>
+    where
+        F: Fn(&MissionMetadata) -> bool + 'a,
+    {
+        self.spans
+            .iter()
+            .filter(move |span| predicate(&span.metadata))
+    }
+
+    /// Returns the periods of a job.
+    pub fn job<'a>(&'a self, job_id: &'a str) -> impl Iterator<Item = &'a JobSpan> {
+        self.find(move |metadata| metadata.job_id.as_deref() == Some(job_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the metadata of a job applying two products.
+    fn metadata(job_id: &str) -> MissionMetadata {
+        MissionMetadata {
+            operator_id: Some(String::from("P-104")),
+            field_id: Some(String::from("north 12")),
+            job_id: Some(job_id.to_string()),
+            products: vec![
+                ProductBatch {
+                    product: String::from("GLY-360"),
+                    batch: String::from("L2291"),
+                },
+                ProductBatch {
+                    product: String::from("adjuvant"),
+                    batch: String::new(),
+                },
+            ],
+            attributes: BTreeMap::from([(String::from("permit"), String::from("EX-7"))]),
+        }
+    }
+
+    /// Test that metadata round trips through an entry payload.
+    #[test]
+    fn test_payload_round_trip() {
+        let metadata = metadata("J-31");
+        let payload = metadata.to_payload();
+        assert_eq!(
+            MissionMetadata::from_payload(&payload),
+            Some(metadata.clone())
+        );
+        assert_eq!(
+            metadata.to_string(),
+            r#"operator="P-104" field="north 12" job="J-31" product="GLY-360" batch="L2291" product="adjuvant" batch="" permit="EX-7""#
+        );
+        assert_eq!(
+            MissionMetadata::from_payload(&[]),
+            Some(MissionMetadata::default())
+        );
+        assert_eq!(
+            MissionMetadata::from_payload(&payload[..payload.len() - 1]),
+            None
+        );
+    }
+
+    /// Test that entries are attributed to the job recorded before them.
+    #[cfg(feature = "parser")]
+    #[test]
+    fn test_job_index() {
+        use std::collections::VecDeque;
+
+        use mavlink::common::MavMessage;
+
+        use crate::mav_parser::{EntryList, LogEntry};
+
+        let entry = |timestamp: u64, job_id: Option<&str>| LogEntry::<MavMessage> {
+            timestamp: Some(timestamp),
+            metadata: job_id.map(metadata),
+            text: Some(String::from("telemetry")),
+            ..Default::default()
+        };
+        let entries = VecDeque::from([
+            entry(0, None),
+            entry(1_000, Some("J-31")),
+            entry(2_000, None),
+            entry(3_000, Some("J-32")),
+            entry(4_000, None),
+            entry(5_000, Some("J-31")),
+            entry(6_000, None),
+        ]);
+        let index = JobIndex::build(&mut EntryList(entries)).unwrap();
+        let bounds: Vec<(Option<u64>, Option<u64>)> = index
+            .spans()
+            .iter()
+            .map(|span| (span.start_us, span.end_us))
+            .collect();
+        assert_eq!(
+            bounds,
+            vec![
+                (Some(1_000), Some(3_000)),
+                (Some(3_000), Some(5_000)),
+                (Some(5_000), Some(6_001)),
+            ]
+        );
+        assert_eq!(index.at(0), None);
+        assert_eq!(index.at(2_999).unwrap().job_id.as_deref(), Some("J-31"));
+        assert_eq!(index.at(3_000).unwrap().job_id.as_deref(), Some("J-32"));
+        assert_eq!(index.at(6_000).unwrap().job_id.as_deref(), Some("J-31"));
+        assert_eq!(index.at(6_001), None);
+        assert_eq!(index.job("J-31").count(), 2);
+        assert_eq!(
+            index
+                .find(|metadata| metadata.operator_id.as_deref() == Some("P-104"))
+                .count(),
+            3
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Detection of log formats and opening of log files without knowing their format in advance.
+//!
+//! The format is determined from the content of the file rather than its extension so that
+//! tools can accept any supported log.
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use mavlink::Message;
+
+use crate::frame;
+use crate::mav_parser::MavParser;
+#[cfg(feature = "mavlog")]
+use crate::mavlog::compat;
+#[cfg(feature = "mavlog")]
+use crate::mavlog::header::FileHeader;
+#[cfg(feature = "mavlog")]
+use crate::mavlog::parser::MavLogParser;
+#[cfg(feature = "tlog")]
+use crate::tlog::parser::TlogParser;
+
+/// Number of bytes from the start of a log needed to reliably detect its format. This covers a
+/// .mav file header and the two largest possible TLOG records.
+pub const DETECT_SIZE: usize = 576;
+/// Size of the timestamp preceding each frame in a TLOG file.
+const TLOG_TIMESTAMP_SIZE: usize = 8;
+/// Offset of the source application id in a .mav file header.
+const MAVLOG_APPLICATION_ID_OFFSET: usize = 24;
+/// Offset of the format version in a .mav file header.
+const MAVLOG_VERSION_OFFSET: usize = 56;
+/// Offset of the UUID byte holding the variant in a .mav file header.
+const MAVLOG_UUID_VARIANT_OFFSET: usize = 8;
+/// Magic number starting a ULog file, followed by the ULog version.
+const ULOG_MAGIC: [u8; 7] = [0x55, 0x4c, 0x6f, 0x67, 0x01, 0x12, 0x35];
+
+/// Compression formats recognized by `detect_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+    Xz,
+    Bzip2,
+    Lz4,
+}
+
+impl Compression {
+    /// Magic numbers identifying each compression format.
+    const MAGICS: [(Compression, &'static [u8]); 5] = [
+        (Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+        (Compression::Gzip, &[0x1f, 0x8b]),
+        (Compression::Xz, &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]),
+        (Compression::Bzip2, b"BZh"),
+        (Compression::Lz4, &[0x04, 0x22, 0x4d, 0x18]),
+    ];
+}
+
+/// Log format detected from the first bytes of a log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedFormat {
+    /// A .mav log with the given format version, which may not be supported, see
+    /// `mavlog::compat::is_supported`.
+    MavLog { version: u32 },
+    /// A TLOG of timestamped MAVLink frames.
+    Tlog,
+    /// A PX4 ULog.
+    ULog,
+    /// Data compressed as a whole, which must be decompressed before its format can be detected.
+    Compressed(Compression),
+    /// The format is not recognized, possibly because too few bytes were provided.
+    Unknown,
+}
+
+/// Detects the format of a log from its first bytes.
+///
+/// Works on any byte stream, not only files, so that data can be routed without relying on a
+/// file extension. A .mav log is recognized by the structure of its file header. A TLOG is
+/// recognized by a MAVLink frame following the first timestamp, and by the frame after it if
+/// enough bytes are available. The two cannot be confused since the byte of a .mav header UUID
+/// where a TLOG frame would start holds the UUID variant, which never matches a MAVLink magic
+/// byte.
+///
+/// # Arguments
+/// - `bytes`: The first bytes of the log, at least `DETECT_SIZE` bytes for a reliable result
+///   unless the log is shorter.
+///
+/// # Returns
+/// The detected format, `DetectedFormat::Unknown` if it is not recognized.
+pub fn detect_format(bytes: &[u8]) -> DetectedFormat {
+    if let Some(version) = mavlog_version(bytes) {
+        return DetectedFormat::MavLog { version };
+    }
+    if bytes.starts_with(&ULOG_MAGIC) {
+        return DetectedFormat::ULog;
+    }
+    for (compression, magic) in Compression::MAGICS {
+        if bytes.starts_with(magic) {
+            return DetectedFormat::Compressed(compression);
+        }
+    }
+    if is_tlog(bytes) {
+        return DetectedFormat::Tlog;
+    }
+    DetectedFormat::Unknown
+}
+
+/// Checks whether bytes look like the start of a .mav log.
+///
+/// The fields expected to stay the same across format versions are always checked: the UUID
+/// variant, a null padded UTF-8 source application id and a non zero format version. The header
+/// is also checked in full when its format version is supported.
+///
+/// # Returns
+/// The format version of the log, or `None` if the bytes are not the start of a .mav log.
+fn mavlog_version(bytes: &[u8]) -> Option<u32> {
+    let version_bytes = bytes.get(MAVLOG_VERSION_OFFSET..MAVLOG_VERSION_OFFSET + 4)?;
+    let version = u32::from_le_bytes(version_bytes.try_into().unwrap());
+    let application_id = &bytes[MAVLOG_APPLICATION_ID_OFFSET..MAVLOG_VERSION_OFFSET];
+    let end = application_id
+        .iter()
+        .position(|&x| x == 0)
+        .unwrap_or(application_id.len());
+    let valid = bytes[MAVLOG_UUID_VARIANT_OFFSET] & 0xc0 == 0x80
+        && version != 0
+        && std::str::from_utf8(&application_id[..end]).is_ok()
+        && application_id[end..].iter().all(|&x| x == 0);
+    if !valid {
+        return None;
+    }
+    #[cfg(feature = "mavlog")]
+    if let Some(packed) = bytes
+        .get(..FileHeader::MIN_SIZE)
+        .filter(|_| compat::is_supported(version))
+    {
+        let valid = compat::try_unpack_header(packed.try_into().unwrap()).is_ok();
+        return valid.then_some(version);
+    }
+    Some(version)
+}
+
+/// Checks whether bytes look like the start of a TLOG file.
+fn is_tlog(bytes: &[u8]) -> bool {
+    let mut offset = 0;
+    // Check up to two records, the second only if it is within the available bytes
+    for record in 0..2 {
+        let start = offset + TLOG_TIMESTAMP_SIZE;
+        let frame_len = match bytes.get(start..start + frame::LENGTH_PEEK_SIZE) {
+            Some(peek) => frame::frame_len(peek),
+            None => return record > 0,
+        };
+        match frame_len {
+            Some(frame_len) => offset = start + frame_len,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Opens a log file of any supported format.
+///
+/// The format is determined from the content of the file with `detect_format`: .mav logs,
+/// including chunked logs, and TLOG files are supported.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to parse messages with.
+///
+/// # Arguments
+/// - `path`: Path of the log file.
+///
+/// # Returns
+/// A parser for the log file.
+///
+/// # Errors
+/// Returns an `io::Error` if the file could not be read or opened by the parser of its format.
+/// The error is of kind `Unsupported` if the file is compressed, is a ULog or if the feature
+/// supporting its format is not enabled, and of kind `InvalidData` if the format is not
+/// recognized.
+pub fn open<M: Message + 'static>(path: &str) -> std::io::Result<Box<dyn MavParser<M = M>>> {
+    match detect_file_format(Path::new(path))? {
+        DetectedFormat::MavLog { .. } => open_mavlog(path),
+        DetectedFormat::Tlog => open_tlog(path),
+        DetectedFormat::ULog => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "ULog files are not supported",
+        )),
+        DetectedFormat::Compressed(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Compressed files must be decompressed before they can be parsed",
+        )),
+        DetectedFormat::Unknown => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Unrecognized log file format",
+        )),
+    }
+}
+
+/// Detects the format of a log file from its first bytes, see `detect_format`.
+pub(crate) fn detect_file_format(path: &Path) -> std::io::Result<DetectedFormat> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(DETECT_SIZE);
+    File::open(path)?
+        .take(DETECT_SIZE as u64)
+        .read_to_end(&mut bytes)?;
+    Ok(detect_format(&bytes))
+}
+
+/// Lists the files of a directory tree.
+pub(crate) fn list_files(directory: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "mavlog")]
+fn open_mavlog<M: Message + 'static>(path: &str) -> std::io::Result<Box<dyn MavParser<M = M>>> {
+    Ok(Box::new(MavLogParser::<M>::try_new(path)?))
+}
+
+#[cfg(not(feature = "mavlog"))]
+fn open_mavlog<M: Message + 'static>(_path: &str) -> std::io::Result<Box<dyn MavParser<M = M>>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        ".mav files require the mavlog feature",
+    ))
+}
+
+#[cfg(feature = "tlog")]
+fn open_tlog<M: Message + 'static>(path: &str) -> std::io::Result<Box<dyn MavParser<M = M>>> {
+    Ok(Box::new(TlogParser::<M>::new(path)))
+}
+
+#[cfg(not(feature = "tlog"))]
+fn open_tlog<M: Message + 'static>(_path: &str) -> std::io::Result<Box<dyn MavParser<M = M>>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "TLOG files require the tlog feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the start of a .mav file header with the given format version.
+    fn mavlog_header(version: u32) -> Vec<u8> {
+        let mut bytes = vec![0u8; 108];
+        bytes[MAVLOG_UUID_VARIANT_OFFSET] = 0x9a;
+        bytes[24..27].copy_from_slice(b"app");
+        bytes[MAVLOG_VERSION_OFFSET..MAVLOG_VERSION_OFFSET + 4]
+            .copy_from_slice(&version.to_le_bytes());
+        bytes
+    }
+
+    /// Test that .mav headers of any format version are detected.
+    #[test]
+    fn test_detect_mavlog() {
+        assert_eq!(
+            detect_format(&mavlog_header(2)),
+            DetectedFormat::MavLog { version: 2 }
+        );
+        assert_eq!(detect_format(&mavlog_header(0)), DetectedFormat::Unknown);
+        let mut invalid_application_id = mavlog_header(2);
+        invalid_application_id[30] = b'x';
+        assert_eq!(
+            detect_format(&invalid_application_id),
+            DetectedFormat::Unknown
+        );
+        let mut invalid_uuid = mavlog_header(2);
+        invalid_uuid[MAVLOG_UUID_VARIANT_OFFSET] = 0x00;
+        assert_eq!(detect_format(&invalid_uuid), DetectedFormat::Unknown);
+        assert_eq!(
+            detect_format(&mavlog_header(1)),
+            DetectedFormat::MavLog { version: 1 }
+        );
+        assert_eq!(
+            detect_format(&mavlog_header(7)),
+            DetectedFormat::MavLog { version: 7 }
+        );
+    }
+
+    /// Test that TLOG records are detected.
+    #[test]
+    fn test_detect_tlog() {
+        // MAVLink 1 HEARTBEAT frame
+        let frame: [u8; 17] = [
+            0xfe, 9, 0, 1, 1, 0, 0, 0, 0, 0, 2, 3, 0x51, 4, 3, 0x1c, 0x7f,
+        ];
+        let mut bytes: Vec<u8> = Vec::new();
+        for timestamp in 0..2u64 {
+            bytes.extend_from_slice(&timestamp.to_be_bytes());
+            bytes.extend_from_slice(&frame);
+        }
+        assert_eq!(detect_format(&bytes), DetectedFormat::Tlog);
+        // the second record does not need to be complete
+        assert_eq!(detect_format(&bytes[..27]), DetectedFormat::Tlog);
+        bytes[25 + 8] = 0x00;
+        assert_eq!(detect_format(&bytes), DetectedFormat::Unknown);
+        assert_eq!(detect_format(&bytes[..8]), DetectedFormat::Unknown);
+    }
+
+    /// Test that ULog and compressed data are detected by their magic numbers.
+    #[test]
+    fn test_detect_magic() {
+        assert_eq!(
+            detect_format(&[0x55, 0x4c, 0x6f, 0x67, 0x01, 0x12, 0x35, 0x01]),
+            DetectedFormat::ULog
+        );
+        assert_eq!(
+            detect_format(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            DetectedFormat::Compressed(Compression::Zstd)
+        );
+        assert_eq!(
+            detect_format(&[0x1f, 0x8b, 0x08]),
+            DetectedFormat::Compressed(Compression::Gzip)
+        );
+        assert_eq!(
+            detect_format(b"BZh91AY&SY"),
+            DetectedFormat::Compressed(Compression::Bzip2)
+        );
+        assert_eq!(detect_format(b"plain text"), DetectedFormat::Unknown);
+        assert_eq!(detect_format(&[]), DetectedFormat::Unknown);
+    }
+}
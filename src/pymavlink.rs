@@ -0,0 +1,443 @@
+//! Regression comparison of this crate against pymavlink.
+//!
+//! Tooling migrated from Python to this crate must decode logs exactly as before. `compare_tlog`
+//! parses a tlog with `TlogParser` and with pymavlink's `mavlogdump.py`, run as a subprocess,
+//! then compares how many messages of each type both decoded and the values of their fields,
+//! pairing the n-th message of a type decoded by one with the n-th decoded by the other. The
+//! time each took is reported as well.
+//!
+//! Messages decoded by this crate are converted with `json::to_json`, so fields holding enums or
+//! bit flags, which mavlogdump reports as plain numbers, cannot be compared and are counted as
+//! skipped. The `type` field of pymavlink messages is named `mavtype` by the mavlink crate.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use mavlink::Message;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::json::to_json;
+use crate::mav_parser::for_each_entry;
+use crate::tlog::parser::TlogParser;
+
+/// Settings of a comparison with pymavlink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PymavlinkConfig {
+    /// The program running mavlogdump followed by its first arguments, such as
+    /// `["python3", "-m", "pymavlink.tools.mavlogdump"]`.
+    pub command: Vec<String>,
+    /// The pymavlink dialect to decode with, matching the dialect of the crate parser.
+    pub dialect: String,
+    /// Relative tolerance of numeric comparisons. Floats are compared as decoded by the
+    /// mavlink crate, in single precision, while pymavlink reports them in double precision.
+    pub tolerance: f64,
+    /// The largest number of mismatched fields reported. Further mismatches are only counted.
+    pub max_mismatches: usize,
+}
+
+impl Default for PymavlinkConfig {
+    /// Runs `mavlogdump.py` from the `PATH` with the ardupilotmega dialect, compares numbers
+    /// with a relative tolerance of 1e-6 and reports up to 100 mismatches.
+    fn default() -> Self {
+        Self {
+            command: vec![String::from("mavlogdump.py")],
+            dialect: String::from("ardupilotmega"),
+            tolerance: 1e-6,
+            max_mismatches: 100,
+        }
+    }
+}
+
+/// A field decoded differently by this crate and by pymavlink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldMismatch {
+    /// Name of the message.
+    pub message: String,
+    /// Position of the message among the messages of its type, starting at 0.
+    pub index: u64,
+    /// Name of the field as reported by pymavlink.
+    pub field: String,
+    /// The value decoded by this crate, as JSON.
+    pub crate_value: String,
+    /// The value decoded by pymavlink, as JSON.
+    pub pymavlink_value: String,
+}
+
+/// Outcome of the comparison of a log decoded by this crate and by pymavlink.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ComparisonReport {
+    /// Number of messages decoded by this crate by message name.
+    pub crate_counts: BTreeMap<String, u64>,
+    /// Number of messages decoded by pymavlink by message name.
+    pub pymavlink_counts: BTreeMap<String, u64>,
+    /// Number of fields decoded by both and compared.
+    pub compared_fields: u64,
+    /// Number of fields that could not be compared, such as enums or fields only one decoded.
+    pub skipped_fields: u64,
+    /// Number of compared fields with different values.
+    pub mismatched_fields: u64,
+    /// The first mismatched fields, see `PymavlinkConfig::max_mismatches`.
+    pub mismatches: Vec<FieldMismatch>,
+    /// Time this crate took to decode the log and convert its messages to JSON.
+    pub crate_duration: Duration,
+    /// Time mavlogdump took to decode the log, including the start of the interpreter.
+    pub pymavlink_duration: Duration,
+}
+
+impl ComparisonReport {
+    /// Returns the message names decoded a different number of times, with the number of
+    /// messages decoded by this crate and by pymavlink.
+    pub fn count_differences(&self) -> Vec<(String, u64, u64)> {
+        let mut names: Vec<&String> = self
+            .crate_counts
+            .keys()
+            .chain(self.pymavlink_counts.keys())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let ours = self.crate_counts.get(name).copied().unwrap_or(0);
+                let theirs = self.pymavlink_counts.get(name).copied().unwrap_or(0);
+                (ours != theirs).then(|| (name.clone(), ours, theirs))
+            })
+            .collect()
+    }
+
+    /// Returns whether both decoded the same messages with the same field values.
+    pub fn is_match(&self) -> bool {
+        self.mismatched_fields == 0 && self.count_differences().is_empty()
+    }
+}
+
+/// Formats the message totals and timings, the count differences and the reported mismatches,
+/// one per line.
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "messages: crate {} in {:.3} s, pymavlink {} in {:.3} s",
+            self.crate_counts.values().sum::<u64>(),
+            self.crate_duration.as_secs_f64(),
+            self.pymavlink_counts.values().sum::<u64>(),
+            self.pymavlink_duration.as_secs_f64()
+        )?;
+        for (name, ours, theirs) in self.count_differences() {
+            writeln!(f, "count {name}: crate {ours}, pymavlink {theirs}")?;
+        }
+        writeln!(
+            f,
+            "fields: {} compared, {} skipped, {} mismatched",
+            self.compared_fields, self.skipped_fields, self.mismatched_fields
+        )?;
+        for mismatch in &self.mismatches {
+            writeln!(
+                f,
+                "mismatch {} #{} {}: crate {}, pymavlink {}",
+                mismatch.message,
+                mismatch.index,
+                mismatch.field,
+                mismatch.crate_value,
+                mismatch.pymavlink_value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a tlog with this crate and with mavlogdump and compares the results.
+///
+/// Both decoded logs are held in memory while they are compared.
+///
+/// # Type Parameters
+/// - `M`: The dialect to decode with, matching `PymavlinkConfig::dialect`.
+///
+/// # Arguments
+/// - `path`: Path of the tlog.
+/// - `config`: Settings of the comparison.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read, mavlogdump could not be run or failed,
+/// or its output is not valid JSON.
+pub fn compare_tlog<M: Message + Serialize>(
+    path: &str,
+    config: &PymavlinkConfig,
+) -> std::io::Result<ComparisonReport> {
+    // the parser panics on files it cannot open
+    std::fs::File::open(path)?;
+    let started = Instant::now();
+    let mut ours: Vec<Value> = Vec::new();
+    let mut parser = TlogParser::<M>::new(path);
+    for_each_entry(&mut parser, |entry| {
+        ours.extend(to_json(&entry));
+        Ok(())
+    })?;
+    let crate_duration = started.elapsed();
+
+    let started = Instant::now();
+    let theirs = run_mavlogdump(path, config)?;
+    let pymavlink_duration = started.elapsed();
+
+    let mut report = compare_messages(&ours, &theirs, config);
+    report.crate_duration = crate_duration;
+    report.pymavlink_duration = pymavlink_duration;
+    Ok(report)
+}
+
+/// Runs mavlogdump on a log and reads the messages it decoded.
+///
+/// # Arguments
+/// - `path`: Path of the log.
+/// - `config`: Settings holding the command and dialect.
+///
+/// # Returns
+/// One JSON object per message, holding its `meta` and `data` as written by mavlogdump.
+///
+/// # Errors
+/// Returns an `io::Error` if mavlogdump could not be run, exited with an error, or wrote a line
+/// that is not valid JSON.
+pub fn run_mavlogdump(path: &str, config: &PymavlinkConfig) -> std::io::Result<Vec<Value>> {
+    let (program, args) = config.command.split_first().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "Empty mavlogdump command")
+    })?;
+    let mut child = Command::new(program)
+        .args(args)
+        .args(["--format", "json", "--show-source", "--no-bad-data"])
+        .args(["--dialect", &config.dialect])
+        .arg(path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut messages: Vec<Value> = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            let line = line?;
+            // mavlogdump writes NaN and infinite floats as bare words, which JSON does not allow
+            let message: Value = serde_json::from_str(&replace_non_finite(&line))?;
+            if message.get("meta").is_some() {
+                messages.push(message);
+            }
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!(
+            "mavlogdump failed: {status}"
+        )));
+    }
+    Ok(messages)
+}
+
+/// Compares messages decoded by this crate with messages decoded by mavlogdump.
+///
+/// # Arguments
+/// - `ours`: The messages decoded by this crate, as converted by `json::to_json`.
+/// - `theirs`: The messages decoded by mavlogdump, see `run_mavlogdump`.
+/// - `config`: Settings of the comparison.
+///
+/// # Returns
+/// The report of the comparison, without timings.
+pub fn compare_messages(
+    ours: &[Value],
+    theirs: &[Value],
+    config: &PymavlinkConfig,
+) -> ComparisonReport {
+    let mut report = ComparisonReport::default();
+    let mut ours_by_name: BTreeMap<String, Vec<&Value>> = BTreeMap::new();
+    for message in ours.iter().filter_map(|object| object.get("message")) {
+        let name = message["type"].as_str().unwrap_or_default().to_string();
+        *report.crate_counts.entry(name.clone()).or_default() += 1;
+        ours_by_name.entry(name).or_default().push(message);
+    }
+    for object in theirs {
+        let name = object["meta"]["type"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let index = report.pymavlink_counts.entry(name.clone()).or_default();
+        let position = *index;
+        *index += 1;
+        let Some(message) = ours_by_name
+            .get(&name)
+            .and_then(|messages| messages.get(position as usize))
+        else {
+            continue;
+        };
+        let Some(fields) = object["data"].as_object() else {
+            continue;
+        };
+        for (field, their_value) in fields {
+            // the mavlink crate renames fields that are Rust keywords
+            let our_field = if field == "type" { "mavtype" } else { field };
+            let verdict = message
+                .get(our_field)
+                .and_then(|our_value| values_match(our_value, their_value, config.tolerance));
+            match verdict {
+                None => report.skipped_fields += 1,
+                Some(true) => report.compared_fields += 1,
+                Some(false) => {
+                    report.compared_fields += 1;
+                    report.mismatched_fields += 1;
+                    if report.mismatches.len() < config.max_mismatches {
+                        report.mismatches.push(FieldMismatch {
+                            message: name.clone(),
+                            index: position,
+                            field: field.clone(),
+                            crate_value: message[our_field].to_string(),
+                            pymavlink_value: their_value.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    report
+}
+
+/// Compares a field value decoded by this crate with the value decoded by pymavlink.
+///
+/// # Returns
+/// Whether the values match, or `None` if they cannot be compared, such as an enum decoded by
+/// this crate as its name.
+fn values_match(ours: &Value, theirs: &Value, tolerance: f64) -> Option<bool> {
+    match (ours, theirs) {
+        (Value::Number(ours), Value::Number(theirs)) => {
+            let (ours, theirs) = (ours.as_f64()?, theirs.as_f64()?);
+            let scale = ours.abs().max(theirs.abs()).max(1.0);
+            Some((ours - theirs).abs() <= tolerance * scale)
+        }
+        // non finite floats are written as null by both
+        (Value::Null, Value::Null) => Some(true),
+        (Value::Null, Value::Number(_)) | (Value::Number(_), Value::Null) => Some(false),
+        (Value::String(ours), Value::String(theirs)) => Some(ours == theirs),
+        // char arrays are decoded as bytes by this crate and as strings by pymavlink
+        (Value::Array(bytes), Value::String(theirs)) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .map(|byte| byte.as_u64().map(|byte| byte as u8))
+                .collect::<Option<_>>()?;
+            let end = bytes
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(bytes.len());
+            Some(String::from_utf8_lossy(&bytes[..end]) == theirs.trim_end_matches('\0'))
+        }
+        (Value::Array(ours), Value::Array(theirs)) => {
+            if ours.len() != theirs.len() {
+                return Some(false);
+            }
+            let mut matched = true;
+            for (ours, theirs) in ours.iter().zip(theirs) {
+                matched &= values_match(ours, theirs, tolerance)?;
+            }
+            Some(matched)
+        }
+        _ => None,
+    }
+}
+
+/// Replaces the `NaN`, `Infinity` and `-Infinity` words written by Python outside of strings
+/// with `null`.
+fn replace_non_finite(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if !in_string {
+            let word = ["-Infinity", "Infinity", "NaN"]
+                .into_iter()
+                .find(|word| rest.starts_with(word));
+            if let Some(word) = word {
+                out.push_str("null");
+                rest = &rest[word.len()..];
+                continue;
+            }
+        }
+        if in_string && escaped {
+            escaped = false;
+        } else if in_string && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_string = !in_string;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Test that numbers, char arrays and keyword fields are compared and enums skipped.
+    #[test]
+    fn test_compare_messages() {
+        let ours = vec![
+            json!({"header": {}, "message": {
+                "type": "HEARTBEAT",
+                "custom_mode": 4,
+                "mavtype": {"type": "MAV_TYPE_QUADROTOR"},
+            }}),
+            json!({"header": {}, "message": {
+                "type": "PARAM_VALUE",
+                "param_id": [87, 80, 78, 65, 86, 95, 83, 80, 69, 69, 68, 0, 0, 0, 0, 0],
+                "param_value": 0.1,
+                "param_count": 900,
+            }}),
+            json!({"header": {}, "message": {"type": "HEARTBEAT", "custom_mode": 5}}),
+        ];
+        let theirs = vec![
+            json!({"meta": {"type": "HEARTBEAT"}, "data": {"custom_mode": 4, "type": 2}}),
+            json!({"meta": {"type": "PARAM_VALUE"}, "data": {
+                "param_id": "WPNAV_SPEED",
+                "param_value": 0.10000000149011612,
+                "param_count": 901,
+            }}),
+            json!({"meta": {"type": "HEARTBEAT"}, "data": {"custom_mode": 5}}),
+            json!({"meta": {"type": "HEARTBEAT"}, "data": {"custom_mode": 6}}),
+        ];
+        let report = compare_messages(&ours, &theirs, &PymavlinkConfig::default());
+        assert_eq!(report.compared_fields, 5);
+        assert_eq!(report.skipped_fields, 1);
+        assert_eq!(report.mismatched_fields, 1);
+        assert_eq!(
+            report.mismatches,
+            vec![FieldMismatch {
+                message: String::from("PARAM_VALUE"),
+                index: 0,
+                field: String::from("param_count"),
+                crate_value: String::from("900"),
+                pymavlink_value: String::from("901"),
+            }]
+        );
+        assert_eq!(
+            report.count_differences(),
+            vec![(String::from("HEARTBEAT"), 2, 3)]
+        );
+        assert!(!report.is_match());
+        assert!(
+            report
+                .to_string()
+                .contains("count HEARTBEAT: crate 2, pymavlink 3\n")
+        );
+    }
+
+    /// Test that non finite floats written by Python are read as null, leaving strings as is.
+    #[test]
+    fn test_replace_non_finite() {
+        let line = r#"{"a": NaN, "b": [-Infinity, Infinity], "c": "NaN \"Infinity\""}"#;
+        let value: Value = serde_json::from_str(&replace_non_finite(line)).unwrap();
+        assert_eq!(
+            value,
+            json!({"a": null, "b": [null, null], "c": "NaN \"Infinity\""})
+        );
+    }
+}
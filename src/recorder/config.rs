@@ -0,0 +1,703 @@
+//! Recorders built from a declarative TOML or YAML config file.
+//!
+//! A `PipelineConfig` describes every source to record: the connection address, the filter and
+//! per message rate limits selecting what is written, the rotation and compression of the log,
+//! and an optional Prometheus metrics address. Deployments on a companion computer can then be
+//! changed by editing the file rather than recompiling the recording binary.
+//!
+//! ```toml
+//! [[sources]]
+//! address = "udpin:0.0.0.0:14550"
+//! reconnect_delay_ms = 500
+//! filter = "sysid == 1 && msg != 'PARAM_VALUE'"
+//! metrics_address = "0.0.0.0:9100"
+//!
+//! [sources.rate_limits]
+//! ATTITUDE = { max_hz = 10.0 }
+//! RAW_IMU = { every_nth = 5 }
+//!
+//! [sources.log]
+//! path = "/data/flight.mav"
+//! max_bytes = 50000000
+//! backup_count = 20
+//! sequence = true
+//!
+//! [sources.log.compression]
+//! chunked = true
+//! block_size = 65536
+//! ```
+//!
+//! Unknown keys are rejected so that a misspelled setting is not silently ignored. Uploading
+//! finished logs is not part of this crate and is left to the deployment.
+//!
+//! The filter, rate limits and reconnect delay of running recorders can be changed by
+//! reloading the config with `apply_reload`, or by sending SIGHUP to `record_from_config`.
+//! Rotation and format settings are fixed when the log file is created, so changing them
+//! requires a restart.
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use mavlink::Message;
+use serde::Deserialize;
+
+use super::metrics::MetricsServer;
+use super::{LiveSettings, Recorder, ReloadHandle};
+use crate::analysis::decimate::Rate;
+use crate::analysis::filter::ParserFilter;
+use crate::mavlog::header::FormatFlags;
+use crate::mavlog::logger::RotatingMavLogger;
+
+/// Interval at which `record_from_config` checks for a reload request.
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Description of every source to record.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    /// The sources to record, each to its own log.
+    #[serde(default)]
+    pub sources: Vec<SourceConfig>,
+}
+
+/// Description of a single recorded connection.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    /// The mavlink crate address string of the connection to record.
+    pub address: String,
+    /// Time to wait between reconnection attempts in milliseconds.
+    #[serde(default = "default_reconnect_delay_ms")]
+    pub reconnect_delay_ms: u64,
+    /// Optional `ParserFilter` expression selecting the frames to record.
+    #[serde(default)]
+    pub filter: Option<String>,
+    /// Rate limits by message name or numeric message id.
+    #[serde(default)]
+    pub rate_limits: BTreeMap<String, RateLimit>,
+    /// Optional address to serve Prometheus metrics on, such as `0.0.0.0:9100`.
+    #[serde(default)]
+    pub metrics_address: Option<String>,
+    /// The log the frames are written to.
+    pub log: LogConfig,
+}
+
+/// Rate limit of a single message, with exactly one of its fields set.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// Record at most the given number of messages per second.
+    #[serde(default)]
+    pub max_hz: Option<f64>,
+    /// Record the first of every N messages.
+    #[serde(default)]
+    pub every_nth: Option<u32>,
+}
+
+/// Rotation and format of a recorded log.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// The base path for the log files. Parent directories are expected to exist.
+    pub path: String,
+    /// The maximum size of a log file before it is rotated.
+    pub max_bytes: u64,
+    /// The number of backup files to keep.
+    pub backup_count: usize,
+    /// Only log MAVLink messages, see `FormatFlags::mavlink_only`.
+    #[serde(default)]
+    pub mavlink_only: bool,
+    /// Leave out entry timestamps, see `FormatFlags::no_timestamp`.
+    #[serde(default)]
+    pub no_timestamp: bool,
+    /// Number entries, see `FormatFlags::sequence`.
+    #[serde(default)]
+    pub sequence: bool,
+    /// Chain entry hashes, see `FormatFlags::hash_chain`.
+    #[serde(default)]
+    pub hash_chain: bool,
+    /// Store 32-bit payload sizes, see `FormatFlags::large_entries`.
+    #[serde(default)]
+    pub large_entries: bool,
+    /// Optional compression of the log. Requires the compression feature.
+    #[serde(default)]
+    pub compression: Option<CompressionConfig>,
+}
+
+/// Compression of a recorded log.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompressionConfig {
+    /// Group entries into independently compressed blocks, see `FormatFlags::chunked`.
+    #[serde(default)]
+    pub chunked: bool,
+    /// Uncompressed size of the blocks of a chunked log in bytes.
+    #[serde(default)]
+    pub block_size: Option<usize>,
+    /// Path of a zstd dictionary to compress entry payloads with, as stored by
+    /// `dictionary::save_dictionary` so that parsers can find it.
+    #[serde(default)]
+    pub dictionary: Option<String>,
+    /// The zstd compression level used with the dictionary.
+    #[serde(default)]
+    pub level: Option<i32>,
+}
+
+/// A recorder built from a config along with the servers exposing it.
+pub struct Pipeline<M: Message> {
+    /// The recorder of the source, ready to `run`.
+    pub recorder: Recorder<M>,
+    /// The metrics server of the recorder, if a metrics address is configured. Dropping it
+    /// stops serving the metrics.
+    pub metrics_server: Option<MetricsServer>,
+}
+
+fn default_reconnect_delay_ms() -> u64 {
+    1000
+}
+
+/// Builds an `InvalidInput` error describing an invalid config.
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+impl PipelineConfig {
+    /// Parses a config from TOML.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The TOML document.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the config or an `io::Error` of kind `InvalidData` describing why
+    /// the document does not describe a config.
+    pub fn from_toml(text: &str) -> std::io::Result<Self> {
+        toml::from_str(text)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Parses a config from YAML.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The YAML document.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the config or an `io::Error` of kind `InvalidData` describing why
+    /// the document does not describe a config.
+    pub fn from_yaml(text: &str) -> std::io::Result<Self> {
+        serde_yaml::from_str(text)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Reads a config file, parsed as TOML or YAML according to its extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of a `.toml`, `.yaml` or `.yml` file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the config or an `io::Error` if the file could not be read or
+    /// parsed. An error of kind `InvalidInput` is returned for other extensions.
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        match extension {
+            "toml" => Self::from_toml(&std::fs::read_to_string(path)?),
+            "yaml" | "yml" => Self::from_yaml(&std::fs::read_to_string(path)?),
+            _ => Err(invalid(format!(
+                "Config files must have a toml, yaml or yml extension: {path}"
+            ))),
+        }
+    }
+
+    /// Builds the recorders of every source.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a pipeline per source, in the order of the config, or the error
+    /// of the first source that could not be built.
+    pub fn build<M: Message>(&self) -> std::io::Result<Vec<Pipeline<M>>> {
+        self.sources.iter().map(SourceConfig::build).collect()
+    }
+}
+
+impl SourceConfig {
+    /// Builds the recorder of the source and starts its metrics server.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the pipeline or an `io::Error`. An error of kind `InvalidInput` is
+    /// returned for an invalid filter expression, rate limit or unknown message name, and the
+    /// errors of `RotatingMavLogger` if the log could not be created.
+    pub fn build<M: Message>(&self) -> std::io::Result<Pipeline<M>> {
+        let LiveSettings {
+            filter,
+            rate_limits,
+            reconnect_delay,
+        } = self.live_settings::<M>()?;
+        let logger = self.log.build()?;
+        let mut recorder = Recorder::with_logger(&self.address, logger, reconnect_delay);
+        if let Some(filter) = filter {
+            recorder.set_filter(filter);
+        }
+        if !rate_limits.is_empty() {
+            recorder.set_rate_limits(rate_limits);
+        }
+        let metrics_server = match &self.metrics_address {
+            Some(address) => Some(recorder.metrics().serve(address)?),
+            None => None,
+        };
+        Ok(Pipeline {
+            recorder,
+            metrics_server,
+        })
+    }
+
+    /// Returns the settings of the source that can be replaced while recording.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the settings or an `io::Error` of kind `InvalidInput` for an
+    /// invalid filter expression, rate limit or unknown message name.
+    pub fn live_settings<M: Message>(&self) -> std::io::Result<LiveSettings> {
+        let filter = match &self.filter {
+            Some(expr) => Some(ParserFilter::from_expr(expr).map_err(|e| {
+                invalid(format!("Invalid filter at {}: {}", e.position, e.message))
+            })?),
+            None => None,
+        };
+        let mut rates: BTreeMap<u32, Rate> = BTreeMap::new();
+        for (name, limit) in &self.rate_limits {
+            let msg_id = match name.parse::<u32>() {
+                Ok(msg_id) => msg_id,
+                Err(_) => M::message_id_from_name(name)
+                    .map_err(|_| invalid(format!("Unknown message in rate limits: {name}")))?,
+            };
+            let rate = match (limit.max_hz, limit.every_nth) {
+                (Some(hz), None) if hz > 0.0 => Rate::MaxHz(hz),
+                (None, Some(n)) => Rate::EveryNth(n),
+                _ => {
+                    return Err(invalid(format!(
+                        "Rate limit of {name} must set either a positive max_hz or every_nth"
+                    )));
+                }
+            };
+            rates.insert(msg_id, rate);
+        }
+        Ok(LiveSettings {
+            filter,
+            rate_limits: rates,
+            reconnect_delay: Duration::from_millis(self.reconnect_delay_ms),
+        })
+    }
+
+    /// Lists the settings that differ from another config of the same source.
+    fn changes(&self, previous: &SourceConfig) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.filter != previous.filter {
+            changes.push("filter");
+        }
+        if self.rate_limits != previous.rate_limits {
+            changes.push("rate_limits");
+        }
+        if self.reconnect_delay_ms != previous.reconnect_delay_ms {
+            changes.push("reconnect_delay_ms");
+        }
+        changes
+    }
+}
+
+/// Applies a changed config to the recorders built from the previous one.
+///
+/// Only the filter, rate limits and reconnect delay of a source can change while recording, so
+/// that the connection is kept and the log file is not split. Each recorder whose settings
+/// change records a marker entry listing the changed settings.
+///
+/// # Arguments
+///
+/// * `previous` - The config the recorders currently follow.
+/// * `next` - The changed config.
+/// * `handles` - The reload handles of the recorders, in the order of the sources.
+///
+/// # Returns
+///
+/// A `Result` indicating whether the config was applied. Nothing is applied if any source is
+/// invalid, and an error of kind `Unsupported` is returned if sources were added or removed or
+/// their address, metrics address or log changed, as these require a restart.
+pub fn apply_reload<M: Message>(
+    previous: &PipelineConfig,
+    next: &PipelineConfig,
+    handles: &[ReloadHandle],
+) -> std::io::Result<()> {
+    if next.sources.len() != previous.sources.len() || handles.len() != next.sources.len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Sources can only be added or removed on restart",
+        ));
+    }
+    let mut reloads = Vec::new();
+    for (source, old) in next.sources.iter().zip(&previous.sources) {
+        if source.address != old.address
+            || source.metrics_address != old.metrics_address
+            || source.log != old.log
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "The address, metrics address and log of {} can only change on restart",
+                    old.address
+                ),
+            ));
+        }
+        reloads.push((source.live_settings::<M>()?, source.changes(old)));
+    }
+    for (handle, (settings, changes)) in handles.iter().zip(reloads) {
+        if !changes.is_empty() {
+            handle.reload(settings, &changes.join(", "));
+        }
+    }
+    Ok(())
+}
+
+impl LogConfig {
+    /// Returns the format flags described by the config.
+    pub fn format_flags(&self) -> FormatFlags {
+        FormatFlags {
+            mavlink_only: self.mavlink_only,
+            no_timestamp: self.no_timestamp,
+            sequence: self.sequence,
+            hash_chain: self.hash_chain,
+            large_entries: self.large_entries,
+            chunked: self
+                .compression
+                .as_ref()
+                .is_some_and(|compression| compression.chunked),
+            ..Default::default()
+        }
+    }
+
+    /// Creates the logger described by the config.
+    fn build(&self) -> std::io::Result<RotatingMavLogger> {
+        let flags = self.format_flags();
+        let dictionary = self
+            .compression
+            .as_ref()
+            .and_then(|compression| compression.dictionary.as_deref());
+        let mut logger = match dictionary {
+            Some(dictionary) => self.build_compressed(flags, dictionary)?,
+            None => RotatingMavLogger::new(
+                &self.path,
+                self.max_bytes,
+                self.backup_count,
+                Some(flags),
+                None,
+            )?,
+        };
+        if let Some(block_size) = self
+            .compression
+            .as_ref()
+            .and_then(|compression| compression.block_size)
+        {
+            logger.set_block_size(block_size);
+        }
+        Ok(logger)
+    }
+
+    /// Creates a logger compressing entry payloads with a dictionary.
+    #[cfg(feature = "compression")]
+    fn build_compressed(
+        &self,
+        flags: FormatFlags,
+        dictionary: &str,
+    ) -> std::io::Result<RotatingMavLogger> {
+        use crate::mavlog::dictionary::EntryCompressor;
+
+        let dictionary = std::fs::read(dictionary)?;
+        let compressor = match self.compression.as_ref().and_then(|c| c.level) {
+            Some(level) => EntryCompressor::with_level(&dictionary, level)?,
+            None => EntryCompressor::new(&dictionary)?,
+        };
+        RotatingMavLogger::new_compressed(
+            &self.path,
+            self.max_bytes,
+            self.backup_count,
+            Some(flags),
+            None,
+            compressor,
+        )
+    }
+
+    /// Dictionary compression is unavailable without the compression feature.
+    #[cfg(not(feature = "compression"))]
+    fn build_compressed(
+        &self,
+        _flags: FormatFlags,
+        _dictionary: &str,
+    ) -> std::io::Result<RotatingMavLogger> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Dictionary compression requires the compression feature",
+        ))
+    }
+}
+
+/// Records every source of a config file, each in its own thread, until a log cannot be
+/// written.
+///
+/// This is the configurable counterpart of `record` for recording daemons that run for the
+/// lifetime of the process. On unix, the config file is reloaded on SIGHUP and applied with
+/// `apply_reload`. A rejected reload is recorded as a marker entry in every log and the
+/// previous config is kept.
+///
+/// # Arguments
+///
+/// * `path` - Path of a `.toml`, `.yaml` or `.yml` config file.
+///
+/// # Returns
+///
+/// A `Result` indicating failure to load the config or to create or write a log.
+pub fn record_from_config<M: Message + Send + 'static>(path: &str) -> std::io::Result<()> {
+    let mut config = PipelineConfig::load(path)?;
+    let pipelines = config.build::<M>()?;
+    let handles: Vec<ReloadHandle> = pipelines
+        .iter()
+        .map(|pipeline| pipeline.recorder.reload_handle())
+        .collect();
+    let threads: Vec<_> = pipelines
+        .into_iter()
+        .map(|mut pipeline| {
+            std::thread::spawn(move || {
+                let result = pipeline.recorder.run();
+                drop(pipeline.metrics_server);
+                result
+            })
+        })
+        .collect();
+
+    let hangup = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, hangup.clone())?;
+    while !threads.iter().all(|thread| thread.is_finished()) {
+        std::thread::sleep(RELOAD_POLL_INTERVAL);
+        if hangup.swap(false, Ordering::SeqCst) {
+            let reloaded = PipelineConfig::load(path)
+                .and_then(|next| apply_reload::<M>(&config, &next, &handles).map(|_| next));
+            match reloaded {
+                Ok(next) => config = next,
+                Err(e) => {
+                    for handle in &handles {
+                        handle.report_failure(&e.to_string());
+                    }
+                }
+            }
+        }
+    }
+    let mut result = Ok(());
+    for thread in threads {
+        let outcome = thread
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("Recorder thread panicked")));
+        if result.is_ok() {
+            result = outcome;
+        }
+    }
+    result
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use mavlink::common::MavMessage;
+    use mavlink::{MAVLinkV2MessageRaw, MavHeader};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mav_parser::for_each_entry;
+    use crate::mavlog::parser::MavLogParser;
+
+    /// Test that a recorder built from TOML applies its filter and rate limits.
+    #[test]
+    fn test_config_pipeline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("config.mav");
+        let text = format!(
+            r#"
+            [[sources]]
+            address = "tcpout:{}"
+            filter = "msg != 'SYS_STATUS'"
+            metrics_address = "127.0.0.1:0"
+            rate_limits = {{ HEARTBEAT = {{ every_nth = 2 }} }}
+
+            [sources.log]
+            path = "{}"
+            max_bytes = 100000
+            backup_count = 0
+            sequence = true
+            "#,
+            listener.local_addr().unwrap(),
+            log_path.to_str().unwrap()
+        );
+        let config = PipelineConfig::from_toml(&text).unwrap();
+        let mut pipelines = config.build::<MavMessage>().unwrap();
+        assert_eq!(pipelines.len(), 1);
+        let mut pipeline = pipelines.remove(0);
+        assert!(pipeline.metrics_server.is_some());
+        let stop = pipeline.recorder.stop_handle();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for sequence in 0..6 {
+                let msg = if sequence % 3 == 2 {
+                    MavMessage::SYS_STATUS(Default::default())
+                } else {
+                    MavMessage::HEARTBEAT(Default::default())
+                };
+                let mut raw = MAVLinkV2MessageRaw::new();
+                raw.serialize_message(
+                    MavHeader {
+                        sequence,
+                        ..Default::default()
+                    },
+                    &msg,
+                );
+                stream.write_all(raw.raw_bytes()).unwrap();
+            }
+            stream.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+            stop.store(true, Ordering::SeqCst);
+        });
+        pipeline.recorder.run().unwrap();
+        server.join().unwrap();
+        assert_eq!(pipeline.recorder.stats().frames, 2);
+
+        let mut parser = MavLogParser::<MavMessage>::new(log_path.to_str().unwrap());
+        let mut sequences: Vec<u8> = Vec::new();
+        for_each_entry(&mut parser, |entry| {
+            if let (Some(header), Some(MavMessage::HEARTBEAT(_))) =
+                (entry.mav_header, &entry.mav_message)
+            {
+                sequences.push(header.sequence);
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sequences, vec![0, 3]);
+    }
+
+    /// Test that a reloaded filter applies to the running recorder with a marker entry.
+    #[test]
+    fn test_config_reload() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("reload.mav");
+        let text = format!(
+            r#"
+            [[sources]]
+            address = "tcpout:{}"
+            log = {{ path = "{}", max_bytes = 100000, backup_count = 0 }}
+            "#,
+            listener.local_addr().unwrap(),
+            log_path.to_str().unwrap()
+        );
+        let config = PipelineConfig::from_toml(&text).unwrap();
+        let mut pipeline = config.build::<MavMessage>().unwrap().remove(0);
+        let handles = vec![pipeline.recorder.reload_handle()];
+        let stop = pipeline.recorder.stop_handle();
+
+        let mut moved = config.clone();
+        moved.sources[0].address = String::from("udpin:0.0.0.0:14550");
+        let error = apply_reload::<MavMessage>(&config, &moved, &handles).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+        let mut filtered = config.clone();
+        filtered.sources[0].filter = Some(String::from("msg != 'HEARTBEAT'"));
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let send = |stream: &mut std::net::TcpStream, msg: &MavMessage| {
+                let mut raw = MAVLinkV2MessageRaw::new();
+                raw.serialize_message(MavHeader::default(), msg);
+                stream.write_all(raw.raw_bytes()).unwrap();
+                stream.flush().unwrap();
+            };
+            let heartbeat = MavMessage::HEARTBEAT(Default::default());
+            send(&mut stream, &heartbeat);
+            send(&mut stream, &heartbeat);
+            std::thread::sleep(Duration::from_millis(200));
+            apply_reload::<MavMessage>(&config, &filtered, &handles).unwrap();
+            send(&mut stream, &heartbeat);
+            send(&mut stream, &MavMessage::SYS_STATUS(Default::default()));
+            std::thread::sleep(Duration::from_millis(200));
+            stop.store(true, Ordering::SeqCst);
+        });
+        pipeline.recorder.run().unwrap();
+        server.join().unwrap();
+        assert_eq!(pipeline.recorder.stats().frames, 3);
+
+        let mut parser = MavLogParser::<MavMessage>::new(log_path.to_str().unwrap());
+        let mut kinds: Vec<String> = Vec::new();
+        for_each_entry(&mut parser, |entry| {
+            match (entry.mav_message, entry.text) {
+                (Some(msg), _) => kinds.push(String::from(msg.message_name())),
+                (None, Some(text)) => kinds.push(text),
+                _ => {}
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            kinds,
+            vec![
+                "HEARTBEAT",
+                "HEARTBEAT",
+                "recorder: config changed (filter)",
+                "SYS_STATUS"
+            ]
+        );
+    }
+
+    /// Test that YAML configs are equivalent and invalid settings are rejected.
+    #[test]
+    fn test_config_yaml_and_errors() {
+        let yaml = "
+sources:
+  - address: udpin:0.0.0.0:14550
+    rate_limits:
+      ATTITUDE: { max_hz: 10.0 }
+    log:
+      path: flight.mav
+      max_bytes: 1000
+      backup_count: 2
+";
+        let toml = r#"
+            [[sources]]
+            address = "udpin:0.0.0.0:14550"
+            rate_limits = { ATTITUDE = { max_hz = 10.0 } }
+            log = { path = "flight.mav", max_bytes = 1000, backup_count = 2 }
+            "#;
+        let config = PipelineConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config, PipelineConfig::from_toml(toml).unwrap());
+        assert_eq!(config.sources[0].reconnect_delay_ms, 1000);
+
+        let misspelled = toml.replace("max_bytes", "max_byte");
+        let error = PipelineConfig::from_toml(&misspelled).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut unknown = config.sources[0].clone();
+        unknown
+            .rate_limits
+            .insert(String::from("NOT_A_MESSAGE"), RateLimit::default());
+        let error = unknown.build::<MavMessage>().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,391 @@
+//! Records every vehicle on a shared connection to its own rotating .mav log.
+//!
+//! A `RecorderManager` starts a recording for a system id when the first HEARTBEAT of that
+//! system arrives and closes it once the system has been silent for the inactivity timeout, so
+//! a ground station sees a new recording each time a vehicle joins the link. Frames of systems
+//! that have not sent a HEARTBEAT yet are not recorded.
+//!
+//! Recordings are written to `sys<system id>_<start time>_<index>.mav` in the configured
+//! directory, with the start time in seconds since the UNIX epoch and an index unique within the
+//! manager. Recordings can be listed and rotated from other threads through a `ManagerHandle`.
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use mavlink::error::MessageReadError;
+use mavlink::{MavConnection, MavFrame, MavHeader, MavlinkVersion, Message};
+
+use crate::mav_logger::MavLogger;
+use crate::mavlog::header::FormatFlags;
+use crate::mavlog::logger::RotatingMavLogger;
+
+/// HEARTBEAT message id.
+const HEARTBEAT_ID: u32 = 0;
+
+/// Configuration of the logs written by a recorder manager.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManagerConfig {
+    /// The directory the logs are written to. It is expected to exist.
+    pub directory: String,
+    /// The maximum size of a log file before it is rotated.
+    pub max_bytes: u64,
+    /// The number of backup files to keep for each recording.
+    pub backup_count: usize,
+    /// Optional format flags for the log files.
+    pub format_flags: Option<FormatFlags>,
+    /// Time to wait between reconnection attempts.
+    pub reconnect_delay: Duration,
+    /// Time without frames from a system after which its recording is closed.
+    pub inactivity_timeout: Duration,
+}
+
+impl ManagerConfig {
+    /// Creates a new `ManagerConfig` with default format flags, a one second reconnect delay and
+    /// a 30 second inactivity timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory the logs are written to. It is expected to exist.
+    /// * `max_bytes` - The maximum size of a log file before it is rotated.
+    /// * `backup_count` - The number of backup files to keep for each recording.
+    pub fn new(directory: &str, max_bytes: u64, backup_count: usize) -> Self {
+        Self {
+            directory: String::from(directory),
+            max_bytes,
+            backup_count,
+            format_flags: None,
+            reconnect_delay: Duration::from_secs(1),
+            inactivity_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Description of a recording in progress.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveRecording {
+    /// MAVLink system id of the recorded vehicle.
+    pub system_id: u8,
+    /// Path of the log file.
+    pub path: String,
+    /// Time the recording started.
+    pub started: SystemTime,
+    /// Number of MAVLink frames written to the recording.
+    pub frames: u64,
+    /// Time the last frame of the system was received.
+    pub last_frame: Instant,
+}
+
+/// A recording and the logger writing it.
+struct Session {
+    logger: RotatingMavLogger,
+    recording: ActiveRecording,
+}
+
+/// The recordings of a manager, shared with its handles.
+struct Fleet {
+    config: ManagerConfig,
+    sessions: BTreeMap<u8, Session>,
+    next_index: u64,
+}
+
+impl Fleet {
+    /// Creates a new recording for a system.
+    fn open(&mut self, system_id: u8, now: Instant) -> std::io::Result<Session> {
+        let started = SystemTime::now();
+        let start_s = started
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let name = format!("sys{system_id}_{start_s}_{}.mav", self.next_index);
+        let path = Path::new(&self.config.directory).join(name);
+        let path = path.to_string_lossy().into_owned();
+        let logger = RotatingMavLogger::new(
+            &path,
+            self.config.max_bytes,
+            self.config.backup_count,
+            self.config.format_flags,
+            None,
+        )?;
+        self.next_index += 1;
+        Ok(Session {
+            logger,
+            recording: ActiveRecording {
+                system_id,
+                path,
+                started,
+                frames: 0,
+                last_frame: now,
+            },
+        })
+    }
+
+    /// Writes a frame to the recording of its system, starting one on a HEARTBEAT.
+    fn write<M: Message>(&mut self, frame: MavFrame<M>, now: Instant) -> std::io::Result<()> {
+        let system_id = frame.header.system_id;
+        if !self.sessions.contains_key(&system_id) {
+            if frame.msg.message_id() != HEARTBEAT_ID {
+                return Ok(());
+            }
+            let session = self.open(system_id, now)?;
+            self.sessions.insert(system_id, session);
+        }
+        let session = self.sessions.get_mut(&system_id).expect("inserted above");
+        session.logger.write_mavlink(frame)?;
+        session.recording.frames += 1;
+        session.recording.last_frame = now;
+        Ok(())
+    }
+
+    /// Closes the recordings of systems silent for longer than the inactivity timeout.
+    fn expire(&mut self, now: Instant) {
+        let timeout = self.config.inactivity_timeout;
+        self.sessions
+            .retain(|_, session| now.duration_since(session.recording.last_frame) <= timeout);
+    }
+}
+
+/// Handle listing and rotating the recordings of a `RecorderManager` from any thread.
+#[derive(Clone)]
+pub struct ManagerHandle {
+    fleet: Arc<Mutex<Fleet>>,
+}
+
+impl ManagerHandle {
+    fn lock(&self) -> MutexGuard<'_, Fleet> {
+        // the fleet is only ever modified by its own methods, so its state stays usable
+        self.fleet.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the recordings in progress, ordered by system id.
+    pub fn recordings(&self) -> Vec<ActiveRecording> {
+        let fleet = self.lock();
+        fleet
+            .sessions
+            .values()
+            .map(|session| session.recording.clone())
+            .collect()
+    }
+
+    /// Closes the recording of a system and starts a new one in a new file.
+    ///
+    /// # Arguments
+    ///
+    /// * `system_id` - The MAVLink system id of the recording to rotate.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the path of the new recording, `None` if the system is not being
+    /// recorded, or an `io::Error` if the new log could not be created. The recording is closed
+    /// either way.
+    pub fn rotate(&self, system_id: u8) -> std::io::Result<Option<String>> {
+        let mut fleet = self.lock();
+        let last_frame = match fleet.sessions.remove(&system_id) {
+            Some(session) => session.recording.last_frame,
+            None => return Ok(None),
+        };
+        let session = fleet.open(system_id, last_frame)?;
+        let path = session.recording.path.clone();
+        fleet.sessions.insert(system_id, session);
+        Ok(Some(path))
+    }
+}
+
+/// Records every vehicle on a live MAVLink connection to its own rotating .mav log.
+pub struct RecorderManager<M: Message> {
+    address: String,
+    reconnect_delay: Duration,
+    fleet: Arc<Mutex<Fleet>>,
+    stop: Arc<AtomicBool>,
+    _phantom: std::marker::PhantomData<M>,
+}
+
+impl<M: Message> RecorderManager<M> {
+    /// Creates a new `RecorderManager`. No connection is made until `run` is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The mavlink crate address string of the connection to record.
+    /// * `config` - Configuration of the logs to write.
+    pub fn new(address: &str, config: ManagerConfig) -> Self {
+        Self {
+            address: String::from(address),
+            reconnect_delay: config.reconnect_delay,
+            fleet: Arc::new(Mutex::new(Fleet {
+                config,
+                sessions: BTreeMap::new(),
+                next_index: 0,
+            })),
+            stop: Arc::new(AtomicBool::new(false)),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns a handle that stops the manager when set to `true`.
+    ///
+    /// The manager checks the handle between frames and reconnection attempts. A manager
+    /// blocked on a silent link stops once the next frame arrives or the link drops.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Returns a handle to list and rotate the recordings from any thread.
+    pub fn handle(&self) -> ManagerHandle {
+        ManagerHandle {
+            fleet: self.fleet.clone(),
+        }
+    }
+
+    /// Records the connection until stopped.
+    ///
+    /// Reconnects with the configured delay whenever the link fails. Messages that fail to
+    /// parse are skipped. Inactive recordings are closed as frames arrive, so recordings stay
+    /// open while the whole link is silent. All recordings are closed when the manager stops.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error is returned only if a log could not
+    /// be created or written.
+    pub fn run(&mut self) -> std::io::Result<()> {
+        let result = self.record();
+        self.lock().sessions.clear();
+        result
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Fleet> {
+        self.fleet.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the connection until stopped, leaving the recordings open.
+    fn record(&mut self) -> std::io::Result<()> {
+        let mut connection = match self.connect() {
+            Some(connection) => connection,
+            None => return Ok(()),
+        };
+        while !self.stop.load(Ordering::SeqCst) {
+            match connection.recv() {
+                Ok((header, msg)) => {
+                    let protocol_version = connection.get_protocol_version();
+                    self.on_frame(header, msg, protocol_version, Instant::now())?;
+                }
+                Err(MessageReadError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(MessageReadError::Io(_)) => {
+                    if self.stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    connection = match self.connect() {
+                        Some(connection) => connection,
+                        None => break,
+                    };
+                }
+                Err(MessageReadError::Parse(_)) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a received frame and closes inactive recordings.
+    fn on_frame(
+        &self,
+        header: MavHeader,
+        msg: M,
+        protocol_version: MavlinkVersion,
+        now: Instant,
+    ) -> std::io::Result<()> {
+        let mut fleet = self.lock();
+        fleet.expire(now);
+        let frame = MavFrame {
+            header,
+            msg,
+            protocol_version,
+        };
+        fleet.write(frame, now)
+    }
+
+    /// Connects to the address, retrying until successful or stopped.
+    fn connect(&self) -> Option<Box<dyn MavConnection<M> + Sync + Send>> {
+        while !self.stop.load(Ordering::SeqCst) {
+            match mavlink::connect::<M>(&self.address) {
+                Ok(connection) => return Some(connection),
+                Err(_) => std::thread::sleep(self.reconnect_delay),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use mavlink::common::MavMessage;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mav_parser::for_each_entry;
+    use crate::mavlog::parser::MavLogParser;
+
+    fn header(system_id: u8) -> MavHeader {
+        MavHeader {
+            system_id,
+            ..Default::default()
+        }
+    }
+
+    fn count_frames(path: &str) -> u64 {
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let mut frames = 0;
+        for_each_entry(&mut parser, |entry| {
+            if entry.mav_message.is_some() {
+                frames += 1;
+            }
+            Ok(())
+        })
+        .unwrap();
+        frames
+    }
+
+    /// Test that recordings start on HEARTBEAT, close after the inactivity timeout and rotate
+    /// on request.
+    #[test]
+    fn test_manager_recordings() {
+        let dir = TempDir::new().unwrap();
+        let mut config = ManagerConfig::new(dir.path().to_str().unwrap(), 100000, 0);
+        config.inactivity_timeout = Duration::from_secs(5);
+        let manager = RecorderManager::<MavMessage>::new("udpin:127.0.0.1:0", config);
+        let handle = manager.handle();
+        let heartbeat = || MavMessage::HEARTBEAT(Default::default());
+        let attitude = || MavMessage::ATTITUDE(Default::default());
+        let start = Instant::now();
+        let at = |s: u64| start + Duration::from_secs(s);
+        let v2 = MavlinkVersion::V2;
+
+        // system 2 is not recorded before its first HEARTBEAT
+        manager.on_frame(header(2), attitude(), v2, at(0)).unwrap();
+        manager.on_frame(header(1), heartbeat(), v2, at(0)).unwrap();
+        manager.on_frame(header(1), attitude(), v2, at(1)).unwrap();
+        manager.on_frame(header(2), heartbeat(), v2, at(2)).unwrap();
+        let recordings = handle.recordings();
+        assert_eq!(recordings.len(), 2);
+        assert_eq!(recordings[0].system_id, 1);
+        assert_eq!(recordings[0].frames, 2);
+        assert_eq!(recordings[1].system_id, 2);
+        assert_eq!(recordings[1].frames, 1);
+        let first_path = recordings[0].path.clone();
+
+        let rotated_path = handle.rotate(1).unwrap().unwrap();
+        assert_ne!(rotated_path, first_path);
+        assert_eq!(handle.rotate(3).unwrap(), None);
+        manager.on_frame(header(1), attitude(), v2, at(4)).unwrap();
+
+        // system 2 has been silent for more than 5 s
+        manager.on_frame(header(1), attitude(), v2, at(8)).unwrap();
+        let recordings = handle.recordings();
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].path, rotated_path);
+        assert_eq!(recordings[0].frames, 2);
+
+        drop(manager);
+        assert_eq!(count_frames(&first_path), 2);
+        drop(handle);
+        assert_eq!(count_frames(&rotated_path), 2);
+    }
+}
@@ -0,0 +1,310 @@
+//! Prometheus metrics of a recorder.
+//!
+//! `Recorder::metrics` returns a `RecorderMetrics` updated as frames are recorded. Its state is
+//! rendered in the Prometheus text format with `RecorderMetrics::render`, or served to scrapers
+//! on `/metrics` with `RecorderMetrics::serve`, so that long running recorders can be monitored
+//! and alerted on.
+//!
+//! The mavlink crate discards frames failing their CRC before the recorder receives them. Such
+//! frames are counted as dropped frames, detected from gaps in the sequence numbers of each
+//! component, along with frames lost on the link.
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use mavlink::MavHeader;
+
+/// Time span over which the frame and byte rates are averaged.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// Interval between the samples the rates are computed from.
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counters of a recorder.
+#[derive(Default)]
+struct MetricsState {
+    frames: u64,
+    bytes: u64,
+    dropped: u64,
+    parse_errors: u64,
+    reconnects: u64,
+    rotations: u64,
+    /// Sequence number of the last frame by system and component id.
+    last_sequence: BTreeMap<(u8, u8), u8>,
+    /// Time the last frame of each system was received.
+    last_seen: BTreeMap<u8, Instant>,
+    /// Samples of the time, frame count and byte count, oldest first.
+    samples: VecDeque<(Instant, u64, u64)>,
+}
+
+impl MetricsState {
+    /// Returns the frame and byte rates over the rate window.
+    fn rates(&self, now: Instant) -> (f64, f64) {
+        match self.samples.front() {
+            Some(&(time, frames, bytes)) if now > time => {
+                let elapsed = now.duration_since(time).as_secs_f64();
+                (
+                    (self.frames - frames) as f64 / elapsed,
+                    (self.bytes - bytes) as f64 / elapsed,
+                )
+            }
+            _ => (0.0, 0.0),
+        }
+    }
+}
+
+/// Metrics of a recorder, shared between the recorder and the threads reading them.
+#[derive(Clone, Default)]
+pub struct RecorderMetrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl RecorderMetrics {
+    fn lock(&self) -> MutexGuard<'_, MetricsState> {
+        // the state is only ever modified by the methods below, so it stays usable
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Counts a recorded frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The MAVLink header of the frame.
+    /// * `bytes` - The number of bytes written to the log for the frame.
+    /// * `now` - The time the frame was received.
+    pub(crate) fn on_frame(&self, header: &MavHeader, bytes: u64, now: Instant) {
+        let mut state = self.lock();
+        if state
+            .samples
+            .back()
+            .is_none_or(|&(time, _, _)| now.duration_since(time) >= RATE_SAMPLE_INTERVAL)
+        {
+            let sample = (now, state.frames, state.bytes);
+            state.samples.push_back(sample);
+        }
+        while state
+            .samples
+            .front()
+            .is_some_and(|&(time, _, _)| now.duration_since(time) > RATE_WINDOW)
+        {
+            state.samples.pop_front();
+        }
+        state.frames += 1;
+        state.bytes += bytes;
+        let key = (header.system_id, header.component_id);
+        if let Some(last) = state.last_sequence.insert(key, header.sequence) {
+            state.dropped += header.sequence.wrapping_sub(last.wrapping_add(1)) as u64;
+        }
+        state.last_seen.insert(header.system_id, now);
+    }
+
+    /// Counts a message that failed to parse.
+    pub(crate) fn on_parse_error(&self) {
+        self.lock().parse_errors += 1;
+    }
+
+    /// Counts a re-established link.
+    pub(crate) fn on_reconnect(&self) {
+        self.lock().reconnects += 1;
+    }
+
+    /// Updates the number of log file rotations.
+    pub(crate) fn set_rotations(&self, rotations: u64) {
+        self.lock().rotations = rotations;
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let now = Instant::now();
+        let state = self.lock();
+        let (frame_rate, byte_rate) = state.rates(now);
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+            writeln!(text, "# HELP {name} {help}").unwrap();
+            writeln!(text, "# TYPE {name} {kind}").unwrap();
+            writeln!(text, "{name} {value}").unwrap();
+        };
+        metric(
+            "mavlink_recorder_frames_total",
+            "counter",
+            "MAVLink frames written to the log.",
+            state.frames as f64,
+        );
+        metric(
+            "mavlink_recorder_frames_per_second",
+            "gauge",
+            "MAVLink frames written per second over the last 10 seconds.",
+            frame_rate,
+        );
+        metric(
+            "mavlink_recorder_bytes_total",
+            "counter",
+            "Bytes of entries written to the log.",
+            state.bytes as f64,
+        );
+        metric(
+            "mavlink_recorder_bytes_per_second",
+            "gauge",
+            "Bytes of entries written per second over the last 10 seconds.",
+            byte_rate,
+        );
+        metric(
+            "mavlink_recorder_dropped_frames_total",
+            "counter",
+            "Frames missing from the sequence numbers of each component.",
+            state.dropped as f64,
+        );
+        metric(
+            "mavlink_recorder_parse_errors_total",
+            "counter",
+            "Received messages that could not be parsed.",
+            state.parse_errors as f64,
+        );
+        metric(
+            "mavlink_recorder_reconnects_total",
+            "counter",
+            "Times the link dropped and was re-established.",
+            state.reconnects as f64,
+        );
+        metric(
+            "mavlink_recorder_rotations_total",
+            "counter",
+            "Times the log file rotated.",
+            state.rotations as f64,
+        );
+        let name = "mavlink_recorder_last_seen_age_seconds";
+        writeln!(
+            text,
+            "# HELP {name} Time since the last frame of each system."
+        )
+        .unwrap();
+        writeln!(text, "# TYPE {name} gauge").unwrap();
+        for (system_id, last_seen) in &state.last_seen {
+            let age = now.saturating_duration_since(*last_seen).as_secs_f64();
+            writeln!(text, "{name}{{system_id=\"{system_id}\"}} {age}").unwrap();
+        }
+        text
+    }
+
+    /// Serves the metrics to Prometheus scrapers on `/metrics` in a background thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on, such as `0.0.0.0:9100`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the server, which stops serving when dropped, or an `io::Error` if
+    /// the address could not be bound.
+    pub fn serve(&self, address: &str) -> std::io::Result<MetricsServer> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let metrics = self.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                // a failed scrape only concerns its scraper
+                if let Ok(stream) = stream {
+                    let _ = metrics.answer(stream);
+                }
+            }
+        });
+        Ok(MetricsServer { local_addr, stop })
+    }
+
+    /// Answers an HTTP request for the metrics.
+    fn answer(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+        let path = request_line.split(' ').nth(1).unwrap_or_default();
+        let (status, body) = if path == "/metrics" {
+            ("200 OK", self.render())
+        } else {
+            ("404 Not Found", String::new())
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.flush()
+    }
+}
+
+/// Server answering Prometheus scrapes, stopped when dropped.
+pub struct MetricsServer {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+impl MetricsServer {
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // wake up the serving thread so that it sees the stop flag
+        let _ = TcpStream::connect(self.local_addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn header(system_id: u8, sequence: u8) -> MavHeader {
+        MavHeader {
+            system_id,
+            component_id: 1,
+            sequence,
+        }
+    }
+
+    /// Test that counted frames are rendered and served to scrapers.
+    #[test]
+    fn test_metrics_scrape() {
+        let metrics = RecorderMetrics::default();
+        let start = Instant::now();
+        metrics.on_frame(&header(1, 0), 20, start);
+        metrics.on_frame(&header(1, 3), 20, start + Duration::from_secs(1));
+        metrics.on_frame(&header(2, 0), 20, start + Duration::from_secs(2));
+        metrics.on_parse_error();
+        metrics.set_rotations(2);
+
+        let server = metrics.serve("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nmavlink_recorder_frames_total 3\n"));
+        assert!(response.contains("\nmavlink_recorder_bytes_total 60\n"));
+        assert!(response.contains("\nmavlink_recorder_dropped_frames_total 2\n"));
+        assert!(response.contains("\nmavlink_recorder_parse_errors_total 1\n"));
+        assert!(response.contains("\nmavlink_recorder_rotations_total 2\n"));
+        assert!(response.contains("\nmavlink_recorder_last_seen_age_seconds{system_id=\"1\"} "));
+        assert!(response.contains("\nmavlink_recorder_last_seen_age_seconds{system_id=\"2\"} "));
+
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
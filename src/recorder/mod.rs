@@ -0,0 +1,1002 @@
+//! This module provides a recorder that logs a live MAVLink connection to a rotating .mav log.
+//!
+//! The connection is described with the same address strings used by the mavlink crate such as
+//! `udpin:0.0.0.0:14550`, `tcpout:127.0.0.1:5760` or `serial:/dev/ttyUSB0:57600`. When the link
+//! drops the recorder keeps reconnecting and records a gap marker text entry for the outage. A
+//! link silent for longer than the link timeout is recorded as an outage as well.
+//!
+//! With the `analysis` feature, analyzers can be attached to a recorder to be fed every frame as
+//! it is written, while their state is read from other threads for live dashboards. Frames can
+//! also be selected with a `ParserFilter` and rate limited per message before being written, and
+//! both replaced at runtime through a `ReloadHandle` without reconnecting or rotating the log.
+//! When an incident occurs, a `CaptureHandle` lifts the rate limits for a while and writes the
+//! frames they recently left out, so that the incident is recorded at full rate.
+//!
+//! A `manager::RecorderManager` records each vehicle of a shared connection to its own log.
+//!
+//! The activity of a recorder can be scraped by Prometheus through `metrics::RecorderMetrics`.
+//!
+//! With the `config` feature, recorders are built from a TOML or YAML file with `config`.
+#[cfg(feature = "analysis")]
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+#[cfg(feature = "analysis")]
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use mavlink::error::MessageReadError;
+#[cfg(feature = "analysis")]
+use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavlinkVersion};
+use mavlink::{MavConnection, MavFrame, Message};
+
+#[cfg(feature = "analysis")]
+use crate::analysis::analyzer::Analyzer;
+#[cfg(feature = "analysis")]
+use crate::analysis::decimate::{Decimator, Rate};
+#[cfg(feature = "analysis")]
+use crate::analysis::filter::ParserFilter;
+#[cfg(feature = "analysis")]
+use crate::analysis::live::{AnalyzerHandle, SharedAnalyzer};
+use crate::mav_logger::MavLogger;
+#[cfg(feature = "analysis")]
+use crate::mav_parser::LogEntry;
+use crate::mavlog::header::FormatFlags;
+#[cfg(feature = "analysis")]
+use crate::mavlog::logger::EntryType;
+use crate::mavlog::logger::RotatingMavLogger;
+
+#[cfg(feature = "config")]
+pub mod config;
+pub mod manager;
+pub mod metrics;
+
+use metrics::RecorderMetrics;
+
+/// Configuration of the log written by a recorder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecorderConfig {
+    /// The base path for the log files. A file extension of .mav is recommended.
+    pub base_path: String,
+    /// The maximum size of a log file before it is rotated.
+    pub max_bytes: u64,
+    /// The number of backup files to keep.
+    pub backup_count: usize,
+    /// Optional format flags for the log file.
+    pub format_flags: Option<FormatFlags>,
+    /// Time to wait between reconnection attempts.
+    pub reconnect_delay: Duration,
+}
+
+impl RecorderConfig {
+    /// Creates a new `RecorderConfig` with default format flags and a one second reconnect delay.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_path` - The base path for the log files. Parent directories are expected to exist.
+    /// * `max_bytes` - The maximum size of a log file before it is rotated.
+    /// * `backup_count` - The number of backup files to keep.
+    pub fn new(base_path: &str, max_bytes: u64, backup_count: usize) -> Self {
+        Self {
+            base_path: String::from(base_path),
+            max_bytes,
+            backup_count,
+            format_flags: None,
+            reconnect_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Counters describing the activity of a recorder.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecorderStats {
+    /// Number of MAVLink frames written to the log.
+    pub frames: u64,
+    /// Number of times the link dropped and was re-established.
+    pub reconnects: u64,
+}
+
+/// Filter and rate limits of a running recorder, replaced through a `ReloadHandle`.
+#[cfg(feature = "analysis")]
+#[derive(Debug, Clone, Default)]
+pub struct LiveSettings {
+    /// Filter selecting the frames to record, or `None` to record every frame.
+    pub filter: Option<ParserFilter>,
+    /// The rate to record messages at by MAVLink message id. Messages with other ids are all
+    /// recorded.
+    pub rate_limits: BTreeMap<u32, Rate>,
+    /// Time to wait between reconnection attempts.
+    pub reconnect_delay: Duration,
+}
+
+/// Handle replacing the live settings of a running recorder from any thread.
+///
+/// Changes are applied before the next frame is recorded and marked in the log with a text
+/// entry, so the log shows where the recorded selection changed.
+#[cfg(feature = "analysis")]
+#[derive(Clone, Default)]
+pub struct ReloadHandle {
+    /// Settings to apply, or `None` for a failed reload, with the text of their marker entry.
+    pending: Arc<Mutex<Vec<(Option<LiveSettings>, String)>>>,
+}
+
+#[cfg(feature = "analysis")]
+impl ReloadHandle {
+    /// Replaces the live settings of the recorder.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The new settings.
+    /// * `description` - A short description of the change recorded in the marker entry.
+    pub fn reload(&self, settings: LiveSettings, description: &str) {
+        self.queue(
+            Some(settings),
+            format!("recorder: config changed ({description})"),
+        );
+    }
+
+    /// Records that a reload was attempted but rejected, leaving the settings unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the reload was rejected.
+    pub fn report_failure(&self, reason: &str) {
+        self.queue(None, format!("recorder: config reload failed ({reason})"));
+    }
+
+    fn queue(&self, settings: Option<LiveSettings>, text: String) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((settings, text));
+    }
+
+    /// Takes the changes queued since the last call.
+    fn take(&self) -> Vec<(Option<LiveSettings>, String)> {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// How long frames left out by rate limits are kept in memory by default, see
+/// `Recorder::set_capture_history`.
+#[cfg(feature = "analysis")]
+const CAPTURE_HISTORY: Duration = Duration::from_secs(10);
+/// Largest number of frames left out by rate limits kept in memory by default.
+#[cfg(feature = "analysis")]
+const CAPTURE_MAX_FRAMES: usize = 10_000;
+
+/// Handle opening a capture window of a running recorder from any thread, such as when an
+/// operator presses a button or an anomaly is detected.
+///
+/// During a capture window the recorder ignores its rate limits and records every frame its
+/// filter selects. When the window opens, the frames the rate limits left out shortly before
+/// are written with their original timestamps, after the entries already written.
+#[cfg(feature = "analysis")]
+#[derive(Clone, Default)]
+pub struct CaptureHandle {
+    /// Durations of the requested windows with the text of their marker entry.
+    pending: Arc<Mutex<Vec<(Duration, String)>>>,
+}
+
+#[cfg(feature = "analysis")]
+impl CaptureHandle {
+    /// Opens a capture window, or extends the current one.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - How long every frame is recorded, counted from the next received frame.
+    /// * `reason` - A short description of the trigger recorded in the marker entry.
+    pub fn trigger(&self, duration: Duration, reason: &str) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((
+                duration,
+                format!(
+                    "recorder: capture triggered for {:.3} s ({reason})",
+                    duration.as_secs_f64()
+                ),
+            ));
+    }
+
+    /// Takes the windows requested since the last call.
+    fn take(&self) -> Vec<(Duration, String)> {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Time without frames after which the link is marked as lost by default, see
+/// `Recorder::set_link_timeout`.
+const LINK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest time the recorder waits for a frame before checking its stop handle and link
+/// timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Time the receiving thread waits before polling a non-blocking connection again.
+const WOULD_BLOCK_DELAY: Duration = Duration::from_millis(1);
+/// Number of received frames queued for the recorder before the receiving thread waits.
+const RECEIVE_QUEUE: usize = 1024;
+
+/// A frame left out by rate limits, kept in case a capture window opens.
+#[cfg(feature = "analysis")]
+struct DroppedFrame {
+    received: Instant,
+    timestamp_us: Option<u64>,
+    bytes: Vec<u8>,
+}
+
+/// Records a live MAVLink connection to a rotating .mav log.
+pub struct Recorder<M: Message> {
+    address: String,
+    reconnect_delay: Duration,
+    link_timeout: Duration,
+    logger: RotatingMavLogger,
+    stop: Arc<AtomicBool>,
+    stats: RecorderStats,
+    metrics: Option<RecorderMetrics>,
+    #[cfg(feature = "analysis")]
+    analyzers: Vec<Box<dyn FnMut(&LogEntry<M>) + Send>>,
+    #[cfg(feature = "analysis")]
+    filter: Option<ParserFilter>,
+    #[cfg(feature = "analysis")]
+    rate_limits: Option<Decimator>,
+    #[cfg(feature = "analysis")]
+    reload: ReloadHandle,
+    #[cfg(feature = "analysis")]
+    capture: CaptureHandle,
+    /// End of the current capture window.
+    #[cfg(feature = "analysis")]
+    capture_until: Option<Instant>,
+    #[cfg(feature = "analysis")]
+    capture_history: Duration,
+    #[cfg(feature = "analysis")]
+    capture_max_frames: usize,
+    /// Frames recently left out by rate limits, oldest first.
+    #[cfg(feature = "analysis")]
+    dropped: VecDeque<DroppedFrame>,
+    _phantom: std::marker::PhantomData<M>,
+}
+
+impl<M: Message> Recorder<M> {
+    /// Creates a new `Recorder`. No connection is made until `run` is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The mavlink crate address string of the connection to record.
+    /// * `config` - Configuration of the log to write.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `Recorder` or an `io::Error` if the log could not be created.
+    pub fn new(address: &str, config: RecorderConfig) -> std::io::Result<Self> {
+        let logger = RotatingMavLogger::new(
+            &config.base_path,
+            config.max_bytes,
+            config.backup_count,
+            config.format_flags,
+            None,
+        )?;
+        Ok(Self::with_logger(address, logger, config.reconnect_delay))
+    }
+
+    /// Creates a new `Recorder` writing to an existing logger, such as one created with
+    /// `RotatingMavLogger::new_compressed` or `RotatingMavLogger::new_encrypted`.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The mavlink crate address string of the connection to record.
+    /// * `logger` - The logger to write the frames to.
+    /// * `reconnect_delay` - Time to wait between reconnection attempts.
+    pub fn with_logger(
+        address: &str,
+        logger: RotatingMavLogger,
+        reconnect_delay: Duration,
+    ) -> Self {
+        Self {
+            address: String::from(address),
+            reconnect_delay,
+            link_timeout: LINK_TIMEOUT,
+            logger,
+            stop: Arc::new(AtomicBool::new(false)),
+            stats: RecorderStats::default(),
+            metrics: None,
+            #[cfg(feature = "analysis")]
+            analyzers: Vec::new(),
+            #[cfg(feature = "analysis")]
+            filter: None,
+            #[cfg(feature = "analysis")]
+            rate_limits: None,
+            #[cfg(feature = "analysis")]
+            reload: ReloadHandle::default(),
+            #[cfg(feature = "analysis")]
+            capture: CaptureHandle::default(),
+            #[cfg(feature = "analysis")]
+            capture_until: None,
+            #[cfg(feature = "analysis")]
+            capture_history: CAPTURE_HISTORY,
+            #[cfg(feature = "analysis")]
+            capture_max_frames: CAPTURE_MAX_FRAMES,
+            #[cfg(feature = "analysis")]
+            dropped: VecDeque::new(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns a handle that stops the recorder when set to `true`.
+    ///
+    /// The recorder checks the handle between frames and reconnection attempts, and at least
+    /// every 100 ms while the link is silent.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Sets how long the link may stay silent before it is marked as lost, 5 seconds by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time without frames after which a link lost gap marker is written. The
+    ///     link restored marker follows with its next frame.
+    pub fn set_link_timeout(&mut self, timeout: Duration) {
+        self.link_timeout = timeout;
+    }
+
+    /// Returns the counters describing the recorder activity so far.
+    pub fn stats(&self) -> &RecorderStats {
+        &self.stats
+    }
+
+    /// Returns the metrics of the recorder, to be read or served from any thread.
+    ///
+    /// Metrics are only collected once this is first called, and are shared by every handle
+    /// returned afterwards.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the metrics, see `RecorderMetrics::serve` to expose them to Prometheus.
+    pub fn metrics(&mut self) -> RecorderMetrics {
+        self.metrics
+            .get_or_insert_with(RecorderMetrics::default)
+            .clone()
+    }
+
+    /// Attaches an analyzer fed with every frame the recorder writes.
+    ///
+    /// Entries passed to the analyzer hold the MAVLink header and message of the frame and the
+    /// time it was received in microseconds since the UNIX epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `analyzer` - The analyzer to feed, such as `LiveStats`.
+    ///
+    /// # Returns
+    ///
+    /// A handle to read the state of the analyzer from any thread while recording.
+    #[cfg(feature = "analysis")]
+    pub fn add_analyzer<A>(&mut self, analyzer: A) -> AnalyzerHandle<A>
+    where
+        A: Analyzer<M> + Send + 'static,
+    {
+        let mut shared = SharedAnalyzer::new(analyzer);
+        let handle = shared.handle();
+        self.analyzers
+            .push(Box::new(move |entry| shared.on_entry(entry)));
+        handle
+    }
+
+    /// Only records the frames selected by a filter.
+    ///
+    /// Filters see the time each frame was received in microseconds since the UNIX epoch as the
+    /// entry timestamp. Frames left out are neither written nor fed to the attached analyzers.
+    #[cfg(feature = "analysis")]
+    pub fn set_filter(&mut self, filter: ParserFilter) {
+        self.filter = Some(filter);
+    }
+
+    /// Limits the rate messages are recorded at.
+    ///
+    /// Messages are limited separately per message id and sending component, like with
+    /// `Decimate`. Frames left out are neither written nor fed to the attached analyzers.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate_per_msgid` - The rate to record messages at by MAVLink message id. Messages with
+    ///     other ids are all recorded.
+    #[cfg(feature = "analysis")]
+    pub fn set_rate_limits(&mut self, rate_per_msgid: BTreeMap<u32, Rate>) {
+        self.rate_limits = Some(Decimator::new(rate_per_msgid));
+    }
+
+    /// Returns a handle replacing the filter, rate limits and reconnect delay while recording.
+    #[cfg(feature = "analysis")]
+    pub fn reload_handle(&self) -> ReloadHandle {
+        self.reload.clone()
+    }
+
+    /// Returns a handle opening capture windows while recording.
+    #[cfg(feature = "analysis")]
+    pub fn capture_handle(&self) -> CaptureHandle {
+        self.capture.clone()
+    }
+
+    /// Sets how many of the frames left out by rate limits are kept in memory, to be written
+    /// when a capture window opens. Frames are kept for 10 seconds, at most 10000 of them, by
+    /// default.
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - How long frames are kept. Zero keeps none.
+    /// * `max_frames` - The largest number of frames kept. The oldest are discarded first.
+    #[cfg(feature = "analysis")]
+    pub fn set_capture_history(&mut self, history: Duration, max_frames: usize) {
+        self.capture_history = history;
+        self.capture_max_frames = max_frames;
+        self.evict_dropped(Instant::now());
+    }
+
+    /// Records the connection until stopped.
+    ///
+    /// Reconnects with the configured delay whenever the link fails. A link that stays silent
+    /// for longer than the link timeout is marked as lost until its next frame, as connections
+    /// such as `udpin` never fail. Messages that fail to parse are skipped.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. An error is returned only if the log could not
+    /// be written.
+    pub fn run(&mut self) -> std::io::Result<()>
+    where
+        M: Send + 'static,
+    {
+        let mut frames = match self.connect() {
+            Some(connection) => spawn_receiver(connection),
+            None => return Ok(()),
+        };
+        let mut last_frame = Instant::now();
+        // time the link was lost at, while it stays lost
+        let mut lost: Option<Instant> = None;
+        while !self.stop.load(Ordering::SeqCst) {
+            let received = match frames.recv_timeout(POLL_INTERVAL) {
+                Ok(received) => received,
+                Err(RecvTimeoutError::Timeout) => {
+                    if lost.is_none() && last_frame.elapsed() >= self.link_timeout {
+                        self.write_marker(&format!(
+                            "recorder: link lost (no frames for {:.3} s)",
+                            last_frame.elapsed().as_secs_f64()
+                        ))?;
+                        lost = Some(last_frame);
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => Err(MessageReadError::Io(
+                    std::io::Error::other("the receiving thread stopped"),
+                )),
+            };
+            match received {
+                Ok(frame) => {
+                    if let Some(lost) = lost.take() {
+                        self.on_restored(lost)?;
+                    }
+                    last_frame = Instant::now();
+                    self.record_frame(frame)?;
+                }
+                Err(MessageReadError::Io(e)) => {
+                    if self.stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if lost.is_none() {
+                        self.write_marker(&format!("recorder: link lost ({e})"))?;
+                    }
+                    let lost_at = *lost.get_or_insert_with(Instant::now);
+                    frames = match self.connect() {
+                        Some(connection) => spawn_receiver(connection),
+                        None => break,
+                    };
+                    self.on_restored(lost_at)?;
+                    lost = None;
+                    last_frame = Instant::now();
+                }
+                Err(MessageReadError::Parse(_)) => {
+                    if let Some(metrics) = &self.metrics {
+                        metrics.on_parse_error();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a received frame to the log, unless the filter or rate limits leave it out.
+    fn record_frame(&mut self, frame: MavFrame<M>) -> std::io::Result<()> {
+        #[cfg(feature = "analysis")]
+        self.apply_reloads()?;
+        #[cfg(feature = "analysis")]
+        self.apply_captures()?;
+        let MavFrame {
+            header,
+            msg,
+            protocol_version,
+        } = frame;
+        #[cfg(feature = "analysis")]
+        let Some(msg) = self.admit(header, msg, protocol_version)? else {
+            return Ok(());
+        };
+        let frame = MavFrame {
+            header,
+            msg,
+            protocol_version,
+        };
+        let written = self.logger.bytes_written();
+        self.logger.write_mavlink(frame)?;
+        self.stats.frames += 1;
+        if let Some(metrics) = &self.metrics {
+            let bytes = self.logger.bytes_written() - written;
+            metrics.on_frame(&header, bytes, Instant::now());
+            metrics.set_rotations(self.logger.rotations());
+        }
+        Ok(())
+    }
+
+    /// Counts a re-established link and writes its gap marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `lost` - When the link was lost.
+    fn on_restored(&mut self, lost: Instant) -> std::io::Result<()> {
+        self.stats.reconnects += 1;
+        if let Some(metrics) = &self.metrics {
+            metrics.on_reconnect();
+        }
+        self.write_marker(&format!(
+            "recorder: link restored after {:.3} s",
+            lost.elapsed().as_secs_f64()
+        ))
+    }
+
+    /// Connects to the address, retrying until successful or stopped.
+    fn connect(&self) -> Option<Box<dyn MavConnection<M> + Sync + Send>> {
+        while !self.stop.load(Ordering::SeqCst) {
+            match mavlink::connect::<M>(&self.address) {
+                Ok(connection) => return Some(connection),
+                Err(_) => std::thread::sleep(self.reconnect_delay),
+            }
+        }
+        None
+    }
+
+    /// Applies the live settings queued through the reload handle.
+    #[cfg(feature = "analysis")]
+    fn apply_reloads(&mut self) -> std::io::Result<()> {
+        for (settings, text) in self.reload.take() {
+            if let Some(settings) = settings {
+                self.filter = settings.filter;
+                self.rate_limits = (!settings.rate_limits.is_empty())
+                    .then(|| Decimator::new(settings.rate_limits));
+                self.reconnect_delay = settings.reconnect_delay;
+            }
+            self.write_marker(&text)?;
+        }
+        Ok(())
+    }
+
+    /// Opens the capture windows requested through the capture handle, writing the frames
+    /// recently left out by rate limits, and closes the current window once it ended.
+    #[cfg(feature = "analysis")]
+    fn apply_captures(&mut self) -> std::io::Result<()> {
+        let now = Instant::now();
+        let requests = self.capture.take();
+        if requests.is_empty() {
+            if self.capture_until.is_some_and(|until| now >= until) {
+                self.capture_until = None;
+                self.write_marker("recorder: capture ended")?;
+            }
+            return Ok(());
+        }
+        for (duration, text) in requests {
+            let until = now + duration;
+            self.capture_until = Some(self.capture_until.map_or(until, |end| end.max(until)));
+            self.write_marker(&text)?;
+        }
+        self.evict_dropped(now);
+        let written = self.logger.bytes_written();
+        let frames = self.dropped.len() as u64;
+        for frame in std::mem::take(&mut self.dropped) {
+            self.logger
+                .write_at(EntryType::Mavlink, frame.timestamp_us, &frame.bytes)?;
+        }
+        self.stats.frames += frames;
+        if let Some(metrics) = &self.metrics {
+            metrics.set_rotations(self.logger.rotations());
+        }
+        if frames > 0 {
+            let bytes = self.logger.bytes_written() - written;
+            self.write_marker(&format!(
+                "recorder: capture wrote {frames} earlier frames ({bytes} bytes)"
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Discards the frames left out by rate limits that are too old or too many to keep.
+    #[cfg(feature = "analysis")]
+    fn evict_dropped(&mut self, now: Instant) {
+        while self.dropped.len() > self.capture_max_frames
+            || self
+                .dropped
+                .front()
+                .is_some_and(|frame| now.duration_since(frame.received) > self.capture_history)
+        {
+            self.dropped.pop_front();
+        }
+    }
+
+    /// Keeps a frame left out by rate limits in case a capture window opens.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure. The error of the logger clock is returned if
+    /// it rejects backwards time.
+    #[cfg(feature = "analysis")]
+    fn keep_dropped(
+        &mut self,
+        header: mavlink::MavHeader,
+        msg: &M,
+        protocol_version: MavlinkVersion,
+    ) -> std::io::Result<()> {
+        if self.capture_history.is_zero() || self.capture_max_frames == 0 {
+            return Ok(());
+        }
+        let timestamp_us = if self.logger.header().format_flags.no_timestamp {
+            None
+        } else {
+            Some(self.logger.clock.now_us()?)
+        };
+        let bytes = match protocol_version {
+            MavlinkVersion::V1 => {
+                let mut raw = MAVLinkV1MessageRaw::new();
+                raw.serialize_message(header, msg);
+                raw.raw_bytes().to_vec()
+            }
+            MavlinkVersion::V2 => {
+                let mut raw = MAVLinkV2MessageRaw::new();
+                raw.serialize_message(header, msg);
+                raw.raw_bytes().to_vec()
+            }
+        };
+        let now = Instant::now();
+        self.dropped.push_back(DroppedFrame {
+            received: now,
+            timestamp_us,
+            bytes,
+        });
+        self.evict_dropped(now);
+        Ok(())
+    }
+
+    /// Applies the filter and rate limits to a received message and feeds the attached analyzers
+    /// with it if it is to be recorded. Rate limits are ignored during a capture window, and the
+    /// frames they leave out otherwise are kept for the next window.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the message to log, or `None` if it is left out.
+    #[cfg(feature = "analysis")]
+    fn admit(
+        &mut self,
+        header: mavlink::MavHeader,
+        msg: M,
+        protocol_version: mavlink::MavlinkVersion,
+    ) -> std::io::Result<Option<M>> {
+        if self.analyzers.is_empty() && self.filter.is_none() && self.rate_limits.is_none() {
+            return Ok(Some(msg));
+        }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros() as u64)
+            .unwrap_or(0);
+        let mut entry = LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(header),
+            mav_message: Some(msg),
+            protocol_version: Some(protocol_version),
+            ..Default::default()
+        };
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(&entry))
+        {
+            return Ok(None);
+        }
+        let limited = self.capture_until.is_none()
+            && self
+                .rate_limits
+                .as_mut()
+                .is_some_and(|limits| !limits.keep(&entry));
+        if limited {
+            if let Some(msg) = &entry.mav_message {
+                self.keep_dropped(header, msg, protocol_version)?;
+            }
+            return Ok(None);
+        }
+        for analyzer in &mut self.analyzers {
+            analyzer(&entry);
+        }
+        Ok(entry.mav_message.take())
+    }
+
+    /// Writes a gap marker text entry unless the log only accepts MAVLink.
+    fn write_marker(&mut self, text: &str) -> std::io::Result<()> {
+        if self.logger.header().format_flags.mavlink_only {
+            return Ok(());
+        }
+        self.logger.write_text(text)
+    }
+}
+
+/// Receives the frames of a connection on a new thread, so that the recorder can notice a
+/// silent link and its stop handle while no frame arrives.
+///
+/// The thread ends after the first error of the connection other than a parse error, or once
+/// the returned receiver is dropped and the next frame arrives.
+fn spawn_receiver<M: Message + Send + 'static>(
+    connection: Box<dyn MavConnection<M> + Sync + Send>,
+) -> Receiver<Result<MavFrame<M>, MessageReadError>> {
+    let (sender, receiver) = mpsc::sync_channel(RECEIVE_QUEUE);
+    std::thread::spawn(move || {
+        loop {
+            let received = match connection.recv_frame() {
+                // non-blocking connections report that no message is available yet
+                Err(MessageReadError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(WOULD_BLOCK_DELAY);
+                    continue;
+                }
+                received => received,
+            };
+            let failed = matches!(received, Err(MessageReadError::Io(_)));
+            if sender.send(received).is_err() || failed {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Records a live MAVLink connection to a rotating .mav log, reconnecting whenever the link
+/// drops. Only returns if the log cannot be created or written.
+///
+/// This is a convenience wrapper around `Recorder` for recording daemons that run for the
+/// lifetime of the process. Use `Recorder` directly to be able to stop recording.
+///
+/// # Arguments
+///
+/// * `address` - The mavlink crate address string of the connection to record.
+/// * `config` - Configuration of the log to write.
+///
+/// # Returns
+///
+/// A `Result` indicating failure to create or write the log.
+pub fn record<M: Message + Send + 'static>(
+    address: &str,
+    config: RecorderConfig,
+) -> std::io::Result<()> {
+    Recorder::<M>::new(address, config)?.run()
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use mavlink::common::MavMessage;
+    use mavlink::{MAVLinkV2MessageRaw, MavHeader};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mav_parser::for_each_entry;
+    use crate::mavlog::parser::MavLogParser;
+
+    /// Test that a dropped TCP link is re-established and recorded with gap markers.
+    #[test]
+    fn test_record_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("tcpout:{}", listener.local_addr().unwrap());
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("record.mav");
+        let mut config = RecorderConfig::new(log_path.to_str().unwrap(), 100000, 0);
+        config.reconnect_delay = Duration::from_millis(10);
+        let mut recorder = Recorder::<MavMessage>::new(&address, config).unwrap();
+        let stop = recorder.stop_handle();
+
+        let server = std::thread::spawn(move || {
+            let mut raw = MAVLinkV2MessageRaw::new();
+            raw.serialize_message(
+                MavHeader::default(),
+                &MavMessage::HEARTBEAT(Default::default()),
+            );
+            for round in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                for _ in 0..3 {
+                    stream.write_all(raw.raw_bytes()).unwrap();
+                }
+                stream.flush().unwrap();
+                std::thread::sleep(Duration::from_millis(200));
+                if round == 1 {
+                    stop.store(true, Ordering::SeqCst);
+                }
+            }
+        });
+        recorder.run().unwrap();
+        server.join().unwrap();
+        assert_eq!(recorder.stats().frames, 6);
+        assert_eq!(recorder.stats().reconnects, 1);
+
+        let mut parser = MavLogParser::<MavMessage>::new(log_path.to_str().unwrap());
+        let mut texts: Vec<String> = Vec::new();
+        let mut frames: u64 = 0;
+        for_each_entry(&mut parser, |entry| {
+            if entry.mav_message.is_some() {
+                frames += 1;
+            }
+            texts.extend(entry.text);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(frames, 6);
+        assert_eq!(texts.len(), 2);
+        assert!(texts[0].starts_with("recorder: link lost"));
+        assert!(texts[1].starts_with("recorder: link restored"));
+    }
+
+    /// Test that a link staying silent without failing is recorded with gap markers.
+    #[test]
+    fn test_record_silent_link() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("tcpout:{}", listener.local_addr().unwrap());
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("record.mav");
+        let config = RecorderConfig::new(log_path.to_str().unwrap(), 100000, 0);
+        let mut recorder = Recorder::<MavMessage>::new(&address, config).unwrap();
+        recorder.set_link_timeout(Duration::from_millis(200));
+        let stop = recorder.stop_handle();
+
+        let server = std::thread::spawn(move || {
+            let mut raw = MAVLinkV2MessageRaw::new();
+            raw.serialize_message(
+                MavHeader::default(),
+                &MavMessage::HEARTBEAT(Default::default()),
+            );
+            let (mut stream, _) = listener.accept().unwrap();
+            for pause in [600, 50] {
+                stream.write_all(raw.raw_bytes()).unwrap();
+                stream.flush().unwrap();
+                std::thread::sleep(Duration::from_millis(pause));
+            }
+            stop.store(true, Ordering::SeqCst);
+            // keep the link open until the recorder stopped
+            std::thread::sleep(Duration::from_millis(300));
+        });
+        recorder.run().unwrap();
+        server.join().unwrap();
+        assert_eq!(recorder.stats().frames, 2);
+        assert_eq!(recorder.stats().reconnects, 1);
+
+        let mut parser = MavLogParser::<MavMessage>::new(log_path.to_str().unwrap());
+        let mut entries: Vec<String> = Vec::new();
+        for_each_entry(&mut parser, |entry| {
+            entries.push(entry.text.unwrap_or_else(|| String::from("frame")));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0], "frame");
+        assert!(entries[1].starts_with("recorder: link lost (no frames for"));
+        assert!(entries[2].starts_with("recorder: link restored"));
+        assert_eq!(entries[3], "frame");
+    }
+
+    /// Test that attached analyzers are fed every recorded frame.
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_record_analyzers() {
+        use crate::analysis::live::LiveStats;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("tcpout:{}", listener.local_addr().unwrap());
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("record.mav");
+        let config = RecorderConfig::new(log_path.to_str().unwrap(), 100000, 0);
+        let mut recorder = Recorder::<MavMessage>::new(&address, config).unwrap();
+        let stats = recorder.add_analyzer(LiveStats::new(10_000_000));
+        let stop = recorder.stop_handle();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for sequence in [0, 1, 3] {
+                let mut raw = MAVLinkV2MessageRaw::new();
+                raw.serialize_message(
+                    MavHeader {
+                        sequence,
+                        ..Default::default()
+                    },
+                    &MavMessage::HEARTBEAT(Default::default()),
+                );
+                stream.write_all(raw.raw_bytes()).unwrap();
+            }
+            stream.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+            stop.store(true, Ordering::SeqCst);
+        });
+        recorder.run().unwrap();
+        server.join().unwrap();
+
+        let snapshot = stats.with(|stats| stats.snapshot()).unwrap();
+        assert_eq!(snapshot.messages, 3);
+        assert_eq!(snapshot.dropped, 1);
+        assert_eq!(snapshot.rates_hz[&0], 0.3);
+    }
+
+    /// Test that a capture window writes the frames rate limits recently left out and records
+    /// every frame until it ends.
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_record_capture_window() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("tcpout:{}", listener.local_addr().unwrap());
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("record.mav");
+        let config = RecorderConfig::new(log_path.to_str().unwrap(), 100000, 0);
+        let mut recorder = Recorder::<MavMessage>::new(&address, config).unwrap();
+        recorder.set_rate_limits(BTreeMap::from([(30, Rate::EveryNth(3))]));
+        let capture = recorder.capture_handle();
+        let stop = recorder.stop_handle();
+
+        let server = std::thread::spawn(move || {
+            let mut raw = MAVLinkV2MessageRaw::new();
+            raw.serialize_message(
+                MavHeader::default(),
+                &MavMessage::ATTITUDE(Default::default()),
+            );
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut send = |count: usize| {
+                for _ in 0..count {
+                    stream.write_all(raw.raw_bytes()).unwrap();
+                }
+                stream.flush().unwrap();
+                std::thread::sleep(Duration::from_millis(300));
+            };
+            send(6);
+            capture.trigger(Duration::from_millis(100), "operator button");
+            send(3);
+            send(3);
+            stop.store(true, Ordering::SeqCst);
+        });
+        recorder.run().unwrap();
+        server.join().unwrap();
+        // 2 frames kept by the rate limits, 4 written when the window opened, 3 during the
+        // window and 1 of the 3 received after it
+        assert_eq!(recorder.stats().frames, 10);
+
+        let mut parser = MavLogParser::<MavMessage>::new(log_path.to_str().unwrap());
+        let mut entries: Vec<String> = Vec::new();
+        for_each_entry(&mut parser, |entry| {
+            entries.push(match entry.text {
+                Some(text) => text,
+                None => String::from("frame"),
+            });
+            Ok(())
+        })
+        .unwrap();
+        let frame = String::from("frame");
+        let mut expected = vec![frame.clone(); 2];
+        expected.push(String::from(
+            "recorder: capture triggered for 0.100 s (operator button)",
+        ));
+        expected.extend(vec![frame.clone(); 4]);
+        assert!(entries[7].starts_with("recorder: capture wrote 4 earlier frames"));
+        expected.push(entries[7].clone());
+        expected.extend(vec![frame.clone(); 3]);
+        expected.push(String::from("recorder: capture ended"));
+        expected.push(frame);
+        assert_eq!(entries, expected);
+    }
+}
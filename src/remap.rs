@@ -0,0 +1,348 @@
+//! Remapping of the system and component ids of logged MAVLink traffic.
+//!
+//! Logs flown by different vehicles often share system ids, such as the default id 1. To replay
+//! several of them into a single simulation environment, the ids of each log are rewritten
+//! with an `IdRemap` so that they no longer collide. `RemappedParser` rewrites the headers of
+//! the entries of a parser, such as one replayed with `Replay`, and `RemappingLogger` rewrites
+//! serialized frames written to a logger, computing their checksum again.
+//!
+//! Only the ids of the frame headers are rewritten. Ids inside payloads, such as the target
+//! system of a command, are left as logged.
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use mavlink::error::MessageReadError;
+use mavlink::{MAV_STX_V2, MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavFrame, Message};
+
+use crate::frame::{self, FrameError};
+use crate::mav_logger::{MavFrameLogger, MavLogger};
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Rules rewriting system and component ids.
+///
+/// A rule for a component takes precedence over a rule for its system. Ids without a rule are
+/// kept.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdRemap {
+    /// New system ids by original system id, keeping the component id.
+    systems: BTreeMap<u8, u8>,
+    /// New system and component ids by original system and component id.
+    components: BTreeMap<(u8, u8), (u8, u8)>,
+}
+
+impl IdRemap {
+    /// Creates a new `IdRemap` without rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule rewriting a system id, for all of its components.
+    ///
+    /// # Arguments
+    /// - `from`: The system id as logged.
+    /// - `to`: The system id to rewrite it to.
+    pub fn map_system(mut self, from: u8, to: u8) -> Self {
+        self.systems.insert(from, to);
+        self
+    }
+
+    /// Adds a rule rewriting the ids of a single component.
+    ///
+    /// # Arguments
+    /// - `from`: The system and component ids as logged.
+    /// - `to`: The system and component ids to rewrite them to.
+    pub fn map_component(mut self, from: (u8, u8), to: (u8, u8)) -> Self {
+        self.components.insert(from, to);
+        self
+    }
+
+    /// Returns the ids a system and component are rewritten to.
+    ///
+    /// # Arguments
+    /// - `system_id`: The system id as logged.
+    /// - `component_id`: The component id as logged.
+    pub fn apply(&self, system_id: u8, component_id: u8) -> (u8, u8) {
+        if let Some(&ids) = self.components.get(&(system_id, component_id)) {
+            return ids;
+        }
+        let system_id = self.systems.get(&system_id).copied().unwrap_or(system_id);
+        (system_id, component_id)
+    }
+
+    /// Rewrites the ids of a serialized frame.
+    ///
+    /// The checksum of a rewritten frame is computed again. A MAVLink 2 signature covers the
+    /// ids, so the signature of a rewritten frame is removed. It can be signed again with a
+    /// `Resigner` when the `resign` feature is enabled. Frames without a matching rule are
+    /// returned unchanged, signature included.
+    ///
+    /// # Arguments
+    /// - `bytes`: Exactly the bytes of one MAVLink 1 or MAVLink 2 frame.
+    ///
+    /// # Type Parameters
+    /// - `M`: The dialect of the frame, which provides the checksum seed of its message.
+    ///
+    /// # Errors
+    /// Returns `FrameError::Invalid` if the bytes are not a single frame with a valid checksum,
+    /// which includes frames of messages missing from the dialect.
+    pub fn remap_frame<M: Message>(&self, bytes: &[u8]) -> Result<Vec<u8>, FrameError> {
+        if !frame::is_complete(bytes) {
+            return Err(FrameError::Invalid);
+        }
+        let (checksum_start, checksum) = frame::checksum::<M>(bytes).ok_or(FrameError::Invalid)?;
+        if bytes[checksum_start..checksum_start + 2] != checksum.to_le_bytes() {
+            return Err(FrameError::Invalid);
+        }
+        let (system_id, component_id) = frame::source(bytes).ok_or(FrameError::Invalid)?;
+        let ids = self.apply(system_id, component_id);
+        if ids == (system_id, component_id) {
+            return Ok(bytes.to_vec());
+        }
+
+        let mut remapped: Vec<u8> = bytes[..checksum_start + 2].to_vec();
+        let system_index = if bytes[0] == MAV_STX_V2 {
+            remapped[2] &= !frame::IFLAG_SIGNED;
+            5
+        } else {
+            3
+        };
+        remapped[system_index] = ids.0;
+        remapped[system_index + 1] = ids.1;
+        let (_, checksum) = frame::checksum::<M>(&remapped).ok_or(FrameError::Invalid)?;
+        remapped[checksum_start..].copy_from_slice(&checksum.to_le_bytes());
+        Ok(remapped)
+    }
+}
+
+/// Parser adapter rewriting the ids of the MAVLink headers of entries.
+///
+/// Messages are serialized again with the rewritten headers by whoever writes them, so their
+/// checksums are always consistent.
+pub struct RemappedParser<P: MavParser> {
+    parser: P,
+    remap: IdRemap,
+}
+
+impl<P: MavParser> RemappedParser<P> {
+    /// Creates a new `RemappedParser`.
+    ///
+    /// # Arguments
+    /// - `parser`: The parser to read entries from.
+    /// - `remap`: The rules rewriting the ids of the entries.
+    pub fn new(parser: P, remap: IdRemap) -> Self {
+        Self { parser, remap }
+    }
+
+    /// Returns the wrapped parser.
+    pub fn into_inner(self) -> P {
+        self.parser
+    }
+}
+
+impl<P: MavParser> MavParser for RemappedParser<P> {
+    type M = P::M;
+
+    /// Reads the next entry and rewrites the ids of its header.
+    ///
+    /// # Errors
+    /// Returns the errors of the wrapped parser unchanged.
+    fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+        let mut entry = self.parser.parse_next_entry()?;
+        if let Some(header) = &mut entry.mav_header {
+            (header.system_id, header.component_id) =
+                self.remap.apply(header.system_id, header.component_id);
+        }
+        Ok(entry)
+    }
+}
+
+/// A logger rewriting the ids of every frame before writing it to another logger.
+///
+/// To send the frames on a link that requires signing, wrap a `ResigningLogger`, which signs
+/// the rewritten frames again.
+///
+/// # Type Parameters
+/// - `L`: The logger the rewritten frames are written to.
+/// - `M`: The dialect of the frames written with `write_frame`. Frames written with
+///   `write_mavlink` use the dialect of their message.
+pub struct RemappingLogger<L: MavFrameLogger, M: Message> {
+    inner: L,
+    remap: IdRemap,
+    _phantom: PhantomData<M>,
+}
+
+impl<L: MavFrameLogger, M: Message> RemappingLogger<L, M> {
+    /// Creates a new `RemappingLogger`.
+    ///
+    /// # Arguments
+    /// - `inner`: The logger to write the rewritten frames to.
+    /// - `remap`: The rules rewriting the ids of the frames.
+    pub fn new(inner: L, remap: IdRemap) -> Self {
+        Self {
+            inner,
+            remap,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the wrapped logger.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L: MavFrameLogger, M: Message> MavFrameLogger for RemappingLogger<L, M> {
+    /// Rewrites the ids of a serialized MAVLink frame and writes it to the wrapped logger.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` of kind `InvalidInput` if the bytes are not a single frame with a
+    /// valid checksum, or the error of the wrapped logger.
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        let remapped = self.remap.remap_frame::<M>(frame).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The data is not a single valid MAVLink frame",
+            )
+        })?;
+        self.inner.write_frame(&remapped)
+    }
+}
+
+impl<L: MavFrameLogger, M: Message> MavLogger for RemappingLogger<L, M> {
+    /// Serializes a MAVLink message with rewritten ids and writes it to the wrapped logger.
+    fn write_mavlink<D: Message>(&mut self, mut frame: MavFrame<D>) -> std::io::Result<()> {
+        (frame.header.system_id, frame.header.component_id) = self
+            .remap
+            .apply(frame.header.system_id, frame.header.component_id);
+        match frame.protocol_version {
+            mavlink::MavlinkVersion::V1 => {
+                let mut msg = MAVLinkV1MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                self.inner.write_frame(msg.raw_bytes())
+            }
+            mavlink::MavlinkVersion::V2 => {
+                let mut msg = MAVLinkV2MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                self.inner.write_frame(msg.raw_bytes())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::MavMessage;
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    /// Logger collecting the frames written to it.
+    #[derive(Default)]
+    struct FrameList(Vec<Vec<u8>>);
+
+    impl MavFrameLogger for FrameList {
+        fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+            self.0.push(frame.to_vec());
+            Ok(())
+        }
+    }
+
+    fn header(system_id: u8, component_id: u8) -> MavHeader {
+        MavHeader {
+            sequence: 9,
+            system_id,
+            component_id,
+        }
+    }
+
+    fn remap() -> IdRemap {
+        IdRemap::new()
+            .map_system(1, 11)
+            .map_component((1, 154), (21, 100))
+    }
+
+    /// Test that component rules take precedence over system rules.
+    #[test]
+    fn test_apply() {
+        assert_eq!(remap().apply(1, 1), (11, 1));
+        assert_eq!(remap().apply(1, 154), (21, 100));
+        assert_eq!(remap().apply(2, 154), (2, 154));
+    }
+
+    /// Test that rewritten frames of both versions decode with their new ids, and that frames
+    /// without a rule are kept unchanged.
+    #[test]
+    fn test_remap_frame() {
+        let msg = MavMessage::HEARTBEAT(Default::default());
+        let mut v1 = MAVLinkV1MessageRaw::new();
+        v1.serialize_message(header(1, 154), &msg);
+        let remapped = remap().remap_frame::<MavMessage>(v1.raw_bytes()).unwrap();
+        let decoded = frame::decode::<MavMessage>(&remapped).unwrap();
+        assert_eq!(decoded.header, header(21, 100));
+        assert_eq!(decoded.msg, msg);
+
+        let mut v2 = MAVLinkV2MessageRaw::new();
+        v2.serialize_message(header(1, 1), &msg);
+        let remapped = remap().remap_frame::<MavMessage>(v2.raw_bytes()).unwrap();
+        let decoded = frame::decode::<MavMessage>(&remapped).unwrap();
+        assert_eq!(decoded.header, header(11, 1));
+        assert_eq!(decoded.msg, msg);
+
+        v2.serialize_message(header(3, 1), &msg);
+        let kept = remap().remap_frame::<MavMessage>(v2.raw_bytes()).unwrap();
+        assert_eq!(kept, v2.raw_bytes());
+
+        let mut corrupted = v2.raw_bytes().to_vec();
+        corrupted[12] ^= 0xff;
+        assert!(matches!(
+            remap().remap_frame::<MavMessage>(&corrupted),
+            Err(FrameError::Invalid)
+        ));
+    }
+
+    /// Test that the headers of parsed entries are rewritten and frames written to a remapping
+    /// logger carry the new ids.
+    #[test]
+    fn test_remapped_parser_and_logger() {
+        let log = EntryList(VecDeque::from([
+            LogEntry {
+                mav_header: Some(header(1, 1)),
+                mav_message: Some(MavMessage::HEARTBEAT(Default::default())),
+                ..Default::default()
+            },
+            LogEntry {
+                text: Some(String::from("no header")),
+                ..Default::default()
+            },
+        ]));
+        let mut parser = RemappedParser::new(log, remap());
+        assert_eq!(
+            parser.parse_next_entry().unwrap().mav_header,
+            Some(header(11, 1))
+        );
+        assert_eq!(parser.parse_next_entry().unwrap().mav_header, None);
+
+        let mut logger: RemappingLogger<FrameList, MavMessage> =
+            RemappingLogger::new(FrameList::default(), remap());
+        logger
+            .write_mavlink(MavFrame {
+                header: header(1, 154),
+                msg: MavMessage::HEARTBEAT(Default::default()),
+                protocol_version: mavlink::MavlinkVersion::V2,
+            })
+            .unwrap();
+        let mut v2 = MAVLinkV2MessageRaw::new();
+        v2.serialize_message(header(1, 1), &MavMessage::HEARTBEAT(Default::default()));
+        logger.write_frame(v2.raw_bytes()).unwrap();
+        let sources: Vec<Option<(u8, u8)>> = logger
+            .into_inner()
+            .0
+            .iter()
+            .map(|bytes| frame::source(bytes))
+            .collect();
+        assert_eq!(sources, vec![Some((21, 100)), Some((11, 1))]);
+    }
+}
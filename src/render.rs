@@ -0,0 +1,591 @@
+//! Human readable text of MAVLink messages.
+//!
+//! The `Display` implementation of `LogEntry` prints messages as their `Debug` representation,
+//! with positions as 1e7 integers and modes as bit fields. A `TextRenderer` prints the messages
+//! read most often in a form meant for people: positions in degrees and meters, attitudes in
+//! degrees, flight modes by name and commands by the name of their MAV_CMD. Enum values are
+//! named as by the `Debug` representation of the dialect, so names follow the MAVLink
+//! definitions the dialect was generated from. Other messages keep their `Debug` fields unless a
+//! custom renderer is registered for them.
+//!
+//! ```
+//! use mavlink::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+//! use mavlink_log::render::TextRenderer;
+//!
+//! let renderer = TextRenderer::new();
+//! let msg = MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+//!     lat: 473_977_419,
+//!     lon: 85_455_938,
+//!     ..Default::default()
+//! });
+//! assert!(renderer.render(&msg).starts_with("GLOBAL_POSITION_INT lat=47.3977419 lon=8.5455938"));
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Write};
+
+use mavlink::Message;
+
+use crate::fields;
+use crate::mav_parser::LogEntry;
+
+/// MAV_MODE_FLAG_CUSTOM_MODE_ENABLED bit of the HEARTBEAT base_mode.
+const CUSTOM_MODE_ENABLED: u8 = 0x01;
+/// MAV_MODE_FLAG_SAFETY_ARMED bit of the HEARTBEAT base_mode.
+const SAFETY_ARMED: u8 = 0x80;
+/// MAV_AUTOPILOT_ARDUPILOTMEGA.
+const AUTOPILOT_ARDUPILOT: u8 = 3;
+/// MAV_AUTOPILOT_PX4.
+const AUTOPILOT_PX4: u8 = 12;
+/// GLOBAL_POSITION_INT and GPS_RAW_INT heading or course when it is not known.
+const HEADING_UNKNOWN: u16 = u16::MAX;
+/// Maximum length of the text of a STATUSTEXT.
+const STATUSTEXT_LEN: usize = 50;
+
+/// Names of the MAV_MODE values, the standard modes encoded in the HEARTBEAT base_mode.
+const MAV_MODES: [(u8, &str); 11] = [
+    (0, "PREFLIGHT"),
+    (64, "MANUAL_DISARMED"),
+    (66, "TEST_DISARMED"),
+    (80, "STABILIZE_DISARMED"),
+    (88, "GUIDED_DISARMED"),
+    (92, "AUTO_DISARMED"),
+    (192, "MANUAL_ARMED"),
+    (194, "TEST_ARMED"),
+    (208, "STABILIZE_ARMED"),
+    (216, "GUIDED_ARMED"),
+    (220, "AUTO_ARMED"),
+];
+
+/// ArduCopter flight modes by custom_mode.
+const ARDUCOPTER_MODES: [(u32, &str); 26] = [
+    (0, "STABILIZE"),
+    (1, "ACRO"),
+    (2, "ALT_HOLD"),
+    (3, "AUTO"),
+    (4, "GUIDED"),
+    (5, "LOITER"),
+    (6, "RTL"),
+    (7, "CIRCLE"),
+    (9, "LAND"),
+    (11, "DRIFT"),
+    (13, "SPORT"),
+    (14, "FLIP"),
+    (15, "AUTOTUNE"),
+    (16, "POSHOLD"),
+    (17, "BRAKE"),
+    (18, "THROW"),
+    (19, "AVOID_ADSB"),
+    (20, "GUIDED_NOGPS"),
+    (21, "SMART_RTL"),
+    (22, "FLOWHOLD"),
+    (23, "FOLLOW"),
+    (24, "ZIGZAG"),
+    (25, "SYSTEMID"),
+    (26, "AUTOROTATE"),
+    (27, "AUTO_RTL"),
+    (28, "TURTLE"),
+];
+
+/// ArduPlane flight modes by custom_mode.
+const ARDUPLANE_MODES: [(u32, &str); 25] = [
+    (0, "MANUAL"),
+    (1, "CIRCLE"),
+    (2, "STABILIZE"),
+    (3, "TRAINING"),
+    (4, "ACRO"),
+    (5, "FLY_BY_WIRE_A"),
+    (6, "FLY_BY_WIRE_B"),
+    (7, "CRUISE"),
+    (8, "AUTOTUNE"),
+    (10, "AUTO"),
+    (11, "RTL"),
+    (12, "LOITER"),
+    (13, "TAKEOFF"),
+    (14, "AVOID_ADSB"),
+    (15, "GUIDED"),
+    (17, "QSTABILIZE"),
+    (18, "QHOVER"),
+    (19, "QLOITER"),
+    (20, "QLAND"),
+    (21, "QRTL"),
+    (22, "QAUTOTUNE"),
+    (23, "QACRO"),
+    (24, "THERMAL"),
+    (25, "LOITER_ALT_QLAND"),
+    (26, "AUTOLAND"),
+];
+
+/// ArduRover flight modes by custom_mode.
+const ARDUROVER_MODES: [(u32, &str); 14] = [
+    (0, "MANUAL"),
+    (1, "ACRO"),
+    (3, "STEERING"),
+    (4, "HOLD"),
+    (5, "LOITER"),
+    (6, "FOLLOW"),
+    (7, "SIMPLE"),
+    (8, "DOCK"),
+    (9, "CIRCLE"),
+    (10, "AUTO"),
+    (11, "RTL"),
+    (12, "SMART_RTL"),
+    (15, "GUIDED"),
+    (16, "INITIALISING"),
+];
+
+/// PX4 main modes, stored in the third byte of custom_mode.
+const PX4_MAIN_MODES: [(u8, &str); 9] = [
+    (1, "MANUAL"),
+    (2, "ALTCTL"),
+    (3, "POSCTL"),
+    (4, "AUTO"),
+    (5, "ACRO"),
+    (6, "OFFBOARD"),
+    (7, "STABILIZED"),
+    (8, "RATTITUDE"),
+    (9, "TERMINATION"),
+];
+
+/// PX4 sub modes of the AUTO main mode, stored in the fourth byte of custom_mode.
+const PX4_AUTO_SUB_MODES: [(u8, &str); 9] = [
+    (1, "READY"),
+    (2, "TAKEOFF"),
+    (3, "LOITER"),
+    (4, "MISSION"),
+    (5, "RTL"),
+    (6, "LAND"),
+    (8, "FOLLOW_TARGET"),
+    (9, "PRECLAND"),
+    (10, "VTOL_TAKEOFF"),
+];
+
+/// Looks up the name of a value in a table.
+fn lookup<K: PartialEq + Copy>(table: &[(K, &'static str)], value: K) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(key, _)| *key == value)
+        .map(|(_, name)| *name)
+}
+
+/// Returns the name of a flight mode from the HEARTBEAT fields.
+///
+/// Custom modes are named for ArduPilot copters, planes and rovers and for PX4, standard modes
+/// by their MAV_MODE name.
+///
+/// # Arguments
+/// - `autopilot`: The MAV_AUTOPILOT of the vehicle.
+/// - `mav_type`: The MAV_TYPE of the vehicle.
+/// - `base_mode`: The MAV_MODE_FLAG bits.
+/// - `custom_mode`: The autopilot specific mode.
+///
+/// # Returns
+/// The name of the mode, or `None` if it is not known.
+pub fn mode_name(autopilot: u8, mav_type: u8, base_mode: u8, custom_mode: u32) -> Option<String> {
+    if base_mode & CUSTOM_MODE_ENABLED == 0 {
+        return lookup(&MAV_MODES, base_mode).map(String::from);
+    }
+    match autopilot {
+        AUTOPILOT_ARDUPILOT => {
+            let table: &[(u32, &str)] = match mav_type {
+                // quadrotor, coaxial, helicopter, hexarotor, octorotor, tricopter,
+                // dodecarotor and decarotor
+                2 | 3 | 4 | 13 | 14 | 15 | 29 | 35 => &ARDUCOPTER_MODES,
+                // fixed wing and VTOL types
+                1 | 19..=25 => &ARDUPLANE_MODES,
+                // ground rover and surface boat
+                10 | 11 => &ARDUROVER_MODES,
+                _ => return None,
+            };
+            lookup(table, custom_mode).map(String::from)
+        }
+        AUTOPILOT_PX4 => {
+            let [_, _, main_mode, sub_mode] = custom_mode.to_le_bytes();
+            let main_name = lookup(&PX4_MAIN_MODES, main_mode)?;
+            match lookup(&PX4_AUTO_SUB_MODES, sub_mode) {
+                Some(sub_name) if main_name == "AUTO" => Some(format!("{main_name}.{sub_name}")),
+                _ => Some(String::from(main_name)),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// A message being rendered, as passed to the renderer registered for its id.
+pub struct MessageFields {
+    payload: [u8; fields::MAX_PAYLOAD_SIZE],
+    fields: Vec<(String, String)>,
+}
+
+impl MessageFields {
+    /// Reads the payload and `Debug` fields of a message.
+    pub fn new<M: Message + Debug>(msg: &M) -> Self {
+        MessageFields {
+            payload: fields::payload(msg),
+            fields: fields::debug_fields(&format!("{msg:?}")),
+        }
+    }
+
+    /// Returns the payload of the message as serialized for MAVLink 2, zero padded to the
+    /// maximum payload size so that any field offset can be read.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Returns the value of a field as formatted by `Debug`, enum values by their name.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the value of a field as formatted by `Debug`, or a number if the field is not
+    /// found, as when a dialect names it differently.
+    fn field_or(&self, name: &str, number: impl ToString) -> String {
+        self.field(name)
+            .map_or_else(|| number.to_string(), String::from)
+    }
+
+    fn i32_at(&self, offset: usize) -> i32 {
+        fields::read_i32(&self.payload, offset)
+    }
+
+    fn f32_at(&self, offset: usize) -> f32 {
+        fields::read_f32(&self.payload, offset)
+    }
+}
+
+/// Renders the fields of a message that follow its name, or returns `None` to fall back to the
+/// `Debug` fields.
+pub type RenderFn = fn(&MessageFields) -> Option<String>;
+
+/// Formats a position given as 1e7 degree integers and an altitude in millimeters.
+fn position(fields: &MessageFields, offset: usize) -> String {
+    format!(
+        "lat={:.7} lon={:.7} alt={:.2}m",
+        fields.i32_at(offset) as f64 / 1e7,
+        fields.i32_at(offset + 4) as f64 / 1e7,
+        fields.i32_at(offset + 8) as f64 / 1e3
+    )
+}
+
+/// Formats a heading given in centidegrees.
+fn heading(centidegrees: u16) -> String {
+    if centidegrees == HEADING_UNKNOWN {
+        String::from("unknown")
+    } else {
+        format!("{:.2}", centidegrees as f64 / 100.0)
+    }
+}
+
+fn render_heartbeat(fields: &MessageFields) -> Option<String> {
+    let payload = fields.payload();
+    let custom_mode = fields::read_u32(payload, 0);
+    let mav_type = fields::read_u8(payload, 4);
+    let autopilot = fields::read_u8(payload, 5);
+    let base_mode = fields::read_u8(payload, 6);
+    let mode = mode_name(autopilot, mav_type, base_mode, custom_mode)
+        .unwrap_or_else(|| format!("custom_mode:{custom_mode}"));
+    Some(format!(
+        "type={} autopilot={} mode={} armed={} status={}",
+        fields.field_or("mavtype", mav_type),
+        fields.field_or("autopilot", autopilot),
+        mode,
+        base_mode & SAFETY_ARMED != 0,
+        fields.field_or("system_status", fields::read_u8(payload, 7))
+    ))
+}
+
+fn render_global_position_int(fields: &MessageFields) -> Option<String> {
+    let payload = fields.payload();
+    Some(format!(
+        "{} relative_alt={:.2}m velocity=[{:.2}, {:.2}, {:.2}]m/s hdg={}",
+        position(fields, 4),
+        fields.i32_at(16) as f64 / 1e3,
+        fields::read_i16(payload, 20) as f64 / 100.0,
+        fields::read_i16(payload, 22) as f64 / 100.0,
+        fields::read_i16(payload, 24) as f64 / 100.0,
+        heading(fields::read_u16(payload, 26))
+    ))
+}
+
+fn render_gps_raw_int(fields: &MessageFields) -> Option<String> {
+    let payload = fields.payload();
+    Some(format!(
+        "fix={} satellites={} {} groundspeed={:.2}m/s cog={}",
+        fields.field_or("fix_type", fields::read_u8(payload, 28)),
+        fields::read_u8(payload, 29),
+        position(fields, 8),
+        fields::read_u16(payload, 24) as f64 / 100.0,
+        heading(fields::read_u16(payload, 26))
+    ))
+}
+
+fn render_home_position(fields: &MessageFields) -> Option<String> {
+    Some(position(fields, 0))
+}
+
+fn render_attitude(fields: &MessageFields) -> Option<String> {
+    Some(format!(
+        "roll={:.2} pitch={:.2} yaw={:.2}",
+        fields.f32_at(4).to_degrees(),
+        fields.f32_at(8).to_degrees(),
+        fields.f32_at(12).to_degrees()
+    ))
+}
+
+fn render_command_long(fields: &MessageFields) -> Option<String> {
+    let payload = fields.payload();
+    let params: Vec<String> = (0..7)
+        .map(|index| fields.f32_at(index * 4).to_string())
+        .collect();
+    Some(format!(
+        "{} target={}/{} confirmation={} params=[{}]",
+        fields.field_or("command", fields::read_u16(payload, 28)),
+        fields::read_u8(payload, 30),
+        fields::read_u8(payload, 31),
+        fields::read_u8(payload, 32),
+        params.join(", ")
+    ))
+}
+
+fn render_command_int(fields: &MessageFields) -> Option<String> {
+    let payload = fields.payload();
+    let params: Vec<String> = (0..4)
+        .map(|index| fields.f32_at(index * 4).to_string())
+        .collect();
+    Some(format!(
+        "{} target={}/{} frame={} params=[{}] x={} y={} z={}",
+        fields.field_or("command", fields::read_u16(payload, 28)),
+        fields::read_u8(payload, 30),
+        fields::read_u8(payload, 31),
+        fields.field_or("frame", fields::read_u8(payload, 32)),
+        params.join(", "),
+        fields.i32_at(16),
+        fields.i32_at(20),
+        fields.f32_at(24)
+    ))
+}
+
+fn render_command_ack(fields: &MessageFields) -> Option<String> {
+    let payload = fields.payload();
+    Some(format!(
+        "{} result={}",
+        fields.field_or("command", fields::read_u16(payload, 0)),
+        fields.field_or("result", fields::read_u8(payload, 2))
+    ))
+}
+
+fn render_statustext(fields: &MessageFields) -> Option<String> {
+    let payload = fields.payload();
+    let text = &payload[1..1 + STATUSTEXT_LEN];
+    let len = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+    Some(format!(
+        "{} {:?}",
+        fields.field_or("severity", fields::read_u8(payload, 0)),
+        String::from_utf8_lossy(&text[..len])
+    ))
+}
+
+/// Renders MAVLink messages as human readable text.
+///
+/// Messages without a renderer are printed as their name followed by their `Debug` fields as
+/// `name=value` pairs.
+#[derive(Clone)]
+pub struct TextRenderer {
+    renderers: BTreeMap<u32, RenderFn>,
+}
+
+impl Default for TextRenderer {
+    /// Provides a renderer for the HEARTBEAT, ATTITUDE, GPS_RAW_INT, GLOBAL_POSITION_INT,
+    /// HOME_POSITION, COMMAND_INT, COMMAND_LONG, COMMAND_ACK and STATUSTEXT messages.
+    fn default() -> Self {
+        let renderers: [(u32, RenderFn); 9] = [
+            (fields::HEARTBEAT_ID, render_heartbeat),
+            (fields::ATTITUDE_ID, render_attitude),
+            (fields::GPS_RAW_INT_ID, render_gps_raw_int),
+            (fields::GLOBAL_POSITION_INT_ID, render_global_position_int),
+            (fields::HOME_POSITION_ID, render_home_position),
+            (fields::COMMAND_INT_ID, render_command_int),
+            (fields::COMMAND_LONG_ID, render_command_long),
+            (fields::COMMAND_ACK_ID, render_command_ack),
+            (fields::STATUSTEXT_ID, render_statustext),
+        ];
+        TextRenderer {
+            renderers: renderers.into_iter().collect(),
+        }
+    }
+}
+
+impl TextRenderer {
+    /// Creates a new `TextRenderer` with the default renderers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the renderer of a message, replacing any previous one.
+    ///
+    /// # Arguments
+    /// - `message_id`: The id of the message.
+    /// - `renderer`: The renderer, following the layout of the message in the dialect used.
+    pub fn register(&mut self, message_id: u32, renderer: RenderFn) {
+        self.renderers.insert(message_id, renderer);
+    }
+
+    /// Renders a message as its name followed by its fields.
+    pub fn render<M: Message + Debug>(&self, msg: &M) -> String {
+        let rendered = self
+            .renderers
+            .get(&msg.message_id())
+            .and_then(|renderer| renderer(&MessageFields::new(msg)));
+        let mut text = String::from(msg.message_name());
+        match rendered {
+            Some(rendered) => write!(text, " {rendered}").unwrap(),
+            None => {
+                let fields: Vec<String> = fields::debug_fields(&format!("{msg:?}"))
+                    .into_iter()
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect();
+                if !fields.is_empty() {
+                    write!(text, " {}", fields.join(" ")).unwrap();
+                }
+            }
+        }
+        text
+    }
+
+    /// Renders an entry as a single line, as the `Display` implementation of `LogEntry` does
+    /// with its MAVLink message rendered by `render`.
+    pub fn render_entry<M: Message + Debug>(&self, entry: &LogEntry<M>) -> String {
+        struct Rendered<'a, M: Message>(&'a TextRenderer, &'a LogEntry<M>);
+
+        impl<M: Message + Debug> std::fmt::Display for Rendered<'_, M> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.1.fmt_with(f, |msg| self.0.render(msg))
+            }
+        }
+
+        Rendered(self, entry).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::MavHeader;
+    use mavlink::common::{
+        ATTITUDE_DATA, COMMAND_LONG_DATA, GLOBAL_POSITION_INT_DATA, HEARTBEAT_DATA, MavAutopilot,
+        MavCmd, MavMessage, MavModeFlag, MavSeverity, MavType, STATUSTEXT_DATA,
+    };
+
+    use super::*;
+
+    /// Test that modes are named from the autopilot and vehicle type.
+    #[test]
+    fn test_mode_name() {
+        assert_eq!(mode_name(3, 2, 0x81, 4).as_deref(), Some("GUIDED"));
+        assert_eq!(mode_name(3, 1, 0x01, 10).as_deref(), Some("AUTO"));
+        assert_eq!(mode_name(3, 10, 0x01, 4).as_deref(), Some("HOLD"));
+        assert_eq!(
+            mode_name(12, 2, 0x01, (4 << 16) | (4 << 24)).as_deref(),
+            Some("AUTO.MISSION")
+        );
+        assert_eq!(mode_name(12, 2, 0x01, 3 << 16).as_deref(), Some("POSCTL"));
+        assert_eq!(mode_name(0, 2, 216, 0).as_deref(), Some("GUIDED_ARMED"));
+        assert_eq!(mode_name(0, 2, 0x01, 4), None);
+    }
+
+    /// Test that key messages are rendered with units and names.
+    #[test]
+    fn test_render() {
+        let renderer = TextRenderer::new();
+        let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode: 5,
+            mavtype: MavType::MAV_TYPE_QUADROTOR,
+            autopilot: MavAutopilot::MAV_AUTOPILOT_ARDUPILOTMEGA,
+            base_mode: MavModeFlag::MAV_MODE_FLAG_CUSTOM_MODE_ENABLED
+                | MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED,
+            ..Default::default()
+        });
+        let text = renderer.render(&heartbeat);
+        assert!(
+            text.starts_with(
+                "HEARTBEAT type=MAV_TYPE_QUADROTOR autopilot=MAV_AUTOPILOT_ARDUPILOTMEGA \
+                 mode=LOITER armed=true status="
+            ),
+            "{text}"
+        );
+
+        let position = MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+            lat: 473_977_419,
+            lon: 85_455_938,
+            alt: 488_000,
+            relative_alt: 10_500,
+            vx: 150,
+            hdg: 9000,
+            ..Default::default()
+        });
+        assert_eq!(
+            renderer.render(&position),
+            "GLOBAL_POSITION_INT lat=47.3977419 lon=8.5455938 alt=488.00m relative_alt=10.50m \
+             velocity=[1.50, 0.00, 0.00]m/s hdg=90.00"
+        );
+
+        let attitude = MavMessage::ATTITUDE(ATTITUDE_DATA {
+            roll: std::f32::consts::FRAC_PI_2,
+            ..Default::default()
+        });
+        assert_eq!(
+            renderer.render(&attitude),
+            "ATTITUDE roll=90.00 pitch=0.00 yaw=0.00"
+        );
+
+        let command = MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command: MavCmd::MAV_CMD_NAV_TAKEOFF,
+            target_system: 1,
+            target_component: 1,
+            param7: 10.0,
+            ..Default::default()
+        });
+        assert_eq!(
+            renderer.render(&command),
+            "COMMAND_LONG MAV_CMD_NAV_TAKEOFF target=1/1 confirmation=0 \
+             params=[0, 0, 0, 0, 0, 0, 10]"
+        );
+
+        let mut text = [0u8; 50];
+        text[..9].copy_from_slice(b"Low batt!");
+        let status = MavMessage::STATUSTEXT(STATUSTEXT_DATA {
+            severity: MavSeverity::MAV_SEVERITY_WARNING,
+            text,
+            ..Default::default()
+        });
+        assert_eq!(
+            renderer.render(&status),
+            "STATUSTEXT MAV_SEVERITY_WARNING \"Low batt!\""
+        );
+    }
+
+    /// Test that entries are rendered on one line, with custom renderers and fallbacks.
+    #[test]
+    fn test_render_entry() {
+        let mut renderer = TextRenderer::new();
+        renderer.register(fields::ATTITUDE_ID, |_| Some(String::from("level")));
+        renderer.register(fields::STATUSTEXT_ID, |_| None);
+        let entry = LogEntry {
+            timestamp: Some(1_500_000),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 3,
+            }),
+            mav_message: Some(MavMessage::ATTITUDE(ATTITUDE_DATA::default())),
+            ..Default::default()
+        };
+        assert_eq!(
+            renderer.render_entry(&entry),
+            "1.500000 MAVLINK 1/1 seq=3 ATTITUDE level"
+        );
+        let status = MavMessage::STATUSTEXT(STATUSTEXT_DATA::default());
+        assert!(renderer.render(&status).starts_with("STATUSTEXT severity="));
+    }
+}
@@ -0,0 +1,283 @@
+//! Replay of logs paced by their entry timestamps.
+//!
+//! `Replay` wraps a parser and holds each entry back until it is due, so that consumers such as
+//! a ground station or a simulated link see the log at the rate it was recorded, or a multiple
+//! of it. Time is read from a `ReplayClock`: `WallClock` sleeps in real time, while
+//! `VirtualClock` jumps straight to the due time of each entry, so that tests replay a log
+//! faster than real time and always observe the same times.
+//!
+//! Entries can also be taken one at a time with `Replay::step` without waiting, such as from a
+//! debugger stepping through a flight. Paced replay then resumes from the stepped entry.
+//!
+//! ```
+//! # use mavlink_log::mav_parser::{LogEntry, MavParser};
+//! # use mavlink::common::MavMessage;
+//! # use mavlink::error::MessageReadError;
+//! # struct Log(Vec<u64>);
+//! # impl MavParser for Log {
+//! #     type M = MavMessage;
+//! #     fn parse_next_entry(&mut self) -> Result<LogEntry<MavMessage>, MessageReadError> {
+//! #         match self.0.pop() {
+//! #             Some(timestamp) => Ok(LogEntry { timestamp: Some(timestamp), ..Default::default() }),
+//! #             None => Err(MessageReadError::Io(std::io::ErrorKind::UnexpectedEof.into())),
+//! #         }
+//! #     }
+//! # }
+//! use std::time::Duration;
+//!
+//! use mavlink_log::mav_parser::for_each_entry;
+//! use mavlink_log::replay::{Replay, VirtualClock};
+//!
+//! // a ten second log, timestamps in microseconds
+//! let parser = Log(vec![10_000_000, 5_000_000, 0]);
+//! let mut replay = Replay::with_clock(parser, VirtualClock::default());
+//! replay.set_speed(2.0);
+//! for_each_entry(&mut replay, |_| Ok(())).unwrap();
+//! assert_eq!(replay.clock().elapsed(), Duration::from_secs(5));
+//! ```
+use std::time::{Duration, Instant};
+
+use mavlink::error::MessageReadError;
+
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Source of time pacing a replay.
+pub trait ReplayClock {
+    /// Returns the time elapsed since the clock started.
+    fn elapsed(&self) -> Duration;
+
+    /// Waits until the clock reads at least `deadline`. Returns immediately if it already does.
+    fn wait_until(&mut self, deadline: Duration);
+}
+
+/// Clock following real time, sleeping until entries are due.
+pub struct WallClock {
+    start: Instant,
+}
+
+impl Default for WallClock {
+    /// A clock started now.
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl ReplayClock for WallClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn wait_until(&mut self, deadline: Duration) {
+        let elapsed = self.start.elapsed();
+        if deadline > elapsed {
+            std::thread::sleep(deadline - elapsed);
+        }
+    }
+}
+
+/// Clock that only moves when waited on or advanced, for deterministic replays.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VirtualClock {
+    elapsed: Duration,
+}
+
+impl VirtualClock {
+    /// Creates a new `VirtualClock` reading the given time.
+    pub fn new(elapsed: Duration) -> Self {
+        Self { elapsed }
+    }
+
+    /// Moves the clock forward, such as to simulate time spent between steps.
+    pub fn advance(&mut self, duration: Duration) {
+        self.elapsed += duration;
+    }
+}
+
+impl ReplayClock for VirtualClock {
+    fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Jumps to the deadline without sleeping.
+    fn wait_until(&mut self, deadline: Duration) {
+        self.elapsed = self.elapsed.max(deadline);
+    }
+}
+
+/// Parser adapter yielding entries when they are due, see the module documentation.
+///
+/// Entries without a timestamp are yielded immediately. An entry with a timestamp earlier than
+/// the previous one is yielded immediately and paces the entries following it.
+pub struct Replay<P: MavParser, C: ReplayClock = WallClock> {
+    parser: P,
+    clock: C,
+    speed: f64,
+    /// Timestamp of the reference entry and the clock time it was yielded at.
+    anchor: Option<(u64, Duration)>,
+    /// Timestamp of the last entry yielded.
+    last_timestamp: Option<u64>,
+}
+
+impl<P: MavParser> Replay<P, WallClock> {
+    /// Creates a new `Replay` in real time.
+    ///
+    /// # Arguments
+    /// - `parser`: The parser to read entries from.
+    pub fn new(parser: P) -> Self {
+        Self::with_clock(parser, WallClock::default())
+    }
+}
+
+impl<P: MavParser, C: ReplayClock> Replay<P, C> {
+    /// Creates a new `Replay` paced by the given clock.
+    ///
+    /// # Arguments
+    /// - `parser`: The parser to read entries from.
+    /// - `clock`: The clock to wait on, such as a `VirtualClock` in tests.
+    pub fn with_clock(parser: P, clock: C) -> Self {
+        Self {
+            parser,
+            clock,
+            speed: 1.0,
+            anchor: None,
+            last_timestamp: None,
+        }
+    }
+
+    /// Sets the replay speed as a multiple of the recorded rate, from the last entry yielded on.
+    ///
+    /// # Arguments
+    /// - `speed`: The speed, such as 2.0 to replay twice as fast. Infinity replays without
+    ///   waiting.
+    ///
+    /// # Panics
+    /// Panics if the speed is not positive.
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(speed > 0.0, "The replay speed must be positive");
+        if let Some(timestamp) = self.last_timestamp {
+            self.anchor = Some((timestamp, self.clock.elapsed()));
+        }
+        self.speed = speed;
+    }
+
+    /// Returns the clock pacing the replay.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Returns the clock pacing the replay, such as to advance a `VirtualClock` between steps.
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Returns the wrapped parser.
+    pub fn into_inner(self) -> P {
+        self.parser
+    }
+
+    /// Reads the next entry without waiting for it to be due.
+    ///
+    /// Paced replay continues from this entry, as if it was due at the current clock time.
+    ///
+    /// # Errors
+    /// Returns the errors of the wrapped parser unchanged.
+    pub fn step(&mut self) -> Result<LogEntry<P::M>, MessageReadError> {
+        let entry = self.parser.parse_next_entry()?;
+        if let Some(timestamp) = entry.timestamp {
+            self.anchor = Some((timestamp, self.clock.elapsed()));
+            self.last_timestamp = Some(timestamp);
+        }
+        Ok(entry)
+    }
+}
+
+impl<P: MavParser, C: ReplayClock> MavParser for Replay<P, C> {
+    type M = P::M;
+
+    /// Reads the next entry once it is due.
+    ///
+    /// # Errors
+    /// Returns the errors of the wrapped parser unchanged, without waiting.
+    fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+        let entry = self.parser.parse_next_entry()?;
+        let Some(timestamp) = entry.timestamp else {
+            return Ok(entry);
+        };
+        match self.anchor {
+            Some((anchor_timestamp, anchor_time)) if timestamp >= anchor_timestamp => {
+                let offset = (timestamp - anchor_timestamp) as f64 / 1e6 / self.speed;
+                self.clock
+                    .wait_until(anchor_time + Duration::from_secs_f64(offset));
+            }
+            _ => self.anchor = Some((timestamp, self.clock.elapsed())),
+        }
+        self.last_timestamp = Some(timestamp);
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::MavMessage;
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entries(timestamps: &[u64]) -> EntryList {
+        EntryList(
+            timestamps
+                .iter()
+                .map(|&timestamp| LogEntry {
+                    timestamp: Some(timestamp),
+                    ..Default::default()
+                })
+                .collect(),
+        )
+    }
+
+    /// Test that entries are yielded at their due time on a virtual clock, including after
+    /// stepping and changing speed.
+    #[test]
+    fn test_replay_virtual_clock() {
+        let start = 1_700_000_000_000_000;
+        let log = entries(&[
+            start,
+            start + 1_000_000,
+            start + 3_000_000,
+            start + 7_000_000,
+        ]);
+        let mut replay = Replay::with_clock(log, VirtualClock::new(Duration::from_secs(10)));
+        let mut times = Vec::new();
+        while let Ok(entry) = replay.parse_next_entry() {
+            times.push((entry.timestamp.unwrap() - start, replay.clock().elapsed()));
+        }
+        assert_eq!(
+            times,
+            vec![
+                (0, Duration::from_secs(10)),
+                (1_000_000, Duration::from_secs(11)),
+                (3_000_000, Duration::from_secs(13)),
+                (7_000_000, Duration::from_secs(17)),
+            ]
+        );
+
+        let log = entries(&[
+            start,
+            start + 1_000_000,
+            start + 3_000_000,
+            start + 7_000_000,
+        ]);
+        let mut replay = Replay::with_clock(log, VirtualClock::default());
+        replay.step().unwrap();
+        replay.step().unwrap();
+        assert_eq!(replay.clock().elapsed(), Duration::ZERO);
+        replay.clock_mut().advance(Duration::from_millis(500));
+        replay.parse_next_entry().unwrap();
+        assert_eq!(replay.clock().elapsed(), Duration::from_secs(2));
+        replay.set_speed(4.0);
+        replay.parse_next_entry().unwrap();
+        assert_eq!(replay.clock().elapsed(), Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,559 @@
+//! Single file HTML flight reports.
+//!
+//! A report summarizes a log in one self-contained HTML page: the map track, plots of key
+//! channels, the weather, the STATUSTEXT timeline, the last value of every parameter and the link
+//! quality.
+//! Plots are embedded as SVG so the page needs no scripts or network access to display.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use mavlink::Message;
+
+use crate::analysis::analyzer::{Analyzer, analyze};
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser};
+use crate::weather::WeatherObservation;
+
+/// Maximum number of points drawn per plot. Longer series are thinned out evenly.
+const MAX_PLOT_POINTS: usize = 2000;
+/// Width of the embedded plots in pixels.
+const PLOT_WIDTH: f64 = 800.0;
+/// Height of the embedded time series plots in pixels.
+const PLOT_HEIGHT: f64 = 200.0;
+/// Size of the map track plot in pixels.
+const MAP_SIZE: f64 = 500.0;
+/// Margin around the plotted data in pixels.
+const PLOT_MARGIN: f64 = 40.0;
+/// Maximum length of the text of a STATUSTEXT.
+const STATUSTEXT_LEN: usize = 50;
+/// Maximum length of a parameter id.
+const PARAM_ID_LEN: usize = 16;
+/// SYS_STATUS battery voltage when it is not reported.
+const VOLTAGE_UNKNOWN: u16 = u16::MAX;
+/// Names of the MAV_SEVERITY values.
+const SEVERITIES: [&str; 8] = [
+    "EMERGENCY",
+    "ALERT",
+    "CRITICAL",
+    "ERROR",
+    "WARNING",
+    "NOTICE",
+    "INFO",
+    "DEBUG",
+];
+
+/// A STATUSTEXT message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusText {
+    pub timestamp_us: Option<u64>,
+    pub system_id: u8,
+    pub component_id: u8,
+    /// MAV_SEVERITY of the message.
+    pub severity: u8,
+    pub text: String,
+}
+
+/// Data of a log needed to render a flight report.
+///
+/// Time series hold (timestamp in microseconds, value) samples.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FlightReport {
+    /// Timestamp of the first timestamped entry.
+    pub start_us: Option<u64>,
+    /// Timestamp of the last timestamped entry.
+    pub end_us: Option<u64>,
+    /// Positions from GLOBAL_POSITION_INT as (latitude, longitude) in degrees.
+    pub track: Vec<(f64, f64)>,
+    /// Altitude above home from GLOBAL_POSITION_INT, in meters.
+    pub relative_altitude_m: Vec<(u64, f64)>,
+    /// Groundspeed from VFR_HUD, in meters per second.
+    pub groundspeed_m_s: Vec<(u64, f64)>,
+    /// Battery voltage from SYS_STATUS, in volts.
+    pub battery_voltage_v: Vec<(u64, f64)>,
+    /// Local signal strength from RADIO_STATUS.
+    pub rssi: Vec<(u64, f64)>,
+    /// Remote signal strength from RADIO_STATUS.
+    pub remote_rssi: Vec<(u64, f64)>,
+    /// Communication drop rate from SYS_STATUS, in percent.
+    pub comm_drop_rate_pct: Vec<(u64, f64)>,
+    /// Wind speed estimated by the autopilot from WIND_COV, in meters per second.
+    pub wind_speed_m_s: Vec<(u64, f64)>,
+    /// All weather entries in log order with their timestamp, if any.
+    pub weather: Vec<(Option<u64>, WeatherObservation)>,
+    /// All STATUSTEXT messages in log order.
+    pub status_texts: Vec<StatusText>,
+    /// Last value of every parameter by system id and parameter id.
+    pub parameters: BTreeMap<(u8, String), f32>,
+}
+
+impl FlightReport {
+    /// Reads a full log and collects the data of its flight report.
+    ///
+    /// Time series only include timestamped messages. A default `FlightReport` is also an
+    /// `Analyzer` collecting the same data, to build a report along with other analyses.
+    ///
+    /// # Arguments
+    /// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the log could not be read.
+    pub fn collect<P: MavParser + ?Sized>(parser: &mut P) -> std::io::Result<Self> {
+        analyze(parser, FlightReport::default())
+    }
+
+    /// Renders the report as a self-contained HTML page.
+    ///
+    /// # Arguments
+    /// - `title`: The title of the page, usually the name of the log.
+    ///
+    /// # Returns
+    /// The HTML page.
+    pub fn to_html(&self, title: &str) -> String {
+        let mut html = String::new();
+        let title = escape(title);
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+             td,th{{border:1px solid #ccc;padding:2px 8px;text-align:left}}\
+             svg{{background:#fafafa;border:1px solid #ccc}}</style>\n</head>\n<body>\n\
+             <h1>{title}</h1>\n"
+        );
+        if let (Some(start), Some(end)) = (self.start_us, self.end_us) {
+            let _ = writeln!(
+                html,
+                "<p>Duration: {:.1} s</p>",
+                end.saturating_sub(start) as f64 / 1e6
+            );
+        }
+
+        html.push_str("<h2>Track</h2>\n");
+        html.push_str(&self.track_svg());
+        html.push_str("<h2>Flight</h2>\n");
+        html.push_str(&self.plot_svg("Altitude above home (m)", &[&self.relative_altitude_m]));
+        html.push_str(&self.plot_svg("Groundspeed (m/s)", &[&self.groundspeed_m_s]));
+        html.push_str(&self.plot_svg("Battery voltage (V)", &[&self.battery_voltage_v]));
+        html.push_str("<h2>Weather</h2>\n");
+        html.push_str(&self.plot_svg("Wind speed (m/s)", &[&self.wind_speed_m_s]));
+        html.push_str(&self.weather_table());
+        html.push_str("<h2>Link Quality</h2>\n");
+        html.push_str(&self.plot_svg("RSSI, local and remote", &[&self.rssi, &self.remote_rssi]));
+        html.push_str(&self.plot_svg("Communication drop rate (%)", &[&self.comm_drop_rate_pct]));
+
+        html.push_str("<h2>Status Messages</h2>\n<table>\n");
+        html.push_str("<tr><th>Time (s)</th><th>Source</th><th>Severity</th><th>Text</th></tr>\n");
+        for status_text in &self.status_texts {
+            let time = status_text
+                .timestamp_us
+                .map(|timestamp| format!("{:.3}", self.seconds(timestamp)))
+                .unwrap_or_default();
+            let severity = SEVERITIES
+                .get(status_text.severity as usize)
+                .copied()
+                .unwrap_or("UNKNOWN");
+            let _ = writeln!(
+                html,
+                "<tr><td>{time}</td><td>{}/{}</td><td>{severity}</td><td>{}</td></tr>",
+                status_text.system_id,
+                status_text.component_id,
+                escape(&status_text.text)
+            );
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Parameters</h2>\n<table>\n");
+        html.push_str("<tr><th>System</th><th>Name</th><th>Value</th></tr>\n");
+        for ((system_id, id), value) in &self.parameters {
+            let _ = writeln!(
+                html,
+                "<tr><td>{system_id}</td><td>{}</td><td>{value}</td></tr>",
+                escape(id)
+            );
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Renders the weather entries as a table, one row per observation.
+    fn weather_table(&self) -> String {
+        if self.weather.is_empty() {
+            return String::from("<p>No weather observations.</p>\n");
+        }
+        let mut table = String::from(
+            "<table>\n<tr><th>Time (s)</th><th>Wind (m/s)</th><th>Wind from (deg)</th>\
+             <th>Temperature (C)</th><th>Humidity (%)</th><th>Note</th></tr>\n",
+        );
+        let value =
+            |value: Option<f32>| value.map(|value| format!("{value:.1}")).unwrap_or_default();
+        for (timestamp, weather) in &self.weather {
+            let time = timestamp
+                .map(|timestamp| format!("{:.3}", self.seconds(timestamp)))
+                .unwrap_or_default();
+            let _ = writeln!(
+                table,
+                "<tr><td>{time}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                value(weather.wind_speed_m_s),
+                value(weather.wind_direction_deg),
+                value(weather.temperature_c),
+                value(weather.humidity_pct),
+                escape(&weather.note)
+            );
+        }
+        table.push_str("</table>\n");
+        table
+    }
+
+    /// Converts a timestamp to seconds since the start of the log.
+    fn seconds(&self, timestamp: u64) -> f64 {
+        timestamp.saturating_sub(self.start_us.unwrap_or(0)) as f64 / 1e6
+    }
+
+    /// Renders the track as an SVG polyline with north up.
+    fn track_svg(&self) -> String {
+        if self.track.is_empty() {
+            return String::from("<p>No position data.</p>\n");
+        }
+        let (min_lat, max_lat) = bounds(self.track.iter().map(|p| p.0));
+        let (min_lon, max_lon) = bounds(self.track.iter().map(|p| p.1));
+        // scale longitude to the same ground distance as latitude
+        let lon_scale = ((min_lat + max_lat) / 2.0).to_radians().cos();
+        let extent = ((max_lat - min_lat).max((max_lon - min_lon) * lon_scale)).max(1e-9);
+        let scale = (MAP_SIZE - 2.0 * PLOT_MARGIN) / extent;
+        let points = thin(&self.track)
+            .map(|&(lat, lon)| {
+                let x = PLOT_MARGIN + (lon - min_lon) * lon_scale * scale;
+                let y = MAP_SIZE - PLOT_MARGIN - (lat - min_lat) * scale;
+                format!("{x:.1},{y:.1}")
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        format!(
+            "<svg width=\"{MAP_SIZE}\" height=\"{MAP_SIZE}\" xmlns=\"http://www.w3.org/2000/svg\">\
+             <polyline fill=\"none\" stroke=\"#1f77b4\" stroke-width=\"2\" points=\"{points}\"/>\
+             <text x=\"5\" y=\"15\" font-size=\"12\">{max_lat:.6}, {min_lon:.6}</text>\
+             <text x=\"5\" y=\"{}\" font-size=\"12\">{min_lat:.6}</text></svg>\n",
+            MAP_SIZE - 5.0
+        )
+    }
+
+    /// Renders time series as SVG polylines sharing their axes.
+    fn plot_svg(&self, title: &str, series: &[&Vec<(u64, f64)>]) -> String {
+        const COLORS: [&str; 2] = ["#1f77b4", "#ff7f0e"];
+        if series.iter().all(|samples| samples.is_empty()) {
+            return format!("<h3>{}</h3>\n<p>No data.</p>\n", escape(title));
+        }
+        let samples = || series.iter().flat_map(|samples| samples.iter());
+        let (min_t, max_t) = bounds(samples().map(|sample| self.seconds(sample.0)));
+        let (min_v, max_v) = bounds(samples().map(|sample| sample.1));
+        let t_range = (max_t - min_t).max(1e-9);
+        let v_range = (max_v - min_v).max(1e-9);
+        let plot_width = PLOT_WIDTH - 2.0 * PLOT_MARGIN;
+        let plot_height = PLOT_HEIGHT - 2.0 * PLOT_MARGIN;
+        let mut svg = format!(
+            "<h3>{}</h3>\n<svg width=\"{PLOT_WIDTH}\" height=\"{PLOT_HEIGHT}\" \
+             xmlns=\"http://www.w3.org/2000/svg\">",
+            escape(title)
+        );
+        for (samples, color) in series.iter().zip(COLORS.iter().cycle()) {
+            let points = thin(samples)
+                .map(|&(timestamp, value)| {
+                    let x = PLOT_MARGIN + (self.seconds(timestamp) - min_t) / t_range * plot_width;
+                    let y = PLOT_HEIGHT - PLOT_MARGIN - (value - min_v) / v_range * plot_height;
+                    format!("{x:.1},{y:.1}")
+                })
+                .collect::<Vec<String>>()
+                .join(" ");
+            let _ = write!(
+                svg,
+                "<polyline fill=\"none\" stroke=\"{color}\" stroke-width=\"1.5\" \
+                 points=\"{points}\"/>"
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<text x=\"5\" y=\"{top}\" font-size=\"12\">{max_v:.2}</text>\
+             <text x=\"5\" y=\"{bottom}\" font-size=\"12\">{min_v:.2}</text>\
+             <text x=\"{PLOT_MARGIN}\" y=\"{time}\" font-size=\"12\">{min_t:.1} s</text>\
+             <text x=\"{right}\" y=\"{time}\" font-size=\"12\">{max_t:.1} s</text></svg>",
+            top = PLOT_MARGIN,
+            bottom = PLOT_HEIGHT - PLOT_MARGIN,
+            time = PLOT_HEIGHT - 10.0,
+            right = PLOT_WIDTH - PLOT_MARGIN - 40.0,
+        );
+        svg
+    }
+}
+
+impl<M: Message> Analyzer<M> for FlightReport {
+    type Report = FlightReport;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        if let Some(timestamp) = entry.timestamp {
+            self.start_us.get_or_insert(timestamp);
+            self.end_us = Some(timestamp);
+        }
+        if let Some(weather) = &entry.weather {
+            self.weather.push((entry.timestamp, weather.clone()));
+            return;
+        }
+        let (header, msg) = match (&entry.mav_header, &entry.mav_message) {
+            (Some(header), Some(msg)) => (header, msg),
+            _ => return,
+        };
+        let payload = fields::payload(msg);
+        match msg.message_id() {
+            fields::STATUSTEXT_ID => self.status_texts.push(StatusText {
+                timestamp_us: entry.timestamp,
+                system_id: header.system_id,
+                component_id: header.component_id,
+                severity: fields::read_u8(&payload, 0),
+                text: null_terminated(&payload[1..1 + STATUSTEXT_LEN]),
+            }),
+            fields::PARAM_VALUE_ID => {
+                let id = null_terminated(&payload[8..8 + PARAM_ID_LEN]);
+                let value = fields::read_f32(&payload, 0);
+                self.parameters.insert((header.system_id, id), value);
+            }
+            fields::GLOBAL_POSITION_INT_ID => {
+                let latitude_deg = fields::read_i32(&payload, 4) as f64 / 1e7;
+                let longitude_deg = fields::read_i32(&payload, 8) as f64 / 1e7;
+                // autopilots without a position estimate report 0, 0
+                if latitude_deg != 0.0 || longitude_deg != 0.0 {
+                    self.track.push((latitude_deg, longitude_deg));
+                }
+                if let Some(timestamp) = entry.timestamp {
+                    let altitude_m = fields::read_i32(&payload, 16) as f64 / 1e3;
+                    self.relative_altitude_m.push((timestamp, altitude_m));
+                }
+            }
+            fields::VFR_HUD_ID => {
+                if let Some(timestamp) = entry.timestamp {
+                    let groundspeed = fields::read_f32(&payload, 4) as f64;
+                    self.groundspeed_m_s.push((timestamp, groundspeed));
+                }
+            }
+            fields::SYS_STATUS_ID => {
+                if let Some(timestamp) = entry.timestamp {
+                    let voltage = fields::read_u16(&payload, 14);
+                    if voltage != VOLTAGE_UNKNOWN {
+                        self.battery_voltage_v
+                            .push((timestamp, voltage as f64 / 1e3));
+                    }
+                    let drop_rate = fields::read_u16(&payload, 18) as f64 / 1e2;
+                    self.comm_drop_rate_pct.push((timestamp, drop_rate));
+                }
+            }
+            fields::RADIO_STATUS_ID => {
+                if let Some(timestamp) = entry.timestamp {
+                    self.rssi
+                        .push((timestamp, fields::read_u8(&payload, 4) as f64));
+                    self.remote_rssi
+                        .push((timestamp, fields::read_u8(&payload, 5) as f64));
+                }
+            }
+            fields::WIND_COV_ID => {
+                let north = fields::read_f32(&payload, 8) as f64;
+                let east = fields::read_f32(&payload, 12) as f64;
+                // autopilots without a wind estimate report NaN
+                if let Some(timestamp) = entry
+                    .timestamp
+                    .filter(|_| north.is_finite() && east.is_finite())
+                {
+                    self.wind_speed_m_s.push((timestamp, north.hypot(east)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> FlightReport {
+        self
+    }
+}
+
+/// Reads a full log and writes its flight report to an HTML file.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `title`: The title of the report.
+/// - `path`: Path of the HTML file to create.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the file could not be written.
+pub fn write_report<P: MavParser + ?Sized>(
+    parser: &mut P,
+    title: &str,
+    path: &str,
+) -> std::io::Result<()> {
+    let report = FlightReport::collect(parser)?;
+    std::fs::write(path, report.to_html(title))
+}
+
+/// Decodes a null padded string field.
+fn null_terminated(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&x| x == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Escapes text for inclusion in HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the minimum and maximum of values.
+fn bounds(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    })
+}
+
+/// Iterates over at most `MAX_PLOT_POINTS` evenly spaced items.
+fn thin<T>(items: &[T]) -> impl Iterator<Item = &T> {
+    let step = items.len().div_ceil(MAX_PLOT_POINTS).max(1);
+    items.iter().step_by(step)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::MavHeader;
+    use mavlink::common::{
+        GLOBAL_POSITION_INT_DATA, MavMessage, MavSeverity, PARAM_VALUE_DATA, STATUSTEXT_DATA,
+        SYS_STATUS_DATA,
+    };
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    fn fixed<const N: usize>(text: &str) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        bytes[..text.len()].copy_from_slice(text.as_bytes());
+        bytes
+    }
+
+    fn sample_log() -> EntryList {
+        let mut entries: VecDeque<LogEntry<MavMessage>> = VecDeque::new();
+        entries.push_back(entry(
+            1_000_000,
+            MavMessage::PARAM_VALUE(PARAM_VALUE_DATA {
+                param_id: fixed("BATT_CAPACITY"),
+                param_value: 3300.0,
+                ..Default::default()
+            }),
+        ));
+        for i in 0..10u64 {
+            entries.push_back(entry(
+                1_000_000 + i * 1_000_000,
+                MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                    lat: 470_000_000 + i as i32 * 100,
+                    lon: 80_000_000 + i as i32 * 100,
+                    relative_alt: i as i32 * 1000,
+                    ..Default::default()
+                }),
+            ));
+            entries.push_back(entry(
+                1_000_000 + i * 1_000_000,
+                MavMessage::SYS_STATUS(SYS_STATUS_DATA {
+                    voltage_battery: 12600 - i as u16 * 10,
+                    ..Default::default()
+                }),
+            ));
+        }
+        entries.push_back(entry(
+            5_500_000,
+            MavMessage::STATUSTEXT(STATUSTEXT_DATA {
+                severity: MavSeverity::MAV_SEVERITY_WARNING,
+                text: fixed("Battery <low>"),
+                ..Default::default()
+            }),
+        ));
+        // between the first and second positions
+        entries.insert(
+            3,
+            LogEntry {
+                timestamp: Some(2_000_000),
+                weather: Some(WeatherObservation {
+                    temperature_c: Some(24.0),
+                    note: String::from("Drift towards <field 4>"),
+                    ..WeatherObservation::from_wind(0.0, 3.0)
+                }),
+                ..Default::default()
+            },
+        );
+        EntryList(entries)
+    }
+
+    /// Test that the report data is collected from the log.
+    #[test]
+    fn test_collect() {
+        let report = FlightReport::collect(&mut sample_log()).unwrap();
+        assert_eq!(report.start_us, Some(1_000_000));
+        assert_eq!(report.end_us, Some(5_500_000));
+        assert_eq!(report.track.len(), 10);
+        assert_eq!(report.relative_altitude_m[9], (10_000_000, 9.0));
+        assert_eq!(report.battery_voltage_v[0], (1_000_000, 12.6));
+        assert_eq!(report.status_texts.len(), 1);
+        assert_eq!(report.status_texts[0].text, "Battery <low>");
+        assert_eq!(report.status_texts[0].severity, 4);
+        assert_eq!(
+            report.parameters.get(&(1, String::from("BATT_CAPACITY"))),
+            Some(&3300.0)
+        );
+        assert!(report.rssi.is_empty());
+        assert_eq!(report.weather.len(), 1);
+        assert_eq!(report.weather[0].0, Some(2_000_000));
+    }
+
+    /// Test that the report is rendered as a self-contained HTML page.
+    #[test]
+    fn test_to_html() {
+        let report = FlightReport::collect(&mut sample_log()).unwrap();
+        let html = report.to_html("Flight <1>");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Flight &lt;1&gt;</title>"));
+        assert!(html.contains("<td>WARNING</td><td>Battery &lt;low&gt;</td>"));
+        assert!(html.contains("<td>BATT_CAPACITY</td><td>3300</td>"));
+        assert!(html.contains("<td>4.500</td>"));
+        assert_eq!(html.matches("<polyline").count(), 4);
+        assert!(html.contains("<h3>Groundspeed (m/s)</h3>\n<p>No data.</p>"));
+        assert!(html.contains(
+            "<tr><td>1.000</td><td>3.0</td><td>270.0</td><td>24.0</td><td></td>\
+             <td>Drift towards &lt;field 4&gt;</td></tr>"
+        ));
+        assert!(!html.contains("<script"));
+    }
+
+    /// Test that long series are thinned out to a bounded number of points.
+    #[test]
+    fn test_thin() {
+        let items: Vec<u32> = (0..10_000).collect();
+        let thinned: Vec<&u32> = thin(&items).collect();
+        assert_eq!(thinned.len(), 2000);
+        assert_eq!(*thinned[1], 5);
+        assert_eq!(thin(&items[..10]).count(), 10);
+    }
+}
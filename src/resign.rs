@@ -0,0 +1,377 @@
+//! Re-signing of MAVLink 2 frames for links that require message signing.
+//!
+//! Frames read from a log keep the signature of the link they were recorded on, if any. A
+//! receiver holding another key rejects them, and their signing timestamps are long past. When
+//! a log is replayed or converted to a link that requires signing, `Resigner` strips the old
+//! signature of each frame and signs it again with the secret key and link id of the new link,
+//! timestamped from the current time as the MAVLink signing specification requires.
+//! `ResigningLogger` applies it to every frame written to a logger, such as one writing to the
+//! link. See <https://mavlink.io/en/guide/message_signing.html> for the signing specification.
+use std::marker::PhantomData;
+
+use mavlink::{MAV_STX_V2, MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavFrame, Message};
+use sha2::{Digest, Sha256};
+
+use crate::clock::{BackwardsPolicy, Clock, ClockSource};
+use crate::frame::{self, FrameError};
+use crate::mav_logger::{MavFrameLogger, MavLogger};
+
+/// Start of the MAVLink signing timestamps, 2015-01-01T00:00:00Z, in microseconds since the
+/// Unix epoch.
+pub const SIGNING_EPOCH_US: u64 = 1_420_070_400_000_000;
+/// Number of signature bytes following the link id and timestamp of a signed frame.
+const SIGNATURE_HASH_SIZE: usize = 6;
+/// Number of bytes of the signing timestamp.
+const SIGNING_TIMESTAMP_SIZE: usize = 6;
+
+/// The signing configuration of a link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkKey {
+    /// The secret key shared by the ends of the link.
+    pub secret_key: [u8; 32],
+    /// The id of the link the frames are signed for.
+    pub link_id: u8,
+}
+
+/// Converts a time to a MAVLink signing timestamp.
+///
+/// # Arguments
+/// - `unix_us`: The time in microseconds since the Unix epoch.
+///
+/// # Returns
+/// The time in units of 10 microseconds since `SIGNING_EPOCH_US`, or 0 for earlier times.
+pub fn signing_timestamp(unix_us: u64) -> u64 {
+    unix_us.saturating_sub(SIGNING_EPOCH_US) / 10
+}
+
+/// Computes the signature of a signed frame with a secret key.
+fn signature_hash(secret_key: &[u8; 32], signed_bytes: &[u8]) -> [u8; SIGNATURE_HASH_SIZE] {
+    let digest = Sha256::new()
+        .chain_update(secret_key)
+        .chain_update(signed_bytes)
+        .finalize();
+    digest[..SIGNATURE_HASH_SIZE].try_into().unwrap()
+}
+
+/// Checks whether a frame is signed with a key.
+///
+/// # Arguments
+/// - `key`: The signing configuration of the link.
+/// - `bytes`: Exactly the bytes of one MAVLink 1 or MAVLink 2 frame.
+///
+/// # Returns
+/// `true` if the frame is a signed MAVLink 2 frame with the link id of `key` and a signature
+/// made with its secret key. The signing timestamp is not checked.
+pub fn verify(key: &LinkKey, bytes: &[u8]) -> bool {
+    if !frame::is_complete(bytes) || bytes[0] != MAV_STX_V2 || bytes[2] & frame::IFLAG_SIGNED == 0 {
+        return false;
+    }
+    let hash_start = bytes.len() - SIGNATURE_HASH_SIZE;
+    let link_id = bytes[hash_start - SIGNING_TIMESTAMP_SIZE - 1];
+    link_id == key.link_id
+        && signature_hash(&key.secret_key, &bytes[..hash_start]) == bytes[hash_start..]
+}
+
+/// Strips and replaces the signatures of MAVLink 2 frames, see the module documentation.
+#[derive(Clone, Debug)]
+pub struct Resigner {
+    key: Option<LinkKey>,
+    /// The last signing timestamp used, which following timestamps must exceed.
+    last_timestamp: Option<u64>,
+}
+
+impl Resigner {
+    /// Creates a new `Resigner` signing frames with a key.
+    ///
+    /// # Arguments
+    /// - `key`: The signing configuration of the link the frames are sent on.
+    pub fn new(key: LinkKey) -> Self {
+        Self {
+            key: Some(key),
+            last_timestamp: None,
+        }
+    }
+
+    /// Creates a new `Resigner` stripping the signature of frames without signing them again,
+    /// for links that do not accept signed frames.
+    pub fn strip() -> Self {
+        Self {
+            key: None,
+            last_timestamp: None,
+        }
+    }
+
+    /// Returns the signing configuration frames are signed with, or `None` if signatures are
+    /// only stripped.
+    pub fn key(&self) -> Option<&LinkKey> {
+        self.key.as_ref()
+    }
+
+    /// Replaces the signature of a frame.
+    ///
+    /// The signature of a MAVLink 2 frame, if any, is removed and the checksum recomputed for
+    /// the new incompatibility flags. The frame is then signed with the timestamp of `unix_us`,
+    /// or one more than the previous timestamp if that is not later, so that the timestamps of
+    /// the link always increase as the specification requires. MAVLink 1 frames cannot be
+    /// signed and are returned unchanged.
+    ///
+    /// # Arguments
+    /// - `bytes`: Exactly the bytes of one MAVLink 1 or MAVLink 2 frame.
+    /// - `unix_us`: The time the frame is sent at, in microseconds since the Unix epoch.
+    ///
+    /// # Type Parameters
+    /// - `M`: The dialect of the frame, which provides the checksum seed of its message.
+    ///
+    /// # Errors
+    /// Returns `FrameError::Invalid` if the bytes are not a single frame with a valid checksum,
+    /// which includes MAVLink 2 frames of messages missing from the dialect.
+    pub fn resign<M: Message>(
+        &mut self,
+        bytes: &[u8],
+        unix_us: u64,
+    ) -> Result<Vec<u8>, FrameError> {
+        if !frame::is_complete(bytes) {
+            return Err(FrameError::Invalid);
+        }
+        if bytes[0] != MAV_STX_V2 {
+            return Ok(bytes.to_vec());
+        }
+        let (checksum_start, checksum) = frame::checksum::<M>(bytes).ok_or(FrameError::Invalid)?;
+        if bytes[checksum_start..checksum_start + 2] != checksum.to_le_bytes() {
+            return Err(FrameError::Invalid);
+        }
+
+        // the checksum covers the incompatibility flags, it is computed again once they change
+        let mut resigned: Vec<u8> = bytes[..checksum_start + 2].to_vec();
+        match self.key {
+            Some(_) => resigned[2] |= frame::IFLAG_SIGNED,
+            None => resigned[2] &= !frame::IFLAG_SIGNED,
+        }
+        let (_, checksum) = frame::checksum::<M>(&resigned).ok_or(FrameError::Invalid)?;
+        resigned[checksum_start..].copy_from_slice(&checksum.to_le_bytes());
+        if let Some(key) = &self.key {
+            let timestamp = match self.last_timestamp {
+                Some(last) => signing_timestamp(unix_us).max(last + 1),
+                None => signing_timestamp(unix_us),
+            };
+            self.last_timestamp = Some(timestamp);
+            resigned.push(key.link_id);
+            resigned.extend_from_slice(&timestamp.to_le_bytes()[..SIGNING_TIMESTAMP_SIZE]);
+            let hash = signature_hash(&key.secret_key, &resigned);
+            resigned.extend_from_slice(&hash);
+        }
+        Ok(resigned)
+    }
+}
+
+/// A logger re-signing every frame before writing it to another logger.
+///
+/// Frames are timestamped for signing by the wall clock when they are written, unless another
+/// clock is set with `set_clock`.
+///
+/// # Type Parameters
+/// - `L`: The logger the re-signed frames are written to.
+/// - `M`: The dialect of the frames written with `write_frame`. Frames written with
+///   `write_mavlink` use the dialect of their message.
+pub struct ResigningLogger<L: MavFrameLogger, M: Message> {
+    inner: L,
+    resigner: Resigner,
+    clock: Clock,
+    _phantom: PhantomData<M>,
+}
+
+impl<L: MavFrameLogger, M: Message> ResigningLogger<L, M> {
+    /// Creates a new `ResigningLogger`.
+    ///
+    /// # Arguments
+    /// - `inner`: The logger to write the re-signed frames to.
+    /// - `resigner`: The `Resigner` replacing the signature of each frame.
+    pub fn new(inner: L, resigner: Resigner) -> Self {
+        Self {
+            inner,
+            resigner,
+            clock: Clock::new(ClockSource::Wall, BackwardsPolicy::Hold),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets the clock signing timestamps are read from.
+    ///
+    /// # Arguments
+    /// - `clock`: The clock to read the time frames are sent at from. It should count
+    ///   microseconds since the Unix epoch.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Returns the wrapped logger.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+
+    /// Re-signs a frame of a dialect and writes it to the wrapped logger.
+    fn write_resigned<D: Message>(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        let unix_us = self.clock.now_us()?;
+        let resigned = self.resigner.resign::<D>(bytes, unix_us).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The data is not a single valid MAVLink frame",
+            )
+        })?;
+        self.inner.write_frame(&resigned)
+    }
+}
+
+impl<L: MavFrameLogger, M: Message> MavFrameLogger for ResigningLogger<L, M> {
+    /// Re-signs a serialized MAVLink frame and writes it to the wrapped logger.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` of kind `InvalidInput` if the bytes are not a single frame with a
+    /// valid checksum, or the error of the wrapped logger.
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.write_resigned::<M>(frame)
+    }
+}
+
+impl<L: MavFrameLogger, M: Message> MavLogger for ResigningLogger<L, M> {
+    /// Serializes a MAVLink message, signs it and writes it to the wrapped logger.
+    fn write_mavlink<D: Message>(&mut self, frame: MavFrame<D>) -> std::io::Result<()> {
+        match frame.protocol_version {
+            mavlink::MavlinkVersion::V1 => {
+                let mut msg = MAVLinkV1MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                self.write_resigned::<D>(msg.raw_bytes())
+            }
+            mavlink::MavlinkVersion::V2 => {
+                let mut msg = MAVLinkV2MessageRaw::new();
+                msg.serialize_message(frame.header, &frame.msg);
+                self.write_resigned::<D>(msg.raw_bytes())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use mavlink::MavHeader;
+    use mavlink::common::{MavMessage, SYS_STATUS_DATA};
+
+    use super::*;
+
+    /// Logger collecting the frames written to it.
+    #[derive(Default)]
+    struct FrameList(Vec<Vec<u8>>);
+
+    impl MavFrameLogger for FrameList {
+        fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+            self.0.push(frame.to_vec());
+            Ok(())
+        }
+    }
+
+    fn key(secret: u8, link_id: u8) -> LinkKey {
+        LinkKey {
+            secret_key: [secret; 32],
+            link_id,
+        }
+    }
+
+    fn v2_frame() -> Vec<u8> {
+        let mut msg = MAVLinkV2MessageRaw::new();
+        msg.serialize_message(
+            MavHeader::default(),
+            &MavMessage::SYS_STATUS(SYS_STATUS_DATA {
+                voltage_battery: 12_600,
+                ..Default::default()
+            }),
+        );
+        msg.raw_bytes().to_vec()
+    }
+
+    /// Test that a signed frame is signed again with another key and stays decodable.
+    #[test]
+    fn test_resign_replaces_signature() {
+        let unix_us = 1_700_000_000_000_000;
+        let original = Resigner::new(key(1, 0))
+            .resign::<MavMessage>(&v2_frame(), unix_us)
+            .unwrap();
+        assert!(verify(&key(1, 0), &original));
+
+        let mut resigner = Resigner::new(key(2, 5));
+        let resigned = resigner.resign::<MavMessage>(&original, unix_us).unwrap();
+        assert_eq!(resigned.len(), v2_frame().len() + frame::SIGNATURE_SIZE);
+        assert!(verify(&key(2, 5), &resigned));
+        assert!(!verify(&key(1, 0), &resigned));
+        assert!(!verify(&key(2, 6), &resigned));
+        let decoded = frame::decode::<MavMessage>(&resigned).unwrap();
+        assert_eq!(decoded.flags, Some((frame::IFLAG_SIGNED, 0)));
+
+        // the timestamp follows the send time and always increases
+        let timestamp = |bytes: &[u8]| {
+            let start = bytes.len() - SIGNATURE_HASH_SIZE - SIGNING_TIMESTAMP_SIZE;
+            let mut timestamp = [0u8; 8];
+            timestamp[..6].copy_from_slice(&bytes[start..start + 6]);
+            u64::from_le_bytes(timestamp)
+        };
+        assert_eq!(timestamp(&resigned), signing_timestamp(unix_us));
+        let again = resigner.resign::<MavMessage>(&original, unix_us).unwrap();
+        assert_eq!(timestamp(&again), signing_timestamp(unix_us) + 1);
+    }
+
+    /// Test that signatures are stripped, MAVLink 1 frames kept and corrupted frames rejected.
+    #[test]
+    fn test_resign_strip_and_invalid() {
+        let signed = Resigner::new(key(1, 0))
+            .resign::<MavMessage>(&v2_frame(), 1_700_000_000_000_000)
+            .unwrap();
+        let stripped = Resigner::strip()
+            .resign::<MavMessage>(&signed, 1_700_000_000_000_000)
+            .unwrap();
+        assert_eq!(stripped, v2_frame());
+
+        let mut v1 = MAVLinkV1MessageRaw::new();
+        v1.serialize_message(
+            MavHeader::default(),
+            &MavMessage::HEARTBEAT(Default::default()),
+        );
+        let kept = Resigner::new(key(1, 0))
+            .resign::<MavMessage>(v1.raw_bytes(), 0)
+            .unwrap();
+        assert_eq!(kept, v1.raw_bytes());
+
+        let mut corrupted = v2_frame();
+        corrupted[12] ^= 0xff;
+        assert!(matches!(
+            Resigner::new(key(1, 0)).resign::<MavMessage>(&corrupted, 0),
+            Err(FrameError::Invalid)
+        ));
+    }
+
+    /// Test that frames and messages written to a re-signing logger reach it signed.
+    #[test]
+    fn test_resigning_logger() {
+        let mut logger: ResigningLogger<FrameList, MavMessage> =
+            ResigningLogger::new(FrameList::default(), Resigner::new(key(3, 1)));
+        let time = AtomicU64::new(1_700_000_000_000_000);
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || time.fetch_add(1_000, Ordering::Relaxed))),
+            BackwardsPolicy::Hold,
+        ));
+        logger.write_frame(&v2_frame()).unwrap();
+        logger
+            .write_mavlink(MavFrame {
+                header: MavHeader::default(),
+                msg: MavMessage::HEARTBEAT(Default::default()),
+                protocol_version: mavlink::MavlinkVersion::V2,
+            })
+            .unwrap();
+        let error = logger.write_frame(&v2_frame()[1..]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let frames = logger.into_inner().0;
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|frame| verify(&key(3, 1), frame)));
+    }
+}
@@ -0,0 +1,557 @@
+//! Retention of the logs of a directory tree.
+//!
+//! Archives of flight logs grow without bound. A `RetentionPolicy` keeps recent logs at full
+//! rate, decimates older logs in place so that they keep their overview at a fraction of their
+//! size, deletes logs past a maximum age, and optionally deletes the oldest logs until the
+//! archive fits a storage budget. Running it from a nightly job keeps an archive within its
+//! budget automatically.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use mavlink::common::MavMessage;
+//! use mavlink_log::retention::{RetentionPolicy, enforce_retention};
+//!
+//! let policy = RetentionPolicy {
+//!     full_rate_days: 14,
+//!     ..RetentionPolicy::default()
+//! };
+//! let report = enforce_retention::<MavMessage>(Path::new("/data/flights"), &policy).unwrap();
+//! println!("{} bytes freed", report.bytes_freed());
+//! ```
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use mavlink::{MavFrame, MavlinkVersion, Message};
+
+use crate::DetectedFormat;
+use crate::analysis::decimate::{Decimator, Rate};
+use crate::clock::{BackwardsPolicy, Clock, ClockSource};
+use crate::fields;
+use crate::frame;
+use crate::mav_logger::MavLogger;
+use crate::mav_parser::for_each_entry;
+use crate::mavlog::copy::copy_entries;
+use crate::mavlog::header::FormatFlags;
+use crate::mavlog::logger::RotatingMavLogger;
+use crate::mavlog::parser::{MavLogParser, read_header};
+use crate::open::{detect_file_format, list_files};
+use crate::tlog::logger::RotatingTlog;
+use crate::tlog::parser::TlogParser;
+
+/// Suffix of the file a log is decimated to before it replaces the log.
+const TEMPORARY_SUFFIX: &str = ".retention-tmp";
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// What a retention policy did to a log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAction {
+    /// The log was left as it is, because it is recent or already decimated.
+    Kept,
+    /// The log was decimated in place.
+    Decimated,
+    /// The log was deleted because it is older than the maximum age.
+    Expired,
+    /// The log was deleted because the archive exceeded its storage budget.
+    OverBudget,
+}
+
+/// What a retention policy did to one log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRetention {
+    /// The log.
+    pub path: PathBuf,
+    /// The modification time of the log, which retention ages are measured from.
+    pub modified: SystemTime,
+    /// The size of the log before the policy ran, in bytes.
+    pub bytes_before: u64,
+    /// The size of the log after the policy ran, in bytes, 0 if it was deleted.
+    pub bytes_after: u64,
+    /// What the policy did.
+    pub action: RetentionAction,
+}
+
+/// Outcome of running a retention policy on a directory tree.
+#[derive(Debug, Default)]
+pub struct RetentionReport {
+    /// Every log of the tree, ordered by path.
+    pub logs: Vec<LogRetention>,
+    /// The logs that could not be read or rewritten, with their errors. They are left as they
+    /// are and not counted against the storage budget.
+    pub failures: Vec<(PathBuf, std::io::Error)>,
+}
+
+impl RetentionReport {
+    /// Returns the number of bytes freed by decimating and deleting logs.
+    pub fn bytes_freed(&self) -> u64 {
+        self.logs
+            .iter()
+            .map(|log| log.bytes_before - log.bytes_after)
+            .sum()
+    }
+
+    /// Returns the size of the logs left in the archive, in bytes.
+    pub fn bytes_retained(&self) -> u64 {
+        self.logs.iter().map(|log| log.bytes_after).sum()
+    }
+
+    /// Returns the logs the policy did a given action to.
+    pub fn with_action(&self, action: RetentionAction) -> impl Iterator<Item = &LogRetention> {
+        self.logs.iter().filter(move |log| log.action == action)
+    }
+}
+
+/// Policy deciding which logs of an archive are kept at full rate, decimated or deleted.
+///
+/// The age of a log is the time since it was last modified. Decimating a log preserves its
+/// modification time, so that it keeps aging. Decimated .mav logs keep their non MAVLink entries
+/// and their format flags, except for encryption, compression and hash chains, and get a new
+/// UUID. Decimated TLOGs are re-encoded from the messages of dialect `M`, so messages of other
+/// dialects and MAVLink 2 signatures are dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    /// Logs younger than this number of days are kept at full rate.
+    pub full_rate_days: u64,
+    /// Logs older than this number of days are deleted, `None` to never delete logs by age.
+    pub delete_after_days: Option<u64>,
+    /// The rate MAVLink messages of older logs are decimated to.
+    pub rate: Rate,
+    /// Ids of the messages kept at full rate in decimated logs, such as status texts and
+    /// command acknowledgements that are sparse but meaningful.
+    pub full_rate_messages: BTreeSet<u32>,
+    /// Maximum size of the logs of the archive in bytes. The oldest logs are deleted until the
+    /// archive fits, after decimation. `None` for no budget.
+    pub max_total_bytes: Option<u64>,
+    /// Reports what the policy would do without modifying the archive. Logs are still decimated
+    /// to a temporary file to measure the bytes that would be freed.
+    pub dry_run: bool,
+}
+
+impl Default for RetentionPolicy {
+    /// Keeps 30 days at full rate, decimates older logs to 1 Hz and deletes logs after a year.
+    fn default() -> Self {
+        RetentionPolicy {
+            full_rate_days: 30,
+            delete_after_days: Some(365),
+            rate: Rate::MaxHz(1.0),
+            full_rate_messages: BTreeSet::from([
+                fields::PARAM_VALUE_ID,
+                fields::COMMAND_INT_ID,
+                fields::COMMAND_LONG_ID,
+                fields::COMMAND_ACK_ID,
+                fields::HOME_POSITION_ID,
+                fields::STATUSTEXT_ID,
+            ]),
+            max_total_bytes: None,
+            dry_run: false,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Runs the policy on the .mav and TLOG logs of a directory tree.
+    ///
+    /// Other files are ignored. A log that fails to be decimated is left unchanged and reported
+    /// as a failure, the other logs are still processed.
+    ///
+    /// # Type Parameters
+    /// - `M`: The MAVLink dialect to parse messages with.
+    ///
+    /// # Arguments
+    /// - `root`: The root of the directory tree.
+    /// - `now`: The time log ages are measured at.
+    ///
+    /// # Returns
+    /// What the policy did to each log.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the directory tree could not be listed.
+    pub fn apply<M: Message + 'static>(
+        &self,
+        root: &Path,
+        now: SystemTime,
+    ) -> std::io::Result<RetentionReport> {
+        let mut files = Vec::new();
+        list_files(root, &mut files)?;
+        files.sort();
+        let mut report = RetentionReport::default();
+        for path in files {
+            match self.apply_to_log::<M>(&path, now) {
+                Ok(Some(log)) => report.logs.push(log),
+                Ok(None) => {}
+                Err(e) => report.failures.push((path, e)),
+            }
+        }
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            self.enforce_budget(&mut report, max_total_bytes);
+        }
+        Ok(report)
+    }
+
+    /// Runs the policy on one file.
+    ///
+    /// # Returns
+    /// What the policy did, or `None` if the file is not a .mav or TLOG log.
+    fn apply_to_log<M: Message + 'static>(
+        &self,
+        path: &Path,
+        now: SystemTime,
+    ) -> std::io::Result<Option<LogRetention>> {
+        if path.to_string_lossy().ends_with(TEMPORARY_SUFFIX) {
+            return Ok(None);
+        }
+        let format = detect_file_format(path)?;
+        if !matches!(format, DetectedFormat::MavLog { .. } | DetectedFormat::Tlog) {
+            return Ok(None);
+        }
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        let age = now.duration_since(modified).unwrap_or(Duration::ZERO);
+        let mut log = LogRetention {
+            path: path.to_path_buf(),
+            modified,
+            bytes_before: metadata.len(),
+            bytes_after: metadata.len(),
+            action: RetentionAction::Kept,
+        };
+        if self
+            .delete_after_days
+            .is_some_and(|days| age > days_to_duration(days))
+        {
+            if !self.dry_run {
+                std::fs::remove_file(path)?;
+            }
+            log.bytes_after = 0;
+            log.action = RetentionAction::Expired;
+        } else if age > days_to_duration(self.full_rate_days) {
+            let decimated = self.decimate::<M>(path, &format, modified)?;
+            if let Some(bytes_after) = decimated {
+                log.bytes_after = bytes_after;
+                log.action = RetentionAction::Decimated;
+            }
+        }
+        Ok(Some(log))
+    }
+
+    /// Decimates a log in place, or to a temporary file that is then deleted in dry run mode.
+    ///
+    /// # Returns
+    /// The size of the decimated log, or `None` if decimation dropped no message, in which case
+    /// the log is left unchanged.
+    fn decimate<M: Message + 'static>(
+        &self,
+        path: &Path,
+        format: &DetectedFormat,
+        modified: SystemTime,
+    ) -> std::io::Result<Option<u64>> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(TEMPORARY_SUFFIX);
+        let temporary = PathBuf::from(temporary);
+        let result = match format {
+            DetectedFormat::MavLog { .. } => self.decimate_mavlog::<M>(path, &temporary),
+            _ => self.decimate_tlog::<M>(path, &temporary),
+        };
+        let dropped = match result {
+            Ok(dropped) => dropped,
+            Err(e) => {
+                let _ = std::fs::remove_file(&temporary);
+                return Err(e);
+            }
+        };
+        let bytes_after = std::fs::metadata(&temporary)?.len();
+        if dropped == 0 || self.dry_run {
+            std::fs::remove_file(&temporary)?;
+            return Ok((dropped > 0).then_some(bytes_after));
+        }
+        std::fs::rename(&temporary, path)?;
+        std::fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(modified)?;
+        Ok(Some(bytes_after))
+    }
+
+    /// Creates the decimator applying the policy rates.
+    fn decimator(&self) -> Decimator {
+        let full_rate: BTreeMap<u32, Rate> = self
+            .full_rate_messages
+            .iter()
+            .map(|&msg_id| (msg_id, Rate::EveryNth(1)))
+            .collect();
+        Decimator::with_default_rate(full_rate, Some(self.rate))
+    }
+
+    /// Decimates a .mav log to another file without decoding its messages.
+    ///
+    /// # Returns
+    /// The number of messages dropped.
+    fn decimate_mavlog<M: Message + 'static>(
+        &self,
+        path: &Path,
+        destination: &Path,
+    ) -> std::io::Result<u64> {
+        let path_text = path.to_string_lossy();
+        let header = read_header(&path_text)?;
+        if header.format_flags.encrypted {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Encrypted logs cannot be decimated",
+            ));
+        }
+        let source_flags = header.format_flags;
+        let format_flags = FormatFlags {
+            mavlink_only: source_flags.mavlink_only,
+            no_timestamp: source_flags.no_timestamp,
+            sequence: source_flags.sequence,
+            large_entries: source_flags.large_entries,
+            ..FormatFlags::default()
+        };
+        let mut parser = MavLogParser::<M>::try_new(&path_text)?;
+        let mut logger = RotatingMavLogger::new(
+            &destination.to_string_lossy(),
+            u64::MAX,
+            0,
+            Some(format_flags),
+            Some(header.message_definition),
+        )?;
+        let decimator = RefCell::new(self.decimator());
+        copy_entries(&mut parser, &mut logger, |entry| {
+            match (entry.message_id(), frame::source(&entry.payload)) {
+                (Some(msg_id), Some((system_id, component_id))) => decimator
+                    .borrow_mut()
+                    .keep_message(msg_id, system_id, component_id, entry.timestamp),
+                _ => true,
+            }
+        })?;
+        logger.finish()?;
+        Ok(decimator.into_inner().dropped())
+    }
+
+    /// Decimates a TLOG to another file, preserving record timestamps.
+    ///
+    /// # Returns
+    /// The number of messages dropped.
+    fn decimate_tlog<M: Message + 'static>(
+        &self,
+        path: &Path,
+        destination: &Path,
+    ) -> std::io::Result<u64> {
+        let mut parser = TlogParser::<M>::new(&path.to_string_lossy());
+        let mut logger = RotatingTlog::new(&destination.to_string_lossy(), u64::MAX, 0)?;
+        let time = Arc::new(AtomicU64::new(0));
+        let clock_time = time.clone();
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || clock_time.load(Ordering::Relaxed))),
+            BackwardsPolicy::Allow,
+        ));
+        let mut decimator = self.decimator();
+        for_each_entry(&mut parser, |entry| {
+            if !decimator.keep(&entry) {
+                return Ok(());
+            }
+            let (Some(header), Some(msg)) = (entry.mav_header, entry.mav_message) else {
+                return Ok(());
+            };
+            time.store(entry.timestamp.unwrap_or_default(), Ordering::Relaxed);
+            logger.write_mavlink(MavFrame {
+                header,
+                msg,
+                protocol_version: entry.protocol_version.unwrap_or(MavlinkVersion::V2),
+            })
+        })?;
+        Ok(decimator.dropped())
+    }
+
+    /// Deletes the oldest logs left until the archive fits its budget.
+    fn enforce_budget(&self, report: &mut RetentionReport, max_total_bytes: u64) {
+        let mut total_bytes = report.bytes_retained();
+        let mut oldest_first: Vec<usize> = (0..report.logs.len())
+            .filter(|&index| report.logs[index].bytes_after > 0)
+            .collect();
+        oldest_first.sort_by_key(|&index| report.logs[index].modified);
+        for index in oldest_first {
+            if total_bytes <= max_total_bytes {
+                break;
+            }
+            let log = &mut report.logs[index];
+            let removed = if self.dry_run {
+                Ok(())
+            } else {
+                std::fs::remove_file(&log.path)
+            };
+            if let Err(e) = removed {
+                report.failures.push((log.path.clone(), e));
+                continue;
+            }
+            total_bytes -= log.bytes_after;
+            log.bytes_after = 0;
+            log.action = RetentionAction::OverBudget;
+        }
+    }
+}
+
+/// Converts a number of days to a duration, saturating instead of overflowing.
+fn days_to_duration(days: u64) -> Duration {
+    Duration::from_secs(days.saturating_mul(SECONDS_PER_DAY))
+}
+
+/// Runs a retention policy on the .mav and TLOG logs of a directory tree, measuring ages at the
+/// current time, see `RetentionPolicy::apply`.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to parse messages with.
+///
+/// # Arguments
+/// - `root`: The root of the directory tree.
+/// - `policy`: The policy to run.
+///
+/// # Errors
+/// Returns an `io::Error` if the directory tree could not be listed.
+pub fn enforce_retention<M: Message + 'static>(
+    root: &Path,
+    policy: &RetentionPolicy,
+) -> std::io::Result<RetentionReport> {
+    policy.apply::<M>(root, SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::MavHeader;
+    use mavlink::common::{MavMessage, STATUSTEXT_DATA};
+
+    use super::*;
+    use crate::mav_parser::MavParser;
+
+    const DAY: Duration = Duration::from_secs(SECONDS_PER_DAY);
+    const START_US: u64 = 1_700_000_000_000_000;
+
+    /// Writes a 10 Hz log of 10 seconds with one status text, aged by a number of days.
+    fn write_log(path: &Path, now: SystemTime, age_days: u32) {
+        let time = Arc::new(AtomicU64::new(0));
+        let clock_time = time.clone();
+        let mut logger = RotatingTlog::new(path.to_str().unwrap(), u64::MAX, 0).unwrap();
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || clock_time.load(Ordering::Relaxed))),
+            BackwardsPolicy::Allow,
+        ));
+        for i in 0..100u64 {
+            time.store(START_US + i * 100_000, Ordering::Relaxed);
+            let msg = if i == 55 {
+                MavMessage::STATUSTEXT(STATUSTEXT_DATA::default())
+            } else {
+                MavMessage::HEARTBEAT(Default::default())
+            };
+            logger
+                .write_mavlink(MavFrame {
+                    header: MavHeader::default(),
+                    msg,
+                    protocol_version: MavlinkVersion::V2,
+                })
+                .unwrap();
+        }
+        drop(logger);
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(now - DAY * age_days)
+            .unwrap();
+    }
+
+    /// Returns the message ids of a log with their timestamps.
+    fn messages(path: &Path) -> Vec<(u64, u32)> {
+        let mut parser = crate::open::<MavMessage>(path.to_str().unwrap()).unwrap();
+        let mut messages = Vec::new();
+        while let Ok(entry) = parser.parse_next_entry() {
+            messages.push((
+                entry.timestamp.unwrap(),
+                entry.mav_message.unwrap().message_id(),
+            ));
+        }
+        messages
+    }
+
+    /// Test that logs are kept, decimated or deleted by age, and that decimation is idempotent.
+    #[test]
+    fn test_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        write_log(&dir.path().join("recent.tlog"), now, 2);
+        write_log(&dir.path().join("old.tlog"), now, 40);
+        write_log(&dir.path().join("expired.tlog"), now, 400);
+        std::fs::write(dir.path().join("notes.txt"), b"not a log").unwrap();
+
+        let policy = RetentionPolicy::default();
+        let report = policy.apply::<MavMessage>(dir.path(), now).unwrap();
+        assert!(report.failures.is_empty());
+        let actions: Vec<(&str, RetentionAction)> = report
+            .logs
+            .iter()
+            .map(|log| (log.path.file_name().unwrap().to_str().unwrap(), log.action))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                ("expired.tlog", RetentionAction::Expired),
+                ("old.tlog", RetentionAction::Decimated),
+                ("recent.tlog", RetentionAction::Kept),
+            ]
+        );
+        assert!(!dir.path().join("expired.tlog").exists());
+        assert_eq!(messages(&dir.path().join("recent.tlog")).len(), 100);
+        // one heartbeat per second, and the status text kept at full rate
+        let old = messages(&dir.path().join("old.tlog"));
+        assert_eq!(old.len(), 11);
+        assert_eq!(old[0], (START_US, 0));
+        assert!(old.contains(&(START_US + 5_500_000, fields::STATUSTEXT_ID)));
+        let modified = std::fs::metadata(dir.path().join("old.tlog"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(modified, now - DAY * 40);
+        assert_eq!(
+            report.bytes_freed(),
+            report.logs[0].bytes_before + report.logs[1].bytes_before - report.logs[1].bytes_after
+        );
+
+        let report = policy.apply::<MavMessage>(dir.path(), now).unwrap();
+        assert_eq!(report.with_action(RetentionAction::Kept).count(), 2);
+        assert_eq!(report.bytes_freed(), 0);
+    }
+
+    /// Test that the oldest logs are deleted to fit the budget, and that a dry run changes
+    /// nothing.
+    #[test]
+    fn test_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (name, age_days) in [("a.tlog", 3), ("b.tlog", 1), ("c.tlog", 2)] {
+            write_log(&dir.path().join(name), now, age_days);
+        }
+        let log_bytes = std::fs::metadata(dir.path().join("a.tlog")).unwrap().len();
+        let mut policy = RetentionPolicy {
+            max_total_bytes: Some(log_bytes * 2),
+            dry_run: true,
+            ..RetentionPolicy::default()
+        };
+        let report = policy.apply::<MavMessage>(dir.path(), now).unwrap();
+        assert_eq!(report.bytes_freed(), log_bytes);
+        assert!(dir.path().join("a.tlog").exists());
+
+        policy.dry_run = false;
+        let report = policy.apply::<MavMessage>(dir.path(), now).unwrap();
+        let over_budget: Vec<&Path> = report
+            .with_action(RetentionAction::OverBudget)
+            .map(|log| log.path.as_path())
+            .collect();
+        assert_eq!(over_budget, vec![dir.path().join("a.tlog")]);
+        assert!(!dir.path().join("a.tlog").exists());
+        assert_eq!(report.bytes_retained(), log_bytes * 2);
+    }
+}
@@ -0,0 +1,610 @@
+//! Export of MAVLink logs as ROS 2 bags.
+//!
+//! Common messages are converted to the ROS 2 messages mavros publishes for them, on the same
+//! topics, so that a log can be replayed through existing ROS tooling with `ros2 bag play`:
+//!
+//! | MAVLink             | Topic                          | Type                         |
+//! |---------------------|--------------------------------|------------------------------|
+//! | ATTITUDE            | `<ns>/imu/data`                | `sensor_msgs/msg/Imu`        |
+//! | GLOBAL_POSITION_INT | `<ns>/global_position/global`  | `sensor_msgs/msg/NavSatFix`  |
+//! | GPS_RAW_INT         | `<ns>/global_position/raw/fix` | `sensor_msgs/msg/NavSatFix`  |
+//! | VFR_HUD             | `<ns>/vfr_hud`                 | `mavros_msgs/msg/VFR_HUD`    |
+//! | STATUSTEXT          | `<ns>/statustext/recv`         | `mavros_msgs/msg/StatusText` |
+//!
+//! Like mavros, orientations and rates are converted from the NED and aircraft frames of MAVLink
+//! to the ENU and `base_link` frames of ROS. Altitudes are written as reported, above mean sea
+//! level, without the geoid correction mavros applies.
+//!
+//! Bags are written with the MCAP storage, messages serialized as CDR. The SQLite storage is not
+//! supported. Entries without a timestamp cannot be placed in a bag and are skipped.
+use std::f64::consts::FRAC_PI_2;
+use std::io::{BufWriter, Write};
+
+use mavlink::Message;
+
+use crate::fields;
+use crate::mav_parser::{LogEntry, MavParser, for_each_entry};
+
+/// Magic bytes starting and ending an MCAP file.
+const MCAP_MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+/// MCAP record opcodes.
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_DATA_END: u8 = 0x0F;
+/// Length of the STATUSTEXT text field.
+const STATUSTEXT_LEN: usize = 50;
+/// GPS_RAW_INT accuracy when it is not reported.
+const ACCURACY_UNKNOWN: u16 = u16::MAX;
+
+/// Separator between the definitions of a ROS 2 message and of its dependencies.
+const DEFINITION_SEPARATOR: &str =
+    "================================================================================\n";
+
+const HEADER_DEFINITION: &str = "MSG: std_msgs/Header\n\
+    builtin_interfaces/Time stamp\n\
+    string frame_id\n";
+const TIME_DEFINITION: &str = "MSG: builtin_interfaces/Time\n\
+    int32 sec\n\
+    uint32 nanosec\n";
+const QUATERNION_DEFINITION: &str = "MSG: geometry_msgs/Quaternion\n\
+    float64 x 0\n\
+    float64 y 0\n\
+    float64 z 0\n\
+    float64 w 1\n";
+const VECTOR3_DEFINITION: &str = "MSG: geometry_msgs/Vector3\n\
+    float64 x\n\
+    float64 y\n\
+    float64 z\n";
+const NAV_SAT_STATUS_DEFINITION: &str = "MSG: sensor_msgs/NavSatStatus\n\
+    int8 STATUS_NO_FIX = -1\n\
+    int8 STATUS_FIX = 0\n\
+    int8 STATUS_SBAS_FIX = 1\n\
+    int8 STATUS_GBAS_FIX = 2\n\
+    int8 status\n\
+    uint16 SERVICE_GPS = 1\n\
+    uint16 SERVICE_GLONASS = 2\n\
+    uint16 SERVICE_COMPASS = 4\n\
+    uint16 SERVICE_GALILEO = 8\n\
+    uint16 service\n";
+
+/// A ROS 2 message type written to bags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RosType {
+    Imu,
+    NavSatFix,
+    VfrHud,
+    StatusText,
+}
+
+impl RosType {
+    /// Returns the full name of the type.
+    fn name(self) -> &'static str {
+        match self {
+            RosType::Imu => "sensor_msgs/msg/Imu",
+            RosType::NavSatFix => "sensor_msgs/msg/NavSatFix",
+            RosType::VfrHud => "mavros_msgs/msg/VFR_HUD",
+            RosType::StatusText => "mavros_msgs/msg/StatusText",
+        }
+    }
+
+    /// Returns the definition of the type followed by those of its dependencies.
+    fn definition(self) -> String {
+        let (definition, dependencies): (&str, &[&str]) = match self {
+            RosType::Imu => (
+                "std_msgs/Header header\n\
+                 geometry_msgs/Quaternion orientation\n\
+                 float64[9] orientation_covariance\n\
+                 geometry_msgs/Vector3 angular_velocity\n\
+                 float64[9] angular_velocity_covariance\n\
+                 geometry_msgs/Vector3 linear_acceleration\n\
+                 float64[9] linear_acceleration_covariance\n",
+                &[
+                    HEADER_DEFINITION,
+                    TIME_DEFINITION,
+                    QUATERNION_DEFINITION,
+                    VECTOR3_DEFINITION,
+                ],
+            ),
+            RosType::NavSatFix => (
+                "std_msgs/Header header\n\
+                 NavSatStatus status\n\
+                 float64 latitude\n\
+                 float64 longitude\n\
+                 float64 altitude\n\
+                 float64[9] position_covariance\n\
+                 uint8 COVARIANCE_TYPE_UNKNOWN = 0\n\
+                 uint8 COVARIANCE_TYPE_APPROXIMATED = 1\n\
+                 uint8 COVARIANCE_TYPE_DIAGONAL_KNOWN = 2\n\
+                 uint8 COVARIANCE_TYPE_KNOWN = 3\n\
+                 uint8 position_covariance_type\n",
+                &[
+                    HEADER_DEFINITION,
+                    TIME_DEFINITION,
+                    NAV_SAT_STATUS_DEFINITION,
+                ],
+            ),
+            RosType::VfrHud => (
+                "std_msgs/Header header\n\
+                 float32 airspeed\n\
+                 float32 groundspeed\n\
+                 int16 heading\n\
+                 float32 throttle\n\
+                 float32 altitude\n\
+                 float32 climb\n",
+                &[HEADER_DEFINITION, TIME_DEFINITION],
+            ),
+            RosType::StatusText => (
+                "std_msgs/Header header\n\
+                 uint8 severity\n\
+                 string text\n",
+                &[HEADER_DEFINITION, TIME_DEFINITION],
+            ),
+        };
+        let mut full = String::from(definition);
+        for dependency in dependencies {
+            full.push_str(DEFINITION_SEPARATOR);
+            full.push_str(dependency);
+        }
+        full
+    }
+}
+
+/// A ROS 2 message converted from a MAVLink message.
+struct RosMessage {
+    /// Topic relative to the namespace, starting with `/`.
+    topic: &'static str,
+    ros_type: RosType,
+    /// The CDR serialized message.
+    data: Vec<u8>,
+}
+
+/// Serializer of ROS 2 messages in little-endian CDR.
+struct CdrWriter {
+    buf: Vec<u8>,
+}
+
+impl CdrWriter {
+    /// Size of the encapsulation header, which alignment does not take into account.
+    const HEADER_SIZE: usize = 4;
+
+    fn new() -> Self {
+        // CDR_LE representation, no options
+        Self {
+            buf: vec![0x00, 0x01, 0x00, 0x00],
+        }
+    }
+
+    /// Pads the buffer so that the next value of `size` bytes is aligned.
+    fn align(&mut self, size: usize) {
+        while (self.buf.len() - Self::HEADER_SIZE) % size != 0 {
+            self.buf.push(0);
+        }
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn i8(&mut self, value: i8) {
+        self.buf.push(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.align(2);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i16(&mut self, value: i16) {
+        self.align(2);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.align(8);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64s(&mut self, values: &[f64]) {
+        for &value in values {
+            self.f64(value);
+        }
+    }
+
+    /// Writes a string as its length including the null terminator, its bytes and a null.
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32 + 1);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// Writes a `std_msgs/Header`.
+    fn header(&mut self, timestamp_us: u64, frame_id: &str) {
+        self.i32((timestamp_us / 1_000_000) as i32);
+        self.u32((timestamp_us % 1_000_000) as u32 * 1000);
+        self.string(frame_id);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Converts roll, pitch and yaw in radians to a quaternion, returned as (x, y, z, w).
+fn quaternion(roll: f64, pitch: f64, yaw: f64) -> (f64, f64, f64, f64) {
+    let (sr, cr) = (roll / 2.0).sin_cos();
+    let (sp, cp) = (pitch / 2.0).sin_cos();
+    let (sy, cy) = (yaw / 2.0).sin_cos();
+    (
+        sr * cp * cy - cr * sp * sy,
+        cr * sp * cy + sr * cp * sy,
+        cr * cp * sy - sr * sp * cy,
+        cr * cp * cy + sr * sp * sy,
+    )
+}
+
+/// Writes the status, position and covariance fields of a `sensor_msgs/NavSatFix`.
+///
+/// # Arguments
+/// - `status`: The `NavSatStatus` status.
+/// - `position`: Latitude and longitude in degrees and altitude in meters.
+/// - `accuracy`: Horizontal and vertical standard deviations in meters, if known.
+fn nav_sat_fix(
+    cdr: &mut CdrWriter,
+    status: i8,
+    position: (f64, f64, f64),
+    accuracy: Option<(f64, f64)>,
+) {
+    cdr.i8(status);
+    // SERVICE_GPS
+    cdr.u16(1);
+    cdr.f64(position.0);
+    cdr.f64(position.1);
+    cdr.f64(position.2);
+    let mut covariance = [0.0; 9];
+    if let Some((horizontal, vertical)) = accuracy {
+        covariance[0] = horizontal * horizontal;
+        covariance[4] = horizontal * horizontal;
+        covariance[8] = vertical * vertical;
+    }
+    cdr.f64s(&covariance);
+    // COVARIANCE_TYPE_APPROXIMATED or COVARIANCE_TYPE_UNKNOWN
+    cdr.u8(u8::from(accuracy.is_some()));
+}
+
+/// Converts a MAVLink message to the ROS 2 message mavros publishes for it.
+///
+/// # Returns
+/// The converted message, or `None` if the message has no ROS 2 equivalent.
+fn convert<M: Message>(msg: &M, timestamp_us: u64) -> Option<RosMessage> {
+    let payload = fields::payload(msg);
+    let mut cdr = CdrWriter::new();
+    let (topic, ros_type) = match msg.message_id() {
+        fields::ATTITUDE_ID => {
+            let angle = |offset| fields::read_f32(&payload, offset) as f64;
+            cdr.header(timestamp_us, "base_link");
+            // NED and forward-right-down to ENU and forward-left-up
+            let (x, y, z, w) = quaternion(angle(4), -angle(8), FRAC_PI_2 - angle(12));
+            cdr.f64s(&[x, y, z, w]);
+            cdr.f64s(&[0.0; 9]);
+            cdr.f64s(&[angle(16), -angle(20), -angle(24)]);
+            cdr.f64s(&[0.0; 9]);
+            cdr.f64s(&[0.0; 3]);
+            // a first covariance element of -1 marks the acceleration as not provided
+            cdr.f64s(&[-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+            ("/imu/data", RosType::Imu)
+        }
+        fields::GLOBAL_POSITION_INT_ID => {
+            cdr.header(timestamp_us, "base_link");
+            let position = (
+                fields::read_i32(&payload, 4) as f64 / 1e7,
+                fields::read_i32(&payload, 8) as f64 / 1e7,
+                fields::read_i32(&payload, 12) as f64 / 1e3,
+            );
+            // STATUS_FIX
+            nav_sat_fix(&mut cdr, 0, position, None);
+            ("/global_position/global", RosType::NavSatFix)
+        }
+        fields::GPS_RAW_INT_ID => {
+            cdr.header(timestamp_us, "gps");
+            let position = (
+                fields::read_i32(&payload, 8) as f64 / 1e7,
+                fields::read_i32(&payload, 12) as f64 / 1e7,
+                fields::read_i32(&payload, 16) as f64 / 1e3,
+            );
+            let (eph, epv) = (
+                fields::read_u16(&payload, 20),
+                fields::read_u16(&payload, 22),
+            );
+            let accuracy = (eph != ACCURACY_UNKNOWN && epv != ACCURACY_UNKNOWN)
+                .then(|| (eph as f64 / 1e2, epv as f64 / 1e2));
+            // GPS_FIX_TYPE to NavSatStatus as mavros maps them
+            let status = match fields::read_u8(&payload, 28) {
+                0 | 1 => -1,
+                4 => 1,
+                5 | 6 => 2,
+                _ => 0,
+            };
+            nav_sat_fix(&mut cdr, status, position, accuracy);
+            ("/global_position/raw/fix", RosType::NavSatFix)
+        }
+        fields::VFR_HUD_ID => {
+            cdr.header(timestamp_us, "");
+            cdr.f32(fields::read_f32(&payload, 0));
+            cdr.f32(fields::read_f32(&payload, 4));
+            cdr.i16(fields::read_i16(&payload, 16));
+            cdr.f32(fields::read_u16(&payload, 18) as f32 / 100.0);
+            cdr.f32(fields::read_f32(&payload, 8));
+            cdr.f32(fields::read_f32(&payload, 12));
+            ("/vfr_hud", RosType::VfrHud)
+        }
+        fields::STATUSTEXT_ID => {
+            cdr.header(timestamp_us, "");
+            cdr.u8(fields::read_u8(&payload, 0));
+            let text = &payload[1..1 + STATUSTEXT_LEN];
+            let end = text.iter().position(|&x| x == 0).unwrap_or(text.len());
+            cdr.string(&String::from_utf8_lossy(&text[..end]));
+            ("/statustext/recv", RosType::StatusText)
+        }
+        _ => return None,
+    };
+    Some(RosMessage {
+        topic,
+        ros_type,
+        data: cdr.finish(),
+    })
+}
+
+/// Writer of unindexed MCAP files.
+struct McapWriter<W: Write> {
+    writer: W,
+    /// Types of the schemas written so far, the schema id being the index plus one.
+    schemas: Vec<RosType>,
+    /// Topics of the channels written so far, the channel id being the index plus one.
+    channels: Vec<String>,
+    /// Number of messages written to each channel.
+    sequences: Vec<u32>,
+}
+
+impl<W: Write> McapWriter<W> {
+    /// Writes the magic bytes and header of the file.
+    fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(MCAP_MAGIC)?;
+        let mut header = Vec::new();
+        put_string(&mut header, "ros2");
+        put_string(&mut header, "mavlink_log");
+        write_record(&mut writer, OP_HEADER, &header)?;
+        Ok(Self {
+            writer,
+            schemas: Vec::new(),
+            channels: Vec::new(),
+            sequences: Vec::new(),
+        })
+    }
+
+    /// Writes a message, adding its schema and channel the first time they are used.
+    fn write_message(
+        &mut self,
+        message: &RosMessage,
+        topic: &str,
+        log_time_ns: u64,
+    ) -> std::io::Result<()> {
+        let schema_id = match self.schemas.iter().position(|&t| t == message.ros_type) {
+            Some(index) => index as u16 + 1,
+            None => {
+                self.schemas.push(message.ros_type);
+                let schema_id = self.schemas.len() as u16;
+                let mut record = schema_id.to_le_bytes().to_vec();
+                put_string(&mut record, message.ros_type.name());
+                put_string(&mut record, "ros2msg");
+                put_string(&mut record, &message.ros_type.definition());
+                write_record(&mut self.writer, OP_SCHEMA, &record)?;
+                schema_id
+            }
+        };
+        let channel_index = match self.channels.iter().position(|t| t == topic) {
+            Some(index) => index,
+            None => {
+                self.channels.push(topic.to_string());
+                self.sequences.push(0);
+                let channel_id = self.channels.len() as u16;
+                let mut record = channel_id.to_le_bytes().to_vec();
+                record.extend_from_slice(&schema_id.to_le_bytes());
+                put_string(&mut record, topic);
+                put_string(&mut record, "cdr");
+                // empty metadata map
+                record.extend_from_slice(&0u32.to_le_bytes());
+                write_record(&mut self.writer, OP_CHANNEL, &record)?;
+                self.channels.len() - 1
+            }
+        };
+        let mut record = (channel_index as u16 + 1).to_le_bytes().to_vec();
+        record.extend_from_slice(&self.sequences[channel_index].to_le_bytes());
+        record.extend_from_slice(&log_time_ns.to_le_bytes());
+        record.extend_from_slice(&log_time_ns.to_le_bytes());
+        record.extend_from_slice(&message.data);
+        self.sequences[channel_index] += 1;
+        write_record(&mut self.writer, OP_MESSAGE, &record)
+    }
+
+    /// Writes the end of the data section, an empty summary and the closing magic bytes.
+    fn finish(mut self) -> std::io::Result<()> {
+        // a CRC of 0 means it was not computed
+        write_record(&mut self.writer, OP_DATA_END, &0u32.to_le_bytes())?;
+        let mut footer = Vec::new();
+        footer.extend_from_slice(&0u64.to_le_bytes());
+        footer.extend_from_slice(&0u64.to_le_bytes());
+        footer.extend_from_slice(&0u32.to_le_bytes());
+        write_record(&mut self.writer, OP_FOOTER, &footer)?;
+        self.writer.write_all(MCAP_MAGIC)?;
+        self.writer.flush()
+    }
+}
+
+/// Appends a string prefixed with its length.
+fn put_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// Writes an MCAP record as its opcode, length and content.
+fn write_record<W: Write>(writer: &mut W, opcode: u8, content: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&[opcode])?;
+    writer.write_all(&(content.len() as u64).to_le_bytes())?;
+    writer.write_all(content)
+}
+
+/// Reads a full log and writes the messages that have a ROS 2 equivalent to an MCAP bag.
+///
+/// Messages of every system are written to the same topics. To export a single vehicle of a
+/// log recording several, filter the entries of the parser first.
+///
+/// # Arguments
+/// - `parser`: The parser to read entries from. It is consumed until the end of the log.
+/// - `path`: Path of the `.mcap` file to create.
+/// - `namespace`: The namespace of the topics, `/mavros` to match a default mavros node.
+///
+/// # Returns
+/// The number of ROS 2 messages written.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be read or the bag could not be written.
+pub fn export_mcap<P: MavParser + ?Sized>(
+    parser: &mut P,
+    path: &str,
+    namespace: &str,
+) -> std::io::Result<u64> {
+    let mut bag = McapWriter::new(BufWriter::new(std::fs::File::create(path)?))?;
+    let namespace = namespace.trim_end_matches('/');
+    let mut written: u64 = 0;
+    for_each_entry(parser, |entry: LogEntry<P::M>| {
+        let (Some(timestamp), Some(msg)) = (entry.timestamp, &entry.mav_message) else {
+            return Ok(());
+        };
+        if let Some(message) = convert(msg, timestamp) {
+            let topic = format!("{namespace}{}", message.topic);
+            bag.write_message(&message, &topic, timestamp * 1000)?;
+            written += 1;
+        }
+        Ok(())
+    })?;
+    bag.finish()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mavlink::common::{ATTITUDE_DATA, MavMessage, MavSeverity, STATUSTEXT_DATA};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mav_parser::EntryList;
+
+    fn entry(timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    /// Test that the records of an exported bag are framed correctly, with one schema and channel
+    /// per topic and CDR serialized messages.
+    #[test]
+    fn test_export_mcap() {
+        let mut text = [0u8; STATUSTEXT_LEN];
+        text[..5].copy_from_slice(b"armed");
+        let entries = VecDeque::from([
+            entry(1_000_000, MavMessage::ATTITUDE(ATTITUDE_DATA::default())),
+            entry(
+                1_500_000,
+                MavMessage::STATUSTEXT(STATUSTEXT_DATA {
+                    severity: MavSeverity::MAV_SEVERITY_INFO,
+                    text: text.into(),
+                    ..Default::default()
+                }),
+            ),
+            entry(2_000_000, MavMessage::ATTITUDE(ATTITUDE_DATA::default())),
+            entry(2_500_000, MavMessage::HEARTBEAT(Default::default())),
+        ]);
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("flight.mcap");
+        let path = path.to_str().unwrap();
+        let written = export_mcap(&mut EntryList(entries), path, "/mavros/").unwrap();
+        assert_eq!(written, 3);
+
+        let bytes = std::fs::read(path).unwrap();
+        assert_eq!(&bytes[..8], MCAP_MAGIC);
+        assert_eq!(&bytes[bytes.len() - 8..], MCAP_MAGIC);
+        let mut records = Vec::new();
+        let mut pos = 8;
+        while pos < bytes.len() - 8 {
+            let len = u64::from_le_bytes(bytes[pos + 1..pos + 9].try_into().unwrap()) as usize;
+            records.push((bytes[pos], bytes[pos + 9..pos + 9 + len].to_vec()));
+            pos += 9 + len;
+        }
+        assert_eq!(pos, bytes.len() - 8);
+        let opcodes: Vec<u8> = records.iter().map(|(opcode, _)| *opcode).collect();
+        assert_eq!(
+            opcodes,
+            vec![
+                OP_HEADER,
+                OP_SCHEMA,
+                OP_CHANNEL,
+                OP_MESSAGE,
+                OP_SCHEMA,
+                OP_CHANNEL,
+                OP_MESSAGE,
+                OP_MESSAGE,
+                OP_DATA_END,
+                OP_FOOTER,
+            ]
+        );
+
+        // channel id, schema id, then the topic
+        let channel = &records[5].1;
+        assert_eq!(&channel[4..8], &23u32.to_le_bytes());
+        assert_eq!(&channel[8..31], b"/mavros/statustext/recv");
+        // channel id, sequence, log and publish times, then the CDR message
+        let statustext = &records[6].1;
+        assert_eq!(&statustext[6..14], &1_500_000_000u64.to_le_bytes());
+        let cdr = &statustext[22..];
+        assert_eq!(&cdr[..4], &[0x00, 0x01, 0x00, 0x00]);
+        // stamp, empty frame id, severity, then the text aligned to 4 bytes
+        assert_eq!(&cdr[4..8], &1i32.to_le_bytes());
+        assert_eq!(&cdr[8..12], &500_000_000u32.to_le_bytes());
+        assert_eq!(&cdr[16..18], &[0, MavSeverity::MAV_SEVERITY_INFO as u8]);
+        assert_eq!(&cdr[20..24], &6u32.to_le_bytes());
+        assert_eq!(&cdr[24..30], b"armed\0");
+        // the second ATTITUDE continues the sequence of its channel
+        assert_eq!(&records[7].1[2..6], &1u32.to_le_bytes());
+    }
+
+    /// Test that a level attitude heading north points the ROS yaw along +y.
+    #[test]
+    fn test_attitude_to_enu() {
+        let (x, y, z, w) = quaternion(0.0, 0.0, FRAC_PI_2);
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        assert!(x.abs() < 1e-9 && y.abs() < 1e-9);
+        assert!((z - half).abs() < 1e-9 && (w - half).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,30 @@
+//! Small reference log files for testing tools built on top of this crate.
+//!
+//! The fixtures are embedded in the library so that parsers, converters and viewers can be
+//! tested against canonical files without shipping their own. Write them to a file to open them
+//! with the parsers of this crate.
+
+/// Returns a minimal .mav log using the default format flags.
+///
+/// The log is timestamped, records MAVLink 2 frames of the common dialect from system 1,
+/// component 1 and holds, in order:
+/// - the text entry `mavlink_log minimal sample`,
+/// - a HEARTBEAT of an armed quadrotor,
+/// - an ATTITUDE,
+/// - a GLOBAL_POSITION_INT,
+/// - the raw entry `DE AD BE EF`,
+/// - a second HEARTBEAT one second after the first entry.
+///
+/// Entry timestamps start at 1 700 000 000 000 000 µs.
+pub fn minimal_mavlog() -> &'static [u8] {
+    include_bytes!("../samples/minimal.mav")
+}
+
+/// Returns a tlog recorded by a ground station.
+///
+/// The log holds the first 40 records of a flight with an ArduPilot vehicle: MAVLink 2 frames
+/// of the ardupilotmega dialect, each preceded by a big endian timestamp in microseconds since
+/// the UNIX epoch. The first record is timestamped 1 632 843 969 792 995 µs.
+pub fn qgc_tlog() -> &'static [u8] {
+    include_bytes!("../samples/qgc.tlog")
+}
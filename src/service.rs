@@ -0,0 +1,375 @@
+//! A gRPC service giving network access to a directory of logs.
+//!
+//! `DirectoryService` implements the `LogService` of `proto/log_service.proto` with tonic, so
+//! that ground infrastructure can list logs, stream their entries and summarize them without
+//! sharing a filesystem. Logs of any format supported by `open` are served. Entries are streamed
+//! as they are parsed, optionally selected by a `ParserFilter` expression.
+//!
+//! Generating the gRPC code requires `protoc` to be installed when building with the `service`
+//! feature.
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use mavlink::{MavlinkVersion, Message};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::analysis::filter::ParserFilter;
+use crate::fields;
+use crate::mav_parser::{LogEntry, for_each_entry};
+use crate::open::{DETECT_SIZE, DetectedFormat, detect_format, open};
+
+/// Code generated from `proto/log_service.proto`.
+pub mod proto {
+    tonic::include_proto!("mavlink_log.v1");
+}
+
+use proto::log_service_server::{LogService, LogServiceServer};
+use proto::{
+    Entry, GetSummaryRequest, ListLogsRequest, ListLogsResponse, LogInfo, MavlinkMessage,
+    StreamEntriesRequest, Summary,
+};
+
+/// Number of entries buffered for a client reading a stream.
+const STREAM_BUFFER: usize = 256;
+
+/// Log service serving the logs of a directory.
+///
+/// # Type Parameters
+/// - `M`: The MAVLink dialect to parse messages with.
+pub struct DirectoryService<M> {
+    dir: PathBuf,
+    dialect: PhantomData<fn() -> M>,
+}
+
+impl<M: Message + 'static> DirectoryService<M> {
+    /// Creates a new service.
+    ///
+    /// # Arguments
+    /// - `dir`: The directory holding the logs. Logs in subdirectories are not served.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            dialect: PhantomData,
+        }
+    }
+
+    /// Wraps the service in a tonic server, to be added to a `tonic::transport::Server`.
+    pub fn into_server(self) -> LogServiceServer<Self> {
+        LogServiceServer::new(self)
+    }
+
+    /// Serves the service until the server fails.
+    ///
+    /// # Arguments
+    /// - `addr`: The address to listen on.
+    ///
+    /// # Errors
+    /// Returns a `tonic::transport::Error` if the server could not be started or failed.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_server())
+            .serve(addr)
+            .await
+    }
+
+    /// Returns the path of a log of the directory.
+    ///
+    /// # Errors
+    /// Returns an `invalid_argument` status if the name is not a plain file name, so that no
+    /// file outside of the directory can be read.
+    fn log_path(&self, name: &str) -> Result<String, Status> {
+        let plain = Path::new(name).file_name().is_some_and(|file| file == name);
+        if !plain {
+            return Err(Status::invalid_argument(format!(
+                "'{name}' is not the name of a log"
+            )));
+        }
+        Ok(self.dir.join(name).to_string_lossy().into_owned())
+    }
+}
+
+/// Converts an `io::Error` to a status.
+fn io_status(error: std::io::Error) -> Status {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => Status::not_found(error.to_string()),
+        std::io::ErrorKind::InvalidData | std::io::ErrorKind::Unsupported => {
+            Status::failed_precondition(error.to_string())
+        }
+        _ => Status::internal(error.to_string()),
+    }
+}
+
+/// Returns whether a file starts like a supported log.
+fn is_log(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut bytes = Vec::with_capacity(DETECT_SIZE);
+    let read = std::fs::File::open(path)
+        .and_then(|file| file.take(DETECT_SIZE as u64).read_to_end(&mut bytes));
+    read.is_ok()
+        && matches!(
+            detect_format(&bytes),
+            DetectedFormat::MavLog { .. } | DetectedFormat::Tlog
+        )
+}
+
+/// Lists the logs of a directory, sorted by name.
+fn list_logs(dir: &Path) -> std::io::Result<Vec<LogInfo>> {
+    let mut logs = Vec::new();
+    for dir_entry in std::fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let metadata = dir_entry.metadata()?;
+        if !metadata.is_file() || !is_log(&dir_entry.path()) {
+            continue;
+        }
+        let modified_unix_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_millis() as u64);
+        logs.push(LogInfo {
+            name: dir_entry.file_name().to_string_lossy().into_owned(),
+            size_bytes: metadata.len(),
+            modified_unix_ms,
+        });
+    }
+    logs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(logs)
+}
+
+/// Converts a log entry to its protobuf message.
+///
+/// # Returns
+/// The entry, or `None` for entries other than MAVLink messages, text and raw data.
+fn to_proto<M: Message>(entry: LogEntry<M>) -> Option<Entry> {
+    use proto::entry::Content;
+
+    let content = if let Some(msg) = &entry.mav_message {
+        let header = entry.mav_header.unwrap_or_default();
+        let mut payload = vec![0u8; fields::MAX_PAYLOAD_SIZE];
+        let len = msg.ser(MavlinkVersion::V2, &mut payload);
+        payload.truncate(len);
+        Content::Mavlink(MavlinkMessage {
+            system_id: header.system_id as u32,
+            component_id: header.component_id as u32,
+            sequence: header.sequence as u32,
+            message_id: msg.message_id(),
+            message_name: msg.message_name().to_string(),
+            payload,
+        })
+    } else if let Some(text) = entry.text {
+        Content::Text(text)
+    } else if let Some(raw) = entry.raw {
+        Content::Raw(raw)
+    } else {
+        return None;
+    };
+    Some(Entry {
+        timestamp_us: entry.timestamp,
+        sequence: entry.sequence,
+        content: Some(content),
+    })
+}
+
+/// Reads a full log and summarizes it.
+fn summarize<M: Message + 'static>(path: &str) -> std::io::Result<Summary> {
+    let mut parser = open::<M>(path)?;
+    let mut summary = Summary::default();
+    let mut message_counts: HashMap<String, u64> = HashMap::new();
+    for_each_entry(&mut parser, |entry| {
+        summary.entry_count += 1;
+        if let Some(timestamp) = entry.timestamp {
+            summary.first_timestamp_us.get_or_insert(timestamp);
+            summary.last_timestamp_us = Some(timestamp);
+        }
+        if let Some(msg) = &entry.mav_message {
+            summary.message_count += 1;
+            *message_counts
+                .entry(msg.message_name().to_string())
+                .or_default() += 1;
+        }
+        Ok(())
+    })?;
+    summary.message_counts = message_counts;
+    Ok(summary)
+}
+
+/// Runs blocking work on the blocking thread pool of the runtime.
+async fn blocking<T, F>(work: F) -> Result<T, Status>
+where
+    T: Send + 'static,
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(io_status)
+}
+
+#[tonic::async_trait]
+impl<M: Message + 'static> LogService for DirectoryService<M> {
+    async fn list_logs(
+        &self,
+        _request: Request<ListLogsRequest>,
+    ) -> Result<Response<ListLogsResponse>, Status> {
+        let dir = self.dir.clone();
+        let logs = blocking(move || list_logs(&dir)).await?;
+        Ok(Response::new(ListLogsResponse { logs }))
+    }
+
+    type StreamEntriesStream = ReceiverStream<Result<Entry, Status>>;
+
+    async fn stream_entries(
+        &self,
+        request: Request<StreamEntriesRequest>,
+    ) -> Result<Response<Self::StreamEntriesStream>, Status> {
+        let request = request.into_inner();
+        let path = self.log_path(&request.name)?;
+        let filter = match request.filter.trim() {
+            "" => None,
+            expr => Some(
+                ParserFilter::from_expr(expr)
+                    .map_err(|e| Status::invalid_argument(e.to_string()))?,
+            ),
+        };
+        // check the log opens before answering so that a missing log fails the call itself
+        let check_path = path.clone();
+        blocking(move || open::<M>(&check_path).map(|_| ())).await?;
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            let result = open::<M>(&path).and_then(|mut parser| {
+                for_each_entry(&mut parser, |entry| {
+                    if filter
+                        .as_ref()
+                        .is_some_and(|filter| !filter.matches(&entry))
+                    {
+                        return Ok(());
+                    }
+                    let Some(entry) = to_proto(entry) else {
+                        return Ok(());
+                    };
+                    sender
+                        .blocking_send(Ok(entry))
+                        .map_err(|_| std::io::Error::other("client disconnected"))
+                })
+            });
+            if let Err(e) = result {
+                // fails if the client disconnected, in which case nobody is left to tell
+                let _ = sender.blocking_send(Err(io_status(e)));
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn get_summary(
+        &self,
+        request: Request<GetSummaryRequest>,
+    ) -> Result<Response<Summary>, Status> {
+        let path = self.log_path(&request.into_inner().name)?;
+        let summary = blocking(move || summarize::<M>(&path)).await?;
+        Ok(Response::new(summary))
+    }
+}
+
+#[cfg(all(test, feature = "logger"))]
+mod tests {
+    use mavlink::common::MavMessage;
+    use mavlink::{MavFrame, MavHeader};
+    use tempfile::TempDir;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::mav_logger::MavLogger;
+    use crate::mavlog::logger::RotatingMavLogger;
+
+    fn frame(system_id: u8, msg: MavMessage) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader {
+                system_id,
+                component_id: 1,
+                sequence: 0,
+            },
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    /// Test that the logs of a directory are listed, streamed with a filter and summarized.
+    #[test]
+    fn test_directory_service() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("flight.mav");
+        let mut logger =
+            RotatingMavLogger::new(path.to_str().unwrap(), 100000, 0, None, None).unwrap();
+        logger
+            .write_mavlink(frame(1, MavMessage::HEARTBEAT(Default::default())))
+            .unwrap();
+        logger
+            .write_mavlink(frame(2, MavMessage::HEARTBEAT(Default::default())))
+            .unwrap();
+        logger
+            .write_mavlink(frame(1, MavMessage::ATTITUDE(Default::default())))
+            .unwrap();
+        logger.write_text("note").unwrap();
+        drop(logger);
+        std::fs::write(dir.path().join("notes.txt"), "not a log").unwrap();
+
+        let service = DirectoryService::<MavMessage>::new(dir.path());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let logs = service
+                .list_logs(Request::new(ListLogsRequest {}))
+                .await
+                .unwrap()
+                .into_inner()
+                .logs;
+            assert_eq!(logs.len(), 1);
+            assert_eq!(logs[0].name, "flight.mav");
+
+            let request = StreamEntriesRequest {
+                name: String::from("flight.mav"),
+                filter: String::from("sysid == 1"),
+            };
+            let entries: Vec<Entry> = service
+                .stream_entries(Request::new(request))
+                .await
+                .unwrap()
+                .into_inner()
+                .map(|entry| entry.unwrap())
+                .collect()
+                .await;
+            let names: Vec<String> = entries
+                .into_iter()
+                .map(|entry| match entry.content {
+                    Some(proto::entry::Content::Mavlink(msg)) => msg.message_name,
+                    _ => String::new(),
+                })
+                .collect();
+            assert_eq!(names, vec!["HEARTBEAT", "ATTITUDE"]);
+
+            let summary = service
+                .get_summary(Request::new(GetSummaryRequest {
+                    name: String::from("flight.mav"),
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(summary.entry_count, 4);
+            assert_eq!(summary.message_count, 3);
+            assert_eq!(summary.message_counts["HEARTBEAT"], 2);
+
+            let escape = service
+                .get_summary(Request::new(GetSummaryRequest {
+                    name: String::from("../flight.mav"),
+                }))
+                .await;
+            assert_eq!(escape.unwrap_err().code(), tonic::Code::InvalidArgument);
+        });
+    }
+}
@@ -0,0 +1,246 @@
+//! This module provides Ed25519 signatures for tamper-evident log files.
+//!
+//! A log file is signed by hashing its complete content with SHA-512 and signing the digest. The
+//! signature is stored next to the log in a sidecar file with the `.sig` suffix appended to the
+//! log file name, so the log itself stays readable by any parser.
+//! See docs/mav_log_file_format.md for the layout of the sidecar.
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::{Signature, Signer, Verifier};
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+use sha2::{Digest, Sha512};
+
+use crate::keys::KeyProvider;
+
+/// Magic bytes identifying a signature sidecar.
+const MAGIC: &[u8; 6] = b"MAVSIG";
+/// Version of the signature sidecar format.
+const VERSION: u16 = 1;
+
+/// Struct representing the content of a signature sidecar.
+#[derive(PartialEq, Clone, Debug)]
+pub struct FileSignature {
+    /// Identifier of the key the file was signed with.
+    pub key_id: String,
+    /// Ed25519 signature over the SHA-512 digest of the file.
+    pub signature: [u8; 64],
+}
+
+impl FileSignature {
+    /// Size of the packed signature sidecar in bytes.
+    pub const SIZE: usize = 104;
+
+    /// Unpacks the content of a signature sidecar.
+    ///
+    /// # Arguments
+    /// - `packed_data`: The content of the sidecar file.
+    ///
+    /// # Returns
+    /// A `Result` containing the `FileSignature` or an `io::Error` of kind `InvalidData` if the
+    /// content is not a supported signature sidecar.
+    pub fn unpack(packed_data: &[u8]) -> std::io::Result<Self> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Not a supported signature sidecar",
+            )
+        };
+        if packed_data.len() != Self::SIZE || &packed_data[0..6] != MAGIC {
+            return Err(invalid());
+        }
+        if u16::from_le_bytes([packed_data[6], packed_data[7]]) != VERSION {
+            return Err(invalid());
+        }
+        // stop at the first null byte when unpacking a string
+        let key_id_end: usize = packed_data[8..40]
+            .iter()
+            .position(|&x| x == 0)
+            .map_or(40, |index| index + 8);
+        let key_id =
+            String::from_utf8(packed_data[8..key_id_end].to_vec()).map_err(|_| invalid())?;
+        Ok(FileSignature {
+            key_id,
+            signature: packed_data[40..104].try_into().unwrap(),
+        })
+    }
+
+    /// Packs the `FileSignature` into the content of a signature sidecar.
+    ///
+    /// # Returns
+    /// A `Vec<u8>` containing the magic bytes, format version, key id (32 bytes, UTF-8 encoded)
+    /// and signature.
+    pub fn pack(&self) -> Vec<u8> {
+        assert!(self.key_id.len() <= 32, "key_id must be 32 bytes or less");
+        let mut key_id_bytes = [0u8; 32];
+        key_id_bytes[..self.key_id.len()].copy_from_slice(self.key_id.as_bytes());
+
+        let mut packed: Vec<u8> = Vec::with_capacity(Self::SIZE);
+        packed.extend_from_slice(MAGIC);
+        packed.extend_from_slice(&VERSION.to_le_bytes());
+        packed.extend_from_slice(&key_id_bytes);
+        packed.extend_from_slice(&self.signature);
+        packed
+    }
+}
+
+/// Returns the path of the signature sidecar for a log file.
+pub fn sidecar_path(file_path: &Path) -> PathBuf {
+    let mut name = file_path.as_os_str().to_os_string();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// Signs a log file and writes the signature sidecar.
+///
+/// # Arguments
+/// - `file_path`: The path of the log file to sign.
+/// - `signing_key`: The Ed25519 key to sign with.
+/// - `key_id`: Identifier of the key recorded in the sidecar, at most 32 bytes.
+///
+/// # Returns
+/// A `Result` containing the path of the written sidecar or an `io::Error` if the log could not
+/// be read, the sidecar could not be written or the key id is too long.
+pub fn sign_file(
+    file_path: &str,
+    signing_key: &SigningKey,
+    key_id: &str,
+) -> std::io::Result<PathBuf> {
+    if key_id.len() > 32 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Key id must be 32 bytes or less",
+        ));
+    }
+    let digest = file_digest(Path::new(file_path))?;
+    let signature = FileSignature {
+        key_id: String::from(key_id),
+        signature: signing_key.sign(&digest).to_bytes(),
+    };
+    let path = sidecar_path(Path::new(file_path));
+    std::fs::write(&path, signature.pack())?;
+    Ok(path)
+}
+
+/// Reads the signature sidecar of a log file.
+///
+/// # Arguments
+/// - `file_path`: The path of the signed log file.
+///
+/// # Returns
+/// A `Result` containing the `FileSignature` or an `io::Error` if the sidecar is missing or
+/// invalid.
+pub fn read_signature(file_path: &str) -> std::io::Result<FileSignature> {
+    FileSignature::unpack(&std::fs::read(sidecar_path(Path::new(file_path)))?)
+}
+
+/// Verifies the signature sidecar of a log file.
+///
+/// # Arguments
+/// - `file_path`: The path of the signed log file.
+/// - `verifying_key`: The public key matching the key the file was signed with.
+///
+/// # Returns
+/// A `Result` containing `true` if the file is unchanged since it was signed and `false`
+/// otherwise, or an `io::Error` if the log or its sidecar could not be read.
+pub fn verify_signature(file_path: &str, verifying_key: &VerifyingKey) -> std::io::Result<bool> {
+    let signature = read_signature(file_path)?;
+    let digest = file_digest(Path::new(file_path))?;
+    Ok(verifying_key
+        .verify(&digest, &Signature::from_bytes(&signature.signature))
+        .is_ok())
+}
+
+/// Verifies the signature sidecar of a log file with the key named in the sidecar.
+///
+/// # Arguments
+/// - `file_path`: The path of the signed log file.
+/// - `key_provider`: Provider of the Ed25519 public keys, looked up by the sidecar key id.
+///
+/// # Returns
+/// A `Result` containing `true` if the file is unchanged since it was signed and `false`
+/// otherwise, or an `io::Error` if the log or its sidecar could not be read, or of kind
+/// `NotFound` if the key is not available.
+pub fn verify_signature_with(
+    file_path: &str,
+    key_provider: &dyn KeyProvider,
+) -> std::io::Result<bool> {
+    let signature = read_signature(file_path)?;
+    let key = key_provider.key(&signature.key_id).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Verifying key {} is not available", signature.key_id),
+        )
+    })?;
+    // A key that is not a valid curve point cannot have produced any signature
+    let Ok(verifying_key) = VerifyingKey::from_bytes(&key) else {
+        return Ok(false);
+    };
+    verify_signature(file_path, &verifying_key)
+}
+
+/// Computes the SHA-512 digest of a file.
+fn file_digest(file_path: &Path) -> std::io::Result<[u8; 64]> {
+    let mut file = File::open(file_path)?;
+    let mut hasher = Sha512::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    Ok(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that a signed file verifies until it is modified or checked with another key.
+    #[test]
+    fn test_sign_and_verify() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("flight.mav");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, b"flight data").unwrap();
+
+        let signing_key = SigningKey::from_bytes(&[3u8; 32]);
+        let sidecar = sign_file(path, &signing_key, "ops-2024").unwrap();
+        assert_eq!(sidecar, PathBuf::from(format!("{path}.sig")));
+        assert_eq!(read_signature(path).unwrap().key_id, "ops-2024");
+        assert!(verify_signature(path, &signing_key.verifying_key()).unwrap());
+
+        let other_key = SigningKey::from_bytes(&[4u8; 32]);
+        assert!(!verify_signature(path, &other_key.verifying_key()).unwrap());
+
+        std::fs::write(path, b"flight dat4").unwrap();
+        assert!(!verify_signature(path, &signing_key.verifying_key()).unwrap());
+    }
+
+    /// Tests that verification picks the public key named in the sidecar after a key rotation.
+    #[test]
+    fn test_verify_with_rotated_keys() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("flight.mav");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, b"flight data").unwrap();
+
+        let old_key = SigningKey::from_bytes(&[3u8; 32]);
+        let new_key = SigningKey::from_bytes(&[4u8; 32]);
+        let mut public_keys = crate::keys::InMemoryKeyProvider::new();
+        public_keys.insert("ops-2024", old_key.verifying_key().to_bytes());
+        public_keys.insert("ops-2025", new_key.verifying_key().to_bytes());
+
+        sign_file(path, &old_key, "ops-2024").unwrap();
+        assert!(verify_signature_with(path, &public_keys).unwrap());
+
+        sign_file(path, &new_key, "ops-2026").unwrap();
+        let error = verify_signature_with(path, &public_keys).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+}
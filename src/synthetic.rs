@@ -0,0 +1,564 @@
+//! Generation of realistic synthetic logs for tests and benchmarks.
+//!
+//! A `SyntheticParser` simulates a vehicle sending heartbeats, attitude, GPS positions
+//! following a random walk and status texts at configurable rates, and yields them as a
+//! `MavParser` without touching the disk. The same flight is written to a .mav log with
+//! `write_mavlog` or to a tlog with `write_tlog`. Every value is drawn from a seeded `Rng`, so a
+//! seed always produces the same log, of any length.
+//!
+//! Corruption can be injected at a configurable rate. The parser reports a corrupted frame as an
+//! `InvalidData` error in place of the entry, as the file parsers do for corrupted content,
+//! while the file writers flip a byte of the written file for each corrupted frame.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use mavlink_log::mav_parser::for_each_entry;
+//! use mavlink_log::synthetic::{SyntheticConfig, SyntheticParser};
+//!
+//! let config = SyntheticConfig {
+//!     duration: Duration::from_secs(10),
+//!     ..Default::default()
+//! };
+//! let mut entries = 0;
+//! for_each_entry(&mut SyntheticParser::new(config), |_| {
+//!     entries += 1;
+//!     Ok(())
+//! })
+//! .unwrap();
+//! assert!(entries > 500);
+//! ```
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use mavlink::common::{
+    ATTITUDE_DATA, GLOBAL_POSITION_INT_DATA, GPS_RAW_INT_DATA, GpsFixType, HEARTBEAT_DATA,
+    MavAutopilot, MavMessage, MavModeFlag, MavSeverity, MavState, MavType, STATUSTEXT_DATA,
+};
+use mavlink::error::MessageReadError;
+use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+
+use crate::clock::{BackwardsPolicy, Clock, ClockSource};
+use crate::mav_logger::MavLogger;
+use crate::mav_parser::{LogEntry, MavParser};
+use crate::mavlog::header::FormatFlags;
+use crate::mavlog::logger::RotatingMavLogger;
+use crate::testing::Rng;
+use crate::tlog::logger::RotatingTlog;
+
+/// Meters per degree of latitude.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+/// Largest horizontal speed of the simulated vehicle in meters per second.
+const MAX_SPEED: f64 = 5.0;
+/// Status texts sent in turn by the simulated vehicle.
+const STATUS_TEXTS: [&str; 4] = [
+    "EKF3 IMU0 is using GPS",
+    "Mission: 1 WP",
+    "Battery voltage nominal",
+    "Reached waypoint",
+];
+
+/// Description of a synthetic flight.
+///
+/// Rates of 0 leave the corresponding messages out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticConfig {
+    /// Seed of the pseudo random values.
+    pub seed: u64,
+    /// Time of the first entry in microseconds since the UNIX epoch.
+    pub start_us: u64,
+    /// Length of the flight.
+    pub duration: Duration,
+    /// System id of the simulated vehicle.
+    pub system_id: u8,
+    /// Component id of the simulated autopilot.
+    pub component_id: u8,
+    /// Rate of HEARTBEAT messages in Hz.
+    pub heartbeat_hz: f64,
+    /// Rate of ATTITUDE messages in Hz.
+    pub attitude_hz: f64,
+    /// Rate of GPS_RAW_INT and GLOBAL_POSITION_INT messages in Hz.
+    pub gps_hz: f64,
+    /// Rate of STATUSTEXT messages in Hz.
+    pub statustext_hz: f64,
+    /// Latitude the flight starts at in degrees.
+    pub home_lat: f64,
+    /// Longitude the flight starts at in degrees.
+    pub home_lon: f64,
+    /// Altitude above mean sea level the flight starts at in meters.
+    pub home_alt: f64,
+    /// Probability of each frame being corrupted, between 0 and 1.
+    pub corruption_rate: f64,
+}
+
+impl Default for SyntheticConfig {
+    /// A one minute flight at typical ArduPilot stream rates without corruption.
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            start_us: 1_700_000_000_000_000,
+            duration: Duration::from_secs(60),
+            system_id: 1,
+            component_id: 1,
+            heartbeat_hz: 1.0,
+            attitude_hz: 50.0,
+            gps_hz: 5.0,
+            statustext_hz: 0.1,
+            home_lat: 47.397742,
+            home_lon: 8.545594,
+            home_alt: 488.0,
+            corruption_rate: 0.0,
+        }
+    }
+}
+
+/// Messages sent by the simulated vehicle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stream {
+    Heartbeat,
+    Attitude,
+    GpsRaw,
+    GlobalPosition,
+    StatusText,
+}
+
+/// Parser yielding the entries of a synthetic flight, see the module documentation.
+pub struct SyntheticParser {
+    config: SyntheticConfig,
+    rng: Rng,
+    /// Streams with their period and the time of their next message in microseconds.
+    schedule: Vec<(Stream, u64, u64)>,
+    end_us: u64,
+    sequence: u8,
+    /// Position of the vehicle north and east of home in meters.
+    north: f64,
+    east: f64,
+    /// Velocity of the vehicle north and east in meters per second.
+    velocity_north: f64,
+    velocity_east: f64,
+    /// Time the position was last updated in microseconds.
+    position_us: u64,
+    statustexts: usize,
+}
+
+impl SyntheticParser {
+    /// Creates a new `SyntheticParser`.
+    ///
+    /// # Arguments
+    /// - `config`: The flight to simulate.
+    pub fn new(config: SyntheticConfig) -> Self {
+        let start_us = config.start_us;
+        let mut schedule = Vec::new();
+        for (stream, hz) in [
+            (Stream::Heartbeat, config.heartbeat_hz),
+            (Stream::Attitude, config.attitude_hz),
+            (Stream::GpsRaw, config.gps_hz),
+            (Stream::GlobalPosition, config.gps_hz),
+            (Stream::StatusText, config.statustext_hz),
+        ] {
+            if hz > 0.0 {
+                let period_us = ((1e6 / hz) as u64).max(1);
+                schedule.push((stream, period_us, start_us));
+            }
+        }
+        Self {
+            rng: Rng::new(config.seed),
+            schedule,
+            end_us: start_us + config.duration.as_micros() as u64,
+            sequence: 0,
+            north: 0.0,
+            east: 0.0,
+            velocity_north: 0.0,
+            velocity_east: 0.0,
+            position_us: start_us,
+            statustexts: 0,
+            config,
+        }
+    }
+
+    /// Generates the next frame of the flight.
+    ///
+    /// # Returns
+    /// The entry and whether it is to be corrupted, or `None` at the end of the flight.
+    fn next_frame(&mut self) -> Option<(LogEntry<MavMessage>, bool)> {
+        let next = self
+            .schedule
+            .iter_mut()
+            .min_by_key(|(_, _, next_us)| *next_us)?;
+        let (stream, timestamp) = (next.0, next.2);
+        if timestamp >= self.end_us {
+            return None;
+        }
+        next.2 += next.1;
+        let msg = self.message(stream, timestamp);
+        let header = MavHeader {
+            system_id: self.config.system_id,
+            component_id: self.config.component_id,
+            sequence: self.sequence,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        let corrupted = self.unit() < self.config.corruption_rate;
+        let entry = LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(header),
+            mav_message: Some(msg),
+            protocol_version: Some(MavlinkVersion::V2),
+            ..Default::default()
+        };
+        Some((entry, corrupted))
+    }
+
+    /// Returns a pseudo random number within `0.0..1.0`.
+    fn unit(&mut self) -> f64 {
+        (self.rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a pseudo random number within `-1.0..1.0`.
+    fn noise(&mut self) -> f64 {
+        self.unit() * 2.0 - 1.0
+    }
+
+    /// Moves the vehicle along its random walk up to the given time.
+    fn update_position(&mut self, timestamp: u64) {
+        let dt = timestamp.saturating_sub(self.position_us) as f64 / 1e6;
+        if dt <= 0.0 {
+            return;
+        }
+        self.position_us = timestamp;
+        self.velocity_north =
+            (self.velocity_north + self.noise() * dt.sqrt()).clamp(-MAX_SPEED, MAX_SPEED);
+        self.velocity_east =
+            (self.velocity_east + self.noise() * dt.sqrt()).clamp(-MAX_SPEED, MAX_SPEED);
+        self.north += self.velocity_north * dt;
+        self.east += self.velocity_east * dt;
+    }
+
+    /// Generates the message of a stream at the given time.
+    fn message(&mut self, stream: Stream, timestamp: u64) -> MavMessage {
+        let elapsed = (timestamp - self.config.start_us) as f64 / 1e6;
+        let time_boot_ms = (elapsed * 1e3) as u32;
+        match stream {
+            Stream::Heartbeat => MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+                custom_mode: 3,
+                mavtype: MavType::MAV_TYPE_QUADROTOR,
+                autopilot: MavAutopilot::MAV_AUTOPILOT_ARDUPILOTMEGA,
+                base_mode: MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED
+                    | MavModeFlag::MAV_MODE_FLAG_CUSTOM_MODE_ENABLED,
+                system_status: MavState::MAV_STATE_ACTIVE,
+                mavlink_version: 3,
+            }),
+            Stream::Attitude => {
+                let turn = std::f64::consts::TAU * elapsed;
+                let yaw = self.velocity_east.atan2(self.velocity_north);
+                MavMessage::ATTITUDE(ATTITUDE_DATA {
+                    time_boot_ms,
+                    roll: (0.2 * (turn / 7.0).sin() + 0.01 * self.noise()) as f32,
+                    pitch: (0.1 * (turn / 11.0).sin() + 0.01 * self.noise()) as f32,
+                    yaw: yaw as f32,
+                    rollspeed: (0.2 * (turn / 7.0).cos() * std::f64::consts::TAU / 7.0) as f32,
+                    pitchspeed: (0.1 * (turn / 11.0).cos() * std::f64::consts::TAU / 11.0) as f32,
+                    yawspeed: (0.05 * self.noise()) as f32,
+                })
+            }
+            Stream::GpsRaw => {
+                self.update_position(timestamp);
+                let (lat, lon) = self.lat_lon();
+                let speed = self.velocity_north.hypot(self.velocity_east);
+                let course = self
+                    .velocity_east
+                    .atan2(self.velocity_north)
+                    .to_degrees()
+                    .rem_euclid(360.0);
+                MavMessage::GPS_RAW_INT(GPS_RAW_INT_DATA {
+                    time_usec: timestamp,
+                    fix_type: GpsFixType::GPS_FIX_TYPE_3D_FIX,
+                    lat,
+                    lon,
+                    alt: (self.config.home_alt * 1e3) as i32 + 10_000,
+                    eph: 80 + (20.0 * self.unit()) as u16,
+                    epv: 120 + (20.0 * self.unit()) as u16,
+                    vel: (speed * 100.0) as u16,
+                    cog: (course * 100.0) as u16,
+                    satellites_visible: 14,
+                    ..Default::default()
+                })
+            }
+            Stream::GlobalPosition => {
+                self.update_position(timestamp);
+                let (lat, lon) = self.lat_lon();
+                let heading = self
+                    .velocity_east
+                    .atan2(self.velocity_north)
+                    .to_degrees()
+                    .rem_euclid(360.0);
+                MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                    time_boot_ms,
+                    lat,
+                    lon,
+                    alt: (self.config.home_alt * 1e3) as i32 + 10_000,
+                    relative_alt: 10_000 + (100.0 * self.noise()) as i32,
+                    vx: (self.velocity_north * 100.0) as i16,
+                    vy: (self.velocity_east * 100.0) as i16,
+                    vz: 0,
+                    hdg: (heading * 100.0) as u16,
+                })
+            }
+            Stream::StatusText => {
+                let content = STATUS_TEXTS[self.statustexts % STATUS_TEXTS.len()];
+                self.statustexts += 1;
+                let mut text = [0u8; 50];
+                text[..content.len()].copy_from_slice(content.as_bytes());
+                MavMessage::STATUSTEXT(STATUSTEXT_DATA {
+                    severity: MavSeverity::MAV_SEVERITY_INFO,
+                    text,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    /// Returns the position of the vehicle in degrees * 1e7.
+    fn lat_lon(&self) -> (i32, i32) {
+        let lat = self.config.home_lat + self.north / METERS_PER_DEGREE;
+        let lon = self.config.home_lon
+            + self.east / (METERS_PER_DEGREE * self.config.home_lat.to_radians().cos());
+        ((lat * 1e7) as i32, (lon * 1e7) as i32)
+    }
+}
+
+impl MavParser for SyntheticParser {
+    type M = MavMessage;
+
+    /// Generates the next entry of the flight.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` of kind `InvalidData` in place of a corrupted frame, and of kind
+    /// `UnexpectedEof` at the end of the flight.
+    fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+        match self.next_frame() {
+            Some((entry, false)) => Ok(entry),
+            Some((_, true)) => Err(MessageReadError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Corrupted synthetic frame",
+            ))),
+            None => Err(MessageReadError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "End of the synthetic flight",
+            ))),
+        }
+    }
+}
+
+/// Writes a synthetic flight to a .mav log.
+///
+/// # Arguments
+/// - `config`: The flight to simulate.
+/// - `path`: Path of the log to create. Parent directories are expected to exist.
+/// - `format_flags`: Optional format flags of the log.
+///
+/// # Returns
+/// The number of frames written, corrupted ones included.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be written.
+pub fn write_mavlog(
+    config: SyntheticConfig,
+    path: &str,
+    format_flags: Option<FormatFlags>,
+) -> std::io::Result<u64> {
+    let mut logger = RotatingMavLogger::new(path, u64::MAX, 0, format_flags, None)?;
+    let header_len = logger.header().pack().len() as u64;
+    let time = Arc::new(AtomicU64::new(config.start_us));
+    logger.set_clock(shared_clock(&time));
+    let mut parser = SyntheticParser::new(config);
+    let (frames, corrupted) = write_frames(&mut parser, &mut logger, &time)?;
+    logger.flush()?;
+    drop(logger);
+    corrupt_file(path, header_len, corrupted, &mut parser.rng)?;
+    Ok(frames)
+}
+
+/// Writes a synthetic flight to a tlog.
+///
+/// # Arguments
+/// - `config`: The flight to simulate.
+/// - `path`: Path of the log to create. Parent directories are expected to exist.
+///
+/// # Returns
+/// The number of frames written, corrupted ones included.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be written.
+pub fn write_tlog(config: SyntheticConfig, path: &str) -> std::io::Result<u64> {
+    let mut logger = RotatingTlog::new(path, u64::MAX, 0)?;
+    let time = Arc::new(AtomicU64::new(config.start_us));
+    logger.set_clock(shared_clock(&time));
+    let mut parser = SyntheticParser::new(config);
+    let (frames, corrupted) = write_frames(&mut parser, &mut logger, &time)?;
+    drop(logger);
+    corrupt_file(path, 0, corrupted, &mut parser.rng)?;
+    Ok(frames)
+}
+
+/// Returns a clock reading the time of the frame being written.
+fn shared_clock(time: &Arc<AtomicU64>) -> Clock {
+    let time = time.clone();
+    Clock::new(
+        ClockSource::Custom(Box::new(move || time.load(Ordering::SeqCst))),
+        BackwardsPolicy::Allow,
+    )
+}
+
+/// Writes every frame of a flight to a logger.
+///
+/// # Returns
+/// The number of frames written and the number of them to corrupt.
+fn write_frames<L: MavLogger>(
+    parser: &mut SyntheticParser,
+    logger: &mut L,
+    time: &AtomicU64,
+) -> std::io::Result<(u64, u64)> {
+    let mut frames: u64 = 0;
+    let mut corrupted: u64 = 0;
+    while let Some((entry, corrupt)) = parser.next_frame() {
+        time.store(entry.timestamp.unwrap_or_default(), Ordering::SeqCst);
+        let (Some(header), Some(msg)) = (entry.mav_header, entry.mav_message) else {
+            continue;
+        };
+        logger.write_mavlink(MavFrame {
+            header,
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        })?;
+        frames += 1;
+        corrupted += u64::from(corrupt);
+    }
+    Ok((frames, corrupted))
+}
+
+/// Inverts one byte at a pseudo random position after the file header for each corrupted frame.
+fn corrupt_file(path: &str, header_len: u64, corrupted: u64, rng: &mut Rng) -> std::io::Result<()> {
+    if corrupted == 0 {
+        return Ok(());
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+    let len = file.metadata()?.len();
+    if len <= header_len {
+        return Ok(());
+    }
+    for _ in 0..corrupted {
+        let offset = header_len + rng.below(len - header_len);
+        let mut byte = [0u8; 1];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut byte)?;
+        byte[0] = !byte[0];
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&byte)?;
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mav_parser::for_each_entry;
+    use crate::mavlog::parser::MavLogParser;
+
+    /// Counts the entries of each message and the corrupted entries of a parser.
+    fn census<P: MavParser<M = MavMessage>>(parser: &mut P) -> (Vec<(String, u64)>, u64) {
+        let mut counts: std::collections::BTreeMap<String, u64> = Default::default();
+        let mut corrupted: u64 = 0;
+        loop {
+            match parser.parse_next_entry() {
+                Ok(entry) => {
+                    if let Some(msg) = entry.mav_message {
+                        *counts
+                            .entry(String::from(mavlink::Message::message_name(&msg)))
+                            .or_default() += 1;
+                    }
+                }
+                Err(MessageReadError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    break;
+                }
+                Err(_) => corrupted += 1,
+            }
+        }
+        (counts.into_iter().collect(), corrupted)
+    }
+
+    /// Test that a seed always produces the same flight at the configured rates.
+    #[test]
+    fn test_synthetic_parser() {
+        let config = SyntheticConfig {
+            seed: 7,
+            duration: Duration::from_secs(20),
+            corruption_rate: 0.05,
+            ..Default::default()
+        };
+        let (counts, corrupted) = census(&mut SyntheticParser::new(config.clone()));
+        let total: u64 = counts.iter().map(|(_, count)| count).sum::<u64>() + corrupted;
+        assert_eq!(total, 20 + 20 * 50 + 2 * 20 * 5 + 2);
+        assert!(corrupted > 20 && corrupted < 100);
+        assert_eq!(
+            census(&mut SyntheticParser::new(config)),
+            (counts, corrupted)
+        );
+
+        let mut first = None;
+        let mut last = None;
+        for_each_entry(&mut SyntheticParser::new(Default::default()), |entry| {
+            if let Some(MavMessage::GLOBAL_POSITION_INT(position)) = entry.mav_message {
+                first.get_or_insert((position.lat, position.lon));
+                last = Some((position.lat, position.lon));
+            }
+            Ok(())
+        })
+        .unwrap();
+        // the vehicle moves, but stays within a few hundred meters of home
+        assert_ne!(first, last);
+        let (lat, lon) = last.unwrap();
+        assert!((lat - 473_977_420).abs() < 60_000);
+        assert!((lon - 85_455_940).abs() < 90_000);
+    }
+
+    /// Test that the written logs hold the flight and the injected corruption.
+    #[test]
+    fn test_synthetic_files() {
+        let dir = TempDir::new().unwrap();
+        let config = SyntheticConfig {
+            duration: Duration::from_secs(5),
+            ..Default::default()
+        };
+        let expected = census(&mut SyntheticParser::new(config.clone()));
+
+        let mavlog = dir.path().join("synthetic.mav");
+        let mavlog = mavlog.to_str().unwrap();
+        let frames = write_mavlog(config.clone(), mavlog, None).unwrap();
+        assert_eq!(frames, 5 + 250 + 50 + 1);
+        let mut parser = MavLogParser::<MavMessage>::new(mavlog);
+        assert_eq!(census(&mut parser), expected);
+
+        let tlog = dir.path().join("synthetic.tlog");
+        let tlog = tlog.to_str().unwrap();
+        write_tlog(config.clone(), tlog).unwrap();
+        let mut parser = crate::tlog::parser::TlogParser::<MavMessage>::new(tlog);
+        assert_eq!(census(&mut parser), expected);
+
+        let corrupted = SyntheticConfig {
+            corruption_rate: 0.2,
+            ..config
+        };
+        write_mavlog(corrupted, mavlog, None).unwrap();
+        let mut parser = MavLogParser::<MavMessage>::new(mavlog);
+        let (counts, _) = census(&mut parser);
+        let intact: u64 = counts.iter().map(|(_, count)| count).sum();
+        assert!(intact < frames);
+    }
+}
@@ -0,0 +1,369 @@
+//! Enrichment of exported positions with terrain data.
+//!
+//! Logged altitudes are above mean sea level or above the home position, while agricultural
+//! operations care about the height above the crop. An `Enrichment` is invoked for every
+//! position fix of an export and returns extra values written along with the message, such as
+//! the above ground level altitude computed by `AglEnrichment` from a user supplied
+//! `TerrainLookup`. With the `geotiff` feature, `GeoTiffTerrain` looks up elevations in a
+//! digital elevation model.
+use mavlink::Message;
+
+use crate::fields;
+use crate::mav_parser::LogEntry;
+
+/// A position of a vehicle read from a log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionFix {
+    /// Timestamp of the entry holding the position.
+    pub timestamp_us: Option<u64>,
+    /// MAVLink system id of the vehicle.
+    pub system_id: u8,
+    /// Latitude in degrees.
+    pub latitude_deg: f64,
+    /// Longitude in degrees.
+    pub longitude_deg: f64,
+    /// Altitude above mean sea level in meters.
+    pub altitude_msl_m: f64,
+}
+
+/// Reads the position fix of an entry.
+///
+/// Positions are read from GLOBAL_POSITION_INT and GPS_RAW_INT messages. Positions at
+/// latitude and longitude 0, sent without a position estimate, are not fixes.
+///
+/// # Arguments
+/// - `entry`: The entry to read.
+///
+/// # Returns
+/// The position fix, or `None` if the entry holds no position.
+pub fn position_fix<M: Message>(entry: &LogEntry<M>) -> Option<PositionFix> {
+    let (header, msg) = (entry.mav_header.as_ref()?, entry.mav_message.as_ref()?);
+    let offset = match msg.message_id() {
+        fields::GLOBAL_POSITION_INT_ID => 4,
+        fields::GPS_RAW_INT_ID => 8,
+        _ => return None,
+    };
+    let payload = fields::payload(msg);
+    let latitude = fields::read_i32(&payload, offset);
+    let longitude = fields::read_i32(&payload, offset + 4);
+    if latitude == 0 && longitude == 0 {
+        return None;
+    }
+    Some(PositionFix {
+        timestamp_us: entry.timestamp,
+        system_id: header.system_id,
+        latitude_deg: latitude as f64 / 1e7,
+        longitude_deg: longitude as f64 / 1e7,
+        altitude_msl_m: fields::read_i32(&payload, offset + 8) as f64 / 1e3,
+    })
+}
+
+/// Reads the home position of an entry.
+///
+/// # Arguments
+/// - `entry`: The entry to read.
+///
+/// # Returns
+/// The home position as a position fix, or `None` if the entry is not a HOME_POSITION message.
+pub fn home_position<M: Message>(entry: &LogEntry<M>) -> Option<PositionFix> {
+    let (header, msg) = (entry.mav_header.as_ref()?, entry.mav_message.as_ref()?);
+    if msg.message_id() != fields::HOME_POSITION_ID {
+        return None;
+    }
+    let payload = fields::payload(msg);
+    Some(PositionFix {
+        timestamp_us: entry.timestamp,
+        system_id: header.system_id,
+        latitude_deg: fields::read_i32(&payload, 0) as f64 / 1e7,
+        longitude_deg: fields::read_i32(&payload, 4) as f64 / 1e7,
+        altitude_msl_m: fields::read_i32(&payload, 8) as f64 / 1e3,
+    })
+}
+
+/// Hook computing extra values for each position fix of an export.
+///
+/// A pair of enrichments is an enrichment exporting the values of both.
+pub trait Enrichment {
+    /// Computes the extra values of a position fix.
+    ///
+    /// # Arguments
+    /// - `fix`: The position fix.
+    ///
+    /// # Returns
+    /// The names and values to export with the message holding the position. Values that
+    /// cannot be computed for this fix are left out.
+    fn enrich(&mut self, fix: &PositionFix) -> Vec<(String, f64)>;
+
+    /// Receives the home position of a vehicle, read with `home_position`. Does nothing by
+    /// default.
+    fn on_home(&mut self, _home: &PositionFix) {}
+}
+
+impl<A: Enrichment, B: Enrichment> Enrichment for (A, B) {
+    fn enrich(&mut self, fix: &PositionFix) -> Vec<(String, f64)> {
+        let mut values = self.0.enrich(fix);
+        values.extend(self.1.enrich(fix));
+        values
+    }
+
+    fn on_home(&mut self, home: &PositionFix) {
+        self.0.on_home(home);
+        self.1.on_home(home);
+    }
+}
+
+/// Source of terrain elevations.
+///
+/// Closures taking a latitude and longitude in degrees are lookups.
+pub trait TerrainLookup {
+    /// Returns the terrain elevation above mean sea level in meters at a position, or `None`
+    /// if it is unknown.
+    fn elevation_m(&mut self, latitude_deg: f64, longitude_deg: f64) -> Option<f64>;
+}
+
+impl<F: FnMut(f64, f64) -> Option<f64>> TerrainLookup for F {
+    fn elevation_m(&mut self, latitude_deg: f64, longitude_deg: f64) -> Option<f64> {
+        self(latitude_deg, longitude_deg)
+    }
+}
+
+/// Enrichment adding the terrain elevation as `terrain_alt` and the altitude above ground level
+/// as `agl`, both in meters.
+pub struct AglEnrichment<T: TerrainLookup> {
+    terrain: T,
+}
+
+impl<T: TerrainLookup> AglEnrichment<T> {
+    /// Creates a new `AglEnrichment`.
+    ///
+    /// # Arguments
+    /// - `terrain`: The source of terrain elevations, which must be relative to the same datum
+    ///   as the logged altitudes.
+    pub fn new(terrain: T) -> Self {
+        AglEnrichment { terrain }
+    }
+}
+
+impl<T: TerrainLookup> Enrichment for AglEnrichment<T> {
+    fn enrich(&mut self, fix: &PositionFix) -> Vec<(String, f64)> {
+        match self
+            .terrain
+            .elevation_m(fix.latitude_deg, fix.longitude_deg)
+        {
+            Some(elevation_m) => vec![
+                (String::from("terrain_alt"), elevation_m),
+                (String::from("agl"), fix.altitude_msl_m - elevation_m),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Terrain elevations read from a single band GeoTIFF digital elevation model.
+///
+/// The raster must be in geographic coordinates, such as WGS 84 (EPSG:4326) as SRTM and
+/// Copernicus tiles are distributed, and georeferenced with a pixel scale and a tie point.
+/// Projected rasters must be reprojected first, for example with `gdalwarp -t_srs EPSG:4326`.
+/// Elevations are interpolated bilinearly between pixel centers.
+#[cfg(feature = "geotiff")]
+pub struct GeoTiffTerrain {
+    grid: Grid,
+}
+
+#[cfg(feature = "geotiff")]
+impl GeoTiffTerrain {
+    /// Reads a GeoTIFF file.
+    ///
+    /// # Arguments
+    /// - `path`: Path of the GeoTIFF file.
+    ///
+    /// # Returns
+    /// The terrain of the raster.
+    ///
+    /// # Errors
+    /// Returns an `io::Error` if the file could not be read, or of kind `InvalidData` if it is
+    /// not a georeferenced single band raster.
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        use tiff::decoder::{Decoder, DecodingResult};
+        use tiff::tags::Tag;
+
+        let invalid = |reason: String| std::io::Error::new(std::io::ErrorKind::InvalidData, reason);
+        let tiff_error = |e: tiff::TiffError| invalid(format!("Invalid GeoTIFF {path}: {e}"));
+        let mut decoder = Decoder::new(std::io::BufReader::new(std::fs::File::open(path)?))
+            .map_err(tiff_error)?;
+        let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+        let scale = decoder
+            .get_tag_f64_vec(Tag::ModelPixelScaleTag)
+            .map_err(tiff_error)?;
+        let tie_point = decoder
+            .get_tag_f64_vec(Tag::ModelTiepointTag)
+            .map_err(tiff_error)?;
+        if scale.len() < 2 || tie_point.len() < 6 {
+            return Err(invalid(format!("GeoTIFF {path} is not georeferenced")));
+        }
+        let no_data = decoder
+            .find_tag(Tag::GdalNodata)
+            .map_err(tiff_error)?
+            .and_then(|value| value.into_string().ok())
+            .and_then(|value| value.trim_end_matches('\0').trim().parse::<f64>().ok());
+        let samples: Vec<f64> = match decoder.read_image().map_err(tiff_error)? {
+            DecodingResult::U8(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::U16(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::I16(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::I32(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::F32(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::F64(data) => data,
+            _ => return Err(invalid(format!("Unsupported sample format in {path}"))),
+        };
+        if samples.len() != width as usize * height as usize {
+            return Err(invalid(format!("GeoTIFF {path} has more than one band")));
+        }
+        Ok(GeoTiffTerrain {
+            grid: Grid {
+                width: width as usize,
+                height: height as usize,
+                // longitude and latitude of the corner of pixel (0, 0)
+                origin: (
+                    tie_point[3] - tie_point[0] * scale[0],
+                    tie_point[4] + tie_point[1] * scale[1],
+                ),
+                scale: (scale[0], scale[1]),
+                samples,
+                no_data,
+            },
+        })
+    }
+}
+
+#[cfg(feature = "geotiff")]
+impl TerrainLookup for GeoTiffTerrain {
+    fn elevation_m(&mut self, latitude_deg: f64, longitude_deg: f64) -> Option<f64> {
+        self.grid.interpolate(latitude_deg, longitude_deg)
+    }
+}
+
+/// Raster of elevations, north up, with rows running south.
+#[cfg_attr(not(feature = "geotiff"), allow(dead_code))]
+struct Grid {
+    width: usize,
+    height: usize,
+    /// Longitude and latitude of the north west corner of the raster in degrees.
+    origin: (f64, f64),
+    /// Width and height of a pixel in degrees.
+    scale: (f64, f64),
+    /// Elevations in row major order.
+    samples: Vec<f64>,
+    /// Value of pixels without elevation.
+    no_data: Option<f64>,
+}
+
+#[cfg_attr(not(feature = "geotiff"), allow(dead_code))]
+impl Grid {
+    /// Returns the elevation of a pixel, if it has one.
+    fn sample(&self, column: usize, row: usize) -> Option<f64> {
+        let value = *self.samples.get(row * self.width + column)?;
+        (Some(value) != self.no_data && value.is_finite()).then_some(value)
+    }
+
+    /// Interpolates the elevation at a position between the four surrounding pixel centers.
+    ///
+    /// Positions within half a pixel of the edge use the edge pixels. Positions outside of the
+    /// raster or next to a pixel without elevation have no elevation.
+    fn interpolate(&self, latitude_deg: f64, longitude_deg: f64) -> Option<f64> {
+        let x = (longitude_deg - self.origin.0) / self.scale.0;
+        let y = (self.origin.1 - latitude_deg) / self.scale.1;
+        if !(0.0..=self.width as f64).contains(&x) || !(0.0..=self.height as f64).contains(&y) {
+            return None;
+        }
+        // coordinates relative to pixel centers
+        let x = (x - 0.5).clamp(0.0, (self.width - 1) as f64);
+        let y = (y - 0.5).clamp(0.0, (self.height - 1) as f64);
+        let (column, row) = (x.floor() as usize, y.floor() as usize);
+        let next_column = (column + 1).min(self.width - 1);
+        let next_row = (row + 1).min(self.height - 1);
+        let (fx, fy) = (x - column as f64, y - row as f64);
+        let corners = [
+            (column, row, (1.0 - fx) * (1.0 - fy)),
+            (next_column, row, fx * (1.0 - fy)),
+            (column, next_row, (1.0 - fx) * fy),
+            (next_column, next_row, fx * fy),
+        ];
+        let mut elevation_m = 0.0;
+        for (column, row, weight) in corners {
+            // pixels without weight may lack elevation
+            if weight > 0.0 {
+                elevation_m += self.sample(column, row)? * weight;
+            }
+        }
+        Some(elevation_m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::MavHeader;
+    use mavlink::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+
+    use super::*;
+
+    /// Test that position fixes are enriched with the altitude above ground level.
+    #[test]
+    fn test_agl_enrichment() {
+        let entry = LogEntry {
+            timestamp: Some(1_000_000),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            }),
+            mav_message: Some(MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                lat: 470_000_000,
+                lon: 80_000_000,
+                alt: 520_500,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let fix = position_fix(&entry).unwrap();
+        assert_eq!((fix.latitude_deg, fix.longitude_deg), (47.0, 8.0));
+        assert_eq!(fix.altitude_msl_m, 520.5);
+
+        let mut enrichment = AglEnrichment::new(|latitude_deg: f64, _longitude_deg: f64| {
+            (latitude_deg > 46.0).then_some(500.0)
+        });
+        assert_eq!(
+            enrichment.enrich(&fix),
+            vec![
+                (String::from("terrain_alt"), 500.0),
+                (String::from("agl"), 20.5)
+            ]
+        );
+        let outside = PositionFix {
+            latitude_deg: 45.0,
+            ..fix
+        };
+        assert!(enrichment.enrich(&outside).is_empty());
+    }
+
+    /// Test that elevations are interpolated between pixel centers.
+    #[test]
+    fn test_grid_interpolate() {
+        let grid = Grid {
+            width: 2,
+            height: 2,
+            origin: (8.0, 47.0),
+            scale: (0.5, 0.5),
+            samples: vec![100.0, 200.0, 300.0, -9999.0],
+            no_data: Some(-9999.0),
+        };
+        // center of the north west pixel
+        assert_eq!(grid.interpolate(46.75, 8.25), Some(100.0));
+        assert_eq!(grid.interpolate(46.75, 8.5), Some(150.0));
+        // beyond the last pixel center
+        assert_eq!(grid.interpolate(46.75, 8.9), Some(200.0));
+        assert_eq!(grid.interpolate(46.5, 8.25), Some(200.0));
+        // next to the pixel without elevation
+        assert_eq!(grid.interpolate(46.5, 8.5), None);
+        assert_eq!(grid.interpolate(47.1, 8.25), None);
+    }
+}
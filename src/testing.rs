@@ -0,0 +1,388 @@
+//! Utilities for validating changes to the .mav format against this implementation.
+//!
+//! Crates extending the format can generate random valid file headers and entries, write them
+//! with `round_trip` and check that the parser returns them unchanged. Generators draw from a
+//! seeded `Rng`, so a failing case is reproduced by running the same seed again.
+//!
+//! ```
+//! use mavlink_log::testing::{Rng, random_entries, random_format_flags, round_trip};
+//!
+//! for seed in 0..10 {
+//!     let mut rng = Rng::new(seed);
+//!     let flags = random_format_flags(&mut rng);
+//!     let entries = random_entries(&mut rng, &flags, 50);
+//!     round_trip(flags, &entries).unwrap();
+//! }
+//! ```
+use mavlink::common::{
+    ATTITUDE_DATA, GLOBAL_POSITION_INT_DATA, HEARTBEAT_DATA, MavAutopilot, MavMessage, MavModeFlag,
+    MavSeverity, MavState, MavType, STATUSTEXT_DATA,
+};
+use mavlink::error::MessageReadError;
+use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+use uuid::Uuid;
+
+use crate::mav_parser::{LogEntry, MavParser};
+use crate::mavlog::header::{FileHeader, FormatFlags, MavlinkMessageDefinition};
+use crate::mavlog::logger::{EntryType, RotatingMavLogger};
+use crate::mavlog::parser::MavLogParser;
+
+/// Small deterministic pseudo random number generator (SplitMix64) driving the generators.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a new `Rng` from a seed.
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// Returns the next pseudo random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a pseudo random number below `bound`.
+    ///
+    /// # Panics
+    /// Panics if `bound` is 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "The bound must not be 0");
+        self.next_u64() % bound
+    }
+
+    /// Returns `true` with a probability of one half.
+    pub fn coin(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+
+    /// Returns `len` pseudo random bytes.
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+
+    /// Returns a pseudo random printable ASCII string of at most `max_len` characters.
+    pub fn ascii(&mut self, max_len: usize) -> String {
+        let len = self.below(max_len as u64 + 1) as usize;
+        (0..len)
+            .map(|_| (b' ' + self.below(95) as u8) as char)
+            .collect()
+    }
+
+    /// Returns a pseudo random finite float within `-range..range`.
+    fn float(&mut self, range: f32) -> f32 {
+        (self.below(2_000_001) as f32 / 1_000_000.0 - 1.0) * range
+    }
+}
+
+/// An entry written by `round_trip` and expected back from the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestEntry {
+    /// A MAVLink 2 frame.
+    Mavlink { header: MavHeader, msg: MavMessage },
+    /// A text entry.
+    Text(String),
+    /// A raw data entry.
+    Raw(Vec<u8>),
+    /// A blob, stored across as many entries as needed.
+    Blob(Vec<u8>),
+    /// RTCM correction data received on a link.
+    Rtcm { link: u8, data: Vec<u8> },
+}
+
+/// Generates valid format flags that `round_trip` supports.
+///
+/// Flags needing keys or dictionaries, `encrypted`, `dictionary` and `chunked`, are never set.
+/// `hash_chain` is only set with the `hash_chain` feature.
+pub fn random_format_flags(rng: &mut Rng) -> FormatFlags {
+    let mavlink_only = rng.below(4) == 0;
+    FormatFlags {
+        mavlink_only,
+        no_timestamp: rng.coin(),
+        sequence: !mavlink_only && rng.coin(),
+        hash_chain: cfg!(feature = "hash_chain") && !mavlink_only && rng.coin(),
+        large_entries: !mavlink_only && rng.coin(),
+        ..Default::default()
+    }
+}
+
+/// Generates a valid file header without message definition payload, encryption header or
+/// dictionary id.
+pub fn random_header(rng: &mut Rng) -> FileHeader {
+    let mut header = FileHeader::new(
+        random_format_flags(rng),
+        MavlinkMessageDefinition::default(),
+    );
+    header.uuid = Uuid::from_bytes(rng.bytes(16).try_into().unwrap());
+    header.timestamp_us = rng.next_u64();
+    header.src_application_id = rng.ascii(32);
+    header.message_definition.version_major = 1 + rng.below(2) as u32;
+    header.message_definition.version_minor = rng.next_u64() as u32;
+    header.message_definition.dialect = rng.ascii(32);
+    header
+}
+
+/// Generates a MAVLink message with random field values.
+///
+/// Floating point fields are always finite so that messages compare equal after a round trip.
+pub fn random_message(rng: &mut Rng) -> MavMessage {
+    match rng.below(4) {
+        0 => MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode: rng.next_u64() as u32,
+            mavtype: MavType::MAV_TYPE_QUADROTOR,
+            autopilot: MavAutopilot::MAV_AUTOPILOT_ARDUPILOTMEGA,
+            base_mode: MavModeFlag::from_bits_truncate(rng.next_u64() as u8),
+            system_status: MavState::MAV_STATE_ACTIVE,
+            mavlink_version: 3,
+        }),
+        1 => MavMessage::ATTITUDE(ATTITUDE_DATA {
+            time_boot_ms: rng.next_u64() as u32,
+            roll: rng.float(3.0),
+            pitch: rng.float(1.5),
+            yaw: rng.float(3.0),
+            rollspeed: rng.float(10.0),
+            pitchspeed: rng.float(10.0),
+            yawspeed: rng.float(10.0),
+        }),
+        2 => MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+            time_boot_ms: rng.next_u64() as u32,
+            lat: rng.next_u64() as i32,
+            lon: rng.next_u64() as i32,
+            alt: rng.next_u64() as i32,
+            relative_alt: rng.next_u64() as i32,
+            vx: rng.next_u64() as i16,
+            vy: rng.next_u64() as i16,
+            vz: rng.next_u64() as i16,
+            hdg: rng.next_u64() as u16,
+        }),
+        _ => {
+            let mut text = [0u8; 50];
+            let content = rng.ascii(50);
+            text[..content.len()].copy_from_slice(content.as_bytes());
+            MavMessage::STATUSTEXT(STATUSTEXT_DATA {
+                severity: MavSeverity::MAV_SEVERITY_INFO,
+                text,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+/// Generates an entry that a log with the given format flags can hold.
+///
+/// Logs with the `mavlink_only` flag only get MAVLink entries.
+pub fn random_entry(rng: &mut Rng, flags: &FormatFlags) -> TestEntry {
+    let kind = if flags.mavlink_only { 0 } else { rng.below(5) };
+    match kind {
+        0 => TestEntry::Mavlink {
+            header: MavHeader {
+                system_id: rng.next_u64() as u8,
+                component_id: rng.next_u64() as u8,
+                sequence: rng.next_u64() as u8,
+            },
+            msg: random_message(rng),
+        },
+        1 => TestEntry::Text(rng.ascii(200)),
+        2 => {
+            let len = rng.below(300) as usize;
+            TestEntry::Raw(rng.bytes(len))
+        }
+        3 => {
+            // occasionally span several fragments
+            let len = match rng.below(8) {
+                0 => rng.below(200_000),
+                _ => rng.below(1_000),
+            } as usize;
+            TestEntry::Blob(rng.bytes(len))
+        }
+        _ => {
+            let len = rng.below(300) as usize;
+            TestEntry::Rtcm {
+                link: rng.next_u64() as u8,
+                data: rng.bytes(len),
+            }
+        }
+    }
+}
+
+/// Generates `count` entries with increasing timestamps in microseconds.
+pub fn random_entries(rng: &mut Rng, flags: &FormatFlags, count: usize) -> Vec<(u64, TestEntry)> {
+    let mut timestamp_us = rng.below(1 << 50);
+    (0..count)
+        .map(|_| {
+            timestamp_us += rng.below(1_000_000);
+            (timestamp_us, random_entry(rng, flags))
+        })
+        .collect()
+}
+
+/// Asserts that a file header is unpacked to the same header it was packed from.
+///
+/// # Panics
+/// Panics if the packed header does not unpack, or packs differently once unpacked.
+pub fn check_header(header: &FileHeader) {
+    let packed = header.pack();
+    let fixed: [u8; FileHeader::MIN_SIZE] = packed[..FileHeader::MIN_SIZE].try_into().unwrap();
+    let unpacked = FileHeader::try_unpack(&fixed).expect("packed header does not unpack");
+    assert_eq!(unpacked.pack(), packed, "header changed in a round trip");
+}
+
+/// Writes entries to a .mav log, parses it back and asserts that the same entries are returned.
+///
+/// The log is written to a temporary file that is removed afterwards. Entry timestamps are
+/// only compared if the format records them.
+///
+/// # Arguments
+/// - `flags`: The format flags of the log.
+/// - `entries`: The entries to write with their timestamps in microseconds.
+///
+/// # Errors
+/// Returns an `io::Error` if the log could not be written or read, for example if an entry is
+/// not supported by the format flags.
+///
+/// # Panics
+/// Panics if the parsed entries differ from the written entries.
+pub fn round_trip(flags: FormatFlags, entries: &[(u64, TestEntry)]) -> std::io::Result<()> {
+    let expected: Vec<(Option<u64>, TestEntry)> = entries
+        .iter()
+        .map(|(timestamp_us, entry)| {
+            let timestamp_us = (!flags.no_timestamp).then_some(*timestamp_us);
+            (timestamp_us, entry.clone())
+        })
+        .collect();
+    let path = std::env::temp_dir().join(format!("mavlink_log_round_trip_{}.mav", Uuid::new_v4()));
+    let path = path.to_str().expect("temporary path is not UTF-8");
+    let result = write_and_parse(path, flags, entries);
+    std::fs::remove_file(path).ok();
+    let (parsed, sequence_errors) = result?;
+    assert_eq!(sequence_errors, 0, "sequence numbers are not continuous");
+    assert_eq!(
+        parsed.len(),
+        expected.len(),
+        "a different number of entries was parsed"
+    );
+    for (index, (written, parsed)) in expected.iter().zip(&parsed).enumerate() {
+        assert_eq!(written, parsed, "entry {index} changed in a round trip");
+    }
+    Ok(())
+}
+
+/// Writes entries to a log file and parses them back.
+///
+/// # Returns
+/// The parsed entries with their timestamps and the number of sequence errors.
+fn write_and_parse(
+    path: &str,
+    flags: FormatFlags,
+    entries: &[(u64, TestEntry)],
+) -> std::io::Result<(Vec<(Option<u64>, TestEntry)>, u64)> {
+    let mut logger = RotatingMavLogger::new(path, u64::MAX, 0, Some(flags), None)?;
+    for (timestamp_us, entry) in entries {
+        let timestamp_us = Some(*timestamp_us);
+        match entry {
+            TestEntry::Mavlink { header, msg } => {
+                let frame = MavFrame {
+                    header: *header,
+                    msg: msg.clone(),
+                    protocol_version: MavlinkVersion::V2,
+                };
+                logger.write_mavlink_at(frame, timestamp_us)?;
+            }
+            TestEntry::Text(text) => {
+                logger.write_at(EntryType::Text, timestamp_us, text.as_bytes())?
+            }
+            TestEntry::Raw(data) => logger.write_at(EntryType::Raw, timestamp_us, data)?,
+            TestEntry::Blob(data) => {
+                logger.write_blob_at(data, timestamp_us)?;
+            }
+            TestEntry::Rtcm { link, data } => logger.write_rtcm_at(*link, data, timestamp_us)?,
+        }
+    }
+    logger.flush()?;
+    drop(logger);
+
+    let mut parser = MavLogParser::<MavMessage>::try_new(path)?;
+    let mut parsed: Vec<(Option<u64>, TestEntry)> = Vec::new();
+    loop {
+        let entry = match parser.parse_next_entry() {
+            Ok(entry) => entry,
+            Err(MessageReadError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(MessageReadError::Io(e)) => return Err(e),
+            Err(MessageReadError::Parse(e)) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    e.to_string(),
+                ));
+            }
+        };
+        parsed.push((entry.timestamp, to_test_entry(entry)));
+    }
+    Ok((parsed, parser.sequence_errors()))
+}
+
+/// Converts a parsed entry to the entry it was written from.
+///
+/// # Panics
+/// Panics if the entry holds nothing `round_trip` writes.
+fn to_test_entry(entry: LogEntry<MavMessage>) -> TestEntry {
+    if let (Some(header), Some(msg)) = (entry.mav_header, entry.mav_message) {
+        return TestEntry::Mavlink { header, msg };
+    }
+    if let Some(text) = entry.text {
+        return TestEntry::Text(text);
+    }
+    if let Some(blob) = entry.blob {
+        return TestEntry::Blob(blob.data);
+    }
+    if let Some(rtcm) = entry.rtcm {
+        return TestEntry::Rtcm {
+            link: rtcm.link,
+            data: rtcm.data,
+        };
+    }
+    match entry.raw {
+        Some(data) => TestEntry::Raw(data),
+        None => panic!("parsed an entry holding no data"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that random logs of every supported format round trip.
+    #[test]
+    fn test_random_round_trips() {
+        for seed in 0..20 {
+            let mut rng = Rng::new(seed);
+            let flags = random_format_flags(&mut rng);
+            let entries = random_entries(&mut rng, &flags, 40);
+            round_trip(flags, &entries).unwrap();
+        }
+    }
+
+    /// Test that random file headers round trip.
+    #[test]
+    fn test_random_headers() {
+        let mut rng = Rng::new(7);
+        for _ in 0..50 {
+            check_header(&random_header(&mut rng));
+        }
+    }
+
+    /// Test that the generator is reproducible from its seed.
+    #[test]
+    fn test_rng_seed() {
+        let mut rng = Rng::new(42);
+        let first: Vec<u64> = (0..5).map(|_| rng.next_u64()).collect();
+        let mut rng = Rng::new(42);
+        let second: Vec<u64> = (0..5).map(|_| rng.next_u64()).collect();
+        assert_eq!(first, second);
+        assert_ne!(Rng::new(43).next_u64(), first[0]);
+    }
+}
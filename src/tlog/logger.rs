@@ -3,18 +3,47 @@
 //! The tlog format is the informal logging format used by MAVLink ground stations.
 //! You can learn more at docs/tlog_file_format.md.
 
-use std::time::SystemTime;
-
 use mavlink::{MavFrame, Message};
 use rotating_file_handler::RotatingFileHandler;
 
-use crate::mav_logger::MavLogger;
+use crate::clock::{BackwardsPolicy, Clock, ClockSource};
+use crate::frame;
+use crate::mav_logger::{MavFrameLogger, MavLogger};
 use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw};
 
 /// `RotatingTLog` is a logger that writes MAVLink messages to a file with rotation support.
 /// The log file rotates when it reaches a specified size limit.
+///
+/// Record timestamps are read from the system clock, as tlog readers expect microseconds since
+/// the Unix epoch, unless another clock is set with `set_clock`.
+///
+/// Timestamps are written little-endian unless QGroundControl compatibility is enabled with
+/// `set_qgc_compatible`, see `new_qgc`.
+///
+/// A Mission Planner compatible rlog can be written beside the tlog, see `enable_rlog`.
 pub struct RotatingTlog {
+    base_path: String,
     file_handler: RotatingFileHandler,
+    rlog_handler: Option<RotatingFileHandler>,
+    clock: Clock,
+    qgc_compatible: bool,
+}
+
+/// Returns the name QGroundControl gives a tlog started at a time, such as
+/// `2024-05-01 13-45-07.tlog`.
+///
+/// # Arguments
+///
+/// * `timestamp_us` - The start time of the log in microseconds since the Unix epoch, named in
+///     the local time zone of the system like QGroundControl does.
+///
+/// # Returns
+///
+/// The file name, or `None` if the timestamp is out of the range of calendar dates.
+#[cfg(feature = "chrono")]
+pub fn qgc_file_name(timestamp_us: u64) -> Option<String> {
+    let start = crate::datetime::to_local(timestamp_us)?;
+    Some(start.format("%Y-%m-%d %H-%M-%S.tlog").to_string())
 }
 
 impl RotatingTlog {
@@ -33,7 +62,162 @@ impl RotatingTlog {
     /// A `Result` which is `Ok` if the `RotatingTLog` was created successfully, or an `Err` if there was an error.
     pub fn new(base_path: &str, max_bytes: u64, backup_count: usize) -> std::io::Result<Self> {
         let file_handler = RotatingFileHandler::new(base_path, max_bytes, backup_count, None)?;
-        Ok(Self { file_handler })
+        Ok(Self {
+            base_path: base_path.to_string(),
+            file_handler,
+            rlog_handler: None,
+            clock: Clock::new(ClockSource::Wall, BackwardsPolicy::Hold),
+            qgc_compatible: false,
+        })
+    }
+
+    /// Creates a new `RotatingTLog` writing a tlog QGroundControl can replay, named like the
+    /// tlogs QGroundControl saves.
+    ///
+    /// The log is created in a directory with the name returned by `qgc_file_name` for the
+    /// current time, and written with QGroundControl compatibility enabled, see
+    /// `set_qgc_compatible`. QGroundControl only lists files ending with .tlog, so the backup
+    /// files of a rotated log have to be renamed to be replayed.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to create the log in, such as the Telemetry directory of
+    ///     QGroundControl. It is expected to exist.
+    /// * `max_bytes` - The maximum size in bytes before the log file is rotated.
+    /// * `backup_count` - The number of backup files to keep. With none, the log file is
+    ///     started over once it reaches `max_bytes` and the earlier records are lost.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `RotatingTLog` and the path of its log file, or an `Err` if the
+    /// log could not be created.
+    #[cfg(feature = "chrono")]
+    pub fn new_qgc(
+        directory: &std::path::Path,
+        max_bytes: u64,
+        backup_count: usize,
+    ) -> std::io::Result<(Self, std::path::PathBuf)> {
+        let mut clock = Clock::new(ClockSource::Wall, BackwardsPolicy::Hold);
+        let name = qgc_file_name(clock.now_us()?).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "The system time is out of the range of calendar dates",
+            )
+        })?;
+        let path = directory.join(name);
+        let path_str = path.to_str().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The log directory is not valid UTF-8",
+            )
+        })?;
+        let mut logger = Self::new(path_str, max_bytes, backup_count)?;
+        logger.set_qgc_compatible(true);
+        Ok((logger, path))
+    }
+
+    /// Enables or disables strict QGroundControl compatibility.
+    ///
+    /// QGroundControl writes and expects record timestamps as big-endian microseconds since the
+    /// Unix epoch, directly followed by the frame with no other framing. When enabled, timestamps
+    /// are written big-endian and only complete MAVLink frames are accepted, which
+    /// `write_mavlink_raw` always requires. Readers of this crate accept either byte order, see
+    /// `TimestampHeuristics`.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to write timestamps big-endian.
+    pub fn set_qgc_compatible(&mut self, enabled: bool) {
+        self.qgc_compatible = enabled;
+    }
+
+    /// Enables writing a Mission Planner compatible rlog beside the tlog.
+    ///
+    /// Mission Planner records `<name>.rlog` next to `<name>.tlog`, holding the bytes of the link
+    /// without timestamps. Once enabled, every frame written to the tlog is also appended to the
+    /// rlog, which has the path of the tlog with its extension replaced by .rlog. The rlog can be
+    /// read with `RlogParser`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bytes` - The maximum size in bytes before the rlog file rotates.
+    /// * `backup_count` - The number of rlog backup files to keep.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if the rlog was created successfully, or an `Err` if there was an
+    /// error.
+    pub fn enable_rlog(&mut self, max_bytes: u64, backup_count: usize) -> std::io::Result<()> {
+        let rlog_path = std::path::Path::new(&self.base_path).with_extension("rlog");
+        let rlog_path = rlog_path.to_str().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The log path is not valid UTF-8",
+            )
+        })?;
+        self.rlog_handler = Some(RotatingFileHandler::new(
+            rlog_path,
+            max_bytes,
+            backup_count,
+            None,
+        )?);
+        Ok(())
+    }
+
+    /// Sets the clock record timestamps are read from.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock to read record timestamps from. It should count microseconds since
+    ///     the Unix epoch.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Writes a serialized MAVLink frame to the log exactly as captured.
+    ///
+    /// Unlike `write_mavlink`, the frame is not re-serialized, so its incompatibility and
+    /// compatibility flags and its signature are preserved.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The bytes of exactly one MAVLink 1 or MAVLink 2 frame.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if the frame was logged successfully, or an `Err` of kind
+    /// `InvalidInput` if the bytes are not a single complete frame.
+    pub fn write_mavlink_raw(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        if !frame::is_complete(frame) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The data is not a single complete MAVLink frame",
+            ));
+        }
+        self.emit_record(frame)
+    }
+
+    /// Writes a record holding the current time and a serialized frame.
+    fn emit_record(&mut self, frame_bytes: &[u8]) -> std::io::Result<()> {
+        let timestamp_us: u64 = self.clock.now_us()?;
+        let mut record_bytes: Vec<u8> = if self.qgc_compatible {
+            timestamp_us.to_be_bytes().to_vec()
+        } else {
+            timestamp_us.to_le_bytes().to_vec()
+        };
+        record_bytes.extend_from_slice(frame_bytes);
+        self.file_handler.emit(&record_bytes)?;
+        if let Some(rlog_handler) = &mut self.rlog_handler {
+            rlog_handler.emit(frame_bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl MavFrameLogger for RotatingTlog {
+    /// Writes a serialized MAVLink frame to the log file, see `write_mavlink_raw`.
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.write_mavlink_raw(frame)
     }
 }
 
@@ -48,26 +232,20 @@ impl MavLogger for RotatingTlog {
     ///
     /// A `Result` which is `Ok` if the message was logged successfully, or an `Err` if there was an error.
     fn write_mavlink<M: Message>(&mut self, frame: MavFrame<M>) -> std::io::Result<()> {
-        let timestamp_us: u64 = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_micros() as u64;
-        let mut record_bytes: Vec<u8> = timestamp_us.to_le_bytes().to_vec();
-
+        // tlogs have no entry to record the correction model of a GPS disciplined clock in
+        self.clock.observe(&frame.msg);
         match frame.protocol_version {
             mavlink::MavlinkVersion::V1 => {
                 let mut msg: MAVLinkV1MessageRaw = MAVLinkV1MessageRaw::new();
                 msg.serialize_message(frame.header, &frame.msg);
-                record_bytes.extend_from_slice(msg.raw_bytes());
+                self.emit_record(msg.raw_bytes())
             }
             mavlink::MavlinkVersion::V2 => {
                 let mut msg: MAVLinkV2MessageRaw = MAVLinkV2MessageRaw::new();
                 msg.serialize_message(frame.header, &frame.msg);
-                record_bytes.extend_from_slice(msg.raw_bytes());
+                self.emit_record(msg.raw_bytes())
             }
         }
-        self.file_handler.emit(&record_bytes)?;
-        Ok(())
     }
 }
 
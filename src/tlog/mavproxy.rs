@@ -0,0 +1,141 @@
+//! This module provides an opener for TLOG files recorded by MAVProxy.
+//!
+//! Alongside `<name>.tlog`, MAVProxy records the unfiltered link bytes to `<name>.tlog.raw` and
+//! the vehicle parameters to `<name>.parm`, or to `mav.parm` in the directory of the TLOG. `MavProxyTlog` parses the TLOG as usual while making
+//! the sidecar content available so nothing is lost on ingestion.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use mavlink::Message;
+use mavlink::error::MessageReadError;
+
+use super::parser::TlogParser;
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// A TLOG parser that also exposes MAVProxy sidecar files.
+pub struct MavProxyTlog<M: Message> {
+    parser: TlogParser<M>,
+    raw_path: Option<PathBuf>,
+    param_path: Option<PathBuf>,
+    params: BTreeMap<String, f64>,
+}
+
+impl<M: Message> MavProxyTlog<M> {
+    /// Opens a TLOG file and looks for its MAVProxy sidecar files.
+    ///
+    /// # Arguments
+    /// - `file_path`: The path to the TLOG file to be parsed.
+    ///
+    /// # Returns
+    /// A `Result` containing the `MavProxyTlog` or an `io::Error` if the TLOG file could not be
+    /// opened or a parameter sidecar exists but could not be read.
+    pub fn new(file_path: &str) -> std::io::Result<Self> {
+        let tlog_path = Path::new(file_path);
+        let raw_path = Self::sibling(tlog_path, ".raw").filter(|path| path.is_file());
+        let param_path = [
+            Self::sibling(tlog_path, ".parm"),
+            Some(tlog_path.with_extension("parm")),
+            Some(tlog_path.with_file_name("mav.parm")),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|path| path.is_file());
+
+        let params = match &param_path {
+            Some(path) => parse_params(&std::fs::read_to_string(path)?),
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            parser: TlogParser::try_new(file_path)?,
+            raw_path,
+            param_path,
+            params,
+        })
+    }
+
+    /// Returns the path of the raw link capture, if one was found.
+    pub fn raw_path(&self) -> Option<&Path> {
+        self.raw_path.as_deref()
+    }
+
+    /// Opens the raw link capture for reading, if one was found.
+    ///
+    /// The raw capture contains every byte received on the link including data that is not
+    /// MAVLink, without timestamps.
+    pub fn open_raw(&self) -> std::io::Result<Option<File>> {
+        match &self.raw_path {
+            Some(path) => Ok(Some(File::open(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the path of the parameter file, if one was found.
+    pub fn param_path(&self) -> Option<&Path> {
+        self.param_path.as_deref()
+    }
+
+    /// Returns the parameters read from the parameter file keyed by name.
+    ///
+    /// The map is empty if no parameter file was found.
+    pub fn params(&self) -> &BTreeMap<String, f64> {
+        &self.params
+    }
+
+    /// Returns the underlying TLOG parser.
+    pub fn parser(&self) -> &TlogParser<M> {
+        &self.parser
+    }
+
+    /// Builds the path of a sidecar by appending a suffix to the TLOG path.
+    fn sibling(tlog_path: &Path, suffix: &str) -> Option<PathBuf> {
+        let mut name = tlog_path.file_name()?.to_os_string();
+        name.push(suffix);
+        Some(tlog_path.with_file_name(name))
+    }
+}
+
+impl<M: Message> MavParser for MavProxyTlog<M> {
+    type M = M;
+
+    /// Reads the next MAVLink message from the TLOG file.
+    ///
+    /// Delegates to the underlying `TlogParser`.
+    fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+        self.parser.parse_next_entry()
+    }
+}
+
+/// Parses the content of a MAVProxy parameter file.
+///
+/// Each line holds a parameter name followed by its value separated by whitespace. Empty lines,
+/// comments starting with `#` and malformed lines are ignored.
+fn parse_params(content: &str) -> BTreeMap<String, f64> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let name = parts.next()?;
+            let value = parts.next()?.parse::<f64>().ok()?;
+            Some((String::from(name), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests parsing of a MAVProxy parameter file including comments and malformed lines.
+    #[test]
+    fn test_parse_params() {
+        let params =
+            parse_params("# comment\nSYSID_THISMAV 1\n\nWPNAV_SPEED  500.5\nBROKEN\nBAD x\n");
+        assert_eq!(params.len(), 2);
+        assert_eq!(params["SYSID_THISMAV"], 1.0);
+        assert_eq!(params["WPNAV_SPEED"], 500.5);
+    }
+}
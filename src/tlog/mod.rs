@@ -1,5 +1,14 @@
 #[cfg(feature = "parser")]
 pub mod parser;
 
+#[cfg(feature = "parser")]
+pub mod timestamp;
+
+#[cfg(feature = "parser")]
+pub mod mavproxy;
+
 #[cfg(feature = "logger")]
 pub mod logger;
+
+#[cfg(feature = "parser")]
+pub mod rlog;
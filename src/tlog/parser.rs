@@ -3,23 +3,53 @@
 /// implements the `MavParser` trait to read and process MAVLink messages
 /// from a TLOG file.
 /// See /docs/tlog_file_format.md for more information on the TLOG file format.
+use std::fs::File;
+use std::marker::PhantomData;
+
+use mavlink::Message;
 use mavlink::error::MessageReadError;
-use mavlink::{MavConnection, Message};
 
+use crate::byte_reader::ByteReader;
+use crate::frame::{self, FrameError};
 use crate::mav_parser::LogEntry;
 use crate::mav_parser::MavParser;
 
+use super::timestamp::{TimestampHeuristics, TimestampStatus};
+
+/// Size of the timestamp preceding each frame in a TLOG file.
+const TIMESTAMP_SIZE: usize = 8;
+
 /// A parser for telemetry log (TLOG) files that uses the MAVLink protocol.
 ///
-/// The `TlogParser` struct wraps a MAVLink connection to read messages
-/// from a TLOG file. It implements the `MavParser` trait, allowing it to
-/// process MAVLink messages and return them as `LogEntry` objects.
+/// The `TlogParser` reads timestamped MAVLink frames from a TLOG file. It
+/// implements the `MavParser` trait, allowing it to process MAVLink messages
+/// and return them as `LogEntry` objects.
+///
+/// MAVLink 1 and MAVLink 2 frames may be interleaved in the same file. The
+/// version is detected per frame. Bytes that do not form a valid record, such
+/// as serial noise, are skipped until the next valid record and counted so the
+/// amount of discarded data can be reported.
+///
+/// Record timestamps are checked with configurable `TimestampHeuristics` so
+/// that timestamps written in milliseconds or little-endian are normalized to
+/// microseconds and implausible timestamps are flagged.
 ///
 /// # Type Parameters
 /// - `M`: The type of MAVLink message being parsed.
 pub struct TlogParser<M: Message> {
-    /// A boxed MAVLink connection object for reading messages from the TLOG file.
-    file_conn: Box<dyn MavConnection<M> + Sync + Send>,
+    /// Reader for the TLOG file.
+    reader: ByteReader<File>,
+    /// Number of bytes discarded while searching for valid records.
+    skipped_bytes: u64,
+    /// Heuristics used to check and correct record timestamps.
+    heuristics: TimestampHeuristics,
+    /// The most recent timestamp that was not flagged as suspect.
+    previous_timestamp: Option<u64>,
+    /// Outcome of the timestamp check for the most recently parsed entry.
+    last_timestamp_status: Option<TimestampStatus>,
+    /// Number of entries with a timestamp flagged as suspect.
+    suspect_timestamps: u64,
+    _phantom: PhantomData<M>,
 }
 
 impl<M: Message> TlogParser<M> {
@@ -30,13 +60,96 @@ impl<M: Message> TlogParser<M> {
     ///
     /// # Panics
     /// This function will panic if the provided file path is invalid or if
-    /// the connection to the TLOG file cannot be established.
+    /// the TLOG file cannot be opened. Use `try_new` to handle this case as an error.
     ///
     pub fn new(file_path: &str) -> Self {
-        let connection_string = format!("file:{}", file_path);
-        let vehicle =
-            mavlink::connect::<M>(&connection_string).expect("An invalid file path was provided");
-        Self { file_conn: vehicle }
+        Self::with_timestamp_heuristics(file_path, TimestampHeuristics::default())
+    }
+
+    /// Creates a new `TlogParser` instance for the specified TLOG file path without panicking.
+    ///
+    /// # Arguments
+    /// - `file_path`: The path to the TLOG file to be parsed.
+    ///
+    /// # Returns
+    /// A `Result` containing the parser or an `io::Error` if the file could not be opened.
+    pub fn try_new(file_path: &str) -> std::io::Result<Self> {
+        Self::try_with_timestamp_heuristics(file_path, TimestampHeuristics::default())
+    }
+
+    /// Creates a new `TlogParser` instance using custom timestamp heuristics.
+    ///
+    /// # Arguments
+    /// - `file_path`: The path to the TLOG file to be parsed.
+    /// - `heuristics`: The heuristics used to check and correct record timestamps.
+    ///
+    /// # Panics
+    /// This function will panic if the provided file path is invalid or if
+    /// the TLOG file cannot be opened. Use `try_with_timestamp_heuristics` to handle this case
+    /// as an error.
+    ///
+    pub fn with_timestamp_heuristics(file_path: &str, heuristics: TimestampHeuristics) -> Self {
+        Self::try_with_timestamp_heuristics(file_path, heuristics)
+            .expect("An invalid file path was provided")
+    }
+
+    /// Creates a new `TlogParser` instance using custom timestamp heuristics without panicking.
+    ///
+    /// # Arguments
+    /// - `file_path`: The path to the TLOG file to be parsed.
+    /// - `heuristics`: The heuristics used to check and correct record timestamps.
+    ///
+    /// # Returns
+    /// A `Result` containing the parser or an `io::Error` if the file could not be opened.
+    pub fn try_with_timestamp_heuristics(
+        file_path: &str,
+        heuristics: TimestampHeuristics,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
+            reader: ByteReader::new(File::open(file_path)?),
+            skipped_bytes: 0,
+            heuristics,
+            previous_timestamp: None,
+            last_timestamp_status: None,
+            suspect_timestamps: 0,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns how the timestamp of the most recently parsed entry was
+    /// interpreted, or `None` if no entry has been parsed yet.
+    pub fn last_timestamp_status(&self) -> Option<TimestampStatus> {
+        self.last_timestamp_status
+    }
+
+    /// Returns the number of entries parsed so far with a timestamp flagged
+    /// as suspect.
+    pub fn suspect_timestamps(&self) -> u64 {
+        self.suspect_timestamps
+    }
+
+    /// Returns the number of bytes skipped so far because they did not form a
+    /// valid TLOG record.
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped_bytes
+    }
+
+    /// Discards a single byte while searching for the next valid record.
+    fn skip_byte(&mut self) {
+        self.reader.consume(1);
+        self.skipped_bytes += 1;
+    }
+
+    /// Applies the timestamp heuristics to a raw record timestamp.
+    fn check_timestamp(&mut self, raw: u64) -> u64 {
+        let (timestamp, status) = self.heuristics.normalize(raw, self.previous_timestamp);
+        if status == TimestampStatus::Suspect {
+            self.suspect_timestamps += 1;
+        } else {
+            self.previous_timestamp = Some(timestamp);
+        }
+        self.last_timestamp_status = Some(status);
+        timestamp
     }
 }
 
@@ -49,19 +162,59 @@ impl<M: Message> MavParser for TlogParser<M> {
     /// - `Ok(LogEntry)`: If a message is successfully read from the TLOG file.
     /// - `Err(MessageReadError)`: If an error occurs while reading the message.
     ///
-    /// The `LogEntry` contains the MAVLink message, its header, and optional
-    /// metadata such as a timestamp or raw data.
+    /// The `LogEntry` contains the MAVLink message, its header, the record
+    /// timestamp normalized to microseconds and the location of the record in
+    /// the file.
+    ///
+    /// A record with an intact frame that does not parse, for example a
+    /// message missing from the dialect, is consumed and reported as a
+    /// `MessageReadError::Parse` error so parsing can continue with the next
+    /// call.
     ///
     fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
-        match self.file_conn.recv() {
-            Ok((header, msg)) => Ok(LogEntry {
-                timestamp: None,
-                mav_header: Some(header),
-                mav_message: Some(msg),
-                text: None,
-                raw: None,
-            }),
-            Err(err) => return Err(err),
+        loop {
+            let record_start = self.reader.peek(TIMESTAMP_SIZE + frame::LENGTH_PEEK_SIZE)?;
+            let frame_len = match frame::frame_len(&record_start[TIMESTAMP_SIZE..]) {
+                Some(len) => len,
+                None => {
+                    self.skip_byte();
+                    continue;
+                }
+            };
+
+            let offset = self.reader.position();
+            // A magic byte in noise near the end of the file may announce a frame longer than
+            // the rest of the file, so only the search for the next record ends at the end
+            let record = match self.reader.peek(TIMESTAMP_SIZE + frame_len) {
+                Ok(record) => record,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    self.skip_byte();
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let timestamp = u64::from_be_bytes(record[..TIMESTAMP_SIZE].try_into().unwrap());
+            match frame::decode::<M>(&record[TIMESTAMP_SIZE..]) {
+                Ok(decoded) => {
+                    self.reader.consume(TIMESTAMP_SIZE + frame_len);
+                    return Ok(LogEntry {
+                        timestamp: Some(self.check_timestamp(timestamp)),
+                        mav_header: Some(decoded.header),
+                        mav_message: Some(decoded.msg),
+                        protocol_version: Some(decoded.version),
+                        incompat_flags: decoded.flags.map(|(incompat, _)| incompat),
+                        compat_flags: decoded.flags.map(|(_, compat)| compat),
+                        offset: Some(offset),
+                        entry_len: Some((TIMESTAMP_SIZE + frame_len) as u64),
+                        ..Default::default()
+                    });
+                }
+                Err(FrameError::Parse(err)) => {
+                    self.reader.consume(TIMESTAMP_SIZE + frame_len);
+                    return Err(MessageReadError::Parse(err));
+                }
+                Err(FrameError::Invalid) => self.skip_byte(),
+            }
         }
     }
 }
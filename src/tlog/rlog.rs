@@ -0,0 +1,120 @@
+//! This module provides a parser for the `.rlog` files Mission Planner records beside its tlogs.
+//!
+//! Mission Planner writes `<name>.rlog` next to `<name>.tlog`. Despite being often taken for an
+//! index of the tlog, the rlog is the raw capture of the link: the bytes received, MAVLink
+//! frames and anything else, without timestamps. It lets frames the tlog dropped, such as
+//! frames with a bad checksum or from an unknown dialect, be recovered. `RlogParser` reads the
+//! MAVLink frames of an rlog, skipping the other bytes, and `rlog_path` finds the rlog of a tlog.
+use std::fs::File;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use mavlink::Message;
+use mavlink::error::MessageReadError;
+
+use crate::byte_reader::ByteReader;
+use crate::frame::{self, FrameError};
+use crate::mav_parser::{LogEntry, MavParser};
+
+/// Returns the path of the rlog recorded beside a tlog, if it exists.
+///
+/// Both `<name>.rlog`, as Mission Planner and `RotatingTlog::enable_rlog` name it, and
+/// `<name>.tlog.rlog` are looked for.
+///
+/// # Arguments
+/// - `tlog_path`: The path of the tlog.
+pub fn rlog_path(tlog_path: &Path) -> Option<PathBuf> {
+    let mut suffixed = tlog_path.file_name()?.to_os_string();
+    suffixed.push(".rlog");
+    [
+        tlog_path.with_extension("rlog"),
+        tlog_path.with_file_name(suffixed),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// A parser for the MAVLink frames of a raw link capture.
+///
+/// Entries hold the MAVLink message, its header and its location in the file, but no
+/// timestamp. Bytes that do not form a valid frame are skipped and counted.
+///
+/// # Type Parameters
+/// - `M`: The type of MAVLink message being parsed.
+pub struct RlogParser<M: Message> {
+    /// Reader for the rlog file.
+    reader: ByteReader<File>,
+    /// Number of bytes discarded while searching for valid frames.
+    skipped_bytes: u64,
+    _phantom: PhantomData<M>,
+}
+
+impl<M: Message> RlogParser<M> {
+    /// Creates a new `RlogParser` for the specified rlog file path.
+    ///
+    /// # Arguments
+    /// - `file_path`: The path to the rlog file to be parsed.
+    ///
+    /// # Returns
+    /// A `Result` containing the parser or an `io::Error` if the file could not be opened.
+    pub fn new(file_path: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            reader: ByteReader::new(File::open(file_path)?),
+            skipped_bytes: 0,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the number of bytes skipped so far because they did not form a valid frame.
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped_bytes
+    }
+
+    /// Discards a single byte while searching for the next valid frame.
+    fn skip_byte(&mut self) {
+        self.reader.consume(1);
+        self.skipped_bytes += 1;
+    }
+}
+
+impl<M: Message> MavParser for RlogParser<M> {
+    type M = M;
+
+    /// Reads the next MAVLink frame from the rlog file and returns it as a `LogEntry`.
+    ///
+    /// A frame that is intact but does not parse, for example a message missing from the
+    /// dialect, is consumed and reported as a `MessageReadError::Parse` error so parsing can
+    /// continue with the next call.
+    ///
+    fn parse_next_entry(&mut self) -> Result<LogEntry<Self::M>, MessageReadError> {
+        loop {
+            let start = self.reader.peek(frame::LENGTH_PEEK_SIZE)?;
+            let Some(frame_len) = frame::frame_len(start) else {
+                self.skip_byte();
+                continue;
+            };
+            let offset = self.reader.position();
+            let bytes = self.reader.peek(frame_len)?;
+            match frame::decode::<M>(bytes) {
+                Ok(decoded) => {
+                    self.reader.consume(frame_len);
+                    return Ok(LogEntry {
+                        mav_header: Some(decoded.header),
+                        mav_message: Some(decoded.msg),
+                        protocol_version: Some(decoded.version),
+                        incompat_flags: decoded.flags.map(|(incompat, _)| incompat),
+                        compat_flags: decoded.flags.map(|(_, compat)| compat),
+                        offset: Some(offset),
+                        entry_len: Some(frame_len as u64),
+                        ..Default::default()
+                    });
+                }
+                Err(FrameError::Parse(err)) => {
+                    self.reader.consume(frame_len);
+                    return Err(MessageReadError::Parse(err));
+                }
+                Err(FrameError::Invalid) => self.skip_byte(),
+            }
+        }
+    }
+}
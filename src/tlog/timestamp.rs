@@ -0,0 +1,167 @@
+//! Heuristics for detecting and correcting implausible TLOG record timestamps.
+//!
+//! TLOG timestamps are expected to be big-endian unix timestamps in microseconds. Some producers
+//! write milliseconds or little-endian values instead. Since any of these interpretations of a
+//! real timestamp lands in a narrow plausible window, the intended value can be recovered by
+//! trying each interpretation in turn.
+
+/// Unix timestamp of 2000-01-01T00:00:00Z in microseconds.
+const YEAR_2000_US: u64 = 946_684_800_000_000;
+/// Unix timestamp of 2100-01-01T00:00:00Z in microseconds.
+const YEAR_2100_US: u64 = 4_102_444_800_000_000;
+
+/// Outcome of checking a single record timestamp.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum TimestampStatus {
+    /// The timestamp was plausible as written.
+    Valid,
+    /// The timestamp was written little-endian and has been byte swapped.
+    ByteSwapped,
+    /// The timestamp was written in milliseconds and has been converted to microseconds.
+    Milliseconds,
+    /// The timestamp was written little-endian in milliseconds and has been corrected.
+    ByteSwappedMilliseconds,
+    /// No interpretation of the timestamp was plausible. The value is reported as written.
+    Suspect,
+}
+
+/// Configuration of the timestamp heuristics applied while parsing a TLOG.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct TimestampHeuristics {
+    /// If false, timestamps are reported as written and never flagged.
+    pub enabled: bool,
+    /// Smallest timestamp in microseconds considered plausible.
+    pub min_valid_us: u64,
+    /// Largest timestamp in microseconds considered plausible.
+    pub max_valid_us: u64,
+    /// If set, little-endian timestamps are detected and corrected.
+    pub detect_byte_swap: bool,
+    /// If set, millisecond timestamps are detected and converted to microseconds.
+    pub detect_milliseconds: bool,
+    /// If set, a timestamp differing from the previous plausible timestamp by more than this
+    /// many microseconds is flagged as suspect.
+    pub max_jump_us: Option<u64>,
+}
+
+impl TimestampHeuristics {
+    /// Heuristics that leave every timestamp untouched.
+    pub fn disabled() -> Self {
+        TimestampHeuristics {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    /// Checks a raw record timestamp and corrects it if possible.
+    ///
+    /// # Arguments
+    /// - `raw`: The timestamp as read big-endian from the record.
+    /// - `previous`: The previous plausible timestamp in the log, if any.
+    ///
+    /// # Returns
+    /// The timestamp in microseconds along with how it was interpreted.
+    pub fn normalize(&self, raw: u64, previous: Option<u64>) -> (u64, TimestampStatus) {
+        if !self.enabled {
+            return (raw, TimestampStatus::Valid);
+        }
+        let swapped = raw.swap_bytes();
+        let mut candidates: Vec<(u64, TimestampStatus)> = vec![(raw, TimestampStatus::Valid)];
+        if self.detect_byte_swap {
+            candidates.push((swapped, TimestampStatus::ByteSwapped));
+        }
+        if self.detect_milliseconds {
+            candidates.push((raw.saturating_mul(1000), TimestampStatus::Milliseconds));
+            if self.detect_byte_swap {
+                candidates.push((
+                    swapped.saturating_mul(1000),
+                    TimestampStatus::ByteSwappedMilliseconds,
+                ));
+            }
+        }
+
+        let plausible = candidates
+            .into_iter()
+            .find(|(value, _)| (self.min_valid_us..=self.max_valid_us).contains(value));
+        match (plausible, previous, self.max_jump_us) {
+            (Some((value, _)), Some(previous), Some(max_jump))
+                if value.abs_diff(previous) > max_jump =>
+            {
+                (value, TimestampStatus::Suspect)
+            }
+            (Some(candidate), _, _) => candidate,
+            (None, _, _) => (raw, TimestampStatus::Suspect),
+        }
+    }
+}
+
+impl Default for TimestampHeuristics {
+    /// Provides default values for `TimestampHeuristics`.
+    ///
+    /// By default the heuristics are enabled, timestamps between the years 2000 and 2100 are
+    /// considered plausible, byte swap and millisecond detection are enabled and jumps between
+    /// timestamps are not checked.
+    fn default() -> Self {
+        TimestampHeuristics {
+            enabled: true,
+            min_valid_us: YEAR_2000_US,
+            max_valid_us: YEAR_2100_US,
+            detect_byte_swap: true,
+            detect_milliseconds: true,
+            max_jump_us: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESTAMP_US: u64 = 1_632_843_969_792_995;
+
+    /// Tests that each supported encoding of a timestamp is detected and normalized.
+    #[test]
+    fn test_normalize_encodings() {
+        let heuristics = TimestampHeuristics::default();
+        assert_eq!(
+            heuristics.normalize(TIMESTAMP_US, None),
+            (TIMESTAMP_US, TimestampStatus::Valid)
+        );
+        assert_eq!(
+            heuristics.normalize(TIMESTAMP_US.swap_bytes(), None),
+            (TIMESTAMP_US, TimestampStatus::ByteSwapped)
+        );
+        let timestamp_ms = TIMESTAMP_US / 1000;
+        assert_eq!(
+            heuristics.normalize(timestamp_ms, None),
+            (timestamp_ms * 1000, TimestampStatus::Milliseconds)
+        );
+        assert_eq!(
+            heuristics.normalize(timestamp_ms.swap_bytes(), None),
+            (
+                timestamp_ms * 1000,
+                TimestampStatus::ByteSwappedMilliseconds
+            )
+        );
+        assert_eq!(heuristics.normalize(5, None), (5, TimestampStatus::Suspect));
+    }
+
+    /// Tests that large jumps are flagged and that disabled heuristics change nothing.
+    #[test]
+    fn test_normalize_jump_and_disabled() {
+        let heuristics = TimestampHeuristics {
+            max_jump_us: Some(1_000_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            heuristics.normalize(TIMESTAMP_US, Some(TIMESTAMP_US - 10)),
+            (TIMESTAMP_US, TimestampStatus::Valid)
+        );
+        assert_eq!(
+            heuristics.normalize(TIMESTAMP_US, Some(TIMESTAMP_US - 2_000_000)),
+            (TIMESTAMP_US, TimestampStatus::Suspect)
+        );
+
+        let heuristics = TimestampHeuristics::disabled();
+        assert_eq!(heuristics.normalize(5, None), (5, TimestampStatus::Valid));
+    }
+}
@@ -0,0 +1,192 @@
+//! Environmental context recorded alongside a flight.
+//!
+//! Spray drift investigations need the wind, temperature and humidity at the time of application
+//! along with the track. Loggers record them as weather entries with
+//! `RotatingMavLogger::write_weather`, either measured, such as the wind estimate of a WIND_COV
+//! message, or observed and entered by the operator. Weather entries are listed in flight
+//! reports.
+use std::fmt;
+
+use mavlink::{MavlinkVersion, Message};
+
+/// WIND_COV message id.
+const WIND_COV_ID: u32 = 231;
+
+/// Length of the numeric fields of an encoded observation, preceding the note.
+const VALUES_LEN: usize = 16;
+
+/// Weather at the time of an entry. Values that were not measured or observed are `None`.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeatherObservation {
+    /// Wind speed in meters per second.
+    pub wind_speed_m_s: Option<f32>,
+    /// Direction the wind blows from in degrees clockwise from true north.
+    pub wind_direction_deg: Option<f32>,
+    /// Air temperature in degrees Celsius.
+    pub temperature_c: Option<f32>,
+    /// Relative humidity in percent.
+    pub humidity_pct: Option<f32>,
+    /// Weather observed by the operator, such as "gusts, light rain", empty if there is none.
+    pub note: String,
+}
+
+impl WeatherObservation {
+    /// Creates an observation of the wind from its velocity.
+    ///
+    /// # Arguments
+    /// - `wind_north_m_s`: The velocity of the air towards the north in meters per second.
+    /// - `wind_east_m_s`: The velocity of the air towards the east in meters per second.
+    pub fn from_wind(wind_north_m_s: f32, wind_east_m_s: f32) -> Self {
+        // the wind blows from the opposite of the direction the air moves towards
+        let direction = wind_east_m_s.atan2(wind_north_m_s).to_degrees() + 180.0;
+        WeatherObservation {
+            wind_speed_m_s: Some(wind_north_m_s.hypot(wind_east_m_s)),
+            wind_direction_deg: Some(direction.rem_euclid(360.0)),
+            ..Default::default()
+        }
+    }
+
+    /// Creates an observation of the wind estimated by an autopilot.
+    ///
+    /// # Arguments
+    /// - `msg`: A WIND_COV message.
+    ///
+    /// # Returns
+    /// The wind speed and direction, or `None` if the message is not a WIND_COV message or the
+    /// autopilot has no estimate.
+    pub fn from_wind_cov<M: Message>(msg: &M) -> Option<Self> {
+        if msg.message_id() != WIND_COV_ID {
+            return None;
+        }
+        let mut payload = [0u8; 255];
+        msg.ser(MavlinkVersion::V2, &mut payload);
+        let read =
+            |offset: usize| f32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap());
+        let (north, east) = (read(8), read(12));
+        (north.is_finite() && east.is_finite()).then(|| Self::from_wind(north, east))
+    }
+
+    /// Encodes the observation as the payload of a weather entry.
+    ///
+    /// The wind speed, wind direction, temperature and humidity are little endian floats, NaN
+    /// if unknown, followed by the note as UTF-8.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::with_capacity(VALUES_LEN + self.note.len());
+        for value in [
+            self.wind_speed_m_s,
+            self.wind_direction_deg,
+            self.temperature_c,
+            self.humidity_pct,
+        ] {
+            payload.extend_from_slice(&value.unwrap_or(f32::NAN).to_le_bytes());
+        }
+        payload.extend_from_slice(self.note.as_bytes());
+        payload
+    }
+
+    /// Decodes the payload of a weather entry, see `to_payload`.
+    ///
+    /// # Returns
+    /// The observation, or `None` if the payload is truncated or the note is not valid UTF-8.
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        if payload.len() < VALUES_LEN {
+            return None;
+        }
+        let value = |index: usize| {
+            let value = f32::from_le_bytes(payload[4 * index..4 * index + 4].try_into().unwrap());
+            (!value.is_nan()).then_some(value)
+        };
+        Some(WeatherObservation {
+            wind_speed_m_s: value(0),
+            wind_direction_deg: value(1),
+            temperature_c: value(2),
+            humidity_pct: value(3),
+            note: String::from_utf8(payload[VALUES_LEN..].to_vec()).ok()?,
+        })
+    }
+}
+
+/// Formats the known values with their units followed by the quoted note, such as
+/// `wind=3.2m/s from 270deg temperature=18.5C "gusts"`.
+impl fmt::Display for WeatherObservation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        if let Some(speed) = self.wind_speed_m_s {
+            parts.push(match self.wind_direction_deg {
+                Some(direction) => format!("wind={speed:.1}m/s from {direction:.0}deg"),
+                None => format!("wind={speed:.1}m/s"),
+            });
+        }
+        if let Some(temperature) = self.temperature_c {
+            parts.push(format!("temperature={temperature:.1}C"));
+        }
+        if let Some(humidity) = self.humidity_pct {
+            parts.push(format!("humidity={humidity:.0}%"));
+        }
+        if !self.note.is_empty() {
+            parts.push(format!("{:?}", self.note));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::common::{MavMessage, WIND_COV_DATA};
+
+    use super::*;
+
+    /// Test that the wind direction is where the wind blows from.
+    #[test]
+    fn test_from_wind_cov() {
+        // air moving east is a west wind
+        let msg = MavMessage::WIND_COV(WIND_COV_DATA {
+            wind_x: 0.0,
+            wind_y: 4.0,
+            ..Default::default()
+        });
+        let weather = WeatherObservation::from_wind_cov(&msg).unwrap();
+        assert_eq!(weather.wind_speed_m_s, Some(4.0));
+        assert!((weather.wind_direction_deg.unwrap() - 270.0).abs() < 1e-3);
+        let north_wind = WeatherObservation::from_wind(-3.0, 0.0);
+        assert!(north_wind.wind_direction_deg.unwrap().abs() < 1e-3);
+
+        let no_estimate = MavMessage::WIND_COV(WIND_COV_DATA {
+            wind_x: f32::NAN,
+            wind_y: f32::NAN,
+            ..Default::default()
+        });
+        assert_eq!(WeatherObservation::from_wind_cov(&no_estimate), None);
+        let heartbeat = MavMessage::HEARTBEAT(Default::default());
+        assert_eq!(WeatherObservation::from_wind_cov(&heartbeat), None);
+    }
+
+    /// Test that observations round trip through an entry payload with unknown values.
+    #[test]
+    fn test_payload_round_trip() {
+        let weather = WeatherObservation {
+            temperature_c: Some(18.5),
+            humidity_pct: Some(62.0),
+            note: String::from("gusts from the hedge"),
+            ..WeatherObservation::from_wind(0.0, 4.0)
+        };
+        let payload = weather.to_payload();
+        assert_eq!(payload.len(), 16 + weather.note.len());
+        assert_eq!(
+            WeatherObservation::from_payload(&payload),
+            Some(weather.clone())
+        );
+        assert_eq!(
+            weather.to_string(),
+            r#"wind=4.0m/s from 270deg temperature=18.5C humidity=62% "gusts from the hedge""#
+        );
+
+        let empty = WeatherObservation::default();
+        assert_eq!(
+            WeatherObservation::from_payload(&empty.to_payload()),
+            Some(empty)
+        );
+        assert_eq!(WeatherObservation::from_payload(&[0; 15]), None);
+    }
+}
@@ -0,0 +1,267 @@
+//! Live broadcast of entries to WebSocket clients as JSON.
+//!
+//! `WebSocketPublisher` is an `Analyzer`, so it is attached to a capture with
+//! `Recorder::add_analyzer` and broadcasts every frame while the recorder writes the .mav log.
+//! Each MAVLink entry is sent as one text message holding the mavlink2rest JSON object produced
+//! by `json::to_json`, so browser dashboards can display live data with the same code that reads
+//! exported logs.
+//!
+//! Entries can be selected with a `ParserFilter` and rate limited per message and component to
+//! keep dashboards responsive over slow links. A client that cannot keep up misses entries
+//! rather than slowing down the capture.
+use std::collections::BTreeMap;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use mavlink::Message;
+use serde::Serialize;
+
+use crate::analysis::analyzer::Analyzer;
+use crate::analysis::filter::ParserFilter;
+use crate::json::to_json;
+use crate::mav_parser::LogEntry;
+
+/// Number of messages queued for a client before further entries are dropped for it.
+const CLIENT_QUEUE: usize = 256;
+
+/// Counters describing the activity of a publisher.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PublisherStats {
+    /// Number of entries broadcast to the connected clients.
+    pub broadcast: u64,
+    /// Number of messages not sent to a client because its queue was full.
+    pub dropped: u64,
+}
+
+/// Analyzer broadcasting entries to WebSocket clients.
+pub struct WebSocketPublisher {
+    local_addr: SocketAddr,
+    clients: Arc<Mutex<Vec<SyncSender<String>>>>,
+    stop: Arc<AtomicBool>,
+    filter: Option<ParserFilter>,
+    min_period_us: Option<u64>,
+    /// Timestamp of the last entry sent by message id and sending component.
+    last_sent_us: BTreeMap<(u32, u8, u8), u64>,
+    stats: PublisherStats,
+}
+
+impl WebSocketPublisher {
+    /// Creates a new `WebSocketPublisher` accepting clients in a background thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on, such as `0.0.0.0:8765`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `WebSocketPublisher` or an `io::Error` if the address could
+    /// not be bound.
+    pub fn bind(address: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let clients: Arc<Mutex<Vec<SyncSender<String>>>> = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let accepted = clients.clone();
+        let stopped = stop.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                // clients failing the handshake are ignored
+                if let Some(sender) = stream.ok().and_then(serve_client) {
+                    accepted
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push(sender);
+                }
+            }
+        });
+        Ok(Self {
+            local_addr,
+            clients,
+            stop,
+            filter: None,
+            min_period_us: None,
+            last_sent_us: BTreeMap::new(),
+            stats: PublisherStats::default(),
+        })
+    }
+
+    /// Returns the address the publisher listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns the number of connected clients.
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Only broadcasts the entries selected by a filter.
+    pub fn set_filter(&mut self, filter: ParserFilter) {
+        self.filter = Some(filter);
+    }
+
+    /// Limits the rate each message is broadcast at.
+    ///
+    /// Messages are limited separately per message id and sending component, based on entry
+    /// timestamps. Messages without a timestamp are always broadcast.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_rate_hz` - The maximum number of messages per second.
+    pub fn set_max_rate(&mut self, max_rate_hz: f64) {
+        self.min_period_us = Some((1e6 / max_rate_hz) as u64);
+    }
+
+    /// Returns the counters describing the publisher activity so far.
+    pub fn stats(&self) -> &PublisherStats {
+        &self.stats
+    }
+
+    /// Decides whether an entry is rate limited away.
+    fn rate_limited<M: Message>(&mut self, entry: &LogEntry<M>) -> bool {
+        let (Some(min_period_us), Some(timestamp), Some(msg)) =
+            (self.min_period_us, entry.timestamp, &entry.mav_message)
+        else {
+            return false;
+        };
+        let header = entry.mav_header.unwrap_or_default();
+        let key = (msg.message_id(), header.system_id, header.component_id);
+        match self.last_sent_us.get(&key) {
+            // restart after a timestamp going backwards
+            Some(&last_sent_us) if timestamp >= last_sent_us => {
+                if timestamp - last_sent_us < min_period_us {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+        self.last_sent_us.insert(key, timestamp);
+        false
+    }
+}
+
+/// Completes the WebSocket handshake of a client and starts the thread sending to it.
+///
+/// # Returns
+///
+/// The sender queuing messages for the client, or `None` if the handshake failed.
+fn serve_client(stream: TcpStream) -> Option<SyncSender<String>> {
+    let mut websocket = tungstenite::accept(stream).ok()?;
+    let (sender, receiver) = mpsc::sync_channel::<String>(CLIENT_QUEUE);
+    std::thread::spawn(move || {
+        // ends when the publisher is dropped or the client disconnects
+        for text in receiver {
+            if websocket.send(tungstenite::Message::text(text)).is_err() {
+                break;
+            }
+        }
+        let _ = websocket.close(None);
+    });
+    Some(sender)
+}
+
+impl<M: Message + Serialize> Analyzer<M> for WebSocketPublisher {
+    type Report = PublisherStats;
+
+    fn on_entry(&mut self, entry: &LogEntry<M>) {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(entry))
+            || self.rate_limited(entry)
+        {
+            return;
+        }
+        let Some(object) = to_json(entry) else {
+            return;
+        };
+        let text = object.to_string();
+        let mut dropped: u64 = 0;
+        self.clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|client| match client.try_send(text.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    dropped += 1;
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+        self.stats.broadcast += 1;
+        self.stats.dropped += dropped;
+    }
+
+    /// Stops accepting clients and disconnects the connected ones.
+    fn finish(self) -> PublisherStats {
+        self.stats.clone()
+    }
+}
+
+impl Drop for WebSocketPublisher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // wake up the accepting thread so that it sees the stop flag
+        let _ = TcpStream::connect(self.local_addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use mavlink::MavHeader;
+    use mavlink::common::MavMessage;
+
+    use super::*;
+
+    fn entry(timestamp: u64, msg: MavMessage) -> LogEntry<MavMessage> {
+        LogEntry {
+            timestamp: Some(timestamp),
+            mav_header: Some(MavHeader::default()),
+            mav_message: Some(msg),
+            ..Default::default()
+        }
+    }
+
+    /// Test that clients receive the filtered and rate limited entries as JSON.
+    #[test]
+    fn test_websocket_publisher() {
+        let mut publisher = WebSocketPublisher::bind("127.0.0.1:0").unwrap();
+        publisher.set_filter(ParserFilter::from_expr("msg != 'HEARTBEAT'").unwrap());
+        publisher.set_max_rate(10.0);
+        let url = format!("ws://{}", publisher.local_addr());
+        let (mut client, _) = tungstenite::connect(url).unwrap();
+        let start = Instant::now();
+        while publisher.client_count() == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let attitude = MavMessage::ATTITUDE(Default::default());
+        Analyzer::<MavMessage>::on_entry(&mut publisher, &entry(1_000_000, attitude.clone()));
+        Analyzer::<MavMessage>::on_entry(
+            &mut publisher,
+            &entry(1_010_000, MavMessage::HEARTBEAT(Default::default())),
+        );
+        Analyzer::<MavMessage>::on_entry(&mut publisher, &entry(1_050_000, attitude.clone()));
+        Analyzer::<MavMessage>::on_entry(&mut publisher, &entry(1_100_000, attitude));
+
+        let mut timestamps = Vec::new();
+        for _ in 0..2 {
+            let text = client.read().unwrap().into_text().unwrap();
+            let object: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(object["message"]["type"], "ATTITUDE");
+            timestamps.push(object["timestamp_us"].as_u64().unwrap());
+        }
+        assert_eq!(timestamps, vec![1_000_000, 1_100_000]);
+        let stats = Analyzer::<MavMessage>::finish(publisher);
+        assert_eq!(stats.broadcast, 2);
+        assert_eq!(stats.dropped, 0);
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(all(
+    feature = "mavlog",
+    feature = "logger",
+    feature = "parser",
+    feature = "analysis"
+))]
+#[cfg(test)]
+mod actuator_tests {
+    use mavlink::common::{GLOBAL_POSITION_INT_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::analysis::{AsAppliedConfig, as_applied_map};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::{ActuatorEvent, MavParser};
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+
+    /// Returns a GLOBAL_POSITION_INT frame at a latitude of 47 degrees and a longitude of 8
+    /// degrees offset by `north` times 1e-4 degrees.
+    fn position(north: i32) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            },
+            msg: MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                lat: 470_000_000 + north * 1000,
+                lon: 80_000_000,
+                ..Default::default()
+            }),
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    /// Test that actuator events round trip with their state and rate.
+    #[test]
+    fn test_actuator_entry_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("spray.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, None, None).unwrap();
+        logger.write_actuator(3, true, 120.5).unwrap();
+        drop(logger);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let entry = parser.parse_next_entry().unwrap();
+        assert_eq!(
+            entry.actuator,
+            Some(ActuatorEvent {
+                actuator: 3,
+                active: true,
+                rate: 120.5,
+            })
+        );
+        assert!(entry.to_string().ends_with(" ACTUATOR id=3 on rate=120.5"));
+    }
+
+    /// Test that the as-applied map of a logged job follows the vehicle while spraying.
+    #[test]
+    fn test_logged_as_applied_map() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("job.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, None, None).unwrap();
+        logger.write_mavlink(position(0)).unwrap();
+        logger.write_actuator(0, true, 150.0).unwrap();
+        logger.write_mavlink(position(1)).unwrap();
+        logger.write_mavlink(position(2)).unwrap();
+        logger.write_actuator(0, false, 0.0).unwrap();
+        logger.write_mavlink(position(3)).unwrap();
+        drop(logger);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let report = as_applied_map(&mut parser, &AsAppliedConfig::default()).unwrap();
+        assert_eq!(report.events.len(), 2);
+        assert_eq!(report.segments.len(), 1);
+        assert_eq!(report.segments[0].rate, 150.0);
+        assert_eq!(report.segments[0].path.len(), 3);
+    }
+}
@@ -0,0 +1,31 @@
+/// This module contains tests for the log analysis utilities. The sample TLOG
+/// file located at `tests/data/tlog_data_0.tlog` is used as input since it
+/// contains traffic from both a vehicle and a ground station.
+#[cfg(all(feature = "tlog", feature = "analysis"))]
+mod analysis_tests {
+    use mavlink::ardupilotmega::MavMessage;
+    use mavlink_log::analysis::discover_systems;
+    use mavlink_log::tlog::parser::TlogParser;
+
+    /// This test verifies that `discover_systems` reports the vehicle and the
+    /// ground station found in the sample TLOG along with the type information
+    /// from their HEARTBEAT messages.
+    #[test]
+    fn test_discover_systems() {
+        let mut tlog = TlogParser::<MavMessage>::new("tests/data/tlog_data_0.tlog");
+        let systems = discover_systems(&mut tlog).expect("Failed to read log");
+        assert_eq!(systems.len(), 2);
+
+        let vehicle = &systems[0];
+        assert_eq!((vehicle.system_id, vehicle.component_id), (1, 1));
+        assert_eq!(vehicle.mav_type, Some(12)); // MAV_TYPE_SUBMARINE
+        assert_eq!(vehicle.autopilot, Some(3)); // MAV_AUTOPILOT_ARDUPILOTMEGA
+        assert_eq!(vehicle.message_count, 1136);
+
+        let gcs = &systems[1];
+        assert_eq!((gcs.system_id, gcs.component_id), (255, 230));
+        assert_eq!(gcs.mav_type, Some(6)); // MAV_TYPE_GCS
+        assert_eq!(gcs.autopilot, Some(8)); // MAV_AUTOPILOT_INVALID
+        assert_eq!(gcs.message_count, 290);
+    }
+}
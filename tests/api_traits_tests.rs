@@ -0,0 +1,58 @@
+/// Tests that the public format types can be compared, cloned, printed and shared across
+/// threads.
+#[cfg(all(
+    feature = "mavlog",
+    feature = "tlog",
+    feature = "logger",
+    feature = "parser"
+))]
+#[cfg(test)]
+mod api_traits_tests {
+    use mavlink::common::MavMessage;
+    use mavlink_log::mav_parser::LogEntry;
+    use mavlink_log::mavlog::background::BackgroundMavLogger;
+    use mavlink_log::mavlog::header::{FileHeader, FormatFlags, MavlinkMessageDefinition};
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::tlog::logger::RotatingTlog;
+    use mavlink_log::tlog::parser::TlogParser;
+    use mavlink_log::tlog::timestamp::TimestampHeuristics;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    fn assert_send<T: Send>() {}
+
+    /// Test the thread safety of the public types at compile time.
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<FileHeader>();
+        assert_send_sync::<FormatFlags>();
+        assert_send_sync::<MavlinkMessageDefinition>();
+        assert_send_sync::<LogEntry<MavMessage>>();
+        assert_send_sync::<TimestampHeuristics>();
+        assert_send::<RotatingMavLogger>();
+        assert_send::<BackgroundMavLogger>();
+        assert_send::<RotatingTlog>();
+        assert_send::<TlogParser<MavMessage>>();
+    }
+
+    /// Test that headers and entries can be cloned and compared.
+    #[test]
+    fn test_clone_eq() {
+        let header = FileHeader::default();
+        let copy = header.clone();
+        assert_eq!(header, copy);
+        assert!(format!("{header:?}").contains("FileHeader"));
+        let flags = FormatFlags {
+            sequence: true,
+            ..header.format_flags
+        };
+        assert_ne!(flags, header.format_flags);
+
+        let entry: LogEntry<MavMessage> = LogEntry {
+            timestamp: Some(5),
+            mav_message: Some(MavMessage::HEARTBEAT(Default::default())),
+            ..Default::default()
+        };
+        assert_eq!(entry.clone(), entry);
+        assert_ne!(entry, LogEntry::default());
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod blob_tests {
+    use mavlink::common::MavMessage;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::mavlog::header::FormatFlags;
+    use mavlink_log::mavlog::logger::{BLOB_FRAGMENT_SIZE, RotatingMavLogger};
+    use mavlink_log::mavlog::parser::MavLogParser;
+
+    /// Builds a blob spanning three fragments.
+    fn blob_data() -> Vec<u8> {
+        (0..150_000u32).map(|i| (i % 253) as u8).collect()
+    }
+
+    /// Writes a sequenced log with a text entry, a blob, an empty blob and another text entry.
+    ///
+    /// Returns the offset of the first blob fragment.
+    fn write_log(path: &str) -> usize {
+        let flags = FormatFlags {
+            sequence: true,
+            ..Default::default()
+        };
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, Some(flags), None).unwrap();
+        logger.write_text("before").unwrap();
+        let offset = std::fs::metadata(path).unwrap().len() as usize;
+        assert_eq!(logger.write_blob(&blob_data()).unwrap(), 0);
+        assert_eq!(logger.write_blob(&[]).unwrap(), 1);
+        logger.write_text("after").unwrap();
+        offset
+    }
+
+    /// Test that blob fragments are reassembled into a single entry.
+    #[test]
+    fn test_blob_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("blob.mav");
+        let path = path.to_str().unwrap();
+        write_log(path);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        parser.set_strict_sequence(true);
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "before");
+        let entry = parser.parse_next_entry().unwrap();
+        assert_eq!(entry.sequence, Some(1));
+        let blob = entry.blob.unwrap();
+        assert_eq!((blob.id, blob.fragments), (0, 3));
+        assert_eq!(blob.data, blob_data());
+        let blob = parser.parse_next_entry().unwrap().blob.unwrap();
+        assert_eq!((blob.id, blob.fragments), (1, 1));
+        assert!(blob.data.is_empty());
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "after");
+        assert_eq!(parser.sequence_errors(), 0);
+    }
+
+    /// Test that a blob missing a fragment is reported and the following entries still parse.
+    #[test]
+    fn test_blob_missing_fragment() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("blob.mav");
+        let path = path.to_str().unwrap();
+        let offset = write_log(path);
+        // remove the second fragment, which follows the first fragment
+        let mut content = std::fs::read(path).unwrap();
+        let fragment_size = 1 + 8 + 4 + 2 + 12 + BLOB_FRAGMENT_SIZE;
+        let removed = offset + fragment_size;
+        content.drain(removed..removed + fragment_size);
+        std::fs::write(path, content).unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "before");
+        match parser.parse_next_entry() {
+            Err(mavlink::error::MessageReadError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            _ => panic!("Expected an incomplete blob"),
+        }
+        assert_eq!(parser.parse_next_entry().unwrap().blob.unwrap().id, 1);
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "after");
+    }
+}
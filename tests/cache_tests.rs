@@ -0,0 +1,137 @@
+/// Tests writing decoded entries to a cache and reading them back.
+#[cfg(all(
+    feature = "cache",
+    feature = "mavlog",
+    feature = "logger",
+    feature = "parser"
+))]
+#[cfg(test)]
+mod cache_tests {
+    use mavlink::common::{ATTITUDE_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::cache::{
+        CacheParser, CacheWriter, SourceStamp, is_fresh, open_cached, write_cache,
+    };
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::{LogEntry, MavParser, for_each_entry};
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+    use tempfile::TempDir;
+
+    fn attitude(time_boot_ms: u32) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader::default(),
+            msg: MavMessage::ATTITUDE(ATTITUDE_DATA {
+                time_boot_ms,
+                pitch: 0.25,
+                ..Default::default()
+            }),
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    fn parse_all<P: MavParser<M = MavMessage>>(parser: &mut P) -> Vec<LogEntry<MavMessage>> {
+        let mut entries = Vec::new();
+        for_each_entry(parser, |entry| {
+            entries.push(entry);
+            Ok(())
+        })
+        .unwrap();
+        entries
+    }
+
+    /// Test that a cache holds the same entries as the log it was written from, and that it is
+    /// rewritten once the log changes.
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("log.mav");
+        let cache_path = dir.path().join("log.cache");
+        let log_path = log_path.to_str().unwrap();
+        let cache_path = cache_path.to_str().unwrap();
+        {
+            let mut logger = RotatingMavLogger::new(log_path, 100000, 0, None, None).unwrap();
+            for i in 0..10 {
+                logger.write_mavlink(attitude(i)).unwrap();
+            }
+            logger.write_text("landed").unwrap();
+        }
+        let expected = parse_all(&mut MavLogParser::<MavMessage>::new(log_path));
+        assert_eq!(expected.len(), 11);
+
+        assert!(!is_fresh(cache_path, log_path).unwrap());
+        let source = SourceStamp::of(log_path).unwrap();
+        let mut parser = MavLogParser::<MavMessage>::new(log_path);
+        assert_eq!(write_cache(&mut parser, source, cache_path).unwrap(), 11);
+        assert!(is_fresh(cache_path, log_path).unwrap());
+
+        let mut cache = CacheParser::<MavMessage>::open(cache_path).unwrap();
+        assert_eq!(cache.source(), source);
+        assert_eq!(parse_all(&mut cache), expected);
+
+        // rewriting the log makes the cache stale
+        {
+            let mut logger = RotatingMavLogger::new(log_path, 100000, 0, None, None).unwrap();
+            for i in 0..12 {
+                logger.write_mavlink(attitude(i)).unwrap();
+            }
+        }
+        assert!(!is_fresh(cache_path, log_path).unwrap());
+        let mut cache = open_cached::<MavMessage>(log_path, cache_path).unwrap();
+        assert_eq!(
+            parse_all(&mut cache),
+            parse_all(&mut MavLogParser::<MavMessage>::new(log_path))
+        );
+        assert!(is_fresh(cache_path, log_path).unwrap());
+    }
+
+    /// Test that files other than caches are rejected.
+    #[test]
+    fn test_not_a_cache() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("log.cache");
+        std::fs::write(&path, [0u8; 64]).unwrap();
+        let error = CacheParser::<MavMessage>::open(path.to_str().unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    /// Test that record lengths above the maximum are reported as corruption instead of being
+    /// allocated.
+    #[test]
+    fn test_record_length_bound() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("log.cache");
+        let path = path.to_str().unwrap();
+        let mut writer = CacheWriter::new(Vec::new(), SourceStamp::default()).unwrap();
+        writer
+            .write_entry(&LogEntry {
+                timestamp: Some(1_000),
+                mav_message: Some(attitude(1).msg),
+                ..Default::default()
+            })
+            .unwrap();
+        let mut bytes = writer.finish().unwrap();
+        let valid_len = bytes.len();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(path, &bytes).unwrap();
+
+        let mut cache = CacheParser::<MavMessage>::open(path).unwrap();
+        assert_eq!(
+            cache.parse_next_entry().unwrap().mav_message,
+            Some(attitude(1).msg)
+        );
+        match cache.parse_next_entry() {
+            Err(mavlink::error::MessageReadError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            _ => panic!("an oversized record should be reported as invalid data"),
+        }
+
+        std::fs::write(path, &bytes[..valid_len]).unwrap();
+        let mut cache = CacheParser::<MavMessage>::open(path).unwrap();
+        cache.set_max_record_len(4);
+        assert!(cache.parse_next_entry().is_err());
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod camera_tests {
+    use mavlink::common::{CAMERA_IMAGE_CAPTURED_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mavlog::camera::{GEOTAG_FILE_NAME, extract_images};
+    use mavlink_log::mavlog::header::FormatFlags;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+
+    fn frame(msg: MavMessage) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader {
+                system_id: 1,
+                component_id: 100,
+                sequence: 0,
+            },
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    fn captured(image_index: i32) -> MavFrame<MavMessage> {
+        frame(MavMessage::CAMERA_IMAGE_CAPTURED(
+            CAMERA_IMAGE_CAPTURED_DATA {
+                time_utc: 1_700_000_000_000_000 + image_index as u64,
+                lat: 473_977_418,
+                lon: 85_455_938 + image_index,
+                alt: 488_000,
+                relative_alt: 30_000,
+                image_index,
+                ..Default::default()
+            },
+        ))
+    }
+
+    /// Builds an image larger than a single blob fragment.
+    fn image(image_index: i32) -> Vec<u8> {
+        (0..100_000u32)
+            .map(|i| (i as i32 + image_index) as u8)
+            .collect()
+    }
+
+    /// Test that logged images are extracted with their geotags.
+    #[test]
+    fn test_extract_images() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("survey.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, None, None).unwrap();
+        logger.write_camera_image(captured(0), &image(0)).unwrap();
+        logger
+            .write_mavlink(frame(MavMessage::HEARTBEAT(Default::default())))
+            .unwrap();
+        // a message without its image and a blob without a message are skipped
+        logger.write_mavlink(captured(1)).unwrap();
+        logger.write_blob(&[1, 2, 3]).unwrap();
+        logger.write_camera_image(captured(2), &image(2)).unwrap();
+        let error =
+            logger.write_camera_image(frame(MavMessage::HEARTBEAT(Default::default())), &[1]);
+        assert_eq!(error.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        drop(logger);
+
+        let output = dir.path().join("images");
+        std::fs::create_dir(&output).unwrap();
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let images = extract_images(&mut parser, &output).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].image_index, 0);
+        assert_eq!(images[1].image_index, 2);
+        assert_eq!((images[1].system_id, images[1].component_id), (1, 100));
+        assert_eq!(images[1].time_utc_us, 1_700_000_000_000_002);
+        assert!((images[1].latitude_deg - 47.3977418).abs() < 1e-9);
+        assert!((images[1].longitude_deg - 8.5455940).abs() < 1e-9);
+        assert!((images[1].altitude_m - 488.0).abs() < 1e-9);
+        assert!((images[1].relative_altitude_m - 30.0).abs() < 1e-9);
+        assert!(images[0].timestamp_us.is_some());
+        for image_data in &images {
+            let expected = image(image_data.image_index);
+            assert_eq!(std::fs::read(&image_data.path).unwrap(), expected);
+        }
+
+        let geotags = std::fs::read_to_string(output.join(GEOTAG_FILE_NAME)).unwrap();
+        let lines: Vec<&str> = geotags.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[2],
+            "sys1_comp100_cam0_00002.jpg,1700000000000002,47.3977418,8.5455940,488.000,30.000"
+        );
+    }
+
+    /// Test that images cannot be written to a MAVLink only log.
+    #[test]
+    fn test_camera_image_mavlink_only() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("survey.mav");
+        let flags = FormatFlags {
+            mavlink_only: true,
+            ..Default::default()
+        };
+        let mut logger =
+            RotatingMavLogger::new(path.to_str().unwrap(), 100_000_000, 0, Some(flags), None)
+                .unwrap();
+        let size = std::fs::metadata(&path).unwrap().len();
+        let error = logger
+            .write_camera_image(captured(0), &image(0))
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), size);
+    }
+}
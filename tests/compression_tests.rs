@@ -0,0 +1,249 @@
+#[cfg(all(feature = "compression", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod compression_tests {
+    use mavlink::common::{ATTITUDE_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::{MavParser, for_each_entry};
+    use mavlink_log::mavlog::block::{BlockHeader, Codec, recompress, scan_blocks};
+    use mavlink_log::mavlog::dictionary::{
+        EntryCompressor, samples_from_log, save_dictionary, train_dictionary,
+    };
+    use mavlink_log::mavlog::header::FormatFlags;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+
+    /// Number of messages written to each log.
+    const MESSAGE_COUNT: usize = 2000;
+
+    /// Builds a slowly changing attitude message.
+    fn attitude(i: usize) -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: i as u8,
+            },
+            msg: MavMessage::ATTITUDE(ATTITUDE_DATA {
+                time_boot_ms: i as u32 * 20,
+                roll: (i % 50) as f32 * 0.001,
+                pitch: 0.02,
+                yaw: 1.5,
+                ..Default::default()
+            }),
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    /// Writes attitude messages to a logger.
+    fn write_messages(logger: &mut RotatingMavLogger) {
+        for i in 0..MESSAGE_COUNT {
+            logger.write_mavlink(attitude(i)).unwrap();
+        }
+    }
+
+    /// Test that a dictionary trained from a sample log compresses a new log that parses back
+    /// to the same messages.
+    #[test]
+    fn test_dictionary_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let sample_path = dir.path().join("sample.mav");
+        let sample_path = sample_path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(sample_path, 100_000_000, 0, None, None).unwrap();
+        write_messages(&mut logger);
+        drop(logger);
+
+        let samples = samples_from_log::<MavMessage>(sample_path).unwrap();
+        assert_eq!(samples.len(), MESSAGE_COUNT);
+        let dictionary = train_dictionary(&samples, 4096).unwrap();
+        save_dictionary(dir.path(), &dictionary).unwrap();
+
+        let path = dir.path().join("compressed.mav");
+        let path = path.to_str().unwrap();
+        let flags = FormatFlags {
+            sequence: true,
+            ..Default::default()
+        };
+        let compressor = EntryCompressor::new(&dictionary).unwrap();
+        let mut logger =
+            RotatingMavLogger::new_compressed(path, 100_000_000, 0, Some(flags), None, compressor)
+                .unwrap();
+        write_messages(&mut logger);
+        drop(logger);
+        assert!(
+            std::fs::metadata(path).unwrap().len() < std::fs::metadata(sample_path).unwrap().len()
+        );
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        for i in 0..MESSAGE_COUNT {
+            let entry = parser.parse_next_entry().unwrap();
+            assert_eq!(entry.sequence, Some(i as u32));
+            match entry.mav_message.unwrap() {
+                MavMessage::ATTITUDE(data) => assert_eq!(data.time_boot_ms, i as u32 * 20),
+                _ => panic!("Unexpected message"),
+            }
+        }
+    }
+
+    /// Test that a compressed log cannot be opened without its dictionary.
+    #[test]
+    #[should_panic(expected = "Compression dictionary not available.")]
+    fn test_missing_dictionary() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let samples: Vec<Vec<u8>> = (0..MESSAGE_COUNT)
+            .map(|i| format!("field note {} at waypoint {}", i % 7, i % 13).into_bytes())
+            .collect();
+        let dictionary = train_dictionary(&samples, 1024).unwrap();
+
+        let path = dir.path().join("compressed.mav");
+        let path = path.to_str().unwrap();
+        let compressor = EntryCompressor::new(&dictionary).unwrap();
+        let mut logger =
+            RotatingMavLogger::new_compressed(path, 100_000, 0, None, None, compressor).unwrap();
+        logger.write_text("field note 1 at waypoint 2").unwrap();
+        drop(logger);
+
+        MavLogParser::<MavMessage>::new(path);
+    }
+
+    /// Test that a chunked log parses back and that a corrupted block loses only its own
+    /// entries.
+    #[test]
+    fn test_chunked_corrupt_block() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("chunked.mav");
+        let path = path.to_str().unwrap();
+        let flags = FormatFlags {
+            sequence: true,
+            chunked: true,
+            ..Default::default()
+        };
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, Some(flags), None).unwrap();
+        logger.set_block_size(1024);
+        for i in 0..300 {
+            logger.write_text(&format!("entry {i}")).unwrap();
+        }
+        let header_size = logger.header().packed_size();
+        drop(logger);
+
+        let mut content = std::fs::read(path).unwrap();
+        let blocks = scan_blocks(&content[header_size..]).unwrap();
+        assert!(blocks.len() > 3);
+        let entry_count: u32 = blocks.iter().map(|block| block.header.entry_count).sum();
+        assert_eq!(entry_count, 300);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        for i in 0..300 {
+            let entry = parser.parse_next_entry().unwrap();
+            assert_eq!(entry.text.unwrap(), format!("entry {i}"));
+        }
+
+        let corrupted = header_size + blocks[1].offset as usize + BlockHeader::SIZE + 5;
+        content[corrupted] ^= 0xFF;
+        std::fs::write(path, &content).unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let mut parsed: u32 = 0;
+        for_each_entry(&mut parser, |_| {
+            parsed += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(parsed, 300 - blocks[1].header.entry_count);
+        assert_eq!(parser.sequence_errors(), 1);
+    }
+
+    /// Test that a log recompressed into blocks and back out of them keeps its entries and
+    /// footer.
+    #[test]
+    fn test_recompress_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let mut logger =
+            RotatingMavLogger::new(&path("plain.mav"), 100_000_000, 0, None, None).unwrap();
+        write_messages(&mut logger);
+        let footer = logger.finish().unwrap();
+
+        let archive = Codec::Zstd {
+            level: 19,
+            block_size: 4096,
+        };
+        let count = recompress(&path("plain.mav"), &path("archive.mav"), archive).unwrap();
+        assert_eq!(count, MESSAGE_COUNT as u64);
+        let count = recompress(
+            &path("archive.mav"),
+            &path("fast.mav"),
+            Codec::Zstd {
+                level: 1,
+                block_size: 65536,
+            },
+        )
+        .unwrap();
+        assert_eq!(count, MESSAGE_COUNT as u64);
+        recompress(
+            &path("fast.mav"),
+            &path("restored.mav"),
+            Codec::Uncompressed,
+        )
+        .unwrap();
+
+        let plain = std::fs::read(path("plain.mav")).unwrap();
+        let archive = std::fs::read(path("archive.mav")).unwrap();
+        assert!(archive.len() < plain.len() / 4);
+        assert_eq!(std::fs::read(path("restored.mav")).unwrap(), plain);
+
+        let mut parser = MavLogParser::<MavMessage>::new(&path("archive.mav"));
+        assert_eq!(parser.footer(), Some(&footer));
+        for i in 0..MESSAGE_COUNT {
+            let entry = parser.parse_next_entry().unwrap();
+            assert_eq!(entry.mav_message, Some(attitude(i).msg));
+        }
+    }
+
+    /// Test that a hash-chained log recompressed into blocks and back out of them still parses,
+    /// and that a broken chain is not recompressed.
+    #[cfg(feature = "hash_chain")]
+    #[test]
+    fn test_recompress_hash_chain_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let flags = FormatFlags {
+            hash_chain: true,
+            ..Default::default()
+        };
+        let mut logger =
+            RotatingMavLogger::new(&path("plain.mav"), 100_000_000, 0, Some(flags), None).unwrap();
+        write_messages(&mut logger);
+        let entries_start = logger.header().packed_size();
+        logger.finish().unwrap();
+
+        let archive = Codec::Zstd {
+            level: 3,
+            block_size: 4096,
+        };
+        let count = recompress(&path("plain.mav"), &path("archive.mav"), archive).unwrap();
+        assert_eq!(count, MESSAGE_COUNT as u64);
+        recompress(
+            &path("archive.mav"),
+            &path("restored.mav"),
+            Codec::Uncompressed,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read(path("restored.mav")).unwrap(),
+            std::fs::read(path("plain.mav")).unwrap()
+        );
+
+        let mut parser = MavLogParser::<MavMessage>::new(&path("archive.mav"));
+        for i in 0..MESSAGE_COUNT {
+            let entry = parser.parse_next_entry().unwrap();
+            assert_eq!(entry.mav_message, Some(attitude(i).msg));
+        }
+
+        let mut content = std::fs::read(path("plain.mav")).unwrap();
+        content[entries_start + 20] ^= 0xFF;
+        std::fs::write(path("plain.mav"), content).unwrap();
+        let error = recompress(&path("plain.mav"), &path("broken.mav"), archive).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,73 @@
+/// Tests the single line representation of log entries.
+#[cfg(feature = "parser")]
+#[cfg(test)]
+mod display_tests {
+    use mavlink::MavHeader;
+    use mavlink::common::{HEARTBEAT_DATA, MavMessage};
+    use mavlink_log::mav_parser::{Blob, LogEntry};
+
+    /// Test the representation of each kind of entry.
+    #[test]
+    fn test_display_entries() {
+        let heartbeat: LogEntry<MavMessage> = LogEntry {
+            timestamp: Some(1_632_843_969_792_995),
+            mav_header: Some(MavHeader {
+                system_id: 1,
+                component_id: 2,
+                sequence: 7,
+            }),
+            mav_message: Some(MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+                custom_mode: 4,
+                mavlink_version: 3,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let line = heartbeat.to_string();
+        assert!(
+            line.starts_with("1632843969.792995 MAVLINK 1/2 seq=7 HEARTBEAT { custom_mode: 4,"),
+            "{line}"
+        );
+        assert!(line.ends_with("mavlink_version: 3 }"), "{line}");
+        assert!(!line.contains('\n'));
+
+        let text: LogEntry<MavMessage> = LogEntry {
+            timestamp: Some(1_500),
+            sequence: Some(3),
+            text: Some(String::from("armed \"now\"")),
+            ..Default::default()
+        };
+        assert_eq!(text.to_string(), "0.001500 #3 TEXT \"armed \\\"now\\\"\"");
+
+        let raw: LogEntry<MavMessage> = LogEntry {
+            raw: Some((0..40).collect()),
+            ..Default::default()
+        };
+        assert_eq!(
+            raw.to_string(),
+            format!(
+                "- RAW bytes=40 {}...",
+                (0..32).map(|b| format!("{b:02x}")).collect::<String>()
+            )
+        );
+
+        let blob: LogEntry<MavMessage> = LogEntry {
+            timestamp: Some(0),
+            blob: Some(Blob {
+                id: 2,
+                fragments: 3,
+                data: vec![0; 100],
+            }),
+            raw: Some(vec![0; 12]),
+            ..Default::default()
+        };
+        assert_eq!(blob.to_string(), "0.000000 BLOB id=2 fragments=3 bytes=100");
+
+        let drops: LogEntry<MavMessage> = LogEntry {
+            drops: Some(vec![(30, 5), (33, 2)]),
+            ..Default::default()
+        };
+        assert_eq!(drops.to_string(), "- DROPS 30:5 33:2");
+        assert_eq!(LogEntry::<MavMessage>::default().to_string(), "- EMPTY");
+    }
+}
@@ -0,0 +1,61 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod footer_tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+
+    use mavlink::common::MavMessage;
+    use mavlink_log::clock::{BackwardsPolicy, Clock, ClockSource};
+    use mavlink_log::mav_parser::for_each_entry;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+    use tempfile::TempDir;
+
+    /// Test that a finished log reports its duration from the footer and still parses cleanly.
+    #[test]
+    fn test_footer_duration() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("finished.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100000, 0, None, None).unwrap();
+        let time = Arc::new(AtomicU64::new(0));
+        let source_time = time.clone();
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || source_time.load(Ordering::Relaxed))),
+            BackwardsPolicy::Allow,
+        ));
+        for timestamp in [1_000_000, 1_500_000, 4_000_000] {
+            time.store(timestamp, Ordering::Relaxed);
+            logger.write_text("entry").unwrap();
+        }
+        let footer = logger.finish().unwrap();
+        assert_eq!(footer.entry_count, 3);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        assert_eq!(parser.footer(), Some(&footer));
+        assert_eq!(parser.duration(), Some(Duration::from_secs(3)));
+        let mut entries = 0;
+        for_each_entry(&mut parser, |_| {
+            entries += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(entries, 3);
+    }
+
+    /// Test that a log dropped without finishing has no footer.
+    #[test]
+    fn test_no_footer() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("dropped.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100000, 0, None, None).unwrap();
+        logger.write_text("entry").unwrap();
+        drop(logger);
+
+        let parser = MavLogParser::<MavMessage>::new(path);
+        assert_eq!(parser.footer(), None);
+        assert_eq!(parser.duration(), None);
+    }
+}
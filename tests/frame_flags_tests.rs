@@ -0,0 +1,114 @@
+/// Tests that the incompatibility and compatibility flags of MAVLink 2 frames, and the signature
+/// of signed frames, survive being logged and parsed back.
+#[cfg(all(
+    feature = "mavlog",
+    feature = "tlog",
+    feature = "logger",
+    feature = "parser"
+))]
+#[cfg(test)]
+mod frame_flags_tests {
+    use mavlink::common::{ATTITUDE_DATA, MavMessage};
+    use mavlink::{MAVLinkV2MessageRaw, MavHeader, Message};
+    use mavlink_log::mav_parser::{LogEntry, MavParser, for_each_entry};
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+    use mavlink_log::tlog::logger::RotatingTlog;
+    use mavlink_log::tlog::parser::TlogParser;
+    use tempfile::TempDir;
+
+    /// Incompatibility flag of a signed frame.
+    const IFLAG_SIGNED: u8 = 0x01;
+    /// An arbitrary compatibility flag.
+    const COMPAT_FLAG: u8 = 0x80;
+
+    fn attitude() -> MavMessage {
+        MavMessage::ATTITUDE(ATTITUDE_DATA {
+            time_boot_ms: 33,
+            roll: 0.5,
+            ..Default::default()
+        })
+    }
+
+    /// Accumulates a byte into the MAVLink X.25 checksum.
+    fn crc_accumulate(byte: u8, crc: u16) -> u16 {
+        let mut tmp: u8 = byte ^ (crc & 0xff) as u8;
+        tmp ^= tmp << 4;
+        let tmp = tmp as u16;
+        (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4)
+    }
+
+    /// Serializes a MAVLink 2 frame with the given flags and a made up signature.
+    fn signed_frame(msg: &MavMessage) -> Vec<u8> {
+        let mut raw = MAVLinkV2MessageRaw::new();
+        raw.serialize_message(MavHeader::default(), msg);
+        let mut bytes = raw.raw_bytes().to_vec();
+        bytes[2] = IFLAG_SIGNED;
+        bytes[3] = COMPAT_FLAG;
+        let checksum_start = bytes.len() - 2;
+        let crc = bytes[1..checksum_start]
+            .iter()
+            .fold(0xffff, |crc, &byte| crc_accumulate(byte, crc));
+        let crc = crc_accumulate(MavMessage::extra_crc(msg.message_id()), crc);
+        bytes[checksum_start..].copy_from_slice(&crc.to_le_bytes());
+        bytes.extend_from_slice(&[0xA5; 13]);
+        bytes
+    }
+
+    fn parse_all<P: MavParser<M = MavMessage>>(parser: &mut P) -> Vec<LogEntry<MavMessage>> {
+        let mut entries = Vec::new();
+        for_each_entry(parser, |entry| {
+            entries.push(entry);
+            Ok(())
+        })
+        .unwrap();
+        entries
+    }
+
+    /// Test that a signed frame written raw to a .mav log is parsed with its flags, and that the
+    /// entries after it are not disturbed by its signature.
+    #[test]
+    fn test_mavlog_raw_signed_frame() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("signed.mav");
+        let path = path.to_str().unwrap();
+        let frame = signed_frame(&attitude());
+        {
+            let mut logger = RotatingMavLogger::new(path, 100000, 0, None, None).unwrap();
+            logger.write_mavlink_raw(&frame).unwrap();
+            logger.write_mavlink_raw(&frame).unwrap();
+            let error = logger.write_mavlink_raw(&frame[..10]).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        }
+
+        let entries = parse_all(&mut MavLogParser::<MavMessage>::new(path));
+        assert_eq!(entries.len(), 2);
+        for entry in entries {
+            assert_eq!(entry.mav_message, Some(attitude()));
+            assert_eq!(entry.incompat_flags, Some(IFLAG_SIGNED));
+            assert_eq!(entry.compat_flags, Some(COMPAT_FLAG));
+            assert_eq!(entry.entry_len, Some(1 + 8 + 2 + frame.len() as u64));
+        }
+    }
+
+    /// Test that a signed frame written raw to a tlog is stored unchanged.
+    #[test]
+    fn test_tlog_raw_signed_frame() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("signed.tlog");
+        let path = path.to_str().unwrap();
+        let frame = signed_frame(&attitude());
+        {
+            let mut logger = RotatingTlog::new(path, 100000, 0).unwrap();
+            logger.write_mavlink_raw(&frame).unwrap();
+        }
+        let content = std::fs::read(path).unwrap();
+        assert_eq!(&content[8..], frame.as_slice());
+
+        let entries = parse_all(&mut TlogParser::<MavMessage>::new(path));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].mav_message, Some(attitude()));
+        assert_eq!(entries[0].incompat_flags, Some(IFLAG_SIGNED));
+        assert_eq!(entries[0].compat_flags, Some(COMPAT_FLAG));
+    }
+}
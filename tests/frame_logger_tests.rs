@@ -0,0 +1,68 @@
+/// Tests storing loggers of different types behind the object safe `MavFrameLogger` trait.
+#[cfg(all(
+    feature = "mavlog",
+    feature = "tlog",
+    feature = "logger",
+    feature = "parser"
+))]
+#[cfg(test)]
+mod frame_logger_tests {
+    use mavlink::common::{HEARTBEAT_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::mav_logger::{MavFrameLogger, MavLogger};
+    use mavlink_log::mav_parser::{MavParser, for_each_entry};
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+    use mavlink_log::tlog::logger::RotatingTlog;
+    use mavlink_log::tlog::parser::TlogParser;
+    use tempfile::TempDir;
+
+    fn heartbeat() -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader::default(),
+            msg: MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+                mavlink_version: 3,
+                ..Default::default()
+            }),
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    fn count_messages<P: MavParser<M = MavMessage>>(mut parser: P) -> usize {
+        let mut count = 0;
+        for_each_entry(&mut parser, |entry| {
+            assert_eq!(entry.mav_message, Some(heartbeat().msg));
+            count += 1;
+            Ok(())
+        })
+        .unwrap();
+        count
+    }
+
+    /// Test that boxed loggers of different types can be kept in one collection and written to
+    /// both with typed frames and with serialized frames.
+    #[test]
+    fn test_boxed_loggers() {
+        let dir = TempDir::new().unwrap();
+        let mav_path = dir.path().join("log.mav");
+        let tlog_path = dir.path().join("log.tlog");
+        let mav_path = mav_path.to_str().unwrap();
+        let tlog_path = tlog_path.to_str().unwrap();
+        {
+            let mut loggers: Vec<Box<dyn MavFrameLogger>> = vec![
+                Box::new(RotatingMavLogger::new(mav_path, 100000, 0, None, None).unwrap()),
+                Box::new(RotatingTlog::new(tlog_path, 100000, 0).unwrap()),
+            ];
+            let mut raw = mavlink::MAVLinkV2MessageRaw::new();
+            raw.serialize_message(heartbeat().header, &heartbeat().msg);
+            for logger in loggers.iter_mut() {
+                logger.write_mavlink(heartbeat()).unwrap();
+                logger.write_frame(raw.raw_bytes()).unwrap();
+                let error = logger.write_frame(&raw.raw_bytes()[..5]).unwrap_err();
+                assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+            }
+        }
+        assert_eq!(count_messages(MavLogParser::<MavMessage>::new(mav_path)), 2);
+        assert_eq!(count_messages(TlogParser::<MavMessage>::new(tlog_path)), 2);
+    }
+}
@@ -0,0 +1,218 @@
+#[cfg(all(feature = "hash_chain", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod hash_chain_tests {
+    use mavlink::common::MavMessage;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::mavlog::header::{FileHeader, FormatFlags};
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+
+    /// Size of each text entry written by `write_log`.
+    const ENTRY_SIZE: usize = 26;
+
+    /// Writes a hash-chained log with five text entries.
+    fn write_log(path: &str) {
+        let flags = FormatFlags {
+            hash_chain: true,
+            ..Default::default()
+        };
+        let mut logger = RotatingMavLogger::new(path, 100000, 0, Some(flags), None).unwrap();
+        for i in 0..5 {
+            logger.write_text(&format!("entry {i}")).unwrap();
+        }
+    }
+
+    /// Test that an intact hash chain parses.
+    #[test]
+    fn test_hash_chain_intact() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("chained.mav");
+        let path = path.to_str().unwrap();
+        write_log(path);
+        assert_eq!(
+            std::fs::read(path).unwrap().len(),
+            FileHeader::MIN_SIZE + 5 * ENTRY_SIZE
+        );
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        for i in 0..5 {
+            let entry = parser.parse_next_entry().unwrap();
+            assert_eq!(entry.text.unwrap(), format!("entry {i}"));
+        }
+    }
+
+    /// Test that removing an entry breaks the hash chain at the following entry.
+    #[test]
+    fn test_hash_chain_detects_removed_entry() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("chained.mav");
+        let path = path.to_str().unwrap();
+        write_log(path);
+        let mut content = std::fs::read(path).unwrap();
+        let removed = FileHeader::MIN_SIZE + 2 * ENTRY_SIZE;
+        content.drain(removed..removed + ENTRY_SIZE);
+        std::fs::write(path, content).unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        assert!(parser.parse_next_entry().is_ok());
+        assert!(parser.parse_next_entry().is_ok());
+        match parser.parse_next_entry() {
+            Err(mavlink::error::MessageReadError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            _ => panic!("Expected a broken hash chain"),
+        }
+        assert!(parser.parse_next_entry().is_ok());
+    }
+
+    /// Test that removing the first entry breaks the hash chain, as it links to the file header.
+    #[test]
+    fn test_hash_chain_detects_removed_first_entry() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("chained.mav");
+        let path = path.to_str().unwrap();
+        write_log(path);
+        let mut content = std::fs::read(path).unwrap();
+        let removed = FileHeader::MIN_SIZE;
+        content.drain(removed..removed + ENTRY_SIZE);
+        std::fs::write(path, content).unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        match parser.parse_next_entry() {
+            Err(mavlink::error::MessageReadError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            _ => panic!("Expected a broken hash chain"),
+        }
+        assert!(parser.parse_next_entry().is_ok());
+    }
+
+    /// Reads the text entries of a log file, failing on any error such as a broken hash chain.
+    fn read_texts(path: &std::path::Path) -> Vec<String> {
+        let mut parser = MavLogParser::<MavMessage>::new(path.to_str().unwrap());
+        let mut texts: Vec<String> = Vec::new();
+        loop {
+            match parser.parse_next_entry() {
+                Ok(entry) => texts.extend(entry.text),
+                Err(mavlink::error::MessageReadError::Io(e))
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    return texts;
+                }
+                Err(e) => panic!("{} should parse: {e:?}", path.display()),
+            }
+        }
+    }
+
+    /// Reads the text entries of every log file in a directory, oldest file first, leaving out
+    /// files without entries. Files are ordered by their entries since backup names are chosen
+    /// by the file handler.
+    fn read_all_texts(dir: &std::path::Path) -> Vec<Vec<String>> {
+        let mut files: Vec<Vec<String>> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|file| read_texts(&file.unwrap().path()))
+            .filter(|texts| !texts.is_empty())
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Test that the hash chain of a rotated log file starts over at its file header.
+    #[test]
+    fn test_hash_chain_rotated_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("chained.mav");
+        let flags = FormatFlags {
+            hash_chain: true,
+            ..Default::default()
+        };
+        let max_bytes = (FileHeader::MIN_SIZE + 3 * ENTRY_SIZE) as u64;
+        let mut logger =
+            RotatingMavLogger::new(path.to_str().unwrap(), max_bytes, 1, Some(flags), None)
+                .unwrap();
+        for i in 0..5 {
+            logger.write_text(&format!("entry {i}")).unwrap();
+        }
+        assert_eq!(logger.rotations(), 1);
+        drop(logger);
+
+        assert_eq!(read_texts(&path), vec!["entry 3", "entry 4"]);
+        assert_eq!(
+            read_all_texts(dir.path()),
+            vec![
+                vec!["entry 0", "entry 1", "entry 2"],
+                vec!["entry 3", "entry 4"]
+            ]
+        );
+    }
+
+    /// Test that an entry of a chunked log links to the header of a new file when writing the
+    /// previous block to make room for it rotates the file.
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_hash_chain_rotated_chunked_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("chained.mav");
+        let flags = FormatFlags {
+            hash_chain: true,
+            chunked: true,
+            large_entries: true,
+            ..Default::default()
+        };
+        // Every block fills a file, and a block holds two of the entries
+        let max_bytes = FileHeader::MIN_SIZE as u64 + 1;
+        let mut logger =
+            RotatingMavLogger::new(path.to_str().unwrap(), max_bytes, 3, Some(flags), None)
+                .unwrap();
+        logger.set_block_size(usize::MAX);
+        let texts: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .map(|letter| letter.repeat(3 * 1024 * 1024))
+            .collect();
+        for text in &texts {
+            logger.write_text(text).unwrap();
+        }
+        logger.flush().unwrap();
+        assert_eq!(logger.rotations(), 2);
+        drop(logger);
+
+        assert_eq!(
+            read_all_texts(dir.path()),
+            vec![texts[..2].to_vec(), texts[2..].to_vec()]
+        );
+    }
+}
+
+#[cfg(all(feature = "hash_chain", feature = "parser"))]
+#[cfg(test)]
+mod hash_chain_parser_tests {
+    use mavlink::common::MavMessage;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::mavlog::parser::MavLogParser;
+
+    /// Path of a hash-chained log built by hand rather than by the logger, so that the chain
+    /// is checked in builds without it. Its file header has an unknown format flag (0x0100) and
+    /// a source application id that is not valid UTF-8, both dropped when read leniently,
+    /// followed by three text entries, `entry 0` to `entry 2`. The first entry links to the
+    /// header bytes as stored.
+    const LENIENT_PATH: &str = "tests/data/hash_chain_lenient.mav";
+
+    /// Test that the first entry links to the file header as stored, even when the header is
+    /// read leniently and its unpacked fields would pack differently.
+    #[test]
+    fn test_hash_chain_lenient_header() {
+        assert!(MavLogParser::<MavMessage>::try_new(LENIENT_PATH).is_err());
+
+        let mut parser = MavLogParser::<MavMessage>::new_lenient(LENIENT_PATH);
+        for i in 0..3 {
+            let entry = parser.parse_next_entry().unwrap();
+            assert_eq!(entry.text.unwrap(), format!("entry {i}"));
+        }
+        match parser.parse_next_entry() {
+            Err(mavlink::error::MessageReadError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof)
+            }
+            _ => panic!("Expected the end of the file"),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod large_entry_tests {
+    use mavlink::common::MavMessage;
+    use mavlink::error::MessageReadError;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::mavlog::header::FormatFlags;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+
+    /// Builds a raw payload larger than a 16-bit size field can describe.
+    fn large_payload() -> Vec<u8> {
+        (0..200_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Test that oversized entries are rejected without writing anything or skipping a sequence
+    /// number.
+    #[test]
+    fn test_oversized_entry_rejected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("small.mav");
+        let path = path.to_str().unwrap();
+        let flags = FormatFlags {
+            sequence: true,
+            ..Default::default()
+        };
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, Some(flags), None).unwrap();
+        logger.write_text("before").unwrap();
+        let size = std::fs::metadata(path).unwrap().len();
+        let error = logger.write_raw(&large_payload()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(std::fs::metadata(path).unwrap().len(), size);
+        logger.write_raw(&[0u8; u16::MAX as usize]).unwrap();
+        logger.write_text("after").unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "before");
+        assert_eq!(parser.parse_next_entry().unwrap().raw.unwrap().len(), 65535);
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "after");
+        assert_eq!(parser.sequence_errors(), 0);
+    }
+
+    /// Test that entries larger than 64 KiB round trip with the large_entries flag.
+    #[test]
+    fn test_large_entries_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("large.mav");
+        let path = path.to_str().unwrap();
+        let flags = FormatFlags {
+            large_entries: true,
+            ..Default::default()
+        };
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, Some(flags), None).unwrap();
+        logger.write_raw(&large_payload()).unwrap();
+        logger.write_text("done").unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        assert_eq!(
+            parser.parse_next_entry().unwrap().raw.unwrap(),
+            large_payload()
+        );
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "done");
+        assert!(parser.parse_next_entry().is_err());
+    }
+
+    /// Packs a timestamped large text entry with an arbitrary size field.
+    fn text_entry(timestamp: u64, size: u32, text: &str) -> Vec<u8> {
+        let mut bytes = vec![2u8];
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        bytes.extend_from_slice(&size.to_le_bytes());
+        bytes.extend_from_slice(text.as_bytes());
+        bytes
+    }
+
+    /// Test that a corrupted size field is reported as corruption and parsing resumes with the
+    /// next entry instead of reading a huge payload.
+    #[test]
+    fn test_corrupt_size_resync() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("corrupt.mav");
+        let path = path.to_str().unwrap();
+        let flags = FormatFlags {
+            large_entries: true,
+            ..Default::default()
+        };
+        drop(RotatingMavLogger::new(path, 100_000_000, 0, Some(flags), None).unwrap());
+        let mut content = std::fs::read(path).unwrap();
+        content.extend(text_entry(1_000_000, 1, "a"));
+        content.extend(text_entry(2_000_000, 0xFFFF_FF00, "b"));
+        content.extend(text_entry(3_000_000, 1, "c"));
+        std::fs::write(path, content).unwrap();
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "a");
+        match parser.parse_next_entry() {
+            Err(MessageReadError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            _ => panic!("Expected the corrupted entry to be reported"),
+        }
+        let entry = parser.parse_next_entry().unwrap();
+        assert_eq!(entry.text.unwrap(), "c");
+        assert_eq!(entry.offset, None);
+        assert!(parser.parse_next_entry().is_err());
+
+        // a lower maximum rejects entries that are otherwise valid
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        parser.set_max_entry_size(0);
+        assert!(parser.parse_next_entry().is_err());
+    }
+}
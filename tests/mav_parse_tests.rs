@@ -80,6 +80,84 @@ mod mav_parse_tests {
         MavLogParser::<mavlink::ardupilotmega::MavMessage>::new(temp_file.path().to_str().unwrap());
     }
 
+    /// Builds a file header whose source application id is not valid UTF-8.
+    fn invalid_application_id_header() -> [u8; 108] {
+        [
+            // file header
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, // uuid
+            16, 0, 0, 0, 0, 0, 0, 17, // timestamp_us
+            b'a', 0xFF, b'p', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, // src_application_id
+            1, 0, 0, 0, // format_version
+            0, 0, // format_flags
+            // message_definition
+            2, 0, 0, 0, // version_major
+            1, 0, 0, 0, // version_minor
+            b't', b'e', b's', b't', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, // dialect
+            0, 0, // payload_type
+            0, 0, 0, 0, // size
+        ]
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid file header field src_application_id")]
+    fn test_mav_log_parser_file_invalid_application_id() {
+        let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        temp_file
+            .write(&invalid_application_id_header())
+            .expect("Failed to write test file");
+
+        MavLogParser::<mavlink::ardupilotmega::MavMessage>::new(temp_file.path().to_str().unwrap());
+    }
+
+    #[test]
+    fn test_mav_log_parser_lenient_invalid_application_id() {
+        // the lenient parser reads past the invalid field and finds no entries
+        let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        temp_file
+            .write(&invalid_application_id_header())
+            .expect("Failed to write test file");
+
+        let mut parser = MavLogParser::<mavlink::ardupilotmega::MavMessage>::new_lenient(
+            temp_file.path().to_str().unwrap(),
+        );
+        assert!(parser.parse_next_entry().is_err());
+    }
+
+    #[test]
+    fn test_mav_log_parser_arbitrary_bytes() {
+        // malformed files are reported as errors rather than panics, see fuzz/
+        let mut state: u32 = 0x2545_f491;
+        let mut next_byte = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        };
+        for case in 0..200 {
+            let mut data = invalid_application_id_header().to_vec();
+            data[25] = b'p';
+            data.extend((0..case * 7).map(|_| next_byte()));
+            // corrupt a few bytes of the header and entries
+            for _ in 0..case % 5 {
+                let index = next_byte() as usize * 7 % data.len();
+                data[index] = next_byte();
+            }
+            let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+            temp_file.write(&data).expect("Failed to write test file");
+            let path = temp_file.path().to_str().unwrap();
+            for parser in [
+                MavLogParser::<mavlink::ardupilotmega::MavMessage>::try_new(path),
+                MavLogParser::<mavlink::ardupilotmega::MavMessage>::try_new_lenient(path),
+            ] {
+                if let Ok(mut parser) = parser {
+                    while parser.parse_next_entry().is_ok() {}
+                }
+            }
+        }
+    }
+
     #[test]
     #[should_panic(expected = "Custom XML files for message definitions are not supported.")]
     fn test_mav_log_parser_file_unsupported_payload_type_urls() {
@@ -378,6 +456,126 @@ mod mav_parse_tests {
         temp_file.close().unwrap();
     }
 
+    /// Writes a mixed log with sequence numbers where the entry with sequence number 2 is missing.
+    fn write_sequenced_log(temp_file: &mut tempfile::NamedTempFile) {
+        let mut packed_data: Vec<u8> = vec![
+            // file header
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, // uuid
+            16, 0, 0, 0, 0, 0, 0, 17, // timestamp_us
+            b'a', b'p', b'p', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, // src_application_id
+            2, 0, 0, 0, // format_version
+            6, 0, // format_flags
+            // message_definition
+            2, 0, 0, 0, // version_major
+            1, 0, 0, 0, // version_minor
+            b't', b'e', b's', b't', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, // dialect
+            0, 0, // payload_type
+            0, 0, 0, 0, // size
+        ];
+        for sequence in [0u32, 1, 3, 4] {
+            packed_data.push(2); // type
+            packed_data.extend_from_slice(&sequence.to_le_bytes()); // sequence
+            packed_data.extend_from_slice(&[1, 0]); // size
+            packed_data.push(b'a'); // payload
+        }
+        temp_file
+            .write(&packed_data)
+            .expect("Failed to write test file");
+    }
+
+    #[test]
+    fn test_mav_log_parser_sequence_gap() {
+        let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        write_sequenced_log(&mut temp_file);
+
+        let mut parser = MavLogParser::<mavlink::ardupilotmega::MavMessage>::new(
+            temp_file.path().to_str().unwrap(),
+        );
+        for expected in [0, 1, 3, 4] {
+            let entry = parser.parse_next_entry().unwrap();
+            assert_eq!(entry.sequence, Some(expected));
+            assert_eq!(entry.text.unwrap(), "a");
+        }
+        assert_eq!(parser.sequence_errors(), 1);
+        temp_file.close().unwrap();
+    }
+
+    #[test]
+    fn test_mav_log_parser_sequence_gap_strict() {
+        let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        write_sequenced_log(&mut temp_file);
+
+        let mut parser = MavLogParser::<mavlink::ardupilotmega::MavMessage>::new(
+            temp_file.path().to_str().unwrap(),
+        );
+        parser.set_strict_sequence(true);
+        assert_eq!(parser.parse_next_entry().unwrap().sequence, Some(0));
+        assert_eq!(parser.parse_next_entry().unwrap().sequence, Some(1));
+        match parser.parse_next_entry() {
+            Err(mavlink::error::MessageReadError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            _ => panic!("Expected a sequence error"),
+        }
+        assert_eq!(parser.parse_next_entry().unwrap().sequence, Some(4));
+        assert_eq!(parser.sequence_errors(), 1);
+        temp_file.close().unwrap();
+    }
+
+    /// Writes a mixed log recorded with the ardupilotmega dialect holding a single text entry.
+    fn write_ardupilotmega_log(temp_file: &mut tempfile::NamedTempFile) {
+        let mut packed_data: Vec<u8> = vec![
+            // file header
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, // uuid
+            16, 0, 0, 0, 0, 0, 0, 17, // timestamp_us
+            b'a', b'p', b'p', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, // src_application_id
+            1, 0, 0, 0, // format_version
+            2, 0, // format_flags
+            // message_definition
+            2, 0, 0, 0, // version_major
+            1, 0, 0, 0, // version_minor
+        ];
+        let mut dialect = [0u8; 32];
+        dialect[..13].copy_from_slice(b"ardupilotmega");
+        packed_data.extend_from_slice(&dialect);
+        packed_data.extend_from_slice(&[0, 0, 0, 0, 0, 0]); // payload_type and size
+        packed_data.extend_from_slice(&[2, 1, 0, b'a']); // text entry
+        temp_file
+            .write(&packed_data)
+            .expect("Failed to write test file");
+    }
+
+    #[test]
+    fn test_mav_log_parser_dialect_mismatch() {
+        let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        write_ardupilotmega_log(&mut temp_file);
+        let path = temp_file.path().to_str().unwrap();
+
+        let parser = MavLogParser::<mavlink::ardupilotmega::MavMessage>::new(path);
+        assert!(parser.dialect_mismatch().is_none());
+
+        // the mismatch is only a warning unless strict
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let mismatch = parser.dialect_mismatch().unwrap();
+        assert_eq!(mismatch.recorded, "ardupilotmega");
+        assert_eq!(mismatch.compiled, vec!["common"]);
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "a");
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        parser.set_strict_dialect(true);
+        match parser.parse_next_entry() {
+            Err(mavlink::error::MessageReadError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+                assert!(e.to_string().contains("ardupilotmega"));
+            }
+            _ => panic!("Expected a dialect mismatch"),
+        }
+        temp_file.close().unwrap();
+    }
+
     fn populate_data(mavlink_only: bool, timestamp: bool, data: &mut Vec<u8>) {
         let mut msg = MAVLinkV2MessageRaw::new();
         let mut header = MavHeader {
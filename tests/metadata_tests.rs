@@ -0,0 +1,80 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod metadata_tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use mavlink::common::MavMessage;
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::clock::{BackwardsPolicy, Clock, ClockSource};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+    use mavlink_log::metadata::{JobIndex, MissionMetadata, ProductBatch};
+
+    /// Returns the metadata of a job flown by the same operator.
+    fn job(job_id: &str, batch: &str) -> MissionMetadata {
+        MissionMetadata {
+            operator_id: Some(String::from("P-104")),
+            field_id: Some(String::from("F-12")),
+            job_id: Some(job_id.to_string()),
+            products: vec![ProductBatch {
+                product: String::from("GLY-360"),
+                batch: batch.to_string(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Returns a HEARTBEAT frame.
+    fn heartbeat() -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader::default(),
+            msg: MavMessage::HEARTBEAT(Default::default()),
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    /// Test that mission metadata round trips and attributes telemetry to its job.
+    #[test]
+    fn test_metadata_entry_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("job.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, None, None).unwrap();
+        // every reading of the clock advances it, so that no two entries share a timestamp
+        let time = AtomicU64::new(0);
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || time.fetch_add(1_000, Ordering::Relaxed))),
+            BackwardsPolicy::Allow,
+        ));
+        logger.write_metadata(&job("J-31", "L2291")).unwrap();
+        logger.write_mavlink(heartbeat()).unwrap();
+        logger.write_metadata(&job("J-32", "L2300")).unwrap();
+        logger.write_mavlink(heartbeat()).unwrap();
+        drop(logger);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let first = parser.parse_next_entry().unwrap();
+        assert_eq!(first.metadata, Some(job("J-31", "L2291")));
+        assert!(first.to_string().ends_with(
+            r#" METADATA operator="P-104" field="F-12" job="J-31" product="GLY-360" batch="L2291""#
+        ));
+
+        let index = JobIndex::build(&mut MavLogParser::<MavMessage>::new(path)).unwrap();
+        assert_eq!(index.spans().len(), 2);
+        assert_eq!(index.job("J-32").count(), 1);
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let mut jobs: Vec<Option<String>> = Vec::new();
+        while let Ok(entry) = parser.parse_next_entry() {
+            if entry.mav_message.is_some() {
+                let metadata = index.at(entry.timestamp.unwrap()).unwrap();
+                jobs.push(metadata.job_id.clone());
+            }
+        }
+        assert_eq!(
+            jobs,
+            vec![Some(String::from("J-31")), Some(String::from("J-32"))]
+        );
+    }
+}
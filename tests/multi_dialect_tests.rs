@@ -0,0 +1,67 @@
+/// Tests decoding logs holding traffic of several MAVLink dialects in a single pass.
+#[cfg(all(feature = "tlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod multi_dialect_tests {
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion, ardupilotmega, common};
+    use mavlink_log::dialect::MultiDialect;
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::{LogEntry, for_each_entry};
+    use mavlink_log::tlog::logger::RotatingTlog;
+    use mavlink_log::tlog::parser::TlogParser;
+    use tempfile::TempDir;
+
+    type Fleet = MultiDialect<common::MavMessage, ardupilotmega::MavMessage>;
+
+    fn frame<M: mavlink::Message>(msg: M) -> MavFrame<M> {
+        MavFrame {
+            header: MavHeader::default(),
+            msg,
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    /// Test that messages only defined by the fallback dialect are decoded instead of dropped.
+    #[test]
+    fn test_mixed_fleet_tlog() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("fleet.tlog");
+        let path = path.to_str().unwrap();
+        let heartbeat = common::MavMessage::HEARTBEAT(Default::default());
+        let ahrs = ardupilotmega::MavMessage::AHRS(ardupilotmega::AHRS_DATA {
+            omegaIx: 0.25,
+            ..Default::default()
+        });
+        {
+            let mut logger = RotatingTlog::new(path, 100000, 0).unwrap();
+            logger.write_mavlink(frame(heartbeat.clone())).unwrap();
+            logger.write_mavlink(frame(ahrs.clone())).unwrap();
+            logger.write_mavlink(frame(heartbeat.clone())).unwrap();
+        }
+
+        let mut common_only = 0;
+        for_each_entry(&mut TlogParser::<common::MavMessage>::new(path), |entry| {
+            if entry.mav_message.is_some() {
+                common_only += 1;
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(common_only, 2);
+
+        let mut entries: Vec<LogEntry<Fleet>> = Vec::new();
+        for_each_entry(&mut TlogParser::<Fleet>::new(path), |entry| {
+            entries.push(entry);
+            Ok(())
+        })
+        .unwrap();
+        let messages: Vec<Fleet> = entries.into_iter().filter_map(|e| e.mav_message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                MultiDialect::Primary(heartbeat.clone()),
+                MultiDialect::Fallback(ahrs),
+                MultiDialect::Primary(heartbeat),
+            ]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod offset_tests {
+    use mavlink::common::MavMessage;
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::{LogEntry, MavParser, for_each_entry};
+    use mavlink_log::mavlog::header::FormatFlags;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+
+    fn heartbeat() -> MavFrame<MavMessage> {
+        MavFrame {
+            header: MavHeader::default(),
+            msg: MavMessage::HEARTBEAT(Default::default()),
+            protocol_version: MavlinkVersion::V2,
+        }
+    }
+
+    /// Parses a log and checks that its entries are contiguous from the first entry offset to
+    /// the end of the file.
+    fn check_offsets(path: &str, first_offset: u64) -> Vec<LogEntry<MavMessage>> {
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let mut entries: Vec<LogEntry<MavMessage>> = Vec::new();
+        for_each_entry(&mut parser, |entry| {
+            entries.push(entry);
+            Ok(())
+        })
+        .unwrap();
+        let mut offset = first_offset;
+        for entry in &entries {
+            assert_eq!(entry.offset, Some(offset));
+            offset += entry.entry_len.unwrap();
+        }
+        assert_eq!(offset, std::fs::metadata(path).unwrap().len());
+        entries
+    }
+
+    /// Test that entries of a mixed log report their location in the file.
+    #[test]
+    fn test_mixed_entry_offsets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("offsets.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, None, None).unwrap();
+        let first_offset = logger.header().packed_size() as u64;
+        logger.write_text("first").unwrap();
+        logger.write_mavlink(heartbeat()).unwrap();
+        logger.write_blob(&vec![7u8; 100_000]).unwrap();
+        logger.write_raw(&[1, 2, 3]).unwrap();
+        drop(logger);
+
+        let entries = check_offsets(path, first_offset);
+        assert_eq!(entries.len(), 4);
+        let content = std::fs::read(path).unwrap();
+        let text = &entries[0];
+        let start = text.offset.unwrap() as usize;
+        let end = start + text.entry_len.unwrap() as usize;
+        // type, timestamp and size fields followed by the text
+        assert_eq!(content[start], 2);
+        assert_eq!(&content[end - 5..end], b"first");
+        assert!(entries[2].blob.is_some());
+    }
+
+    /// Test that entries of a MAVLink only log report their location in the file.
+    #[test]
+    fn test_mavlink_only_entry_offsets() {
+        for no_timestamp in [false, true] {
+            let dir = tempfile::TempDir::new().unwrap();
+            let path = dir.path().join("offsets.mav");
+            let path = path.to_str().unwrap();
+            let flags = FormatFlags {
+                mavlink_only: true,
+                no_timestamp,
+                ..Default::default()
+            };
+            let mut logger =
+                RotatingMavLogger::new(path, 100_000_000, 0, Some(flags), None).unwrap();
+            let first_offset = logger.header().packed_size() as u64;
+            for _ in 0..5 {
+                logger.write_mavlink(heartbeat()).unwrap();
+            }
+            drop(logger);
+
+            assert_eq!(check_offsets(path, first_offset).len(), 5);
+        }
+    }
+}
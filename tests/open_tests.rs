@@ -0,0 +1,80 @@
+/// This module contains tests for opening logs without specifying their format. The sample TLOG
+/// file located at `tests/data/tlog_data_0.tlog` is used as a TLOG input.
+#[cfg(all(
+    feature = "mavlog",
+    feature = "tlog",
+    feature = "logger",
+    feature = "parser"
+))]
+#[cfg(test)]
+mod open_tests {
+    use std::io::ErrorKind;
+
+    use mavlink::ardupilotmega;
+    use mavlink::common::MavMessage;
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::tlog::parser::TlogParser;
+
+    /// Test that a TLOG is opened with the TLOG parser.
+    #[test]
+    fn test_open_tlog() {
+        let mut parser =
+            mavlink_log::open::<ardupilotmega::MavMessage>("tests/data/tlog_data_0.tlog").unwrap();
+        let mut tlog = TlogParser::<ardupilotmega::MavMessage>::new("tests/data/tlog_data_0.tlog");
+        for _ in 0..10 {
+            let entry = parser.parse_next_entry().unwrap();
+            let expected = tlog.parse_next_entry().unwrap();
+            assert_eq!(entry.timestamp, expected.timestamp);
+            assert_eq!(entry.mav_message, expected.mav_message);
+        }
+    }
+
+    /// Test that a .mav log is opened with the .mav parser regardless of its extension.
+    #[test]
+    fn test_open_mavlog() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("flight.tlog");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100_000, 0, None, None).unwrap();
+        logger.write_text("hello").unwrap();
+        logger
+            .write_mavlink(MavFrame {
+                header: MavHeader::default(),
+                msg: MavMessage::HEARTBEAT(Default::default()),
+                protocol_version: MavlinkVersion::V2,
+            })
+            .unwrap();
+        drop(logger);
+
+        let mut parser = mavlink_log::open::<MavMessage>(path).unwrap();
+        assert_eq!(parser.parse_next_entry().unwrap().text.unwrap(), "hello");
+        assert!(parser.parse_next_entry().unwrap().mav_message.is_some());
+    }
+
+    /// Test that unrecognized and compressed files are rejected.
+    #[test]
+    fn test_open_unrecognized() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "not a log file at all").unwrap();
+        let error = mavlink_log::open::<MavMessage>(path.to_str().unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let path = dir.path().join("flight.mav.zst");
+        std::fs::write(&path, [0x28, 0xb5, 0x2f, 0xfd, 0x00]).unwrap();
+        let error = mavlink_log::open::<MavMessage>(path.to_str().unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+
+        let error = mavlink_log::open::<MavMessage>("does/not/exist")
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+}
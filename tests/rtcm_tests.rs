@@ -0,0 +1,98 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod rtcm_tests {
+    use mavlink::common::{GPS_RTCM_DATA_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+    use mavlink_log::mavlog::rtcm::{RtcmSource, extract_rtcm};
+
+    /// Builds an RTCM message of the given size.
+    fn rtcm_message(size: usize, seed: u8) -> Vec<u8> {
+        (0..size).map(|i| (i as u8).wrapping_add(seed)).collect()
+    }
+
+    /// Splits an RTCM message into GPS_RTCM_DATA frames the way ground stations do.
+    fn injection_frames(message: &[u8], sequence: u8) -> Vec<MavFrame<MavMessage>> {
+        let fragmented = message.len() > 180;
+        message
+            .chunks(180)
+            .enumerate()
+            .map(|(fragment, chunk)| {
+                let mut data = [0u8; 180];
+                data[..chunk.len()].copy_from_slice(chunk);
+                MavFrame {
+                    header: MavHeader {
+                        system_id: 255,
+                        component_id: 190,
+                        sequence: 0,
+                    },
+                    msg: MavMessage::GPS_RTCM_DATA(GPS_RTCM_DATA_DATA {
+                        flags: fragmented as u8 | ((fragment as u8) << 1) | (sequence << 3),
+                        len: chunk.len() as u8,
+                        data,
+                    }),
+                    protocol_version: MavlinkVersion::V2,
+                }
+            })
+            .collect()
+    }
+
+    /// Test that RTCM entries round trip with their link id.
+    #[test]
+    fn test_rtcm_entry_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("rtk.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, None, None).unwrap();
+        logger.write_rtcm(3, &[0xd3, 0x00, 0x01]).unwrap();
+        drop(logger);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let rtcm = parser.parse_next_entry().unwrap().rtcm.unwrap();
+        assert_eq!(rtcm.link, 3);
+        assert_eq!(rtcm.data, vec![0xd3, 0x00, 0x01]);
+    }
+
+    /// Test that the correction streams of RTCM entries and GPS_RTCM_DATA messages are
+    /// reconstructed per source.
+    #[test]
+    fn test_extract_rtcm() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("rtk.mav");
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, None, None).unwrap();
+        let messages = [
+            rtcm_message(100, 0),
+            rtcm_message(400, 1),
+            rtcm_message(360, 2),
+            rtcm_message(50, 3),
+        ];
+        for (sequence, message) in messages.iter().enumerate() {
+            logger.write_rtcm(0, &message[..message.len() / 2]).unwrap();
+            logger.write_rtcm(0, &message[message.len() / 2..]).unwrap();
+            logger.write_rtcm(1, message).unwrap();
+            for frame in injection_frames(message, sequence as u8) {
+                logger.write_mavlink(frame).unwrap();
+            }
+        }
+        // a message missing its first fragment is dropped
+        let frames = injection_frames(&rtcm_message(300, 4), 4);
+        logger.write_mavlink(frames[1].clone()).unwrap();
+        drop(logger);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let streams = extract_rtcm(&mut parser).unwrap();
+        let expected: Vec<u8> = messages.concat();
+        assert_eq!(streams.len(), 3);
+        assert_eq!(streams[&RtcmSource::Link(0)], expected);
+        assert_eq!(streams[&RtcmSource::Link(1)], expected);
+        let source = RtcmSource::Mavlink {
+            system_id: 255,
+            component_id: 190,
+        };
+        assert_eq!(streams[&source], expected);
+    }
+}
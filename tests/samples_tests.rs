@@ -0,0 +1,67 @@
+/// Tests that the embedded sample logs parse into their documented content.
+#[cfg(all(
+    feature = "samples",
+    feature = "mavlog",
+    feature = "tlog",
+    feature = "parser"
+))]
+#[cfg(test)]
+mod samples_tests {
+    use std::io::Write;
+
+    use mavlink::Message;
+    use mavlink::ardupilotmega::MavMessage;
+    use mavlink_log::mav_parser::{LogEntry, MavParser, for_each_entry};
+    use mavlink_log::mavlog::parser::MavLogParser;
+    use mavlink_log::samples;
+    use mavlink_log::tlog::parser::TlogParser;
+
+    /// Writes a sample to a temporary file.
+    fn sample_file(bytes: &[u8]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        file.write_all(bytes).expect("Failed to write sample");
+        file
+    }
+
+    fn parse_all<P: MavParser<M = MavMessage>>(parser: &mut P) -> Vec<LogEntry<MavMessage>> {
+        let mut entries = Vec::new();
+        for_each_entry(parser, |entry| {
+            entries.push(entry);
+            Ok(())
+        })
+        .unwrap();
+        entries
+    }
+
+    /// Test that the minimal .mav sample holds the documented entries.
+    #[test]
+    fn test_minimal_mavlog() {
+        let file = sample_file(samples::minimal_mavlog());
+        let mut parser = MavLogParser::<MavMessage>::new(file.path().to_str().unwrap());
+        let entries = parse_all(&mut parser);
+        assert_eq!(entries.len(), 6);
+        assert_eq!(
+            entries[0].text.as_deref(),
+            Some("mavlink_log minimal sample")
+        );
+        assert_eq!(entries[0].timestamp, Some(1_700_000_000_000_000));
+        let ids: Vec<Option<u32>> = entries
+            .iter()
+            .map(|entry| entry.mav_message.as_ref().map(|msg| msg.message_id()))
+            .collect();
+        assert_eq!(ids, vec![None, Some(0), Some(30), Some(33), None, Some(0)]);
+        assert_eq!(entries[4].raw, Some(vec![0xDE, 0xAD, 0xBE, 0xEF]));
+        assert_eq!(entries[5].timestamp, Some(1_700_000_001_100_000));
+    }
+
+    /// Test that the tlog sample holds 40 valid records.
+    #[test]
+    fn test_qgc_tlog() {
+        let file = sample_file(samples::qgc_tlog());
+        let mut parser = TlogParser::<MavMessage>::new(file.path().to_str().unwrap());
+        let entries = parse_all(&mut parser);
+        assert_eq!(entries.len(), 40);
+        assert_eq!(entries[0].timestamp, Some(1_632_843_969_792_995));
+        assert_eq!(parser.skipped_bytes(), 0);
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod segment_tests {
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use mavlink::common::MavMessage;
+    use mavlink::error::MessageReadError;
+    use mavlink_log::clock::{BackwardsPolicy, Clock, ClockSource};
+    use mavlink_log::mav_parser::{LogEntry, MavParser};
+    use mavlink_log::mavlog::header::FormatFlags;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::{MavLogParser, SegmentBoundary, SegmentMode};
+    use tempfile::TempDir;
+
+    /// Writes a log holding one text entry per timestamp and returns its content.
+    fn write_log(dir: &TempDir, name: &str, flags: FormatFlags, timestamps: &[u64]) -> Vec<u8> {
+        let path = dir.path().join(name);
+        let path = path.to_str().unwrap();
+        let mut logger = RotatingMavLogger::new(path, 100000, 0, Some(flags), None).unwrap();
+        let time = Arc::new(AtomicU64::new(0));
+        let source_time = time.clone();
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || source_time.load(Ordering::Relaxed))),
+            BackwardsPolicy::Allow,
+        ));
+        for &timestamp in timestamps {
+            time.store(timestamp, Ordering::Relaxed);
+            logger.write_text(&format!("{name} {timestamp}")).unwrap();
+        }
+        drop(logger);
+        std::fs::read(path).unwrap()
+    }
+
+    /// Concatenates logs into a single file, as `cat` does.
+    fn concatenate(dir: &TempDir, logs: &[Vec<u8>]) -> String {
+        let path = dir.path().join("concatenated.mav");
+        let mut file = std::fs::File::create(&path).unwrap();
+        for log in logs {
+            file.write_all(log).unwrap();
+        }
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Reads every entry and boundary of a log.
+    fn read_all(
+        parser: &mut MavLogParser<MavMessage>,
+    ) -> (Vec<LogEntry<MavMessage>>, Vec<SegmentBoundary>) {
+        let mut entries = Vec::new();
+        let mut boundaries = Vec::new();
+        loop {
+            match parser.parse_next_entry() {
+                Ok(entry) => entries.push(entry),
+                Err(MessageReadError::Io(error)) => {
+                    match error
+                        .get_ref()
+                        .and_then(|e| e.downcast_ref::<SegmentBoundary>())
+                    {
+                        Some(boundary) => boundaries.push(boundary.clone()),
+                        None if error.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                        None => panic!("unexpected error {error}"),
+                    }
+                }
+                Err(error) => panic!("unexpected error {error:?}"),
+            }
+        }
+        (entries, boundaries)
+    }
+
+    /// Test that an appended log is reported at its offset and its entries are read with the
+    /// format of its own header.
+    #[test]
+    fn test_segment_boundary() {
+        let dir = TempDir::new().unwrap();
+        let first = write_log(&dir, "a", FormatFlags::default(), &[100, 200]);
+        let sequenced = FormatFlags {
+            sequence: true,
+            ..Default::default()
+        };
+        let second = write_log(&dir, "b", sequenced, &[10, 20]);
+        let path = concatenate(&dir, &[first.clone(), second]);
+
+        let mut parser = MavLogParser::<MavMessage>::new(&path);
+        let (entries, boundaries) = read_all(&mut parser);
+        let texts: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry.text.as_deref().unwrap())
+            .collect();
+        assert_eq!(texts, vec!["a 100", "a 200", "b 10", "b 20"]);
+        let timestamps: Vec<u64> = entries.iter().map(|e| e.timestamp.unwrap()).collect();
+        assert_eq!(timestamps, vec![100, 200, 10, 20]);
+        assert_eq!(entries[2].sequence, Some(0));
+        assert_eq!(parser.sequence_errors(), 0);
+
+        assert_eq!(boundaries.len(), 1);
+        assert_eq!(boundaries[0].offset, Some(first.len() as u64));
+        assert!(boundaries[0].header.format_flags.sequence);
+        let header_len = boundaries[0].header.packed_size() as u64;
+        assert_eq!(entries[2].offset, Some(first.len() as u64 + header_len));
+    }
+
+    /// Test that appended logs starting earlier are shifted to continue from the last entry in
+    /// rebase mode.
+    #[test]
+    fn test_segment_rebase() {
+        let dir = TempDir::new().unwrap();
+        let first = write_log(&dir, "a", FormatFlags::default(), &[100, 200]);
+        let second = write_log(&dir, "b", FormatFlags::default(), &[10, 20]);
+        let third = write_log(&dir, "c", FormatFlags::default(), &[500]);
+        let path = concatenate(&dir, &[first, second, third]);
+
+        let mut parser = MavLogParser::<MavMessage>::new(&path);
+        parser.set_segment_mode(SegmentMode::Rebase);
+        let (entries, boundaries) = read_all(&mut parser);
+        assert!(boundaries.is_empty());
+        let timestamps: Vec<u64> = entries.iter().map(|e| e.timestamp.unwrap()).collect();
+        assert_eq!(timestamps, vec![100, 200, 200, 210, 500]);
+    }
+}
@@ -3,10 +3,15 @@
 /// process MAVLink messages from a TLOG file.
 #[cfg(all(feature = "tlog", feature = "parser"))]
 mod tlog_parse_tests {
+    use std::io::Write;
+
     use mavlink::ardupilotmega::MavMessage;
     use mavlink::error::MessageReadError;
+    use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavHeader, MavlinkVersion};
     use mavlink_log::mav_parser::{LogEntry, MavParser};
+    use mavlink_log::tlog::mavproxy::MavProxyTlog;
     use mavlink_log::tlog::parser::TlogParser;
+    use mavlink_log::tlog::timestamp::{TimestampHeuristics, TimestampStatus};
 
     /// This test verifies that the `TlogParser` can correctly parse a TLOG file
     /// by counting the number of MAVLink messages it contains. It uses a sample
@@ -31,4 +36,221 @@ mod tlog_parse_tests {
         }
         assert_eq!(count, 1426);
     }
+
+    /// This test verifies that the record timestamps are extracted from the
+    /// sample TLOG file.
+    #[test]
+    fn test_tlog_parse_timestamps() {
+        let mut tlog = TlogParser::<MavMessage>::new("tests/data/tlog_data_0.tlog");
+        let entry = tlog
+            .parse_next_entry()
+            .expect("Failed to parse first entry");
+        assert_eq!(entry.timestamp, Some(1632843969792995));
+        let mut last: u64 = 0;
+        while let Ok(entry) = tlog.parse_next_entry() {
+            last = entry.timestamp.unwrap();
+        }
+        assert_eq!(last, 1632843981222831);
+        assert_eq!(tlog.skipped_bytes(), 0);
+    }
+
+    /// This test verifies that each record reports its location in the
+    /// sample TLOG file and that the records cover the whole file.
+    #[test]
+    fn test_tlog_parse_offsets() {
+        let mut tlog = TlogParser::<MavMessage>::new("tests/data/tlog_data_0.tlog");
+        let mut offset: u64 = 0;
+        while let Ok(entry) = tlog.parse_next_entry() {
+            assert_eq!(entry.offset, Some(offset));
+            offset += entry.entry_len.unwrap();
+        }
+        let size = std::fs::metadata("tests/data/tlog_data_0.tlog")
+            .unwrap()
+            .len();
+        assert_eq!(offset, size);
+    }
+
+    /// This test verifies that interleaved MAVLink 1 and MAVLink 2 frames are
+    /// both parsed with their protocol version and that garbage between
+    /// records is skipped and counted.
+    #[test]
+    fn test_tlog_parse_mixed_versions_and_garbage() {
+        let header = MavHeader::default();
+        let msg = MavMessage::HEARTBEAT(Default::default());
+        let mut v1 = MAVLinkV1MessageRaw::new();
+        v1.serialize_message(header, &msg);
+        let mut v2 = MAVLinkV2MessageRaw::new();
+        v2.serialize_message(header, &msg);
+        let garbage: [u8; 5] = [0x00, 0xFD, 0x05, 0xFE, 0x11];
+
+        let mut data: Vec<u8> = Vec::new();
+        for i in 0..10u64 {
+            data.extend_from_slice(&i.to_be_bytes());
+            if i % 2 == 0 {
+                data.extend_from_slice(v1.raw_bytes());
+            } else {
+                data.extend_from_slice(v2.raw_bytes());
+            }
+            if i % 3 == 0 {
+                data.extend_from_slice(&garbage);
+            }
+        }
+        let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        temp_file
+            .write_all(&data)
+            .expect("Failed to write test file");
+
+        let mut tlog = TlogParser::<MavMessage>::new(temp_file.path().to_str().unwrap());
+        for i in 0..10u64 {
+            let entry = tlog.parse_next_entry();
+            assert!(entry.is_ok(), "Iteration: {i} {:?}", entry.err());
+            let entry = entry.unwrap();
+            assert_eq!(entry.timestamp, Some(i));
+            assert_eq!(entry.mav_message, Some(msg.clone()));
+            let version = if i % 2 == 0 {
+                MavlinkVersion::V1
+            } else {
+                MavlinkVersion::V2
+            };
+            assert_eq!(entry.protocol_version, Some(version));
+        }
+        assert!(tlog.parse_next_entry().is_err());
+        // garbage was appended after records 0, 3, 6 and 9 but the trailing
+        // garbage is too short to be examined as a record
+        assert_eq!(tlog.skipped_bytes(), 3 * garbage.len() as u64);
+    }
+
+    /// This test verifies that a magic byte in garbage announcing a frame
+    /// longer than the rest of the file is skipped instead of ending the
+    /// parse before the last records.
+    #[test]
+    fn test_tlog_parse_garbage_before_last_records() {
+        let header = MavHeader::default();
+        let msg = MavMessage::HEARTBEAT(Default::default());
+        let mut v2 = MAVLinkV2MessageRaw::new();
+        v2.serialize_message(header, &msg);
+        // a MAVLink 1 magic byte and the largest payload length after a timestamp
+        let garbage: [u8; 11] = [0, 0, 0, 0, 0, 0, 0, 0, 0xFE, 0xFF, 0x00];
+
+        let mut data: Vec<u8> = Vec::new();
+        for i in 0..4u64 {
+            if i == 2 {
+                data.extend_from_slice(&garbage);
+            }
+            data.extend_from_slice(&(10 + i).to_be_bytes());
+            data.extend_from_slice(v2.raw_bytes());
+        }
+        let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        temp_file
+            .write_all(&data)
+            .expect("Failed to write test file");
+
+        let mut tlog = TlogParser::<MavMessage>::new(temp_file.path().to_str().unwrap());
+        for i in 0..4u64 {
+            let entry = tlog.parse_next_entry();
+            assert!(entry.is_ok(), "Iteration: {i} {:?}", entry.err());
+            assert_eq!(entry.unwrap().timestamp, Some(10 + i));
+        }
+        assert!(tlog.parse_next_entry().is_err());
+        assert_eq!(tlog.skipped_bytes(), garbage.len() as u64);
+    }
+
+    /// This test verifies that little-endian timestamps are detected and
+    /// corrected by default and left untouched when heuristics are disabled.
+    #[test]
+    fn test_tlog_parse_little_endian_timestamps() {
+        const START_US: u64 = 1632843969792995;
+        let mut v2 = MAVLinkV2MessageRaw::new();
+        v2.serialize_message(
+            MavHeader::default(),
+            &MavMessage::HEARTBEAT(Default::default()),
+        );
+        let mut data: Vec<u8> = Vec::new();
+        for i in 0..5u64 {
+            data.extend_from_slice(&(START_US + i).to_le_bytes());
+            data.extend_from_slice(v2.raw_bytes());
+        }
+        let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        temp_file
+            .write_all(&data)
+            .expect("Failed to write test file");
+        let path = temp_file.path().to_str().unwrap();
+
+        let mut tlog = TlogParser::<MavMessage>::new(path);
+        for i in 0..5u64 {
+            let entry = tlog.parse_next_entry().expect("Failed to parse entry");
+            assert_eq!(entry.timestamp, Some(START_US + i));
+            assert_eq!(
+                tlog.last_timestamp_status(),
+                Some(TimestampStatus::ByteSwapped)
+            );
+        }
+        assert_eq!(tlog.suspect_timestamps(), 0);
+
+        let mut tlog = TlogParser::<MavMessage>::with_timestamp_heuristics(
+            path,
+            TimestampHeuristics::disabled(),
+        );
+        let entry = tlog.parse_next_entry().expect("Failed to parse entry");
+        assert_eq!(entry.timestamp, Some(START_US.swap_bytes()));
+    }
+
+    /// This test verifies that MAVProxy sidecar files next to a TLOG are found
+    /// and exposed while the TLOG itself parses as usual.
+    #[test]
+    fn test_mavproxy_sidecars() {
+        let dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+        let tlog_path = dir.path().join("flight.tlog");
+        std::fs::copy("tests/data/tlog_data_0.tlog", &tlog_path).expect("Failed to copy tlog");
+        std::fs::write(dir.path().join("flight.tlog.raw"), [0xFD, 0x00, 0x42])
+            .expect("Failed to write raw sidecar");
+        std::fs::write(
+            dir.path().join("flight.parm"),
+            "SYSID_THISMAV 1\nFRAME_CLASS 2\n",
+        )
+        .expect("Failed to write param sidecar");
+
+        let mut tlog = MavProxyTlog::<MavMessage>::new(tlog_path.to_str().unwrap())
+            .expect("Failed to open tlog");
+        assert_eq!(tlog.params().len(), 2);
+        assert_eq!(tlog.params()["FRAME_CLASS"], 2.0);
+        assert_eq!(
+            tlog.raw_path(),
+            Some(dir.path().join("flight.tlog.raw").as_path())
+        );
+        let mut raw: Vec<u8> = Vec::new();
+        std::io::Read::read_to_end(&mut tlog.open_raw().unwrap().unwrap(), &mut raw).unwrap();
+        assert_eq!(raw, vec![0xFD, 0x00, 0x42]);
+
+        let mut count: u64 = 0;
+        while tlog.parse_next_entry().is_ok() {
+            count += 1;
+        }
+        assert_eq!(count, 1426);
+    }
+
+    /// This test verifies that the `mav.parm` MAVProxy writes in the directory of
+    /// the TLOG is found, and that a missing TLOG is reported as an error.
+    #[test]
+    fn test_mavproxy_mav_parm() {
+        let dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+        let tlog_path = dir.path().join("flight.tlog");
+        std::fs::copy("tests/data/tlog_data_0.tlog", &tlog_path).expect("Failed to copy tlog");
+        std::fs::write(dir.path().join("mav.parm"), "SYSID_THISMAV 3\n")
+            .expect("Failed to write param sidecar");
+
+        let tlog = MavProxyTlog::<MavMessage>::new(tlog_path.to_str().unwrap())
+            .expect("Failed to open tlog");
+        assert_eq!(
+            tlog.param_path(),
+            Some(dir.path().join("mav.parm").as_path())
+        );
+        assert_eq!(tlog.params()["SYSID_THISMAV"], 3.0);
+
+        let missing = dir.path().join("missing.tlog");
+        let error = MavProxyTlog::<MavMessage>::new(missing.to_str().unwrap())
+            .err()
+            .expect("A missing tlog should not open");
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
 }
@@ -0,0 +1,115 @@
+#[cfg(all(feature = "tlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod tlog_qgc_tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use mavlink::common::{ATTITUDE_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::clock::{BackwardsPolicy, Clock, ClockSource};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::tlog::logger::RotatingTlog;
+    use mavlink_log::tlog::parser::TlogParser;
+    use mavlink_log::tlog::timestamp::TimestampStatus;
+
+    /// Path of a tlog previously written by `RotatingTlog` with QGroundControl compatibility
+    /// enabled: a HEARTBEAT and an ATTITUDE record, twice, 20 ms apart from 2023-11-14T22:13:20Z,
+    /// with big-endian timestamps. It guards the writer against changes of its own output, it
+    /// was not recorded by QGroundControl.
+    const GOLDEN_PATH: &str = "tests/data/qgc_golden.tlog";
+
+    /// Writes the messages of the golden log with QGroundControl compatibility enabled.
+    fn write_qgc_log(path: &str) {
+        let mut logger = RotatingTlog::new(path, 1_000_000, 0).unwrap();
+        logger.set_qgc_compatible(true);
+        let time = AtomicU64::new(1_700_000_000_000_000);
+        logger.set_clock(Clock::new(
+            ClockSource::Custom(Box::new(move || time.fetch_add(20_000, Ordering::Relaxed))),
+            BackwardsPolicy::Hold,
+        ));
+        let heartbeat = MavFrame {
+            header: MavHeader::default(),
+            msg: MavMessage::HEARTBEAT(Default::default()),
+            protocol_version: MavlinkVersion::V2,
+        };
+        let attitude = MavFrame {
+            header: MavHeader::default(),
+            msg: MavMessage::ATTITUDE(ATTITUDE_DATA {
+                time_boot_ms: 33,
+                roll: 43.5,
+                pitch: 23.3,
+                yaw: 99.9,
+                rollspeed: 1.3,
+                pitchspeed: 13.0,
+                yawspeed: 0.0,
+            }),
+            protocol_version: MavlinkVersion::V2,
+        };
+        for _ in 0..2 {
+            logger.write_mavlink(heartbeat.clone()).unwrap();
+            logger.write_mavlink(attitude.clone()).unwrap();
+        }
+    }
+
+    /// Test that a QGroundControl compatible tlog is still written byte for byte like the golden
+    /// file.
+    #[test]
+    fn test_qgc_golden_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("flight.tlog");
+        let path = path.to_str().unwrap();
+        write_qgc_log(path);
+        assert_eq!(
+            std::fs::read(path).unwrap(),
+            std::fs::read(GOLDEN_PATH).unwrap()
+        );
+    }
+
+    /// Test that the golden file is read with its timestamps as written, without correction.
+    #[test]
+    fn test_qgc_golden_file_parse() {
+        let mut parser = TlogParser::<MavMessage>::new(GOLDEN_PATH);
+        let mut timestamps: Vec<u64> = Vec::new();
+        while let Ok(entry) = parser.parse_next_entry() {
+            assert_eq!(parser.last_timestamp_status(), Some(TimestampStatus::Valid));
+            timestamps.extend(entry.timestamp);
+        }
+        assert_eq!(
+            timestamps,
+            vec![
+                1_700_000_000_000_000,
+                1_700_000_000_020_000,
+                1_700_000_000_040_000,
+                1_700_000_000_060_000,
+            ]
+        );
+    }
+
+    /// Test that logs are named like QGroundControl names them.
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_qgc_file_name() {
+        use mavlink_log::tlog::logger::qgc_file_name;
+
+        let name = qgc_file_name(1_700_000_000_000_000).unwrap();
+        // the day, hour and minute depend on the time zone of the system
+        assert_eq!(name.len(), "2023-11-14 22-13-20.tlog".len());
+        assert!(name.starts_with("2023-11-1"));
+        assert!(name.ends_with("-20.tlog"));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let (mut logger, path) = RotatingTlog::new_qgc(dir.path(), 1_000_000, 2).unwrap();
+        logger
+            .write_mavlink(MavFrame {
+                header: MavHeader::default(),
+                msg: MavMessage::HEARTBEAT(Default::default()),
+                protocol_version: MavlinkVersion::V2,
+            })
+            .unwrap();
+        drop(logger);
+        assert_eq!(path.extension().unwrap(), "tlog");
+        let content = std::fs::read(&path).unwrap();
+        let timestamp = u64::from_be_bytes(content[..8].try_into().unwrap());
+        assert!(timestamp > 1_700_000_000_000_000);
+    }
+}
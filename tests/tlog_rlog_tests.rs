@@ -0,0 +1,81 @@
+#[cfg(all(feature = "tlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod tlog_rlog_tests {
+    use mavlink::common::{ATTITUDE_DATA, MavMessage};
+    use mavlink::{MavFrame, MavHeader, MavlinkVersion};
+    use mavlink_log::mav_logger::MavLogger;
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::tlog::logger::RotatingTlog;
+    use mavlink_log::tlog::parser::TlogParser;
+    use mavlink_log::tlog::rlog::{RlogParser, rlog_path};
+
+    /// Returns a HEARTBEAT and an ATTITUDE frame.
+    fn frames() -> [MavFrame<MavMessage>; 2] {
+        [
+            MavFrame {
+                header: MavHeader::default(),
+                msg: MavMessage::HEARTBEAT(Default::default()),
+                protocol_version: MavlinkVersion::V2,
+            },
+            MavFrame {
+                header: MavHeader::default(),
+                msg: MavMessage::ATTITUDE(ATTITUDE_DATA {
+                    time_boot_ms: 33,
+                    roll: 43.5,
+                    ..Default::default()
+                }),
+                protocol_version: MavlinkVersion::V1,
+            },
+        ]
+    }
+
+    /// Test that the rlog written beside a tlog holds the same frames without timestamps and is
+    /// read back while skipping bytes that are not MAVLink.
+    #[test]
+    fn test_rlog_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let tlog = dir.path().join("flight.tlog");
+        assert_eq!(rlog_path(&tlog), None);
+
+        let mut logger = RotatingTlog::new(tlog.to_str().unwrap(), 1_000_000, 0).unwrap();
+        logger.enable_rlog(1_000_000, 0).unwrap();
+        for frame in frames() {
+            logger.write_mavlink(frame).unwrap();
+        }
+        drop(logger);
+
+        let rlog = rlog_path(&tlog).unwrap();
+        assert_eq!(rlog, dir.path().join("flight.rlog"));
+        let tlog_len = std::fs::metadata(&tlog).unwrap().len();
+        assert_eq!(std::fs::metadata(&rlog).unwrap().len(), tlog_len - 2 * 8);
+
+        // a radio modem writes its own text on the link between frames
+        let mut content = std::fs::read(&rlog).unwrap();
+        content.splice(19..19, b"RSSI: 210".iter().copied());
+        std::fs::write(&rlog, content).unwrap();
+
+        let mut tlog_parser = TlogParser::<MavMessage>::new(tlog.to_str().unwrap());
+        let mut rlog_parser = RlogParser::<MavMessage>::new(rlog.to_str().unwrap()).unwrap();
+        let mut count = 0;
+        while let Ok(expected) = tlog_parser.parse_next_entry() {
+            let entry = rlog_parser.parse_next_entry().unwrap();
+            assert_eq!(entry.timestamp, None);
+            assert_eq!(entry.mav_message, expected.mav_message);
+            assert_eq!(entry.protocol_version, expected.protocol_version);
+            count += 1;
+        }
+        assert_eq!(count, 2);
+        assert!(rlog_parser.parse_next_entry().is_err());
+        assert_eq!(rlog_parser.skipped_bytes(), 9);
+    }
+
+    /// Test that an rlog named after the full name of the tlog is found.
+    #[test]
+    fn test_rlog_path_suffixed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let tlog = dir.path().join("flight.tlog");
+        let rlog = dir.path().join("flight.tlog.rlog");
+        std::fs::write(&rlog, []).unwrap();
+        assert_eq!(rlog_path(&tlog), Some(rlog));
+    }
+}
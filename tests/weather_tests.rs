@@ -0,0 +1,48 @@
+#[cfg(all(feature = "mavlog", feature = "logger", feature = "parser"))]
+#[cfg(test)]
+mod weather_tests {
+    use mavlink::common::{MavMessage, WIND_COV_DATA};
+    use mavlink_log::mav_parser::MavParser;
+    use mavlink_log::mavlog::logger::RotatingMavLogger;
+    use mavlink_log::mavlog::parser::MavLogParser;
+    use mavlink_log::weather::WeatherObservation;
+
+    /// Test that weather observations round trip with their measured values and note.
+    #[test]
+    fn test_weather_entry_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("spray.mav");
+        let path = path.to_str().unwrap();
+        let wind = MavMessage::WIND_COV(WIND_COV_DATA {
+            wind_x: 0.0,
+            wind_y: -2.0,
+            ..Default::default()
+        });
+        let measured = WeatherObservation::from_wind_cov(&wind).unwrap();
+        let observed = WeatherObservation {
+            temperature_c: Some(17.5),
+            note: String::from("overcast"),
+            ..Default::default()
+        };
+        let mut logger = RotatingMavLogger::new(path, 100_000_000, 0, None, None).unwrap();
+        logger.write_weather(&measured).unwrap();
+        logger.write_weather(&observed).unwrap();
+        drop(logger);
+
+        let mut parser = MavLogParser::<MavMessage>::new(path);
+        let first = parser.parse_next_entry().unwrap();
+        assert_eq!(first.weather, Some(measured));
+        assert!(
+            first
+                .to_string()
+                .ends_with(" WEATHER wind=2.0m/s from 90deg")
+        );
+        let second = parser.parse_next_entry().unwrap();
+        assert_eq!(second.weather, Some(observed));
+        assert!(
+            second
+                .to_string()
+                .ends_with(r#" WEATHER temperature=17.5C "overcast""#)
+        );
+    }
+}